@@ -0,0 +1,267 @@
+//! `z80!` inline assembly for retroshield-z80-workbench
+//!
+//! Enable the workbench's `macros` feature and use the macro through
+//! `retroshield_z80_workbench::z80`. Each statement is checked against the
+//! workbench's opcode tables when the program is compiled, then assembled
+//! with `CodeGen::emit_asm` when it runs. The extension tables (`z180`,
+//! `r800`, `z80n`, `undocumented`) are checked when the workbench feature
+//! of the same name is enabled, as they are for `emit_asm`.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use retroshield_z80_syntax as syntax;
+
+/// Syntax strings of the tables `emit_asm` searches: the base set, then
+/// the extensions enabled by features
+fn syntaxes() -> impl Iterator<Item = &'static str> {
+    #[allow(unused_mut)]
+    let mut tables = vec![syntax::Z80];
+    #[cfg(feature = "z180")]
+    tables.push(syntax::Z180);
+    #[cfg(feature = "r800")]
+    tables.push(syntax::R800);
+    #[cfg(feature = "z80n")]
+    tables.push(syntax::Z80N);
+    #[cfg(feature = "undocumented")]
+    tables.push(syntax::UNDOCUMENTED);
+    tables.into_iter().flatten().copied()
+}
+
+/// Check a statement against the opcode tables
+///
+/// `line` has interpolations replaced by `0` and no whitespace inside
+/// operands.
+fn check(line: &str) -> Result<(), String> {
+    let (word, operands) = line.split_once(' ').unwrap_or((line, ""));
+    let word = word.trim_start_matches('.').to_ascii_uppercase();
+    if word == "RST" || syntax::DIRECTIVES.contains(&word.as_str()) {
+        return Ok(());
+    }
+    let operands: Vec<&str> = if operands.is_empty() { Vec::new() } else { operands.split(',').collect() };
+    let mut known = false;
+    for entry in syntaxes().filter(|s| syntax::mnemonic(s) == word) {
+        known = true;
+        if syntax::match_operands(entry, &operands).is_some() {
+            return Ok(());
+        }
+    }
+    if known {
+        Err(format!("invalid operands for {}: {}", word, operands.join(",")))
+    } else {
+        Err(format!("unknown instruction {}", word))
+    }
+}
+
+/// One statement rendered as `emit_asm` text
+#[derive(Default)]
+struct Line {
+    /// `format!` string, with `{}` for each interpolation
+    template: String,
+    /// Text for `check`, with interpolations as `0`
+    check: String,
+    /// Interpolated Rust expressions
+    args: Vec<TokenStream>,
+    /// Whether the last token was a word or number
+    after_word: bool,
+}
+
+impl Line {
+    fn push(&mut self, text: &str, word: bool) {
+        if word && self.after_word {
+            self.template.push(' ');
+            self.check.push(' ');
+        }
+        self.template.push_str(text);
+        self.check.push_str(text);
+        self.after_word = word;
+    }
+
+    fn interpolate(&mut self, arg: TokenStream) {
+        if self.after_word {
+            self.template.push(' ');
+            self.check.push(' ');
+        }
+        self.template.push_str("{}");
+        self.check.push('0');
+        self.args.push(arg);
+        self.after_word = true;
+    }
+
+    fn render(&mut self, tokens: impl IntoIterator<Item = TokenTree>) -> Result<(), (Span, String)> {
+        for token in tokens {
+            match token {
+                TokenTree::Ident(ident) => self.push(&ident.to_string(), true),
+                TokenTree::Punct(punct) => self.push(&punct.as_char().to_string(), false),
+                TokenTree::Literal(lit) => {
+                    // Strings and characters become byte values, so Rust
+                    // processes their escapes
+                    let text = lit.to_string();
+                    let mut value: TokenStream = TokenTree::Group(Group::new(Delimiter::Parenthesis, TokenTree::Literal(lit).into())).into();
+                    if text.starts_with('"') {
+                        value.extend(parse(".bytes().map(|b| b.to_string()).collect::<::std::vec::Vec<_>>().join(\",\")"));
+                        self.interpolate(value);
+                    } else if text.starts_with('\'') {
+                        value.extend(parse(" as u32"));
+                        self.interpolate(value);
+                    } else {
+                        self.push(&text, true);
+                    }
+                }
+                TokenTree::Group(group) => match group.delimiter() {
+                    Delimiter::Parenthesis => {
+                        self.push("(", false);
+                        self.render(group.stream())?;
+                        self.push(")", false);
+                    }
+                    Delimiter::Brace => self.interpolate(group.stream()),
+                    Delimiter::None => self.render(group.stream())?,
+                    Delimiter::Bracket => return Err((group.span(), "unexpected [ in z80!".to_string())),
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render one statement, handling `label name` and `ex af, af`
+fn render_statement(tokens: Vec<TokenTree>) -> Result<Line, (Span, String)> {
+    let span = tokens[0].span();
+    let mut line = Line::default();
+    let mut rest = &tokens[..];
+    if let TokenTree::Ident(ident) = &tokens[0] {
+        if ident.to_string() == "label" && tokens.len() == 2 {
+            line.render(tokens[1..].iter().cloned())?;
+            line.push(":", false);
+            return Ok(line);
+        }
+        if let Some(TokenTree::Punct(p)) = tokens.get(1) {
+            if p.as_char() == ':' && p.spacing() == Spacing::Alone {
+                line.render(tokens[..2].iter().cloned())?;
+                line.push(" ", false);
+                rest = &tokens[2..];
+            }
+        }
+    }
+    let start = line.check.len();
+    if let Some((mnemonic, operands)) = rest.split_first() {
+        line.render([mnemonic.clone()])?;
+        line.push(" ", false);
+        line.render(operands.iter().cloned())?;
+    }
+    if line.check[start..].eq_ignore_ascii_case("ex af,af") {
+        // `AF'` doesn't lex as a Rust token
+        line.push("'", false);
+    }
+    // The check text has a space after the mnemonic, and no other spaces
+    let statement = line.check[start..].to_string();
+    let statement = match statement.split_once(' ') {
+        Some((word, ops)) => format!("{} {}", word, ops.replace(' ', "")),
+        None => statement,
+    };
+    if !statement.is_empty() {
+        check(&statement).map_err(|message| (span, message))?;
+    }
+    Ok(line)
+}
+
+fn compile_error(span: Span, message: &str) -> TokenStream {
+    let mut lit = Literal::string(message);
+    lit.set_span(span);
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    let mut group = Group::new(Delimiter::Parenthesis, TokenTree::Literal(lit).into());
+    group.set_span(span);
+    [
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(group),
+    ]
+    .into_iter()
+    .collect()
+}
+
+fn parse(tokens: &str) -> TokenStream {
+    tokens.parse().unwrap()
+}
+
+/// Z80 assembly statements emitted into a `CodeGen`
+///
+/// ```ignore
+/// z80!(rom, {
+///     label main;
+///     ld a, {count + 1};        // Rust expression
+///     call print_byte_dec;
+///     jr nz, main;
+///     ld (ix + {OFFSET}), 0x20;
+///     ex af, af;                // EX AF,AF'
+///     db "Hi\r\n", 0;
+/// });
+/// ```
+///
+/// Statements are separated by `;`. Instruction syntax is checked at compile
+/// time; values (ranges, 8-bit forward references) are checked when the
+/// statement runs, panicking like the builder methods. `{expr}` interpolates
+/// any `Display` value, so a `&str` can name a label.
+#[proc_macro]
+pub fn z80(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+    let mut rom = Vec::new();
+    for token in tokens.by_ref() {
+        match &token {
+            TokenTree::Punct(p) if p.as_char() == ',' => break,
+            _ => rom.push(token),
+        }
+    }
+    let body = match tokens.next() {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => group,
+        _ => return compile_error(Span::call_site(), "expected z80!(rom, { ... })"),
+    };
+
+    let mut statements = Vec::new();
+    let mut current = Vec::new();
+    for token in body.stream() {
+        match &token {
+            TokenTree::Punct(p) if p.as_char() == ';' => statements.push(std::mem::take(&mut current)),
+            _ => current.push(token),
+        }
+    }
+    statements.push(current);
+
+    let mut out = parse("let __rom = &mut");
+    out.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, rom.into_iter().collect()))]);
+    out.extend(parse(";"));
+    for statement in statements.into_iter().filter(|s| !s.is_empty()) {
+        let line = match render_statement(statement) {
+            Ok(line) => line,
+            Err((span, message)) => return compile_error(span, &message),
+        };
+        let mut args: TokenStream = TokenTree::Literal(Literal::string(&line.template)).into();
+        for arg in line.args {
+            args.extend(parse(","));
+            args.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, arg))]);
+        }
+        let mut text = parse("&::std::format!");
+        text.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, args))]);
+        out.extend(parse("__rom.emit_asm"));
+        out.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, text))]);
+        out.extend(parse(".unwrap_or_else(|e| ::core::panic!(\"z80!: {}\", e.message));"));
+    }
+    TokenTree::Group(Group::new(Delimiter::Brace, out)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert!(syntaxes().any(|s| s == "LD A,(IX+d)"));
+        assert_eq!(check("ld a,0"), Ok(()));
+        assert_eq!(check("LD (ix+0),0"), Ok(()));
+        assert_eq!(check("jr nz,main"), Ok(()));
+        assert_eq!(check("ex af,af'"), Ok(()));
+        assert_eq!(check("db 0"), Ok(()));
+        assert_eq!(check("ld a,hl"), Err("invalid operands for LD: a,hl".to_string()));
+        assert_eq!(check("frob"), Err("unknown instruction FROB".to_string()));
+        assert_eq!(check("mlt bc").is_ok(), cfg!(feature = "z180"));
+    }
+}
@@ -0,0 +1,623 @@
+//! Text assembler
+//!
+//! `emit_asm` assembles Z80 source text into the ROM through the same
+//! label and fixup machinery as the instruction helpers, so pasted listings
+//! and builder calls can be mixed freely. Encodings come from [`OPCODES`]
+//! and the extension tables enabled by features (`z180`, `undocumented`,
+//! ...), so any instruction with a helper can be written as text; extension
+//! instructions need the matching `RomConfig::cpu`.
+//!
+//! ```rust
+//! use retroshield_z80_workbench::prelude::*;
+//!
+//! let mut rom = CodeGen::new();
+//! rom.emit_asm("
+//!         ld a, 5
+//! loop:   dec a          ; count down
+//!         jr nz, loop
+//!         ret
+//! ").unwrap();
+//! assert_eq!(rom.rom(), &[0x3E, 0x05, 0x3D, 0x20, 0xFD, 0xC9]);
+//! ```
+//!
+//! Syntax:
+//! - Labels end with `:`, or start in the first column; `name EQU value`
+//!   defines a constant.
+//! - Numbers: `42`, `0x2A`, `$2A`, `2Ah`, `%101010`, `0b101010`, `'*'`;
+//!   `$` alone is the address of the current instruction.
+//! - Operands are `label`, `number` or sums like `table+2` and `$-3`; at
+//!   most one label may still be undefined (forward references become
+//!   fixups; in an 8-bit operand, to the label's low byte).
+//! - Directives: `ORG` (pads forward with 0xFF), `DB`/`DEFB`/`DEFM`
+//!   (numbers and quoted strings, with a doubled quote standing for the
+//!   quote itself: `'it''s'`), `DW`/`DEFW`, `DS`/`DEFS count[,fill]`,
+//!   `END`.
+//!
+//! `RST` takes the address (`rst 38h`). Instructions without a helper,
+//! such as the CB-prefixed `(IX+d)` forms, are not accepted.
+//!
+//! [`OPCODES`]: crate::opcodes::OPCODES
+
+use std::fmt;
+
+use retroshield_z80_syntax::{match_operands, mnemonic, DIRECTIVES, RESERVED};
+
+use crate::opcodes::{self, Opcode, Operand};
+use crate::CodeGen;
+
+/// Error from [`CodeGen::emit_asm`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// 1-based line number in the source text
+    pub line: usize,
+    /// What was wrong with the line
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Value of an operand expression
+enum Value {
+    Const(i32),
+    /// A label not defined yet, plus an offset
+    Label(String, i32),
+}
+
+fn is_mnemonic(word: &str) -> bool {
+    let word = word.trim_start_matches('.').to_ascii_uppercase();
+    word == "RST"
+        || DIRECTIVES.contains(&word.as_str())
+        || opcodes::tables().into_iter().flatten().any(|op| mnemonic(op.syntax) == word)
+}
+
+/// Byte positions in `text` that are outside quotes
+///
+/// `'` and `"` both quote; the `'` of `AF'` does not.
+fn unquoted(text: &str) -> Vec<(usize, char)> {
+    let mut out = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut prev: [char; 2] = [' ', ' '];
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' => quote = Some(c),
+            None if c == '\''
+                && !(prev[0].eq_ignore_ascii_case(&'A') && prev[1].eq_ignore_ascii_case(&'F')) =>
+            {
+                quote = Some(c)
+            }
+            None => out.push((i, c)),
+        }
+        prev = [prev[1], c];
+    }
+    out
+}
+
+/// Strip a `;` comment
+fn strip_comment(line: &str) -> &str {
+    match unquoted(line).into_iter().find(|&(_, c)| c == ';') {
+        Some((i, _)) => &line[..i],
+        None => line,
+    }
+}
+
+/// Split operands at top-level commas
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, c) in unquoted(text) {
+        if c == ',' {
+            parts.push(text[start..i].trim());
+            start = i + 1;
+        }
+    }
+    parts.push(text[start..].trim());
+    parts
+}
+
+/// Parse a number literal
+fn parse_number(token: &str) -> Option<i32> {
+    let lower = token.to_ascii_lowercase();
+    let (digits, radix) = if let Some(hex) = lower.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        (bin, 2)
+    } else if let Some(hex) = lower.strip_prefix('$') {
+        (hex, 16)
+    } else if let Some(bin) = lower.strip_prefix('%') {
+        (bin, 2)
+    } else if let Some(hex) = lower.strip_suffix('h') {
+        (hex, 16)
+    } else if lower.ends_with('b') && lower[..lower.len() - 1].chars().all(|c| c == '0' || c == '1') {
+        (&lower[..lower.len() - 1], 2)
+    } else {
+        (lower.as_str(), 10)
+    };
+    i32::from_str_radix(digits, radix).ok()
+}
+
+impl CodeGen {
+    /// Assemble Z80 source text at the current position
+    ///
+    /// Stops at the first bad line; the lines before it have been emitted.
+    pub fn emit_asm(&mut self, source: &str) -> Result<(), AsmError> {
+        for (i, line) in source.lines().enumerate() {
+            match self.asm_line(line) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(message) => return Err(AsmError { line: i + 1, message }),
+            }
+        }
+        Ok(())
+    }
+
+    /// Assemble one line; returns true at `END`
+    fn asm_line(&mut self, line: &str) -> Result<bool, String> {
+        let code = strip_comment(line).trim_end();
+        if code.trim().is_empty() {
+            return Ok(false);
+        }
+
+        // Label: `name:` anywhere at the start, or a non-mnemonic in column 1
+        let mut label = None;
+        let mut rest = code.trim_start();
+        let first_len = rest
+            .find(|c: char| c.is_whitespace() || c == ':')
+            .unwrap_or(rest.len());
+        let first = &rest[..first_len];
+        if rest[first_len..].starts_with(':') {
+            label = Some(first);
+            rest = &rest[first_len + 1..];
+        } else if !code.starts_with(char::is_whitespace) && !is_mnemonic(first) {
+            label = Some(first);
+            rest = &rest[first_len..];
+        }
+        let rest = rest.trim();
+        let (word, operands) = match rest.find(char::is_whitespace) {
+            Some(i) => (&rest[..i], rest[i..].trim()),
+            None => (rest, ""),
+        };
+        let word = word.trim_start_matches('.').to_ascii_uppercase();
+        let operands = split_operands(operands);
+
+        if let Some(name) = label {
+            if name.is_empty()
+                || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+                || name.starts_with(|c: char| c.is_ascii_digit())
+            {
+                return Err(format!("invalid label {:?}", name));
+            }
+            if RESERVED.contains(&name.to_ascii_uppercase().as_str()) {
+                return Err(format!("{} is a register name", name));
+            }
+            if self.has_label(name) {
+                return Err(format!("duplicate label {}", name));
+            }
+            if word == "EQU" {
+                let value = self.asm_const(&operands, 0)?;
+                self.equ(name, value as u16);
+                return Ok(false);
+            }
+            self.label(name);
+        }
+
+        let pc = self.pos();
+        match word.as_str() {
+            "" => {}
+            "EQU" => return Err("EQU needs a label".to_string()),
+            "END" => return Ok(true),
+            "ORG" => {
+                let addr = self.asm_const(&operands, 0)?;
+                if !(pc as i32..=0xFFFF).contains(&addr) {
+                    return Err(format!("ORG {:#06X} is behind the current address {:#06X}", addr, pc));
+                }
+                self.pad_to(addr as u16, 0xFF);
+            }
+            "DB" | "DEFB" | "DEFM" => {
+                for op in &operands {
+                    let quoted = op.len() >= 2
+                        && (op.starts_with('"') && op.ends_with('"')
+                            || op.starts_with('\'') && op.ends_with('\''));
+                    if quoted && op.len() != 3 {
+                        let quote = &op[..1];
+                        let text = op[1..op.len() - 1].replace(&quote.repeat(2), quote);
+                        self.emit(text.as_bytes());
+                    } else {
+                        self.asm_byte_emit(op, pc)?;
+                    }
+                }
+            }
+            "DW" | "DEFW" => {
+                for op in &operands {
+                    self.asm_word(op, pc)?;
+                }
+            }
+            "DS" | "DEFS" => {
+                let count = self.asm_const(&operands[..operands.len().min(1)], 0)?;
+                let fill = match operands.get(1) {
+                    Some(op) => self.asm_byte(op, pc)?,
+                    None => 0,
+                };
+                if !(0..=0xFFFF).contains(&count) {
+                    return Err(format!("bad DS size {}", count));
+                }
+                self.emit(&vec![fill; count as usize]);
+            }
+            "RST" => {
+                let n = self.asm_const(&operands, pc)?;
+                if !(0..=0x38).contains(&n) || n % 8 != 0 {
+                    return Err(format!("bad RST address {:#04X}", n));
+                }
+                self.rst(n as u8);
+            }
+            _ => self.asm_instruction(&word, &operands, pc)?,
+        }
+        Ok(false)
+    }
+
+    /// Assemble an instruction from the opcode table
+    fn asm_instruction(&mut self, word: &str, operands: &[&str], pc: u16) -> Result<(), String> {
+        let mut known = false;
+        for op in opcodes::tables().into_iter().flatten() {
+            if mnemonic(op.syntax) != word {
+                continue;
+            }
+            known = true;
+            if let Some(caps) = match_operands(op.syntax, operands) {
+                return self.asm_emit(op, &caps, pc);
+            }
+        }
+        if known {
+            Err(format!("invalid operands for {}: {}", word, operands.join(",")))
+        } else {
+            Err(format!("unknown instruction {}", word))
+        }
+    }
+
+    /// Emit a matched opcode with its operand values
+    fn asm_emit(&mut self, op: &Opcode, caps: &[String], pc: u16) -> Result<(), String> {
+        if let Some(cpu) = op.cpu {
+            if self.config().cpu != cpu {
+                return Err(format!("{} requires the {:?} CPU profile", op.syntax, cpu));
+            }
+        }
+        self.check_instruction(op.syntax);
+        match op.operand {
+            Operand::None => self.emit(op.encoding),
+            Operand::Imm8 => {
+                self.emit(op.encoding);
+                self.asm_byte_emit(&caps[0], pc)?;
+            }
+            Operand::Imm16 => {
+                self.emit(op.encoding);
+                self.asm_word(&caps[0], pc)?;
+            }
+            Operand::Rel8 => {
+                let target = self.asm_eval(&caps[0], pc)?;
+                self.emit(op.encoding);
+                match target {
+                    Value::Const(addr) => {
+                        let disp = addr - (self.pos() as i32 + 1);
+                        if !(-128..=127).contains(&disp) {
+                            return Err(format!("relative jump out of range ({} bytes)", disp));
+                        }
+                        self.emit_byte(disp as u8);
+                    }
+                    Value::Label(name, offset) => self.fixup_rel(&name, offset),
+                }
+            }
+            Operand::Bit => {
+                let bit = self.asm_const(&[caps[0].as_str()], pc)?;
+                if !(0..=7).contains(&bit) {
+                    return Err(format!("bit number {} out of range", bit));
+                }
+                let mut bytes = op.encoding.to_vec();
+                *bytes.last_mut().unwrap() |= (bit as u8) << 3;
+                self.emit(&bytes);
+            }
+            Operand::Disp | Operand::DispImm8 => {
+                let d = self.asm_const(&[caps[0].as_str()], pc)?;
+                if !(-128..=127).contains(&d) {
+                    return Err(format!("index displacement {} out of range", d));
+                }
+                self.emit(op.encoding);
+                self.emit_byte(d as u8);
+                if op.operand == Operand::DispImm8 {
+                    self.asm_byte_emit(&caps[1], pc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluate a single operand that must be known now
+    fn asm_const(&self, operands: &[&str], pc: u16) -> Result<i32, String> {
+        let [text] = operands else {
+            return Err("expected one operand".to_string());
+        };
+        match self.asm_eval(text, pc)? {
+            Value::Const(v) => Ok(v),
+            Value::Label(name, _) => Err(format!("{} must be defined before this line", name)),
+        }
+    }
+
+    /// Evaluate an 8-bit operand
+    fn asm_byte(&self, text: &str, pc: u16) -> Result<u8, String> {
+        match self.asm_eval(text, pc)? {
+            Value::Const(v) if (-128..=255).contains(&v) => Ok(v as u8),
+            Value::Const(v) => Err(format!("{} does not fit in a byte", v)),
+            Value::Label(name, _) => {
+                Err(format!("forward reference to {} in an 8-bit operand", name))
+            }
+        }
+    }
+
+    /// Emit an 8-bit operand, as a fixup to the low byte if it needs an
+    /// undefined label
+    fn asm_byte_emit(&mut self, text: &str, pc: u16) -> Result<(), String> {
+        match self.asm_eval(text, pc)? {
+            Value::Label(name, offset) => self.fixup_lo_expr(&name, offset),
+            Value::Const(_) => {
+                let n = self.asm_byte(text, pc)?;
+                self.emit_byte(n);
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit a 16-bit operand, as a fixup if it needs an undefined label
+    fn asm_word(&mut self, text: &str, pc: u16) -> Result<(), String> {
+        match self.asm_eval(text, pc)? {
+            Value::Const(v) if (-32768..=0xFFFF).contains(&v) => self.emit_word(v as u16),
+            Value::Const(v) => return Err(format!("{} does not fit in a word", v)),
+            Value::Label(name, offset) => self.fixup_expr(&name, offset),
+        }
+        Ok(())
+    }
+
+    /// Evaluate a sum of numbers, characters, symbols and `$`
+    fn asm_eval(&self, text: &str, pc: u16) -> Result<Value, String> {
+        let text = text.trim();
+        let bytes = text.as_bytes();
+        let mut total: i32 = 0;
+        let mut pending: Option<String> = None;
+        let mut i = 0;
+        let mut first = true;
+        while i < bytes.len() {
+            // Sign
+            let mut negative = false;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            match bytes.get(i) {
+                Some(b'+') => i += 1,
+                Some(b'-') => {
+                    negative = true;
+                    i += 1;
+                }
+                _ if !first => return Err(format!("bad expression {:?}", text)),
+                _ => {}
+            }
+            first = false;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+
+            // Term
+            let start = i;
+            let value = match bytes.get(i) {
+                Some(b'\'') if bytes.get(i + 2) == Some(&b'\'') => {
+                    i += 3;
+                    Some(bytes[start + 1] as i32)
+                }
+                Some(b'$') if !matches!(bytes.get(i + 1), Some(b) if b.is_ascii_hexdigit()) => {
+                    i += 1;
+                    Some(pc as i32)
+                }
+                Some(c) if c.is_ascii_alphanumeric() || matches!(c, b'_' | b'.' | b'$' | b'%') => {
+                    i += 1;
+                    while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'_' | b'.')) {
+                        i += 1;
+                    }
+                    let token = &text[start..i];
+                    if token.starts_with(|c: char| c.is_ascii_digit() || c == '$' || c == '%') {
+                        Some(parse_number(token).ok_or_else(|| format!("bad number {}", token))?)
+                    } else if let Some(addr) = self.get_label(token) {
+                        Some(addr as i32)
+                    } else if negative || pending.is_some() {
+                        return Err(format!("undefined symbol {} in {:?}", token, text));
+                    } else {
+                        pending = Some(token.to_string());
+                        None
+                    }
+                }
+                _ => return Err(format!("bad expression {:?}", text)),
+            };
+            if let Some(v) = value {
+                total = if negative { total - v } else { total + v };
+            }
+        }
+        if first {
+            return Err("missing operand".to_string());
+        }
+        Ok(match pending {
+            Some(name) => Value::Label(name, total),
+            None => Value::Const(total),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_builder() {
+        let mut asm = CodeGen::new();
+        asm.emit_asm(
+            "
+start:  ld sp, 3FFFh
+        ld hl, msg      ; forward reference
+        ld (ix-2), 0x41
+        ld a, (ix)
+        bit 7, (hl)
+        jr z, done
+        add a, 'a'-'A'
+        out (81h), a
+        ex af, af'
+done:   jp start
+msg:    db \"Hi\", 13, 10, 0
+        dw msg+1, $
+",
+        )
+        .unwrap();
+        asm.resolve_fixups();
+
+        let mut cg = CodeGen::new();
+        cg.label("start");
+        cg.ld_sp(0x3FFF);
+        cg.ld_hl_label("msg");
+        cg.ld_ix_ind_n(-2, 0x41);
+        cg.ld_a_ix_ind(0);
+        cg.bit_hl_ind(7);
+        cg.emit(&[0x28, 0x05]);      // JR Z, done
+        cg.add_a(0x20);
+        cg.out_a(0x81);
+        cg.ex_af();
+        cg.label("done");
+        cg.jp("start");
+        cg.label("msg");
+        cg.emit_string("Hi\r\n");
+        cg.fixup_expr("msg", 1);
+        cg.emit_word(0x001E);
+        cg.resolve_fixups();
+        assert_eq!(asm.rom(), cg.rom());
+    }
+
+    #[test]
+    fn test_directives() {
+        let mut cg = CodeGen::new();
+        cg.emit_asm(
+            "
+WIDTH   equ 40
+        ld b, WIDTH-8
+        ds 3, 0xAA
+        org 8
+        rst 38h
+        defb %101, 0b11, 1010b
+        end
+        this is never assembled
+",
+        )
+        .unwrap();
+        assert_eq!(cg.rom(), &[
+            0x06, 0x20,              // LD B, WIDTH-8
+            0xAA, 0xAA, 0xAA,        // DS 3, 0xAA
+            0xFF, 0xFF, 0xFF,        // ORG 8
+            0xFF,                    // RST 38H
+            0x05, 0x03, 0x0A,        // DEFB
+        ]);
+        assert_eq!(cg.get_label("WIDTH"), Some(40));
+    }
+
+    #[test]
+    fn test_forward_byte_operands() {
+        let mut cg = CodeGen::new();
+        cg.emit_asm(
+            r#"
+        ld a, COUNT
+        ld (ix+1), COUNT+1
+        cp msg
+        db 'it''s', "say ""hi""", ''''
+        db COUNT-2
+msg:    nop
+COUNT   equ 5
+"#,
+        )
+        .unwrap();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0x3E, 0x05,              // LD A, COUNT
+            0xDD, 0x36, 0x01, 0x06,  // LD (IX+1), COUNT+1
+            0xFE, 0x16,              // CP msg (low byte)
+            b'i', b't', b'\'', b's',
+            b's', b'a', b'y', b' ', b'"', b'h', b'i', b'"',
+            b'\'',
+            0x03,                    // DB COUNT-2
+            0x00,                    // msg: NOP
+        ]);
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn test_z80_macro() {
+        use crate::z80;
+
+        const OFFSET: i8 = -2;
+        let count = 9;
+        let target = "print_byte_dec";
+        let mut cg = CodeGen::new();
+        z80!(cg, {
+            label main;
+            ld a, {count + 1};
+            call {target};
+            ld (ix + {OFFSET}), 0FFh;
+            ex af, af;
+        next: djnz next;
+            jp main;
+            db "Hi\r\n", 'x';
+        });
+        cg.label("print_byte_dec");
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0x3E, 0x0A,              // LD A, 10
+            0xCD, 0x14, 0x00,        // CALL print_byte_dec
+            0xDD, 0x36, 0xFE, 0xFF,  // LD (IX-2), 0xFF
+            0x08,                    // EX AF, AF'
+            0x10, 0xFE,              // DJNZ next
+            0xC3, 0x00, 0x00,        // JP main
+            b'H', b'i', 0x0D, 0x0A, b'x',
+        ]);
+    }
+
+    #[cfg(feature = "z180")]
+    #[test]
+    fn test_extension_instructions() {
+        use crate::{CpuProfile, RomConfig};
+
+        let mut cg = CodeGen::with_config(RomConfig { cpu: CpuProfile::Z180, ..Default::default() });
+        cg.emit_asm(" mlt bc\n out0 (12h), a").unwrap();
+        let mut expected = vec![0xED, 0x4C, 0xED, 0x39, 0x12];
+        #[cfg(feature = "macros")]
+        {
+            crate::z80!(cg, { mlt de; });
+            expected.extend([0xED, 0x5C]);
+        }
+        assert_eq!(cg.rom(), &expected[..]);
+        assert_eq!(
+            CodeGen::new().emit_asm(" mlt bc").unwrap_err().message,
+            "MLT BC requires the Z180 CPU profile"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |src: &str| CodeGen::new().emit_asm(src).unwrap_err();
+        assert_eq!(err(" nop\n frob a").to_string(), "line 2: unknown instruction FROB");
+        assert_eq!(err(" ld a, hl").message, "invalid operands for LD: a,hl");
+        assert_eq!(err(" ds 2, later").message, "forward reference to later in an 8-bit operand");
+        assert_eq!(err("x: nop\nx: nop").message, "duplicate label x");
+        assert_eq!(err(" bit 8, a").message, "bit number 8 out of range");
+        assert_eq!(err("back: ds 200\n jr back").message, "relative jump out of range (-202 bytes)");
+    }
+}
@@ -0,0 +1,300 @@
+//! Bitmaps and fonts converted to display byte layouts
+//!
+//! A `Bitmap` is a 1-bit image built from ASCII art, packed bytes or
+//! (with the `png` feature) a PNG file. `to_bytes` cuts it into cells
+//! left to right, top to bottom, and packs each cell the way the display
+//! wants it, so a font is a strip or sheet of glyphs in character order:
+//!
+//! ```rust
+//! use retroshield_z80_workbench::prelude::*;
+//! use retroshield_z80_workbench::asset::{Bitmap, Layout};
+//!
+//! let arrow = Bitmap::from_rows(&[
+//!     "...#....",
+//!     "..##....",
+//!     ".#######",
+//!     "########",
+//!     ".#######",
+//!     "..##....",
+//!     "...#....",
+//!     "........",
+//! ]);
+//! let mut rom = CodeGen::new();
+//! rom.emit_bitmap("arrow", &arrow, Layout::Tms9918Pattern);
+//! assert_eq!(rom.get_label("arrow_size"), Some(8));
+//! ```
+
+use crate::CodeGen;
+
+/// Byte layout of a display's character or sprite patterns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// TMS9918 graphics-mode patterns: 8x8 cells, a byte per row,
+    /// leftmost pixel in bit 7
+    Tms9918Pattern,
+    /// TMS9918 text-mode patterns: 6x8 cells in bits 7-2 of each row
+    Tms9918Text,
+    /// TMS9918 16x16 sprites: 32 bytes per cell, as the 8x8 quadrants top
+    /// left, bottom left, top right, bottom right
+    Tms9918Sprite16,
+    /// HD44780 LCD CGRAM characters: 5x8 cells in bits 4-0 of each row
+    Hd44780,
+}
+
+impl Layout {
+    /// Cell width and height in pixels
+    pub fn cell_size(self) -> (usize, usize) {
+        match self {
+            Layout::Tms9918Pattern => (8, 8),
+            Layout::Tms9918Text => (6, 8),
+            Layout::Tms9918Sprite16 => (16, 16),
+            Layout::Hd44780 => (5, 8),
+        }
+    }
+}
+
+/// 1-bit image, set pixels are foreground
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    /// Blank bitmap
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    /// Bitmap from ASCII art, one string per row: `#`, `X`, `*` and `@`
+    /// are set, anything else is clear
+    pub fn from_rows(rows: &[&str]) -> Self {
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        let mut bitmap = Self::new(width, rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            assert_eq!(row.chars().count(), width, "row {} is not {} pixels wide", y, width);
+            for (x, c) in row.chars().enumerate() {
+                bitmap.set(x, y, matches!(c, '#' | 'X' | '*' | '@'));
+            }
+        }
+        bitmap
+    }
+
+    /// Bitmap from packed rows, leftmost pixel in bit 7 and each row
+    /// padded to a whole byte, as in most C font arrays
+    ///
+    /// An array of 8x8 glyphs is `from_bytes(8, 8 * count, ...)`: a
+    /// column of cells, which `to_bytes` keeps in order.
+    pub fn from_bytes(width: usize, height: usize, bytes: &[u8]) -> Self {
+        let stride = (width + 7) / 8;
+        assert_eq!(bytes.len(), stride * height, "{}x{} bitmap needs {} bytes", width, height, stride * height);
+        let mut bitmap = Self::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                bitmap.set(x, y, bytes[y * stride + x / 8] & (0x80 >> (x % 8)) != 0);
+            }
+        }
+        bitmap
+    }
+
+    /// Bitmap from a PNG file's contents: pixels brighter than half
+    /// intensity are set, transparent ones are clear
+    #[cfg(feature = "png")]
+    pub fn from_png(data: &[u8]) -> Result<Self, png::DecodingError> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let channels = info.color_type.samples();
+        let mut bitmap = Self::new(info.width as usize, info.height as usize);
+        for (i, px) in buf[..info.buffer_size()].chunks(channels).enumerate() {
+            let (level, alpha) = match px {
+                [l] => (*l as u32, 255),
+                [l, a] => (*l as u32, *a),
+                [r, g, b] => ((*r as u32 * 3 + *g as u32 * 6 + *b as u32) / 10, 255),
+                [r, g, b, a] => ((*r as u32 * 3 + *g as u32 * 6 + *b as u32) / 10, *a),
+                _ => unreachable!(),
+            };
+            bitmap.pixels[i] = level >= 128 && alpha >= 128;
+        }
+        Ok(bitmap)
+    }
+
+    /// Glyphs placed side by side, e.g. a font in character order
+    pub fn strip(glyphs: &[Bitmap]) -> Self {
+        let height = glyphs.first().map_or(0, |g| g.height);
+        let mut bitmap = Self::new(glyphs.iter().map(|g| g.width).sum(), height);
+        let mut left = 0;
+        for (i, glyph) in glyphs.iter().enumerate() {
+            assert_eq!(glyph.height, height, "glyph {} is not {} pixels high", i, height);
+            for y in 0..height {
+                for x in 0..glyph.width {
+                    bitmap.set(left + x, y, glyph.get(x, y));
+                }
+            }
+            left += glyph.width;
+        }
+        bitmap
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Pixel at (x, y); outside the bitmap is clear
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, on: bool) {
+        assert!(x < self.width && y < self.height, "({}, {}) is outside the bitmap", x, y);
+        self.pixels[y * self.width + x] = on;
+    }
+
+    /// Number of `layout` cells in the bitmap
+    pub fn cells(&self, layout: Layout) -> usize {
+        let (w, h) = layout.cell_size();
+        (self.width / w) * (self.height / h)
+    }
+
+    /// Pack the bitmap's cells, left to right then top to bottom
+    ///
+    /// Panics if the size is not a whole number of cells.
+    pub fn to_bytes(&self, layout: Layout) -> Vec<u8> {
+        let (w, h) = layout.cell_size();
+        assert!(
+            self.width % w == 0 && self.height % h == 0,
+            "{}x{} bitmap is not a whole number of {}x{} cells",
+            self.width,
+            self.height,
+            w,
+            h
+        );
+        let mut out = Vec::new();
+        for y in (0..self.height).step_by(h) {
+            for x in (0..self.width).step_by(w) {
+                match layout {
+                    Layout::Tms9918Sprite16 => {
+                        for (qx, qy) in [(0, 0), (0, 8), (8, 0), (8, 8)] {
+                            self.pack_rows(&mut out, x + qx, y + qy, 8, 7);
+                        }
+                    }
+                    Layout::Hd44780 => self.pack_rows(&mut out, x, y, 5, 4),
+                    _ => self.pack_rows(&mut out, x, y, w, 7),
+                }
+            }
+        }
+        out
+    }
+
+    /// Pack 8 rows of `width` pixels at (x, y), leftmost in bit `top`
+    fn pack_rows(&self, out: &mut Vec<u8>, x: usize, y: usize, width: usize, top: usize) {
+        for row in y..y + 8 {
+            let bits = (0..width).filter(|&i| self.get(x + i, row)).fold(0, |b, i| b | 1 << (top - i));
+            out.push(bits);
+        }
+    }
+}
+
+impl CodeGen {
+    /// Emit `bitmap` packed for `layout` at `label`; returns the size in
+    /// bytes
+    ///
+    /// `<label>_size` is the byte count, ready for `vdp_load`'s length or
+    /// `ld_bc_label`; `<label>_count` is the number of cells.
+    ///
+    /// Labels created: `label`, `<label>_size`, `<label>_count` (equ)
+    pub fn emit_bitmap(&mut self, label: &str, bitmap: &Bitmap, layout: Layout) -> usize {
+        let bytes = bitmap.to_bytes(layout);
+        self.label(label);
+        self.emit(&bytes);
+        self.equ(&format!("{}_size", label), bytes.len() as u16);
+        self.equ(&format!("{}_count", label), bitmap.cells(layout) as u16);
+        bytes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(c: char) -> Bitmap {
+        match c {
+            'A' => Bitmap::from_rows(&[
+                "..#..", ".#.#.", "#...#", "#...#", "#####", "#...#", "#...#", ".....",
+            ]),
+            _ => Bitmap::from_rows(&[
+                "####.", "#...#", "#...#", "####.", "#...#", "#...#", "####.", ".....",
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_font_layouts() {
+        let font = Bitmap::strip(&[glyph('A'), glyph('B')]);
+        assert_eq!(font.to_bytes(Layout::Hd44780), [
+            0x04, 0x0A, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x00,
+            0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E, 0x00,
+        ]);
+
+        // Text mode: pad the 5-pixel glyphs to 6 with a blank column
+        let blank = Bitmap::new(1, 8);
+        let font = Bitmap::strip(&[glyph('A'), blank.clone(), glyph('B'), blank]);
+        assert_eq!(font.cells(Layout::Tms9918Text), 2);
+        assert_eq!(&font.to_bytes(Layout::Tms9918Text)[..8], &[0x20, 0x50, 0x88, 0x88, 0xF8, 0x88, 0x88, 0x00]);
+
+        let bytes = [0x18, 0x3C, 0x66, 0x7E, 0x66, 0x66, 0x66, 0x00, 0xFF, 0, 0, 0, 0, 0, 0, 0x81];
+        let column = Bitmap::from_bytes(8, 16, &bytes);
+        assert_eq!(column.to_bytes(Layout::Tms9918Pattern), bytes);
+    }
+
+    #[test]
+    fn test_sprite_quadrants() {
+        let mut sprite = Bitmap::new(16, 16);
+        sprite.set(0, 0, true);      // Top left
+        sprite.set(15, 0, true);     // Top right
+        sprite.set(0, 15, true);     // Bottom left
+        let bytes = sprite.to_bytes(Layout::Tms9918Sprite16);
+        assert_eq!(bytes.len(), 32);
+        assert_eq!((bytes[0], bytes[15], bytes[16], bytes[31]), (0x80, 0x80, 0x01, 0x00));
+    }
+
+    #[test]
+    fn test_emit_bitmap() {
+        let mut cg = CodeGen::new();
+        cg.emit_bitmap("font", &Bitmap::strip(&[glyph('A'), glyph('B')]), Layout::Hd44780);
+        assert_eq!(cg.size(), 16);
+        assert_eq!(cg.get_label("font"), Some(0));
+        assert_eq!(cg.get_label("font_size"), Some(16));
+        assert_eq!(cg.get_label("font_count"), Some(2));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_from_png() {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, 3, 2);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0xFF, 0x00, 0x80, 0x7F, 0xFF, 0x00]).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(Bitmap::from_png(&data).unwrap(), Bitmap::from_rows(&["#.#", ".#."]));
+    }
+
+    #[test]
+    #[should_panic(expected = "not a whole number of 8x8 cells")]
+    fn test_partial_cell() {
+        Bitmap::new(12, 8).to_bytes(Layout::Tms9918Pattern);
+    }
+}
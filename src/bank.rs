@@ -0,0 +1,358 @@
+//! Bank-switched ROMs
+//!
+//! A [`BankedRom`] holds a common area, always mapped, and a set of banks
+//! that share one switched window selected by writing the bank number to an
+//! output port. Each area is its own `CodeGen`; `link` generates the
+//! cross-bank call stubs, resolves labels between the areas and lays out
+//! the physical ROM.
+//!
+//! ```rust
+//! use retroshield_z80_workbench::prelude::*;
+//! use retroshield_z80_workbench::bank::{BankConfig, BankedRom};
+//!
+//! let mut rom = BankedRom::new(RomConfig::default(), BankConfig {
+//!     window: 0x4000,
+//!     size: 0x4000,
+//!     count: 2,
+//!     port: 0x40,
+//! });
+//! rom.common().emit_startup(0x3FFF);
+//! rom.common().call("bank_init");
+//! rom.common().far_call("greet");  // Switches to bank 1 and back
+//! rom.common().halt();
+//!
+//! rom.bank(1).label("greet");
+//! rom.bank(1).ld_a(b'!');
+//! rom.bank(1).out_a(0x81);
+//! rom.bank(1).ret();
+//!
+//! let image = rom.link("game").unwrap();
+//! assert_eq!(image.bytes.len(), 0x4000 + 2 * 0x4000);
+//! ```
+//!
+//! `far_call("x")` calls a stub `far_x` in the common area, which saves the
+//! current bank, selects the one holding `x`, calls it and switches back,
+//! preserving every register both ways. Code in a bank can call its own
+//! labels and the common area directly; labels in other banks need
+//! `far_call`. The common area may reference any bank's labels directly
+//! once it has selected that bank with `bank_select`.
+//!
+//! Allocate RAM variables in the common area: every bank starts with the
+//! common area's `ram_start`, and overlapping RAM is a link error. Stubs
+//! switch banks with interrupts enabled, so interrupt handlers must live in
+//! the common area and leave the bank alone.
+
+use crate::project::{ProjectError, RomImage, UnitPlacement};
+use crate::{CodeGen, RomConfig};
+
+/// Bank window and select port
+#[derive(Clone, Debug)]
+pub struct BankConfig {
+    /// Address the selected bank appears at
+    pub window: u16,
+    /// Size of the window and of each bank in bytes
+    pub size: u16,
+    /// Number of banks
+    pub count: u8,
+    /// Output port the bank number is written to
+    pub port: u8,
+}
+
+/// Common area plus switched banks, linked into one physical ROM
+pub struct BankedRom {
+    config: BankConfig,
+    common: CodeGen,
+    banks: Vec<CodeGen>,
+}
+
+impl BankedRom {
+    /// Create the common area with `config` (from `org` up to the window)
+    /// and `banks.count` empty banks at the window
+    pub fn new(config: RomConfig, banks: BankConfig) -> Self {
+        assert!(banks.count > 0, "need at least one bank");
+        assert!(config.org < banks.window, "the common area must start below the bank window");
+        assert!(
+            banks.window as u32 + banks.size as u32 <= 0x10000,
+            "bank window extends past 0xFFFF"
+        );
+        let bank_config = RomConfig {
+            org: banks.window,
+            ..config.clone()
+        };
+        let mut common = CodeGen::with_config(config);
+        common.ram_var("bank_current", 1);
+        Self {
+            banks: (0..banks.count).map(|_| CodeGen::with_config(bank_config.clone())).collect(),
+            config: banks,
+            common,
+        }
+    }
+
+    /// The always-mapped common area
+    pub fn common(&mut self) -> &mut CodeGen {
+        &mut self.common
+    }
+
+    /// Bank `n`
+    pub fn bank(&mut self, n: u8) -> &mut CodeGen {
+        assert!(n < self.config.count, "bank {} does not exist", n);
+        &mut self.banks[n as usize]
+    }
+
+    /// Bank switching routines, placed at the end of the common area
+    ///
+    /// - `bank_select` switches to bank A. Preserves all registers.
+    /// - `bank_init` switches to bank 0; call once at startup.
+    /// - `bank_return` is the shared tail of the `far_` stubs.
+    fn emit_bank_runtime(&mut self) {
+        let port = self.config.port;
+        let current = self.common.get_label("bank_current").unwrap();
+        let cg = &mut self.common;
+
+        cg.label("bank_init");
+        cg.xor_a();
+        cg.label("bank_select");
+        cg.ld_addr_a(current);
+        cg.out_a(port);
+        cg.ret();
+
+        // Stack: caller's HL, with the caller's bank in L below the return
+        cg.label("bank_return");
+        cg.ex_sp_hl();               // L = caller's bank, HL result saved
+        cg.push_af();
+        cg.ld_a_l();
+        cg.call("bank_select");
+        cg.pop_af();
+        cg.pop_hl();
+        cg.ret();
+    }
+
+    /// Emit the stub `far_<label>` calling `label` in bank `bank`
+    fn emit_far_stub(&mut self, label: &str, bank: u8) {
+        let current = self.common.get_label("bank_current").unwrap();
+        let cg = &mut self.common;
+        cg.label(&format!("far_{}", label));
+        cg.push_hl();
+        cg.ld_hl_addr(current);
+        cg.ex_sp_hl();               // Save the caller's bank, restore HL
+        cg.push_af();
+        cg.ld_a(bank);
+        cg.call("bank_select");
+        cg.pop_af();
+        cg.call(label);
+        cg.jp("bank_return");
+    }
+
+    /// Generate the far-call stubs, resolve every area and lay out the
+    /// physical ROM: the common area padded up to the window, then each
+    /// bank padded to the bank size
+    ///
+    /// In the returned image, `org` is the common area's and bank labels
+    /// have their window addresses.
+    pub fn link(mut self, name: &str) -> Result<RomImage, ProjectError> {
+        let unit_name = |i: usize| if i == 0 { "common".to_string() } else { format!("bank{}", i - 1) };
+
+        // Exports of every area: index 0 is common, then the banks
+        let mut exports: Vec<(String, u16, usize)> = Vec::new();
+        let areas: Vec<&CodeGen> = std::iter::once(&self.common).chain(&self.banks).collect();
+        for (i, cg) in areas.iter().enumerate() {
+            for (label, addr) in cg.labels().filter(|(l, _)| !l.starts_with('_')) {
+                if let Some((_, _, j)) = exports.iter().find(|(l, _, _)| l == label) {
+                    return Err(ProjectError::DuplicateSymbol {
+                        name: label.to_string(),
+                        first: unit_name(*j),
+                        second: unit_name(i),
+                    });
+                }
+                exports.push((label.to_string(), addr, i));
+            }
+        }
+
+        // Stubs for every far_ reference to a banked label
+        let mut stubs: Vec<(String, u8)> = Vec::new();
+        let mut areas: Vec<&mut CodeGen> = std::iter::once(&mut self.common).chain(&mut self.banks).collect();
+        for cg in areas.iter_mut() {
+            for missing in cg.undefined_labels() {
+                let Some(target) = missing.strip_prefix("far_") else { continue };
+                if let Some((_, _, i)) = exports.iter().find(|(l, _, i)| l == target && *i > 0) {
+                    if !stubs.iter().any(|(l, _)| l == target) {
+                        stubs.push((target.to_string(), (*i - 1) as u8));
+                    }
+                }
+            }
+        }
+        self.emit_bank_runtime();
+        for (label, bank) in &stubs {
+            self.emit_far_stub(label, *bank);
+        }
+        for (label, addr) in self.common.labels().filter(|(l, _)| l.starts_with("far_") || l.starts_with("bank_")) {
+            if !exports.iter().any(|(l, _, _)| l == label) {
+                exports.push((label.to_string(), addr, 0));
+            }
+        }
+
+        // Common sees every label, banks see common and their own
+        let mut areas: Vec<&mut CodeGen> = std::iter::once(&mut self.common).chain(&mut self.banks).collect();
+        for (i, cg) in areas.iter_mut().enumerate() {
+            for (label, addr, j) in &exports {
+                if (i == 0 || *j == 0) && !cg.has_label(label) {
+                    cg.equ(label, *addr);
+                }
+            }
+            if let Some(missing) = cg.undefined_labels().into_iter().next() {
+                if exports.iter().any(|(l, _, _)| *l == missing) {
+                    return Err(ProjectError::CrossBank {
+                        unit: unit_name(i),
+                        name: missing,
+                    });
+                }
+                return Err(ProjectError::Undefined {
+                    unit: unit_name(i),
+                    name: missing,
+                });
+            }
+            cg.resolve_fixups();
+        }
+
+        // Budgets and RAM
+        let org = self.common.config().org;
+        let common_budget = self.config.window - org;
+        let areas: Vec<&CodeGen> = std::iter::once(&self.common).chain(&self.banks).collect();
+        let mut units = Vec::new();
+        for (i, cg) in areas.iter().enumerate() {
+            let budget = if i == 0 { common_budget } else { self.config.size };
+            if cg.size() > budget as usize {
+                return Err(ProjectError::OverBudget {
+                    unit: unit_name(i),
+                    size: cg.size(),
+                    budget,
+                });
+            }
+            units.push(UnitPlacement {
+                name: unit_name(i),
+                org: cg.config().org,
+                size: cg.size() as u16,
+                budget,
+                ram: (cg.config().ram_start, cg.ram_pos()),
+                flags: cg.flags().map(String::from).collect(),
+            });
+        }
+        for (i, a) in units.iter().enumerate() {
+            for b in &units[i + 1..] {
+                if a.ram.0 < b.ram.1 && b.ram.0 < a.ram.1 {
+                    return Err(ProjectError::RamOverlap {
+                        first: a.name.clone(),
+                        second: b.name.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut bytes = Vec::new();
+        for (cg, unit) in areas.iter().zip(&units) {
+            bytes.extend_from_slice(cg.rom());
+            bytes.resize(bytes.len() + (unit.budget - unit.size) as usize, 0xFF);
+        }
+
+        let mut symbols: Vec<(String, u16)> = exports.into_iter().map(|(l, a, _)| (l, a)).collect();
+        symbols.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+        Ok(RomImage {
+            name: name.to_string(),
+            org,
+            bytes,
+            symbols,
+            units,
+        })
+    }
+}
+
+impl CodeGen {
+    /// Call `label` in another bank through its `far_` stub
+    ///
+    /// The stub is generated by `BankedRom::link`; it restores the caller's
+    /// bank on return and preserves all registers.
+    pub fn far_call(&mut self, label: &str) {
+        self.call(&format!("far_{}", label));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn banked() -> BankedRom {
+        BankedRom::new(
+            RomConfig {
+                org: 0x0000,
+                ..Default::default()
+            },
+            BankConfig {
+                window: 0x0100,
+                size: 0x0040,
+                count: 2,
+                port: 0x40,
+            },
+        )
+    }
+
+    #[test]
+    fn test_far_call() {
+        let mut rom = banked();
+        rom.common().far_call("greet");
+        rom.common().halt();
+        rom.common().label("putc");
+        rom.common().ret();
+        rom.bank(1).label("greet");
+        rom.bank(1).call("putc");
+        rom.bank(1).ret();
+        let image = rom.link("test").unwrap();
+
+        assert_eq!(image.bytes.len(), 0x0100 + 2 * 0x0040);
+        assert_eq!(&image.bytes[..0x27], &[
+            0xCD, 0x15, 0x00,        // CALL far_greet
+            0x76,                    // HALT
+            0xC9,                    // putc: RET
+            0xAF,                    // bank_init: XOR A
+            0x32, 0x00, 0x20,        // bank_select: LD (bank_current), A
+            0xD3, 0x40,              // OUT (0x40), A
+            0xC9,                    // RET
+            0xE3,                    // bank_return: EX (SP), HL
+            0xF5,                    // PUSH AF
+            0x7D,                    // LD A, L
+            0xCD, 0x06, 0x00,        // CALL bank_select
+            0xF1,                    // POP AF
+            0xE1,                    // POP HL
+            0xC9,                    // RET
+            0xE5,                    // far_greet: PUSH HL
+            0x2A, 0x00, 0x20,        // LD HL, (bank_current)
+            0xE3,                    // EX (SP), HL
+            0xF5,                    // PUSH AF
+            0x3E, 0x01,              // LD A, 1
+            0xCD, 0x06, 0x00,        // CALL bank_select
+            0xF1,                    // POP AF
+            0xCD, 0x00, 0x01,        // CALL greet
+            0xC3, 0x0C, 0x00,        // JP bank_return
+        ][..]);
+        // Bank 0 is empty, bank 1 calls into the common area
+        assert!(image.bytes[0x0100..0x0140].iter().all(|&b| b == 0xFF));
+        assert_eq!(&image.bytes[0x0140..0x0144], &[0xCD, 0x04, 0x00, 0xC9]);
+        assert_eq!(image.symbol("far_greet"), Some(0x0015));
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let mut rom = banked();
+        rom.bank(0).label("a");
+        rom.bank(0).call("b");
+        rom.bank(1).label("b");
+        assert!(matches!(rom.link("x"), Err(ProjectError::CrossBank { unit, name }) if unit == "bank0" && name == "b"));
+
+        let mut rom = banked();
+        rom.bank(0).emit(&[0; 0x41]);
+        assert!(matches!(rom.link("x"), Err(ProjectError::OverBudget { budget: 0x40, .. })));
+
+        let mut rom = banked();
+        rom.bank(1).ram_var("scratch", 2);
+        assert!(matches!(rom.link("x"), Err(ProjectError::RamOverlap { .. })));
+    }
+}
@@ -2,10 +2,26 @@
 //!
 //! Provides the fundamental emit/label/fixup machinery for building Z80 ROMs.
 
-use std::collections::HashMap;
+use crate::stdlib::crc::crc16_ccitt;
+use crate::stdlib::io::MC6850Config;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs::File;
 use std::io::Write;
 
+/// Target CPU profile, selecting which extended instruction sets may be used
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CpuProfile {
+    /// Plain Z80 (RetroShield)
+    #[default]
+    Z80,
+    /// Zilog Z180 / Z8S180 (feature `z180`)
+    Z180,
+    /// ASCII R800 (feature `r800`)
+    R800,
+    /// ZX Spectrum Next Z80N (feature `z80n`)
+    Z80N,
+}
+
 /// Configuration for ROM generation
 #[derive(Clone)]
 pub struct RomConfig {
@@ -15,6 +31,18 @@ pub struct RomConfig {
     pub stack_top: u16,
     /// RAM start address
     pub ram_start: u16,
+    /// Target CPU; extended instructions panic unless it matches
+    pub cpu: CpuProfile,
+    /// Reserve the shadow registers (EXX, EX AF,AF') for interrupt handlers;
+    /// using them outside an ISR builder then panics
+    pub shadow_regs_for_isr: bool,
+    /// CPU clock in Hz, used by cycle-counted timing routines
+    pub clock_hz: u32,
+    /// How `emit_string` and `emit_string_raw` encode text
+    pub text: TextEncoding,
+    /// Console ACIA ports and status bits, used by the serial I/O routines
+    /// and the emulator
+    pub serial: MC6850Config,
 }
 
 impl Default for RomConfig {
@@ -23,17 +51,269 @@ impl Default for RomConfig {
             org: 0x0000,
             stack_top: 0x3FFF,
             ram_start: 0x2000,
+            cpu: CpuProfile::Z80,
+            shadow_regs_for_isr: false,
+            clock_hz: 4_000_000,
+            text: TextEncoding::default(),
+            serial: MC6850Config::default(),
+        }
+    }
+}
+
+/// How `emit_string_checked` treats bytes a VT100 can't display
+///
+/// Printable ASCII plus CR, LF, TAB and ESC always pass through; NUL, other
+/// control characters, DEL and non-ASCII characters are subject to the
+/// policy.
+#[derive(Clone, Copy, Debug)]
+pub enum StringPolicy {
+    /// Reject the string
+    Reject,
+    /// Replace each offending byte with the text `\xNN`
+    Escape,
+    /// Replace characters found in the table, reject the rest
+    Map(&'static [(char, u8)]),
+}
+
+/// Typographic punctuation mapped to plain ASCII, for `StringPolicy::Map`
+pub const ASCII_FALLBACK: &[(char, u8)] = &[
+    ('\u{2018}', b'\''),  // Left single quote
+    ('\u{2019}', b'\''),  // Right single quote
+    ('\u{201C}', b'"'),   // Left double quote
+    ('\u{201D}', b'"'),   // Right double quote
+    ('\u{2013}', b'-'),   // En dash
+    ('\u{2014}', b'-'),   // Em dash
+    ('\u{00A0}', b' '),   // No-break space
+    ('\u{2022}', b'*'),   // Bullet
+];
+
+/// A character rejected by `emit_string_checked` or a `TextEncoding`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StringError {
+    /// Byte offset of the character in the string
+    pub offset: usize,
+    /// The rejected character; for a bad escape, the one after the `\`
+    pub ch: char,
+}
+
+impl std::fmt::Display for StringError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cannot encode character {:?} at byte {}", self.ch, self.offset)
+    }
+}
+
+impl std::error::Error for StringError {}
+
+/// A conflict found by `CodeGen::append` or `CodeGen::link`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LinkError {
+    /// A public label is defined by both at different addresses
+    DuplicateSymbol { name: String, first: String, second: String },
+    /// The RAM areas overlap
+    RamOverlap { first: String, second: String },
+}
+
+impl std::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::DuplicateSymbol { name, first, second } => {
+                write!(f, "{} is defined by both {} and {}", name, first, second)
+            }
+            LinkError::RamOverlap { first, second } => {
+                write!(f, "{} and {} overlap in RAM", first, second)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// A terminal character set for characters outside ASCII
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Codepage {
+    pub table: &'static [(char, u8)],
+    /// Sent before a run of mapped characters
+    pub enter: &'static [u8],
+    /// Sent after the run
+    pub exit: &'static [u8],
+}
+
+/// IBM PC code page 437 box drawing, shading and symbols
+pub const CP437: Codepage = Codepage {
+    table: &[
+        ('\u{2500}', 0xC4), ('\u{2502}', 0xB3), ('\u{250C}', 0xDA), ('\u{2510}', 0xBF),  // ─ │ ┌ ┐
+        ('\u{2514}', 0xC0), ('\u{2518}', 0xD9), ('\u{251C}', 0xC3), ('\u{2524}', 0xB4),  // └ ┘ ├ ┤
+        ('\u{252C}', 0xC2), ('\u{2534}', 0xC1), ('\u{253C}', 0xC5),                      // ┬ ┴ ┼
+        ('\u{2550}', 0xCD), ('\u{2551}', 0xBA), ('\u{2554}', 0xC9), ('\u{2557}', 0xBB),  // ═ ║ ╔ ╗
+        ('\u{255A}', 0xC8), ('\u{255D}', 0xBC),                                          // ╚ ╝
+        ('\u{2591}', 0xB0), ('\u{2592}', 0xB1), ('\u{2593}', 0xB2), ('\u{2588}', 0xDB),  // ░ ▒ ▓ █
+        ('\u{2580}', 0xDF), ('\u{2584}', 0xDC), ('\u{25A0}', 0xFE),                      // ▀ ▄ ■
+        ('\u{00B0}', 0xF8), ('\u{00B1}', 0xF1), ('\u{00B7}', 0xFA), ('\u{2264}', 0xF3),  // ° ± · ≤
+        ('\u{2265}', 0xF2), ('\u{03C0}', 0xE3), ('\u{00A3}', 0x9C),                      // ≥ π £
+    ],
+    enter: &[],
+    exit: &[],
+};
+
+/// VT100 DEC special graphics; runs are wrapped in ESC ( 0 ... ESC ( B
+pub const DEC_GRAPHICS: Codepage = Codepage {
+    table: &[
+        ('\u{2518}', b'j'), ('\u{2510}', b'k'), ('\u{250C}', b'l'), ('\u{2514}', b'm'),  // ┘ ┐ ┌ └
+        ('\u{253C}', b'n'), ('\u{2500}', b'q'), ('\u{251C}', b't'), ('\u{2524}', b'u'),  // ┼ ─ ├ ┤
+        ('\u{2534}', b'v'), ('\u{252C}', b'w'), ('\u{2502}', b'x'),                      // ┴ ┬ │
+        ('\u{25C6}', b'`'), ('\u{2592}', b'a'), ('\u{00B0}', b'f'), ('\u{00B1}', b'g'),  // ◆ ▒ ° ±
+        ('\u{2264}', b'y'), ('\u{2265}', b'z'), ('\u{03C0}', b'{'), ('\u{2260}', b'|'),  // ≤ ≥ π ≠
+        ('\u{00A3}', b'}'), ('\u{00B7}', b'~'),                                          // £ ·
+    ],
+    enter: b"\x1B(0",
+    exit: b"\x1B(B",
+};
+
+/// How `emit_string` turns text into bytes (`RomConfig::text`)
+///
+/// The default copies the UTF-8 bytes unchanged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextEncoding {
+    /// Interpret backslash escapes written in the text: `\n`, `\r`, `\t`,
+    /// `\e` (ESC), `\\` and `\xNN`
+    pub escapes: bool,
+    /// Bytes for a line break, used for LF characters and `\n`
+    pub newline: &'static [u8],
+    /// Character set for non-ASCII characters; without one they are
+    /// emitted as UTF-8
+    pub codepage: Option<Codepage>,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self {
+            escapes: false,
+            newline: b"\n",
+            codepage: None,
+        }
+    }
+}
+
+impl TextEncoding {
+    /// Encode `s`, without a terminator
+    pub fn encode(&self, s: &str) -> Result<Vec<u8>, StringError> {
+        let mut out = Vec::with_capacity(s.len());
+        let mut shifted = false;
+        let mut chars = s.char_indices().peekable();
+        while let Some((offset, ch)) = chars.next() {
+            let mapped = match self.codepage {
+                Some(cp) if !ch.is_ascii() => match cp.table.iter().find(|(c, _)| *c == ch) {
+                    Some(&(_, b)) => Some(b),
+                    None => return Err(StringError { offset, ch }),
+                },
+                _ => None,
+            };
+            if let Some(cp) = self.codepage {
+                if mapped.is_some() != shifted {
+                    out.extend_from_slice(if shifted { cp.exit } else { cp.enter });
+                    shifted = !shifted;
+                }
+            }
+            if let Some(b) = mapped {
+                out.push(b);
+                continue;
+            }
+            match ch {
+                '\n' => out.extend_from_slice(self.newline),
+                '\\' if self.escapes => {
+                    let Some((offset, esc)) = chars.next() else {
+                        return Err(StringError { offset, ch });
+                    };
+                    match esc {
+                        'n' => out.extend_from_slice(self.newline),
+                        'r' => out.push(0x0D),
+                        't' => out.push(0x09),
+                        'e' => out.push(0x1B),
+                        '\\' => out.push(b'\\'),
+                        'x' => {
+                            let mut value = 0;
+                            for _ in 0..2 {
+                                match chars.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+                                    Some((_, c)) => value = value << 4 | c.to_digit(16).unwrap() as u8,
+                                    None => return Err(StringError { offset, ch: esc }),
+                                }
+                            }
+                            out.push(value);
+                        }
+                        _ => return Err(StringError { offset, ch: esc }),
+                    }
+                }
+                _ => {
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                }
+            }
         }
+        if let (true, Some(cp)) = (shifted, self.codepage) {
+            out.extend_from_slice(cp.exit);
+        }
+        Ok(out)
     }
 }
 
+/// Width of a fixup placeholder
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FixupKind {
+    /// 16-bit little-endian address
+    Word,
+    /// Low byte of the address
+    Lo,
+    /// High byte of the address
+    Hi,
+    /// Relative jump displacement from the byte after the placeholder
+    Rel,
+}
+
 /// Core code generator
 pub struct CodeGen {
     rom: Vec<u8>,
     labels: HashMap<String, u16>,
-    fixups: Vec<(usize, String)>,
+    fixups: Vec<(usize, String, i32, FixupKind)>,
     config: RomConfig,
     unique_counter: u32,
+    ram_next: u16,
+    ram_sizes: HashMap<String, u16>,
+    /// Initial values of RAM variables, copied from ROM at startup
+    ram_init: Vec<(u16, Vec<u8>)>,
+    /// Pooled string literals as (text, label)
+    string_pool: Vec<(String, String)>,
+    /// Number of pool entries already placed in ROM
+    pool_emitted: usize,
+    /// (continue, break) labels of the enclosing structured loops and
+    /// blocks; blocks that can only be left have no continue label
+    pub(crate) loops: Vec<(Option<String>, String)>,
+    /// Nesting depth of `with_interrupts_disabled` blocks
+    pub(crate) critical_depth: u32,
+    /// Label of the outermost block's restore-and-return stub, once a
+    /// `return_*` inside it needs one
+    pub(crate) critical_return: Option<String>,
+    /// Inside an interrupt handler (between ISR prologue and epilogue)
+    pub(crate) in_isr: bool,
+    /// Epilogue label and saved registers of the routine being emitted
+    pub(crate) routine: Option<(String, crate::Regs)>,
+    /// ROM offset of the slot reserved by `embed_checksum`
+    checksum: Option<usize>,
+    /// Labels defined again at another address, as (name, first, second)
+    redefined: Vec<(String, u16, u16)>,
+    /// Backward relative jumps out of range, as (ROM offset, label, distance)
+    rel_errors: Vec<(usize, String, i32)>,
+    /// Replaceable regions by name, as (start label, end label)
+    regions: HashMap<String, (String, String)>,
+    /// Label prefixes of the enclosing `namespace` and
+    /// `with_label_prefix` blocks, outermost first
+    scopes: Vec<String>,
+    /// References made inside those blocks, by the innermost prefixed
+    /// name recorded, to the outer names to try if it stays undefined
+    scoped_refs: HashMap<String, Vec<String>>,
+    /// Build flags tested by `when` and `unless`
+    flags: BTreeSet<String>,
+    /// Labels defined by `equ`, which keep their value when appended
+    equs: HashSet<String>,
 }
 
 impl CodeGen {
@@ -48,8 +328,26 @@ impl CodeGen {
             rom: Vec::new(),
             labels: HashMap::new(),
             fixups: Vec::new(),
+            ram_next: config.ram_start,
+            ram_sizes: HashMap::new(),
+            ram_init: Vec::new(),
+            string_pool: Vec::new(),
+            pool_emitted: 0,
             config,
             unique_counter: 0,
+            loops: Vec::new(),
+            critical_depth: 0,
+            critical_return: None,
+            in_isr: false,
+            routine: None,
+            checksum: None,
+            redefined: Vec::new(),
+            rel_errors: Vec::new(),
+            regions: HashMap::new(),
+            scopes: Vec::new(),
+            scoped_refs: HashMap::new(),
+            flags: BTreeSet::new(),
+            equs: HashSet::new(),
         }
     }
 
@@ -58,6 +356,72 @@ impl CodeGen {
         &self.config
     }
 
+    /// Set build flag `name`, enabling the `when(name, ..)` blocks that
+    /// follow
+    pub fn set_flag(&mut self, name: &str) {
+        self.flags.insert(name.to_string());
+    }
+
+    /// Clear build flag `name`
+    pub fn clear_flag(&mut self, name: &str) {
+        self.flags.remove(name);
+    }
+
+    /// Check whether build flag `name` is set
+    pub fn has_flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+
+    /// Set build flags, in sorted order
+    pub fn flags(&self) -> impl Iterator<Item = &str> {
+        self.flags.iter().map(String::as_str)
+    }
+
+    /// Emit `body` only if build flag `flag` is set
+    ///
+    /// One builder can then produce debug and release ROMs: wrap logging
+    /// routines, their calls and assertions in `when("debug", ..)` and
+    /// pick the variant with `set_flag`.
+    pub fn when<F: FnOnce(&mut CodeGen)>(&mut self, flag: &str, body: F) {
+        if self.has_flag(flag) {
+            body(self);
+        }
+    }
+
+    /// Emit `body` only if build flag `flag` is not set
+    pub fn unless<F: FnOnce(&mut CodeGen)>(&mut self, flag: &str, body: F) {
+        if !self.has_flag(flag) {
+            body(self);
+        }
+    }
+
+    /// Panic unless the configured CPU profile is `cpu`
+    ///
+    /// Called by extended instruction helpers before emitting.
+    pub fn require_cpu(&self, cpu: CpuProfile, instruction: &str) {
+        assert!(
+            self.config.cpu == cpu,
+            "{} requires the {:?} CPU profile (RomConfig::cpu is {:?})",
+            instruction,
+            cpu,
+            self.config.cpu
+        );
+    }
+
+    /// Check an instruction helper against the configured usage rules
+    ///
+    /// Called by every instruction helper before emitting.
+    pub(crate) fn check_instruction(&self, syntax: &str) {
+        if self.config.shadow_regs_for_isr && !self.in_isr {
+            assert!(
+                syntax != "EXX" && syntax != "EX AF,AF'",
+                "{} outside an ISR, but the shadow registers are reserved for ISRs \
+                 (RomConfig::shadow_regs_for_isr)",
+                syntax
+            );
+        }
+    }
+
     /// Get current emit position (address)
     pub fn pos(&self) -> u16 {
         self.config.org + self.rom.len() as u16
@@ -92,66 +456,738 @@ impl CodeGen {
         self.rom.push((word >> 8) as u8);
     }
 
-    /// Emit a null-terminated string
+    /// Emit a null-terminated string, encoded per `RomConfig::text`
+    ///
+    /// Bytes are not checked, so an embedded NUL truncates the printed
+    /// text; `emit_string_checked` validates first. Panics if the text
+    /// can't be encoded (a bad escape, or a character missing from the
+    /// codepage).
     pub fn emit_string(&mut self, s: &str) {
-        for b in s.bytes() {
-            self.rom.push(b);
-        }
+        self.emit_string_raw(s);
         self.rom.push(0);
     }
 
-    /// Emit a string without null terminator
+    /// Emit a null-terminated string with an explicit encoding
+    ///
+    /// On error nothing is emitted.
+    pub fn emit_string_encoded(&mut self, s: &str, encoding: &TextEncoding) -> Result<(), StringError> {
+        let bytes = encoding.encode(s)?;
+        self.rom.extend_from_slice(&bytes);
+        self.rom.push(0);
+        Ok(())
+    }
+
+    /// Emit a null-terminated string, checking every character first
+    ///
+    /// Unprintable characters are handled per `policy`; on error nothing is
+    /// emitted.
+    pub fn emit_string_checked(
+        &mut self,
+        s: &str,
+        policy: StringPolicy,
+    ) -> Result<(), StringError> {
+        let mut out = Vec::with_capacity(s.len() + 1);
+        for (offset, ch) in s.char_indices() {
+            if matches!(ch, ' '..='~' | '\r' | '\n' | '\t' | '\x1B') {
+                out.push(ch as u8);
+                continue;
+            }
+            match policy {
+                StringPolicy::Reject => return Err(StringError { offset, ch }),
+                StringPolicy::Escape => {
+                    let mut buf = [0; 4];
+                    for b in ch.encode_utf8(&mut buf).bytes() {
+                        out.extend_from_slice(format!("\\x{:02X}", b).as_bytes());
+                    }
+                }
+                StringPolicy::Map(table) => match table.iter().find(|(c, _)| *c == ch) {
+                    Some(&(_, b)) => out.push(b),
+                    None => return Err(StringError { offset, ch }),
+                },
+            }
+        }
+        out.push(0);
+        self.rom.extend_from_slice(&out);
+        Ok(())
+    }
+
+    /// Emit a string without null terminator, encoded per `RomConfig::text`
     pub fn emit_string_raw(&mut self, s: &str) {
-        for b in s.bytes() {
-            self.rom.push(b);
+        let bytes = self.config.text.encode(s).unwrap_or_else(|e| panic!("{:?}: {}", s, e));
+        self.rom.extend_from_slice(&bytes);
+    }
+
+    /// Emit a length-prefixed string: a length byte, then the text
+    /// encoded per `RomConfig::text`, with no terminator
+    ///
+    /// Panics if the encoded text is longer than 255 bytes.
+    pub fn emit_pstring(&mut self, s: &str) {
+        let bytes = self.config.text.encode(s).unwrap_or_else(|e| panic!("{:?}: {}", s, e));
+        assert!(bytes.len() <= 255, "{:?} is {} bytes, too long for a length byte", s, bytes.len());
+        self.rom.push(bytes.len() as u8);
+        self.rom.extend_from_slice(&bytes);
+    }
+
+    /// Emit a table of `len` bytes computed at build time: entry `i` is
+    /// `f(i)`, e.g. a sine table or a CRC lookup table
+    ///
+    /// Labels created: `label`
+    pub fn emit_table_u8(&mut self, label: &str, len: usize, f: impl FnMut(usize) -> u8) {
+        self.label(label);
+        self.rom.extend((0..len).map(f));
+    }
+
+    /// Emit a table of `len` little-endian words computed at build time,
+    /// e.g. tone periods for a note table
+    ///
+    /// Labels created: `label`
+    pub fn emit_table_u16(&mut self, label: &str, len: usize, mut f: impl FnMut(usize) -> u16) {
+        self.label(label);
+        for i in 0..len {
+            self.emit_word(f(i));
+        }
+    }
+
+    /// Emit `bytes` `n` times, e.g. a fill pattern or a NOP slide
+    pub fn emit_repeat(&mut self, bytes: &[u8], n: usize) {
+        for _ in 0..n {
+            self.rom.extend_from_slice(bytes);
+        }
+    }
+
+    /// Emit `body` `n` times, passing the iteration number, for unrolled
+    /// loops
+    ///
+    /// Each iteration runs under its own private label prefix (as in
+    /// `with_label_prefix`), so labels it defines don't collide between
+    /// copies: a `jr("again")` in the body reaches that copy's `again`.
+    /// Other names resolve to the global labels as usual.
+    pub fn times<F: FnMut(&mut CodeGen, usize)>(&mut self, n: usize, mut body: F) {
+        let base = self.unique_label("times");
+        for i in 0..n {
+            self.with_label_prefix(&format!("{}.{}.", base, i), |cg| body(cg, i));
         }
     }
 
+    /// Fill with `fill` bytes up to absolute address `addr`
+    ///
+    /// Panics if code has already been emitted past `addr`.
+    pub fn pad_to(&mut self, addr: u16, fill: u8) {
+        let pos = self.pos();
+        assert!(
+            addr >= pos,
+            "cannot pad to {:#06X}: already at {:#06X}",
+            addr,
+            pos
+        );
+        self.rom.resize(self.rom.len() + (addr - pos) as usize, fill);
+    }
+
     // ========== Label Management ==========
 
     /// Define a label at current position
     pub fn label(&mut self, name: &str) {
-        self.labels.insert(name.to_string(), self.pos());
+        let name = qualified(&self.scopes, name);
+        let pos = self.pos();
+        self.equs.remove(&name);
+        if let Some(old) = self.labels.insert(name.clone(), pos) {
+            if old != pos {
+                self.redefined.push((name, old, pos));
+            }
+        }
     }
 
     /// Check if a label exists
     pub fn has_label(&self, name: &str) -> bool {
-        self.labels.contains_key(name)
+        self.resolve_name(name).is_some()
     }
 
     /// Get label address (if defined)
     pub fn get_label(&self, name: &str) -> Option<u16> {
-        self.labels.get(name).copied()
+        self.resolve_name(name).map(|name| self.labels[&name])
+    }
+
+    /// Define a label at a fixed address (EQU), e.g. a routine in another ROM
+    pub fn equ(&mut self, name: &str, addr: u16) {
+        let name = qualified(&self.scopes, name);
+        self.labels.insert(name.clone(), addr);
+        self.equs.insert(name);
+    }
+
+    /// Emit `body` with the labels it defines prefixed by `name.`
+    ///
+    /// Private labels (starting with `_`) become `_name.label`, and
+    /// namespaces nest (`lcd.init.loop`). References inside try the
+    /// innermost namespace first, then the enclosing ones, then the
+    /// global name, so a module can use short names for its own labels
+    /// and still call shared routines. Names containing `.` are taken as
+    /// already qualified; code outside calls `lcd.init`.
+    pub fn namespace<F: FnOnce(&mut CodeGen)>(&mut self, name: &str, body: F) {
+        self.with_label_prefix(&format!("{}.", name), body);
+    }
+
+    /// Emit `body` with `prefix` prepended to the labels it defines
+    ///
+    /// Lookups work as in `namespace`, so stdlib emitters run inside
+    /// define prefixed routines that call each other: emitting the I/O
+    /// routines under `serial_` gives `serial_putchar`, `serial_print_string`
+    /// and so on, leaving `putchar` free for another device.
+    pub fn with_label_prefix<F: FnOnce(&mut CodeGen)>(&mut self, prefix: &str, body: F) {
+        self.scopes.push(prefix.to_string());
+        body(self);
+        self.scopes.pop();
+    }
+
+    /// Names `name` may refer to from the current namespace, innermost first
+    fn candidates(&self, name: &str) -> Vec<String> {
+        if name.contains('.') {
+            return vec![name.to_string()];
+        }
+        (0..=self.scopes.len())
+            .rev()
+            .map(|depth| qualified(&self.scopes[..depth], name))
+            .collect()
+    }
+
+    /// The defined label `name` refers to from the current namespace
+    fn resolve_name(&self, name: &str) -> Option<String> {
+        if self.scopes.is_empty() {
+            return self.labels.contains_key(name).then(|| name.to_string());
+        }
+        self.candidates(name).into_iter().find(|c| self.labels.contains_key(c))
+    }
+
+    /// Name to record a reference to `name` under; inside a namespace,
+    /// `finalize` falls back to the outer names if it stays undefined
+    fn reference(&mut self, name: &str) -> String {
+        let mut candidates = self.candidates(name);
+        let inner = candidates.remove(0);
+        if !candidates.is_empty() {
+            self.scoped_refs.entry(inner.clone()).or_insert(candidates);
+        }
+        inner
+    }
+
+    /// Iterate over all defined labels (ROM and RAM), in no particular order
+    pub fn labels(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.labels.iter().map(|(name, addr)| (name.as_str(), *addr))
+    }
+
+    // ========== String Pool ==========
+
+    /// Get the label of a pooled, null-terminated string literal
+    ///
+    /// Identical strings share one copy, and a string that is the tail of
+    /// a longer one (such as "\r\n") points into it. The pool is placed by
+    /// `emit_string_pool`, or at the end of the ROM by `resolve_fixups`.
+    pub fn pooled_string(&mut self, text: &str) -> String {
+        if let Some((_, label)) = self.string_pool.iter().find(|(t, _)| t == text) {
+            return label.clone();
+        }
+        let label = self.unique_label("str");
+        self.string_pool.push((text.to_string(), label.clone()));
+        label
+    }
+
+    /// Emit all pooled strings not yet placed in ROM
+    ///
+    /// Strings are encoded per `RomConfig::text` first, so tails are
+    /// shared only where the encoded bytes match.
+    pub fn emit_string_pool(&mut self) {
+        let mut pending: Vec<(Vec<u8>, String)> = self.string_pool[self.pool_emitted..]
+            .iter()
+            .map(|(text, label)| {
+                let bytes = self.config.text.encode(text).unwrap_or_else(|e| panic!("{:?}: {}", text, e));
+                (bytes, label.clone())
+            })
+            .collect();
+        self.pool_emitted = self.string_pool.len();
+        // Longest first, so shorter strings can share a longer one's tail
+        pending.sort_by_key(|(bytes, _)| std::cmp::Reverse(bytes.len()));
+        let mut placed: Vec<(Vec<u8>, u16)> = Vec::new();
+        for (bytes, label) in pending {
+            let shared = placed
+                .iter()
+                .find(|(b, _)| b.ends_with(&bytes))
+                .map(|(b, addr)| addr + (b.len() - bytes.len()) as u16);
+            match shared {
+                Some(addr) => {
+                    self.labels.insert(label, addr);
+                }
+                None => {
+                    placed.push((bytes.clone(), self.pos()));
+                    self.label(&label);
+                    self.rom.extend_from_slice(&bytes);
+                    self.rom.push(0);
+                }
+            }
+        }
+    }
+
+    // ========== Regions ==========
+
+    /// Emit `body` as a replaceable region between labels `name` and
+    /// `<name>_end`
+    pub fn region<F: FnOnce(&mut CodeGen)>(&mut self, name: &str, body: F) {
+        let end = format!("{}_end", name);
+        self.label(name);
+        body(self);
+        self.label(&end);
+        self.mark_region(name, name, &end);
+    }
+
+    /// Name the code between two defined labels as a replaceable region
+    pub fn mark_region(&mut self, name: &str, start_label: &str, end_label: &str) {
+        let start_label = self.resolve_name(start_label).unwrap_or_else(|| panic!("Undefined label: {}", start_label));
+        let end_label = self.resolve_name(end_label).unwrap_or_else(|| panic!("Undefined label: {}", end_label));
+        assert!(self.labels[&start_label] <= self.labels[&end_label], "region {} ends before it starts", name);
+        self.regions.insert(name.to_string(), (start_label, end_label));
+    }
+
+    /// Re-emit a region's contents in place, padding with NOPs to its old
+    /// size; returns the bytes left over
+    ///
+    /// Labels and fixups inside the region are dropped first, so `body`
+    /// can reuse their names. Everything outside the region keeps its
+    /// address, so call `resolve_fixups` again and only references to
+    /// labels inside the region change. Code outside should enter through
+    /// the start label: a `jr` emitted earlier into the region is not
+    /// updated.
+    ///
+    /// Panics if the region is unknown or the new code is larger.
+    pub fn replace_region<F: FnOnce(&mut CodeGen)>(&mut self, name: &str, body: F) -> u16 {
+        let (start_label, end_label) = self
+            .regions
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| panic!("Unknown region: {}", name));
+        let start = self.labels[&start_label];
+        let end = self.labels[&end_label];
+        let (from, to) = ((start - self.config.org) as usize, (end - self.config.org) as usize);
+
+        // Labels on the first byte may belong to code before the region,
+        // so they stay; moving one inside `body` is not a redefinition.
+        // RAM variables and `equ` values only happen to fall in the range.
+        let (ram, equs) = (&self.ram_sizes, &self.equs);
+        self.labels
+            .retain(|label, addr| *addr <= start || *addr >= end || ram.contains_key(label) || equs.contains(label));
+        self.fixups.retain(|(offset, ..)| !(from..to).contains(offset));
+        self.rel_errors.retain(|(offset, ..)| !(from..to).contains(offset));
+
+        let tail = self.rom.split_off(from);
+        let redefined = self.redefined.len();
+        body(self);
+        let mut i = redefined;
+        while i < self.redefined.len() {
+            if self.redefined[i].1 == start {
+                self.redefined.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        let size = self.rom.len() - from;
+        assert!(
+            size <= to - from,
+            "region {} is {} bytes, replacement is {}",
+            name,
+            to - from,
+            size
+        );
+        self.rom.resize(to, 0x00);  // NOP
+        self.rom.extend_from_slice(&tail[to - from..]);
+        (to - from - size) as u16
+    }
+
+    // ========== RAM Allocation ==========
+
+    /// Emit the RAM initialization jumped to by `emit_startup`: zero every
+    /// RAM variable, then copy initialized values from ROM
+    ///
+    /// Emitted automatically by `resolve_fixups`, once all variables are known.
+    /// With no RAM variables there is nothing to do, and `_crt0` is just
+    /// `_crt0_done`.
+    fn emit_crt0(&mut self) {
+        let start = self.config.ram_start;
+        if self.ram_next == start && self.ram_init.is_empty() {
+            if let Some(&done) = self.labels.get("_crt0_done") {
+                self.label_at("_crt0", done);
+                return;
+            }
+        }
+        self.label("_crt0");
+        let size = self.ram_next.wrapping_sub(start);
+        if size > 0 {
+            self.ld_hl(start);
+            self.emit(&[0x36, 0x00]);    // LD (HL), 0
+            if size > 1 {
+                self.ld_de(start + 1);
+                self.ld_bc(size - 1);
+                self.emit(&[0xED, 0xB0]);    // LDIR
+            }
+        }
+
+        // Merge variables that are adjacent in RAM into one copy each
+        let mut runs: Vec<(u16, Vec<u8>)> = Vec::new();
+        for (addr, init) in &self.ram_init {
+            match runs.last_mut() {
+                Some((run_addr, bytes)) if *run_addr + bytes.len() as u16 == *addr => {
+                    bytes.extend_from_slice(init);
+                }
+                _ => runs.push((*addr, init.clone())),
+            }
+        }
+        let labels: Vec<String> = runs.iter().map(|_| self.unique_label("crt0_data")).collect();
+        for ((addr, bytes), label) in runs.iter().zip(&labels) {
+            self.ld_hl_label(label);
+            self.ld_de(*addr);
+            self.ld_bc(bytes.len() as u16);
+            self.emit(&[0xED, 0xB0]);    // LDIR
+        }
+        self.jp("_crt0_done");
+        for ((_, bytes), label) in runs.iter().zip(&labels) {
+            self.label(label);
+            self.emit(bytes);
+        }
+    }
+
+    /// Reserve `size` bytes of RAM and define `name` as a label at its address
+    ///
+    /// Allocation starts at `RomConfig::ram_start` and grows upward.
+    /// Returns the allocated address.
+    pub fn ram_var(&mut self, name: &str, size: u16) -> u16 {
+        let addr = self.ram_next;
+        let name = qualified(&self.scopes, name);
+        self.labels.insert(name.clone(), addr);
+        self.ram_sizes.insert(name, size);
+        self.ram_next = self.ram_next.wrapping_add(size);
+        addr
+    }
+
+    /// Reserve a RAM variable whose initial value lives in ROM
+    ///
+    /// The startup code emitted by `emit_startup` copies `init` into the
+    /// variable; all other RAM variables start zeroed. Returns the address.
+    pub fn ram_var_init(&mut self, name: &str, init: &[u8]) -> u16 {
+        let addr = self.ram_var(name, init.len() as u16);
+        self.ram_init.push((addr, init.to_vec()));
+        addr
+    }
+
+    /// Get the size of a variable reserved with `ram_var`
+    pub fn ram_var_size(&self, name: &str) -> Option<u16> {
+        self.candidates(name).iter().find_map(|c| self.ram_sizes.get(c)).copied()
+    }
+
+    /// Get the next free RAM address
+    pub fn ram_pos(&self) -> u16 {
+        self.ram_next
     }
 
     /// Record a fixup for later resolution (emits placeholder word)
     pub fn fixup(&mut self, name: &str) {
-        self.fixups.push((self.rom.len(), name.to_string()));
+        self.fixup_expr(name, 0);
+    }
+
+    /// Record a fixup resolving to `name + offset` (emits placeholder word)
+    pub fn fixup_expr(&mut self, name: &str, offset: i32) {
+        let name = self.reference(name);
+        self.fixups.push((self.rom.len(), name, offset, FixupKind::Word));
         self.emit_word(0); // Placeholder
     }
 
+    /// Record a fixup resolving to the low byte of `name` (emits placeholder byte)
+    pub fn fixup_lo(&mut self, name: &str) {
+        self.fixup_lo_expr(name, 0);
+    }
+
+    /// Record a fixup resolving to the low byte of `name + offset` (emits placeholder byte)
+    pub fn fixup_lo_expr(&mut self, name: &str, offset: i32) {
+        let name = self.reference(name);
+        self.fixups.push((self.rom.len(), name, offset, FixupKind::Lo));
+        self.emit_byte(0); // Placeholder
+    }
+
+    /// Record a fixup resolving to the high byte of `name` (emits placeholder byte)
+    pub fn fixup_hi(&mut self, name: &str) {
+        let name = self.reference(name);
+        self.fixups.push((self.rom.len(), name, 0, FixupKind::Hi));
+        self.emit_byte(0); // Placeholder
+    }
+
+    /// Record a relative jump displacement to `name + offset`, resolved
+    /// (and range-checked) later; allows forward JR/DJNZ targets
+    pub(crate) fn fixup_rel(&mut self, name: &str, offset: i32) {
+        let name = self.reference(name);
+        self.fixups.push((self.rom.len(), name, offset, FixupKind::Rel));
+        self.emit_byte(0); // Placeholder
+    }
+
+    /// Record a fixup over placeholder bytes already emitted at ROM offset `at`
+    pub(crate) fn add_fixup(&mut self, at: usize, name: &str, offset: i32, kind: FixupKind) {
+        self.fixups.push((at, name.to_string(), offset, kind));
+    }
+
+    /// Count RAM up to `end` as allocated, so the startup code zeroes it
+    pub(crate) fn reserve_ram_to(&mut self, end: u16) {
+        self.ram_next = self.ram_next.max(end);
+    }
+
+    /// Place the generated tail of the ROM (crt0, string pool); idempotent
+    pub(crate) fn finalize(&mut self) {
+        if !self.has_label("_crt0") && self.fixups.iter().any(|(_, name, _, _)| name == "_crt0") {
+            self.emit_crt0();
+        }
+        self.emit_string_pool();
+        for (_, name, _, _) in &mut self.fixups {
+            if self.labels.contains_key(name.as_str()) {
+                continue;
+            }
+            let outer = self.scoped_refs.get(name.as_str()).and_then(|outer| {
+                outer.iter().find(|c| self.labels.contains_key(c.as_str()))
+            });
+            if let Some(outer) = outer {
+                *name = outer.clone();
+            }
+        }
+    }
+
+    /// Pending fixups as (ROM offset, label, offset added, kind)
+    pub(crate) fn fixups(&self) -> &[(usize, String, i32, FixupKind)] {
+        &self.fixups
+    }
+
+    /// Whether `name` was defined by `equ` rather than in the code
+    pub(crate) fn is_equ(&self, name: &str) -> bool {
+        self.equs.contains(name)
+    }
+
+    /// Define a label at `addr` in code emitted by other means (an
+    /// imported object), so it moves with the code like one from `label`
+    pub(crate) fn label_at(&mut self, name: &str, addr: u16) {
+        let name = qualified(&self.scopes, name);
+        self.equs.remove(&name);
+        self.labels.insert(name, addr);
+    }
+
+    /// Labels defined a second time at another address, as (name, first, second)
+    pub(crate) fn redefined_labels(&self) -> &[(String, u16, u16)] {
+        &self.redefined
+    }
+
+    /// Backward relative jumps that were out of range when emitted, as
+    /// (ROM offset of the displacement, label, distance)
+    pub(crate) fn rel_errors(&self) -> &[(usize, String, i32)] {
+        &self.rel_errors
+    }
+
+    /// Finalize the ROM and list referenced labels that are still undefined
+    pub(crate) fn undefined_labels(&mut self) -> Vec<String> {
+        self.finalize();
+        let mut missing: Vec<String> = Vec::new();
+        for (_, name, _, _) in &self.fixups {
+            if !self.labels.contains_key(name) && !missing.contains(name) {
+                missing.push(name.clone());
+            }
+        }
+        missing
+    }
+
     /// Resolve all fixups - call after all code is emitted
     pub fn resolve_fixups(&mut self) {
-        for (offset, name) in &self.fixups {
+        self.finalize();
+        if let Some((_, name, disp)) = self.rel_errors.first() {
+            panic!("Relative jump to {} out of range ({} bytes)", name, disp);
+        }
+        for (offset, name, delta, kind) in &self.fixups {
             let addr = *self.labels.get(name).unwrap_or_else(|| {
                 panic!("Undefined label: {}", name)
             });
-            self.rom[*offset] = addr as u8;
-            self.rom[*offset + 1] = (addr >> 8) as u8;
+            let addr = (addr as i32).wrapping_add(*delta) as u16;
+            match kind {
+                FixupKind::Word => {
+                    self.rom[*offset] = addr as u8;
+                    self.rom[*offset + 1] = (addr >> 8) as u8;
+                }
+                FixupKind::Lo => self.rom[*offset] = addr as u8,
+                FixupKind::Hi => self.rom[*offset] = (addr >> 8) as u8,
+                FixupKind::Rel => {
+                    let from = self.config.org as i32 + *offset as i32 + 1;
+                    let disp = addr as i32 - from;
+                    assert!(
+                        (-128..=127).contains(&disp),
+                        "Relative jump to {} out of range ({} bytes)",
+                        name,
+                        disp
+                    );
+                    self.rom[*offset] = disp as u8;
+                }
+            }
+        }
+        if let Some(slot) = self.checksum {
+            let end = self.pos();
+            let crc = crc16_ccitt(&self.rom[..slot], 0);
+            let crc = crc16_ccitt(&self.rom[slot + 4..], crc);
+            self.rom[slot..slot + 4].copy_from_slice(&[crc as u8, (crc >> 8) as u8, end as u8, (end >> 8) as u8]);
         }
     }
 
+    /// Reserve a 4-byte slot at `label` for the image checksum
+    ///
+    /// `resolve_fixups` fills it with the CRC-16/CCITT (initial value 0)
+    /// of the whole image, skipping the slot itself, followed by the
+    /// address just past the image. `emit_rom_selfcheck` checks it at run
+    /// time. Panics if a slot was already reserved.
+    pub fn embed_checksum(&mut self, label: &str) {
+        assert!(self.checksum.is_none(), "embed_checksum called twice");
+        self.label(label);
+        self.checksum = Some(self.rom.len());
+        self.emit(&[0; 4]);
+    }
+
     /// Emit a relative jump offset (for JR, DJNZ)
-    /// target_label must already be defined
+    ///
+    /// A label not defined yet becomes a fixup, resolved and range-checked
+    /// by `resolve_fixups`.
     pub fn emit_relative(&mut self, target_label: &str) {
-        let target = *self.labels.get(target_label).unwrap_or_else(|| {
-            panic!("Undefined label for relative jump: {}", target_label)
-        });
+        let Some(target) = self.get_label(target_label) else {
+            self.fixup_rel(target_label, 0);
+            return;
+        };
         let current = self.pos() + 1; // +1 because offset is from after the offset byte
-        let offset = (target as i32 - current as i32) as i8;
+        let offset = target as i32 - current as i32;
+        if !(-128..=127).contains(&offset) {
+            self.rel_errors.push((self.rom.len(), target_label.to_string(), offset));
+        }
         self.emit_byte(offset as u8);
     }
 
+    // ========== Linking ==========
+
+    /// Append the code of another generator, as if it had been emitted here
+    ///
+    /// Labels in `other`'s code and its pending fixups move with the code;
+    /// RAM variables and `equ` labels keep their value, so give `other` a
+    /// `RomConfig::ram_start` past this generator's RAM. Labels starting
+    /// with `_` are private: one that is already defined here is renamed.
+    /// Addresses `other` emitted as plain numbers (such as `ld_hl` of a
+    /// `get_label` value) are not relocated.
+    ///
+    /// Fails without changing anything if a public label is defined by
+    /// both at different addresses, or the RAM areas overlap.
+    pub fn append(&mut self, other: &CodeGen) -> Result<(), LinkError> {
+        let offset = self.rom.len();
+        let base = self.pos();
+        let (org, end) = (other.config.org as u32, other.config.org as u32 + other.rom.len() as u32);
+        let (ram, other_ram) = ((self.config.ram_start, self.ram_next), (other.config.ram_start, other.ram_next));
+        if ram.0 < ram.1 && other_ram.0 < other_ram.1 && ram.0 < other_ram.1 && other_ram.0 < ram.1 {
+            return Err(LinkError::RamOverlap {
+                first: "code".to_string(),
+                second: "appended code".to_string(),
+            });
+        }
+        let relocate = |label: &str, addr: u16| {
+            let fixed = other.ram_sizes.contains_key(label) || other.equs.contains(label);
+            if !fixed && (org..=end).contains(&(addr as u32)) {
+                base.wrapping_add(addr - other.config.org)
+            } else {
+                addr
+            }
+        };
+        for (name, addr) in &other.labels {
+            match self.labels.get(name) {
+                Some(&old) if !name.starts_with('_') && old != relocate(name, *addr) => {
+                    return Err(LinkError::DuplicateSymbol {
+                        name: name.clone(),
+                        first: "code".to_string(),
+                        second: "appended code".to_string(),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        // Private labels taken here, and pending pooled strings, get new names
+        let mut renamed: HashMap<String, String> = HashMap::new();
+        self.unique_counter = self.unique_counter.max(other.unique_counter);
+        let mut names: Vec<&String> = other.labels.keys().filter(|n| n.starts_with('_')).collect();
+        names.sort();
+        for name in names {
+            if self.labels.contains_key(name) {
+                let new = self.unique_label(&name[1..]);
+                renamed.insert(name.clone(), new);
+            }
+        }
+        for (text, label) in &other.string_pool[other.pool_emitted..] {
+            let new = self.pooled_string(text);
+            renamed.insert(label.clone(), new);
+        }
+        let name = |label: &String| renamed.get(label).unwrap_or(label).clone();
+
+        self.rom.extend_from_slice(&other.rom);
+        for (label, addr) in &other.labels {
+            self.labels.insert(name(label), relocate(label, *addr));
+        }
+        for (label, size) in &other.ram_sizes {
+            self.ram_sizes.insert(name(label), *size);
+        }
+        self.equs.extend(other.equs.iter().map(name));
+        for (inner, outer) in &other.scoped_refs {
+            self.scoped_refs
+                .entry(name(inner))
+                .or_insert_with(|| outer.iter().map(name).collect());
+        }
+        self.ram_init.extend(other.ram_init.iter().cloned());
+        self.ram_next = self.ram_next.max(other.ram_next);
+        for (at, label, delta, kind) in &other.fixups {
+            self.fixups.push((at + offset, name(label), *delta, *kind));
+        }
+        for (at, label, distance) in &other.rel_errors {
+            self.rel_errors.push((at + offset, name(label), *distance));
+        }
+        for (label, first, second) in &other.redefined {
+            self.redefined.push((name(label), relocate(label, *first), relocate(label, *second)));
+        }
+        for (region, (start, end)) in &other.regions {
+            self.regions.entry(region.clone()).or_insert_with(|| (name(start), name(end)));
+        }
+        if self.checksum.is_none() {
+            self.checksum = other.checksum.map(|slot| slot + offset);
+        }
+        Ok(())
+    }
+
+    /// Combine generators into one, each placed right after the previous
+    ///
+    /// The result has the first generator's config. Errors name the
+    /// generators by index, as `part 0`, `part 1`, ...
+    pub fn link(parts: &[CodeGen]) -> Result<CodeGen, LinkError> {
+        let config = parts.first().map(|p| p.config.clone()).unwrap_or_default();
+        let mut linked = CodeGen::with_config(config);
+        for (i, part) in parts.iter().enumerate() {
+            let part_name = |j: usize| format!("part {}", j);
+            linked.append(part).map_err(|e| match e {
+                LinkError::DuplicateSymbol { name, .. } => LinkError::DuplicateSymbol {
+                    first: part_name(parts.iter().position(|p| p.has_label(&name)).unwrap_or(0)),
+                    second: part_name(i),
+                    name,
+                },
+                LinkError::RamOverlap { .. } => LinkError::RamOverlap {
+                    first: part_name(
+                        parts[..i]
+                            .iter()
+                            .position(|p| {
+                                let (start, end) = (p.config.ram_start, p.ram_next);
+                                start < end && start < part.ram_next && part.config.ram_start < end
+                            })
+                            .unwrap_or(0),
+                    ),
+                    second: part_name(i),
+                },
+            })?;
+        }
+        Ok(linked)
+    }
+
     // ========== Output ==========
 
     /// Get the raw ROM bytes
@@ -174,32 +1210,48 @@ impl CodeGen {
     /// Write ROM as Intel HEX format
     pub fn write_hex(&self, path: &str) -> std::io::Result<()> {
         let mut file = File::create(path)?;
+        file.write_all(intel_hex(&self.rom, self.config.org).as_bytes())
+    }
+}
 
-        for (i, chunk) in self.rom.chunks(16).enumerate() {
-            let addr = self.config.org + (i * 16) as u16;
-            let len = chunk.len() as u8;
-
-            // Calculate checksum
-            let mut checksum: u8 = len;
-            checksum = checksum.wrapping_add((addr >> 8) as u8);
-            checksum = checksum.wrapping_add(addr as u8);
-            // Record type 00 = data
-            for &b in chunk {
-                checksum = checksum.wrapping_add(b);
-            }
-            checksum = (!checksum).wrapping_add(1);
+/// `name` with the label prefixes in `scope`, keeping a leading `_`
+fn qualified(scope: &[String], name: &str) -> String {
+    if scope.is_empty() || name.contains('.') {
+        return name.to_string();
+    }
+    match name.strip_prefix('_') {
+        Some(rest) => format!("_{}{}", scope.concat(), rest),
+        None => format!("{}{}", scope.concat(), name),
+    }
+}
 
-            write!(file, ":{:02X}{:04X}00", len, addr)?;
-            for &b in chunk {
-                write!(file, "{:02X}", b)?;
-            }
-            writeln!(file, "{:02X}", checksum)?;
+/// Format `bytes` loaded at `org` as Intel HEX, 16 bytes per record
+pub(crate) fn intel_hex(bytes: &[u8], org: u16) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let addr = org.wrapping_add((i * 16) as u16);
+        let len = chunk.len() as u8;
+
+        // Calculate checksum
+        let mut checksum: u8 = len;
+        checksum = checksum.wrapping_add((addr >> 8) as u8);
+        checksum = checksum.wrapping_add(addr as u8);
+        // Record type 00 = data
+        for &b in chunk {
+            checksum = checksum.wrapping_add(b);
         }
+        checksum = (!checksum).wrapping_add(1);
 
-        // End of file record
-        writeln!(file, ":00000001FF")?;
-        Ok(())
+        out.push_str(&format!(":{:02X}{:04X}00", len, addr));
+        for &b in chunk {
+            out.push_str(&format!("{:02X}", b));
+        }
+        out.push_str(&format!("{:02X}\n", checksum));
     }
+
+    // End of file record
+    out.push_str(":00000001FF\n");
+    out
 }
 
 impl Default for CodeGen {
@@ -243,6 +1295,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::byte_char_slices)]
     fn test_emit_string_raw() {
         let mut cg = CodeGen::new();
         cg.emit_string_raw("Hi");
@@ -299,12 +1352,150 @@ mod tests {
         assert_eq!(cg.pos(), 4);
     }
 
+    #[test]
+    fn test_emit_string_checked() {
+        let mut cg = CodeGen::new();
+        cg.emit_string_checked("Ok\r\n", StringPolicy::Reject).unwrap();
+        assert_eq!(cg.rom(), b"Ok\r\n\0");
+
+        let err = cg.emit_string_checked("A\0B", StringPolicy::Reject).unwrap_err();
+        assert_eq!(err, StringError { offset: 1, ch: '\0' });
+        assert_eq!(cg.size(), 5);    // Nothing emitted on error
+
+        let mut cg = CodeGen::new();
+        cg.emit_string_checked("1\u{7F}2\u{e9}", StringPolicy::Escape).unwrap();
+        assert_eq!(cg.rom(), b"1\\x7F2\\xC3\\xA9\0");
+
+        let mut cg = CodeGen::new();
+        let quoted = "\u{201C}Hi\u{201D} \u{2014}";
+        cg.emit_string_checked(quoted, StringPolicy::Map(ASCII_FALLBACK)).unwrap();
+        assert_eq!(cg.rom(), b"\"Hi\" -\0");
+        assert!(cg.emit_string_checked("\u{e9}", StringPolicy::Map(ASCII_FALLBACK)).is_err());
+    }
+
+    #[test]
+    fn test_text_encoding() {
+        let plain = TextEncoding::default();
+        assert_eq!(plain.encode("a\\n\u{e9}\n").unwrap(), b"a\\n\xC3\xA9\n");
+
+        let text = TextEncoding { escapes: true, newline: b"\r\n", codepage: Some(DEC_GRAPHICS) };
+        let mut cg = CodeGen::with_config(RomConfig { text, ..Default::default() });
+        cg.emit_string("\\e[1m\\x41\\\\\\t\n\u{250C}\u{2500}\u{2510}ok\\n");
+        assert_eq!(cg.rom(), b"\x1B[1mA\\\t\r\n\x1B(0lqk\x1B(Bok\r\n\0");
+
+        assert_eq!(text.encode("\\q"), Err(StringError { offset: 1, ch: 'q' }));
+        assert_eq!(text.encode("\\x4"), Err(StringError { offset: 1, ch: 'x' }));
+        assert_eq!(text.encode("\u{263A}"), Err(StringError { offset: 0, ch: '\u{263A}' }));
+        let cp437 = TextEncoding { codepage: Some(CP437), ..Default::default() };
+        let mut cg = CodeGen::new();
+        cg.emit_string_encoded("\u{2554}\u{2550}\u{2557} \u{00B0}", &cp437).unwrap();
+        assert_eq!(cg.rom(), b"\xC9\xCD\xBB \xF8\0");
+        assert!(cg.emit_string_encoded("\u{263A}", &cp437).is_err());
+        assert_eq!(cg.size(), 6);
+    }
+
+    #[test]
+    fn test_emit_pstring() {
+        let mut cg = CodeGen::new();
+        cg.emit_pstring("Hi!");
+        cg.emit_pstring("");
+        assert_eq!(cg.rom(), b"\x03Hi!\x00");
+    }
+
+    #[test]
+    #[should_panic(expected = "too long for a length byte")]
+    fn test_emit_pstring_too_long() {
+        CodeGen::new().emit_pstring(&"x".repeat(256));
+    }
+
+    #[test]
+    fn test_emit_tables() {
+        let mut cg = CodeGen::new();
+        cg.emit_table_u8("sine", 4, |i| (127.0 * (i as f64 * std::f64::consts::FRAC_PI_2).sin()) as i8 as u8);
+        cg.emit_table_u16("squares", 3, |i| (i * 300) as u16);
+        assert_eq!(cg.rom(), &[
+            0x00, 0x7F, 0x00, 0x81,  // sine: 0, 127, 0, -127
+            0x00, 0x00,              // squares: 0
+            0x2C, 0x01,              // 300
+            0x58, 0x02,              // 600
+        ]);
+        assert_eq!(cg.get_label("squares"), Some(4));
+    }
+
+    #[test]
+    fn test_times() {
+        let mut cg = CodeGen::new();
+        cg.times(2, |cg, i| {
+            cg.label("again");
+            cg.ld_a(i as u8);
+            cg.call("step");
+            cg.jr_nz("again");
+        });
+        cg.emit_repeat(&[0x00, 0xFF], 2);
+        cg.label("step");
+        cg.ret();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0x3E, 0x00,        // _times_1.0.again: LD A, 0
+            0xCD, 0x12, 0x00,  // CALL step
+            0x20, 0xF9,        // JR NZ, again (this copy)
+            0x3E, 0x01,        // _times_1.1.again: LD A, 1
+            0xCD, 0x12, 0x00,  // CALL step
+            0x20, 0xF9,        // JR NZ, again (this copy)
+            0x00, 0xFF, 0x00, 0xFF,
+            0xC9,              // step: RET
+        ]);
+        assert_eq!(cg.get_label("_times_1.1.again"), Some(0x0007));
+        assert!(!cg.has_label("again"));
+        assert!(cg.redefined_labels().is_empty());
+    }
+
+    #[test]
+    fn test_when_flags() {
+        let build = |debug: bool| {
+            let mut cg = CodeGen::new();
+            if debug {
+                cg.set_flag("debug");
+            }
+            cg.when("debug", |cg| cg.ld_a(b'!'));
+            cg.unless("debug", |cg| cg.nop());
+            cg.ret();
+            cg
+        };
+        assert_eq!(build(true).rom(), &[0x3E, 0x21, 0xC9]);  // LD A, '!'; RET
+        assert_eq!(build(false).rom(), &[0x00, 0xC9]);       // NOP; RET
+
+        let mut cg = build(true);
+        cg.set_flag("trace");
+        assert_eq!(cg.flags().collect::<Vec<_>>(), ["debug", "trace"]);
+        cg.clear_flag("debug");
+        assert!(!cg.has_flag("debug"));
+    }
+
+    #[test]
+    fn test_pad_to() {
+        let mut cg = CodeGen::new();
+        cg.emit(&[0x00]);
+        cg.pad_to(0x0004, 0xFF);
+        cg.pad_to(0x0004, 0xFF);
+        assert_eq!(cg.rom(), &[0x00, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot pad")]
+    fn test_pad_to_behind_panics() {
+        let mut cg = CodeGen::new();
+        cg.emit(&[0x00, 0x00]);
+        cg.pad_to(0x0001, 0xFF);
+    }
+
     #[test]
     fn test_config() {
         let config = RomConfig {
             org: 0x8000,
             stack_top: 0xFFFF,
             ram_start: 0xC000,
+            ..Default::default()
         };
         let mut cg = CodeGen::with_config(config);
         assert_eq!(cg.pos(), 0x8000);
@@ -312,6 +1503,284 @@ mod tests {
         assert_eq!(cg.pos(), 0x8001);
     }
 
+    #[test]
+    fn test_fixup_expr() {
+        let mut cg = CodeGen::new();
+        cg.fixup_expr("table", 4);
+        cg.fixup_expr("table", -1);
+        cg.label("table");
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[0x08, 0x00, 0x03, 0x00]);
+    }
+
+    #[test]
+    fn test_fixup_lo_hi() {
+        let mut cg = CodeGen::with_config(RomConfig {
+            org: 0x1200,
+            ..Default::default()
+        });
+        cg.fixup_hi("vectors");
+        cg.fixup_lo("vectors");
+        cg.emit(&[0x00; 0x32]);
+        cg.label("vectors");
+        cg.resolve_fixups();
+        assert_eq!(&cg.rom()[..2], &[0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_replace_region() {
+        let mut cg = CodeGen::new();
+        cg.call("blink");
+        cg.region("blink", |cg| {
+            cg.ld_a(1);
+            cg.label("blink_loop");
+            cg.jp("blink_loop");
+        });
+        cg.call("blink_loop");
+        cg.resolve_fixups();
+        assert_eq!(cg.get_label("blink_end"), Some(0x0008));
+
+        let free = cg.replace_region("blink", |cg| {
+            cg.label("blink_loop");
+            cg.ret();
+        });
+        cg.resolve_fixups();
+        assert_eq!(free, 4);
+        assert_eq!(cg.rom(), &[
+            0xCD, 0x03, 0x00,  // CALL blink
+            0xC9,              // blink: blink_loop: RET
+            0x00,              // NOP
+            0x00,              // NOP
+            0x00,              // NOP
+            0x00,              // NOP
+            0xCD, 0x03, 0x00,  // CALL blink_loop
+        ]);
+        assert!(cg.redefined_labels().is_empty());
+    }
+
+    #[test]
+    fn test_replace_region_keeps_equ() {
+        let mut cg = CodeGen::new();
+        cg.equ("ACIA_DATA", 0x0004);
+        cg.nop();
+        cg.nop();
+        cg.region("acia", |cg| {
+            cg.ld_hl(0);
+            cg.ld_de(0);
+            cg.nop();
+            cg.nop();
+        });
+        cg.ld_hl_label("ACIA_DATA");
+        assert_eq!((cg.get_label("acia"), cg.get_label("acia_end")), (Some(0x0002), Some(0x000A)));
+
+        cg.replace_region("acia", |cg| cg.ret());
+        cg.resolve_fixups();
+        assert_eq!(cg.get_label("ACIA_DATA"), Some(0x0004));
+        assert_eq!(&cg.rom()[0x0A..], &[0x21, 0x04, 0x00]);  // LD HL, ACIA_DATA
+    }
+
+    #[test]
+    #[should_panic(expected = "region blink is 1 bytes, replacement is 2")]
+    fn test_replace_region_too_big() {
+        let mut cg = CodeGen::new();
+        cg.region("blink", |cg| cg.ret());
+        cg.replace_region("blink", |cg| cg.ld_a(0));
+    }
+
+    #[test]
+    fn test_namespace() {
+        let mut cg = CodeGen::new();
+        cg.label("init");
+        cg.ret();
+        cg.namespace("lcd", |cg| {
+            cg.label("init");
+            cg.call("reset");
+            cg.call("putc");
+            cg.label("_loop");
+            cg.djnz("_loop");
+            cg.ret();
+            cg.label("reset");
+            cg.ret();
+        });
+        cg.namespace("kbd", |cg| {
+            cg.label("_loop");
+            cg.jr("_loop");
+            cg.call("lcd.init");
+            cg.call("init");
+        });
+        cg.label("putc");
+        cg.ret();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xC9,                   // init: RET
+            0xCD, 0x0A, 0x00,       // lcd.init: CALL reset (lcd.reset)
+            0xCD, 0x13, 0x00,       // CALL putc (global)
+            0x10, 0xFE,             // _lcd.loop: DJNZ _loop
+            0xC9,                   // RET
+            0xC9,                   // lcd.reset: RET
+            0x18, 0xFE,             // _kbd.loop: JR _loop
+            0xCD, 0x01, 0x00,       // CALL lcd.init
+            0xCD, 0x00, 0x00,       // CALL init (global)
+            0xC9,                   // putc: RET
+        ]);
+        assert_eq!(cg.get_label("lcd.init"), Some(0x0001));
+        assert_eq!(cg.get_label("_kbd.loop"), Some(0x000B));
+        assert!(!cg.has_label("reset"));
+        assert!(cg.redefined_labels().is_empty());
+    }
+
+    #[test]
+    fn test_label_prefix() {
+        let mut cg = CodeGen::new();
+        cg.with_label_prefix("serial_", |cg| {
+            cg.emit_putchar();
+            cg.emit_conout();
+            cg.emit_print_string();
+        });
+        cg.label("putchar");           // A second device
+        cg.out_a(0x40);
+        cg.ret();
+        cg.emit_conout();
+        cg.emit_print_string();
+        cg.resolve_fixups();
+
+        let target = |cg: &CodeGen, label: &str, at: u16| {
+            let addr = (cg.get_label(label).unwrap() + at) as usize;
+            u16::from_le_bytes([cg.rom()[addr], cg.rom()[addr + 1]])
+        };
+        assert_eq!(target(&cg, "serial_conout", 1), cg.get_label("serial_putchar").unwrap());
+        assert_eq!(target(&cg, "conout", 1), cg.get_label("putchar").unwrap());
+        assert_eq!(target(&cg, "serial_print_string", 4), cg.get_label("serial_conout").unwrap());
+        assert_eq!(target(&cg, "print_string", 4), cg.get_label("conout").unwrap());
+        assert!(cg.has_label("serial_putchar_wait"));
+        assert!(cg.redefined_labels().is_empty());
+    }
+
+    #[test]
+    fn test_link() {
+        let mut main = CodeGen::new();
+        main.label("main");
+        main.label("_wait");
+        main.ld_a(b'!');
+        main.call("putc");
+        main.jr("_wait");
+        let mut driver = CodeGen::new();
+        driver.label("putc");
+        driver.label("_wait");
+        driver.in_a(0x80);
+        driver.and_a(0x02);
+        driver.jp_z("_wait");
+        driver.out_a(0x81);
+        driver.ret();
+
+        let mut cg = CodeGen::link(&[main, driver]).unwrap();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0x3E, 0x21,             // LD A, '!'
+            0xCD, 0x07, 0x00,       // CALL putc
+            0x18, 0xF9,             // JR _wait
+            0xDB, 0x80,             // IN A, (0x80)
+            0xE6, 0x02,             // AND 0x02
+            0xCA, 0x07, 0x00,       // JP Z, _wait (the driver's)
+            0xD3, 0x81,             // OUT (0x81), A
+            0xC9,                   // RET
+        ]);
+        assert_eq!(cg.get_label("putc"), Some(0x0007));
+        assert_eq!(cg.get_label("_wait"), Some(0x0000));
+    }
+
+    #[test]
+    fn test_append_equ_and_prefixed_refs() {
+        let mut cg = CodeGen::new();
+        cg.label("putchar");
+        cg.ret();
+        let mut driver = CodeGen::new();
+        driver.equ("BAUD", 0x0002);                  // Inside driver's code range
+        driver.with_label_prefix("serial_", |cg| {
+            cg.label("init");
+            cg.ld_a_label_lo("BAUD");
+            cg.call("putchar");                      // No serial_putchar: the global one
+        });
+        cg.append(&driver).unwrap();
+        cg.resolve_fixups();
+        assert_eq!(cg.get_label("BAUD"), Some(0x0002));
+        assert_eq!(cg.get_label("serial_init"), Some(0x0001));
+        assert_eq!(&cg.rom()[1..], &[
+            0x3E, 0x02,             // serial_init: LD A, BAUD
+            0xCD, 0x00, 0x00,       // CALL putchar
+        ]);
+        assert!(cg.undefined_labels().is_empty());
+    }
+
+    #[test]
+    fn test_link_collisions() {
+        let putc = || {
+            let mut cg = CodeGen::new();
+            cg.label("putc");
+            cg.ret();
+            cg
+        };
+        assert_eq!(
+            CodeGen::link(&[CodeGen::new(), putc(), putc()]).err().map(|e| e.to_string()),
+            Some("putc is defined by both part 1 and part 2".to_string())
+        );
+
+        let var = |name: &str, ram_start| {
+            let mut cg = CodeGen::with_config(RomConfig { ram_start, ..Default::default() });
+            cg.ram_var(name, 4);
+            cg
+        };
+        let mut cg = var("a", 0x2000);
+        assert_eq!(
+            cg.append(&var("b", 0x2002)).err().map(|e| e.to_string()),
+            Some("code and appended code overlap in RAM".to_string())
+        );
+        assert!(!cg.has_label("b"));
+        cg.append(&var("b", 0x2004)).unwrap();
+        assert_eq!(cg.get_label("b"), Some(0x2004));
+        assert_eq!(cg.ram_pos(), 0x2008);
+    }
+
+    #[test]
+    fn test_string_pool() {
+        let mut cg = CodeGen::new();
+        let crlf = cg.pooled_string("\r\n");
+        let hello = cg.pooled_string("Hello\r\n");
+        assert_eq!(cg.pooled_string("\r\n"), crlf);
+        let bye = cg.pooled_string("Bye");
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), b"Hello\r\n\0Bye\0");
+        assert_eq!(cg.get_label(&hello), Some(0));
+        assert_eq!(cg.get_label(&crlf), Some(5));
+        assert_eq!(cg.get_label(&bye), Some(8));
+    }
+
+    #[test]
+    fn test_string_pool_encoded() {
+        let text = TextEncoding { escapes: true, newline: b"\r\n", codepage: None };
+        let mut cg = CodeGen::with_config(RomConfig { text, ..Default::default() });
+        let line = cg.pooled_string("\nX");
+        let x = cg.pooled_string("X");
+        let escaped = cg.pooled_string("a\\n");    // Escape for LF, not a tail "n"
+        let n = cg.pooled_string("n");
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), b"\r\nX\0a\r\n\0n\0");
+        assert_eq!(cg.get_label(&line), Some(0));
+        assert_eq!(cg.get_label(&x), Some(2));
+        assert_eq!(cg.get_label(&escaped), Some(4));
+        assert_eq!(cg.get_label(&n), Some(8));
+    }
+
+    #[test]
+    fn test_ram_var() {
+        let mut cg = CodeGen::new();
+        assert_eq!(cg.ram_var("counter", 2), 0x2000);
+        assert_eq!(cg.ram_var("buffer", 16), 0x2002);
+        assert_eq!(cg.get_label("buffer"), Some(0x2002));
+        assert_eq!(cg.ram_pos(), 0x2012);
+        assert_eq!(cg.size(), 0); // No ROM bytes emitted
+    }
+
     #[test]
     fn test_rom_mut() {
         let mut cg = CodeGen::new();
@@ -0,0 +1,1190 @@
+//! Structured control-flow builders
+//!
+//! Emit conditional blocks and loops without hand-managing branch labels:
+//!
+//! ```rust
+//! use retroshield_z80_workbench::prelude::*;
+//!
+//! let mut rom = CodeGen::new();
+//! rom.cp(b'y');
+//! rom.if_z(|r| {
+//!     r.ld_a(1);
+//! }).else_(|r| {
+//!     r.ld_a(0);
+//! });
+//!
+//! // Poll up to 100 times, leaving early once a byte is received
+//! rom.ld_b(100);
+//! rom.while_nz(|r| { r.ld_a_b(); r.or_a_a(); }, |r| {
+//!     r.dec_b();
+//!     r.in_a(0x80);
+//!     r.bit_a(0);
+//!     r.break_nz();
+//! });
+//! ```
+
+use std::ops::BitOr;
+
+use crate::{CodeGen, Reg16, Reg8};
+
+/// An emitted `if` block awaiting an optional `else` branch
+///
+/// The block is closed when this value is dropped or `else_` is called.
+pub struct IfBlock<'a> {
+    cg: &'a mut CodeGen,
+    skip_label: String,
+    closed: bool,
+}
+
+impl IfBlock<'_> {
+    /// Emit the else branch, taken when the `if` condition was false
+    pub fn else_<F: FnOnce(&mut CodeGen)>(mut self, body: F) {
+        let end = self.cg.unique_label("endif");
+        self.cg.jp(&end);
+        self.cg.label(&self.skip_label);
+        body(self.cg);
+        self.cg.label(&end);
+        self.closed = true;
+    }
+}
+
+impl Drop for IfBlock<'_> {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.cg.label(&self.skip_label);
+        }
+    }
+}
+
+/// Set of register pairs saved by [`CodeGen::routine`]
+///
+/// Combine with `|`, e.g. `Regs::BC | Regs::HL`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Regs(u8);
+
+impl Regs {
+    /// No registers
+    pub const NONE: Regs = Regs(0);
+    /// AF
+    pub const AF: Regs = Regs(0x01);
+    /// BC
+    pub const BC: Regs = Regs(0x02);
+    /// DE
+    pub const DE: Regs = Regs(0x04);
+    /// HL
+    pub const HL: Regs = Regs(0x08);
+    /// IX
+    pub const IX: Regs = Regs(0x10);
+    /// IY
+    pub const IY: Regs = Regs(0x20);
+
+    /// Check whether every register in `other` is in this set
+    pub fn contains(self, other: Regs) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Regs {
+    type Output = Regs;
+
+    fn bitor(self, rhs: Regs) -> Regs {
+        Regs(self.0 | rhs.0)
+    }
+}
+
+/// Push order for saved registers (popped in reverse)
+const SAVE_ORDER: [(Regs, &[u8], &[u8]); 6] = [
+    (Regs::AF, &[0xF5], &[0xF1]),
+    (Regs::BC, &[0xC5], &[0xC1]),
+    (Regs::DE, &[0xD5], &[0xD1]),
+    (Regs::HL, &[0xE5], &[0xE1]),
+    (Regs::IX, &[0xDD, 0xE5], &[0xDD, 0xE1]),
+    (Regs::IY, &[0xFD, 0xE5], &[0xFD, 0xE1]),
+];
+
+/// Largest frame allocated with `DEC SP`s; bigger frames move SP through IX
+const FRAME_DEC_SP_MAX: u8 = 8;
+
+/// IX-based stack frame of a routine built with [`CodeGen::routine_with_frame`]
+///
+/// IX points at the caller's saved IX. Locals sit below it, and above it
+/// are the registers saved by the routine, the return address and any
+/// arguments the caller pushed:
+///
+/// ```text
+/// IX+arg(0)..   caller's pushed arguments (last pushed first)
+///               return address, saved registers
+/// IX+0, IX+1    caller's IX
+/// IX+local(0).. locals, local(0) = IX-locals
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    locals: u8,
+    args: u8,
+}
+
+impl Frame {
+    /// IX displacement of local byte `offset`
+    pub fn local(&self, offset: u8) -> i8 {
+        assert!(offset < self.locals, "local {} outside a {}-byte frame", offset, self.locals);
+        (offset as i16 - self.locals as i16) as i8
+    }
+
+    /// IX displacement of byte `offset` of the caller's pushed arguments,
+    /// starting from the last argument pushed
+    pub fn arg(&self, offset: u8) -> i8 {
+        let d = self.args as u16 + offset as u16;
+        assert!(d <= 127, "argument {} is out of IX range", offset);
+        d as i8
+    }
+
+    /// Bytes of locals
+    pub fn size(&self) -> u8 {
+        self.locals
+    }
+}
+
+/// Boxed arm body of a [`Switch`]
+type Arm<'a> = Box<dyn FnOnce(&mut CodeGen) + 'a>;
+
+/// Minimum number of cases before `switch_a` considers a jump table
+const SWITCH_TABLE_MIN_CASES: usize = 4;
+
+/// Case arms collected for [`CodeGen::switch_a`]
+pub struct Switch<'a> {
+    cases: Vec<(u8, Arm<'a>)>,
+    default: Option<Arm<'a>>,
+}
+
+impl<'a> Switch<'a> {
+    /// Add an arm taken when A equals `value`
+    pub fn case<F: FnOnce(&mut CodeGen) + 'a>(&mut self, value: u8, body: F) -> &mut Self {
+        assert!(
+            self.cases.iter().all(|(v, _)| *v != value),
+            "duplicate switch case {:#04X}",
+            value
+        );
+        self.cases.push((value, Box::new(body)));
+        self
+    }
+
+    /// Set the arm taken when no case matches
+    pub fn default<F: FnOnce(&mut CodeGen) + 'a>(&mut self, body: F) -> &mut Self {
+        self.default = Some(Box::new(body));
+        self
+    }
+}
+
+impl CodeGen {
+    /// Emit `body` guarded by a jump over it (taken via `skip` when the
+    /// condition is false)
+    fn if_block<F: FnOnce(&mut CodeGen)>(
+        &mut self,
+        skip: fn(&mut CodeGen, &str),
+        body: F,
+    ) -> IfBlock<'_> {
+        let skip_label = self.unique_label("else");
+        skip(self, &skip_label);
+        body(self);
+        IfBlock {
+            cg: self,
+            skip_label,
+            closed: false,
+        }
+    }
+
+    /// Emit `body` executed only if the Z flag is set
+    pub fn if_z<F: FnOnce(&mut CodeGen)>(&mut self, body: F) -> IfBlock<'_> {
+        self.if_block(CodeGen::jp_nz, body)
+    }
+
+    /// Emit `body` executed only if the Z flag is clear
+    pub fn if_nz<F: FnOnce(&mut CodeGen)>(&mut self, body: F) -> IfBlock<'_> {
+        self.if_block(CodeGen::jp_z, body)
+    }
+
+    /// Emit `body` executed only if the carry flag is set
+    pub fn if_c<F: FnOnce(&mut CodeGen)>(&mut self, body: F) -> IfBlock<'_> {
+        self.if_block(CodeGen::jp_nc, body)
+    }
+
+    /// Emit `body` executed only if the carry flag is clear
+    pub fn if_nc<F: FnOnce(&mut CodeGen)>(&mut self, body: F) -> IfBlock<'_> {
+        self.if_block(CodeGen::jp_c, body)
+    }
+
+    /// Emit `body` executed only if the sign flag is clear (positive)
+    pub fn if_p<F: FnOnce(&mut CodeGen)>(&mut self, body: F) -> IfBlock<'_> {
+        self.if_block(CodeGen::jp_m, body)
+    }
+
+    /// Emit `body` executed only if the sign flag is set (minus)
+    pub fn if_m<F: FnOnce(&mut CodeGen)>(&mut self, body: F) -> IfBlock<'_> {
+        self.if_block(CodeGen::jp_p, body)
+    }
+
+    /// Emit `body` executed only if parity is even / overflow is set
+    /// (after `LD A,I`: interrupts were enabled)
+    pub fn if_pe<F: FnOnce(&mut CodeGen)>(&mut self, body: F) -> IfBlock<'_> {
+        self.if_block(CodeGen::jp_po, body)
+    }
+
+    /// Emit `body` executed only if parity is odd / overflow is clear
+    pub fn if_po<F: FnOnce(&mut CodeGen)>(&mut self, body: F) -> IfBlock<'_> {
+        self.if_block(CodeGen::jp_pe, body)
+    }
+
+    // ========== Loops ==========
+
+    /// Emit a pre-tested loop: `test` sets the flags and `exit` leaves the
+    /// loop when the condition no longer holds
+    fn while_block<T, F>(&mut self, exit: fn(&mut CodeGen, &str), test: T, body: F)
+    where
+        T: FnOnce(&mut CodeGen),
+        F: FnOnce(&mut CodeGen),
+    {
+        let top = self.unique_label("while");
+        let end = self.unique_label("wend");
+        self.label(&top);
+        test(self);
+        exit(self, &end);
+        self.loops.push((Some(top.clone()), end.clone()));
+        body(self);
+        self.loops.pop();
+        self.jp(&top);
+        self.label(&end);
+    }
+
+    /// Emit a post-tested loop: `body` must leave the flags set for `again`,
+    /// which jumps back to the top while the loop should continue
+    fn repeat_block<F: FnOnce(&mut CodeGen)>(&mut self, again: fn(&mut CodeGen, &str), body: F) {
+        let top = self.unique_label("repeat");
+        let test = self.unique_label("until");
+        let end = self.unique_label("rend");
+        self.label(&top);
+        self.loops.push((Some(test.clone()), end.clone()));
+        body(self);
+        self.loops.pop();
+        self.label(&test);
+        again(self, &top);
+        self.label(&end);
+    }
+
+    /// Emit `body` repeated while the Z flag set by `test` is set
+    pub fn while_z<T, F>(&mut self, test: T, body: F)
+    where
+        T: FnOnce(&mut CodeGen),
+        F: FnOnce(&mut CodeGen),
+    {
+        self.while_block(CodeGen::jp_nz, test, body)
+    }
+
+    /// Emit `body` repeated while the Z flag set by `test` is clear
+    pub fn while_nz<T, F>(&mut self, test: T, body: F)
+    where
+        T: FnOnce(&mut CodeGen),
+        F: FnOnce(&mut CodeGen),
+    {
+        self.while_block(CodeGen::jp_z, test, body)
+    }
+
+    /// Emit `body` repeated while the carry flag set by `test` is set
+    pub fn while_c<T, F>(&mut self, test: T, body: F)
+    where
+        T: FnOnce(&mut CodeGen),
+        F: FnOnce(&mut CodeGen),
+    {
+        self.while_block(CodeGen::jp_nc, test, body)
+    }
+
+    /// Emit `body` repeated while the carry flag set by `test` is clear
+    pub fn while_nc<T, F>(&mut self, test: T, body: F)
+    where
+        T: FnOnce(&mut CodeGen),
+        F: FnOnce(&mut CodeGen),
+    {
+        self.while_block(CodeGen::jp_c, test, body)
+    }
+
+    /// Emit `body` repeated until it finishes with the Z flag set
+    pub fn repeat_until_z<F: FnOnce(&mut CodeGen)>(&mut self, body: F) {
+        self.repeat_block(CodeGen::jp_nz, body)
+    }
+
+    /// Emit `body` repeated until it finishes with the Z flag clear
+    pub fn repeat_until_nz<F: FnOnce(&mut CodeGen)>(&mut self, body: F) {
+        self.repeat_block(CodeGen::jp_z, body)
+    }
+
+    /// Emit `body` repeated until it finishes with the carry flag set
+    pub fn repeat_until_c<F: FnOnce(&mut CodeGen)>(&mut self, body: F) {
+        self.repeat_block(CodeGen::jp_nc, body)
+    }
+
+    /// Emit `body` repeated until it finishes with the carry flag clear
+    pub fn repeat_until_nc<F: FnOnce(&mut CodeGen)>(&mut self, body: F) {
+        self.repeat_block(CodeGen::jp_c, body)
+    }
+
+    /// Emit `body` repeated forever (leave it with `break_*`)
+    pub fn forever<F: FnOnce(&mut CodeGen)>(&mut self, body: F) {
+        let top = self.unique_label("loop");
+        let end = self.unique_label("lend");
+        self.label(&top);
+        self.loops.push((Some(top.clone()), end.clone()));
+        body(self);
+        self.loops.pop();
+        self.jp(&top);
+        self.label(&end);
+    }
+
+    /// Emit `body` executed `count` times, counting down in B
+    ///
+    /// Counts up to 255 load B and close with DJNZ (or DEC B / JP NZ when
+    /// the body is too long for a relative jump). Larger counts fall back
+    /// to a 16-bit BC counter, which also clobbers A. The body must
+    /// preserve B (BC for 16-bit counts).
+    pub fn loop_b<F: FnOnce(&mut CodeGen)>(&mut self, count: u16, body: F) {
+        assert!(count > 0, "loop_b count must be at least 1");
+        let top = self.unique_label("loop_b");
+        let next = self.unique_label("loop_b_next");
+        let end = self.unique_label("loop_b_end");
+        if count <= 255 {
+            self.ld_b(count as u8);
+        } else {
+            self.ld_bc(count);
+        }
+        self.label(&top);
+        self.loops.push((Some(next.clone()), end.clone()));
+        body(self);
+        self.loops.pop();
+        self.label(&next);
+        if count > 255 {
+            self.dec_bc();
+            self.ld_a_b();
+            self.or_c();
+            self.jp_nz(&top);
+        } else if self.get_label(&top).unwrap() as i32 - (self.pos() as i32 + 2) >= -128 {
+            self.djnz(&top);
+        } else {
+            self.dec_b();
+            self.jp_nz(&top);
+        }
+        self.label(&end);
+    }
+
+    // ========== Dispatch ==========
+
+    /// Emit a multi-way branch on the value in A
+    ///
+    /// Sparse cases compile to a CP/JP Z chain, which leaves A intact.
+    /// Four or more cases filling at least half of their value range
+    /// compile to a jump table instead, which clobbers A, DE and HL, so
+    /// arms should not rely on those registers. Every arm continues after
+    /// the switch when its body ends.
+    pub fn switch_a<'a, F: FnOnce(&mut Switch<'a>)>(&mut self, arms: F) {
+        let mut sw = Switch {
+            cases: Vec::new(),
+            default: None,
+        };
+        arms(&mut sw);
+
+        let end = self.unique_label("endsw");
+        let default_label = match sw.default {
+            Some(_) => self.unique_label("default"),
+            None => end.clone(),
+        };
+        let case_labels: Vec<String> = sw.cases.iter().map(|_| self.unique_label("case")).collect();
+
+        let min = sw.cases.iter().map(|(v, _)| *v).min().unwrap_or(0);
+        let max = sw.cases.iter().map(|(v, _)| *v).max().unwrap_or(0);
+        let span = (max - min) as usize + 1;
+
+        if sw.cases.len() >= SWITCH_TABLE_MIN_CASES && span <= sw.cases.len() * 2 {
+            let table = self.unique_label("swtable");
+            if min != 0 {
+                self.sub_a(min);
+            }
+            if span < 256 {
+                self.cp(span as u8);
+                self.jp_nc(&default_label);
+            }
+            self.emit(&[0x6F]);          // LD L, A
+            self.ld_h(0);
+            self.add_hl_hl();
+            self.ld_de_label(&table);
+            self.add_hl_de();
+            self.ld_a_hl_ind();
+            self.inc_hl();
+            self.emit(&[0x66]);          // LD H, (HL)
+            self.emit(&[0x6F]);          // LD L, A
+            self.jp_hl();
+            self.label(&table);
+            for value in min..=max {
+                match sw.cases.iter().position(|(v, _)| *v == value) {
+                    Some(i) => self.fixup(&case_labels[i]),
+                    None => self.fixup(&default_label),
+                }
+            }
+        } else {
+            for ((value, _), label) in sw.cases.iter().zip(&case_labels) {
+                self.cp(*value);
+                self.jp_z(label);
+            }
+            if sw.default.is_none() {
+                self.jp(&end);
+            }
+        }
+
+        // Default arm first (it follows the CP chain), then the cases
+        let mut bodies: Vec<(String, Arm<'a>)> = Vec::new();
+        if let Some(body) = sw.default {
+            bodies.push((default_label, body));
+        }
+        bodies.extend(case_labels.into_iter().zip(sw.cases.into_iter().map(|(_, body)| body)));
+        let last = bodies.len().saturating_sub(1);
+        for (i, (label, body)) in bodies.into_iter().enumerate() {
+            self.label(&label);
+            body(self);
+            if i != last {
+                self.jp(&end);
+            }
+        }
+        self.label(&end);
+    }
+
+    // ========== Routines ==========
+
+    /// Emit a subroutine labelled `name` that preserves `saves`
+    ///
+    /// The saved registers are pushed on entry; leaving through `return_*`
+    /// (or falling off the end of `body`) pops them and returns. Plain
+    /// `ret` inside the body would skip the pops, so use `return_*` instead.
+    pub fn routine<F: FnOnce(&mut CodeGen)>(&mut self, name: &str, saves: Regs, body: F) {
+        assert!(self.routine.is_none(), "routine {} nested inside another routine", name);
+        let exit = self.unique_label("rexit");
+        self.label(name);
+        for (reg, push, _) in SAVE_ORDER {
+            if saves.contains(reg) {
+                self.emit(push);
+            }
+        }
+        self.routine = Some((exit.clone(), saves));
+        body(self);
+        self.routine = None;
+        self.label(&exit);
+        for (reg, _, pop) in SAVE_ORDER.iter().rev() {
+            if saves.contains(*reg) {
+                self.emit(pop);
+            }
+        }
+        self.ret();
+    }
+
+    /// Emit a subroutine labelled `name` with `locals` bytes of IX-based
+    /// stack frame
+    ///
+    /// Like `routine`, but IX is also saved and set up as the frame pointer,
+    /// so the routine is reentrant and may recurse. `body` gets the
+    /// [`Frame`] to address locals and arguments with; leave through
+    /// `return_*`, which frees the frame. The prologue clobbers flags.
+    pub fn routine_with_frame<F: FnOnce(&mut CodeGen, Frame)>(
+        &mut self,
+        name: &str,
+        saves: Regs,
+        locals: u8,
+        body: F,
+    ) {
+        assert!(self.routine.is_none(), "routine {} nested inside another routine", name);
+        assert!(!saves.contains(Regs::IX), "IX is the frame pointer and always saved");
+        assert!(locals <= 128, "frame locals must fit IX-128..IX-1");
+        let exit = self.unique_label("rexit");
+        self.label(name);
+        let mut saved = 0;
+        for (reg, push, _) in SAVE_ORDER {
+            if saves.contains(reg) {
+                self.emit(push);
+                saved += 1;
+            }
+        }
+        self.push_ix();
+        if locals <= FRAME_DEC_SP_MAX {
+            self.ld_ix(0);
+            self.add_ix_sp();
+            for _ in 0..locals {
+                self.dec_sp();
+            }
+        } else {
+            self.ld_ix(-(locals as i16) as u16);
+            self.add_ix_sp();
+            self.ld_sp_ix();
+            self.ld_ix(locals as u16);
+            self.add_ix_sp();
+        }
+        // IX in the saved set makes every return_* go through the epilogue
+        self.routine = Some((exit.clone(), saves | Regs::IX));
+        body(
+            self,
+            Frame {
+                locals,
+                args: 2 + 2 * saved + 2,
+            },
+        );
+        self.routine = None;
+        self.label(&exit);
+        self.ld_sp_ix();
+        self.pop_ix();
+        for (reg, _, pop) in SAVE_ORDER.iter().rev() {
+            if saves.contains(*reg) {
+                self.emit(pop);
+            }
+        }
+        self.ret();
+    }
+
+    /// LD r, (IX+d): load a frame byte
+    pub fn load_local(&mut self, reg: Reg8, d: i8) {
+        assert!(reg != Reg8::HlInd, "load_local needs a register");
+        self.emit(&[0xDD, 0x46 | reg.code() << 3, d as u8]);
+    }
+
+    /// LD (IX+d), r: store a frame byte
+    pub fn store_local(&mut self, d: i8, reg: Reg8) {
+        assert!(reg != Reg8::HlInd, "store_local needs a register");
+        self.emit(&[0xDD, 0x70 | reg.code(), d as u8]);
+    }
+
+    /// Load BC, DE or HL from the frame word at IX+d (low byte first)
+    pub fn load_local16(&mut self, rr: Reg16, d: i8) {
+        let (hi, lo) = Self::frame_pair(rr);
+        self.load_local(lo, d);
+        self.load_local(hi, d.checked_add(1).expect("frame word out of IX range"));
+    }
+
+    /// Store BC, DE or HL into the frame word at IX+d (low byte first)
+    pub fn store_local16(&mut self, d: i8, rr: Reg16) {
+        let (hi, lo) = Self::frame_pair(rr);
+        self.store_local(d, lo);
+        self.store_local(d.checked_add(1).expect("frame word out of IX range"), hi);
+    }
+
+    /// High and low registers of a frame word pair
+    fn frame_pair(rr: Reg16) -> (Reg8, Reg8) {
+        match rr {
+            Reg16::BC => (Reg8::B, Reg8::C),
+            Reg16::DE => (Reg8::D, Reg8::E),
+            Reg16::HL => (Reg8::H, Reg8::L),
+            _ => panic!("frame words load into BC, DE or HL, not {:?}", rr),
+        }
+    }
+
+    /// Label `return_*` jump to, or `None` if they can RET directly
+    ///
+    /// That is the routine's epilogue if it saved registers, or inside
+    /// `with_interrupts_disabled` a stub that first restores the
+    /// interrupt state.
+    fn routine_exit(&mut self) -> Option<String> {
+        let (exit, saves) = self
+            .routine
+            .as_ref()
+            .expect("return_* used outside of a routine builder");
+        let exit = (*saves != Regs::NONE).then(|| exit.clone());
+        if self.critical_depth == 0 {
+            return exit;
+        }
+        if self.critical_return.is_none() {
+            self.critical_return = Some(self.unique_label("critical_return"));
+        }
+        self.critical_return.clone()
+    }
+
+    /// Return from the current routine, restoring its saved registers
+    pub fn return_(&mut self) {
+        match self.routine_exit() {
+            Some(exit) => self.jp(&exit),
+            None => self.ret(),
+        }
+    }
+
+    /// Return from the current routine if the Z flag is set
+    pub fn return_z(&mut self) {
+        match self.routine_exit() {
+            Some(exit) => self.jp_z(&exit),
+            None => self.ret_z(),
+        }
+    }
+
+    /// Return from the current routine if the Z flag is clear
+    pub fn return_nz(&mut self) {
+        match self.routine_exit() {
+            Some(exit) => self.jp_nz(&exit),
+            None => self.ret_nz(),
+        }
+    }
+
+    /// Return from the current routine if the carry flag is set
+    pub fn return_c(&mut self) {
+        match self.routine_exit() {
+            Some(exit) => self.jp_c(&exit),
+            None => self.ret_c(),
+        }
+    }
+
+    /// Return from the current routine if the carry flag is clear
+    pub fn return_nc(&mut self) {
+        match self.routine_exit() {
+            Some(exit) => self.jp_nc(&exit),
+            None => self.ret_nc(),
+        }
+    }
+
+    // ========== Critical Sections ==========
+
+    /// Emit `body` with interrupts disabled, then restore the interrupt
+    /// state found on entry
+    ///
+    /// The outermost block saves IFF2 with `LD A,I` / `PUSH AF` before its
+    /// DI, and ends with `POP AF` / `JP PO` over the EI, so a block entered
+    /// with interrupts off (in an ISR, say) leaves them off. A and the
+    /// flags are clobbered at both ends. Leave the block early with
+    /// `break_*`, which jumps to the restore rather than out of an
+    /// enclosing loop; `return_*` restore the state in a stub after the
+    /// block and then return. `continue_` would skip the restore and
+    /// panics here. Nested blocks only save and restore once.
+    pub fn with_interrupts_disabled<F: FnOnce(&mut CodeGen)>(&mut self, body: F) {
+        let end = self.unique_label("critical_end");
+        let outermost = self.critical_depth == 0;
+        if outermost {
+            self.ld_a_i();           // P/V = IFF2
+            self.push_af();
+            self.di();
+        }
+        self.critical_depth += 1;
+        self.loops.push((None, end.clone()));
+        body(self);
+        self.loops.pop();
+        self.critical_depth -= 1;
+        self.label(&end);
+        if !outermost {
+            return;
+        }
+        self.restore_interrupts();
+        if let Some(stub) = self.critical_return.take() {
+            let done = self.unique_label("critical_done");
+            self.jp(&done);
+            self.label(&stub);
+            self.restore_interrupts();
+            self.return_();
+            self.label(&done);
+        }
+    }
+
+    /// POP AF, then EI unless the P/V flag saved by `LD A,I` is clear
+    fn restore_interrupts(&mut self) {
+        let skip = self.unique_label("critical_di");
+        self.pop_af();
+        self.jp_po(&skip);
+        self.ei();
+        self.label(&skip);
+    }
+
+    /// Emit an inline copy of RAM variable `from` into `to` with interrupts
+    /// disabled, so an ISR never sees a half-updated multi-byte value
+    ///
+    /// Copies the size of `from`; `to` must be at least as large.
+    /// Clobbers AF, BC, DE, HL.
+    pub fn emit_critical_copy(&mut self, from: &str, to: &str) {
+        let size = self
+            .ram_var_size(from)
+            .unwrap_or_else(|| panic!("{} is not a RAM variable (see ram_var)", from));
+        let dest = self
+            .ram_var_size(to)
+            .unwrap_or_else(|| panic!("{} is not a RAM variable (see ram_var)", to));
+        assert!(dest >= size, "RAM variable {} is {} bytes, need {}", to, dest, size);
+        let src = self.get_label(from).unwrap();
+        let dst = self.get_label(to).unwrap();
+        self.ld_hl(src);
+        self.ld_de(dst);
+        self.ld_bc(size);
+        self.with_interrupts_disabled(|r| {
+            r.emit(&[0xED, 0xB0]);   // LDIR
+        });
+    }
+
+    /// Labels (continue, break) of the innermost enclosing loop or block
+    fn innermost_loop(&self) -> (Option<String>, String) {
+        self.loops
+            .last()
+            .cloned()
+            .expect("break/continue used outside of a loop builder")
+    }
+
+    /// Jump out of the innermost loop (or `with_interrupts_disabled` block)
+    pub fn break_(&mut self) {
+        let (_, end) = self.innermost_loop();
+        self.jp(&end);
+    }
+
+    /// Jump out of the innermost loop if the Z flag is set
+    pub fn break_z(&mut self) {
+        let (_, end) = self.innermost_loop();
+        self.jp_z(&end);
+    }
+
+    /// Jump out of the innermost loop if the Z flag is clear
+    pub fn break_nz(&mut self) {
+        let (_, end) = self.innermost_loop();
+        self.jp_nz(&end);
+    }
+
+    /// Jump out of the innermost loop if the carry flag is set
+    pub fn break_c(&mut self) {
+        let (_, end) = self.innermost_loop();
+        self.jp_c(&end);
+    }
+
+    /// Jump out of the innermost loop if the carry flag is clear
+    pub fn break_nc(&mut self) {
+        let (_, end) = self.innermost_loop();
+        self.jp_nc(&end);
+    }
+
+    /// Jump to the next iteration of the innermost loop
+    ///
+    /// In `while_*` loops this re-runs the test; in `repeat_until_*` loops
+    /// it jumps to the closing test, which uses the current flags.
+    pub fn continue_(&mut self) {
+        let (next, _) = self.innermost_loop();
+        let next = next.expect("continue_ cannot leave a with_interrupts_disabled block");
+        self.jp(&next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_without_else() {
+        let mut cg = CodeGen::new();
+        cg.if_z(|r| {
+            r.inc_a();
+        });
+        cg.halt();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xC2, 0x04, 0x00,  // JP NZ, skip
+            0x3C,              // INC A
+            0x76,              // skip: HALT
+        ]);
+    }
+
+    #[test]
+    fn test_if_pe() {
+        let mut cg = CodeGen::new();
+        cg.ld_a_i();
+        cg.if_pe(|r| {
+            r.ei();
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xED, 0x57,        // LD A, I
+            0xE2, 0x06, 0x00,  // JP PO, skip
+            0xFB,              // EI
+        ]);
+    }
+
+    #[test]
+    fn test_if_else() {
+        let mut cg = CodeGen::new();
+        cg.if_c(|r| {
+            r.inc_a();
+        })
+        .else_(|r| {
+            r.dec_a();
+        });
+        cg.halt();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xD2, 0x07, 0x00,  // JP NC, else
+            0x3C,              // INC A
+            0xC3, 0x08, 0x00,  // JP end
+            0x3D,              // else: DEC A
+            0x76,              // end: HALT
+        ]);
+    }
+
+    #[test]
+    fn test_nested_if() {
+        let mut cg = CodeGen::new();
+        cg.if_nz(|r| {
+            r.if_m(|r| {
+                r.nop();
+            });
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xCA, 0x07, 0x00,  // JP Z, outer_skip
+            0xF2, 0x07, 0x00,  // JP P, inner_skip
+            0x00,              // NOP
+        ]);
+    }
+
+    #[test]
+    fn test_while_nz() {
+        let mut cg = CodeGen::new();
+        cg.while_nz(|r| r.or_a_a(), |r| {
+            r.dec_a();
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xB7,              // top: OR A
+            0xCA, 0x08, 0x00,  // JP Z, end
+            0x3D,              // DEC A
+            0xC3, 0x00, 0x00,  // JP top
+        ]);
+    }
+
+    #[test]
+    fn test_repeat_until_with_break_continue() {
+        let mut cg = CodeGen::new();
+        cg.repeat_until_z(|r| {
+            r.break_c();
+            r.continue_();
+            r.dec_a();
+        });
+        cg.halt();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xDA, 0x0A, 0x00,  // top: JP C, end
+            0xC3, 0x07, 0x00,  // JP test
+            0x3D,              // DEC A
+            0xC2, 0x00, 0x00,  // test: JP NZ, top
+            0x76,              // end: HALT
+        ]);
+    }
+
+    #[test]
+    fn test_break_targets_innermost_loop() {
+        let mut cg = CodeGen::new();
+        cg.forever(|r| {
+            r.forever(|r| {
+                r.break_();
+            });
+            r.break_();
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xC3, 0x06, 0x00,  // outer/inner top: JP inner_end
+            0xC3, 0x00, 0x00,  // JP inner_top
+            0xC3, 0x0C, 0x00,  // inner_end: JP outer_end
+            0xC3, 0x00, 0x00,  // JP outer_top
+        ]);
+    }
+
+    #[test]
+    fn test_loop_b() {
+        let mut cg = CodeGen::new();
+        cg.loop_b(10, |r| {
+            r.inc_a();
+        });
+        cg.loop_b(1000, |r| {
+            r.inc_hl();
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0x06, 0x0A,        // LD B, 10
+            0x3C,              // top: INC A
+            0x10, 0xFD,        // DJNZ top
+            0x01, 0xE8, 0x03,  // LD BC, 1000
+            0x23,              // top: INC HL
+            0x0B,              // DEC BC
+            0x78,              // LD A, B
+            0xB1,              // OR C
+            0xC2, 0x08, 0x00,  // JP NZ, top
+        ]);
+    }
+
+    #[test]
+    fn test_loop_b_long_body() {
+        let mut cg = CodeGen::new();
+        cg.loop_b(3, |r| {
+            for _ in 0..200 {
+                r.nop();
+            }
+        });
+        cg.resolve_fixups();
+        assert_eq!(&cg.rom()[202..], &[
+            0x05,              // DEC B
+            0xC2, 0x02, 0x00,  // JP NZ, top
+        ]);
+    }
+
+    #[test]
+    fn test_switch_chain() {
+        let mut cg = CodeGen::new();
+        cg.switch_a(|s| {
+            s.case(b'a', |r| r.inc_b());
+            s.case(b'z', |r| r.dec_b());
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xFE, b'a',        // CP 'a'
+            0xCA, 0x0D, 0x00,  // JP Z, case_a
+            0xFE, b'z',        // CP 'z'
+            0xCA, 0x11, 0x00,  // JP Z, case_z
+            0xC3, 0x12, 0x00,  // JP end
+            0x04,              // case_a: INC B
+            0xC3, 0x12, 0x00,  // JP end
+            0x05,              // case_z: DEC B
+        ]);
+    }
+
+    #[test]
+    fn test_switch_jump_table() {
+        let mut cg = CodeGen::new();
+        cg.switch_a(|s| {
+            s.case(1, |r| r.inc_b());
+            s.case(2, |r| r.inc_c());
+            s.case(3, |r| r.dec_b());
+            s.case(5, |r| r.dec_c());
+            s.default(|r| r.xor_a());
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xD6, 0x01,        // SUB 1
+            0xFE, 0x05,        // CP 5
+            0xD2, 0x1E, 0x00,  // JP NC, default
+            0x6F,              // LD L, A
+            0x26, 0x00,        // LD H, 0
+            0x29,              // ADD HL, HL
+            0x11, 0x14, 0x00,  // LD DE, table
+            0x19,              // ADD HL, DE
+            0x7E,              // LD A, (HL)
+            0x23,              // INC HL
+            0x66,              // LD H, (HL)
+            0x6F,              // LD L, A
+            0xE9,              // JP (HL)
+            0x22, 0x00,        // table: case 1
+            0x26, 0x00,        // case 2
+            0x2A, 0x00,        // case 3
+            0x1E, 0x00,        // (4) default
+            0x2E, 0x00,        // case 5
+            0xAF,              // default: XOR A
+            0xC3, 0x2F, 0x00,  // JP end
+            0x04,              // case 1: INC B
+            0xC3, 0x2F, 0x00,  // JP end
+            0x0C,              // case 2: INC C
+            0xC3, 0x2F, 0x00,  // JP end
+            0x05,              // case 3: DEC B
+            0xC3, 0x2F, 0x00,  // JP end
+            0x0D,              // case 5: DEC C
+        ]);
+    }
+
+    #[test]
+    fn test_routine_restores_on_every_exit() {
+        let mut cg = CodeGen::new();
+        cg.routine("count", Regs::BC | Regs::HL, |r| {
+            r.or_a_a();
+            r.return_z();
+            r.inc_a();
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.get_label("count"), Some(0x0000));
+        assert_eq!(cg.rom(), &[
+            0xC5,              // PUSH BC
+            0xE5,              // PUSH HL
+            0xB7,              // OR A
+            0xCA, 0x07, 0x00,  // JP Z, exit
+            0x3C,              // INC A
+            0xE1,              // exit: POP HL
+            0xC1,              // POP BC
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    fn test_routine_without_saves() {
+        let mut cg = CodeGen::new();
+        cg.routine("noop", Regs::NONE, |r| {
+            r.return_c();
+        });
+        assert_eq!(cg.rom(), &[
+            0xD8,              // RET C
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    fn test_routine_with_frame() {
+        let mut cg = CodeGen::new();
+        cg.routine_with_frame("sum", Regs::BC, 2, |r, f| {
+            assert_eq!((f.local(0), f.local(1), f.arg(0)), (-2, -1, 6));
+            r.load_local(Reg8::A, f.arg(0));
+            r.or_a_a();
+            r.return_z();
+            r.store_local16(f.local(0), Reg16::HL);
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xC5,              // PUSH BC
+            0xDD, 0xE5,        // PUSH IX
+            0xDD, 0x21, 0x00, 0x00, // LD IX, 0
+            0xDD, 0x39,        // ADD IX, SP
+            0x3B,              // DEC SP
+            0x3B,              // DEC SP
+            0xDD, 0x7E, 0x06,  // LD A, (IX+6)
+            0xB7,              // OR A
+            0xCA, 0x18, 0x00,  // JP Z, exit
+            0xDD, 0x75, 0xFE,  // LD (IX-2), L
+            0xDD, 0x74, 0xFF,  // LD (IX-1), H
+            0xDD, 0xF9,        // exit: LD SP, IX
+            0xDD, 0xE1,        // POP IX
+            0xC1,              // POP BC
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    fn test_large_frame() {
+        let mut cg = CodeGen::new();
+        cg.routine_with_frame("big", Regs::NONE, 100, |r, f| {
+            assert_eq!((f.local(0), f.arg(0)), (-100, 4));
+            r.return_();
+        });
+        assert_eq!(&cg.rom()[..16], &[
+            0xDD, 0xE5,        // PUSH IX
+            0xDD, 0x21, 0x9C, 0xFF, // LD IX, -100
+            0xDD, 0x39,        // ADD IX, SP
+            0xDD, 0xF9,        // LD SP, IX
+            0xDD, 0x21, 0x64, 0x00, // LD IX, 100
+            0xDD, 0x39,        // ADD IX, SP
+        ]);
+    }
+
+    #[test]
+    fn test_critical_section() {
+        let mut cg = CodeGen::new();
+        cg.forever(|r| {
+            r.with_interrupts_disabled(|r| {
+                r.with_interrupts_disabled(|r| {
+                    r.or_a_a();
+                });
+                r.break_z();
+                r.inc_a();
+            });
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xED, 0x57,        // top: LD A, I (P/V = IFF2)
+            0xF5,              // PUSH AF
+            0xF3,              // DI
+            0xB7,              // OR A (nested block: no save/restore)
+            0xCA, 0x09, 0x00,  // JP Z, critical_end
+            0x3C,              // INC A
+            0xF1,              // critical_end: POP AF
+            0xE2, 0x0E, 0x00,  // JP PO, critical_di
+            0xFB,              // EI
+            0xC3, 0x00, 0x00,  // critical_di: JP top
+        ]);
+    }
+
+    #[test]
+    fn test_return_inside_critical_section() {
+        let mut cg = CodeGen::new();
+        cg.routine("r", Regs::BC, |r| {
+            r.with_interrupts_disabled(|r| {
+                r.or_a_a();
+                r.return_z();
+            });
+            r.inc_a();
+        });
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xC5,              // r: PUSH BC
+            0xED, 0x57,        // LD A, I
+            0xF5,              // PUSH AF
+            0xF3,              // DI
+            0xB7,              // OR A
+            0xCA, 0x11, 0x00,  // JP Z, critical_return
+            0xF1,              // critical_end: POP AF
+            0xE2, 0x0E, 0x00,  // JP PO, critical_di
+            0xFB,              // EI
+            0xC3, 0x19, 0x00,  // critical_di: JP critical_done
+            0xF1,              // critical_return: POP AF
+            0xE2, 0x16, 0x00,  // JP PO, critical_di
+            0xFB,              // EI
+            0xC3, 0x1A, 0x00,  // critical_di: JP rexit
+            0x3C,              // critical_done: INC A
+            0xC1,              // rexit: POP BC
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "continue_ cannot leave")]
+    fn test_continue_inside_critical_section() {
+        let mut cg = CodeGen::new();
+        cg.forever(|r| r.with_interrupts_disabled(|r| r.continue_()));
+    }
+
+    #[test]
+    fn test_critical_copy() {
+        let mut cg = CodeGen::new();
+        cg.ram_var("ticks", 4);
+        cg.ram_var("snapshot", 4);
+        cg.emit_critical_copy("ticks", "snapshot");
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0x21, 0x00, 0x20,  // LD HL, ticks
+            0x11, 0x04, 0x20,  // LD DE, snapshot
+            0x01, 0x04, 0x00,  // LD BC, 4
+            0xED, 0x57,        // LD A, I
+            0xF5,              // PUSH AF
+            0xF3,              // DI
+            0xED, 0xB0,        // LDIR
+            0xF1,              // POP AF
+            0xE2, 0x14, 0x00,  // JP PO, critical_di
+            0xFB,              // EI
+        ]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_break_outside_loop() {
+        let mut cg = CodeGen::new();
+        cg.break_();
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod emulator_tests {
+    use crate::emulator::Emulator;
+    use crate::{CodeGen, Regs};
+
+    #[test]
+    fn test_critical_section_restores_interrupt_state() {
+        let mut cg = CodeGen::new();
+        cg.routine("crit", Regs::BC, |r| {
+            r.with_interrupts_disabled(|r| {
+                r.cp(1);
+                r.return_z();            // A = 1: through the return stub
+                r.cp(2);
+                r.break_z();             // A = 2: through the block's end
+            });
+            r.ld_c(0x55);
+        });
+        cg.resolve_fixups();
+        let mut emu = Emulator::new(&cg);
+        for enabled in [false, true] {
+            for a in [0, 1, 2] {
+                emu.cpu.iff1 = enabled;
+                emu.cpu.iff2 = enabled;
+                emu.cpu.a = a;
+                assert!(emu.call_label("crit", 100));
+                assert_eq!((emu.cpu.iff1, emu.cpu.iff2), (enabled, enabled), "A = {}", a);
+            }
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Coverage reports from emulator runs
+//!
+//! Given the set of addresses an emulator executed (instruction start
+//! addresses), report stdlib routines that were never entered and
+//! conditional branches that only ever went one way, and suggest the
+//! individual `emit_*` calls that could replace `include_stdlib`.
+//! With the `emulator` feature, `Emulator::record_executed` collects the
+//! addresses.
+//!
+//! ```rust
+//! use retroshield_z80_workbench::prelude::*;
+//!
+//! let mut rom = CodeGen::new();
+//! rom.call("newline");
+//! rom.halt();
+//! rom.include_stdlib();
+//! rom.resolve_fixups();
+//!
+//! // Addresses reported by the emulator (here: a hand-written trace)
+//! let newline = rom.get_label("newline").unwrap();
+//! let report = rom.coverage_report([0x0000, 0x0003, newline]);
+//! assert!(report.unused_routines.contains(&"div16".to_string()));
+//! println!("{}", report);
+//! ```
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::opcodes::{decode, Operand};
+use crate::stdlib::ROUTINES;
+use crate::CodeGen;
+
+/// A conditional branch that was executed but only went one way
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BranchCoverage {
+    /// Address of the branch instruction
+    pub addr: u16,
+    /// Disassembled instruction
+    pub text: String,
+    /// Whether the branch target was ever executed (`None` for RET cc)
+    pub taken: Option<bool>,
+    /// Whether the following instruction was ever executed
+    pub fallthrough: bool,
+}
+
+/// Result of [`CodeGen::coverage_report`]
+#[derive(Clone, Debug, Default)]
+pub struct CoverageReport {
+    /// Stdlib routines present in the ROM but never entered
+    pub unused_routines: Vec<String>,
+    /// Stdlib routines that were entered
+    pub used_routines: Vec<String>,
+    /// Executed conditional branches missing one of their outcomes
+    pub partial_branches: Vec<BranchCoverage>,
+}
+
+impl CoverageReport {
+    /// Stdlib emitter calls covering only the routines that were used
+    pub fn suggested_stdlib(&self) -> Vec<&'static str> {
+        ROUTINES
+            .iter()
+            .filter(|(label, _)| self.used_routines.iter().any(|u| u == label))
+            .map(|(_, emitter)| *emitter)
+            .collect()
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Unused stdlib routines:")?;
+        for name in &self.unused_routines {
+            writeln!(f, "  {}", name)?;
+        }
+        writeln!(f, "Partially covered branches:")?;
+        for b in &self.partial_branches {
+            let missing = if b.taken == Some(false) { "never taken" } else { "never fell through" };
+            writeln!(f, "  {:04X}  {:<16} {}", b.addr, b.text, missing)?;
+        }
+        writeln!(f, "Suggested stdlib (instead of include_stdlib):")?;
+        for emitter in self.suggested_stdlib() {
+            writeln!(f, "  rom.{}();", emitter)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check whether an opcode syntax is a conditional branch
+fn is_conditional(syntax: &str) -> bool {
+    let mut parts = syntax.splitn(2, ' ');
+    let mnemonic = parts.next().unwrap_or("");
+    let operands = parts.next().unwrap_or("");
+    let cond = operands.split(',').next().unwrap_or("");
+    match mnemonic {
+        "DJNZ" => true,
+        "JP" | "JR" | "CALL" | "RET" => {
+            matches!(cond, "NZ" | "Z" | "NC" | "C" | "PO" | "PE" | "P" | "M")
+        }
+        _ => false,
+    }
+}
+
+impl CodeGen {
+    /// Build a coverage report from the addresses an emulator executed
+    pub fn coverage_report<I: IntoIterator<Item = u16>>(&self, executed: I) -> CoverageReport {
+        let executed: HashSet<u16> = executed.into_iter().collect();
+        let mut report = CoverageReport::default();
+
+        for (label, _) in ROUTINES {
+            if let Some(addr) = self.get_label(label) {
+                if executed.contains(&addr) {
+                    report.used_routines.push(label.to_string());
+                } else {
+                    report.unused_routines.push(label.to_string());
+                }
+            }
+        }
+
+        let org = self.config().org;
+        let mut pcs: Vec<u16> = executed.iter().copied().collect();
+        pcs.sort_unstable();
+        for pc in pcs {
+            let offset = pc.wrapping_sub(org) as usize;
+            if offset >= self.rom().len() {
+                continue;
+            }
+            let Some(d) = decode(&self.rom()[offset..]) else {
+                continue;
+            };
+            if !is_conditional(d.opcode.syntax) {
+                continue;
+            }
+            let next = pc.wrapping_add(d.size() as u16);
+            let taken = match d.opcode.operand {
+                Operand::Imm16 => Some(d.value),
+                Operand::Rel8 => Some(next.wrapping_add(d.value as u8 as i8 as u16)),
+                _ => None,
+            }
+            .map(|target| executed.contains(&target));
+            let fallthrough = executed.contains(&next);
+            if taken == Some(false) || !fallthrough {
+                report.partial_branches.push(BranchCoverage {
+                    addr: pc,
+                    text: d.text(pc),
+                    taken,
+                    fallthrough,
+                });
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unused_routines_and_suggestion() {
+        let mut rom = CodeGen::new();
+        rom.call("print_string");
+        rom.halt();
+        rom.include_stdlib();
+        rom.resolve_fixups();
+        let used = ["print_string", "conout", "putchar"].map(|l| rom.get_label(l).unwrap());
+        let report = rom.coverage_report(used);
+        assert!(report.unused_routines.contains(&"div16".to_string()));
+        assert!(!report.unused_routines.contains(&"conout".to_string()));
+        assert_eq!(
+            report.suggested_stdlib(),
+            ["emit_putchar", "emit_conout", "emit_print_string"]
+        );
+    }
+
+    #[test]
+    fn test_partial_branch() {
+        let mut rom = CodeGen::new();
+        rom.or_a_a();                // 0x0000
+        rom.jp_z("skip");            // 0x0001
+        rom.inc_a();                 // 0x0004
+        rom.label("skip");
+        rom.halt();                  // 0x0005
+        rom.resolve_fixups();
+        let report = rom.coverage_report([0x0000, 0x0001, 0x0004, 0x0005]);
+        assert!(report.partial_branches.is_empty());
+
+        let report = rom.coverage_report([0x0000, 0x0001, 0x0004]);
+        assert_eq!(report.partial_branches, [BranchCoverage {
+            addr: 0x0001,
+            text: "JP Z,0x0005".to_string(),
+            taken: Some(false),
+            fallthrough: true,
+        }]);
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn test_report_from_emulator() {
+        use crate::emulator::Emulator;
+
+        let mut rom = CodeGen::new();
+        rom.ld_a(5);
+        rom.or_a_a();
+        rom.jp_z("skip");
+        rom.call("print_byte_dec");
+        rom.halt();
+        rom.label("skip");
+        rom.halt();
+        rom.include_stdlib();
+        rom.resolve_fixups();
+
+        let mut emu = Emulator::new(&rom);
+        emu.record_executed();
+        assert!(emu.run(10_000));
+        assert_eq!(emu.output(), "5");
+        let report = rom.coverage_report(emu.executed());
+        assert!(report.used_routines.contains(&"print_byte_dec".to_string()));
+        assert!(report.unused_routines.contains(&"div16".to_string()));
+        assert!(report.partial_branches.iter().any(|b| b.addr == 0x0003 && b.taken == Some(false)));
+    }
+}
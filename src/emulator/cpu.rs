@@ -0,0 +1,1169 @@
+//! Z80 CPU core
+//!
+//! Instruction-level: every documented instruction, plus the undocumented
+//! flag bits 3 and 5, IXH/IXL/IYH/IYL and SLL. Each step returns the
+//! instruction's T-states. Interrupts are taken between instructions by
+//! `interrupt` and `nmi`.
+
+/// Memory and I/O bus seen by the CPU
+pub trait Bus {
+    /// Read a byte of memory
+    fn read(&mut self, addr: u16) -> u8;
+    /// Write a byte of memory
+    fn write(&mut self, addr: u16, value: u8);
+    /// Read a port; the high byte is A or B, as on the real address bus
+    fn input(&mut self, port: u16) -> u8;
+    /// Write a port
+    fn output(&mut self, port: u16, value: u8);
+}
+
+/// Flag register bits
+pub const FLAG_C: u8 = 0x01;
+pub const FLAG_N: u8 = 0x02;
+pub const FLAG_PV: u8 = 0x04;
+pub const FLAG_X: u8 = 0x08;
+pub const FLAG_H: u8 = 0x10;
+pub const FLAG_Y: u8 = 0x20;
+pub const FLAG_Z: u8 = 0x40;
+pub const FLAG_S: u8 = 0x80;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Index {
+    Hl,
+    Ix,
+    Iy,
+}
+
+/// Z80 register file and execution state
+///
+/// The alternate set is kept as register pairs; `halted` is set by HALT
+/// and cleared by an interrupt, and `cycles` counts T-states since reset.
+#[derive(Clone, Debug, Default)]
+pub struct Cpu {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub af_alt: u16,
+    pub bc_alt: u16,
+    pub de_alt: u16,
+    pub hl_alt: u16,
+    pub ix: u16,
+    pub iy: u16,
+    pub sp: u16,
+    pub pc: u16,
+    pub i: u8,
+    pub r: u8,
+    pub iff1: bool,
+    pub iff2: bool,
+    pub im: u8,
+    pub halted: bool,
+    pub cycles: u64,
+    ei_pending: bool,
+}
+
+fn parity(v: u8) -> bool {
+    v.count_ones() % 2 == 0
+}
+
+fn szp(v: u8) -> u8 {
+    let mut f = v & (FLAG_S | FLAG_X | FLAG_Y);
+    if v == 0 {
+        f |= FLAG_Z;
+    }
+    if parity(v) {
+        f |= FLAG_PV;
+    }
+    f
+}
+
+impl Cpu {
+    /// CPU in its reset state: all registers zero, interrupts off, IM 0
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register pair AF
+    pub fn af(&self) -> u16 {
+        u16::from_be_bytes([self.a, self.f])
+    }
+
+    /// Register pair BC
+    pub fn bc(&self) -> u16 {
+        u16::from_be_bytes([self.b, self.c])
+    }
+
+    /// Register pair DE
+    pub fn de(&self) -> u16 {
+        u16::from_be_bytes([self.d, self.e])
+    }
+
+    /// Register pair HL
+    pub fn hl(&self) -> u16 {
+        u16::from_be_bytes([self.h, self.l])
+    }
+
+    /// Set register pair AF
+    pub fn set_af(&mut self, v: u16) {
+        [self.a, self.f] = v.to_be_bytes();
+    }
+
+    /// Set register pair BC
+    pub fn set_bc(&mut self, v: u16) {
+        [self.b, self.c] = v.to_be_bytes();
+    }
+
+    /// Set register pair DE
+    pub fn set_de(&mut self, v: u16) {
+        [self.d, self.e] = v.to_be_bytes();
+    }
+
+    /// Set register pair HL
+    pub fn set_hl(&mut self, v: u16) {
+        [self.h, self.l] = v.to_be_bytes();
+    }
+
+    fn flag(&self, f: u8) -> bool {
+        self.f & f != 0
+    }
+
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let v = bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        v
+    }
+
+    fn fetch_word(&mut self, bus: &mut impl Bus) -> u16 {
+        let lo = self.fetch(bus);
+        let hi = self.fetch(bus);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn read_word(bus: &mut impl Bus, addr: u16) -> u16 {
+        let lo = bus.read(addr);
+        let hi = bus.read(addr.wrapping_add(1));
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn write_word(bus: &mut impl Bus, addr: u16, v: u16) {
+        bus.write(addr, v as u8);
+        bus.write(addr.wrapping_add(1), (v >> 8) as u8);
+    }
+
+    fn push(&mut self, bus: &mut impl Bus, v: u16) {
+        self.sp = self.sp.wrapping_sub(2);
+        Self::write_word(bus, self.sp, v);
+    }
+
+    fn pop(&mut self, bus: &mut impl Bus) -> u16 {
+        let v = Self::read_word(bus, self.sp);
+        self.sp = self.sp.wrapping_add(2);
+        v
+    }
+
+    fn inc_r(&mut self) {
+        self.r = (self.r & 0x80) | (self.r.wrapping_add(1) & 0x7F);
+    }
+
+    fn cond(&self, cc: u8) -> bool {
+        match cc {
+            0 => !self.flag(FLAG_Z),
+            1 => self.flag(FLAG_Z),
+            2 => !self.flag(FLAG_C),
+            3 => self.flag(FLAG_C),
+            4 => !self.flag(FLAG_PV),
+            5 => self.flag(FLAG_PV),
+            6 => !self.flag(FLAG_S),
+            _ => self.flag(FLAG_S),
+        }
+    }
+
+    fn idx_reg(&self, idx: Index) -> u16 {
+        match idx {
+            Index::Hl => self.hl(),
+            Index::Ix => self.ix,
+            Index::Iy => self.iy,
+        }
+    }
+
+    fn set_idx_reg(&mut self, idx: Index, v: u16) {
+        match idx {
+            Index::Hl => self.set_hl(v),
+            Index::Ix => self.ix = v,
+            Index::Iy => self.iy = v,
+        }
+    }
+
+    /// Read 8-bit register by table index (6 is not handled here)
+    fn reg8(&self, r: u8, idx: Index) -> u8 {
+        match r {
+            0 => self.b,
+            1 => self.c,
+            2 => self.d,
+            3 => self.e,
+            4 => (self.idx_reg(idx) >> 8) as u8,
+            5 => self.idx_reg(idx) as u8,
+            7 => self.a,
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_reg8(&mut self, r: u8, idx: Index, v: u8) {
+        match r {
+            0 => self.b = v,
+            1 => self.c = v,
+            2 => self.d = v,
+            3 => self.e = v,
+            4 => {
+                let w = self.idx_reg(idx);
+                self.set_idx_reg(idx, (w & 0x00FF) | ((v as u16) << 8));
+            }
+            5 => {
+                let w = self.idx_reg(idx);
+                self.set_idx_reg(idx, (w & 0xFF00) | v as u16);
+            }
+            7 => self.a = v,
+            _ => unreachable!(),
+        }
+    }
+
+    fn rp(&self, p: u8, idx: Index) -> u16 {
+        match p {
+            0 => self.bc(),
+            1 => self.de(),
+            2 => self.idx_reg(idx),
+            _ => self.sp,
+        }
+    }
+
+    fn set_rp(&mut self, p: u8, idx: Index, v: u16) {
+        match p {
+            0 => self.set_bc(v),
+            1 => self.set_de(v),
+            2 => self.set_idx_reg(idx, v),
+            _ => self.sp = v,
+        }
+    }
+
+    fn rp2(&self, p: u8, idx: Index) -> u16 {
+        if p == 3 {
+            self.af()
+        } else {
+            self.rp(p, idx)
+        }
+    }
+
+    fn set_rp2(&mut self, p: u8, idx: Index, v: u16) {
+        if p == 3 {
+            self.set_af(v)
+        } else {
+            self.set_rp(p, idx, v)
+        }
+    }
+
+    // ========== ALU ==========
+
+    fn add8(&mut self, v: u8, carry: bool) {
+        let c = if carry && self.flag(FLAG_C) { 1 } else { 0 };
+        let a = self.a;
+        let res16 = a as u16 + v as u16 + c as u16;
+        let res = res16 as u8;
+        let mut f = res & (FLAG_S | FLAG_X | FLAG_Y);
+        if res == 0 {
+            f |= FLAG_Z;
+        }
+        if (a & 0x0F) + (v & 0x0F) + c > 0x0F {
+            f |= FLAG_H;
+        }
+        if (a ^ v) & 0x80 == 0 && (a ^ res) & 0x80 != 0 {
+            f |= FLAG_PV;
+        }
+        if res16 > 0xFF {
+            f |= FLAG_C;
+        }
+        self.a = res;
+        self.f = f;
+    }
+
+    fn sub8(&mut self, v: u8, carry: bool, store: bool) {
+        let c = if carry && self.flag(FLAG_C) { 1 } else { 0 };
+        let a = self.a;
+        let res16 = (a as u16).wrapping_sub(v as u16).wrapping_sub(c as u16);
+        let res = res16 as u8;
+        let mut f = (res & FLAG_S) | FLAG_N;
+        if store {
+            f |= res & (FLAG_X | FLAG_Y);
+        } else {
+            f |= v & (FLAG_X | FLAG_Y);
+        }
+        if res == 0 {
+            f |= FLAG_Z;
+        }
+        if (a & 0x0F) < (v & 0x0F) + c {
+            f |= FLAG_H;
+        }
+        if (a ^ v) & 0x80 != 0 && (a ^ res) & 0x80 != 0 {
+            f |= FLAG_PV;
+        }
+        if res16 > 0xFF {
+            f |= FLAG_C;
+        }
+        if store {
+            self.a = res;
+        }
+        self.f = f;
+    }
+
+    fn alu(&mut self, op: u8, v: u8) {
+        match op {
+            0 => self.add8(v, false),
+            1 => self.add8(v, true),
+            2 => self.sub8(v, false, true),
+            3 => self.sub8(v, true, true),
+            4 => {
+                self.a &= v;
+                self.f = szp(self.a) | FLAG_H;
+            }
+            5 => {
+                self.a ^= v;
+                self.f = szp(self.a);
+            }
+            6 => {
+                self.a |= v;
+                self.f = szp(self.a);
+            }
+            _ => self.sub8(v, false, false),
+        }
+    }
+
+    fn inc8(&mut self, v: u8) -> u8 {
+        let res = v.wrapping_add(1);
+        let mut f = (self.f & FLAG_C) | (res & (FLAG_S | FLAG_X | FLAG_Y));
+        if res == 0 {
+            f |= FLAG_Z;
+        }
+        if v & 0x0F == 0x0F {
+            f |= FLAG_H;
+        }
+        if v == 0x7F {
+            f |= FLAG_PV;
+        }
+        self.f = f;
+        res
+    }
+
+    fn dec8(&mut self, v: u8) -> u8 {
+        let res = v.wrapping_sub(1);
+        let mut f = (self.f & FLAG_C) | FLAG_N | (res & (FLAG_S | FLAG_X | FLAG_Y));
+        if res == 0 {
+            f |= FLAG_Z;
+        }
+        if v & 0x0F == 0 {
+            f |= FLAG_H;
+        }
+        if v == 0x80 {
+            f |= FLAG_PV;
+        }
+        self.f = f;
+        res
+    }
+
+    fn add16(&mut self, a: u16, b: u16) -> u16 {
+        let res = a as u32 + b as u32;
+        let mut f = self.f & (FLAG_S | FLAG_Z | FLAG_PV);
+        f |= ((res >> 8) as u8) & (FLAG_X | FLAG_Y);
+        if (a & 0x0FFF) + (b & 0x0FFF) > 0x0FFF {
+            f |= FLAG_H;
+        }
+        if res > 0xFFFF {
+            f |= FLAG_C;
+        }
+        self.f = f;
+        res as u16
+    }
+
+    fn adc16(&mut self, a: u16, b: u16) -> u16 {
+        let c = (self.f & FLAG_C) as u32;
+        let res32 = a as u32 + b as u32 + c;
+        let res = res32 as u16;
+        let mut f = ((res >> 8) as u8) & (FLAG_S | FLAG_X | FLAG_Y);
+        if res == 0 {
+            f |= FLAG_Z;
+        }
+        if (a & 0x0FFF) as u32 + (b & 0x0FFF) as u32 + c > 0x0FFF {
+            f |= FLAG_H;
+        }
+        if (a ^ b) & 0x8000 == 0 && (a ^ res) & 0x8000 != 0 {
+            f |= FLAG_PV;
+        }
+        if res32 > 0xFFFF {
+            f |= FLAG_C;
+        }
+        self.f = f;
+        res
+    }
+
+    fn sbc16(&mut self, a: u16, b: u16) -> u16 {
+        let c = (self.f & FLAG_C) as u32;
+        let res32 = (a as u32).wrapping_sub(b as u32).wrapping_sub(c);
+        let res = res32 as u16;
+        let mut f = (((res >> 8) as u8) & (FLAG_S | FLAG_X | FLAG_Y)) | FLAG_N;
+        if res == 0 {
+            f |= FLAG_Z;
+        }
+        if ((a & 0x0FFF) as u32) < (b & 0x0FFF) as u32 + c {
+            f |= FLAG_H;
+        }
+        if (a ^ b) & 0x8000 != 0 && (a ^ res) & 0x8000 != 0 {
+            f |= FLAG_PV;
+        }
+        if res32 > 0xFFFF {
+            f |= FLAG_C;
+        }
+        self.f = f;
+        res
+    }
+
+    fn daa(&mut self) {
+        let a = self.a;
+        let mut corr = 0u8;
+        let mut carry = self.flag(FLAG_C);
+        if self.flag(FLAG_H) || a & 0x0F > 9 {
+            corr |= 0x06;
+        }
+        if carry || a > 0x99 {
+            corr |= 0x60;
+            carry = true;
+        }
+        let n = self.flag(FLAG_N);
+        let res = if n { a.wrapping_sub(corr) } else { a.wrapping_add(corr) };
+        let h = if n {
+            self.flag(FLAG_H) && (a & 0x0F) < 6
+        } else {
+            (a & 0x0F) > 9
+        };
+        let mut f = szp(res) | (self.f & FLAG_N);
+        if h {
+            f |= FLAG_H;
+        }
+        if carry {
+            f |= FLAG_C;
+        }
+        self.a = res;
+        self.f = f;
+    }
+
+    /// CB-group rotate/shift. Returns result and sets flags.
+    fn rot(&mut self, op: u8, v: u8) -> u8 {
+        let c_in = self.f & FLAG_C;
+        let (res, c_out) = match op {
+            0 => (v.rotate_left(1), v >> 7),
+            1 => (v.rotate_right(1), v & 1),
+            2 => ((v << 1) | c_in, v >> 7),
+            3 => ((v >> 1) | (c_in << 7), v & 1),
+            4 => (v << 1, v >> 7),
+            5 => ((v >> 1) | (v & 0x80), v & 1),
+            6 => ((v << 1) | 1, v >> 7),
+            _ => (v >> 1, v & 1),
+        };
+        self.f = szp(res) | c_out;
+        res
+    }
+
+    // ========== Execution ==========
+
+    /// Accept a maskable interrupt if enabled. Returns T-states consumed.
+    pub fn interrupt(&mut self, bus: &mut impl Bus, data: u8) -> u32 {
+        if !self.iff1 || self.ei_pending {
+            return 0;
+        }
+        self.halted = false;
+        self.iff1 = false;
+        self.iff2 = false;
+        self.inc_r();
+        let t = match self.im {
+            2 => {
+                self.push(bus, self.pc);
+                let vec = u16::from_be_bytes([self.i, data]);
+                self.pc = Self::read_word(bus, vec);
+                19
+            }
+            1 => {
+                self.push(bus, self.pc);
+                self.pc = 0x0038;
+                13
+            }
+            _ => {
+                // Only RST instructions are supported on the data bus
+                self.push(bus, self.pc);
+                self.pc = (data & 0x38) as u16;
+                13
+            }
+        };
+        self.cycles += t as u64;
+        t
+    }
+
+    /// Non-maskable interrupt
+    pub fn nmi(&mut self, bus: &mut impl Bus) -> u32 {
+        self.halted = false;
+        self.iff2 = self.iff1;
+        self.iff1 = false;
+        self.inc_r();
+        self.push(bus, self.pc);
+        self.pc = 0x0066;
+        self.cycles += 11;
+        11
+    }
+
+    /// Execute one instruction, returning T-states consumed
+    pub fn step(&mut self, bus: &mut impl Bus) -> u32 {
+        self.ei_pending = false;
+        if self.halted {
+            self.inc_r();
+            self.cycles += 4;
+            return 4;
+        }
+        let op = self.fetch(bus);
+        self.inc_r();
+        let t = match op {
+            0xCB => self.exec_cb(bus, Index::Hl, 0),
+            0xED => self.exec_ed(bus),
+            0xDD => self.exec_index(bus, Index::Ix),
+            0xFD => self.exec_index(bus, Index::Iy),
+            _ => self.exec_main(bus, op, Index::Hl),
+        };
+        self.cycles += t as u64;
+        t
+    }
+
+    fn exec_index(&mut self, bus: &mut impl Bus, idx: Index) -> u32 {
+        let op = self.fetch(bus);
+        self.inc_r();
+        match op {
+            0xCB => {
+                let d = self.fetch(bus) as i8;
+                let addr = self.idx_reg(idx).wrapping_add(d as i16 as u16);
+                self.exec_cb(bus, idx, addr)
+            }
+            0xDD | 0xFD | 0xED => {
+                // Prefix acts as a NOP; re-execute the next prefix/op
+                self.pc = self.pc.wrapping_sub(1);
+                4
+            }
+            _ => 4 + self.exec_main(bus, op, idx),
+        }
+    }
+
+    /// Address for an (HL)/(IX+d) operand, fetching displacement if indexed
+    fn mem_operand(&mut self, bus: &mut impl Bus, idx: Index) -> u16 {
+        match idx {
+            Index::Hl => self.hl(),
+            _ => {
+                let d = self.fetch(bus) as i8;
+                self.idx_reg(idx).wrapping_add(d as i16 as u16)
+            }
+        }
+    }
+
+    fn exec_main(&mut self, bus: &mut impl Bus, op: u8, idx: Index) -> u32 {
+        let x = op >> 6;
+        let y = (op >> 3) & 7;
+        let z = op & 7;
+        let p = y >> 1;
+        let q = y & 1;
+        let ixd = idx != Index::Hl;
+        match x {
+            0 => match z {
+                0 => match y {
+                    0 => 4,
+                    1 => {
+                        let af = self.af();
+                        self.set_af(self.af_alt);
+                        self.af_alt = af;
+                        4
+                    }
+                    2 => {
+                        let d = self.fetch(bus) as i8;
+                        self.b = self.b.wrapping_sub(1);
+                        if self.b != 0 {
+                            self.pc = self.pc.wrapping_add(d as i16 as u16);
+                            13
+                        } else {
+                            8
+                        }
+                    }
+                    3 => {
+                        let d = self.fetch(bus) as i8;
+                        self.pc = self.pc.wrapping_add(d as i16 as u16);
+                        12
+                    }
+                    _ => {
+                        let d = self.fetch(bus) as i8;
+                        if self.cond(y - 4) {
+                            self.pc = self.pc.wrapping_add(d as i16 as u16);
+                            12
+                        } else {
+                            7
+                        }
+                    }
+                },
+                1 => {
+                    if q == 0 {
+                        let nn = self.fetch_word(bus);
+                        self.set_rp(p, idx, nn);
+                        10
+                    } else {
+                        let r = self.add16(self.idx_reg(idx), self.rp(p, idx));
+                        self.set_idx_reg(idx, r);
+                        11
+                    }
+                }
+                2 => match (p, q) {
+                    (0, 0) => {
+                        bus.write(self.bc(), self.a);
+                        7
+                    }
+                    (1, 0) => {
+                        bus.write(self.de(), self.a);
+                        7
+                    }
+                    (2, 0) => {
+                        let nn = self.fetch_word(bus);
+                        Self::write_word(bus, nn, self.idx_reg(idx));
+                        16
+                    }
+                    (3, 0) => {
+                        let nn = self.fetch_word(bus);
+                        bus.write(nn, self.a);
+                        13
+                    }
+                    (0, _) => {
+                        self.a = bus.read(self.bc());
+                        7
+                    }
+                    (1, _) => {
+                        self.a = bus.read(self.de());
+                        7
+                    }
+                    (2, _) => {
+                        let nn = self.fetch_word(bus);
+                        let v = Self::read_word(bus, nn);
+                        self.set_idx_reg(idx, v);
+                        16
+                    }
+                    _ => {
+                        let nn = self.fetch_word(bus);
+                        self.a = bus.read(nn);
+                        13
+                    }
+                },
+                3 => {
+                    let v = self.rp(p, idx);
+                    let v = if q == 0 { v.wrapping_add(1) } else { v.wrapping_sub(1) };
+                    self.set_rp(p, idx, v);
+                    6
+                }
+                4 | 5 => {
+                    if y == 6 {
+                        let addr = self.mem_operand(bus, idx);
+                        let v = bus.read(addr);
+                        let r = if z == 4 { self.inc8(v) } else { self.dec8(v) };
+                        bus.write(addr, r);
+                        if ixd { 19 } else { 11 }
+                    } else {
+                        let v = self.reg8(y, idx);
+                        let r = if z == 4 { self.inc8(v) } else { self.dec8(v) };
+                        self.set_reg8(y, idx, r);
+                        4
+                    }
+                }
+                6 => {
+                    if y == 6 {
+                        let addr = self.mem_operand(bus, idx);
+                        let n = self.fetch(bus);
+                        bus.write(addr, n);
+                        if ixd { 15 } else { 10 }
+                    } else {
+                        let n = self.fetch(bus);
+                        self.set_reg8(y, idx, n);
+                        7
+                    }
+                }
+                _ => {
+                    match y {
+                        0 => {
+                            let c = self.a >> 7;
+                            self.a = self.a.rotate_left(1);
+                            self.f = (self.f & (FLAG_S | FLAG_Z | FLAG_PV))
+                                | (self.a & (FLAG_X | FLAG_Y))
+                                | c;
+                        }
+                        1 => {
+                            let c = self.a & 1;
+                            self.a = self.a.rotate_right(1);
+                            self.f = (self.f & (FLAG_S | FLAG_Z | FLAG_PV))
+                                | (self.a & (FLAG_X | FLAG_Y))
+                                | c;
+                        }
+                        2 => {
+                            let c = self.a >> 7;
+                            self.a = (self.a << 1) | (self.f & FLAG_C);
+                            self.f = (self.f & (FLAG_S | FLAG_Z | FLAG_PV))
+                                | (self.a & (FLAG_X | FLAG_Y))
+                                | c;
+                        }
+                        3 => {
+                            let c = self.a & 1;
+                            self.a = (self.a >> 1) | ((self.f & FLAG_C) << 7);
+                            self.f = (self.f & (FLAG_S | FLAG_Z | FLAG_PV))
+                                | (self.a & (FLAG_X | FLAG_Y))
+                                | c;
+                        }
+                        4 => self.daa(),
+                        5 => {
+                            self.a = !self.a;
+                            self.f = (self.f & (FLAG_S | FLAG_Z | FLAG_PV | FLAG_C))
+                                | FLAG_H
+                                | FLAG_N
+                                | (self.a & (FLAG_X | FLAG_Y));
+                        }
+                        6 => {
+                            self.f = (self.f & (FLAG_S | FLAG_Z | FLAG_PV))
+                                | (self.a & (FLAG_X | FLAG_Y))
+                                | FLAG_C;
+                        }
+                        _ => {
+                            let c = self.f & FLAG_C;
+                            self.f = (self.f & (FLAG_S | FLAG_Z | FLAG_PV))
+                                | (self.a & (FLAG_X | FLAG_Y))
+                                | if c != 0 { FLAG_H } else { FLAG_C };
+                        }
+                    }
+                    4
+                }
+            },
+            1 => {
+                if y == 6 && z == 6 {
+                    self.halted = true;
+                    4
+                } else if y == 6 {
+                    let addr = self.mem_operand(bus, idx);
+                    bus.write(addr, self.reg8(z, Index::Hl));
+                    if ixd { 15 } else { 7 }
+                } else if z == 6 {
+                    let addr = self.mem_operand(bus, idx);
+                    let v = bus.read(addr);
+                    self.set_reg8(y, Index::Hl, v);
+                    if ixd { 15 } else { 7 }
+                } else {
+                    let v = self.reg8(z, idx);
+                    self.set_reg8(y, idx, v);
+                    4
+                }
+            }
+            2 => {
+                if z == 6 {
+                    let addr = self.mem_operand(bus, idx);
+                    let v = bus.read(addr);
+                    self.alu(y, v);
+                    if ixd { 15 } else { 7 }
+                } else {
+                    let v = self.reg8(z, idx);
+                    self.alu(y, v);
+                    4
+                }
+            }
+            _ => match z {
+                0 => {
+                    if self.cond(y) {
+                        self.pc = self.pop(bus);
+                        11
+                    } else {
+                        5
+                    }
+                }
+                1 => {
+                    if q == 0 {
+                        let v = self.pop(bus);
+                        self.set_rp2(p, idx, v);
+                        10
+                    } else {
+                        match p {
+                            0 => {
+                                self.pc = self.pop(bus);
+                                10
+                            }
+                            1 => {
+                                let (bc, de, hl) = (self.bc(), self.de(), self.hl());
+                                self.set_bc(self.bc_alt);
+                                self.set_de(self.de_alt);
+                                self.set_hl(self.hl_alt);
+                                self.bc_alt = bc;
+                                self.de_alt = de;
+                                self.hl_alt = hl;
+                                4
+                            }
+                            2 => {
+                                self.pc = self.idx_reg(idx);
+                                4
+                            }
+                            _ => {
+                                self.sp = self.idx_reg(idx);
+                                6
+                            }
+                        }
+                    }
+                }
+                2 => {
+                    let nn = self.fetch_word(bus);
+                    if self.cond(y) {
+                        self.pc = nn;
+                    }
+                    10
+                }
+                3 => match y {
+                    0 => {
+                        self.pc = self.fetch_word(bus);
+                        10
+                    }
+                    1 => unreachable!("CB prefix"),
+                    2 => {
+                        let n = self.fetch(bus);
+                        let port = u16::from_be_bytes([self.a, n]);
+                        bus.output(port, self.a);
+                        11
+                    }
+                    3 => {
+                        let n = self.fetch(bus);
+                        let port = u16::from_be_bytes([self.a, n]);
+                        self.a = bus.input(port);
+                        11
+                    }
+                    4 => {
+                        let v = Self::read_word(bus, self.sp);
+                        Self::write_word(bus, self.sp, self.idx_reg(idx));
+                        self.set_idx_reg(idx, v);
+                        19
+                    }
+                    5 => {
+                        // EX DE,HL is never affected by index prefixes
+                        let de = self.de();
+                        self.set_de(self.hl());
+                        self.set_hl(de);
+                        4
+                    }
+                    6 => {
+                        self.iff1 = false;
+                        self.iff2 = false;
+                        4
+                    }
+                    _ => {
+                        self.iff1 = true;
+                        self.iff2 = true;
+                        self.ei_pending = true;
+                        4
+                    }
+                },
+                4 => {
+                    let nn = self.fetch_word(bus);
+                    if self.cond(y) {
+                        self.push(bus, self.pc);
+                        self.pc = nn;
+                        17
+                    } else {
+                        10
+                    }
+                }
+                5 => {
+                    if q == 0 {
+                        let v = self.rp2(p, idx);
+                        self.push(bus, v);
+                        11
+                    } else if p == 0 {
+                        let nn = self.fetch_word(bus);
+                        self.push(bus, self.pc);
+                        self.pc = nn;
+                        17
+                    } else {
+                        unreachable!("prefix")
+                    }
+                }
+                6 => {
+                    let n = self.fetch(bus);
+                    self.alu(y, n);
+                    7
+                }
+                _ => {
+                    self.push(bus, self.pc);
+                    self.pc = (y * 8) as u16;
+                    11
+                }
+            },
+        }
+    }
+
+    fn exec_cb(&mut self, bus: &mut impl Bus, idx: Index, addr: u16) -> u32 {
+        let op = self.fetch(bus);
+        if idx == Index::Hl {
+            self.inc_r();
+        }
+        let x = op >> 6;
+        let y = (op >> 3) & 7;
+        let z = op & 7;
+        let indexed = idx != Index::Hl;
+        let mem = indexed || z == 6;
+        let addr = if indexed { addr } else { self.hl() };
+        let v = if mem { bus.read(addr) } else { self.reg8(z, Index::Hl) };
+        match x {
+            1 => {
+                let set = v & (1 << y) != 0;
+                let mut f = (self.f & FLAG_C) | FLAG_H;
+                if !set {
+                    f |= FLAG_Z | FLAG_PV;
+                }
+                if set && y == 7 {
+                    f |= FLAG_S;
+                }
+                f |= v & (FLAG_X | FLAG_Y);
+                self.f = f;
+                if indexed {
+                    20
+                } else if mem {
+                    12
+                } else {
+                    8
+                }
+            }
+            _ => {
+                let r = match x {
+                    0 => self.rot(y, v),
+                    2 => v & !(1 << y),
+                    _ => v | (1 << y),
+                };
+                if mem {
+                    bus.write(addr, r);
+                }
+                if z != 6 {
+                    // DD CB forms also copy the result to the register
+                    self.set_reg8(z, Index::Hl, r);
+                }
+                if indexed {
+                    23
+                } else if mem {
+                    15
+                } else {
+                    8
+                }
+            }
+        }
+    }
+
+    fn block_ld(&mut self, bus: &mut impl Bus, inc: bool) {
+        let v = bus.read(self.hl());
+        bus.write(self.de(), v);
+        let delta: u16 = if inc { 1 } else { 0xFFFF };
+        self.set_hl(self.hl().wrapping_add(delta));
+        self.set_de(self.de().wrapping_add(delta));
+        self.set_bc(self.bc().wrapping_sub(1));
+        let n = v.wrapping_add(self.a);
+        let mut f = self.f & (FLAG_S | FLAG_Z | FLAG_C);
+        if n & 0x02 != 0 {
+            f |= FLAG_Y;
+        }
+        f |= n & FLAG_X;
+        if self.bc() != 0 {
+            f |= FLAG_PV;
+        }
+        self.f = f;
+    }
+
+    fn block_cp(&mut self, bus: &mut impl Bus, inc: bool) {
+        let v = bus.read(self.hl());
+        let res = self.a.wrapping_sub(v);
+        let delta: u16 = if inc { 1 } else { 0xFFFF };
+        self.set_hl(self.hl().wrapping_add(delta));
+        self.set_bc(self.bc().wrapping_sub(1));
+        let mut f = (self.f & FLAG_C) | FLAG_N | (res & FLAG_S);
+        if res == 0 {
+            f |= FLAG_Z;
+        }
+        if (self.a & 0x0F) < (v & 0x0F) {
+            f |= FLAG_H;
+        }
+        if self.bc() != 0 {
+            f |= FLAG_PV;
+        }
+        self.f = f;
+    }
+
+    fn block_in(&mut self, bus: &mut impl Bus, inc: bool) {
+        let v = bus.input(self.bc());
+        bus.write(self.hl(), v);
+        let delta: u16 = if inc { 1 } else { 0xFFFF };
+        self.set_hl(self.hl().wrapping_add(delta));
+        self.b = self.b.wrapping_sub(1);
+        let mut f = (self.f & FLAG_C) | FLAG_N | (self.b & FLAG_S);
+        if self.b == 0 {
+            f |= FLAG_Z;
+        }
+        self.f = f;
+    }
+
+    fn block_out(&mut self, bus: &mut impl Bus, inc: bool) {
+        let v = bus.read(self.hl());
+        self.b = self.b.wrapping_sub(1);
+        bus.output(self.bc(), v);
+        let delta: u16 = if inc { 1 } else { 0xFFFF };
+        self.set_hl(self.hl().wrapping_add(delta));
+        let mut f = (self.f & FLAG_C) | FLAG_N | (self.b & FLAG_S);
+        if self.b == 0 {
+            f |= FLAG_Z;
+        }
+        self.f = f;
+    }
+
+    fn exec_ed(&mut self, bus: &mut impl Bus) -> u32 {
+        let op = self.fetch(bus);
+        self.inc_r();
+        let x = op >> 6;
+        let y = (op >> 3) & 7;
+        let z = op & 7;
+        let p = y >> 1;
+        let q = y & 1;
+        match x {
+            1 => match z {
+                0 => {
+                    let v = bus.input(self.bc());
+                    if y != 6 {
+                        self.set_reg8(y, Index::Hl, v);
+                    }
+                    self.f = (self.f & FLAG_C) | szp(v);
+                    12
+                }
+                1 => {
+                    let v = if y == 6 { 0 } else { self.reg8(y, Index::Hl) };
+                    bus.output(self.bc(), v);
+                    12
+                }
+                2 => {
+                    let hl = self.hl();
+                    let rr = self.rp(p, Index::Hl);
+                    let r = if q == 0 { self.sbc16(hl, rr) } else { self.adc16(hl, rr) };
+                    self.set_hl(r);
+                    15
+                }
+                3 => {
+                    let nn = self.fetch_word(bus);
+                    if q == 0 {
+                        Self::write_word(bus, nn, self.rp(p, Index::Hl));
+                    } else {
+                        let v = Self::read_word(bus, nn);
+                        self.set_rp(p, Index::Hl, v);
+                    }
+                    20
+                }
+                4 => {
+                    let a = self.a;
+                    self.a = 0;
+                    self.sub8(a, false, true);
+                    8
+                }
+                5 => {
+                    self.pc = self.pop(bus);
+                    self.iff1 = self.iff2;
+                    14
+                }
+                6 => {
+                    self.im = match y & 3 {
+                        0 | 1 => 0,
+                        2 => 1,
+                        _ => 2,
+                    };
+                    8
+                }
+                _ => match y {
+                    0 => {
+                        self.i = self.a;
+                        9
+                    }
+                    1 => {
+                        self.r = self.a;
+                        9
+                    }
+                    2 | 3 => {
+                        self.a = if y == 2 { self.i } else { self.r };
+                        let mut f = (self.f & FLAG_C) | (self.a & (FLAG_S | FLAG_X | FLAG_Y));
+                        if self.a == 0 {
+                            f |= FLAG_Z;
+                        }
+                        if self.iff2 {
+                            f |= FLAG_PV;
+                        }
+                        self.f = f;
+                        9
+                    }
+                    4 => {
+                        // RRD
+                        let m = bus.read(self.hl());
+                        let new_m = (self.a << 4) | (m >> 4);
+                        self.a = (self.a & 0xF0) | (m & 0x0F);
+                        bus.write(self.hl(), new_m);
+                        self.f = (self.f & FLAG_C) | szp(self.a);
+                        18
+                    }
+                    5 => {
+                        // RLD
+                        let m = bus.read(self.hl());
+                        let new_m = (m << 4) | (self.a & 0x0F);
+                        self.a = (self.a & 0xF0) | (m >> 4);
+                        bus.write(self.hl(), new_m);
+                        self.f = (self.f & FLAG_C) | szp(self.a);
+                        18
+                    }
+                    _ => 8,
+                },
+            },
+            2 if y >= 4 && z <= 3 => {
+                let inc = y & 1 == 0;
+                let repeat = y >= 6;
+                match z {
+                    0 => {
+                        self.block_ld(bus, inc);
+                        if repeat && self.bc() != 0 {
+                            self.pc = self.pc.wrapping_sub(2);
+                            return 21;
+                        }
+                    }
+                    1 => {
+                        self.block_cp(bus, inc);
+                        if repeat && self.bc() != 0 && !self.flag(FLAG_Z) {
+                            self.pc = self.pc.wrapping_sub(2);
+                            return 21;
+                        }
+                    }
+                    2 => {
+                        self.block_in(bus, inc);
+                        if repeat && self.b != 0 {
+                            self.pc = self.pc.wrapping_sub(2);
+                            return 21;
+                        }
+                    }
+                    _ => {
+                        self.block_out(bus, inc);
+                        if repeat && self.b != 0 {
+                            self.pc = self.pc.wrapping_sub(2);
+                            return 21;
+                        }
+                    }
+                }
+                16
+            }
+            _ => 8,
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Breakpoints and watchpoints
+//!
+//! `run_until_break` runs like `run`, but stops at a breakpoint (before
+//! the instruction there executes) or right after an instruction touches
+//! a watched memory address or port, and hands back a `Snapshot` of the
+//! CPU. Calling it again resumes from where it stopped.
+//!
+//! Memory watchpoints see every bus access, opcode fetches included.
+
+use super::{Cpu, Emulator};
+
+/// Which accesses a watchpoint stops on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Any,
+}
+
+impl Access {
+    pub(crate) fn matches(self, write: bool) -> bool {
+        match self {
+            Access::Read => !write,
+            Access::Write => write,
+            Access::Any => true,
+        }
+    }
+}
+
+/// Why `run_until_break` stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// PC reached a breakpoint
+    Breakpoint(u16),
+    /// A watched memory address was read or written
+    Memory { addr: u16, value: u8, write: bool },
+    /// A watched port was read or written
+    Port { port: u8, value: u8, write: bool },
+    /// The CPU executed HALT
+    Halted,
+    /// The step budget ran out
+    StepLimit,
+}
+
+/// CPU state when execution stopped
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub reason: StopReason,
+    pub cpu: Cpu,
+    /// Nearest ROM label of PC, as `label` or `label+N`
+    pub location: Option<String>,
+}
+
+impl Emulator {
+    /// Stop before executing the instruction at `addr`
+    pub fn break_at(&mut self, addr: u16) {
+        if !self.breakpoints.contains(&addr) {
+            self.breakpoints.push(addr);
+        }
+    }
+
+    /// Stop at a ROM label
+    ///
+    /// Panics if the label is undefined.
+    pub fn break_at_label(&mut self, label: &str) {
+        let addr = self.label(label).unwrap_or_else(|| panic!("Undefined label: {}", label));
+        self.break_at(addr);
+    }
+
+    /// Stop after an instruction accesses memory at `addr`
+    pub fn watch_memory(&mut self, addr: u16, access: Access) {
+        self.board.mem_watches.push((addr, access));
+    }
+
+    /// Stop after an instruction accesses any of the `size` bytes at a
+    /// label, such as a RAM variable
+    ///
+    /// Panics if the label is undefined.
+    pub fn watch_label(&mut self, label: &str, size: u16, access: Access) {
+        let addr = self.label(label).unwrap_or_else(|| panic!("Undefined label: {}", label));
+        for i in 0..size.max(1) {
+            self.watch_memory(addr.wrapping_add(i), access);
+        }
+    }
+
+    /// Stop after an instruction reads or writes `port`
+    pub fn watch_port(&mut self, port: u8, access: Access) {
+        self.board.port_watches.push((port, access));
+    }
+
+    /// Remove all breakpoints and watchpoints
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.board.mem_watches.clear();
+        self.board.port_watches.clear();
+    }
+
+    /// Run for at most `max_steps` instructions, stopping at breakpoints,
+    /// watchpoints and HALT
+    ///
+    /// The first instruction is never stopped at, so resuming from a
+    /// breakpoint moves on.
+    pub fn run_until_break(&mut self, max_steps: usize) -> Snapshot {
+        self.board.hit = None;
+        let mut reason = StopReason::StepLimit;
+        for n in 0..max_steps {
+            if self.cpu.halted {
+                reason = StopReason::Halted;
+                break;
+            }
+            if n > 0 && self.breakpoints.contains(&self.cpu.pc) {
+                reason = StopReason::Breakpoint(self.cpu.pc);
+                break;
+            }
+            self.step();
+            if let Some(hit) = self.board.hit.take() {
+                reason = hit;
+                break;
+            }
+        }
+        if reason == StopReason::StepLimit && self.cpu.halted {
+            reason = StopReason::Halted;
+        }
+        self.snapshot(reason)
+    }
+
+    /// The current CPU state
+    pub fn snapshot(&self, reason: StopReason) -> Snapshot {
+        Snapshot {
+            reason,
+            cpu: self.cpu.clone(),
+            location: self.symbolize(self.cpu.pc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CodeGen;
+
+    fn counter_rom() -> CodeGen {
+        let mut rom = CodeGen::new();
+        let count = rom.ram_var("count", 1);
+        rom.ld_b(3);
+        rom.label("loop");
+        rom.ld_a_addr(count);
+        rom.inc_a();
+        rom.ld_addr_a(count);
+        rom.out_a(0x40);
+        rom.djnz("loop");
+        rom.halt();
+        rom.resolve_fixups();
+        rom
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut emu = Emulator::new(&counter_rom());
+        emu.break_at_label("loop");
+        for b in [3, 2, 1] {
+            let snap = emu.run_until_break(100);
+            assert_eq!(snap.reason, StopReason::Breakpoint(0x0002));
+            assert_eq!(snap.location.as_deref(), Some("loop"));
+            assert_eq!(snap.cpu.b, b);
+        }
+        assert_eq!(emu.run_until_break(100).reason, StopReason::Halted);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut emu = Emulator::new(&counter_rom());
+        emu.watch_label("count", 1, Access::Write);
+        emu.watch_port(0x40, Access::Any);
+        let snap = emu.run_until_break(100);
+        assert_eq!(snap.reason, StopReason::Memory { addr: 0x2000, value: 1, write: true });
+        assert_eq!(snap.location.as_deref(), Some("loop+7"));  // After the write
+        let snap = emu.run_until_break(100);
+        assert_eq!(snap.reason, StopReason::Port { port: 0x40, value: 1, write: true });
+
+        emu.clear_breakpoints();
+        assert_eq!(emu.run_until_break(2).reason, StopReason::StepLimit);
+    }
+}
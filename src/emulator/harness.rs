@@ -0,0 +1,195 @@
+//! Scripted serial sessions for end-to-end ROM tests
+//!
+//! `Harness` drives an `Emulator` the way a person at the terminal would:
+//! wait for a prompt, type a line, wait for the reply. Each wait looks only
+//! at output that arrived after the previous match, so a script reads top
+//! to bottom like the session it checks.
+//!
+//! ```rust
+//! use retroshield_z80_workbench::emulator::harness::Harness;
+//! use retroshield_z80_workbench::roms::basic::{self, BasicConfig};
+//!
+//! let rom = basic::build(&BasicConfig::default());
+//! let mut term = Harness::new(&rom);
+//! term.run_until_output_contains("OK", 1_000_000).unwrap();
+//! term.send_line("PRINT 6*7");
+//! let reply = term.run_until_output_contains("OK", 1_000_000).unwrap();
+//! assert!(reply.contains("42"));
+//! ```
+
+use super::Emulator;
+use crate::CodeGen;
+use std::fmt;
+
+/// Why a wait in a `Harness` script failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HarnessError {
+    /// The cycle budget ran out; `output` is everything unmatched so far
+    Timeout { waiting_for: String, output: String },
+    /// The CPU halted first
+    Halted { waiting_for: String, output: String },
+}
+
+impl fmt::Display for HarnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HarnessError::Timeout { waiting_for, output } => {
+                write!(f, "timed out waiting for {:?}; output was {:?}", waiting_for, output)
+            }
+            HarnessError::Halted { waiting_for, output } => {
+                write!(f, "halted waiting for {:?}; output was {:?}", waiting_for, output)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HarnessError {}
+
+/// An emulator with a scripted terminal on its ACIA
+///
+/// Waits keep a cursor into the ACIA output, so leave clearing it
+/// (`Emulator::take_output`) out of harness scripts.
+pub struct Harness {
+    pub emu: Emulator,
+    /// Output already returned by a wait
+    seen: usize,
+    /// Line ending `send_line` appends
+    pub line_ending: String,
+}
+
+impl Harness {
+    /// Load `rom` and reset; lines end in CR
+    pub fn new(rom: &CodeGen) -> Self {
+        Self::from_emulator(Emulator::new(rom))
+    }
+
+    /// Script an emulator that is already set up
+    pub fn from_emulator(emu: Emulator) -> Self {
+        Self {
+            emu,
+            seen: 0,
+            line_ending: "\r".to_string(),
+        }
+    }
+
+    /// Queue bytes on the ACIA receiver
+    pub fn send(&mut self, bytes: &[u8]) {
+        self.emu.input(bytes);
+    }
+
+    /// Queue `line` followed by `line_ending`
+    pub fn send_line(&mut self, line: &str) {
+        let text = format!("{}{}", line, self.line_ending);
+        self.emu.input(text.as_bytes());
+    }
+
+    /// Everything the ROM has written
+    pub fn transcript(&self) -> String {
+        self.emu.output()
+    }
+
+    /// Output not yet returned by a wait
+    pub fn unread(&self) -> String {
+        String::from_utf8_lossy(&self.emu.output_bytes()[self.seen..]).into_owned()
+    }
+
+    /// Run until `text` appears in the unread output, for at most
+    /// `max_cycles` T-states
+    ///
+    /// Returns the unread output up to and including the match, and moves
+    /// past it.
+    pub fn run_until_output_contains(&mut self, text: &str, max_cycles: u64) -> Result<String, HarnessError> {
+        let limit = self.emu.cpu.cycles + max_cycles;
+        let needle = text.as_bytes();
+        let mut checked = usize::MAX;
+        loop {
+            let out = self.emu.output_bytes();
+            if out.len() != checked {
+                checked = out.len();
+                let found = if needle.is_empty() {
+                    Some(0)
+                } else {
+                    out[self.seen..].windows(needle.len()).position(|w| w == needle)
+                };
+                if let Some(pos) = found {
+                    let end = self.seen + pos + needle.len();
+                    let reply = String::from_utf8_lossy(&out[self.seen..end]).into_owned();
+                    self.seen = end;
+                    return Ok(reply);
+                }
+            }
+            if self.emu.cpu.halted {
+                return Err(HarnessError::Halted { waiting_for: text.to_string(), output: self.unread() });
+            }
+            if self.emu.cpu.cycles >= limit {
+                return Err(HarnessError::Timeout { waiting_for: text.to_string(), output: self.unread() });
+            }
+            self.emu.step();
+        }
+    }
+
+    /// `send_line`, then wait for `text`
+    pub fn command(&mut self, line: &str, text: &str, max_cycles: u64) -> Result<String, HarnessError> {
+        self.send_line(line);
+        self.run_until_output_contains(text, max_cycles)
+    }
+
+    /// Run for `cycles` T-states or until HALT; returns true if halted
+    pub fn run_cycles(&mut self, cycles: u64) -> bool {
+        let limit = self.emu.cpu.cycles + cycles;
+        while !self.emu.cpu.halted && self.emu.cpu.cycles < limit {
+            self.emu.step();
+        }
+        self.emu.cpu.halted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo_rom() -> CodeGen {
+        let mut rom = CodeGen::new();
+        rom.ld_sp(0x3FFF);
+        rom.ld_hl_label("banner");
+        rom.call("print_string");
+        rom.label("echo");
+        rom.call("getchar");
+        rom.cp(b'.');
+        rom.jp_z("stop");
+        rom.call("putchar");
+        rom.jp("echo");
+        rom.label("stop");
+        rom.halt();
+        rom.label("banner");
+        rom.emit_string("READY\r\n");
+        rom.emit_io_routines();
+        rom.resolve_fixups();
+        rom
+    }
+
+    #[test]
+    fn test_script() {
+        let mut term = Harness::new(&echo_rom());
+        assert_eq!(term.run_until_output_contains("READY", 10_000), Ok("READY".to_string()));
+        term.send(b"ab");
+        assert_eq!(term.run_until_output_contains("b", 10_000), Ok("\r\nab".to_string()));
+        assert_eq!(term.command("cd", "\r", 10_000), Ok("cd\r".to_string()));
+        assert_eq!(term.transcript(), "READY\r\nabcd\r");
+    }
+
+    #[test]
+    fn test_timeout_and_halt() {
+        let mut term = Harness::new(&echo_rom());
+        assert_eq!(
+            term.run_until_output_contains("nope", 5_000),
+            Err(HarnessError::Timeout { waiting_for: "nope".to_string(), output: "READY\r\n".to_string() })
+        );
+        term.send(b"x.");
+        assert_eq!(
+            term.run_until_output_contains("nope", 5_000),
+            Err(HarnessError::Halted { waiting_for: "nope".to_string(), output: "READY\r\nx".to_string() })
+        );
+        assert!(term.run_cycles(100));
+    }
+}
@@ -0,0 +1,562 @@
+//! In-process Z80 emulator for testing generated ROMs (feature `emulator`)
+//!
+//! Runs a `CodeGen` image on a Z80 core with the RetroShield memory map
+//! taken from its `RomConfig` (ROM from `org` up to `ram_start`, RAM
+//! everywhere else) and an MC6850 ACIA on its `serial` ports, so tests can
+//! check what a routine actually prints and computes rather than only
+//! which labels it defines.
+//!
+//! ```rust
+//! use retroshield_z80_workbench::prelude::*;
+//! use retroshield_z80_workbench::emulator::Emulator;
+//!
+//! let mut rom = CodeGen::new();
+//! rom.emit_putchar();
+//! rom.emit_conout();
+//! rom.emit_print_byte_dec();
+//! rom.resolve_fixups();
+//!
+//! let mut emu = Emulator::new(&rom);
+//! emu.cpu.a = 123;
+//! assert!(emu.call_label("print_byte_dec", 10_000));
+//! assert_eq!(emu.output(), "123");
+//! ```
+//!
+//! Other hardware is modelled by a `Device`, which sees every port access
+//! before the ACIA does. Ports nobody claims read as 0xFF, and writes to
+//! them are logged in `port_writes`.
+//!
+//! For debugging, `trace_to` logs every instruction before it runs - PC,
+//! the nearest ROM label, the disassembly and the registers - and
+//! `trace_ring` keeps only the last few lines, to see how a runaway loop
+//! got where it is.
+
+mod cpu;
+pub mod debug;
+pub mod harness;
+
+pub use debug::{Access, Snapshot, StopReason};
+pub use cpu::{Bus, Cpu, FLAG_C, FLAG_H, FLAG_N, FLAG_PV, FLAG_S, FLAG_X, FLAG_Y, FLAG_Z};
+
+use crate::opcodes::decode;
+use crate::stdlib::io::MC6850Config;
+use crate::CodeGen;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+
+/// A peripheral on the I/O bus
+pub trait Device {
+    /// Value read from `port`, or `None` if the device doesn't decode it
+    fn input(&mut self, port: u8) -> Option<u8>;
+    /// Handle a write to `port`; returns false if the device doesn't
+    /// decode it
+    fn output(&mut self, port: u8, value: u8) -> bool;
+}
+
+/// MC6850 ACIA with a receive queue and a transmit log
+///
+/// Transmit is always ready; receive is ready while `rx` is not empty.
+/// Writes to the control register are kept in `control`.
+#[derive(Clone, Debug)]
+pub struct Acia {
+    pub status_port: u8,
+    pub data_port: u8,
+    pub rx_ready_bit: u8,
+    pub tx_ready_bit: u8,
+    /// Bytes waiting to be read from the data port
+    pub rx: VecDeque<u8>,
+    /// Bytes written to the data port
+    pub tx: Vec<u8>,
+    /// Last value written to the control register
+    pub control: u8,
+}
+
+impl Acia {
+    /// ACIA on the ports and status bits of `config`
+    pub fn new(config: &MC6850Config) -> Self {
+        Self {
+            status_port: config.status_port,
+            data_port: config.data_port,
+            rx_ready_bit: config.rx_ready_bit,
+            tx_ready_bit: config.tx_ready_bit,
+            rx: VecDeque::new(),
+            tx: Vec::new(),
+            control: 0,
+        }
+    }
+}
+
+/// Memory, ACIA and devices: everything the CPU sees
+struct Board {
+    mem: Vec<u8>,
+    /// Read-only range, `rom.0..rom.1`
+    rom: (u16, u16),
+    acia: Acia,
+    device: Option<Box<dyn Device>>,
+    port_writes: Vec<(u8, u8)>,
+    mem_watches: Vec<(u16, Access)>,
+    port_watches: Vec<(u8, Access)>,
+    /// First watchpoint hit by the current instruction
+    hit: Option<StopReason>,
+}
+
+impl Board {
+    fn watch_mem(&mut self, addr: u16, value: u8, write: bool) {
+        if self.hit.is_none() && self.mem_watches.iter().any(|&(a, access)| a == addr && access.matches(write)) {
+            self.hit = Some(StopReason::Memory { addr, value, write });
+        }
+    }
+
+    fn watch_port(&mut self, port: u8, value: u8, write: bool) {
+        if self.hit.is_none() && self.port_watches.iter().any(|&(p, access)| p == port && access.matches(write)) {
+            self.hit = Some(StopReason::Port { port, value, write });
+        }
+    }
+
+    fn port_in(&mut self, port: u8) -> u8 {
+        if let Some(value) = self.device.as_mut().and_then(|d| d.input(port)) {
+            return value;
+        }
+        if port == self.acia.status_port {
+            let rx = if self.acia.rx.is_empty() { 0 } else { self.acia.rx_ready_bit };
+            rx | self.acia.tx_ready_bit
+        } else if port == self.acia.data_port {
+            self.acia.rx.pop_front().unwrap_or(0)
+        } else {
+            0xFF
+        }
+    }
+}
+
+impl Bus for Board {
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.mem[addr as usize];
+        self.watch_mem(addr, value, false);
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.watch_mem(addr, value, true);
+        if !(self.rom.0..self.rom.1).contains(&addr) {
+            self.mem[addr as usize] = value;
+        }
+    }
+
+    fn input(&mut self, port: u16) -> u8 {
+        let port = port as u8;
+        let value = self.port_in(port);
+        self.watch_port(port, value, false);
+        value
+    }
+
+    fn output(&mut self, port: u16, value: u8) {
+        let port = port as u8;
+        self.watch_port(port, value, true);
+        if self.device.as_mut().is_some_and(|d| d.output(port, value)) {
+            return;
+        }
+        if port == self.acia.status_port {
+            self.acia.control = value;
+        } else if port == self.acia.data_port {
+            self.acia.tx.push(value);
+        } else {
+            self.port_writes.push((port, value));
+        }
+    }
+}
+
+/// Where executed instructions are logged
+enum Trace {
+    Off,
+    /// The last `n` lines
+    Ring(VecDeque<String>, usize),
+    Writer(Box<dyn Write>),
+}
+
+/// A Z80 system running a generated ROM
+pub struct Emulator {
+    pub cpu: Cpu,
+    board: Board,
+    labels: HashMap<String, u16>,
+    /// ROM labels sorted by address, for trace lines
+    symbols: Vec<(u16, String)>,
+    stack_top: u16,
+    trace: Trace,
+    breakpoints: Vec<u16>,
+    /// Instruction addresses run since `record_executed`
+    executed: Option<HashSet<u16>>,
+}
+
+impl Emulator {
+    /// Load `rom` at its `org` and reset: PC = `org`, SP = `stack_top`
+    ///
+    /// `rom` should have had `resolve_fixups` called. The ACIA uses the
+    /// ports and status bits of `RomConfig::serial`.
+    pub fn new(rom: &CodeGen) -> Self {
+        let config = rom.config();
+        let mut mem = vec![0; 0x10000];
+        let org = config.org as usize;
+        let len = rom.size().min(0x10000 - org);
+        mem[org..org + len].copy_from_slice(&rom.rom()[..len]);
+        let rom_end = if config.org < config.ram_start { config.ram_start } else { config.org };
+        let code_end = config.org as usize + rom.size();
+        let mut symbols: Vec<(u16, String)> = rom
+            .labels()
+            .filter(|&(_, addr)| addr >= config.org && (addr as usize) < code_end)
+            .map(|(name, addr)| (addr, name.to_string()))
+            .collect();
+        symbols.sort();
+        let mut cpu = Cpu::new();
+        cpu.pc = config.org;
+        cpu.sp = config.stack_top;
+        Self {
+            cpu,
+            board: Board {
+                mem,
+                rom: (config.org, rom_end),
+                acia: Acia::new(&config.serial),
+                device: None,
+                port_writes: Vec::new(),
+                mem_watches: Vec::new(),
+                port_watches: Vec::new(),
+                hit: None,
+            },
+            labels: rom.labels().map(|(name, addr)| (name.to_string(), addr)).collect(),
+            symbols,
+            stack_top: config.stack_top,
+            trace: Trace::Off,
+            breakpoints: Vec::new(),
+            executed: None,
+        }
+    }
+
+    /// The console ACIA
+    pub fn acia(&mut self) -> &mut Acia {
+        &mut self.board.acia
+    }
+
+    /// Attach a device to the I/O bus, replacing any previous one
+    pub fn attach(&mut self, device: Box<dyn Device>) {
+        self.board.device = Some(device);
+    }
+
+    /// Queue bytes for the program to read from the ACIA
+    pub fn input(&mut self, bytes: &[u8]) {
+        self.board.acia.rx.extend(bytes);
+    }
+
+    /// Everything written to the ACIA so far
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.board.acia.tx).into_owned()
+    }
+
+    /// Raw bytes written to the ACIA so far
+    pub fn output_bytes(&self) -> &[u8] {
+        &self.board.acia.tx
+    }
+
+    /// Return and clear the ACIA output
+    pub fn take_output(&mut self) -> String {
+        let out = self.output();
+        self.board.acia.tx.clear();
+        out
+    }
+
+    /// Writes to ports no device or ACIA claimed, as (port, value)
+    pub fn port_writes(&self) -> &[(u8, u8)] {
+        &self.board.port_writes
+    }
+
+    /// Execute one instruction; returns its T-states
+    pub fn step(&mut self) -> u32 {
+        if !matches!(self.trace, Trace::Off) {
+            let line = self.trace_line();
+            match &mut self.trace {
+                Trace::Off => {}
+                Trace::Ring(lines, n) => {
+                    if lines.len() == *n {
+                        lines.pop_front();
+                    }
+                    lines.push_back(line);
+                }
+                Trace::Writer(out) => {
+                    let _ = writeln!(out, "{}", line);
+                }
+            }
+        }
+        if let Some(executed) = &mut self.executed {
+            executed.insert(self.cpu.pc);
+        }
+        self.cpu.step(&mut self.board)
+    }
+
+    /// Start collecting the address of every instruction `step` runs,
+    /// clearing any collected so far
+    pub fn record_executed(&mut self) {
+        self.executed = Some(HashSet::new());
+    }
+
+    /// Addresses collected since `record_executed`, in order, ready for
+    /// `CodeGen::coverage_report`
+    pub fn executed(&self) -> Vec<u16> {
+        let mut addrs: Vec<u16> = self.executed.iter().flatten().copied().collect();
+        addrs.sort_unstable();
+        addrs
+    }
+
+    /// Log every instruction to `out`, e.g. `Box::new(std::io::stderr())`
+    pub fn trace_to(&mut self, out: Box<dyn Write>) {
+        self.trace = Trace::Writer(out);
+    }
+
+    /// Keep the last `lines` trace lines, for `trace_lines`
+    pub fn trace_ring(&mut self, lines: usize) {
+        self.trace = Trace::Ring(VecDeque::with_capacity(lines), lines.max(1));
+    }
+
+    /// Stop tracing
+    pub fn trace_off(&mut self) {
+        self.trace = Trace::Off;
+    }
+
+    /// Lines held by `trace_ring`, oldest first
+    pub fn trace_lines(&self) -> Vec<String> {
+        match &self.trace {
+            Trace::Ring(lines, _) => lines.iter().cloned().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Nearest ROM label at or below `addr`, as `label` or `label+N`
+    pub fn symbolize(&self, addr: u16) -> Option<String> {
+        let i = self.symbols.partition_point(|(a, _)| *a <= addr);
+        // Of several labels on one address, the first by name
+        let (at, _) = self.symbols.get(i.checked_sub(1)?)?;
+        let first = self.symbols.partition_point(|(a, _)| a < at);
+        let (at, name) = &self.symbols[first];
+        Some(match addr - at {
+            0 => name.clone(),
+            off => format!("{}+{}", name, off),
+        })
+    }
+
+    /// The instruction at PC and the registers, as logged by tracing
+    ///
+    /// PC, nearest label, disassembly, register pairs and flags (S Z H
+    /// P/V N C), e.g. `0103 main+3  LD A,0x2A  AF=0044 ... SP=3FFF -Z-P--`
+    /// with wider columns.
+    pub fn trace_line(&self) -> String {
+        let pc = self.cpu.pc;
+        let bytes: Vec<u8> = (0..4).map(|i| self.peek(pc.wrapping_add(i))).collect();
+        let text = match decode(&bytes) {
+            Some(d) => d.text(pc),
+            None => format!("DB 0x{:02X}", bytes[0]),
+        };
+        let f = self.cpu.f;
+        let flags: String = [(FLAG_S, 'S'), (FLAG_Z, 'Z'), (FLAG_H, 'H'), (FLAG_PV, 'P'), (FLAG_N, 'N'), (FLAG_C, 'C')]
+            .iter()
+            .map(|&(bit, c)| if f & bit != 0 { c } else { '-' })
+            .collect();
+        format!(
+            "{:04X} {:<16} {:<16} AF={:04X} BC={:04X} DE={:04X} HL={:04X} IX={:04X} IY={:04X} SP={:04X} {}",
+            pc,
+            self.symbolize(pc).unwrap_or_default(),
+            text,
+            self.cpu.af(),
+            self.cpu.bc(),
+            self.cpu.de(),
+            self.cpu.hl(),
+            self.cpu.ix,
+            self.cpu.iy,
+            self.cpu.sp,
+            flags
+        )
+    }
+
+    /// Run until HALT or for at most `max_steps` instructions; returns
+    /// true if the CPU halted
+    pub fn run(&mut self, max_steps: usize) -> bool {
+        for _ in 0..max_steps {
+            if self.cpu.halted {
+                return true;
+            }
+            self.step();
+        }
+        self.cpu.halted
+    }
+
+    /// Call the routine at `addr` with a fresh stack at `stack_top`, and
+    /// run until it returns; returns false if it halted or ran out of
+    /// steps first
+    ///
+    /// Registers are left as the routine returned them, so set inputs in
+    /// `cpu` beforehand and check results afterwards.
+    pub fn call(&mut self, addr: u16, max_steps: usize) -> bool {
+        const RETURN: u16 = 0xFFFF;
+        self.cpu.halted = false;
+        self.cpu.sp = self.stack_top.wrapping_sub(2);
+        let sp = self.stack_top;
+        self.poke_bytes(self.cpu.sp, &RETURN.to_le_bytes());
+        self.cpu.pc = addr;
+        for _ in 0..max_steps {
+            if self.cpu.pc == RETURN && self.cpu.sp == sp {
+                return true;
+            }
+            if self.cpu.halted {
+                return false;
+            }
+            self.step();
+        }
+        self.cpu.pc == RETURN && self.cpu.sp == sp
+    }
+
+    /// `call` the routine at a label of the ROM
+    ///
+    /// Panics if the label is undefined.
+    pub fn call_label(&mut self, label: &str, max_steps: usize) -> bool {
+        let addr = self.label(label).unwrap_or_else(|| panic!("Undefined label: {}", label));
+        self.call(addr, max_steps)
+    }
+
+    /// Address of a ROM or RAM label
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).copied()
+    }
+
+    /// Raise a maskable interrupt with `data` on the bus (the vector low
+    /// byte in IM 2, an RST opcode in IM 0); returns T-states taken, 0 if
+    /// interrupts are disabled
+    pub fn interrupt(&mut self, data: u8) -> u32 {
+        self.cpu.interrupt(&mut self.board, data)
+    }
+
+    /// Raise a non-maskable interrupt
+    pub fn nmi(&mut self) -> u32 {
+        self.cpu.nmi(&mut self.board)
+    }
+
+    /// Read a byte of memory
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.board.mem[addr as usize]
+    }
+
+    /// Read a little-endian word of memory
+    pub fn peek_word(&self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.peek(addr), self.peek(addr.wrapping_add(1))])
+    }
+
+    /// Write a byte of memory, ROM included
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.board.mem[addr as usize] = value;
+    }
+
+    /// Write bytes from `addr` on, ROM included
+    pub fn poke_bytes(&mut self, addr: u16, bytes: &[u8]) {
+        for (i, &b) in bytes.iter().enumerate() {
+            self.poke(addr.wrapping_add(i as u16), b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn console_rom() -> CodeGen {
+        let mut rom = CodeGen::new();
+        rom.ld_sp(0x3FFF);
+        rom.halt();
+        rom.emit_io_routines();
+        rom.emit_print_byte_dec();
+        rom.resolve_fixups();
+        rom
+    }
+
+    #[test]
+    fn test_print_byte_dec() {
+        let rom = console_rom();
+        let mut emu = Emulator::new(&rom);
+        for (a, text) in [(0, "0"), (7, "7"), (123, "123"), (255, "255")] {
+            emu.cpu.a = a;
+            assert!(emu.call_label("print_byte_dec", 10_000));
+            assert_eq!(emu.take_output(), text);
+        }
+    }
+
+    #[test]
+    fn test_getchar_echo() {
+        let rom = console_rom();
+        let mut emu = Emulator::new(&rom);
+        emu.input(b"x");
+        assert!(emu.call_label("getchar", 1_000));
+        assert_eq!(emu.cpu.a, b'x');
+        assert!(!emu.call_label("getchar", 1_000));  // Nothing left to read
+    }
+
+    #[test]
+    fn test_serial_ports_from_config() {
+        let serial = MC6850Config { status_port: 0x10, data_port: 0x11, ..Default::default() };
+        let mut rom = CodeGen::with_config(crate::RomConfig { serial, ..Default::default() });
+        rom.emit_io_routines();
+        rom.resolve_fixups();
+        let mut emu = Emulator::new(&rom);
+        emu.input(b"k");
+        assert!(emu.call_label("getchar", 1_000));
+        assert!(emu.call_label("putchar", 1_000));
+        assert_eq!(emu.output(), "k");
+    }
+
+    #[test]
+    fn test_run_and_rom_protect() {
+        let mut rom = CodeGen::new();
+        rom.ld_a(0x42);
+        rom.ld_addr_a(0x0000);        // ROM: ignored
+        rom.ld_addr_a(0x2000);
+        rom.out_a(0x10);
+        rom.halt();
+        rom.resolve_fixups();
+        let mut emu = Emulator::new(&rom);
+        assert!(emu.run(100));
+        assert_eq!(emu.peek(0x0000), 0x3E);
+        assert_eq!(emu.peek(0x2000), 0x42);
+        assert_eq!(emu.port_writes(), &[(0x10, 0x42)]);
+        assert_eq!(emu.cpu.cycles, 7 + 13 + 13 + 11 + 4);
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut rom = CodeGen::new();
+        rom.label("main");
+        rom.ld_b(3);
+        rom.label("spin");
+        rom.djnz("spin");
+        rom.halt();
+        rom.resolve_fixups();
+        let mut emu = Emulator::new(&rom);
+        emu.trace_ring(2);
+        assert!(emu.run(100));
+        assert_eq!(emu.symbolize(0x0003), Some("spin+1".to_string()));
+        assert_eq!(emu.trace_lines(), vec![
+            "0002 spin             DJNZ 0x0002      AF=0000 BC=0100 DE=0000 HL=0000 IX=0000 IY=0000 SP=3FFF ------",
+            "0004 spin+2           HALT             AF=0000 BC=0000 DE=0000 HL=0000 IX=0000 IY=0000 SP=3FFF ------",
+        ]);
+    }
+
+    #[test]
+    fn test_im1_interrupt() {
+        let mut rom = CodeGen::new();
+        rom.im1();
+        rom.ei();
+        rom.label("spin");
+        rom.jr("spin");
+        rom.pad_to(0x38, 0);
+        rom.inc_a();
+        rom.halt();
+        rom.resolve_fixups();
+        let mut emu = Emulator::new(&rom);
+        emu.run(10);
+        assert_eq!(emu.interrupt(0xFF), 13);
+        assert!(emu.run(10));
+        assert_eq!(emu.cpu.a, 1);
+    }
+}
@@ -2,636 +2,1902 @@
 //!
 //! Provides ergonomic methods for emitting Z80 instructions.
 //! Instead of `emit(&[0x3E, 0x0A])` you can write `ld_a(0x0A)`.
+//!
+//! Every helper is declared once in the `instructions!` table below, which
+//! generates both the `CodeGen` method and its entry in [`OPCODES`] (used by
+//! the disassembler, the on-target assembler tables and cycle counting).
+
+/// Declare instruction helpers and their opcode table entries
+///
+/// The invocation starts with `static TABLE_NAME;`, naming the generated
+/// opcode table, or `static TABLE_NAME for CpuProfile::X;` for extended
+/// instruction sets whose helpers panic on other CPU profiles. Each entry
+/// is `name(arg: kind) => "SYNTAX", [bytes], T;` where kind is one of
+/// `imm8`, `imm16`, `label` (16-bit fixup), `rel` (relative jump through
+/// `emit_relative`; a label not defined yet becomes a fixup), `bit` (bit
+/// number merged into the last opcode byte) or `disp` (signed IX/IY
+/// displacement), and T is the
+/// T-state count (`taken/not-taken` for conditional branches,
+/// `repeating/last` for block instructions). The only two-operand form is
+/// `(d: disp, n: imm8)`.
+macro_rules! instructions {
+    (static $table:ident; $($entries:tt)*) => {
+        instructions!(@table $table, None; $($entries)*);
+    };
+    (static $table:ident for $cpu:expr; $($entries:tt)*) => {
+        instructions!(@table $table, Some($cpu); $($entries)*);
+    };
+
+    (@table $table:ident, $cpu:expr; $(
+        $(#[$doc:meta])*
+        $name:ident($($arg:ident: $kind:ident),*) => $syntax:literal, [$($byte:expr),+],
+            $cycles:literal $(/ $not_taken:literal)?;
+    )*) => {
+        impl $crate::CodeGen {
+            $(
+                $(#[$doc])*
+                pub fn $name(&mut self $(, $arg: instructions!(@type $kind))*) {
+                    let cpu: Option<$crate::CpuProfile> = $cpu;
+                    if let Some(cpu) = cpu {
+                        self.require_cpu(cpu, $syntax);
+                    }
+                    self.check_instruction($syntax);
+                    instructions!(@emit self, [$($byte),+] $(, $arg, $kind)*);
+                }
+            )*
+        }
+
+        /// Opcode table for every instruction helper, in declaration order
+        pub static $table: &[$crate::opcodes::Opcode] = &[
+            $(
+                $crate::opcodes::Opcode {
+                    helper: stringify!($name),
+                    syntax: $syntax,
+                    encoding: &[$($byte),+],
+                    operand: instructions!(@operand $($kind)*),
+                    cycles: $cycles,
+                    cycles_not_taken: instructions!(@not_taken $($not_taken)?),
+                    cpu: $cpu,
+                    emit: |cg, arg| instructions!(@dispatch cg, $name, arg; $($kind)*),
+                    sample: |cg| cg.$name($(instructions!(@sample $kind)),*),
+                },
+            )*
+        ];
+    };
+
+    (@type imm8) => { u8 };
+    (@type imm16) => { u16 };
+    (@type label) => { &str };
+    (@type rel) => { &str };
+    (@type bit) => { u8 };
+    (@type disp) => { i8 };
+
+    (@emit $cg:ident, [$($byte:expr),+]) => {
+        $cg.emit(&[$($byte),+])
+    };
+    (@emit $cg:ident, [$($byte:expr),+], $arg:ident, imm8) => {{
+        $cg.emit(&[$($byte),+]);
+        $cg.emit_byte($arg);
+    }};
+    (@emit $cg:ident, [$($byte:expr),+], $arg:ident, imm16) => {{
+        $cg.emit(&[$($byte),+]);
+        $cg.emit_word($arg);
+    }};
+    (@emit $cg:ident, [$($byte:expr),+], $arg:ident, label) => {{
+        $cg.emit(&[$($byte),+]);
+        $cg.fixup($arg);
+    }};
+    (@emit $cg:ident, [$($byte:expr),+], $arg:ident, rel) => {{
+        $cg.emit(&[$($byte),+]);
+        $cg.emit_relative($arg);
+    }};
+    (@emit $cg:ident, [$($byte:expr),+], $arg:ident, bit) => {{
+        let mut op = [$($byte),+];
+        let last = op.len() - 1;
+        op[last] |= ($arg & 7) << 3;
+        $cg.emit(&op);
+    }};
+    (@emit $cg:ident, [$($byte:expr),+], $arg:ident, disp) => {{
+        $cg.emit(&[$($byte),+]);
+        $cg.emit_byte($arg as u8);
+    }};
+    (@emit $cg:ident, [$($byte:expr),+], $d:ident, disp, $n:ident, imm8) => {{
+        $cg.emit(&[$($byte),+]);
+        $cg.emit_byte($d as u8);
+        $cg.emit_byte($n);
+    }};
+
+    (@operand) => { $crate::opcodes::Operand::None };
+    (@operand imm8) => { $crate::opcodes::Operand::Imm8 };
+    (@operand imm16) => { $crate::opcodes::Operand::Imm16 };
+    (@operand label) => { $crate::opcodes::Operand::Imm16 };
+    (@operand rel) => { $crate::opcodes::Operand::Rel8 };
+    (@operand bit) => { $crate::opcodes::Operand::Bit };
+    (@operand disp) => { $crate::opcodes::Operand::Disp };
+    (@operand disp imm8) => { $crate::opcodes::Operand::DispImm8 };
+
+    (@dispatch $cg:ident, $name:ident, $arg:ident;) => {
+        match $arg {
+            $crate::opcodes::Arg::None => $cg.$name(),
+            other => $crate::opcodes::wrong_arg(stringify!($name), other),
+        }
+    };
+    (@dispatch $cg:ident, $name:ident, $arg:ident; imm8) => {
+        match $arg {
+            $crate::opcodes::Arg::Imm8(n) => $cg.$name(n),
+            other => $crate::opcodes::wrong_arg(stringify!($name), other),
+        }
+    };
+    (@dispatch $cg:ident, $name:ident, $arg:ident; imm16) => {
+        match $arg {
+            $crate::opcodes::Arg::Imm16(nn) => $cg.$name(nn),
+            other => $crate::opcodes::wrong_arg(stringify!($name), other),
+        }
+    };
+    (@dispatch $cg:ident, $name:ident, $arg:ident; label) => {
+        instructions!(@dispatch $cg, $name, $arg; rel)
+    };
+    (@dispatch $cg:ident, $name:ident, $arg:ident; rel) => {
+        match $arg {
+            $crate::opcodes::Arg::Label(label) => $cg.$name(label),
+            other => $crate::opcodes::wrong_arg(stringify!($name), other),
+        }
+    };
+    (@dispatch $cg:ident, $name:ident, $arg:ident; bit) => {
+        match $arg {
+            $crate::opcodes::Arg::Bit(b) => $cg.$name(b),
+            other => $crate::opcodes::wrong_arg(stringify!($name), other),
+        }
+    };
+    (@dispatch $cg:ident, $name:ident, $arg:ident; disp) => {
+        match $arg {
+            $crate::opcodes::Arg::Disp(d) => $cg.$name(d),
+            other => $crate::opcodes::wrong_arg(stringify!($name), other),
+        }
+    };
+    (@dispatch $cg:ident, $name:ident, $arg:ident; disp imm8) => {
+        match $arg {
+            $crate::opcodes::Arg::DispImm8(d, n) => $cg.$name(d, n),
+            other => $crate::opcodes::wrong_arg(stringify!($name), other),
+        }
+    };
+
+    (@sample imm8) => { 0x12 };
+    (@sample imm16) => { 0x1234 };
+    (@sample label) => { "sample" };
+    (@sample rel) => { "sample" };
+    (@sample bit) => { 0 };
+    (@sample disp) => { 4 };
+
+    (@not_taken) => { None };
+    (@not_taken $t:literal) => { Some($t) };
+}
+#[allow(unused_imports)] // Only used by feature-gated instruction sets
+pub(crate) use instructions;
 
-use crate::CodeGen;
+instructions! {
+    static OPCODES;
 
-impl CodeGen {
     // ========== 8-bit Load Instructions ==========
 
     /// LD A, n
-    pub fn ld_a(&mut self, n: u8) {
-        self.emit(&[0x3E, n]);
-    }
+    ld_a(n: imm8) => "LD A,n", [0x3E], 7;
 
     /// LD B, n
-    pub fn ld_b(&mut self, n: u8) {
-        self.emit(&[0x06, n]);
-    }
+    ld_b(n: imm8) => "LD B,n", [0x06], 7;
 
     /// LD C, n
-    pub fn ld_c(&mut self, n: u8) {
-        self.emit(&[0x0E, n]);
-    }
+    ld_c(n: imm8) => "LD C,n", [0x0E], 7;
 
     /// LD D, n
-    pub fn ld_d(&mut self, n: u8) {
-        self.emit(&[0x16, n]);
-    }
+    ld_d(n: imm8) => "LD D,n", [0x16], 7;
 
     /// LD E, n
-    pub fn ld_e(&mut self, n: u8) {
-        self.emit(&[0x1E, n]);
-    }
+    ld_e(n: imm8) => "LD E,n", [0x1E], 7;
 
     /// LD H, n
-    pub fn ld_h(&mut self, n: u8) {
-        self.emit(&[0x26, n]);
-    }
+    ld_h(n: imm8) => "LD H,n", [0x26], 7;
 
     /// LD L, n
-    pub fn ld_l(&mut self, n: u8) {
-        self.emit(&[0x2E, n]);
-    }
+    ld_l(n: imm8) => "LD L,n", [0x2E], 7;
 
-    /// LD A, (HL)
-    pub fn ld_a_hl_ind(&mut self) {
-        self.emit(&[0x7E]);
-    }
+    // LD r, r' matrix (also available as `ld_r_r`)
+
+    /// LD B, B
+    ld_b_b() => "LD B,B", [0x40], 4;
+
+    /// LD B, C
+    ld_b_c() => "LD B,C", [0x41], 4;
+
+    /// LD B, D
+    ld_b_d() => "LD B,D", [0x42], 4;
+
+    /// LD B, E
+    ld_b_e() => "LD B,E", [0x43], 4;
+
+    /// LD B, H
+    ld_b_h() => "LD B,H", [0x44], 4;
+
+    /// LD B, L
+    ld_b_l() => "LD B,L", [0x45], 4;
+
+    /// LD B, (HL)
+    ld_b_hl_ind() => "LD B,(HL)", [0x46], 7;
+
+    /// LD B, A
+    ld_b_a() => "LD B,A", [0x47], 4;
+
+    /// LD C, B
+    ld_c_b() => "LD C,B", [0x48], 4;
+
+    /// LD C, C
+    ld_c_c() => "LD C,C", [0x49], 4;
+
+    /// LD C, D
+    ld_c_d() => "LD C,D", [0x4A], 4;
+
+    /// LD C, E
+    ld_c_e() => "LD C,E", [0x4B], 4;
+
+    /// LD C, H
+    ld_c_h() => "LD C,H", [0x4C], 4;
+
+    /// LD C, L
+    ld_c_l() => "LD C,L", [0x4D], 4;
+
+    /// LD C, (HL)
+    ld_c_hl_ind() => "LD C,(HL)", [0x4E], 7;
+
+    /// LD C, A
+    ld_c_a() => "LD C,A", [0x4F], 4;
+
+    /// LD D, B
+    ld_d_b() => "LD D,B", [0x50], 4;
+
+    /// LD D, C
+    ld_d_c() => "LD D,C", [0x51], 4;
+
+    /// LD D, D
+    ld_d_d() => "LD D,D", [0x52], 4;
+
+    /// LD D, E
+    ld_d_e() => "LD D,E", [0x53], 4;
+
+    /// LD D, H
+    ld_d_h() => "LD D,H", [0x54], 4;
+
+    /// LD D, L
+    ld_d_l() => "LD D,L", [0x55], 4;
+
+    /// LD D, (HL)
+    ld_d_hl_ind() => "LD D,(HL)", [0x56], 7;
+
+    /// LD D, A
+    ld_d_a() => "LD D,A", [0x57], 4;
+
+    /// LD E, B
+    ld_e_b() => "LD E,B", [0x58], 4;
+
+    /// LD E, C
+    ld_e_c() => "LD E,C", [0x59], 4;
+
+    /// LD E, D
+    ld_e_d() => "LD E,D", [0x5A], 4;
+
+    /// LD E, E
+    ld_e_e() => "LD E,E", [0x5B], 4;
+
+    /// LD E, H
+    ld_e_h() => "LD E,H", [0x5C], 4;
+
+    /// LD E, L
+    ld_e_l() => "LD E,L", [0x5D], 4;
+
+    /// LD E, (HL)
+    ld_e_hl_ind() => "LD E,(HL)", [0x5E], 7;
+
+    /// LD E, A
+    ld_e_a() => "LD E,A", [0x5F], 4;
+
+    /// LD H, B
+    ld_h_b() => "LD H,B", [0x60], 4;
+
+    /// LD H, C
+    ld_h_c() => "LD H,C", [0x61], 4;
+
+    /// LD H, D
+    ld_h_d() => "LD H,D", [0x62], 4;
+
+    /// LD H, E
+    ld_h_e() => "LD H,E", [0x63], 4;
+
+    /// LD H, H
+    ld_h_h() => "LD H,H", [0x64], 4;
+
+    /// LD H, L
+    ld_h_l() => "LD H,L", [0x65], 4;
+
+    /// LD H, (HL)
+    ld_h_hl_ind() => "LD H,(HL)", [0x66], 7;
+
+    /// LD H, A
+    ld_h_a() => "LD H,A", [0x67], 4;
+
+    /// LD L, B
+    ld_l_b() => "LD L,B", [0x68], 4;
+
+    /// LD L, C
+    ld_l_c() => "LD L,C", [0x69], 4;
+
+    /// LD L, D
+    ld_l_d() => "LD L,D", [0x6A], 4;
+
+    /// LD L, E
+    ld_l_e() => "LD L,E", [0x6B], 4;
+
+    /// LD L, H
+    ld_l_h() => "LD L,H", [0x6C], 4;
+
+    /// LD L, L
+    ld_l_l() => "LD L,L", [0x6D], 4;
+
+    /// LD L, (HL)
+    ld_l_hl_ind() => "LD L,(HL)", [0x6E], 7;
+
+    /// LD L, A
+    ld_l_a() => "LD L,A", [0x6F], 4;
+
+    /// LD (HL), B
+    ld_hl_ind_b() => "LD (HL),B", [0x70], 7;
+
+    /// LD (HL), C
+    ld_hl_ind_c() => "LD (HL),C", [0x71], 7;
+
+    /// LD (HL), D
+    ld_hl_ind_d() => "LD (HL),D", [0x72], 7;
+
+    /// LD (HL), E
+    ld_hl_ind_e() => "LD (HL),E", [0x73], 7;
+
+    /// LD (HL), H
+    ld_hl_ind_h() => "LD (HL),H", [0x74], 7;
+
+    /// LD (HL), L
+    ld_hl_ind_l() => "LD (HL),L", [0x75], 7;
 
     /// LD (HL), A
-    pub fn ld_hl_ind_a(&mut self) {
-        self.emit(&[0x77]);
-    }
+    ld_hl_ind_a() => "LD (HL),A", [0x77], 7;
 
     /// LD A, B
-    pub fn ld_a_b(&mut self) {
-        self.emit(&[0x78]);
-    }
+    ld_a_b() => "LD A,B", [0x78], 4;
 
     /// LD A, C
-    pub fn ld_a_c(&mut self) {
-        self.emit(&[0x79]);
-    }
+    ld_a_c() => "LD A,C", [0x79], 4;
 
     /// LD A, D
-    pub fn ld_a_d(&mut self) {
-        self.emit(&[0x7A]);
-    }
+    ld_a_d() => "LD A,D", [0x7A], 4;
 
     /// LD A, E
-    pub fn ld_a_e(&mut self) {
-        self.emit(&[0x7B]);
-    }
+    ld_a_e() => "LD A,E", [0x7B], 4;
 
-    /// LD B, A
-    pub fn ld_b_a(&mut self) {
-        self.emit(&[0x47]);
-    }
+    /// LD A, H
+    ld_a_h() => "LD A,H", [0x7C], 4;
 
-    /// LD C, A
-    pub fn ld_c_a(&mut self) {
-        self.emit(&[0x4F]);
-    }
+    /// LD A, L
+    ld_a_l() => "LD A,L", [0x7D], 4;
 
-    /// LD D, A
-    pub fn ld_d_a(&mut self) {
-        self.emit(&[0x57]);
-    }
+    /// LD A, (HL)
+    ld_a_hl_ind() => "LD A,(HL)", [0x7E], 7;
 
-    /// LD E, A
-    pub fn ld_e_a(&mut self) {
-        self.emit(&[0x5F]);
-    }
+    /// LD A, A
+    ld_a_a() => "LD A,A", [0x7F], 4;
 
     /// LD A, (nn)
-    pub fn ld_a_addr(&mut self, addr: u16) {
-        self.emit(&[0x3A]);
-        self.emit_word(addr);
-    }
+    ld_a_addr(addr: imm16) => "LD A,(nn)", [0x3A], 13;
 
     /// LD (nn), A
-    pub fn ld_addr_a(&mut self, addr: u16) {
-        self.emit(&[0x32]);
-        self.emit_word(addr);
-    }
+    ld_addr_a(addr: imm16) => "LD (nn),A", [0x32], 13;
+
+    /// LD A, (BC)
+    ld_a_bc_ind() => "LD A,(BC)", [0x0A], 7;
+
+    /// LD A, (DE)
+    ld_a_de_ind() => "LD A,(DE)", [0x1A], 7;
+
+    /// LD (BC), A
+    ld_bc_ind_a() => "LD (BC),A", [0x02], 7;
+
+    /// LD (DE), A
+    ld_de_ind_a() => "LD (DE),A", [0x12], 7;
+
+    /// LD (HL), n
+    ld_hl_ind_n(n: imm8) => "LD (HL),n", [0x36], 10;
 
     // ========== 16-bit Load Instructions ==========
 
     /// LD BC, nn
-    pub fn ld_bc(&mut self, nn: u16) {
-        self.emit(&[0x01]);
-        self.emit_word(nn);
-    }
+    ld_bc(nn: imm16) => "LD BC,nn", [0x01], 10;
 
     /// LD DE, nn
-    pub fn ld_de(&mut self, nn: u16) {
-        self.emit(&[0x11]);
-        self.emit_word(nn);
-    }
+    ld_de(nn: imm16) => "LD DE,nn", [0x11], 10;
 
     /// LD HL, nn
-    pub fn ld_hl(&mut self, nn: u16) {
-        self.emit(&[0x21]);
-        self.emit_word(nn);
-    }
+    ld_hl(nn: imm16) => "LD HL,nn", [0x21], 10;
 
     /// LD SP, nn
-    pub fn ld_sp(&mut self, nn: u16) {
-        self.emit(&[0x31]);
-        self.emit_word(nn);
-    }
+    ld_sp(nn: imm16) => "LD SP,nn", [0x31], 10;
+
+    /// LD HL, (nn)
+    ld_hl_addr(addr: imm16) => "LD HL,(nn)", [0x2A], 16;
+
+    /// LD (nn), HL
+    ld_addr_hl(addr: imm16) => "LD (nn),HL", [0x22], 16;
+
+    /// LD DE, (nn) - ED instruction
+    ld_de_addr(addr: imm16) => "LD DE,(nn)", [0xED, 0x5B], 20;
+
+    /// LD (nn), DE - ED instruction
+    ld_addr_de(addr: imm16) => "LD (nn),DE", [0xED, 0x53], 20;
+
+    /// LD BC, (nn) - ED instruction
+    ld_bc_addr(addr: imm16) => "LD BC,(nn)", [0xED, 0x4B], 20;
+
+    /// LD (nn), BC - ED instruction
+    ld_addr_bc(addr: imm16) => "LD (nn),BC", [0xED, 0x43], 20;
+
+    /// LD SP, (nn) - ED instruction
+    ld_sp_addr(addr: imm16) => "LD SP,(nn)", [0xED, 0x7B], 20;
+
+    /// LD (nn), SP - ED instruction
+    ld_addr_sp(addr: imm16) => "LD (nn),SP", [0xED, 0x73], 20;
+
+    /// LD SP, HL
+    ld_sp_hl() => "LD SP,HL", [0xF9], 6;
+
+    // ========== Stack Operations ==========
+
+    /// PUSH AF
+    push_af() => "PUSH AF", [0xF5], 11;
+
+    /// PUSH BC
+    push_bc() => "PUSH BC", [0xC5], 11;
+
+    /// PUSH DE
+    push_de() => "PUSH DE", [0xD5], 11;
+
+    /// PUSH HL
+    push_hl() => "PUSH HL", [0xE5], 11;
+
+    /// POP AF
+    pop_af() => "POP AF", [0xF1], 10;
+
+    /// POP BC
+    pop_bc() => "POP BC", [0xC1], 10;
+
+    /// POP DE
+    pop_de() => "POP DE", [0xD1], 10;
+
+    /// POP HL
+    pop_hl() => "POP HL", [0xE1], 10;
+
+    // ========== Exchange Instructions ==========
+
+    /// EX DE, HL
+    ex_de_hl() => "EX DE,HL", [0xEB], 4;
+
+    /// EX AF, AF'
+    ex_af() => "EX AF,AF'", [0x08], 4;
+
+    /// EXX
+    exx() => "EXX", [0xD9], 4;
+
+    /// EX (SP), HL
+    ex_sp_hl() => "EX (SP),HL", [0xE3], 19;
+
+    // ========== Arithmetic - 8 bit ==========
+
+    /// ADD A, n
+    add_a(n: imm8) => "ADD A,n", [0xC6], 7;
+
+    /// ADD A, B
+    add_a_b() => "ADD A,B", [0x80], 4;
+
+    /// ADD A, C
+    add_a_c() => "ADD A,C", [0x81], 4;
+
+    /// ADD A, D
+    add_a_d() => "ADD A,D", [0x82], 4;
+
+    /// ADD A, E
+    add_a_e() => "ADD A,E", [0x83], 4;
+
+    /// ADD A, H
+    add_a_h() => "ADD A,H", [0x84], 4;
+
+    /// ADD A, L
+    add_a_l() => "ADD A,L", [0x85], 4;
+
+    /// ADD A, (HL)
+    add_a_hl_ind() => "ADD A,(HL)", [0x86], 7;
+
+    /// ADD A, A
+    add_a_a() => "ADD A,A", [0x87], 4;
+
+    /// ADC A, n
+    adc_a(n: imm8) => "ADC A,n", [0xCE], 7;
+
+    /// ADC A, B
+    adc_a_b() => "ADC A,B", [0x88], 4;
+
+    /// ADC A, C
+    adc_a_c() => "ADC A,C", [0x89], 4;
+
+    /// ADC A, D
+    adc_a_d() => "ADC A,D", [0x8A], 4;
+
+    /// ADC A, E
+    adc_a_e() => "ADC A,E", [0x8B], 4;
+
+    /// ADC A, H
+    adc_a_h() => "ADC A,H", [0x8C], 4;
+
+    /// ADC A, L
+    adc_a_l() => "ADC A,L", [0x8D], 4;
+
+    /// ADC A, (HL)
+    adc_a_hl_ind() => "ADC A,(HL)", [0x8E], 7;
+
+    /// ADC A, A
+    adc_a_a() => "ADC A,A", [0x8F], 4;
+
+    /// SUB n
+    sub_a(n: imm8) => "SUB n", [0xD6], 7;
+
+    /// SUB B
+    sub_b() => "SUB B", [0x90], 4;
+
+    /// SUB C
+    sub_c() => "SUB C", [0x91], 4;
+
+    /// SUB D
+    sub_d() => "SUB D", [0x92], 4;
+
+    /// SUB E
+    sub_e() => "SUB E", [0x93], 4;
+
+    /// SUB H
+    sub_h() => "SUB H", [0x94], 4;
+
+    /// SUB L
+    sub_l() => "SUB L", [0x95], 4;
+
+    /// SUB (HL)
+    sub_hl_ind() => "SUB (HL)", [0x96], 7;
+
+    /// SUB A
+    sub_a_a() => "SUB A", [0x97], 4;
+
+    /// SBC A, n
+    sbc_a(n: imm8) => "SBC A,n", [0xDE], 7;
+
+    /// SBC A, B
+    sbc_a_b() => "SBC A,B", [0x98], 4;
+
+    /// SBC A, C
+    sbc_a_c() => "SBC A,C", [0x99], 4;
+
+    /// SBC A, D
+    sbc_a_d() => "SBC A,D", [0x9A], 4;
+
+    /// SBC A, E
+    sbc_a_e() => "SBC A,E", [0x9B], 4;
+
+    /// SBC A, H
+    sbc_a_h() => "SBC A,H", [0x9C], 4;
+
+    /// SBC A, L
+    sbc_a_l() => "SBC A,L", [0x9D], 4;
+
+    /// SBC A, (HL)
+    sbc_a_hl_ind() => "SBC A,(HL)", [0x9E], 7;
+
+    /// SBC A, A
+    sbc_a_a() => "SBC A,A", [0x9F], 4;
+
+    /// DAA (decimal adjust A after BCD add/subtract)
+    daa() => "DAA", [0x27], 4;
+
+    /// NEG (A = -A)
+    neg() => "NEG", [0xED, 0x44], 8;
+
+    /// INC A
+    inc_a() => "INC A", [0x3C], 4;
+
+    /// INC B
+    inc_b() => "INC B", [0x04], 4;
+
+    /// INC C
+    inc_c() => "INC C", [0x0C], 4;
+
+    /// DEC A
+    dec_a() => "DEC A", [0x3D], 4;
+
+    /// DEC B
+    dec_b() => "DEC B", [0x05], 4;
+
+    /// DEC C
+    dec_c() => "DEC C", [0x0D], 4;
+
+    /// INC D
+    inc_d() => "INC D", [0x14], 4;
+
+    /// INC E
+    inc_e() => "INC E", [0x1C], 4;
+
+    /// INC H
+    inc_h() => "INC H", [0x24], 4;
+
+    /// INC L
+    inc_l() => "INC L", [0x2C], 4;
+
+    /// INC (HL)
+    inc_hl_ind() => "INC (HL)", [0x34], 11;
+
+    /// DEC D
+    dec_d() => "DEC D", [0x15], 4;
+
+    /// DEC E
+    dec_e() => "DEC E", [0x1D], 4;
+
+    /// DEC H
+    dec_h() => "DEC H", [0x25], 4;
+
+    /// DEC L
+    dec_l() => "DEC L", [0x2D], 4;
+
+    /// DEC (HL)
+    dec_hl_ind() => "DEC (HL)", [0x35], 11;
+
+    // ========== Arithmetic - 16 bit ==========
+
+    /// INC HL
+    inc_hl() => "INC HL", [0x23], 6;
+
+    /// INC DE
+    inc_de() => "INC DE", [0x13], 6;
+
+    /// INC BC
+    inc_bc() => "INC BC", [0x03], 6;
+
+    /// DEC HL
+    dec_hl() => "DEC HL", [0x2B], 6;
+
+    /// DEC DE
+    dec_de() => "DEC DE", [0x1B], 6;
+
+    /// DEC BC
+    dec_bc() => "DEC BC", [0x0B], 6;
+
+    /// INC SP
+    inc_sp() => "INC SP", [0x33], 6;
+
+    /// DEC SP
+    dec_sp() => "DEC SP", [0x3B], 6;
+
+    /// ADD HL, BC
+    add_hl_bc() => "ADD HL,BC", [0x09], 11;
+
+    /// ADD HL, DE
+    add_hl_de() => "ADD HL,DE", [0x19], 11;
+
+    /// ADD HL, HL
+    add_hl_hl() => "ADD HL,HL", [0x29], 11;
+
+    /// ADD HL, SP
+    add_hl_sp() => "ADD HL,SP", [0x39], 11;
+
+    /// ADC HL, BC
+    adc_hl_bc() => "ADC HL,BC", [0xED, 0x4A], 15;
+
+    /// ADC HL, DE
+    adc_hl_de() => "ADC HL,DE", [0xED, 0x5A], 15;
+
+    /// ADC HL, HL
+    adc_hl_hl() => "ADC HL,HL", [0xED, 0x6A], 15;
+
+    /// ADC HL, SP
+    adc_hl_sp() => "ADC HL,SP", [0xED, 0x7A], 15;
+
+    /// SBC HL, DE
+    sbc_hl_de() => "SBC HL,DE", [0xED, 0x52], 15;
+
+    /// SBC HL, BC
+    sbc_hl_bc() => "SBC HL,BC", [0xED, 0x42], 15;
+
+    /// SBC HL, HL (HL = -carry)
+    sbc_hl_hl() => "SBC HL,HL", [0xED, 0x62], 15;
+
+    /// SBC HL, SP
+    sbc_hl_sp() => "SBC HL,SP", [0xED, 0x72], 15;
+
+    // ========== Logic ==========
+
+    /// AND n
+    and_a(n: imm8) => "AND n", [0xE6], 7;
+
+    /// OR n
+    or_a(n: imm8) => "OR n", [0xF6], 7;
+
+    /// OR A (common for flag check)
+    or_a_a() => "OR A", [0xB7], 4;
+
+    /// OR B
+    or_b() => "OR B", [0xB0], 4;
+
+    /// OR C
+    or_c() => "OR C", [0xB1], 4;
+
+    /// OR L
+    or_l() => "OR L", [0xB5], 4;
+
+    /// AND B
+    and_b() => "AND B", [0xA0], 4;
+
+    /// AND C
+    and_c() => "AND C", [0xA1], 4;
+
+    /// AND D
+    and_d() => "AND D", [0xA2], 4;
+
+    /// AND E
+    and_e() => "AND E", [0xA3], 4;
+
+    /// AND H
+    and_h() => "AND H", [0xA4], 4;
+
+    /// AND L
+    and_l() => "AND L", [0xA5], 4;
+
+    /// AND (HL)
+    and_hl_ind() => "AND (HL)", [0xA6], 7;
+
+    /// AND A
+    and_a_a() => "AND A", [0xA7], 4;
+
+    /// XOR B
+    xor_b() => "XOR B", [0xA8], 4;
+
+    /// XOR C
+    xor_c() => "XOR C", [0xA9], 4;
+
+    /// XOR D
+    xor_d() => "XOR D", [0xAA], 4;
+
+    /// XOR E
+    xor_e() => "XOR E", [0xAB], 4;
+
+    /// XOR H
+    xor_h() => "XOR H", [0xAC], 4;
+
+    /// XOR L
+    xor_l() => "XOR L", [0xAD], 4;
+
+    /// XOR (HL)
+    xor_hl_ind() => "XOR (HL)", [0xAE], 7;
+
+    /// OR D
+    or_d() => "OR D", [0xB2], 4;
+
+    /// OR E
+    or_e() => "OR E", [0xB3], 4;
+
+    /// OR H
+    or_h() => "OR H", [0xB4], 4;
+
+    /// OR (HL)
+    or_hl_ind() => "OR (HL)", [0xB6], 7;
+
+    /// XOR A
+    xor_a() => "XOR A", [0xAF], 4;
+
+    /// XOR n
+    xor_n(n: imm8) => "XOR n", [0xEE], 7;
+
+    /// CP n
+    cp(n: imm8) => "CP n", [0xFE], 7;
+
+    /// CP B
+    cp_b() => "CP B", [0xB8], 4;
+
+    /// CP C
+    cp_c() => "CP C", [0xB9], 4;
+
+    /// CP D
+    cp_d() => "CP D", [0xBA], 4;
+
+    /// CP E
+    cp_e() => "CP E", [0xBB], 4;
+
+    /// CP H
+    cp_h() => "CP H", [0xBC], 4;
+
+    /// CP L
+    cp_l() => "CP L", [0xBD], 4;
+
+    /// CP (HL)
+    cp_hl_ind() => "CP (HL)", [0xBE], 7;
+
+    /// CP A
+    cp_a() => "CP A", [0xBF], 4;
+
+    /// CPL (complement A)
+    cpl() => "CPL", [0x2F], 4;
+
+    // ========== Jumps ==========
+
+    /// JP nn (with fixup)
+    jp(label: label) => "JP nn", [0xC3], 10;
+
+    /// JP nn (absolute address)
+    jp_addr(addr: imm16) => "JP nn", [0xC3], 10;
+
+    /// JP Z, nn
+    jp_z(label: label) => "JP Z,nn", [0xCA], 10;
+
+    /// JP NZ, nn
+    jp_nz(label: label) => "JP NZ,nn", [0xC2], 10;
+
+    /// JP C, nn
+    jp_c(label: label) => "JP C,nn", [0xDA], 10;
+
+    /// JP NC, nn
+    jp_nc(label: label) => "JP NC,nn", [0xD2], 10;
+
+    /// JP P, nn (positive/sign flag clear)
+    jp_p(label: label) => "JP P,nn", [0xF2], 10;
+
+    /// JP M, nn (minus/sign flag set)
+    jp_m(label: label) => "JP M,nn", [0xFA], 10;
+
+    /// JP PO, nn (parity odd / no overflow)
+    jp_po(label: label) => "JP PO,nn", [0xE2], 10;
+
+    /// JP PE, nn (parity even / overflow)
+    jp_pe(label: label) => "JP PE,nn", [0xEA], 10;
+
+    /// JP (HL)
+    jp_hl() => "JP (HL)", [0xE9], 4;
+
+    /// JR e (relative jump)
+    jr(label: rel) => "JR e", [0x18], 12;
+
+    /// JR Z, e
+    jr_z(label: rel) => "JR Z,e", [0x28], 12/7;
+
+    /// JR NZ, e
+    jr_nz(label: rel) => "JR NZ,e", [0x20], 12/7;
+
+    /// JR C, e
+    jr_c(label: rel) => "JR C,e", [0x38], 12/7;
+
+    /// JR NC, e
+    jr_nc(label: rel) => "JR NC,e", [0x30], 12/7;
+
+    /// DJNZ e (decrement B, jump if not zero)
+    djnz(label: rel) => "DJNZ e", [0x10], 13/8;
+
+    // ========== Calls and Returns ==========
+
+    /// CALL nn (with fixup)
+    call(label: label) => "CALL nn", [0xCD], 17;
+
+    /// CALL nn (absolute address)
+    call_addr(addr: imm16) => "CALL nn", [0xCD], 17;
+
+    /// CALL Z, nn
+    call_z(label: label) => "CALL Z,nn", [0xCC], 17/10;
+
+    /// CALL NZ, nn
+    call_nz(label: label) => "CALL NZ,nn", [0xC4], 17/10;
+
+    /// CALL C, nn
+    call_c(label: label) => "CALL C,nn", [0xDC], 17/10;
+
+    /// CALL NC, nn
+    call_nc(label: label) => "CALL NC,nn", [0xD4], 17/10;
+
+    /// CALL PO, nn
+    call_po(label: label) => "CALL PO,nn", [0xE4], 17/10;
+
+    /// CALL PE, nn
+    call_pe(label: label) => "CALL PE,nn", [0xEC], 17/10;
+
+    /// CALL P, nn
+    call_p(label: label) => "CALL P,nn", [0xF4], 17/10;
+
+    /// CALL M, nn
+    call_m(label: label) => "CALL M,nn", [0xFC], 17/10;
+
+    /// RET
+    ret() => "RET", [0xC9], 10;
+
+    /// RET Z
+    ret_z() => "RET Z", [0xC8], 11/5;
+
+    /// RET NZ
+    ret_nz() => "RET NZ", [0xC0], 11/5;
+
+    /// RET C
+    ret_c() => "RET C", [0xD8], 11/5;
+
+    /// RET NC
+    ret_nc() => "RET NC", [0xD0], 11/5;
+
+    /// RET PO
+    ret_po() => "RET PO", [0xE0], 11/5;
+
+    /// RET PE
+    ret_pe() => "RET PE", [0xE8], 11/5;
+
+    /// RET P
+    ret_p() => "RET P", [0xF0], 11/5;
+
+    /// RET M
+    ret_m() => "RET M", [0xF8], 11/5;
+
+    /// RST 00H
+    rst_00() => "RST 00H", [0xC7], 11;
+
+    /// RST 08H
+    rst_08() => "RST 08H", [0xCF], 11;
+
+    /// RST 10H
+    rst_10() => "RST 10H", [0xD7], 11;
+
+    /// RST 18H
+    rst_18() => "RST 18H", [0xDF], 11;
+
+    /// RST 20H
+    rst_20() => "RST 20H", [0xE7], 11;
+
+    /// RST 28H
+    rst_28() => "RST 28H", [0xEF], 11;
+
+    /// RST 30H
+    rst_30() => "RST 30H", [0xF7], 11;
+
+    /// RST 38H
+    rst_38() => "RST 38H", [0xFF], 11;
+
+    // ========== I/O ==========
+
+    /// IN A, (n)
+    in_a(port: imm8) => "IN A,(n)", [0xDB], 11;
+
+    /// OUT (n), A
+    out_a(port: imm8) => "OUT (n),A", [0xD3], 11;
+
+    /// IN B, (C)
+    in_b_c() => "IN B,(C)", [0xED, 0x40], 12;
+
+    /// IN C, (C)
+    in_c_c() => "IN C,(C)", [0xED, 0x48], 12;
+
+    /// IN D, (C)
+    in_d_c() => "IN D,(C)", [0xED, 0x50], 12;
+
+    /// IN E, (C)
+    in_e_c() => "IN E,(C)", [0xED, 0x58], 12;
+
+    /// IN H, (C)
+    in_h_c() => "IN H,(C)", [0xED, 0x60], 12;
+
+    /// IN L, (C)
+    in_l_c() => "IN L,(C)", [0xED, 0x68], 12;
+
+    /// IN A, (C)
+    in_a_c() => "IN A,(C)", [0xED, 0x78], 12;
+
+    /// OUT (C), B
+    out_c_b() => "OUT (C),B", [0xED, 0x41], 12;
+
+    /// OUT (C), C
+    out_c_c() => "OUT (C),C", [0xED, 0x49], 12;
+
+    /// OUT (C), D
+    out_c_d() => "OUT (C),D", [0xED, 0x51], 12;
+
+    /// OUT (C), E
+    out_c_e() => "OUT (C),E", [0xED, 0x59], 12;
+
+    /// OUT (C), H
+    out_c_h() => "OUT (C),H", [0xED, 0x61], 12;
+
+    /// OUT (C), L
+    out_c_l() => "OUT (C),L", [0xED, 0x69], 12;
+
+    /// OUT (C), A
+    out_c_a() => "OUT (C),A", [0xED, 0x79], 12;
+
+    /// INI (IN (C) to (HL), HL++, B--)
+    ini() => "INI", [0xED, 0xA2], 16;
+
+    /// INIR (repeat INI until B = 0)
+    inir() => "INIR", [0xED, 0xB2], 21/16;
+
+    /// IND (IN (C) to (HL), HL--, B--)
+    ind() => "IND", [0xED, 0xAA], 16;
+
+    /// INDR (repeat IND until B = 0)
+    indr() => "INDR", [0xED, 0xBA], 21/16;
+
+    /// OUTI (B--, (HL) to OUT (C), HL++)
+    outi() => "OUTI", [0xED, 0xA3], 16;
+
+    /// OTIR (repeat OUTI until B = 0)
+    otir() => "OTIR", [0xED, 0xB3], 21/16;
+
+    /// OUTD (B--, (HL) to OUT (C), HL--)
+    outd() => "OUTD", [0xED, 0xAB], 16;
+
+    /// OTDR (repeat OUTD until B = 0)
+    otdr() => "OTDR", [0xED, 0xBB], 21/16;
+
+    // ========== Misc ==========
+
+    /// NOP
+    nop() => "NOP", [0x00], 4;
+
+    /// HALT
+    halt() => "HALT", [0x76], 4;
+
+    /// DI (disable interrupts)
+    di() => "DI", [0xF3], 4;
+
+    /// EI (enable interrupts)
+    ei() => "EI", [0xFB], 4;
+
+    /// SCF (set carry flag)
+    scf() => "SCF", [0x37], 4;
+
+    /// CCF (complement carry flag)
+    ccf() => "CCF", [0x3F], 4;
+
+    /// RLD (rotate the low nibble of A and the byte at (HL) left a digit)
+    rld() => "RLD", [0xED, 0x6F], 18;
+
+    /// RRD (rotate the low nibble of A and the byte at (HL) right a digit)
+    rrd() => "RRD", [0xED, 0x67], 18;
+
+    // ========== Interrupt Control ==========
+
+    /// IM 0 (execute the instruction the interrupting device supplies)
+    im0() => "IM 0", [0xED, 0x46], 8;
+
+    /// IM 1 (interrupts call 0x0038)
+    im1() => "IM 1", [0xED, 0x56], 8;
+
+    /// IM 2 (vectored through the table at I * 256)
+    im2() => "IM 2", [0xED, 0x5E], 8;
+
+    /// LD I, A
+    ld_i_a() => "LD I,A", [0xED, 0x47], 9;
+
+    /// LD A, I (P/V = IFF2, the interrupt enable state)
+    ld_a_i() => "LD A,I", [0xED, 0x57], 9;
+
+    /// LD R, A
+    ld_r_a() => "LD R,A", [0xED, 0x4F], 9;
+
+    /// LD A, R
+    ld_a_r() => "LD A,R", [0xED, 0x5F], 9;
+
+    /// RETI (return from maskable interrupt)
+    reti() => "RETI", [0xED, 0x4D], 14;
+
+    /// RETN (return from NMI, restoring IFF1)
+    retn() => "RETN", [0xED, 0x45], 14;
+
+    // ========== Block Transfer and Search ==========
+
+    /// LDI ((DE) = (HL), HL++, DE++, BC--)
+    ldi() => "LDI", [0xED, 0xA0], 16;
+
+    /// LDIR (repeat LDI until BC = 0)
+    ldir() => "LDIR", [0xED, 0xB0], 21/16;
+
+    /// LDD ((DE) = (HL), HL--, DE--, BC--)
+    ldd() => "LDD", [0xED, 0xA8], 16;
+
+    /// LDDR (repeat LDD until BC = 0)
+    lddr() => "LDDR", [0xED, 0xB8], 21/16;
+
+    /// CPI (CP (HL), HL++, BC--)
+    cpi() => "CPI", [0xED, 0xA1], 16;
+
+    /// CPIR (repeat CPI until match or BC = 0)
+    cpir() => "CPIR", [0xED, 0xB1], 21/16;
+
+    /// CPD (CP (HL), HL--, BC--)
+    cpd() => "CPD", [0xED, 0xA9], 16;
+
+    /// CPDR (repeat CPD until match or BC = 0)
+    cpdr() => "CPDR", [0xED, 0xB9], 21/16;
+
+    // ========== Bit Operations ==========
+
+    /// BIT b, A
+    bit_a(bit: bit) => "BIT b,A", [0xCB, 0x47], 8;
+
+    /// SET b, A
+    set_a(bit: bit) => "SET b,A", [0xCB, 0xC7], 8;
+
+    /// RES b, A
+    res_a(bit: bit) => "RES b,A", [0xCB, 0x87], 8;
+
+    /// BIT b, B
+    bit_b(bit: bit) => "BIT b,B", [0xCB, 0x40], 8;
+
+    /// BIT b, C
+    bit_c(bit: bit) => "BIT b,C", [0xCB, 0x41], 8;
+
+    /// BIT b, D
+    bit_d(bit: bit) => "BIT b,D", [0xCB, 0x42], 8;
+
+    /// BIT b, E
+    bit_e(bit: bit) => "BIT b,E", [0xCB, 0x43], 8;
+
+    /// BIT b, H
+    bit_h(bit: bit) => "BIT b,H", [0xCB, 0x44], 8;
+
+    /// BIT b, L
+    bit_l(bit: bit) => "BIT b,L", [0xCB, 0x45], 8;
+
+    /// BIT b, (HL)
+    bit_hl_ind(bit: bit) => "BIT b,(HL)", [0xCB, 0x46], 12;
+
+    /// SET b, B
+    set_b(bit: bit) => "SET b,B", [0xCB, 0xC0], 8;
+
+    /// SET b, C
+    set_c(bit: bit) => "SET b,C", [0xCB, 0xC1], 8;
+
+    /// SET b, D
+    set_d(bit: bit) => "SET b,D", [0xCB, 0xC2], 8;
+
+    /// SET b, E
+    set_e(bit: bit) => "SET b,E", [0xCB, 0xC3], 8;
+
+    /// SET b, H
+    set_h(bit: bit) => "SET b,H", [0xCB, 0xC4], 8;
+
+    /// SET b, L
+    set_l(bit: bit) => "SET b,L", [0xCB, 0xC5], 8;
+
+    /// SET b, (HL)
+    set_hl_ind(bit: bit) => "SET b,(HL)", [0xCB, 0xC6], 15;
+
+    /// RES b, B
+    res_b(bit: bit) => "RES b,B", [0xCB, 0x80], 8;
+
+    /// RES b, C
+    res_c(bit: bit) => "RES b,C", [0xCB, 0x81], 8;
+
+    /// RES b, D
+    res_d(bit: bit) => "RES b,D", [0xCB, 0x82], 8;
+
+    /// RES b, E
+    res_e(bit: bit) => "RES b,E", [0xCB, 0x83], 8;
+
+    /// RES b, H
+    res_h(bit: bit) => "RES b,H", [0xCB, 0x84], 8;
+
+    /// RES b, L
+    res_l(bit: bit) => "RES b,L", [0xCB, 0x85], 8;
+
+    /// RES b, (HL)
+    res_hl_ind(bit: bit) => "RES b,(HL)", [0xCB, 0x86], 15;
+
+    /// RLA (rotate left through carry)
+    rla() => "RLA", [0x17], 4;
+
+    /// RRA (rotate right through carry)
+    rra() => "RRA", [0x1F], 4;
+
+    /// RLCA (rotate left circular)
+    rlca() => "RLCA", [0x07], 4;
+
+    /// RRCA (rotate right circular)
+    rrca() => "RRCA", [0x0F], 4;
+
+    /// SLA A (shift left arithmetic)
+    sla_a() => "SLA A", [0xCB, 0x27], 8;
+
+    /// SRA A (shift right arithmetic)
+    sra_a() => "SRA A", [0xCB, 0x2F], 8;
+
+    /// SRL A (shift right logical)
+    srl_a() => "SRL A", [0xCB, 0x3F], 8;
+
+    /// RLC B (rotate left circular)
+    rlc_b() => "RLC B", [0xCB, 0x00], 8;
+
+    /// RLC C
+    rlc_c() => "RLC C", [0xCB, 0x01], 8;
+
+    /// RLC D
+    rlc_d() => "RLC D", [0xCB, 0x02], 8;
+
+    /// RLC E
+    rlc_e() => "RLC E", [0xCB, 0x03], 8;
+
+    /// RLC H
+    rlc_h() => "RLC H", [0xCB, 0x04], 8;
+
+    /// RLC L
+    rlc_l() => "RLC L", [0xCB, 0x05], 8;
+
+    /// RLC (HL)
+    rlc_hl_ind() => "RLC (HL)", [0xCB, 0x06], 15;
+
+    /// RLC A
+    rlc_a() => "RLC A", [0xCB, 0x07], 8;
+
+    /// RRC B (rotate right circular)
+    rrc_b() => "RRC B", [0xCB, 0x08], 8;
+
+    /// RRC C
+    rrc_c() => "RRC C", [0xCB, 0x09], 8;
+
+    /// RRC D
+    rrc_d() => "RRC D", [0xCB, 0x0A], 8;
+
+    /// RRC E
+    rrc_e() => "RRC E", [0xCB, 0x0B], 8;
+
+    /// RRC H
+    rrc_h() => "RRC H", [0xCB, 0x0C], 8;
+
+    /// RRC L
+    rrc_l() => "RRC L", [0xCB, 0x0D], 8;
+
+    /// RRC (HL)
+    rrc_hl_ind() => "RRC (HL)", [0xCB, 0x0E], 15;
+
+    /// RRC A
+    rrc_a() => "RRC A", [0xCB, 0x0F], 8;
+
+    /// RL B (rotate left through carry)
+    rl_b() => "RL B", [0xCB, 0x10], 8;
+
+    /// RL C
+    rl_c() => "RL C", [0xCB, 0x11], 8;
+
+    /// RL D
+    rl_d() => "RL D", [0xCB, 0x12], 8;
+
+    /// RL E
+    rl_e() => "RL E", [0xCB, 0x13], 8;
+
+    /// RL H
+    rl_h() => "RL H", [0xCB, 0x14], 8;
+
+    /// RL L
+    rl_l() => "RL L", [0xCB, 0x15], 8;
+
+    /// RL (HL)
+    rl_hl_ind() => "RL (HL)", [0xCB, 0x16], 15;
+
+    /// RL A
+    rl_a() => "RL A", [0xCB, 0x17], 8;
+
+    /// RR B (rotate right through carry)
+    rr_b() => "RR B", [0xCB, 0x18], 8;
+
+    /// RR C
+    rr_c() => "RR C", [0xCB, 0x19], 8;
+
+    /// RR D
+    rr_d() => "RR D", [0xCB, 0x1A], 8;
+
+    /// RR E
+    rr_e() => "RR E", [0xCB, 0x1B], 8;
+
+    /// RR H
+    rr_h() => "RR H", [0xCB, 0x1C], 8;
+
+    /// RR L
+    rr_l() => "RR L", [0xCB, 0x1D], 8;
+
+    /// RR (HL)
+    rr_hl_ind() => "RR (HL)", [0xCB, 0x1E], 15;
+
+    /// RR A
+    rr_a() => "RR A", [0xCB, 0x1F], 8;
+
+    /// SLA B
+    sla_b() => "SLA B", [0xCB, 0x20], 8;
+
+    /// SLA C
+    sla_c() => "SLA C", [0xCB, 0x21], 8;
+
+    /// SLA D
+    sla_d() => "SLA D", [0xCB, 0x22], 8;
+
+    /// SLA E
+    sla_e() => "SLA E", [0xCB, 0x23], 8;
+
+    /// SLA H
+    sla_h() => "SLA H", [0xCB, 0x24], 8;
+
+    /// SLA L
+    sla_l() => "SLA L", [0xCB, 0x25], 8;
+
+    /// SLA (HL)
+    sla_hl_ind() => "SLA (HL)", [0xCB, 0x26], 15;
+
+    /// SRA B
+    sra_b() => "SRA B", [0xCB, 0x28], 8;
+
+    /// SRA C
+    sra_c() => "SRA C", [0xCB, 0x29], 8;
+
+    /// SRA D
+    sra_d() => "SRA D", [0xCB, 0x2A], 8;
+
+    /// SRA E
+    sra_e() => "SRA E", [0xCB, 0x2B], 8;
+
+    /// SRA H
+    sra_h() => "SRA H", [0xCB, 0x2C], 8;
+
+    /// SRA L
+    sra_l() => "SRA L", [0xCB, 0x2D], 8;
+
+    /// SRA (HL)
+    sra_hl_ind() => "SRA (HL)", [0xCB, 0x2E], 15;
+
+    /// SRL B
+    srl_b() => "SRL B", [0xCB, 0x38], 8;
+
+    /// SRL C
+    srl_c() => "SRL C", [0xCB, 0x39], 8;
+
+    /// SRL D
+    srl_d() => "SRL D", [0xCB, 0x3A], 8;
+
+    /// SRL E
+    srl_e() => "SRL E", [0xCB, 0x3B], 8;
+
+    /// SRL H
+    srl_h() => "SRL H", [0xCB, 0x3C], 8;
+
+    /// SRL L
+    srl_l() => "SRL L", [0xCB, 0x3D], 8;
+
+    /// SRL (HL)
+    srl_hl_ind() => "SRL (HL)", [0xCB, 0x3E], 15;
+
+    // ========== IX Indexed ==========
+
+    /// LD A, (IX+d)
+    ld_a_ix_ind(d: disp) => "LD A,(IX+d)", [0xDD, 0x7E], 19;
+
+    /// LD B, (IX+d)
+    ld_b_ix_ind(d: disp) => "LD B,(IX+d)", [0xDD, 0x46], 19;
+
+    /// LD C, (IX+d)
+    ld_c_ix_ind(d: disp) => "LD C,(IX+d)", [0xDD, 0x4E], 19;
+
+    /// LD D, (IX+d)
+    ld_d_ix_ind(d: disp) => "LD D,(IX+d)", [0xDD, 0x56], 19;
+
+    /// LD E, (IX+d)
+    ld_e_ix_ind(d: disp) => "LD E,(IX+d)", [0xDD, 0x5E], 19;
+
+    /// LD H, (IX+d)
+    ld_h_ix_ind(d: disp) => "LD H,(IX+d)", [0xDD, 0x66], 19;
+
+    /// LD L, (IX+d)
+    ld_l_ix_ind(d: disp) => "LD L,(IX+d)", [0xDD, 0x6E], 19;
+
+    /// LD (IX+d), A
+    ld_ix_ind_a(d: disp) => "LD (IX+d),A", [0xDD, 0x77], 19;
+
+    /// LD (IX+d), B
+    ld_ix_ind_b(d: disp) => "LD (IX+d),B", [0xDD, 0x70], 19;
+
+    /// LD (IX+d), C
+    ld_ix_ind_c(d: disp) => "LD (IX+d),C", [0xDD, 0x71], 19;
+
+    /// LD (IX+d), D
+    ld_ix_ind_d(d: disp) => "LD (IX+d),D", [0xDD, 0x72], 19;
+
+    /// LD (IX+d), E
+    ld_ix_ind_e(d: disp) => "LD (IX+d),E", [0xDD, 0x73], 19;
+
+    /// LD (IX+d), H
+    ld_ix_ind_h(d: disp) => "LD (IX+d),H", [0xDD, 0x74], 19;
+
+    /// LD (IX+d), L
+    ld_ix_ind_l(d: disp) => "LD (IX+d),L", [0xDD, 0x75], 19;
+
+    /// LD (IX+d), n
+    ld_ix_ind_n(d: disp, n: imm8) => "LD (IX+d),n", [0xDD, 0x36], 19;
+
+    /// ADD A, (IX+d)
+    add_a_ix_ind(d: disp) => "ADD A,(IX+d)", [0xDD, 0x86], 19;
 
-    /// LD HL, (nn)
-    pub fn ld_hl_addr(&mut self, addr: u16) {
-        self.emit(&[0x2A]);
-        self.emit_word(addr);
-    }
+    /// ADC A, (IX+d)
+    adc_a_ix_ind(d: disp) => "ADC A,(IX+d)", [0xDD, 0x8E], 19;
 
-    /// LD (nn), HL
-    pub fn ld_addr_hl(&mut self, addr: u16) {
-        self.emit(&[0x22]);
-        self.emit_word(addr);
-    }
+    /// SUB (IX+d)
+    sub_ix_ind(d: disp) => "SUB (IX+d)", [0xDD, 0x96], 19;
 
-    /// LD DE, (nn) - ED instruction
-    pub fn ld_de_addr(&mut self, addr: u16) {
-        self.emit(&[0xED, 0x5B]);
-        self.emit_word(addr);
-    }
+    /// SBC A, (IX+d)
+    sbc_a_ix_ind(d: disp) => "SBC A,(IX+d)", [0xDD, 0x9E], 19;
 
-    /// LD (nn), DE - ED instruction
-    pub fn ld_addr_de(&mut self, addr: u16) {
-        self.emit(&[0xED, 0x53]);
-        self.emit_word(addr);
-    }
+    /// AND (IX+d)
+    and_ix_ind(d: disp) => "AND (IX+d)", [0xDD, 0xA6], 19;
 
-    /// LD SP, HL
-    pub fn ld_sp_hl(&mut self) {
-        self.emit(&[0xF9]);
-    }
+    /// XOR (IX+d)
+    xor_ix_ind(d: disp) => "XOR (IX+d)", [0xDD, 0xAE], 19;
 
-    // ========== Stack Operations ==========
+    /// OR (IX+d)
+    or_ix_ind(d: disp) => "OR (IX+d)", [0xDD, 0xB6], 19;
 
-    /// PUSH AF
-    pub fn push_af(&mut self) {
-        self.emit(&[0xF5]);
-    }
+    /// CP (IX+d)
+    cp_ix_ind(d: disp) => "CP (IX+d)", [0xDD, 0xBE], 19;
 
-    /// PUSH BC
-    pub fn push_bc(&mut self) {
-        self.emit(&[0xC5]);
-    }
+    /// INC (IX+d)
+    inc_ix_ind(d: disp) => "INC (IX+d)", [0xDD, 0x34], 23;
 
-    /// PUSH DE
-    pub fn push_de(&mut self) {
-        self.emit(&[0xD5]);
-    }
+    /// DEC (IX+d)
+    dec_ix_ind(d: disp) => "DEC (IX+d)", [0xDD, 0x35], 23;
 
-    /// PUSH HL
-    pub fn push_hl(&mut self) {
-        self.emit(&[0xE5]);
-    }
+    /// LD IX, nn
+    ld_ix(nn: imm16) => "LD IX,nn", [0xDD, 0x21], 14;
 
-    /// POP AF
-    pub fn pop_af(&mut self) {
-        self.emit(&[0xF1]);
-    }
+    /// LD IX, (nn)
+    ld_ix_addr(addr: imm16) => "LD IX,(nn)", [0xDD, 0x2A], 20;
 
-    /// POP BC
-    pub fn pop_bc(&mut self) {
-        self.emit(&[0xC1]);
-    }
+    /// LD (nn), IX
+    ld_addr_ix(addr: imm16) => "LD (nn),IX", [0xDD, 0x22], 20;
 
-    /// POP DE
-    pub fn pop_de(&mut self) {
-        self.emit(&[0xD1]);
-    }
+    /// LD SP, IX
+    ld_sp_ix() => "LD SP,IX", [0xDD, 0xF9], 10;
 
-    /// POP HL
-    pub fn pop_hl(&mut self) {
-        self.emit(&[0xE1]);
-    }
+    /// PUSH IX
+    push_ix() => "PUSH IX", [0xDD, 0xE5], 15;
 
-    // ========== Exchange Instructions ==========
+    /// POP IX
+    pop_ix() => "POP IX", [0xDD, 0xE1], 14;
 
-    /// EX DE, HL
-    pub fn ex_de_hl(&mut self) {
-        self.emit(&[0xEB]);
-    }
+    /// EX (SP), IX
+    ex_sp_ix() => "EX (SP),IX", [0xDD, 0xE3], 23;
 
-    /// EX AF, AF'
-    pub fn ex_af(&mut self) {
-        self.emit(&[0x08]);
-    }
+    /// INC IX
+    inc_ix() => "INC IX", [0xDD, 0x23], 10;
 
-    /// EXX
-    pub fn exx(&mut self) {
-        self.emit(&[0xD9]);
-    }
+    /// DEC IX
+    dec_ix() => "DEC IX", [0xDD, 0x2B], 10;
 
-    // ========== Arithmetic - 8 bit ==========
+    /// ADD IX, BC
+    add_ix_bc() => "ADD IX,BC", [0xDD, 0x09], 15;
 
-    /// ADD A, n
-    pub fn add_a(&mut self, n: u8) {
-        self.emit(&[0xC6, n]);
-    }
+    /// ADD IX, DE
+    add_ix_de() => "ADD IX,DE", [0xDD, 0x19], 15;
 
-    /// ADD A, B
-    pub fn add_a_b(&mut self) {
-        self.emit(&[0x80]);
-    }
+    /// ADD IX, IX
+    add_ix_ix() => "ADD IX,IX", [0xDD, 0x29], 15;
 
-    /// ADD A, (HL)
-    pub fn add_a_hl_ind(&mut self) {
-        self.emit(&[0x86]);
-    }
+    /// ADD IX, SP
+    add_ix_sp() => "ADD IX,SP", [0xDD, 0x39], 15;
 
-    /// SUB n
-    pub fn sub_a(&mut self, n: u8) {
-        self.emit(&[0xD6, n]);
-    }
+    /// JP (IX)
+    jp_ix() => "JP (IX)", [0xDD, 0xE9], 8;
 
-    /// SUB B
-    pub fn sub_b(&mut self) {
-        self.emit(&[0x90]);
-    }
+    // ========== IY Indexed ==========
 
-    /// INC A
-    pub fn inc_a(&mut self) {
-        self.emit(&[0x3C]);
-    }
+    /// LD A, (IY+d)
+    ld_a_iy_ind(d: disp) => "LD A,(IY+d)", [0xFD, 0x7E], 19;
 
-    /// INC B
-    pub fn inc_b(&mut self) {
-        self.emit(&[0x04]);
-    }
+    /// LD B, (IY+d)
+    ld_b_iy_ind(d: disp) => "LD B,(IY+d)", [0xFD, 0x46], 19;
 
-    /// INC C
-    pub fn inc_c(&mut self) {
-        self.emit(&[0x0C]);
-    }
+    /// LD C, (IY+d)
+    ld_c_iy_ind(d: disp) => "LD C,(IY+d)", [0xFD, 0x4E], 19;
 
-    /// DEC A
-    pub fn dec_a(&mut self) {
-        self.emit(&[0x3D]);
-    }
+    /// LD D, (IY+d)
+    ld_d_iy_ind(d: disp) => "LD D,(IY+d)", [0xFD, 0x56], 19;
 
-    /// DEC B
-    pub fn dec_b(&mut self) {
-        self.emit(&[0x05]);
-    }
+    /// LD E, (IY+d)
+    ld_e_iy_ind(d: disp) => "LD E,(IY+d)", [0xFD, 0x5E], 19;
 
-    /// DEC C
-    pub fn dec_c(&mut self) {
-        self.emit(&[0x0D]);
-    }
+    /// LD H, (IY+d)
+    ld_h_iy_ind(d: disp) => "LD H,(IY+d)", [0xFD, 0x66], 19;
 
-    // ========== Arithmetic - 16 bit ==========
+    /// LD L, (IY+d)
+    ld_l_iy_ind(d: disp) => "LD L,(IY+d)", [0xFD, 0x6E], 19;
 
-    /// INC HL
-    pub fn inc_hl(&mut self) {
-        self.emit(&[0x23]);
-    }
+    /// LD (IY+d), A
+    ld_iy_ind_a(d: disp) => "LD (IY+d),A", [0xFD, 0x77], 19;
 
-    /// INC DE
-    pub fn inc_de(&mut self) {
-        self.emit(&[0x13]);
-    }
+    /// LD (IY+d), B
+    ld_iy_ind_b(d: disp) => "LD (IY+d),B", [0xFD, 0x70], 19;
 
-    /// INC BC
-    pub fn inc_bc(&mut self) {
-        self.emit(&[0x03]);
-    }
+    /// LD (IY+d), C
+    ld_iy_ind_c(d: disp) => "LD (IY+d),C", [0xFD, 0x71], 19;
 
-    /// DEC HL
-    pub fn dec_hl(&mut self) {
-        self.emit(&[0x2B]);
-    }
+    /// LD (IY+d), D
+    ld_iy_ind_d(d: disp) => "LD (IY+d),D", [0xFD, 0x72], 19;
 
-    /// DEC DE
-    pub fn dec_de(&mut self) {
-        self.emit(&[0x1B]);
-    }
+    /// LD (IY+d), E
+    ld_iy_ind_e(d: disp) => "LD (IY+d),E", [0xFD, 0x73], 19;
 
-    /// DEC BC
-    pub fn dec_bc(&mut self) {
-        self.emit(&[0x0B]);
-    }
+    /// LD (IY+d), H
+    ld_iy_ind_h(d: disp) => "LD (IY+d),H", [0xFD, 0x74], 19;
 
-    /// ADD HL, BC
-    pub fn add_hl_bc(&mut self) {
-        self.emit(&[0x09]);
-    }
+    /// LD (IY+d), L
+    ld_iy_ind_l(d: disp) => "LD (IY+d),L", [0xFD, 0x75], 19;
 
-    /// ADD HL, DE
-    pub fn add_hl_de(&mut self) {
-        self.emit(&[0x19]);
-    }
+    /// LD (IY+d), n
+    ld_iy_ind_n(d: disp, n: imm8) => "LD (IY+d),n", [0xFD, 0x36], 19;
 
-    /// ADD HL, HL
-    pub fn add_hl_hl(&mut self) {
-        self.emit(&[0x29]);
-    }
+    /// ADD A, (IY+d)
+    add_a_iy_ind(d: disp) => "ADD A,(IY+d)", [0xFD, 0x86], 19;
 
-    /// SBC HL, DE
-    pub fn sbc_hl_de(&mut self) {
-        self.emit(&[0xED, 0x52]);
-    }
+    /// ADC A, (IY+d)
+    adc_a_iy_ind(d: disp) => "ADC A,(IY+d)", [0xFD, 0x8E], 19;
 
-    /// SBC HL, BC
-    pub fn sbc_hl_bc(&mut self) {
-        self.emit(&[0xED, 0x42]);
-    }
+    /// SUB (IY+d)
+    sub_iy_ind(d: disp) => "SUB (IY+d)", [0xFD, 0x96], 19;
 
-    // ========== Logic ==========
+    /// SBC A, (IY+d)
+    sbc_a_iy_ind(d: disp) => "SBC A,(IY+d)", [0xFD, 0x9E], 19;
 
-    /// AND n
-    pub fn and_a(&mut self, n: u8) {
-        self.emit(&[0xE6, n]);
-    }
+    /// AND (IY+d)
+    and_iy_ind(d: disp) => "AND (IY+d)", [0xFD, 0xA6], 19;
 
-    /// OR n
-    pub fn or_a(&mut self, n: u8) {
-        self.emit(&[0xF6, n]);
-    }
+    /// XOR (IY+d)
+    xor_iy_ind(d: disp) => "XOR (IY+d)", [0xFD, 0xAE], 19;
 
-    /// OR A (common for flag check)
-    pub fn or_a_a(&mut self) {
-        self.emit(&[0xB7]);
-    }
+    /// OR (IY+d)
+    or_iy_ind(d: disp) => "OR (IY+d)", [0xFD, 0xB6], 19;
 
-    /// OR B
-    pub fn or_b(&mut self) {
-        self.emit(&[0xB0]);
-    }
+    /// CP (IY+d)
+    cp_iy_ind(d: disp) => "CP (IY+d)", [0xFD, 0xBE], 19;
 
-    /// OR L
-    pub fn or_l(&mut self) {
-        self.emit(&[0xB5]);
-    }
+    /// INC (IY+d)
+    inc_iy_ind(d: disp) => "INC (IY+d)", [0xFD, 0x34], 23;
 
-    /// XOR A
-    pub fn xor_a(&mut self) {
-        self.emit(&[0xAF]);
-    }
+    /// DEC (IY+d)
+    dec_iy_ind(d: disp) => "DEC (IY+d)", [0xFD, 0x35], 23;
 
-    /// XOR n
-    pub fn xor_n(&mut self, n: u8) {
-        self.emit(&[0xEE, n]);
-    }
+    /// LD IY, nn
+    ld_iy(nn: imm16) => "LD IY,nn", [0xFD, 0x21], 14;
 
-    /// CP n
-    pub fn cp(&mut self, n: u8) {
-        self.emit(&[0xFE, n]);
-    }
+    /// LD IY, (nn)
+    ld_iy_addr(addr: imm16) => "LD IY,(nn)", [0xFD, 0x2A], 20;
 
-    /// CP B
-    pub fn cp_b(&mut self) {
-        self.emit(&[0xB8]);
-    }
+    /// LD (nn), IY
+    ld_addr_iy(addr: imm16) => "LD (nn),IY", [0xFD, 0x22], 20;
 
-    /// CP (HL)
-    pub fn cp_hl_ind(&mut self) {
-        self.emit(&[0xBE]);
-    }
+    /// LD SP, IY
+    ld_sp_iy() => "LD SP,IY", [0xFD, 0xF9], 10;
 
-    /// CPL (complement A)
-    pub fn cpl(&mut self) {
-        self.emit(&[0x2F]);
-    }
+    /// PUSH IY
+    push_iy() => "PUSH IY", [0xFD, 0xE5], 15;
 
-    // ========== Jumps ==========
+    /// POP IY
+    pop_iy() => "POP IY", [0xFD, 0xE1], 14;
 
-    /// JP nn (with fixup)
-    pub fn jp(&mut self, label: &str) {
-        self.emit(&[0xC3]);
-        self.fixup(label);
-    }
+    /// EX (SP), IY
+    ex_sp_iy() => "EX (SP),IY", [0xFD, 0xE3], 23;
 
-    /// JP nn (absolute address)
-    pub fn jp_addr(&mut self, addr: u16) {
-        self.emit(&[0xC3]);
-        self.emit_word(addr);
-    }
+    /// INC IY
+    inc_iy() => "INC IY", [0xFD, 0x23], 10;
 
-    /// JP Z, nn
-    pub fn jp_z(&mut self, label: &str) {
-        self.emit(&[0xCA]);
-        self.fixup(label);
-    }
+    /// DEC IY
+    dec_iy() => "DEC IY", [0xFD, 0x2B], 10;
 
-    /// JP NZ, nn
-    pub fn jp_nz(&mut self, label: &str) {
-        self.emit(&[0xC2]);
-        self.fixup(label);
-    }
+    /// ADD IY, BC
+    add_iy_bc() => "ADD IY,BC", [0xFD, 0x09], 15;
 
-    /// JP C, nn
-    pub fn jp_c(&mut self, label: &str) {
-        self.emit(&[0xDA]);
-        self.fixup(label);
-    }
+    /// ADD IY, DE
+    add_iy_de() => "ADD IY,DE", [0xFD, 0x19], 15;
 
-    /// JP NC, nn
-    pub fn jp_nc(&mut self, label: &str) {
-        self.emit(&[0xD2]);
-        self.fixup(label);
-    }
+    /// ADD IY, IY
+    add_iy_iy() => "ADD IY,IY", [0xFD, 0x29], 15;
 
-    /// JP P, nn (positive/sign flag clear)
-    pub fn jp_p(&mut self, label: &str) {
-        self.emit(&[0xF2]);
-        self.fixup(label);
-    }
+    /// ADD IY, SP
+    add_iy_sp() => "ADD IY,SP", [0xFD, 0x39], 15;
 
-    /// JP M, nn (minus/sign flag set)
-    pub fn jp_m(&mut self, label: &str) {
-        self.emit(&[0xFA]);
-        self.fixup(label);
-    }
+    /// JP (IY)
+    jp_iy() => "JP (IY)", [0xFD, 0xE9], 8;
+}
 
-    /// JP (HL)
-    pub fn jp_hl(&mut self) {
-        self.emit(&[0xE9]);
-    }
+use crate::opcodes::{self, Arg};
+
+/// 8-bit register operand, in Z80 encoding order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reg8 {
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    /// Memory at (HL)
+    HlInd,
+    A,
+}
 
-    /// JR e (relative jump, label must be defined)
-    pub fn jr(&mut self, label: &str) {
-        self.emit(&[0x18]);
-        self.emit_relative(label);
+impl Reg8 {
+    /// 3-bit register field used in opcodes
+    pub fn code(self) -> u8 {
+        self as u8
     }
 
-    /// JR Z, e
-    pub fn jr_z(&mut self, label: &str) {
-        self.emit(&[0x28]);
-        self.emit_relative(label);
+    /// Name in assembly syntax
+    fn syntax(self) -> &'static str {
+        ["B", "C", "D", "E", "H", "L", "(HL)", "A"][self as usize]
     }
+}
 
-    /// JR NZ, e
-    pub fn jr_nz(&mut self, label: &str) {
-        self.emit(&[0x20]);
-        self.emit_relative(label);
-    }
+/// 16-bit register pair operand
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reg16 {
+    BC,
+    DE,
+    HL,
+    SP,
+    /// Only valid for PUSH and POP
+    AF,
+    IX,
+    IY,
+}
 
-    /// JR C, e
-    pub fn jr_c(&mut self, label: &str) {
-        self.emit(&[0x38]);
-        self.emit_relative(label);
+impl Reg16 {
+    /// 2-bit register pair field used in opcodes (IX/IY take the HL slot)
+    pub fn code(self) -> u8 {
+        match self {
+            Reg16::BC => 0,
+            Reg16::DE => 1,
+            Reg16::HL | Reg16::IX | Reg16::IY => 2,
+            Reg16::SP | Reg16::AF => 3,
+        }
     }
 
-    /// JR NC, e
-    pub fn jr_nc(&mut self, label: &str) {
-        self.emit(&[0x30]);
-        self.emit_relative(label);
+    /// Name in assembly syntax
+    fn syntax(self) -> &'static str {
+        ["BC", "DE", "HL", "SP", "AF", "IX", "IY"][self as usize]
     }
+}
 
-    /// DJNZ e (decrement B, jump if not zero)
-    pub fn djnz(&mut self, label: &str) {
-        self.emit(&[0x10]);
-        self.emit_relative(label);
+/// Condition code, in Z80 encoding order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cond {
+    NZ,
+    Z,
+    NC,
+    C,
+    /// Parity odd / no overflow
+    PO,
+    /// Parity even / overflow
+    PE,
+    /// Sign positive
+    P,
+    /// Sign negative (minus)
+    M,
+}
+
+impl Cond {
+    /// 3-bit condition field used in opcodes
+    pub fn code(self) -> u8 {
+        self as u8
     }
+}
 
-    // ========== Calls and Returns ==========
+/// The opposite condition (`!Cond::NZ == Cond::Z`)
+impl std::ops::Not for Cond {
+    type Output = Cond;
 
-    /// CALL nn (with fixup)
-    pub fn call(&mut self, label: &str) {
-        self.emit(&[0xCD]);
-        self.fixup(label);
+    fn not(self) -> Cond {
+        const ALL: [Cond; 8] = [
+            Cond::NZ, Cond::Z, Cond::NC, Cond::C, Cond::PO, Cond::PE, Cond::P, Cond::M,
+        ];
+        ALL[(self.code() ^ 1) as usize]
     }
+}
 
-    /// CALL nn (absolute address)
-    pub fn call_addr(&mut self, addr: u16) {
-        self.emit(&[0xCD]);
-        self.emit_word(addr);
-    }
+/// 8-bit accumulator operation, in Z80 encoding order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alu {
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    And,
+    Xor,
+    Or,
+    Cp,
+}
 
-    /// CALL Z, nn
-    pub fn call_z(&mut self, label: &str) {
-        self.emit(&[0xCC]);
-        self.fixup(label);
+impl Alu {
+    /// Mnemonic up to the source operand
+    fn syntax(self) -> &'static str {
+        ["ADD A,", "ADC A,", "SUB ", "SBC A,", "AND ", "XOR ", "OR ", "CP "][self as usize]
     }
+}
 
-    /// CALL NZ, nn
-    pub fn call_nz(&mut self, label: &str) {
-        self.emit(&[0xC4]);
-        self.fixup(label);
-    }
+/// Immediate 8-bit operand for the typed methods (`ld(Reg8::B, Imm(5))`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Imm(pub u8);
 
-    /// RET
-    pub fn ret(&mut self) {
-        self.emit(&[0xC9]);
-    }
+/// 8-bit source operand: a register, (HL) or an immediate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Src8 {
+    Reg(Reg8),
+    Imm(u8),
+}
 
-    /// RET Z
-    pub fn ret_z(&mut self) {
-        self.emit(&[0xC8]);
+impl From<Reg8> for Src8 {
+    fn from(r: Reg8) -> Self {
+        Src8::Reg(r)
     }
+}
 
-    /// RET NZ
-    pub fn ret_nz(&mut self) {
-        self.emit(&[0xC0]);
+impl From<Imm> for Src8 {
+    fn from(n: Imm) -> Self {
+        Src8::Imm(n.0)
     }
+}
 
-    /// RET C
-    pub fn ret_c(&mut self) {
-        self.emit(&[0xD8]);
+impl crate::CodeGen {
+    /// RST n for a restart address 0x00, 0x08, ... 0x38 (the `rst_NN` helpers as one method)
+    pub fn rst(&mut self, n: u8) {
+        match n {
+            0x00 => self.rst_00(),
+            0x08 => self.rst_08(),
+            0x10 => self.rst_10(),
+            0x18 => self.rst_18(),
+            0x20 => self.rst_20(),
+            0x28 => self.rst_28(),
+            0x30 => self.rst_30(),
+            0x38 => self.rst_38(),
+            _ => panic!("RST address must be 0x00-0x38 in steps of 8, got {:#04X}", n),
+        }
     }
 
-    /// RET NC
-    pub fn ret_nc(&mut self) {
-        self.emit(&[0xD0]);
+    /// Stop for good: DI, then HALT in a loop
+    ///
+    /// A bare HALT resumes after an interrupt or NMI and runs into whatever
+    /// follows it; this jumps back to the HALT instead.
+    pub fn halt_forever(&mut self) {
+        self.di();
+        let halt = self.unique_label("halt");
+        self.label(&halt);
+        self.halt();
+        self.jr(&halt);
     }
 
-    // ========== I/O ==========
-
-    /// IN A, (n)
-    pub fn in_a(&mut self, port: u8) {
-        self.emit(&[0xDB, port]);
+    /// Sleep until the next interrupt: EI; HALT
+    ///
+    /// EI takes effect after the following instruction, so an interrupt
+    /// already pending wakes the HALT instead of slipping in before it.
+    pub fn wait_for_interrupt(&mut self) {
+        self.ei();
+        self.halt();
     }
 
-    /// OUT (n), A
-    pub fn out_a(&mut self, port: u8) {
-        self.emit(&[0xD3, port]);
+    /// LD dst, src for any register pair (the `ld_x_y` helpers as one method)
+    ///
+    /// Panics on `LD (HL),(HL)`, whose encoding is HALT.
+    pub fn ld_r_r(&mut self, dst: Reg8, src: Reg8) {
+        assert!(
+            !(dst == Reg8::HlInd && src == Reg8::HlInd),
+            "LD (HL),(HL) does not exist (0x76 is HALT)"
+        );
+        self.emit_syntax(&format!("LD {},{}", dst.syntax(), src.syntax()), Arg::None);
     }
 
-    // ========== Misc ==========
-
-    /// NOP
-    pub fn nop(&mut self) {
-        self.emit(&[0x00]);
-    }
+    // ========== Typed operand forms ==========
+    //
+    // The named helpers above, chosen by operand at run time of the
+    // generator: `ld(Reg8::B, Imm(5))` looks up "LD B,n" in the opcode
+    // table and calls `ld_b(5)` through it.
 
-    /// HALT
-    pub fn halt(&mut self) {
-        self.emit(&[0x76]);
+    /// Emit the opcode table entry for `syntax` through its helper
+    fn emit_syntax(&mut self, syntax: &str, arg: Arg) {
+        let op = opcodes::find(syntax).unwrap_or_else(|| panic!("{} does not exist", syntax));
+        (op.emit)(self, arg);
     }
 
-    /// DI (disable interrupts)
-    pub fn di(&mut self) {
-        self.emit(&[0xF3]);
+    /// LD r, r' or LD r, n
+    pub fn ld(&mut self, dst: Reg8, src: impl Into<Src8>) {
+        match src.into() {
+            Src8::Reg(src) => self.ld_r_r(dst, src),
+            Src8::Imm(n) => self.emit_syntax(&format!("LD {},n", dst.syntax()), Arg::Imm8(n)),
+        }
     }
 
-    /// EI (enable interrupts)
-    pub fn ei(&mut self) {
-        self.emit(&[0xFB]);
+    /// LD rr, nn (BC, DE, HL, SP, IX, IY)
+    pub fn ld16(&mut self, dst: Reg16, nn: u16) {
+        self.emit_syntax(&format!("LD {},nn", dst.syntax()), Arg::Imm16(nn));
     }
 
-    /// SCF (set carry flag)
-    pub fn scf(&mut self) {
-        self.emit(&[0x37]);
+    /// ADD/ADC/SUB/SBC/AND/XOR/OR/CP with a register, (HL) or immediate
+    pub fn alu(&mut self, op: Alu, src: impl Into<Src8>) {
+        match src.into() {
+            Src8::Reg(r) => self.emit_syntax(&format!("{}{}", op.syntax(), r.syntax()), Arg::None),
+            Src8::Imm(n) => self.emit_syntax(&format!("{}n", op.syntax()), Arg::Imm8(n)),
+        }
     }
 
-    /// CCF (complement carry flag)
-    pub fn ccf(&mut self) {
-        self.emit(&[0x3F]);
+    /// INC r
+    pub fn inc(&mut self, r: Reg8) {
+        self.emit_syntax(&format!("INC {}", r.syntax()), Arg::None);
     }
 
-    // ========== Bit Operations ==========
-
-    /// BIT b, A
-    pub fn bit_a(&mut self, bit: u8) {
-        self.emit(&[0xCB, 0x47 | (bit << 3)]);
+    /// DEC r
+    pub fn dec(&mut self, r: Reg8) {
+        self.emit_syntax(&format!("DEC {}", r.syntax()), Arg::None);
     }
 
-    /// SET b, A
-    pub fn set_a(&mut self, bit: u8) {
-        self.emit(&[0xCB, 0xC7 | (bit << 3)]);
+    /// INC rr (BC, DE, HL, SP, IX, IY)
+    pub fn inc16(&mut self, rr: Reg16) {
+        self.emit_syntax(&format!("INC {}", rr.syntax()), Arg::None);
     }
 
-    /// RES b, A
-    pub fn res_a(&mut self, bit: u8) {
-        self.emit(&[0xCB, 0x87 | (bit << 3)]);
+    /// DEC rr (BC, DE, HL, SP, IX, IY)
+    pub fn dec16(&mut self, rr: Reg16) {
+        self.emit_syntax(&format!("DEC {}", rr.syntax()), Arg::None);
     }
 
-    /// RLA (rotate left through carry)
-    pub fn rla(&mut self) {
-        self.emit(&[0x17]);
+    /// ADD HL/IX/IY, rr
+    ///
+    /// The source may be BC, DE, SP or the destination itself.
+    pub fn add(&mut self, dst: Reg16, src: Reg16) {
+        self.emit_syntax(&format!("ADD {},{}", dst.syntax(), src.syntax()), Arg::None);
     }
 
-    /// RRA (rotate right through carry)
-    pub fn rra(&mut self) {
-        self.emit(&[0x1F]);
+    /// PUSH rr (BC, DE, HL, AF, IX, IY)
+    pub fn push(&mut self, rr: Reg16) {
+        self.emit_syntax(&format!("PUSH {}", rr.syntax()), Arg::None);
     }
 
-    /// RLCA (rotate left circular)
-    pub fn rlca(&mut self) {
-        self.emit(&[0x07]);
+    /// POP rr (BC, DE, HL, AF, IX, IY)
+    pub fn pop(&mut self, rr: Reg16) {
+        self.emit_syntax(&format!("POP {}", rr.syntax()), Arg::None);
     }
 
-    /// RRCA (rotate right circular)
-    pub fn rrca(&mut self) {
-        self.emit(&[0x0F]);
+    /// JP cc, nn (with fixup)
+    pub fn jp_cond(&mut self, cond: Cond, label: &str) {
+        self.emit_syntax(&format!("JP {:?},nn", cond), Arg::Label(label));
     }
 
-    /// SLA A (shift left arithmetic)
-    pub fn sla_a(&mut self) {
-        self.emit(&[0xCB, 0x27]);
+    /// JR cc, e (NZ, Z, NC or C)
+    pub fn jr_cond(&mut self, cond: Cond, label: &str) {
+        assert!(cond.code() < 4, "JR {:?} does not exist, use jp_cond", cond);
+        self.emit_syntax(&format!("JR {:?},e", cond), Arg::Label(label));
     }
 
-    /// SRA A (shift right arithmetic)
-    pub fn sra_a(&mut self) {
-        self.emit(&[0xCB, 0x2F]);
+    /// CALL cc, nn (with fixup)
+    pub fn call_cond(&mut self, cond: Cond, label: &str) {
+        self.emit_syntax(&format!("CALL {:?},nn", cond), Arg::Label(label));
     }
 
-    /// SRL A (shift right logical)
-    pub fn srl_a(&mut self) {
-        self.emit(&[0xCB, 0x3F]);
+    /// RET cc
+    pub fn ret_cond(&mut self, cond: Cond) {
+        self.emit_syntax(&format!("RET {:?}", cond), Arg::None);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use super::{Alu, Cond, Imm, Reg16, Reg8};
+    use crate::CodeGen;
 
     #[test]
     fn test_ld_a() {
@@ -759,6 +2025,104 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn test_ld_r_r() {
+        let mut cg = CodeGen::new();
+        cg.ld_r_r(Reg8::H, Reg8::E);
+        cg.ld_r_r(Reg8::A, Reg8::HlInd);
+        cg.ld_r_r(Reg8::HlInd, Reg8::C);
+        cg.ld_h_e();
+        cg.ld_l_a();
+        assert_eq!(cg.rom(), &[
+            0x63,        // LD H, E
+            0x7E,        // LD A, (HL)
+            0x71,        // LD (HL), C
+            0x63,        // LD H, E
+            0x6F,        // LD L, A
+        ]);
+    }
+
+    #[test]
+    fn test_interrupt_control() {
+        let mut cg = CodeGen::new();
+        cg.im1();
+        cg.im2();
+        cg.ld_i_a();
+        cg.ld_a_i();
+        cg.reti();
+        cg.retn();
+        assert_eq!(cg.rom(), &[
+            0xED, 0x56,  // IM 1
+            0xED, 0x5E,  // IM 2
+            0xED, 0x47,  // LD I, A
+            0xED, 0x57,  // LD A, I
+            0xED, 0x4D,  // RETI
+            0xED, 0x45,  // RETN
+        ]);
+    }
+
+    #[test]
+    fn test_rst() {
+        let mut cg = CodeGen::new();
+        cg.rst(0x08);
+        cg.rst_38();
+        cg.rst(0x00);
+        assert_eq!(cg.rom(), &[0xCF, 0xFF, 0xC7]);
+
+        for n in (0x00..=0x38).step_by(8) {
+            let mut cg = CodeGen::new();
+            cg.rst(n);
+            assert_eq!(cg.rom(), &[0xC7 | n]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "RST address")]
+    fn test_rst_invalid() {
+        let mut cg = CodeGen::new();
+        cg.rst(0x09);
+    }
+
+    #[test]
+    #[should_panic(expected = "RST address")]
+    fn test_rst_out_of_range() {
+        CodeGen::new().rst(0x40);
+    }
+
+    #[test]
+    #[should_panic(expected = "HALT")]
+    fn test_ld_hl_ind_hl_ind_panics() {
+        let mut cg = CodeGen::new();
+        cg.ld_r_r(Reg8::HlInd, Reg8::HlInd);
+    }
+
+    #[test]
+    fn test_indexed() {
+        let mut cg = CodeGen::new();
+        cg.push_ix();
+        cg.ld_ix(0);
+        cg.add_ix_sp();
+        cg.ld_l_ix_ind(4);
+        cg.ld_ix_ind_a(-1);
+        cg.ld_iy_ind_n(2, 0x55);
+        cg.cp_iy_ind(-128);
+        cg.inc_ix_ind(0);
+        cg.ex_sp_iy();
+        cg.pop_ix();
+        assert_eq!(cg.rom(), &[
+            0xDD, 0xE5,              // PUSH IX
+            0xDD, 0x21, 0x00, 0x00,  // LD IX, 0
+            0xDD, 0x39,              // ADD IX, SP
+            0xDD, 0x6E, 0x04,        // LD L, (IX+4)
+            0xDD, 0x77, 0xFF,        // LD (IX-1), A
+            0xFD, 0x36, 0x02, 0x55,  // LD (IY+2), 0x55
+            0xFD, 0xBE, 0x80,        // CP (IY-128)
+            0xDD, 0x34, 0x00,        // INC (IX+0)
+            0xFD, 0xE3,              // EX (SP), IY
+            0xDD, 0xE1,              // POP IX
+        ]);
+    }
+
     #[test]
     fn test_logic() {
         let mut cg = CodeGen::new();
@@ -877,4 +2241,425 @@ mod tests {
         cg.rrca();
         assert_eq!(cg.rom(), &[0x17, 0x1F, 0x07, 0x0F]);
     }
+
+    #[test]
+    fn test_typed_operands() {
+        let mut typed = CodeGen::new();
+        let mut named = CodeGen::new();
+        typed.ld(Reg8::B, Imm(5));
+        named.ld_b(5);
+        typed.ld(Reg8::H, Reg8::E);
+        named.ld_h_e();
+        typed.ld16(Reg16::DE, 0x1234);
+        named.ld_de(0x1234);
+        typed.ld16(Reg16::IX, 0x4000);
+        named.ld_ix(0x4000);
+        typed.alu(Alu::Cp, Imm(10));
+        named.cp(10);
+        typed.alu(Alu::Sub, Reg8::B);
+        named.sub_b();
+        typed.alu(Alu::Or, Reg8::A);
+        named.or_a_a();
+        typed.inc(Reg8::C);
+        named.inc_c();
+        typed.dec(Reg8::A);
+        named.dec_a();
+        typed.inc16(Reg16::HL);
+        named.inc_hl();
+        typed.dec16(Reg16::BC);
+        named.dec_bc();
+        typed.add(Reg16::HL, Reg16::DE);
+        named.add_hl_de();
+        typed.add(Reg16::IY, Reg16::IY);
+        named.add_iy_iy();
+        typed.push(Reg16::AF);
+        named.push_af();
+        typed.pop(Reg16::IX);
+        named.pop_ix();
+        typed.label("top");
+        named.label("top");
+        typed.jr_cond(Cond::NC, "top");
+        named.jr_nc("top");
+        typed.jp_cond(Cond::M, "top");
+        named.jp_m("top");
+        typed.call_cond(Cond::Z, "top");
+        named.call_z("top");
+        typed.ret_cond(Cond::NZ);
+        named.ret_nz();
+        typed.resolve_fixups();
+        named.resolve_fixups();
+        assert_eq!(typed.rom(), named.rom());
+    }
+
+    #[test]
+    fn test_typed_matches_helpers() {
+        // Every register and condition the typed forms accept
+        let check = |typed: fn(&mut CodeGen), named: fn(&mut CodeGen)| {
+            let mut a = CodeGen::new();
+            let mut b = CodeGen::new();
+            for cg in [&mut a, &mut b] {
+                cg.label("top");
+            }
+            typed(&mut a);
+            named(&mut b);
+            a.resolve_fixups();
+            b.resolve_fixups();
+            assert_eq!(a.rom(), b.rom());
+        };
+        check(|cg| cg.ld(Reg8::B, Reg8::B), |cg| cg.ld_b_b());
+        check(|cg| cg.ld(Reg8::B, Reg8::C), |cg| cg.ld_b_c());
+        check(|cg| cg.ld(Reg8::B, Reg8::D), |cg| cg.ld_b_d());
+        check(|cg| cg.ld(Reg8::B, Reg8::E), |cg| cg.ld_b_e());
+        check(|cg| cg.ld(Reg8::B, Reg8::H), |cg| cg.ld_b_h());
+        check(|cg| cg.ld(Reg8::B, Reg8::L), |cg| cg.ld_b_l());
+        check(|cg| cg.ld(Reg8::B, Reg8::HlInd), |cg| cg.ld_b_hl_ind());
+        check(|cg| cg.ld(Reg8::B, Reg8::A), |cg| cg.ld_b_a());
+        check(|cg| cg.ld(Reg8::B, Imm(0x5A)), |cg| cg.ld_b(0x5A));
+        check(|cg| cg.inc(Reg8::B), |cg| cg.inc_b());
+        check(|cg| cg.dec(Reg8::B), |cg| cg.dec_b());
+        check(|cg| cg.ld(Reg8::C, Reg8::B), |cg| cg.ld_c_b());
+        check(|cg| cg.ld(Reg8::C, Reg8::C), |cg| cg.ld_c_c());
+        check(|cg| cg.ld(Reg8::C, Reg8::D), |cg| cg.ld_c_d());
+        check(|cg| cg.ld(Reg8::C, Reg8::E), |cg| cg.ld_c_e());
+        check(|cg| cg.ld(Reg8::C, Reg8::H), |cg| cg.ld_c_h());
+        check(|cg| cg.ld(Reg8::C, Reg8::L), |cg| cg.ld_c_l());
+        check(|cg| cg.ld(Reg8::C, Reg8::HlInd), |cg| cg.ld_c_hl_ind());
+        check(|cg| cg.ld(Reg8::C, Reg8::A), |cg| cg.ld_c_a());
+        check(|cg| cg.ld(Reg8::C, Imm(0x5A)), |cg| cg.ld_c(0x5A));
+        check(|cg| cg.inc(Reg8::C), |cg| cg.inc_c());
+        check(|cg| cg.dec(Reg8::C), |cg| cg.dec_c());
+        check(|cg| cg.ld(Reg8::D, Reg8::B), |cg| cg.ld_d_b());
+        check(|cg| cg.ld(Reg8::D, Reg8::C), |cg| cg.ld_d_c());
+        check(|cg| cg.ld(Reg8::D, Reg8::D), |cg| cg.ld_d_d());
+        check(|cg| cg.ld(Reg8::D, Reg8::E), |cg| cg.ld_d_e());
+        check(|cg| cg.ld(Reg8::D, Reg8::H), |cg| cg.ld_d_h());
+        check(|cg| cg.ld(Reg8::D, Reg8::L), |cg| cg.ld_d_l());
+        check(|cg| cg.ld(Reg8::D, Reg8::HlInd), |cg| cg.ld_d_hl_ind());
+        check(|cg| cg.ld(Reg8::D, Reg8::A), |cg| cg.ld_d_a());
+        check(|cg| cg.ld(Reg8::D, Imm(0x5A)), |cg| cg.ld_d(0x5A));
+        check(|cg| cg.inc(Reg8::D), |cg| cg.inc_d());
+        check(|cg| cg.dec(Reg8::D), |cg| cg.dec_d());
+        check(|cg| cg.ld(Reg8::E, Reg8::B), |cg| cg.ld_e_b());
+        check(|cg| cg.ld(Reg8::E, Reg8::C), |cg| cg.ld_e_c());
+        check(|cg| cg.ld(Reg8::E, Reg8::D), |cg| cg.ld_e_d());
+        check(|cg| cg.ld(Reg8::E, Reg8::E), |cg| cg.ld_e_e());
+        check(|cg| cg.ld(Reg8::E, Reg8::H), |cg| cg.ld_e_h());
+        check(|cg| cg.ld(Reg8::E, Reg8::L), |cg| cg.ld_e_l());
+        check(|cg| cg.ld(Reg8::E, Reg8::HlInd), |cg| cg.ld_e_hl_ind());
+        check(|cg| cg.ld(Reg8::E, Reg8::A), |cg| cg.ld_e_a());
+        check(|cg| cg.ld(Reg8::E, Imm(0x5A)), |cg| cg.ld_e(0x5A));
+        check(|cg| cg.inc(Reg8::E), |cg| cg.inc_e());
+        check(|cg| cg.dec(Reg8::E), |cg| cg.dec_e());
+        check(|cg| cg.ld(Reg8::H, Reg8::B), |cg| cg.ld_h_b());
+        check(|cg| cg.ld(Reg8::H, Reg8::C), |cg| cg.ld_h_c());
+        check(|cg| cg.ld(Reg8::H, Reg8::D), |cg| cg.ld_h_d());
+        check(|cg| cg.ld(Reg8::H, Reg8::E), |cg| cg.ld_h_e());
+        check(|cg| cg.ld(Reg8::H, Reg8::H), |cg| cg.ld_h_h());
+        check(|cg| cg.ld(Reg8::H, Reg8::L), |cg| cg.ld_h_l());
+        check(|cg| cg.ld(Reg8::H, Reg8::HlInd), |cg| cg.ld_h_hl_ind());
+        check(|cg| cg.ld(Reg8::H, Reg8::A), |cg| cg.ld_h_a());
+        check(|cg| cg.ld(Reg8::H, Imm(0x5A)), |cg| cg.ld_h(0x5A));
+        check(|cg| cg.inc(Reg8::H), |cg| cg.inc_h());
+        check(|cg| cg.dec(Reg8::H), |cg| cg.dec_h());
+        check(|cg| cg.ld(Reg8::L, Reg8::B), |cg| cg.ld_l_b());
+        check(|cg| cg.ld(Reg8::L, Reg8::C), |cg| cg.ld_l_c());
+        check(|cg| cg.ld(Reg8::L, Reg8::D), |cg| cg.ld_l_d());
+        check(|cg| cg.ld(Reg8::L, Reg8::E), |cg| cg.ld_l_e());
+        check(|cg| cg.ld(Reg8::L, Reg8::H), |cg| cg.ld_l_h());
+        check(|cg| cg.ld(Reg8::L, Reg8::L), |cg| cg.ld_l_l());
+        check(|cg| cg.ld(Reg8::L, Reg8::HlInd), |cg| cg.ld_l_hl_ind());
+        check(|cg| cg.ld(Reg8::L, Reg8::A), |cg| cg.ld_l_a());
+        check(|cg| cg.ld(Reg8::L, Imm(0x5A)), |cg| cg.ld_l(0x5A));
+        check(|cg| cg.inc(Reg8::L), |cg| cg.inc_l());
+        check(|cg| cg.dec(Reg8::L), |cg| cg.dec_l());
+        check(|cg| cg.ld(Reg8::HlInd, Reg8::B), |cg| cg.ld_hl_ind_b());
+        check(|cg| cg.ld(Reg8::HlInd, Reg8::C), |cg| cg.ld_hl_ind_c());
+        check(|cg| cg.ld(Reg8::HlInd, Reg8::D), |cg| cg.ld_hl_ind_d());
+        check(|cg| cg.ld(Reg8::HlInd, Reg8::E), |cg| cg.ld_hl_ind_e());
+        check(|cg| cg.ld(Reg8::HlInd, Reg8::H), |cg| cg.ld_hl_ind_h());
+        check(|cg| cg.ld(Reg8::HlInd, Reg8::L), |cg| cg.ld_hl_ind_l());
+        check(|cg| cg.ld(Reg8::HlInd, Reg8::A), |cg| cg.ld_hl_ind_a());
+        check(|cg| cg.ld(Reg8::HlInd, Imm(0x5A)), |cg| cg.ld_hl_ind_n(0x5A));
+        check(|cg| cg.inc(Reg8::HlInd), |cg| cg.inc_hl_ind());
+        check(|cg| cg.dec(Reg8::HlInd), |cg| cg.dec_hl_ind());
+        check(|cg| cg.ld(Reg8::A, Reg8::B), |cg| cg.ld_a_b());
+        check(|cg| cg.ld(Reg8::A, Reg8::C), |cg| cg.ld_a_c());
+        check(|cg| cg.ld(Reg8::A, Reg8::D), |cg| cg.ld_a_d());
+        check(|cg| cg.ld(Reg8::A, Reg8::E), |cg| cg.ld_a_e());
+        check(|cg| cg.ld(Reg8::A, Reg8::H), |cg| cg.ld_a_h());
+        check(|cg| cg.ld(Reg8::A, Reg8::L), |cg| cg.ld_a_l());
+        check(|cg| cg.ld(Reg8::A, Reg8::HlInd), |cg| cg.ld_a_hl_ind());
+        check(|cg| cg.ld(Reg8::A, Reg8::A), |cg| cg.ld_a_a());
+        check(|cg| cg.ld(Reg8::A, Imm(0x5A)), |cg| cg.ld_a(0x5A));
+        check(|cg| cg.inc(Reg8::A), |cg| cg.inc_a());
+        check(|cg| cg.dec(Reg8::A), |cg| cg.dec_a());
+        check(|cg| cg.alu(Alu::Add, Reg8::B), |cg| cg.add_a_b());
+        check(|cg| cg.alu(Alu::Add, Reg8::C), |cg| cg.add_a_c());
+        check(|cg| cg.alu(Alu::Add, Reg8::D), |cg| cg.add_a_d());
+        check(|cg| cg.alu(Alu::Add, Reg8::E), |cg| cg.add_a_e());
+        check(|cg| cg.alu(Alu::Add, Reg8::H), |cg| cg.add_a_h());
+        check(|cg| cg.alu(Alu::Add, Reg8::L), |cg| cg.add_a_l());
+        check(|cg| cg.alu(Alu::Add, Reg8::HlInd), |cg| cg.add_a_hl_ind());
+        check(|cg| cg.alu(Alu::Add, Reg8::A), |cg| cg.add_a_a());
+        check(|cg| cg.alu(Alu::Add, Imm(0x5A)), |cg| cg.add_a(0x5A));
+        check(|cg| cg.alu(Alu::Adc, Reg8::B), |cg| cg.adc_a_b());
+        check(|cg| cg.alu(Alu::Adc, Reg8::C), |cg| cg.adc_a_c());
+        check(|cg| cg.alu(Alu::Adc, Reg8::D), |cg| cg.adc_a_d());
+        check(|cg| cg.alu(Alu::Adc, Reg8::E), |cg| cg.adc_a_e());
+        check(|cg| cg.alu(Alu::Adc, Reg8::H), |cg| cg.adc_a_h());
+        check(|cg| cg.alu(Alu::Adc, Reg8::L), |cg| cg.adc_a_l());
+        check(|cg| cg.alu(Alu::Adc, Reg8::HlInd), |cg| cg.adc_a_hl_ind());
+        check(|cg| cg.alu(Alu::Adc, Reg8::A), |cg| cg.adc_a_a());
+        check(|cg| cg.alu(Alu::Adc, Imm(0x5A)), |cg| cg.adc_a(0x5A));
+        check(|cg| cg.alu(Alu::Sub, Reg8::B), |cg| cg.sub_b());
+        check(|cg| cg.alu(Alu::Sub, Reg8::C), |cg| cg.sub_c());
+        check(|cg| cg.alu(Alu::Sub, Reg8::D), |cg| cg.sub_d());
+        check(|cg| cg.alu(Alu::Sub, Reg8::E), |cg| cg.sub_e());
+        check(|cg| cg.alu(Alu::Sub, Reg8::H), |cg| cg.sub_h());
+        check(|cg| cg.alu(Alu::Sub, Reg8::L), |cg| cg.sub_l());
+        check(|cg| cg.alu(Alu::Sub, Reg8::HlInd), |cg| cg.sub_hl_ind());
+        check(|cg| cg.alu(Alu::Sub, Reg8::A), |cg| cg.sub_a_a());
+        check(|cg| cg.alu(Alu::Sub, Imm(0x5A)), |cg| cg.sub_a(0x5A));
+        check(|cg| cg.alu(Alu::Sbc, Reg8::B), |cg| cg.sbc_a_b());
+        check(|cg| cg.alu(Alu::Sbc, Reg8::C), |cg| cg.sbc_a_c());
+        check(|cg| cg.alu(Alu::Sbc, Reg8::D), |cg| cg.sbc_a_d());
+        check(|cg| cg.alu(Alu::Sbc, Reg8::E), |cg| cg.sbc_a_e());
+        check(|cg| cg.alu(Alu::Sbc, Reg8::H), |cg| cg.sbc_a_h());
+        check(|cg| cg.alu(Alu::Sbc, Reg8::L), |cg| cg.sbc_a_l());
+        check(|cg| cg.alu(Alu::Sbc, Reg8::HlInd), |cg| cg.sbc_a_hl_ind());
+        check(|cg| cg.alu(Alu::Sbc, Reg8::A), |cg| cg.sbc_a_a());
+        check(|cg| cg.alu(Alu::Sbc, Imm(0x5A)), |cg| cg.sbc_a(0x5A));
+        check(|cg| cg.alu(Alu::And, Reg8::B), |cg| cg.and_b());
+        check(|cg| cg.alu(Alu::And, Reg8::C), |cg| cg.and_c());
+        check(|cg| cg.alu(Alu::And, Reg8::D), |cg| cg.and_d());
+        check(|cg| cg.alu(Alu::And, Reg8::E), |cg| cg.and_e());
+        check(|cg| cg.alu(Alu::And, Reg8::H), |cg| cg.and_h());
+        check(|cg| cg.alu(Alu::And, Reg8::L), |cg| cg.and_l());
+        check(|cg| cg.alu(Alu::And, Reg8::HlInd), |cg| cg.and_hl_ind());
+        check(|cg| cg.alu(Alu::And, Reg8::A), |cg| cg.and_a_a());
+        check(|cg| cg.alu(Alu::And, Imm(0x5A)), |cg| cg.and_a(0x5A));
+        check(|cg| cg.alu(Alu::Xor, Reg8::B), |cg| cg.xor_b());
+        check(|cg| cg.alu(Alu::Xor, Reg8::C), |cg| cg.xor_c());
+        check(|cg| cg.alu(Alu::Xor, Reg8::D), |cg| cg.xor_d());
+        check(|cg| cg.alu(Alu::Xor, Reg8::E), |cg| cg.xor_e());
+        check(|cg| cg.alu(Alu::Xor, Reg8::H), |cg| cg.xor_h());
+        check(|cg| cg.alu(Alu::Xor, Reg8::L), |cg| cg.xor_l());
+        check(|cg| cg.alu(Alu::Xor, Reg8::HlInd), |cg| cg.xor_hl_ind());
+        check(|cg| cg.alu(Alu::Xor, Reg8::A), |cg| cg.xor_a());
+        check(|cg| cg.alu(Alu::Xor, Imm(0x5A)), |cg| cg.xor_n(0x5A));
+        check(|cg| cg.alu(Alu::Or, Reg8::B), |cg| cg.or_b());
+        check(|cg| cg.alu(Alu::Or, Reg8::C), |cg| cg.or_c());
+        check(|cg| cg.alu(Alu::Or, Reg8::D), |cg| cg.or_d());
+        check(|cg| cg.alu(Alu::Or, Reg8::E), |cg| cg.or_e());
+        check(|cg| cg.alu(Alu::Or, Reg8::H), |cg| cg.or_h());
+        check(|cg| cg.alu(Alu::Or, Reg8::L), |cg| cg.or_l());
+        check(|cg| cg.alu(Alu::Or, Reg8::HlInd), |cg| cg.or_hl_ind());
+        check(|cg| cg.alu(Alu::Or, Reg8::A), |cg| cg.or_a_a());
+        check(|cg| cg.alu(Alu::Or, Imm(0x5A)), |cg| cg.or_a(0x5A));
+        check(|cg| cg.alu(Alu::Cp, Reg8::B), |cg| cg.cp_b());
+        check(|cg| cg.alu(Alu::Cp, Reg8::C), |cg| cg.cp_c());
+        check(|cg| cg.alu(Alu::Cp, Reg8::D), |cg| cg.cp_d());
+        check(|cg| cg.alu(Alu::Cp, Reg8::E), |cg| cg.cp_e());
+        check(|cg| cg.alu(Alu::Cp, Reg8::H), |cg| cg.cp_h());
+        check(|cg| cg.alu(Alu::Cp, Reg8::L), |cg| cg.cp_l());
+        check(|cg| cg.alu(Alu::Cp, Reg8::HlInd), |cg| cg.cp_hl_ind());
+        check(|cg| cg.alu(Alu::Cp, Reg8::A), |cg| cg.cp_a());
+        check(|cg| cg.alu(Alu::Cp, Imm(0x5A)), |cg| cg.cp(0x5A));
+        check(|cg| cg.ld16(Reg16::BC, 0x1234), |cg| cg.ld_bc(0x1234));
+        check(|cg| cg.inc16(Reg16::BC), |cg| cg.inc_bc());
+        check(|cg| cg.dec16(Reg16::BC), |cg| cg.dec_bc());
+        check(|cg| cg.push(Reg16::BC), |cg| cg.push_bc());
+        check(|cg| cg.pop(Reg16::BC), |cg| cg.pop_bc());
+        check(|cg| cg.ld16(Reg16::DE, 0x1234), |cg| cg.ld_de(0x1234));
+        check(|cg| cg.inc16(Reg16::DE), |cg| cg.inc_de());
+        check(|cg| cg.dec16(Reg16::DE), |cg| cg.dec_de());
+        check(|cg| cg.push(Reg16::DE), |cg| cg.push_de());
+        check(|cg| cg.pop(Reg16::DE), |cg| cg.pop_de());
+        check(|cg| cg.ld16(Reg16::HL, 0x1234), |cg| cg.ld_hl(0x1234));
+        check(|cg| cg.inc16(Reg16::HL), |cg| cg.inc_hl());
+        check(|cg| cg.dec16(Reg16::HL), |cg| cg.dec_hl());
+        check(|cg| cg.push(Reg16::HL), |cg| cg.push_hl());
+        check(|cg| cg.pop(Reg16::HL), |cg| cg.pop_hl());
+        check(|cg| cg.ld16(Reg16::SP, 0x1234), |cg| cg.ld_sp(0x1234));
+        check(|cg| cg.inc16(Reg16::SP), |cg| cg.inc_sp());
+        check(|cg| cg.dec16(Reg16::SP), |cg| cg.dec_sp());
+        check(|cg| cg.push(Reg16::AF), |cg| cg.push_af());
+        check(|cg| cg.pop(Reg16::AF), |cg| cg.pop_af());
+        check(|cg| cg.ld16(Reg16::IX, 0x1234), |cg| cg.ld_ix(0x1234));
+        check(|cg| cg.inc16(Reg16::IX), |cg| cg.inc_ix());
+        check(|cg| cg.dec16(Reg16::IX), |cg| cg.dec_ix());
+        check(|cg| cg.push(Reg16::IX), |cg| cg.push_ix());
+        check(|cg| cg.pop(Reg16::IX), |cg| cg.pop_ix());
+        check(|cg| cg.ld16(Reg16::IY, 0x1234), |cg| cg.ld_iy(0x1234));
+        check(|cg| cg.inc16(Reg16::IY), |cg| cg.inc_iy());
+        check(|cg| cg.dec16(Reg16::IY), |cg| cg.dec_iy());
+        check(|cg| cg.push(Reg16::IY), |cg| cg.push_iy());
+        check(|cg| cg.pop(Reg16::IY), |cg| cg.pop_iy());
+        check(|cg| cg.add(Reg16::HL, Reg16::BC), |cg| cg.add_hl_bc());
+        check(|cg| cg.add(Reg16::HL, Reg16::DE), |cg| cg.add_hl_de());
+        check(|cg| cg.add(Reg16::HL, Reg16::HL), |cg| cg.add_hl_hl());
+        check(|cg| cg.add(Reg16::HL, Reg16::SP), |cg| cg.add_hl_sp());
+        check(|cg| cg.add(Reg16::IX, Reg16::BC), |cg| cg.add_ix_bc());
+        check(|cg| cg.add(Reg16::IX, Reg16::DE), |cg| cg.add_ix_de());
+        check(|cg| cg.add(Reg16::IX, Reg16::IX), |cg| cg.add_ix_ix());
+        check(|cg| cg.add(Reg16::IX, Reg16::SP), |cg| cg.add_ix_sp());
+        check(|cg| cg.add(Reg16::IY, Reg16::BC), |cg| cg.add_iy_bc());
+        check(|cg| cg.add(Reg16::IY, Reg16::DE), |cg| cg.add_iy_de());
+        check(|cg| cg.add(Reg16::IY, Reg16::IY), |cg| cg.add_iy_iy());
+        check(|cg| cg.add(Reg16::IY, Reg16::SP), |cg| cg.add_iy_sp());
+        check(|cg| cg.jp_cond(Cond::NZ, "top"), |cg| cg.jp_nz("top"));
+        check(|cg| cg.call_cond(Cond::NZ, "top"), |cg| cg.call_nz("top"));
+        check(|cg| cg.ret_cond(Cond::NZ), |cg| cg.ret_nz());
+        check(|cg| cg.jr_cond(Cond::NZ, "top"), |cg| cg.jr_nz("top"));
+        check(|cg| cg.jp_cond(Cond::Z, "top"), |cg| cg.jp_z("top"));
+        check(|cg| cg.call_cond(Cond::Z, "top"), |cg| cg.call_z("top"));
+        check(|cg| cg.ret_cond(Cond::Z), |cg| cg.ret_z());
+        check(|cg| cg.jr_cond(Cond::Z, "top"), |cg| cg.jr_z("top"));
+        check(|cg| cg.jp_cond(Cond::NC, "top"), |cg| cg.jp_nc("top"));
+        check(|cg| cg.call_cond(Cond::NC, "top"), |cg| cg.call_nc("top"));
+        check(|cg| cg.ret_cond(Cond::NC), |cg| cg.ret_nc());
+        check(|cg| cg.jr_cond(Cond::NC, "top"), |cg| cg.jr_nc("top"));
+        check(|cg| cg.jp_cond(Cond::C, "top"), |cg| cg.jp_c("top"));
+        check(|cg| cg.call_cond(Cond::C, "top"), |cg| cg.call_c("top"));
+        check(|cg| cg.ret_cond(Cond::C), |cg| cg.ret_c());
+        check(|cg| cg.jr_cond(Cond::C, "top"), |cg| cg.jr_c("top"));
+        check(|cg| cg.jp_cond(Cond::PO, "top"), |cg| cg.jp_po("top"));
+        check(|cg| cg.call_cond(Cond::PO, "top"), |cg| cg.call_po("top"));
+        check(|cg| cg.ret_cond(Cond::PO), |cg| cg.ret_po());
+        check(|cg| cg.jp_cond(Cond::PE, "top"), |cg| cg.jp_pe("top"));
+        check(|cg| cg.call_cond(Cond::PE, "top"), |cg| cg.call_pe("top"));
+        check(|cg| cg.ret_cond(Cond::PE), |cg| cg.ret_pe());
+        check(|cg| cg.jp_cond(Cond::P, "top"), |cg| cg.jp_p("top"));
+        check(|cg| cg.call_cond(Cond::P, "top"), |cg| cg.call_p("top"));
+        check(|cg| cg.ret_cond(Cond::P), |cg| cg.ret_p());
+        check(|cg| cg.jp_cond(Cond::M, "top"), |cg| cg.jp_m("top"));
+        check(|cg| cg.call_cond(Cond::M, "top"), |cg| cg.call_m("top"));
+        check(|cg| cg.ret_cond(Cond::M), |cg| cg.ret_m());
+    }
+
+    #[test]
+    fn test_cond_not() {
+        assert_eq!(!Cond::NZ, Cond::Z);
+        assert_eq!(!Cond::C, Cond::NC);
+        assert_eq!(!Cond::PE, Cond::PO);
+        assert_eq!(!Cond::M, Cond::P);
+    }
+
+    #[test]
+    #[should_panic(expected = "JR PE does not exist")]
+    fn test_jr_cond_rejects_parity() {
+        let mut cg = CodeGen::new();
+        cg.label("top");
+        cg.jr_cond(Cond::PE, "top");
+    }
+
+    #[test]
+    #[should_panic(expected = "ADD HL,IX does not exist")]
+    fn test_add_rejects_mixed_index() {
+        let mut cg = CodeGen::new();
+        cg.add(Reg16::HL, Reg16::IX);
+    }
+
+    #[test]
+    fn test_misc_instructions() {
+        let mut cg = CodeGen::new();
+        cg.daa();
+        cg.neg();
+        cg.rld();
+        cg.rrd();
+        cg.ex_sp_hl();
+        cg.add_a_l();
+        cg.sub_hl_ind();
+        cg.cp_e();
+        cg.wait_for_interrupt();
+        cg.halt_forever();
+        assert_eq!(cg.rom(), &[
+            0x27,        // DAA
+            0xED, 0x44,  // NEG
+            0xED, 0x6F,  // RLD
+            0xED, 0x67,  // RRD
+            0xE3,        // EX (SP), HL
+            0x85,        // ADD A, L
+            0x96,        // SUB (HL)
+            0xBB,        // CP E
+            0xFB,        // EI
+            0x76,        // HALT
+            0xF3,        // DI
+            0x76,        // HALT
+            0x18, 0xFD,  // JR -3 (back to HALT)
+        ]);
+    }
+
+    #[test]
+    fn test_carry_chain() {
+        let mut cg = CodeGen::new();
+        cg.adc_a(0x40);
+        cg.adc_a_c();
+        cg.adc_a_hl_ind();
+        cg.sbc_a(1);
+        cg.sbc_a_e();
+        cg.sbc_a_a();
+        cg.adc_hl_de();
+        cg.sbc_hl_sp();
+        assert_eq!(cg.rom(), &[
+            0xCE, 0x40,  // ADC A, 0x40
+            0x89,        // ADC A, C
+            0x8E,        // ADC A, (HL)
+            0xDE, 0x01,  // SBC A, 1
+            0x9B,        // SBC A, E
+            0x9F,        // SBC A, A
+            0xED, 0x5A,  // ADC HL, DE
+            0xED, 0x72,  // SBC HL, SP
+        ]);
+    }
+
+    #[test]
+    fn test_port_c_io() {
+        let mut cg = CodeGen::new();
+        cg.in_e_c();
+        cg.out_c_a();
+        cg.inir();
+        cg.otdr();
+        cg.outi();
+        assert_eq!(cg.rom(), &[
+            0xED, 0x58,  // IN E, (C)
+            0xED, 0x79,  // OUT (C), A
+            0xED, 0xB2,  // INIR
+            0xED, 0xBB,  // OTDR
+            0xED, 0xA3,  // OUTI
+        ]);
+    }
+
+    #[test]
+    fn test_all_conditions() {
+        let mut cg = CodeGen::new();
+        cg.label("t");
+        cg.jp_po("t");
+        cg.jp_pe("t");
+        cg.call_c("t");
+        cg.call_nc("t");
+        cg.call_po("t");
+        cg.call_pe("t");
+        cg.call_p("t");
+        cg.call_m("t");
+        cg.ret_po();
+        cg.ret_pe();
+        cg.ret_p();
+        cg.ret_m();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xE2, 0x00, 0x00,  // JP PO, t
+            0xEA, 0x00, 0x00,  // JP PE, t
+            0xDC, 0x00, 0x00,  // CALL C, t
+            0xD4, 0x00, 0x00,  // CALL NC, t
+            0xE4, 0x00, 0x00,  // CALL PO, t
+            0xEC, 0x00, 0x00,  // CALL PE, t
+            0xF4, 0x00, 0x00,  // CALL P, t
+            0xFC, 0x00, 0x00,  // CALL M, t
+            0xE0,              // RET PO
+            0xE8,              // RET PE
+            0xF0,              // RET P
+            0xF8,              // RET M
+        ]);
+    }
 }
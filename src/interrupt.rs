@@ -0,0 +1,176 @@
+//! Interrupt handler builders
+//!
+//! Fast handlers swap to the shadow register bank (EXX / EX AF,AF') instead
+//! of pushing registers. Set `RomConfig::shadow_regs_for_isr` to reserve the
+//! bank for them, so main-line code that touches it is rejected.
+//!
+//! Restart vectors (RST 00H-38H, including the IM 1 handler at 38H) sit at
+//! fixed addresses and are placed with `rst_handler`; IM 2 tables come from
+//! `emit_im2_table`.
+
+use crate::CodeGen;
+
+impl CodeGen {
+    /// Emit a fast ISR prologue: switch to the shadow register bank
+    ///
+    /// Must be paired with `isr_epilogue_fast`.
+    pub fn isr_prologue_fast(&mut self) {
+        assert!(!self.in_isr, "ISR prologue inside another ISR");
+        self.in_isr = true;
+        self.ex_af();
+        self.exx();
+    }
+
+    /// Emit a fast ISR epilogue: restore the main bank, EI, RETI
+    pub fn isr_epilogue_fast(&mut self) {
+        assert!(self.in_isr, "ISR epilogue without a prologue");
+        self.exx();
+        self.ex_af();
+        self.in_isr = false;
+        self.ei();
+        self.reti();
+    }
+
+    /// Emit an interrupt handler labelled `name` using the shadow bank
+    ///
+    /// `body` may use every register except IX, IY and SP without saving.
+    pub fn isr_fast<F: FnOnce(&mut CodeGen)>(&mut self, name: &str, body: F) {
+        self.label(name);
+        self.isr_prologue_fast();
+        body(self);
+        self.isr_epilogue_fast();
+    }
+
+    /// Place a handler at the fixed address of restart vector `n`
+    ///
+    /// Pads up to address `n` with 0xFF, labels it `rst_NN` (e.g. `rst_08`)
+    /// and emits `body` there. A body may run past the next vector only if
+    /// that vector is unused; placing vectors out of order panics.
+    ///
+    /// Labels created: `rst_NN`
+    pub fn rst_handler<F: FnOnce(&mut CodeGen)>(&mut self, n: u8, body: F) {
+        assert!(
+            n % 8 == 0 && n <= 0x38,
+            "RST address must be 0x00-0x38 in steps of 8, got {:#04X}",
+            n
+        );
+        assert_eq!(self.config().org, 0, "RST vectors need a ROM at address 0");
+        self.pad_to(n as u16, 0xFF);
+        self.label(&format!("rst_{:02x}", n));
+        body(self);
+    }
+
+    /// Emit a 257-byte IM 2 vector table sending every vector to `handler`
+    ///
+    /// Without a device supplying the vector byte, the CPU may fetch any
+    /// word from the I page, so every table byte holds the same value V and
+    /// the handler is reached through a `JP handler` at address VVh. The
+    /// table starts at the next 256-byte boundary (padded with 0xFF) and the
+    /// gap before the jump is padded too.
+    ///
+    /// Labels created: `im2_table`, `im2_jump`
+    pub fn emit_im2_table(&mut self, handler: &str) {
+        let page = (self.pos() as u32 + 0xFF) / 0x100;
+        assert!(page < 0xFF, "no room for an IM 2 table below 0xFF00");
+        let page = page as u16;
+        self.pad_to(page * 0x100, 0xFF);
+        let v = (page + 1) as u8;
+        self.label("im2_table");
+        self.emit(&[v; 257]);
+        self.pad_to(u16::from_le_bytes([v, v]), 0xFF);
+        self.label("im2_jump");
+        self.jp(handler);
+    }
+
+    /// Emit IM 2 setup for `emit_im2_table`: point I at the table, select IM 2
+    ///
+    /// Requires: `im2_table`
+    pub fn im2_setup(&mut self) {
+        self.ld_a_label_hi("im2_table");
+        self.ld_i_a();
+        self.im2();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RomConfig;
+
+    #[test]
+    fn test_rst_handler() {
+        let mut cg = CodeGen::new();
+        cg.emit_startup(0x3FFF);
+        cg.rst_handler(0x08, |r| r.jp("putchar"));
+        cg.rst_handler(0x10, |r| r.jp("getchar"));
+        cg.label("putchar");
+        cg.label("getchar");
+        cg.resolve_fixups();
+        assert_eq!(cg.get_label("rst_08"), Some(0x0008));
+        assert_eq!(&cg.rom()[0x07..0x13], &[
+            0xFF,                    // Gap
+            0xC3, 0x13, 0x00,        // rst_08: JP putchar
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0xC3, 0x13, 0x00,        // rst_10: JP getchar
+        ]);
+    }
+
+    #[test]
+    fn test_im2_table() {
+        let mut cg = CodeGen::new();
+        cg.im2_setup();
+        cg.label("isr");
+        cg.reti();
+        cg.emit_im2_table("isr");
+        cg.resolve_fixups();
+        assert_eq!(cg.get_label("im2_table"), Some(0x0100));
+        assert_eq!(cg.get_label("im2_jump"), Some(0x0202));
+        assert!(cg.rom()[0x100..=0x200].iter().all(|&b| b == 0x02));
+        assert_eq!(&cg.rom()[..7], &[
+            0x3E, 0x01,              // LD A, high(im2_table)
+            0xED, 0x47,              // LD I, A
+            0xED, 0x5E,              // IM 2
+            0xED,                    // isr: RETI
+        ]);
+        assert_eq!(&cg.rom()[0x201..], &[0xFF, 0xC3, 0x06, 0x00]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot pad")]
+    fn test_rst_handler_out_of_order() {
+        let mut cg = CodeGen::new();
+        cg.rst_handler(0x10, |r| r.nop());
+        cg.rst_handler(0x08, |r| r.nop());
+    }
+
+    fn reserved() -> CodeGen {
+        CodeGen::with_config(RomConfig {
+            shadow_regs_for_isr: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_isr_fast() {
+        let mut cg = reserved();
+        cg.isr_fast("tick_isr", |r| {
+            r.inc_hl();
+        });
+        assert_eq!(cg.rom(), &[
+            0x08,              // EX AF, AF'
+            0xD9,              // EXX
+            0x23,              // INC HL
+            0xD9,              // EXX
+            0x08,              // EX AF, AF'
+            0xFB,              // EI
+            0xED, 0x4D,        // RETI
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "shadow registers are reserved")]
+    fn test_main_line_exx_rejected() {
+        let mut cg = reserved();
+        cg.exx();
+    }
+}
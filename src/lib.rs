@@ -37,31 +37,108 @@
 //!
 //! # Module Structure
 //!
+//! - `asset` - Bitmaps and fonts packed for the TMS9918 and HD44780 LCD (PNG loading with feature `png`)
+//! - `bank` - Bank-switched ROMs with generated cross-bank call stubs
 //! - `codegen` - Core emit/label/fixup machinery
+//! - `assembler` - Z80 assembly text assembled with `emit_asm`, and the `z80!` macro (feature `macros`)
 //! - `instructions` - Z80 instruction helpers
+//! - `opcodes` - Opcode table the instruction helpers are generated from, plus a disassembler
+//! - `control` - Structured control-flow builders (if/else, loops, switch, routines)
+//! - `interrupt` - Interrupt handler builders (shadow-bank ISRs)
+//! - `coverage` - Unused-routine and branch reports from emulator runs
+//! - `emulator` - Z80 core, memory map and MC6850 for running generated ROMs in tests (feature `emulator`)
+//! - `object` - Relocatable object files and a linker for them
+//! - `project` - BIOS, application and asset units linked into one ROM image
+//! - `timing` - T-state counts between labels and per-routine timing reports
+//! - `testing` - Golden-master snapshots of ROM bytes and symbols
+//! - `validate` - Undefined labels, out-of-range jumps and redefined labels in one report
 //! - `stdlib::io` - MC6850 serial I/O routines
 //! - `stdlib::terminal` - VT100/ANSI terminal sequences
 //! - `stdlib::math` - Number conversion and math routines
+//! - `stdlib::keyboard` - Debounced key events with autorepeat
+//! - `stdlib::memory` - Block copy, fill, compare and directory sort keys
+//! - `stdlib::string` - strlen, strcmp, strcpy and strcat
+//! - `stdlib::bcd` - Packed BCD arithmetic, conversion and printing
+//! - `stdlib::crc` - CRC-8 and CRC-16/CCITT over buffers, ROM checksum self-check
+//! - `stdlib::fixed` - Signed 8.8 fixed-point arithmetic and printing
+//! - `stdlib::int32` - 32-bit add, subtract, compare, shift and printing
+//! - `stdlib::delay` - Millisecond busy-waits calibrated to the CPU clock
+//! - `stdlib::timer` - Interrupt-driven tick counter, millis and timeouts
+//! - `stdlib::game` - Fixed-tick game loop over the keyboard and timer
+//! - `stdlib::ctc` - Z80 CTC channel setup and IM 2 vectors
+//! - `stdlib::spi` - Bit-banged SPI and SD card block reads
+//! - `stdlib::i2c` - Bit-banged I2C master and DS1307 real-time clock
+//! - `stdlib::vdp` - TMS9918 video registers, VRAM access and text console
+//! - `stdlib::hexload` - Intel HEX loader over the serial console
+//! - `stdlib::cpm` - CP/M 2.2 BIOS jump vector and BDOS call helpers for .COM programs
+//! - `stdlib::api` - Fixed-address firmware jump table and its symbol export
+//! - `stdlib::buildinfo` - Version and build time block, `print_version`
+//! - `stdlib::memtest` - Walking-ones and address-in-address RAM test
+//! - `stdlib::datetime` - Leap years, day of week and time-of-day conversion
+//! - `stdlib::sensor` - Temperature register formatting and I2C sensor maps
+//! - `stdlib::tone` - Cycle-counted square-wave tones and tunes on a port bit
+//! - `stdlib::psg` - AY-3-8910 sound chip registers, tones and note table
+//! - `stdlib::eventlog` - RAM ring of event records with a named dump
+//! - `stdlib::forms` - Labelled terminal input fields edited into RAM
+//! - `stdlib::compress` - LZ-compressed data blobs and the `decompress` routine
+//! - `stdlib::z180` - Z180 instructions and internal I/O (feature `z180`)
+//! - `stdlib::r800` - R800 multiply instructions (feature `r800`)
+//! - `stdlib::z80n` - ZX Spectrum Next Z80N instructions (feature `z80n`)
+//! - `stdlib::undocumented` - SLL, IXH/IXL/IYH/IYL and DD CB copy forms (feature `undocumented`)
+//! - `roms::edit` - Full-screen text editor ROM
+//! - `roms::asm` - Monitor with a ROM-resident line assembler
+//! - `roms::basic` - Tiny BASIC interpreter with user-defined statements
 
+pub mod asset;
+mod assembler;
+pub mod bank;
 mod codegen;
+mod control;
+pub mod coverage;
+#[cfg(feature = "emulator")]
+pub mod emulator;
 mod instructions;
+mod interrupt;
+pub mod object;
+pub mod opcodes;
+pub mod project;
+pub mod roms;
 pub mod stdlib;
+pub mod testing;
+pub mod timing;
+pub mod validate;
 
-pub use codegen::{CodeGen, RomConfig};
+pub use assembler::AsmError;
+pub use codegen::{
+    CodeGen, Codepage, CpuProfile, LinkError, RomConfig, StringError, StringPolicy, TextEncoding, ASCII_FALLBACK, CP437,
+    DEC_GRAPHICS,
+};
+pub use control::{Frame, IfBlock, Regs, Switch};
+pub use instructions::{Alu, Cond, Imm, Reg16, Reg8, Src8};
+#[cfg(feature = "macros")]
+pub use retroshield_z80_macros::z80;
 
 /// Prelude - import this for convenient access to common types
 pub mod prelude {
-    pub use crate::codegen::{CodeGen, RomConfig};
+    pub use crate::codegen::{CodeGen, CpuProfile, RomConfig, StringPolicy, TextEncoding};
+    pub use crate::control::Regs;
+    pub use crate::instructions::{Alu, Cond, Imm, Reg16, Reg8};
 }
 
 /// Convenience extension methods for CodeGen
 impl CodeGen {
     /// Standard RetroShield startup sequence
-    /// Sets up stack pointer and disables interrupts
+    /// Sets up stack pointer and disables interrupts, then zeroes RAM
+    /// variables and copies initialized ones (see `ram_var_init`) from ROM.
+    /// The initialization code itself is appended by `resolve_fixups`;
+    /// without RAM variables the startup is just `DI`, `LD SP,stack_top`
+    /// and a `JP` to the next instruction (7 bytes).
     pub fn emit_startup(&mut self, stack_top: u16) {
         self.label("_start");
         self.di();
         self.ld_sp(stack_top);
+        self.jp("_crt0");
+        self.label("_crt0_done");
     }
 
     /// Load HL with address of a label (for string pointers, etc.)
@@ -82,6 +159,77 @@ impl CodeGen {
         self.fixup(label);
     }
 
+    /// Load IX with address of a label (table pointers)
+    pub fn ld_ix_label(&mut self, label: &str) {
+        self.emit(&[0xDD, 0x21]); // LD IX, nn
+        self.fixup(label);
+    }
+
+    /// Address of RAM variable `name`, checking it holds at least `size` bytes
+    fn ram_var_addr(&self, name: &str, size: u16) -> u16 {
+        let have = self
+            .ram_var_size(name)
+            .unwrap_or_else(|| panic!("{} is not a RAM variable (see ram_var)", name));
+        assert!(have >= size, "RAM variable {} is {} bytes, need {}", name, have, size);
+        self.get_label(name).unwrap()
+    }
+
+    /// Load HL from the 16-bit RAM variable `name` (little-endian)
+    pub fn load_word_var(&mut self, name: &str) {
+        let addr = self.ram_var_addr(name, 2);
+        self.ld_hl_addr(addr);
+    }
+
+    /// Store HL into the 16-bit RAM variable `name` (little-endian)
+    pub fn store_word_var(&mut self, name: &str) {
+        let addr = self.ram_var_addr(name, 2);
+        self.ld_addr_hl(addr);
+    }
+
+    /// Load DE:HL from the 32-bit RAM variable `name` (HL = low word)
+    pub fn load_dword_var(&mut self, name: &str) {
+        let addr = self.ram_var_addr(name, 4);
+        self.ld_hl_addr(addr);
+        self.ld_de_addr(addr + 2);
+    }
+
+    /// Store DE:HL into the 32-bit RAM variable `name` (HL = low word)
+    pub fn store_dword_var(&mut self, name: &str) {
+        let addr = self.ram_var_addr(name, 4);
+        self.ld_addr_hl(addr);
+        self.ld_addr_de(addr + 2);
+    }
+
+    /// Load HL with the address of a label plus `offset`
+    pub fn ld_hl_label_offset(&mut self, label: &str, offset: i32) {
+        self.emit(&[0x21]); // LD HL, nn
+        self.fixup_expr(label, offset);
+    }
+
+    /// Load DE with the address of a label plus `offset`
+    pub fn ld_de_label_offset(&mut self, label: &str, offset: i32) {
+        self.emit(&[0x11]); // LD DE, nn
+        self.fixup_expr(label, offset);
+    }
+
+    /// Load BC with the address of a label plus `offset`
+    pub fn ld_bc_label_offset(&mut self, label: &str, offset: i32) {
+        self.emit(&[0x01]); // LD BC, nn
+        self.fixup_expr(label, offset);
+    }
+
+    /// Load A with the low byte of a label's address
+    pub fn ld_a_label_lo(&mut self, label: &str) {
+        self.emit(&[0x3E]); // LD A, n
+        self.fixup_lo(label);
+    }
+
+    /// Load A with the high byte of a label's address (e.g. for LD I, A)
+    pub fn ld_a_label_hi(&mut self, label: &str) {
+        self.emit(&[0x3E]); // LD A, n
+        self.fixup_hi(label);
+    }
+
     /// Emit a labeled string constant
     pub fn string_const(&mut self, label: &str, s: &str) {
         self.label(label);
@@ -90,7 +238,7 @@ impl CodeGen {
 
     /// Include all standard library routines
     /// This is a convenience method that includes:
-    /// - I/O routines (getchar, putchar, newline, print_string)
+    /// - I/O routines (getchar, putchar, conout, newline, print_string)
     /// - Terminal routines (clear_screen, cursor_pos, etc.)
     /// - Math routines (print_byte_dec, div16, etc.)
     pub fn include_stdlib(&mut self) {
@@ -116,6 +264,68 @@ mod tests {
         assert!(rom.size() > 0);
     }
 
+    #[test]
+    fn test_startup_without_ram() {
+        let mut rom = CodeGen::new();
+        rom.emit_startup(0x3FFF);
+        rom.halt();
+        rom.resolve_fixups();
+        assert_eq!(rom.rom(), &[
+            0xF3,                    // _start: DI
+            0x31, 0xFF, 0x3F,        // LD SP, 0x3FFF
+            0xC3, 0x07, 0x00,        // JP _crt0 (= _crt0_done)
+            0x76,                    // _crt0_done: HALT
+        ]);
+        assert_eq!(rom.get_label("_crt0"), Some(0x0007));
+    }
+
+    #[test]
+    fn test_startup_initializes_ram() {
+        let mut rom = CodeGen::new();
+        rom.emit_startup(0x3FFF);
+        rom.halt();
+        rom.ram_var("buf", 4);
+        rom.ram_var_init("greeting", b"Hi");
+        rom.ram_var_init("count", &[7, 0]);
+        rom.resolve_fixups();
+        assert_eq!(&rom.rom()[0x07..], &[
+            0x76,                    // _crt0_done: HALT
+            0x21, 0x00, 0x20,        // _crt0: LD HL, 0x2000
+            0x36, 0x00,              // LD (HL), 0
+            0x11, 0x01, 0x20,        // LD DE, 0x2001
+            0x01, 0x07, 0x00,        // LD BC, 7
+            0xED, 0xB0,              // LDIR
+            0x21, 0x23, 0x00,        // LD HL, data
+            0x11, 0x04, 0x20,        // LD DE, greeting
+            0x01, 0x04, 0x00,        // LD BC, 4 (greeting + count)
+            0xED, 0xB0,              // LDIR
+            0xC3, 0x07, 0x00,        // JP _crt0_done
+            b'H', b'i', 7, 0,        // data
+        ]);
+    }
+
+    #[test]
+    fn test_word_vars() {
+        let mut rom = CodeGen::new();
+        rom.ram_var("count", 2);
+        rom.ram_var("total", 4);
+        rom.load_word_var("count");
+        rom.store_dword_var("total");
+        assert_eq!(rom.rom(), &[
+            0x2A, 0x00, 0x20,        // LD HL, (count)
+            0x22, 0x02, 0x20,        // LD (total), HL
+            0xED, 0x53, 0x04, 0x20,  // LD (total+2), DE
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "is 2 bytes, need 4")]
+    fn test_dword_var_too_small() {
+        let mut rom = CodeGen::new();
+        rom.ram_var("count", 2);
+        rom.load_dword_var("count");
+    }
+
     #[test]
     fn test_with_stdlib() {
         let mut rom = CodeGen::new();
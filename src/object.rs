@@ -0,0 +1,788 @@
+//! Relocatable object files and a linker
+//!
+//! `CodeGen::into_object` captures unresolved code (bytes, labels and
+//! pending fixups) as an [`Object`], which can be saved to a file and loaded
+//! again, so a stdlib or a driver set can be generated once and linked into
+//! many ROMs. A [`Linker`] lays objects out, resolves the references between
+//! them and produces the same [`RomImage`] as a `RomProject`. A
+//! [`Library`] bundles objects into one file, and
+//! `CodeGen::import_library` copies in just the members a ROM uses.
+//!
+//! ```rust
+//! use retroshield_z80_workbench::prelude::*;
+//! use retroshield_z80_workbench::object::{Linker, Object};
+//!
+//! let mut lib = CodeGen::new();
+//! lib.label("putchar");
+//! lib.out_a(0x81);
+//! lib.ret();
+//! let lib = Object::parse(&lib.into_object("lib").to_string()).unwrap();
+//!
+//! let mut main = CodeGen::new();
+//! main.label("main");
+//! main.ld_a(b'!');
+//! main.call("putchar");
+//! main.halt();
+//!
+//! let mut linker = Linker::new("demo", 0x0000);
+//! linker.add(main.into_object("main"));
+//! linker.add(lib);
+//! let image = linker.link().unwrap();
+//! assert_eq!(image.symbol("putchar"), Some(0x0006));
+//! ```
+//!
+//! Labels inside an object's code move with it; other labels (RAM
+//! variables, `equ` constants) keep their value. Labels not starting with
+//! `_` are exported to the other objects. Only fixups are relocated: an
+//! address emitted as a plain number (such as `ld_hl` of a `get_label`
+//! value) stays where the object was generated, and RAM variables are not
+//! moved, so give objects with RAM separate `RomConfig::ram_start`s.
+
+use std::fmt;
+use std::fs;
+
+use crate::codegen::FixupKind;
+use crate::project::{ProjectError, RomImage, UnitPlacement};
+use crate::CodeGen;
+
+/// A label defined by an object
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// Offset into the object's code if `relocatable`, else the address
+    pub value: u16,
+    /// Whether the label is in the object's code and moves with it
+    pub relocatable: bool,
+}
+
+/// A reference to a label, patched in when linking
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reloc {
+    /// Offset of the placeholder in the object's code
+    pub offset: u16,
+    /// Label referenced
+    pub name: String,
+    /// Added to the label's address
+    pub addend: i32,
+    pub kind: FixupKind,
+}
+
+/// Code generated but not yet placed at an address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Object {
+    /// Object name, used in the map and in errors
+    pub name: String,
+    /// Code, with zeroed placeholders where `relocs` patch in addresses
+    pub code: Vec<u8>,
+    pub symbols: Vec<Symbol>,
+    pub relocs: Vec<Reloc>,
+    /// RAM start and end (exclusive) allocated by the object
+    pub ram: (u16, u16),
+}
+
+impl CodeGen {
+    /// Capture the code as a relocatable object, instead of calling
+    /// `resolve_fixups`
+    ///
+    /// The crt0 and string pool are placed first, as `resolve_fixups` would.
+    pub fn into_object(mut self, name: &str) -> Object {
+        self.finalize();
+        let org = self.config().org;
+        let end = org as u32 + self.size() as u32;
+        let mut symbols: Vec<Symbol> = self
+            .labels()
+            .map(|(label, addr)| {
+                let relocatable = self.ram_var_size(label).is_none()
+                    && !self.is_equ(label)
+                    && (org as u32..=end).contains(&(addr as u32));
+                Symbol {
+                    name: label.to_string(),
+                    value: if relocatable { addr - org } else { addr },
+                    relocatable,
+                }
+            })
+            .collect();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        let relocs = self
+            .fixups()
+            .iter()
+            .map(|(offset, label, addend, kind)| Reloc {
+                offset: *offset as u16,
+                name: label.clone(),
+                addend: *addend,
+                kind: *kind,
+            })
+            .collect();
+        Object {
+            name: name.to_string(),
+            code: self.rom().to_vec(),
+            symbols,
+            relocs,
+            ram: (self.config().ram_start, self.ram_pos()),
+        }
+    }
+}
+
+fn kind_letter(kind: FixupKind) -> char {
+    match kind {
+        FixupKind::Word => 'W',
+        FixupKind::Lo => 'L',
+        FixupKind::Hi => 'H',
+        FixupKind::Rel => 'R',
+    }
+}
+
+/// Text object file format, one record per line:
+///
+/// ```text
+/// Z80OBJ 1
+/// name lib
+/// ram 2000 2000
+/// code D381C9...                   (up to 32 bytes per line)
+/// sym putchar R 0000               (R = relocatable, A = absolute)
+/// reloc 0004 W putchar +0
+/// ```
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Z80OBJ 1")?;
+        writeln!(f, "name {}", self.name)?;
+        writeln!(f, "ram {:04X} {:04X}", self.ram.0, self.ram.1)?;
+        for chunk in self.code.chunks(32) {
+            let hex: String = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+            writeln!(f, "code {}", hex)?;
+        }
+        for s in &self.symbols {
+            let kind = if s.relocatable { 'R' } else { 'A' };
+            writeln!(f, "sym {} {} {:04X}", s.name, kind, s.value)?;
+        }
+        for r in &self.relocs {
+            writeln!(f, "reloc {:04X} {} {} {:+}", r.offset, kind_letter(r.kind), r.name, r.addend)?;
+        }
+        Ok(())
+    }
+}
+
+impl Object {
+    /// Parse the text written by `Display` / `write`
+    pub fn parse(text: &str) -> Result<Object, ProjectError> {
+        let mut object = Object {
+            name: String::new(),
+            code: Vec::new(),
+            symbols: Vec::new(),
+            relocs: Vec::new(),
+            ram: (0, 0),
+        };
+        let mut reloc_lines = Vec::new();
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, "Z80OBJ 1")) => {}
+            _ => {
+                return Err(ProjectError::BadObject {
+                    line: 1,
+                    message: "not a version 1 object file".to_string(),
+                })
+            }
+        }
+        for (i, line) in lines {
+            let bad = |message: &str| ProjectError::BadObject {
+                line: i + 1,
+                message: message.to_string(),
+            };
+            let hex16 = |s: &str| u16::from_str_radix(s, 16).map_err(|_| bad("bad address"));
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => {}
+                ["name", name] => object.name = name.to_string(),
+                ["ram", start, end] => object.ram = (hex16(start)?, hex16(end)?),
+                ["code", hex] => {
+                    if hex.len() % 2 != 0 {
+                        return Err(bad("odd number of hex digits"));
+                    }
+                    for i in (0..hex.len()).step_by(2) {
+                        let byte = hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok());
+                        object.code.push(byte.ok_or_else(|| bad("bad hex byte"))?);
+                    }
+                }
+                ["sym", name, kind @ ("R" | "A"), value] => object.symbols.push(Symbol {
+                    name: name.to_string(),
+                    value: hex16(value)?,
+                    relocatable: *kind == "R",
+                }),
+                ["reloc", offset, kind, name, addend] => {
+                    let kind = match *kind {
+                        "W" => FixupKind::Word,
+                        "L" => FixupKind::Lo,
+                        "H" => FixupKind::Hi,
+                        "R" => FixupKind::Rel,
+                        _ => return Err(bad("bad reloc kind")),
+                    };
+                    reloc_lines.push(i + 1);
+                    object.relocs.push(Reloc {
+                        offset: hex16(offset)?,
+                        name: name.to_string(),
+                        addend: addend.parse().map_err(|_| bad("bad addend"))?,
+                        kind,
+                    });
+                }
+                _ => return Err(bad("unknown record")),
+            }
+        }
+        let width = |kind: FixupKind| if kind == FixupKind::Word { 2 } else { 1 };
+        if let Some((r, line)) = object
+            .relocs
+            .iter()
+            .zip(reloc_lines)
+            .find(|(r, _)| r.offset as usize + width(r.kind) > object.code.len())
+        {
+            return Err(ProjectError::BadObject {
+                line,
+                message: format!("reloc for {} past the end of the code", r.name),
+            });
+        }
+        Ok(object)
+    }
+
+    /// Save the object to a file
+    pub fn write(&self, path: &str) -> Result<(), ProjectError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Load an object saved with `write`
+    pub fn read(path: &str) -> Result<Object, ProjectError> {
+        Object::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Address of symbol `s` with the object placed at `base`
+    fn address(s: &Symbol, base: u16) -> u16 {
+        if s.relocatable {
+            base.wrapping_add(s.value)
+        } else {
+            s.value
+        }
+    }
+}
+
+/// Objects saved together in one file, like a `.lib` archive
+///
+/// `CodeGen::import_library` copies in only the members that define
+/// labels the code still references.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Library {
+    pub members: Vec<Object>,
+}
+
+/// Text library file format: a header line, then each member as an
+/// object file
+///
+/// ```text
+/// Z80LIB 1
+/// Z80OBJ 1
+/// name console
+/// ...
+/// Z80OBJ 1
+/// name disk
+/// ...
+/// ```
+impl fmt::Display for Library {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Z80LIB 1")?;
+        for object in &self.members {
+            write!(f, "{}", object)?;
+        }
+        Ok(())
+    }
+}
+
+impl Library {
+    /// Create an empty library
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a member
+    pub fn add(&mut self, object: Object) -> &mut Self {
+        self.members.push(object);
+        self
+    }
+
+    /// The member named `name`
+    pub fn member(&self, name: &str) -> Option<&Object> {
+        self.members.iter().find(|o| o.name == name)
+    }
+
+    /// Parse the text written by `Display` / `write`
+    pub fn parse(text: &str) -> Result<Library, ProjectError> {
+        let lines: Vec<&str> = text.lines().collect();
+        if lines.first() != Some(&"Z80LIB 1") {
+            return Err(ProjectError::BadObject {
+                line: 1,
+                message: "not a version 1 library file".to_string(),
+            });
+        }
+        let mut starts: Vec<usize> = (1..lines.len()).filter(|&i| lines[i] == "Z80OBJ 1").collect();
+        if let Some(i) = (1..starts.first().copied().unwrap_or(lines.len())).find(|&i| !lines[i].trim().is_empty()) {
+            return Err(ProjectError::BadObject {
+                line: i + 1,
+                message: "expected a Z80OBJ member".to_string(),
+            });
+        }
+        starts.push(lines.len());
+        let mut library = Library::new();
+        for pair in starts.windows(2) {
+            let member = Object::parse(&lines[pair[0]..pair[1]].join("\n")).map_err(|e| match e {
+                ProjectError::BadObject { line, message } => ProjectError::BadObject {
+                    line: line + pair[0],
+                    message,
+                },
+                e => e,
+            })?;
+            library.add(member);
+        }
+        Ok(library)
+    }
+
+    /// Save the library to a file
+    pub fn write(&self, path: &str) -> Result<(), ProjectError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Load a library saved with `write`
+    pub fn read(path: &str) -> Result<Library, ProjectError> {
+        Library::parse(&fs::read_to_string(path)?)
+    }
+}
+
+impl CodeGen {
+    /// Copy an object's code in at the current position
+    ///
+    /// Its public labels and pending references join this generator's, so
+    /// `resolve_fixups` patches them like code emitted here. Labels
+    /// starting with `_` stay private to the object and are renamed.
+    ///
+    /// Fails without changing anything if a public label is already
+    /// defined at another address, or the object's RAM overlaps this
+    /// generator's.
+    pub fn import_object(&mut self, object: &Object) -> Result<(), ProjectError> {
+        let base = self.pos();
+        let (start, end) = (self.config().ram_start, self.ram_pos());
+        let (ram_start, ram_end) = object.ram;
+        if start < end && ram_start < ram_end && start < ram_end && ram_start < end {
+            return Err(ProjectError::RamOverlap {
+                first: "code".to_string(),
+                second: object.name.clone(),
+            });
+        }
+        for s in object.symbols.iter().filter(|s| !s.name.starts_with('_')) {
+            if self.get_label(&s.name).is_some_and(|addr| addr != Object::address(s, base)) {
+                return Err(ProjectError::DuplicateSymbol {
+                    name: s.name.clone(),
+                    first: "code".to_string(),
+                    second: object.name.clone(),
+                });
+            }
+        }
+
+        let renamed: Vec<(&str, String)> = object
+            .symbols
+            .iter()
+            .filter(|s| s.name.starts_with('_'))
+            .map(|s| (s.name.as_str(), self.unique_label(&s.name[1..])))
+            .collect();
+        let name = |label: &str| {
+            renamed
+                .iter()
+                .find(|(old, _)| *old == label)
+                .map_or_else(|| label.to_string(), |(_, new)| new.clone())
+        };
+        let offset = self.size();
+        self.emit(&object.code);
+        for s in &object.symbols {
+            if s.relocatable {
+                self.label_at(&name(&s.name), Object::address(s, base));
+            } else {
+                self.equ(&name(&s.name), Object::address(s, base));
+            }
+        }
+        for r in &object.relocs {
+            self.add_fixup(offset + r.offset as usize, &name(&r.name), r.addend, r.kind);
+        }
+        self.reserve_ram_to(ram_end);
+        Ok(())
+    }
+
+    /// Import the library members that define labels referenced but not
+    /// yet defined, repeating until nothing more is needed
+    ///
+    /// Returns the names of the members imported, in order.
+    pub fn import_library(&mut self, library: &Library) -> Result<Vec<String>, ProjectError> {
+        let mut imported: Vec<String> = Vec::new();
+        loop {
+            let needed = library.members.iter().find(|o| {
+                !imported.contains(&o.name)
+                    && o.symbols.iter().any(|s| {
+                        !s.name.starts_with('_')
+                            && !self.has_label(&s.name)
+                            && self.fixups().iter().any(|(_, label, _, _)| *label == s.name)
+                    })
+            });
+            let Some(object) = needed else {
+                return Ok(imported);
+            };
+            self.import_object(object)?;
+            imported.push(object.name.clone());
+        }
+    }
+}
+
+/// Lays out objects and resolves references between them
+pub struct Linker {
+    name: String,
+    org: u16,
+    /// Objects with their fixed address, if any
+    objects: Vec<(Object, Option<u16>)>,
+}
+
+impl Linker {
+    /// Create a linker for an image named `name`, starting at `org`
+    pub fn new(name: &str, org: u16) -> Self {
+        Self {
+            name: name.to_string(),
+            org,
+            objects: Vec::new(),
+        }
+    }
+
+    /// Add an object, placed right after the previous one
+    pub fn add(&mut self, object: Object) -> &mut Self {
+        self.objects.push((object, None));
+        self
+    }
+
+    /// Add an object at a fixed address (e.g. an RST or interrupt vector)
+    ///
+    /// Objects added later continue after it.
+    pub fn add_at(&mut self, object: Object, addr: u16) -> &mut Self {
+        self.objects.push((object, Some(addr)));
+        self
+    }
+
+    /// Place the objects and resolve every reference
+    pub fn link(self) -> Result<RomImage, ProjectError> {
+        // Layout
+        let mut next = self.org as u32;
+        let mut placed: Vec<(Object, u16)> = Vec::new();
+        for (object, addr) in self.objects {
+            let base = addr.map_or(next, |a| a as u32);
+            next = base + object.code.len() as u32;
+            if next > 0x10000 {
+                return Err(ProjectError::OverBudget {
+                    unit: object.name.clone(),
+                    size: object.code.len(),
+                    budget: (0x10000 - base) as u16,
+                });
+            }
+            placed.push((object, base as u16));
+        }
+        for (i, (a, a_base)) in placed.iter().enumerate() {
+            for (b, b_base) in &placed[i + 1..] {
+                let overlaps = |(s1, e1): (u32, u32), (s2, e2): (u32, u32)| s1 < e2 && s2 < e1;
+                let region = |o: &Object, base: u16| (base as u32, base as u32 + o.code.len() as u32);
+                if overlaps(region(a, *a_base), region(b, *b_base)) {
+                    return Err(ProjectError::Overlap {
+                        first: a.name.clone(),
+                        second: b.name.clone(),
+                    });
+                }
+                if overlaps((a.ram.0 as u32, a.ram.1 as u32), (b.ram.0 as u32, b.ram.1 as u32)) {
+                    return Err(ProjectError::RamOverlap {
+                        first: a.name.clone(),
+                        second: b.name.clone(),
+                    });
+                }
+            }
+        }
+
+        // Exports
+        let mut symbols: Vec<(String, u16, usize)> = Vec::new();
+        for (i, (object, base)) in placed.iter().enumerate() {
+            for s in object.symbols.iter().filter(|s| !s.name.starts_with('_')) {
+                let addr = Object::address(s, *base);
+                match symbols.iter().find(|(l, _, _)| *l == s.name) {
+                    Some((_, a, j)) if *a != addr => {
+                        return Err(ProjectError::DuplicateSymbol {
+                            name: s.name.clone(),
+                            first: placed[*j].0.name.clone(),
+                            second: object.name.clone(),
+                        });
+                    }
+                    Some(_) => {}
+                    None => symbols.push((s.name.clone(), addr, i)),
+                }
+            }
+        }
+
+        // Relocation, own labels first
+        let org = placed.iter().map(|(_, base)| *base).min().unwrap_or(self.org);
+        let end = placed
+            .iter()
+            .map(|(o, base)| *base as usize + o.code.len())
+            .max()
+            .unwrap_or(org as usize);
+        let mut bytes = vec![0xFF; end - org as usize];
+        for (object, base) in &placed {
+            let mut code = object.code.clone();
+            for r in &object.relocs {
+                let addr = match object.symbols.iter().find(|s| s.name == r.name) {
+                    Some(s) => Object::address(s, *base),
+                    None => match symbols.iter().find(|(l, _, _)| *l == r.name) {
+                        Some((_, addr, _)) => *addr,
+                        None => {
+                            return Err(ProjectError::Undefined {
+                                unit: object.name.clone(),
+                                name: r.name.clone(),
+                            })
+                        }
+                    },
+                };
+                let addr = (addr as i32).wrapping_add(r.addend) as u16;
+                let offset = r.offset as usize;
+                match r.kind {
+                    FixupKind::Word => {
+                        code[offset] = addr as u8;
+                        code[offset + 1] = (addr >> 8) as u8;
+                    }
+                    FixupKind::Lo => code[offset] = addr as u8,
+                    FixupKind::Hi => code[offset] = (addr >> 8) as u8,
+                    FixupKind::Rel => {
+                        let disp = addr as i32 - (*base as i32 + offset as i32 + 1);
+                        if !(-128..=127).contains(&disp) {
+                            return Err(ProjectError::OutOfRange {
+                                unit: object.name.clone(),
+                                name: r.name.clone(),
+                            });
+                        }
+                        code[offset] = disp as u8;
+                    }
+                }
+            }
+            let start = (*base - org) as usize;
+            bytes[start..start + code.len()].copy_from_slice(&code);
+        }
+
+        let mut symbols: Vec<(String, u16)> =
+            symbols.into_iter().map(|(label, addr, _)| (label, addr)).collect();
+        symbols.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+
+        let units = placed
+            .iter()
+            .map(|(o, base)| UnitPlacement {
+                name: o.name.clone(),
+                org: *base,
+                size: o.code.len() as u16,
+                budget: o.code.len() as u16,
+                ram: o.ram,
+                flags: Vec::new(),
+            })
+            .collect();
+
+        Ok(RomImage {
+            name: self.name,
+            org,
+            bytes,
+            symbols,
+            units,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RomConfig;
+
+    fn lib_object() -> Object {
+        let mut lib = CodeGen::with_config(RomConfig {
+            ram_start: 0x2800,
+            ..Default::default()
+        });
+        let count = lib.ram_var("count", 1);
+        lib.label("bump");
+        lib.ld_a_addr(count);
+        lib.inc_a();
+        lib.emit_asm("jr nz, _skip").unwrap();
+        lib.dec_a();
+        lib.label("_skip");
+        lib.ld_addr_a(count);
+        lib.jp("done");
+        lib.into_object("lib")
+    }
+
+    #[test]
+    fn test_object_round_trip() {
+        let object = lib_object();
+        assert_eq!(object.ram, (0x2800, 0x2801));
+        assert!(object.symbols.contains(&Symbol {
+            name: "count".to_string(),
+            value: 0x2800,
+            relocatable: false,
+        }));
+        assert!(object.symbols.contains(&Symbol {
+            name: "bump".to_string(),
+            value: 0x0000,
+            relocatable: true,
+        }));
+        assert_eq!(Object::parse(&object.to_string()).unwrap(), object);
+        assert!(matches!(
+            Object::parse("Z80OBJ 1\ncode 3E\nreloc 0001 W x +0"),
+            Err(ProjectError::BadObject { line: 3, .. })
+        ));
+        assert!(matches!(
+            Object::parse("Z80OBJ 1\nname x\nfrob"),
+            Err(ProjectError::BadObject { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_link_objects() {
+        let mut main = CodeGen::new();
+        main.label("main");
+        main.call("bump");
+        main.emit_asm("jr _skip").unwrap();
+        main.label("_skip");
+        main.label("done");
+        main.halt();
+
+        let mut linker = Linker::new("demo", 0x0000);
+        linker.add(main.into_object("main"));
+        linker.add(lib_object());
+        let image = linker.link().unwrap();
+        assert_eq!(image.bytes, [
+            0xCD, 0x06, 0x00,        // CALL bump
+            0x18, 0x00,              // JR _skip (main's own)
+            0x76,                    // done: HALT
+            0x3A, 0x00, 0x28,        // bump: LD A, (count)
+            0x3C,                    // INC A
+            0x20, 0x01,              // JR NZ, _skip (lib's own)
+            0x3D,                    // DEC A
+            0x32, 0x00, 0x28,        // LD (count), A
+            0xC3, 0x05, 0x00,        // JP done
+        ]);
+        assert_eq!(image.symbol("bump"), Some(0x0006));
+        assert_eq!(image.units[1].ram, (0x2800, 0x2801));
+
+        let mut linker = Linker::new("demo", 0x0000);
+        linker.add(lib_object());
+        assert!(matches!(
+            linker.link(),
+            Err(ProjectError::Undefined { unit, name }) if unit == "lib" && name == "done"
+        ));
+
+        let mut linker = Linker::new("demo", 0x0000);
+        linker.add(lib_object());
+        linker.add_at(lib_object(), 0x0004);
+        assert!(matches!(linker.link(), Err(ProjectError::Overlap { .. })));
+    }
+
+    #[test]
+    fn test_link_keeps_equ() {
+        let mut main = CodeGen::new();
+        main.label("main");
+        main.jp("init");
+        main.nop();
+        let mut lib = CodeGen::new();
+        lib.equ("table_len", 4);                      // Inside lib's code range
+        lib.label("init");
+        lib.ld_a_label_lo("table_len");
+        lib.ld_hl_label("table");
+        lib.ret();
+        lib.label("table");
+        lib.emit(&[1, 2, 3, 4, 5, 6]);
+        let lib = lib.into_object("lib");
+        assert!(lib.symbols.contains(&Symbol {
+            name: "table_len".to_string(),
+            value: 4,
+            relocatable: false,
+        }));
+
+        let mut linker = Linker::new("demo", 0x0000);
+        linker.add(main.into_object("main"));
+        linker.add(lib);
+        let image = linker.link().unwrap();
+        assert_eq!(image.symbol("table_len"), Some(4));
+        assert_eq!(image.symbol("table"), Some(0x000A));
+        assert_eq!(&image.bytes[4..10], &[
+            0x3E, 0x04,              // init: LD A, table_len
+            0x21, 0x0A, 0x00,        // LD HL, table
+            0xC9,                    // RET
+        ]);
+    }
+
+    fn library() -> Library {
+        let mut console = CodeGen::new();
+        console.label("putc");
+        console.label("_wait");
+        console.in_a(0x80);
+        console.and_a(0x02);
+        console.jp_z("_wait");
+        console.out_a(0x81);
+        console.ret();
+        let mut disk = CodeGen::new();
+        disk.label("read_sector");
+        disk.call("putc");
+        disk.ret();
+        let mut lib = Library::new();
+        lib.add(console.into_object("console")).add(disk.into_object("disk"));
+        lib
+    }
+
+    #[test]
+    fn test_library_round_trip() {
+        let lib = library();
+        assert_eq!(Library::parse(&lib.to_string()).unwrap(), lib);
+        assert_eq!(lib.member("disk").map(|o| o.code.len()), Some(4));
+        assert!(matches!(
+            Library::parse("Z80LIB 1\nZ80OBJ 1\nname x\nfrob"),
+            Err(ProjectError::BadObject { line: 4, .. })
+        ));
+        assert!(matches!(
+            Library::parse("Z80OBJ 1\nname x"),
+            Err(ProjectError::BadObject { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_import_library() {
+        let mut main = CodeGen::new();
+        main.label("_wait");
+        main.call("putc");
+        main.jr("_wait");
+        assert_eq!(main.import_library(&library()).unwrap(), ["console"]);
+        main.resolve_fixups();
+        assert_eq!(main.rom(), &[
+            0xCD, 0x05, 0x00,        // CALL putc
+            0x18, 0xFB,              // JR _wait (main's own)
+            0xDB, 0x80,              // putc: IN A, (0x80)
+            0xE6, 0x02,              // AND 0x02
+            0xCA, 0x05, 0x00,        // JP Z, _wait (console's own)
+            0xD3, 0x81,              // OUT (0x81), A
+            0xC9,                    // RET
+        ]);
+        assert!(!main.has_label("read_sector"));
+
+        let mut main = CodeGen::new();
+        main.call("read_sector");
+        assert_eq!(main.import_library(&library()).unwrap(), ["disk", "console"]);
+        assert_eq!(main.get_label("putc"), Some(0x0007));
+
+        let mut main = CodeGen::new();
+        main.label("putc");
+        main.ret();
+        assert!(matches!(
+            main.import_object(&library().members[0]),
+            Err(ProjectError::DuplicateSymbol { name, .. }) if name == "putc"
+        ));
+        assert_eq!(main.size(), 1);
+    }
+}
@@ -0,0 +1,315 @@
+//! Declarative Z80 opcode table
+//!
+//! The instruction helpers in `CodeGen` are generated from the same table
+//! exported here as [`OPCODES`], so tools built on it (the disassembler
+//! below, cycle counts, the on-target assembler in `roms::asm`) always agree
+//! with the emitted encodings.
+//!
+//! The disassembler only knows the instructions that have helpers; any other
+//! byte is shown as `DB`.
+
+pub use crate::instructions::OPCODES;
+
+use crate::{CodeGen, CpuProfile};
+
+/// Operand shape of an opcode table entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    /// No operand
+    None,
+    /// 8-bit immediate, written `n` in the syntax
+    Imm8,
+    /// 16-bit immediate or address, written `nn`
+    Imm16,
+    /// Relative jump displacement, written `e`
+    Rel8,
+    /// Bit number 0-7 merged into the last opcode byte, written `b`
+    Bit,
+    /// Signed IX/IY displacement, written `d`
+    Disp,
+    /// Displacement followed by an 8-bit immediate, written `d` then `n`
+    DispImm8,
+}
+
+/// Operand value for an opcode table entry's `emit`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arg<'a> {
+    /// No operand
+    None,
+    Imm8(u8),
+    Imm16(u16),
+    /// Label for a jump, call or address operand (`nn` or `e`)
+    Label(&'a str),
+    Bit(u8),
+    Disp(i8),
+    DispImm8(i8, u8),
+}
+
+/// Panic for an `emit` call whose operand doesn't fit the helper
+pub(crate) fn wrong_arg(helper: &str, arg: Arg) -> ! {
+    panic!("{} cannot take operand {:?}", helper, arg)
+}
+
+/// Opcode tables compiled in: the base set, then the extensions enabled
+/// by features
+pub(crate) fn tables() -> Vec<&'static [Opcode]> {
+    #[allow(unused_mut)]
+    let mut tables = vec![OPCODES];
+    #[cfg(feature = "z180")]
+    tables.push(crate::stdlib::z180::Z180_OPCODES);
+    #[cfg(feature = "r800")]
+    tables.push(crate::stdlib::r800::R800_OPCODES);
+    #[cfg(feature = "z80n")]
+    tables.push(crate::stdlib::z80n::Z80N_OPCODES);
+    #[cfg(feature = "undocumented")]
+    tables.push(crate::stdlib::undocumented::UNDOCUMENTED_OPCODES);
+    tables
+}
+
+/// Look up the base opcode table entry with assembly syntax `syntax`
+pub fn find(syntax: &str) -> Option<&'static Opcode> {
+    OPCODES.iter().find(|op| op.syntax == syntax)
+}
+
+/// One instruction in the opcode table
+#[derive(Clone, Copy, Debug)]
+pub struct Opcode {
+    /// Name of the `CodeGen` helper that emits it, e.g. `"ld_a"`
+    pub helper: &'static str,
+    /// Assembly syntax, e.g. `"LD A,n"`; lowercase marks the operand
+    pub syntax: &'static str,
+    /// Opcode bytes emitted before the operand
+    pub encoding: &'static [u8],
+    /// Operand following the opcode bytes
+    pub operand: Operand,
+    /// T-states (when taken, for conditional branches; per repeat, for
+    /// block instructions)
+    pub cycles: u8,
+    /// T-states when a conditional branch is not taken, or on the last
+    /// pass of a repeating block instruction
+    pub cycles_not_taken: Option<u8>,
+    /// CPU profile the helper requires, for extended instruction sets
+    pub cpu: Option<CpuProfile>,
+    /// Call the helper with `arg`, which must fit its operand; lets code
+    /// that picks instructions by syntax share the helpers' encoding
+    pub emit: for<'a> fn(&mut CodeGen, Arg<'a>),
+    /// Call the helper with fixed operands (the label `sample` for jumps),
+    /// for checking the table against the helpers
+    #[doc(hidden)]
+    pub sample: fn(&mut CodeGen),
+}
+
+impl Opcode {
+    /// Encoded length in bytes, including the operand
+    pub fn size(&self) -> usize {
+        self.encoding.len()
+            + match self.operand {
+                Operand::None | Operand::Bit => 0,
+                Operand::Imm8 | Operand::Rel8 | Operand::Disp => 1,
+                Operand::Imm16 | Operand::DispImm8 => 2,
+            }
+    }
+
+    /// Check whether `bytes` start with this opcode's encoding
+    fn matches(&self, bytes: &[u8]) -> bool {
+        let n = self.encoding.len();
+        if bytes.len() < self.size() {
+            return false;
+        }
+        match self.operand {
+            Operand::Bit => {
+                bytes[..n - 1] == self.encoding[..n - 1]
+                    && bytes[n - 1] & 0xC7 == self.encoding[n - 1] & 0xC7
+            }
+            _ => bytes[..n] == *self.encoding,
+        }
+    }
+}
+
+/// An instruction decoded from ROM bytes
+#[derive(Clone, Copy, Debug)]
+pub struct Decoded {
+    /// Matching opcode table entry
+    pub opcode: &'static Opcode,
+    /// Operand value (bit number, immediate, or raw displacement); for
+    /// `DispImm8` the displacement is the low byte and the immediate the high
+    pub value: u16,
+}
+
+impl Decoded {
+    /// Encoded length in bytes
+    pub fn size(&self) -> usize {
+        self.opcode.size()
+    }
+
+    /// Format as assembly text, resolving relative jumps from `addr`
+    pub fn text(&self, addr: u16) -> String {
+        let syntax = self.opcode.syntax;
+        match self.opcode.operand {
+            Operand::None => syntax.to_string(),
+            Operand::Imm8 => syntax.replacen('n', &format!("0x{:02X}", self.value), 1),
+            Operand::Imm16 => syntax.replacen("nn", &format!("0x{:04X}", self.value), 1),
+            Operand::Rel8 => {
+                let target = addr
+                    .wrapping_add(self.size() as u16)
+                    .wrapping_add(self.value as u8 as i8 as u16);
+                syntax.replacen('e', &format!("0x{:04X}", target), 1)
+            }
+            Operand::Bit => syntax.replacen('b', &self.value.to_string(), 1),
+            Operand::Disp => syntax.replacen("+d", &disp_text(self.value as u8), 1),
+            Operand::DispImm8 => syntax
+                .replacen("+d", &disp_text(self.value as u8), 1)
+                .replacen(",n", &format!(",0x{:02X}", self.value >> 8), 1),
+        }
+    }
+}
+
+/// Format a displacement byte as a signed offset, e.g. `+0x04` or `-0x02`
+fn disp_text(d: u8) -> String {
+    let d = d as i8;
+    if d < 0 {
+        format!("-0x{:02X}", d.unsigned_abs())
+    } else {
+        format!("+0x{:02X}", d)
+    }
+}
+
+/// Decode the instruction at the start of `bytes`
+///
+/// Returns `None` for opcodes not in the table (or truncated input).
+pub fn decode(bytes: &[u8]) -> Option<Decoded> {
+    let opcode = OPCODES.iter().find(|op| op.matches(bytes))?;
+    let n = opcode.encoding.len();
+    let value = match opcode.operand {
+        Operand::None => 0,
+        Operand::Bit => ((bytes[n - 1] >> 3) & 7) as u16,
+        Operand::Imm8 | Operand::Rel8 | Operand::Disp => bytes[n] as u16,
+        Operand::Imm16 | Operand::DispImm8 => u16::from_le_bytes([bytes[n], bytes[n + 1]]),
+    };
+    Some(Decoded { opcode, value })
+}
+
+/// Disassemble `bytes` loaded at `origin` into (address, text) lines
+pub fn disassemble(bytes: &[u8], origin: u16) -> Vec<(u16, String)> {
+    let mut lines = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let addr = origin.wrapping_add(pos as u16);
+        match decode(&bytes[pos..]) {
+            Some(d) => {
+                lines.push((addr, d.text(addr)));
+                pos += d.size();
+            }
+            None => {
+                lines.push((addr, format!("DB 0x{:02X}", bytes[pos])));
+                pos += 1;
+            }
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_matches_helpers() {
+        let ld_a = OPCODES.iter().find(|op| op.syntax == "LD A,n").unwrap();
+        assert_eq!(ld_a.encoding, &[0x3E]);
+        assert_eq!(ld_a.operand, Operand::Imm8);
+
+        let bit = OPCODES.iter().find(|op| op.syntax == "BIT b,A").unwrap();
+        assert_eq!(bit.encoding, &[0xCB, 0x47]);
+        assert_eq!(bit.operand, Operand::Bit);
+    }
+
+    #[test]
+    fn test_cycles() {
+        let djnz = OPCODES.iter().find(|op| op.syntax == "DJNZ e").unwrap();
+        assert_eq!((djnz.cycles, djnz.cycles_not_taken), (13, Some(8)));
+        let ld = OPCODES.iter().find(|op| op.syntax == "LD HL,nn").unwrap();
+        assert_eq!((ld.cycles, ld.cycles_not_taken), (10, None));
+    }
+
+    #[test]
+    fn test_disassemble_roundtrip() {
+        let mut cg = CodeGen::new();
+        cg.label("loop");
+        cg.ld_a(0x0A);
+        cg.bit_a(3);
+        cg.ld_hl(0x1234);
+        cg.djnz("loop");
+        cg.emit(&[0xED]); // Truncated ED prefix
+        let lines = disassemble(cg.rom(), 0);
+        let text: Vec<&str> = lines.iter().map(|(_, t)| t.as_str()).collect();
+        assert_eq!(text, [
+            "LD A,0x0A",
+            "BIT 3,A",
+            "LD HL,0x1234",
+            "DJNZ 0x0000",
+            "DB 0xED",
+        ]);
+        assert_eq!(lines[3].0, 0x0007);
+    }
+
+    #[test]
+    fn test_syntax_tables() {
+        use retroshield_z80_syntax as syntax;
+
+        #[allow(unused_mut)]
+        let mut pairs = vec![("Z80", OPCODES, syntax::Z80)];
+        #[cfg(feature = "z180")]
+        pairs.push(("Z180", crate::stdlib::z180::Z180_OPCODES, syntax::Z180));
+        #[cfg(feature = "r800")]
+        pairs.push(("R800", crate::stdlib::r800::R800_OPCODES, syntax::R800));
+        #[cfg(feature = "z80n")]
+        pairs.push(("Z80N", crate::stdlib::z80n::Z80N_OPCODES, syntax::Z80N));
+        #[cfg(feature = "undocumented")]
+        pairs.push(("UNDOCUMENTED", crate::stdlib::undocumented::UNDOCUMENTED_OPCODES, syntax::UNDOCUMENTED));
+        for (name, table, syntaxes) in pairs {
+            let actual: Vec<&str> = table.iter().map(|op| op.syntax).collect();
+            assert!(actual == syntaxes, "syntax/src/tables.rs is out of date, {} should be:\n{:#?}", name, actual);
+        }
+    }
+
+    #[test]
+    fn test_disassemble_indexed() {
+        let mut cg = CodeGen::new();
+        cg.ld_a_ix_ind(4);
+        cg.ld_iy_ind_n(-2, 0x7F);
+        cg.add_ix_sp();
+        let lines = disassemble(cg.rom(), 0);
+        let text: Vec<&str> = lines.iter().map(|(_, t)| t.as_str()).collect();
+        assert_eq!(text, ["LD A,(IX+0x04)", "LD (IY-0x02),0x7F", "ADD IX,SP"]);
+    }
+
+    #[test]
+    fn test_table_covers_all_helpers() {
+        let mut helpers = std::collections::HashSet::new();
+        for op in tables().into_iter().flatten() {
+            assert!(helpers.insert(op.helper), "{} has two table entries", op.helper);
+
+            // The helper emits exactly the entry's encoding and operand
+            let mut cg = CodeGen::with_config(crate::RomConfig {
+                cpu: op.cpu.unwrap_or_default(),
+                ..Default::default()
+            });
+            cg.label("sample");
+            (op.sample)(&mut cg);
+            let bytes = cg.rom();
+            assert_eq!(&bytes[..op.encoding.len()], op.encoding, "{}", op.helper);
+            assert_eq!(bytes.len(), op.size(), "{}", op.helper);
+        }
+
+        // Everything the base helpers emit disassembles to an entry with
+        // the same encoding
+        for op in OPCODES {
+            let mut cg = CodeGen::new();
+            cg.label("sample");
+            (op.sample)(&mut cg);
+            let decoded = decode(cg.rom()).unwrap_or_else(|| panic!("{} does not decode", op.helper));
+            assert_eq!((decoded.opcode.encoding, decoded.opcode.operand), (op.encoding, op.operand), "{}", op.helper);
+        }
+    }
+}
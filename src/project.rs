@@ -0,0 +1,491 @@
+//! Multi-unit ROM projects
+//!
+//! A [`RomProject`] combines separately generated units (a BIOS, an
+//! application and an optional asset bundle), each placed at its own `org`
+//! with an address budget. `build` links them, checks budgets, overlaps and
+//! symbols, and writes every output file in one step.
+//!
+//! Linking works on labels: every label not starting with `_` is exported,
+//! and each unit can reference the other units' exports as if they were its
+//! own (`call("bios_putchar")` from the application, `ld_hl_label("logo")`
+//! for an asset). Each unit keeps its own string pool and crt0, so give the
+//! units separate RAM areas with `RomConfig::ram_start`.
+//!
+//! ```rust
+//! use retroshield_z80_workbench::prelude::*;
+//! use retroshield_z80_workbench::project::{RomProject, RomUnit};
+//!
+//! let mut bios = CodeGen::new();
+//! bios.emit_startup(0x3FFF);
+//! bios.jp("app_main");
+//! bios.label("bios_putchar");
+//! bios.out_a(0x81);
+//! bios.ret();
+//!
+//! let mut app = CodeGen::with_config(RomConfig {
+//!     org: 0x0100,
+//!     ram_start: 0x2800,
+//!     ..Default::default()
+//! });
+//! app.label("app_main");
+//! app.ld_hl_label("logo");
+//! app.ld_a_hl_ind();
+//! app.call("bios_putchar");
+//! app.halt();
+//!
+//! let project = RomProject {
+//!     name: "demo".to_string(),
+//!     bios: RomUnit::new("bios", bios, 0x0100),
+//!     app: RomUnit::new("app", app, 0x0F00),
+//!     assets: Some(RomUnit::from_assets("assets", 0x1000, 0x1000, &[("logo", b"Z80")])),
+//! };
+//! let image = project.link().unwrap();
+//! assert_eq!(image.symbol("logo"), Some(0x1000));
+//! assert!(image.map().contains("app"));
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::codegen::intel_hex;
+use crate::{CodeGen, LinkError};
+
+/// One separately generated part of a project image
+pub struct RomUnit {
+    /// Unit name, used in the map and in errors
+    pub name: String,
+    /// Generated code, placed at its `org`
+    pub code: CodeGen,
+    /// Maximum size in bytes; the unit owns `org..org + budget`
+    pub budget: u16,
+}
+
+impl RomUnit {
+    /// Create a unit from generated code
+    pub fn new(name: &str, code: CodeGen, budget: u16) -> Self {
+        Self {
+            name: name.to_string(),
+            code,
+            budget,
+        }
+    }
+
+    /// Create an asset bundle at `org`: each blob gets its label and a
+    /// `<label>_end` label just past it
+    pub fn from_assets(name: &str, org: u16, budget: u16, assets: &[(&str, &[u8])]) -> Self {
+        let mut code = CodeGen::with_config(crate::RomConfig {
+            org,
+            ..Default::default()
+        });
+        for (label, bytes) in assets {
+            code.label(label);
+            code.emit(bytes);
+            code.label(&format!("{}_end", label));
+        }
+        Self::new(name, code, budget)
+    }
+
+    /// ROM range owned by the unit
+    fn region(&self) -> (u32, u32) {
+        let org = self.code.config().org as u32;
+        (org, org + self.budget as u32)
+    }
+
+    /// RAM range allocated by the unit
+    fn ram(&self) -> (u32, u32) {
+        (self.code.config().ram_start as u32, self.code.ram_pos() as u32)
+    }
+}
+
+/// BIOS, application and asset units linked into one ROM image
+pub struct RomProject {
+    /// Project name, used for output file names
+    pub name: String,
+    /// Low-level I/O and startup code
+    pub bios: RomUnit,
+    /// Application code
+    pub app: RomUnit,
+    /// Data tables, fonts, text, ...
+    pub assets: Option<RomUnit>,
+}
+
+/// Error found while linking a [`RomProject`]
+#[derive(Debug)]
+pub enum ProjectError {
+    /// A unit outgrew its budget
+    OverBudget { unit: String, size: usize, budget: u16 },
+    /// Two units' ROM regions overlap
+    Overlap { first: String, second: String },
+    /// Two units' RAM areas overlap
+    RamOverlap { first: String, second: String },
+    /// A label is exported by two units at different addresses
+    DuplicateSymbol { name: String, first: String, second: String },
+    /// A unit references a label no unit defines
+    Undefined { unit: String, name: String },
+    /// A bank references a label in another bank without `far_call`
+    CrossBank { unit: String, name: String },
+    /// A relative jump can't reach a label in another unit
+    OutOfRange { unit: String, name: String },
+    /// An object file could not be parsed
+    BadObject { line: usize, message: String },
+    /// Writing an output file failed
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectError::OverBudget { unit, size, budget } => {
+                write!(f, "{} is {} bytes, over its budget of {}", unit, size, budget)
+            }
+            ProjectError::Overlap { first, second } => {
+                write!(f, "{} and {} overlap in ROM", first, second)
+            }
+            ProjectError::RamOverlap { first, second } => {
+                write!(f, "{} and {} overlap in RAM", first, second)
+            }
+            ProjectError::DuplicateSymbol { name, first, second } => {
+                write!(f, "{} is defined by both {} and {}", name, first, second)
+            }
+            ProjectError::Undefined { unit, name } => {
+                write!(f, "{} references undefined label {}", unit, name)
+            }
+            ProjectError::CrossBank { unit, name } => {
+                write!(f, "{} references {} in another bank; use far_call", unit, name)
+            }
+            ProjectError::OutOfRange { unit, name } => {
+                write!(f, "{} has a relative jump to {} out of range", unit, name)
+            }
+            ProjectError::BadObject { line, message } => {
+                write!(f, "object file line {}: {}", line, message)
+            }
+            ProjectError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProjectError {}
+
+impl From<LinkError> for ProjectError {
+    fn from(e: LinkError) -> Self {
+        match e {
+            LinkError::DuplicateSymbol { name, first, second } => ProjectError::DuplicateSymbol { name, first, second },
+            LinkError::RamOverlap { first, second } => ProjectError::RamOverlap { first, second },
+        }
+    }
+}
+
+impl From<std::io::Error> for ProjectError {
+    fn from(e: std::io::Error) -> Self {
+        ProjectError::Io(e)
+    }
+}
+
+/// Placement of a unit in a linked image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnitPlacement {
+    pub name: String,
+    pub org: u16,
+    pub size: u16,
+    pub budget: u16,
+    /// RAM start and end (exclusive)
+    pub ram: (u16, u16),
+    /// Build flags the unit was generated with (`CodeGen::set_flag`)
+    pub flags: Vec<String>,
+}
+
+/// A linked project image
+#[derive(Clone, Debug)]
+pub struct RomImage {
+    /// Project name
+    pub name: String,
+    /// Address of the first image byte
+    pub org: u16,
+    /// Image bytes; gaps between units are filled with 0xFF
+    pub bytes: Vec<u8>,
+    /// Exported labels of all units, by address
+    pub symbols: Vec<(String, u16)>,
+    /// Where each unit landed
+    pub units: Vec<UnitPlacement>,
+}
+
+impl RomProject {
+    /// Link the units into one image, checking budgets and symbols
+    pub fn link(self) -> Result<RomImage, ProjectError> {
+        let RomProject {
+            name,
+            bios,
+            app,
+            assets,
+        } = self;
+        let mut units: Vec<RomUnit> = vec![bios, app];
+        units.extend(assets);
+
+        for (i, a) in units.iter().enumerate() {
+            for b in &units[i + 1..] {
+                let overlaps = |(s1, e1): (u32, u32), (s2, e2): (u32, u32)| s1 < e2 && s2 < e1;
+                if overlaps(a.region(), b.region()) {
+                    return Err(ProjectError::Overlap {
+                        first: a.name.clone(),
+                        second: b.name.clone(),
+                    });
+                }
+                if overlaps(a.ram(), b.ram()) {
+                    return Err(ProjectError::RamOverlap {
+                        first: a.name.clone(),
+                        second: b.name.clone(),
+                    });
+                }
+            }
+        }
+
+        // Exports of every unit, before any cross-unit imports
+        let mut symbols: Vec<(String, u16, usize)> = Vec::new();
+        for (i, unit) in units.iter().enumerate() {
+            for (label, addr) in unit.code.labels() {
+                if label.starts_with('_') {
+                    continue;
+                }
+                match symbols.iter().find(|(l, _, _)| l == label) {
+                    Some((_, a, j)) if *a != addr => {
+                        return Err(ProjectError::DuplicateSymbol {
+                            name: label.to_string(),
+                            first: units[*j].name.clone(),
+                            second: unit.name.clone(),
+                        });
+                    }
+                    Some(_) => {}
+                    None => symbols.push((label.to_string(), addr, i)),
+                }
+            }
+        }
+
+        for unit in &mut units {
+            for (label, addr, _) in &symbols {
+                if !unit.code.has_label(label) {
+                    unit.code.equ(label, *addr);
+                }
+            }
+            if let Some(missing) = unit.code.undefined_labels().into_iter().next() {
+                return Err(ProjectError::Undefined {
+                    unit: unit.name.clone(),
+                    name: missing,
+                });
+            }
+            unit.code.resolve_fixups();
+            if unit.code.size() > unit.budget as usize {
+                return Err(ProjectError::OverBudget {
+                    unit: unit.name.clone(),
+                    size: unit.code.size(),
+                    budget: unit.budget,
+                });
+            }
+        }
+
+        let org = units.iter().map(|u| u.code.config().org).min().unwrap_or(0);
+        let end = units
+            .iter()
+            .map(|u| u.code.config().org as usize + u.code.size())
+            .max()
+            .unwrap_or(org as usize);
+        let mut bytes = vec![0xFF; end - org as usize];
+        for unit in &units {
+            let start = (unit.code.config().org - org) as usize;
+            bytes[start..start + unit.code.size()].copy_from_slice(unit.code.rom());
+        }
+
+        let mut symbols: Vec<(String, u16)> =
+            symbols.into_iter().map(|(label, addr, _)| (label, addr)).collect();
+        symbols.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+
+        let units = units
+            .iter()
+            .map(|u| UnitPlacement {
+                name: u.name.clone(),
+                org: u.code.config().org,
+                size: u.code.size() as u16,
+                budget: u.budget,
+                ram: (u.code.config().ram_start, u.code.ram_pos()),
+                flags: u.code.flags().map(String::from).collect(),
+            })
+            .collect();
+
+        Ok(RomImage {
+            name,
+            org,
+            bytes,
+            symbols,
+            units,
+        })
+    }
+
+    /// Link the project and write `<name>.bin`, `.hex`, `.sym`, `.map` and
+    /// the Arduino header `<name>.h` into `out_dir`
+    pub fn build(self, out_dir: &str) -> Result<RomImage, ProjectError> {
+        let image = self.link()?;
+        image.write_files(out_dir)?;
+        Ok(image)
+    }
+}
+
+impl RomImage {
+    /// Write `<name>.bin`, `.hex`, `.sym`, `.map` and the Arduino header
+    /// `<name>.h` into `out_dir`
+    pub fn write_files(&self, out_dir: &str) -> Result<(), ProjectError> {
+        let dir = Path::new(out_dir);
+        fs::create_dir_all(dir)?;
+        let path = |ext: &str| dir.join(format!("{}.{}", self.name, ext));
+        fs::write(path("bin"), &self.bytes)?;
+        fs::write(path("hex"), intel_hex(&self.bytes, self.org))?;
+        fs::write(path("sym"), self.symbol_file())?;
+        fs::write(path("map"), self.map())?;
+        fs::write(path("h"), self.arduino_header())?;
+        Ok(())
+    }
+
+    /// Look up an exported label
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.iter().find(|(l, _)| l == name).map(|&(_, a)| a)
+    }
+
+    /// Symbol file: one `ADDR label` line per export
+    pub fn symbol_file(&self) -> String {
+        self.symbols
+            .iter()
+            .map(|(label, addr)| format!("{:04X} {}\n", addr, label))
+            .collect()
+    }
+
+    /// Memory map: ROM use against budget, RAM areas and build flags, per
+    /// unit
+    pub fn map(&self) -> String {
+        let mut out = format!("{}\n\nUnit        Start  End    Used   Budget Free\n", self.name);
+        for u in &self.units {
+            out.push_str(&format!(
+                "{:<11} {:04X}   {:04X}   {:>5}  {:>5}  {:>5}\n",
+                u.name,
+                u.org,
+                u.org as u32 + u.size as u32,
+                u.size,
+                u.budget,
+                u.budget - u.size
+            ));
+        }
+        let ram: Vec<_> = self.units.iter().filter(|u| u.ram.1 > u.ram.0).collect();
+        if !ram.is_empty() {
+            out.push_str("\nUnit        RAM    End\n");
+        }
+        for u in ram {
+            out.push_str(&format!("{:<11} {:04X}   {:04X}\n", u.name, u.ram.0, u.ram.1));
+        }
+        let flagged: Vec<_> = self.units.iter().filter(|u| !u.flags.is_empty()).collect();
+        if !flagged.is_empty() {
+            out.push_str("\nUnit        Flags\n");
+        }
+        for u in flagged {
+            out.push_str(&format!("{:<11} {}\n", u.name, u.flags.join(", ")));
+        }
+        out
+    }
+
+    /// Arduino header holding the image as a `PROGMEM` array
+    pub fn arduino_header(&self) -> String {
+        let guard = format!("{}_H", self.name.to_uppercase().replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+        let mut out = format!(
+            "// {} ROM image, generated by retroshield-z80-workbench\n\
+             #ifndef {guard}\n#define {guard}\n\n\
+             #define ROM_START 0x{:04X}\n#define ROM_SIZE {}\n\n\
+             const unsigned char rom_bin[] PROGMEM = {{\n",
+            self.name,
+            self.org,
+            self.bytes.len(),
+            guard = guard
+        );
+        for chunk in self.bytes.chunks(16) {
+            let line: Vec<String> = chunk.iter().map(|b| format!("0x{:02X}", b)).collect();
+            out.push_str(&format!("  {},\n", line.join(", ")));
+        }
+        out.push_str(&format!("}};\n\n#endif // {}\n", guard));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RomConfig;
+
+    fn unit_at(org: u16, ram_start: u16) -> CodeGen {
+        CodeGen::with_config(RomConfig {
+            org,
+            ram_start,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_link_cross_references() {
+        let mut bios = unit_at(0x0000, 0x2000);
+        bios.jp("app_main");
+        bios.label("bios_ret");
+        bios.ret();
+        let mut app = unit_at(0x0010, 0x2100);
+        app.set_flag("debug");
+        app.label("app_main");
+        app.call("bios_ret");
+        app.ld_hl_label("font");
+        let project = RomProject {
+            name: "test".to_string(),
+            bios: RomUnit::new("bios", bios, 0x10),
+            app: RomUnit::new("app", app, 0x10),
+            assets: Some(RomUnit::from_assets("assets", 0x0020, 0x10, &[("font", &[1, 2])])),
+        };
+        let image = project.link().unwrap();
+        assert_eq!(&image.bytes[..4], &[
+            0xC3, 0x10, 0x00,        // JP app_main
+            0xC9,                    // RET
+        ]);
+        assert_eq!(image.bytes[4], 0xFF);
+        assert_eq!(&image.bytes[0x10..0x16], &[
+            0xCD, 0x03, 0x00,        // CALL bios_ret
+            0x21, 0x20, 0x00,        // LD HL, font
+        ]);
+        assert_eq!(&image.bytes[0x20..], &[1, 2]);
+        assert_eq!(image.symbol("font_end"), Some(0x0022));
+        assert!(image.symbol_file().contains("0003 bios_ret\n"));
+        assert!(image.arduino_header().contains("0xC3, 0x10, 0x00, 0xC9, 0xFF,"));
+        assert!(image.map().ends_with("Unit        Flags\napp         debug\n"));
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let project = |bios_budget: u16, app_org: u16, app_ram: u16, app_label: &str| {
+            let mut bios = unit_at(0x0000, 0x2000);
+            bios.ram_var("bios_var", 4);
+            bios.label("shared");
+            bios.emit(&[0; 8]);
+            let mut app = unit_at(app_org, app_ram);
+            app.ram_var("app_var", 2);
+            app.label(app_label);
+            app.call("missing_or_shared");
+            RomProject {
+                name: "test".to_string(),
+                bios: RomUnit::new("bios", bios, bios_budget),
+                app: RomUnit::new("app", app, 0x100),
+                assets: None,
+            }
+            .link()
+        };
+        let err = project(4, 0x0100, 0x2100, "a").unwrap_err();
+        assert!(matches!(err, ProjectError::OverBudget { size: 8, budget: 4, .. }));
+        let err = project(0x200, 0x0100, 0x2100, "a").unwrap_err();
+        assert!(matches!(err, ProjectError::Overlap { .. }));
+        let err = project(0x100, 0x0100, 0x2002, "a").unwrap_err();
+        assert!(matches!(err, ProjectError::RamOverlap { .. }));
+        let err = project(0x100, 0x0100, 0x2100, "shared").unwrap_err();
+        assert!(matches!(err, ProjectError::DuplicateSymbol { .. }));
+        let err = project(0x100, 0x0100, 0x2100, "a").unwrap_err();
+        assert_eq!(err.to_string(), "app references undefined label missing_or_shared");
+    }
+}
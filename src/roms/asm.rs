@@ -0,0 +1,549 @@
+//! ROM-resident line assembler
+//!
+//! Implements the classic monitor "A addr" command: each input line is
+//! assembled straight into memory at the current address. The mnemonic and
+//! encoding tables are generated from [`OPCODES`], the same table that
+//! drives the `CodeGen` instruction helpers.
+//!
+//! Syntax follows the table (`LD A,(nn)`, `JR NZ,e`, `BIT b,A`, ...):
+//! input is case-insensitive, extra spaces are ignored, and numbers are hex
+//! that must start with a digit (`0FF`, optionally `0FFH`). Relative jumps
+//! take the absolute target address. Index displacements are a raw byte
+//! (`LD A,(IX+0FE)` is IX-2), and `LD (IX+d),n` is not supported since the
+//! assembler parses a single operand per line.
+
+use crate::opcodes::{Operand, OPCODES};
+use crate::CodeGen;
+
+/// Longest accepted input line
+const LINE_MAX: u8 = 40;
+
+/// Pattern placeholder bytes in the ROM table
+const PH_IMM8: u8 = 1;
+const PH_IMM16: u8 = 2;
+const PH_REL: u8 = 3;
+const PH_BIT: u8 = 4;
+
+/// Encode the opcode table for the on-target assembler
+///
+/// Each entry is the syntax with its operand replaced by a placeholder byte,
+/// a 0 terminator, then the opcode length and bytes. An empty pattern ends
+/// the table. Entries whose syntax repeats (e.g. `jp` and `jp_addr`) are
+/// emitted once; two-operand entries (`LD (IX+d),n`) are left out.
+pub fn table_bytes() -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut seen = Vec::new();
+    for op in OPCODES {
+        if seen.contains(&op.syntax) {
+            continue;
+        }
+        seen.push(op.syntax);
+
+        let placeholder = match op.operand {
+            Operand::None => 0,
+            Operand::Imm8 | Operand::Disp => PH_IMM8,
+            Operand::Imm16 => PH_IMM16,
+            Operand::Rel8 => PH_REL,
+            Operand::Bit => PH_BIT,
+            Operand::DispImm8 => continue,
+        };
+        let mut in_operand = false;
+        for c in op.syntax.bytes() {
+            if c.is_ascii_lowercase() {
+                if !in_operand {
+                    out.push(placeholder);
+                    in_operand = true;
+                }
+            } else {
+                out.push(c);
+                in_operand = false;
+            }
+        }
+        out.push(0);
+        out.push(op.encoding.len() as u8);
+        out.extend_from_slice(op.encoding);
+    }
+    out.push(0);
+    out
+}
+
+/// Build a minimal monitor ROM with `A addr` (assemble) and `G addr` (call)
+pub fn build() -> CodeGen {
+    let mut rom = CodeGen::new();
+    let stack_top = rom.config().stack_top;
+    rom.emit_startup(stack_top);
+
+    rom.label("monitor");
+    rom.call("newline");
+    rom.ld_a(b'>');
+    rom.call("conout");
+    rom.call("asm_readline");
+    rom.call("asm_normalize");
+    rom.ld_hl_label("asm_buf");
+    rom.ld_a_hl_ind();
+    rom.cp(b'A');
+    rom.jp_z("monitor_a");
+    rom.cp(b'G');
+    rom.jp_z("monitor_g");
+    rom.or_a_a();
+    rom.jp_z("monitor");
+    rom.label("monitor_err");
+    rom.ld_a(b'?');
+    rom.call("conout");
+    rom.jp("monitor");
+
+    // A addr: assemble from addr until an empty line
+    rom.label("monitor_a");
+    rom.call("monitor_arg");
+    rom.jp_c("monitor_err");
+    rom.call("asm_command");
+    rom.jp("monitor");
+
+    // G addr: call addr, returning to the monitor
+    rom.label("monitor_g");
+    rom.call("monitor_arg");
+    rom.jp_c("monitor_err");
+    rom.call("newline");
+    rom.ld_de_label("monitor");
+    rom.emit(&[0xD5]);          // PUSH DE (return address)
+    rom.jp_hl();
+
+    // monitor_arg: parse "X addr" at HL -> HL = addr, C on error
+    rom.label("monitor_arg");
+    rom.inc_hl();
+    rom.ld_a_hl_ind();
+    rom.cp(b' ');
+    rom.emit(&[0x20, 0x01]);    // JR NZ, +1
+    rom.inc_hl();
+    rom.call("asm_parse_hex");
+    rom.ret_c();
+    rom.ld_a_hl_ind();
+    rom.or_a_a();
+    rom.scf();
+    rom.ret_nz();               // Trailing junk
+    rom.emit(&[0x60]);          // LD H, B
+    rom.emit(&[0x69]);          // LD L, C
+    rom.or_a_a();               // Clear carry
+    rom.ret();
+
+    rom.emit_line_assembler();
+    rom.include_stdlib();
+    rom.resolve_fixups();
+    rom
+}
+
+impl CodeGen {
+    /// Emit the line assembler routines and opcode table
+    ///
+    /// - `asm_command`: interactive loop assembling at HL; prints the
+    ///   address, reads a line, stores the bytes. An empty line returns,
+    ///   an unrecognised line prints `?`.
+    /// - `asm_assemble`: assemble the normalized line in `asm_buf` at
+    ///   `asm_pc`, advancing it. Carry set on error.
+    /// - `asm_readline` / `asm_normalize`: line input into `asm_buf`,
+    ///   then uppercase and canonical spacing.
+    /// - `asm_parse_hex`: hex number at HL -> BC, HL advanced, C if none.
+    ///
+    /// Labels created: `asm_command`, `asm_assemble`, `asm_readline`,
+    /// `asm_normalize`, `asm_parse_hex`, `asm_hexdigit`, `asm_print_word`,
+    /// `asm_print_hex8`, `asm_table`
+    /// RAM used: `asm_pc`, `asm_val`, `asm_kind`, `asm_buf`
+    /// Requires: `getchar`, `conout`, `newline`
+    pub fn emit_line_assembler(&mut self) {
+        let asm_pc = self.ram_var("asm_pc", 2);
+        let asm_val = self.ram_var("asm_val", 2);
+        let asm_kind = self.ram_var("asm_kind", 1);
+        let asm_buf = self.ram_var("asm_buf", LINE_MAX as u16 + 1);
+
+        // asm_command: "A addr" loop, HL = start address
+        self.label("asm_command");
+        self.ld_addr_hl(asm_pc);
+        self.label("asm_command_loop");
+        self.call("newline");
+        self.ld_hl_addr(asm_pc);
+        self.call("asm_print_word");
+        self.ld_a(b':');
+        self.call("conout");
+        self.ld_a(b' ');
+        self.call("conout");
+        self.call("asm_readline");
+        self.call("asm_normalize");
+        self.ld_a_addr(asm_buf);
+        self.or_a_a();
+        self.ret_z();               // Empty line ends the command
+        self.call("asm_assemble");
+        self.jr_nc("asm_command_loop");
+        self.ld_a(b'?');
+        self.call("conout");
+        self.jr("asm_command_loop");
+
+        // asm_assemble: match asm_buf against each table pattern
+        self.label("asm_assemble");
+        self.ld_hl_label("asm_table");
+        self.label("asm_entry");
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z("asm_fail");      // End of table
+        self.ld_de(asm_buf);
+        self.xor_a();
+        self.ld_addr_a(asm_kind);
+        self.label("asm_cmp");
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z("asm_pat_end");
+        self.cp(PH_BIT + 1);
+        self.jp_c("asm_operand");
+        self.ld_b_a();
+        self.emit(&[0x1A]);         // LD A, (DE)
+        self.cp_b();
+        self.jp_nz("asm_skip");
+        self.inc_hl();
+        self.inc_de();
+        self.jr("asm_cmp");
+
+        // Placeholder: parse a number from the input
+        self.label("asm_operand");
+        self.ld_addr_a(asm_kind);
+        self.push_hl();
+        self.ex_de_hl();
+        self.call("asm_parse_hex");
+        self.ex_de_hl();
+        self.pop_hl();
+        self.jp_c("asm_skip");
+        self.emit(&[0xED, 0x43]);   // LD (asm_val), BC
+        self.emit_word(asm_val);
+        self.inc_hl();
+        self.jr("asm_cmp");
+
+        // Pattern consumed: the input must be too
+        self.label("asm_pat_end");
+        self.emit(&[0x1A]);         // LD A, (DE)
+        self.or_a_a();
+        self.inc_hl();              // HL = opcode length
+        self.jp_z("asm_emit");
+        self.dec_hl();
+
+        // No match: skip rest of pattern and the encoding
+        self.label("asm_skip");
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.or_a_a();
+        self.jr_nz("asm_skip");
+        self.emit(&[0x4E]);         // LD C, (HL)
+        self.inc_hl();
+        self.ld_b(0);
+        self.add_hl_bc();
+        self.jr("asm_entry");
+
+        // Match: copy opcode bytes to asm_pc, then the operand
+        self.label("asm_emit");
+        self.emit(&[0x46]);         // LD B, (HL)
+        self.inc_hl();
+        self.ld_de_addr(asm_pc);
+        self.label("asm_emit_loop");
+        self.ld_a_hl_ind();
+        self.emit(&[0x12]);         // LD (DE), A
+        self.inc_hl();
+        self.inc_de();
+        self.djnz("asm_emit_loop");
+
+        self.ld_a_addr(asm_kind);
+        self.cp(PH_IMM8);
+        self.jp_z("asm_emit_imm8");
+        self.cp(PH_IMM16);
+        self.jp_z("asm_emit_imm16");
+        self.cp(PH_REL);
+        self.jp_z("asm_emit_rel");
+        self.cp(PH_BIT);
+        self.jp_nz("asm_done");
+
+        // Bit number goes into bits 3-5 of the last opcode byte
+        self.ld_a_addr(asm_val);
+        self.and_a(7);
+        self.rlca();
+        self.rlca();
+        self.rlca();
+        self.dec_de();
+        self.ex_de_hl();
+        self.emit(&[0xB6]);         // OR (HL)
+        self.ld_hl_ind_a();
+        self.ex_de_hl();
+        self.inc_de();
+        self.jp("asm_done");
+
+        self.label("asm_emit_imm16");
+        self.ld_a_addr(asm_val);
+        self.emit(&[0x12]);         // LD (DE), A
+        self.inc_de();
+        self.ld_a_addr(asm_val + 1);
+        self.emit(&[0x12]);         // LD (DE), A
+        self.inc_de();
+        self.jp("asm_done");
+
+        self.label("asm_emit_rel");
+        self.ld_hl_addr(asm_val);
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.dec_hl();              // HL = target - (addr of offset + 1)
+        self.emit(&[0x7D]);         // LD A, L
+        self.rla();                 // Carry = sign of L
+        self.emit(&[0x7C]);         // LD A, H
+        self.adc_a(0);              // Zero if H sign-extends L
+        self.jp_nz("asm_fail");
+        self.emit(&[0x7D]);         // LD A, L
+        self.jp("asm_emit_byte");
+
+        self.label("asm_emit_imm8");
+        self.ld_a_addr(asm_val);
+        self.label("asm_emit_byte");
+        self.emit(&[0x12]);         // LD (DE), A
+        self.inc_de();
+
+        self.label("asm_done");
+        self.ld_addr_de(asm_pc);
+        self.or_a_a();              // Clear carry: success
+        self.ret();
+
+        self.label("asm_fail");
+        self.scf();
+        self.ret();
+
+        // asm_parse_hex: HL = text -> BC = value, HL past the number
+        // Numbers must start with 0-9; an 'H' suffix is skipped. C if none.
+        self.label("asm_parse_hex");
+        self.ld_bc(0);
+        self.ld_a_hl_ind();
+        self.cp(b'9' + 1);
+        self.ccf();
+        self.ret_c();               // Starts above '9': not a number
+        self.call("asm_hexdigit");
+        self.ret_c();
+        self.label("asm_parse_hex_loop");
+        self.emit(&[0xCB, 0x21]);   // SLA C
+        self.emit(&[0xCB, 0x10]);   // RL B
+        self.emit(&[0xCB, 0x21]);   // SLA C
+        self.emit(&[0xCB, 0x10]);   // RL B
+        self.emit(&[0xCB, 0x21]);   // SLA C
+        self.emit(&[0xCB, 0x10]);   // RL B
+        self.emit(&[0xCB, 0x21]);   // SLA C
+        self.emit(&[0xCB, 0x10]);   // RL B
+        self.emit(&[0xB1]);         // OR C
+        self.ld_c_a();
+        self.inc_hl();
+        self.ld_a_hl_ind();
+        self.call("asm_hexdigit");
+        self.jr_nc("asm_parse_hex_loop");
+        self.ld_a_hl_ind();
+        self.cp(b'H');
+        self.emit(&[0x20, 0x01]);   // JR NZ, +1
+        self.inc_hl();
+        self.or_a_a();
+        self.ret();
+
+        // asm_hexdigit: A = ASCII hex digit -> A = value, C if not a digit
+        self.label("asm_hexdigit");
+        self.sub_a(b'0');
+        self.ret_c();
+        self.cp(10);
+        self.ccf();
+        self.ret_nc();              // 0-9
+        self.sub_a(b'A' - b'0');
+        self.ret_c();
+        self.cp(6);
+        self.ccf();
+        self.ret_c();               // Past 'F'
+        self.add_a(10);
+        self.ret();
+
+        // asm_readline: read a line into asm_buf with echo and backspace
+        self.label("asm_readline");
+        self.ld_hl(asm_buf);
+        self.ld_b(0);
+        self.label("asm_readline_loop");
+        self.call("getchar");
+        self.cp(0x0D);
+        self.jp_z("asm_readline_done");
+        self.cp(0x08);
+        self.jp_z("asm_readline_bs");
+        self.cp(0x7F);
+        self.jp_z("asm_readline_bs");
+        self.cp(b' ');
+        self.jr_c("asm_readline_loop");
+        self.ld_c_a();
+        self.ld_a_b();
+        self.cp(LINE_MAX);
+        self.jr_nc("asm_readline_loop"); // Buffer full
+        self.ld_a_c();
+        self.ld_hl_ind_a();
+        self.inc_hl();
+        self.inc_b();
+        self.call("conout");
+        self.jr("asm_readline_loop");
+        self.label("asm_readline_bs");
+        self.ld_a_b();
+        self.or_a_a();
+        self.jr_z("asm_readline_loop");
+        self.dec_hl();
+        self.dec_b();
+        self.ld_a(0x08);
+        self.call("conout");
+        self.ld_a(b' ');
+        self.call("conout");
+        self.ld_a(0x08);
+        self.call("conout");
+        self.jr("asm_readline_loop");
+        self.label("asm_readline_done");
+        self.emit(&[0x36, 0x00]);   // LD (HL), 0
+        self.ret();
+
+        // asm_normalize: uppercase asm_buf, drop leading/trailing spaces,
+        // collapse runs of spaces and remove spaces around commas
+        self.label("asm_normalize");
+        self.ld_hl(asm_buf);
+        self.emit(&[0x54]);         // LD D, H
+        self.emit(&[0x5D]);         // LD E, L
+        self.ld_c(0);               // C = last stored char (0 = none yet)
+        self.label("asm_norm_loop");
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.or_a_a();
+        self.jp_z("asm_norm_end");
+        self.cp(b' ');
+        self.jp_nz("asm_norm_char");
+        self.ld_a_c();
+        self.or_a_a();
+        self.jr_z("asm_norm_loop"); // Leading space
+        self.cp(b' ');
+        self.jr_z("asm_norm_loop"); // Repeated space
+        self.cp(b',');
+        self.jr_z("asm_norm_loop"); // Space after comma
+        self.ld_a(b' ');
+        self.jp("asm_norm_store");
+        self.label("asm_norm_char");
+        self.cp(b',');
+        self.jp_nz("asm_norm_upper");
+        self.ld_a_c();
+        self.cp(b' ');
+        self.ld_a(b',');
+        self.jp_nz("asm_norm_store");
+        self.dec_de();              // Overwrite space before comma
+        self.jp("asm_norm_store");
+        self.label("asm_norm_upper");
+        self.cp(b'a');
+        self.jp_c("asm_norm_store");
+        self.cp(b'z' + 1);
+        self.jp_nc("asm_norm_store");
+        self.sub_a(0x20);
+        self.label("asm_norm_store");
+        self.emit(&[0x12]);         // LD (DE), A
+        self.inc_de();
+        self.ld_c_a();
+        self.jr("asm_norm_loop");
+        self.label("asm_norm_end");
+        self.ld_a_c();
+        self.cp(b' ');
+        self.emit(&[0x20, 0x01]);   // JR NZ, +1
+        self.dec_de();              // Trailing space
+        self.xor_a();
+        self.emit(&[0x12]);         // LD (DE), A
+        self.ret();
+
+        // asm_print_word: print HL as 4 hex digits
+        self.label("asm_print_word");
+        self.emit(&[0x7C]);         // LD A, H
+        self.call("asm_print_hex8");
+        self.emit(&[0x7D]);         // LD A, L
+        self.label("asm_print_hex8");
+        self.push_af();
+        self.rrca();
+        self.rrca();
+        self.rrca();
+        self.rrca();
+        self.call("asm_print_nibble");
+        self.pop_af();
+        self.label("asm_print_nibble");
+        self.and_a(0x0F);
+        self.add_a(b'0');
+        self.cp(b'9' + 1);
+        self.emit(&[0x38, 0x02]);   // JR C, +2
+        self.add_a(7);
+        self.jp("conout");
+
+        self.label("asm_table");
+        self.emit(&table_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_bytes() {
+        let table = table_bytes();
+        // First entry: "LD A,n" -> pattern, terminator, length, opcode
+        assert_eq!(&table[..9], &[b'L', b'D', b' ', b'A', b',', PH_IMM8, 0, 1, 0x3E]);
+        assert_eq!(table.last(), Some(&0));
+    }
+
+    #[test]
+    fn test_table_dedups_syntax() {
+        let table = table_bytes();
+        let jp: &[u8] = &[b'J', b'P', b' ', PH_IMM16, 0];
+        let count = table.windows(jp.len()).filter(|w| *w == jp).count();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_asm_rom_builds() {
+        let rom = build();
+        assert!(rom.has_label("asm_command"));
+        assert!(rom.has_label("asm_table"));
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod emulator_tests {
+    use super::*;
+    use crate::emulator::harness::Harness;
+    use crate::opcodes;
+
+    const CYCLES: u64 = 2_000_000;
+
+    /// Encoding of `syntax` from the opcode table followed by `operand`
+    fn encode(syntax: &str, operand: &[u8]) -> Vec<u8> {
+        let mut bytes = opcodes::find(syntax).unwrap().encoding.to_vec();
+        bytes.extend_from_slice(operand);
+        bytes
+    }
+
+    #[test]
+    fn test_assemble_and_go() {
+        let mut term = Harness::new(&build());
+        term.run_until_output_contains(">", CYCLES).unwrap();
+        term.command("A 3000", "3000: ", CYCLES).unwrap();
+        term.command("ld a,2a", "3002: ", CYCLES).unwrap();
+        term.command("ld (3100) , a", "3005: ", CYCLES).unwrap();
+        term.command("FROB A", "?", CYCLES).unwrap();    // Bad mnemonic
+        term.run_until_output_contains("3005: ", CYCLES).unwrap();
+        term.command("ret", "3006: ", CYCLES).unwrap();
+        term.command("", ">", CYCLES).unwrap();
+
+        let mut expected = encode("LD A,n", &[0x2A]);
+        expected.extend(encode("LD (nn),A", &[0x00, 0x31]));
+        expected.extend(encode("RET", &[]));
+        let code: Vec<u8> = (0x3000..0x3006).map(|a| term.emu.peek(a)).collect();
+        assert_eq!(code, expected);
+
+        term.command("G 3000", ">", CYCLES).unwrap();
+        assert_eq!(term.emu.peek(0x3100), 0x2A);
+    }
+
+    #[test]
+    fn test_bad_command() {
+        let mut term = Harness::new(&build());
+        term.run_until_output_contains(">", CYCLES).unwrap();
+        let reply = term.command("A ZZ", ">", CYCLES).unwrap();
+        assert!(reply.contains('?'));
+    }
+}
@@ -0,0 +1,1219 @@
+//! Tiny BASIC interpreter ROM
+//!
+//! A small line-numbered BASIC over the serial console. Lines typed with a
+//! number are tokenized and stored in RAM in line order; lines without one
+//! run at once. Values are signed 16-bit integers in the variables `A`-`Z`.
+//!
+//! Statements: `LET`, `PRINT`, `IF ... THEN`, `GOTO`, `INPUT`, `REM`,
+//! `END`, `RUN`, `LIST`, `NEW`, several per line with `:`. Expressions
+//! have `+ - * /`, unary `-`, parentheses and the comparisons `= <> < >
+//! <= >=` (1 for true, 0 for false). Ctrl-C stops a running program.
+//!
+//! Extra statements are added from Rust with `BasicConfig::statements`:
+//! each keyword gets its own token, and the statement jumps to the handler
+//! with HL pointing just past the keyword. A handler uses `bas_expr` and
+//! `bas_var` to read its arguments, returns with HL past them, and may
+//! clobber every other register; `bas_syntax_error` reports bad input.
+
+use crate::CodeGen;
+
+/// Longest accepted input line
+const LINE_MAX: u8 = 72;
+
+/// Built-in statements, in token order from 0x80
+const STATEMENTS: [&str; 10] = ["LET", "PRINT", "IF", "GOTO", "INPUT", "REM", "END", "RUN", "LIST", "NEW"];
+const TOK_REM: u8 = 0x85;
+
+/// Bytes in front of a stored line's text: number (2) and record length
+const LINE_HEADER: u16 = 3;
+
+/// A statement handled by user code
+#[derive(Clone, Debug)]
+pub struct BasicStatement {
+    /// Keyword, at least two upper-case letters
+    pub keyword: String,
+    /// Label jumped to with HL just past the keyword
+    pub handler: String,
+}
+
+/// Interpreter configuration
+#[derive(Clone, Debug)]
+pub struct BasicConfig {
+    /// Bytes of RAM for program lines (each takes 4 bytes plus its text)
+    pub program_size: u16,
+    /// Extra statements, tokenized after the built-in ones
+    pub statements: Vec<BasicStatement>,
+}
+
+impl Default for BasicConfig {
+    fn default() -> Self {
+        Self { program_size: 4096, statements: Vec::new() }
+    }
+}
+
+impl BasicConfig {
+    /// All keywords in token order: statements, then `THEN`
+    fn keywords(&self) -> Vec<String> {
+        let mut keywords: Vec<String> = STATEMENTS.iter().map(|s| s.to_string()).collect();
+        for st in &self.statements {
+            assert!(
+                st.keyword.len() >= 2 && st.keyword.bytes().all(|c| c.is_ascii_uppercase()),
+                "BASIC keyword `{}` must be two or more upper-case letters",
+                st.keyword
+            );
+            assert!(
+                st.keyword != "THEN" && !keywords.contains(&st.keyword),
+                "BASIC keyword `{}` is already defined",
+                st.keyword
+            );
+            keywords.push(st.keyword.clone());
+        }
+        keywords.push("THEN".to_string());
+        assert!(keywords.len() <= 0x7F, "too many BASIC keywords");
+        keywords
+    }
+}
+
+/// Encode the keyword table: each keyword with bit 7 set on its last
+/// letter, then a 0
+pub fn keyword_bytes(keywords: &[String]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for keyword in keywords {
+        let bytes = keyword.as_bytes();
+        out.extend_from_slice(&bytes[..bytes.len() - 1]);
+        out.push(bytes[bytes.len() - 1] | 0x80);
+    }
+    out.push(0);
+    out
+}
+
+/// Build a BASIC ROM with the default RomConfig
+///
+/// Extra statements need their handlers in the same ROM, so embed
+/// `emit_basic` in your own build for those.
+pub fn build(config: &BasicConfig) -> CodeGen {
+    let mut rom = CodeGen::new();
+    let stack_top = rom.config().stack_top;
+    rom.emit_startup(stack_top);
+    rom.jp("basic");
+    rom.emit_basic(config);
+    rom.include_stdlib();
+    rom.emit_char_available();
+    rom.emit_print_word_dec();
+    rom.emit_print_word_sdec();
+    rom.emit_parse_dec();
+    rom.emit_mul16();
+    rom.emit_sdiv16();
+    rom.resolve_fixups();
+    rom
+}
+
+impl CodeGen {
+    /// Emit the BASIC interpreter (entered at `basic`, never returns)
+    ///
+    /// Routines for statement handlers:
+    /// - `bas_expr`: evaluate the expression at HL into DE, HL advanced.
+    /// - `bas_var`: variable letter at HL -> DE = its address, HL
+    ///   advanced; carry set if HL is not at a letter.
+    /// - `bas_syntax_error` / `bas_error` (HL = message): report and go
+    ///   back to the prompt.
+    ///
+    /// Labels created: `basic`, `bas_ready`, `bas_prompt`, `bas_readline`,
+    /// `bas_tokenize`, `bas_keyword`, `bas_exec`, `bas_statement`,
+    /// `bas_run_from`, `bas_find`, `bas_delete`, `bas_insert`, `bas_expr`,
+    /// `bas_sum`, `bas_term`, `bas_factor`, `bas_var`, `bas_skip_line`,
+    /// `bas_error`, `bas_syntax_error`, `bas_keywords`, `bas_stmt_table`
+    /// and the statement handlers `bas_let` .. `bas_new`
+    /// RAM used: `bas_stack`, `bas_cur`, `bas_free`, `bas_vars` (52 bytes),
+    /// `bas_col`, `bas_buf`, `bas_tok`, `bas_prog`
+    /// Requires: `getchar`, `char_available`, `conout`, `newline`,
+    /// `print_string`, `print_word_dec`, `print_word_sdec`, `parse_dec`,
+    /// `mul16`, `sdiv16`
+    pub fn emit_basic(&mut self, config: &BasicConfig) {
+        let keywords = config.keywords();
+        let tok_then = 0x80 + keywords.len() as u8 - 1;
+        let statements = keywords.len() as u8 - 1;
+
+        let stack = self.ram_var("bas_stack", 2);
+        let cur = self.ram_var("bas_cur", 2);
+        let free = self.ram_var("bas_free", 2);
+        let vars = self.ram_var("bas_vars", 52);
+        let col = self.ram_var("bas_col", 1);
+        let buf = self.ram_var("bas_buf", LINE_MAX as u16 + 1);
+        let tok = self.ram_var("bas_tok", LINE_MAX as u16 + 1 + LINE_HEADER);
+        let prog = self.ram_var("bas_prog", config.program_size);
+        let prog_limit = prog + config.program_size;
+
+        let banner = self.pooled_string("TINY BASIC\r\n");
+        let ok = self.pooled_string("OK\r\n");
+        let msg_syntax = self.pooled_string("SYNTAX");
+        let msg_line = self.pooled_string("LINE");
+        let msg_mem = self.pooled_string("MEM");
+        let msg_div = self.pooled_string("DIV0");
+        let msg_break = self.pooled_string("BREAK");
+        let msg_in = self.pooled_string(" IN ");
+
+        let clear_vars = self.unique_label("bas_clear_vars");
+        let clear_loop = self.unique_label("bas_clear_loop");
+        let prompt_line = self.unique_label("bas_prompt_line");
+        let immediate = self.unique_label("bas_immediate");
+        let no_delete = self.unique_label("bas_no_delete");
+        let rl_loop = self.unique_label("bas_rl_loop");
+        let rl_bs = self.unique_label("bas_rl_bs");
+        let tk_loop = self.unique_label("bas_tk_loop");
+        let tk_string = self.unique_label("bas_tk_string");
+        let tk_rem = self.unique_label("bas_tk_rem");
+        let tk_copy = self.unique_label("bas_tk_copy");
+        let tk_upper = self.unique_label("bas_tk_upper");
+        let tk_str_loop = self.unique_label("bas_tk_str_loop");
+        let tk_store = self.unique_label("bas_tk_store");
+        let rl_done = self.unique_label("bas_rl_done");
+        let kw_upper = self.unique_label("bas_kw_upper");
+        let kw_miss = self.unique_label("bas_kw_miss");
+        let kw_seek = self.unique_label("bas_kw_seek");
+        let kw_none = self.unique_label("bas_kw_none");
+        let list_eol = self.unique_label("bas_list_eol");
+        let input_bad = self.unique_label("bas_input_bad");
+        let err_div = self.unique_label("bas_err_div");
+        let err_done = self.unique_label("bas_err_done");
+        let kw_next = self.unique_label("bas_kw_next");
+        let kw_cmp = self.unique_label("bas_kw_cmp");
+        let run_loop = self.unique_label("bas_run_loop");
+        let run_next = self.unique_label("bas_run_next");
+        let find_loop = self.unique_label("bas_find_loop");
+        let find_end = self.unique_label("bas_find_end");
+        let del_skip = self.unique_label("bas_del_skip");
+        let ins_skip = self.unique_label("bas_ins_skip");
+        let rel_loop = self.unique_label("bas_rel_loop");
+        let rel_lt = self.unique_label("bas_rel_lt");
+        let rel_gt = self.unique_label("bas_rel_gt");
+        let rel_eq = self.unique_label("bas_rel_eq");
+        let rel_ov = self.unique_label("bas_rel_ov");
+        let rel_got = self.unique_label("bas_rel_got");
+        let sum_loop = self.unique_label("bas_sum_loop");
+        let sum_sub = self.unique_label("bas_sum_sub");
+        let term_loop = self.unique_label("bas_term_loop");
+        let term_div = self.unique_label("bas_term_div");
+        let fac_neg = self.unique_label("bas_fac_neg");
+        let fac_paren = self.unique_label("bas_fac_paren");
+        let fac_num = self.unique_label("bas_fac_num");
+        let negate_de = self.unique_label("bas_negate_de");
+        let print_loop = self.unique_label("bas_print_loop");
+        let print_str = self.unique_label("bas_print_str");
+        let print_item = self.unique_label("bas_print_item");
+        let print_sep = self.unique_label("bas_print_sep");
+        let print_end = self.unique_label("bas_print_end");
+        let print_nl = self.unique_label("bas_print_nl");
+        let print_open = self.unique_label("bas_print_open");
+        let if_then = self.unique_label("bas_if_then");
+        let input_ask = self.unique_label("bas_input_ask");
+        let input_pos = self.unique_label("bas_input_pos");
+        let list_loop = self.unique_label("bas_list_loop");
+        let list_text = self.unique_label("bas_list_text");
+        let list_tok = self.unique_label("bas_list_tok");
+        let list_done = self.unique_label("bas_list_done");
+        let kw_print = self.unique_label("bas_kw_print");
+        let err_line = self.unique_label("bas_err_line");
+        let err_mem = self.unique_label("bas_err_mem");
+
+        // Entry: empty program, banner
+        self.label("basic");
+        self.ld_addr_sp(stack);
+        self.ld_hl(prog);
+        self.ld_addr_hl(free);
+        self.call(&clear_vars);
+        self.ld_hl_label(&banner);
+        self.call("print_string");
+
+        self.label("bas_ready");
+        self.ld_hl(col);
+        self.ld_a_hl_ind();
+        self.ld_hl_ind_n(0);
+        self.or_a_a();
+        self.call_nz("newline");    // PRINT left the cursor mid-line
+        self.ld_hl_label(&ok);
+        self.call("print_string");
+
+        self.label("bas_prompt");
+        self.ld_sp_addr(stack);
+        self.ld_hl(0);
+        self.ld_addr_hl(cur);       // Not running
+        self.ld_a(b'>');
+        self.call("conout");
+        self.call("bas_readline");
+        self.ld_hl(buf);
+        self.label(&prompt_line);
+        self.ld_a_hl_ind();
+        self.cp(b' ');
+        self.jp_nz(&immediate);
+        self.inc_hl();
+        self.jp(&prompt_line);
+        self.label(&immediate);
+        self.or_a_a();
+        self.jp_z("bas_prompt");
+        self.cp(b'0');
+        self.jp_c(&no_delete);
+        self.cp(b'9' + 1);
+        self.jp_nc(&no_delete);
+
+        // Numbered line: tokenize behind a header, replace the old line
+        self.call("parse_dec");
+        self.jp_c("bas_syntax_error");
+        self.ld_addr_de(tok);
+        self.ld_de(tok + LINE_HEADER);
+        self.call("bas_tokenize");
+        self.ex_de_hl();
+        self.ld_de(tok);
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.ld_a_l();
+        self.ld_addr_a(tok + 2);
+        self.ld_de_addr(tok);
+        self.call("bas_find");
+        self.call_z("bas_delete");
+        self.ld_a_addr(tok + LINE_HEADER);
+        self.or_a_a();
+        self.jp_z("bas_prompt");    // Number alone deletes the line
+        self.call("bas_insert");
+        self.jp("bas_prompt");
+
+        // Anything else runs at once
+        self.label(&no_delete);
+        self.ld_de(tok + LINE_HEADER);
+        self.call("bas_tokenize");
+        self.ld_hl(tok + LINE_HEADER);
+        self.call("bas_exec");
+        self.jp("bas_ready");
+
+        self.label(&clear_vars);
+        self.ld_hl(vars);
+        self.ld_b(52);
+        self.label(&clear_loop);
+        self.ld_hl_ind_n(0);
+        self.inc_hl();
+        self.djnz(&clear_loop);
+        self.ret();
+
+        // bas_readline: edited line into bas_buf, null-terminated
+        self.label("bas_readline");
+        self.ld_hl(buf);
+        self.ld_b(0);
+        self.label(&rl_loop);
+        self.call("getchar");
+        self.cp(0x0D);
+        self.jp_z(&rl_done);
+        self.cp(0x08);
+        self.jp_z(&rl_bs);
+        self.cp(0x7F);
+        self.jp_z(&rl_bs);
+        self.jp_nc(&rl_loop);
+        self.cp(b' ');
+        self.jp_c(&rl_loop);        // Other control characters
+        self.ld_c_a();
+        self.ld_a_b();
+        self.cp(LINE_MAX);
+        self.jp_nc(&rl_loop);       // Full
+        self.ld_a_c();
+        self.ld_hl_ind_a();
+        self.inc_hl();
+        self.inc_b();
+        self.call("conout");
+        self.jp(&rl_loop);
+        self.label(&rl_bs);
+        self.ld_a_b();
+        self.or_a_a();
+        self.jp_z(&rl_loop);
+        self.dec_hl();
+        self.dec_b();
+        self.ld_a(0x08);
+        self.call("conout");
+        self.ld_a(b' ');
+        self.call("conout");
+        self.ld_a(0x08);
+        self.call("conout");
+        self.jp(&rl_loop);
+        self.label(&rl_done);
+        self.ld_hl_ind_n(0);
+        self.xor_a();
+        self.ld_addr_a(col);
+        self.jp("newline");
+
+        // bas_tokenize: HL = text, DE = output; spaces outside strings are
+        // dropped, letters upper-cased, keywords replaced by tokens. DE
+        // ends past the terminator.
+        self.label("bas_tokenize");
+        self.label(&tk_loop);
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z(&tk_copy);
+        self.cp(b' ');
+        self.jp_nz(&tk_string);
+        self.inc_hl();
+        self.jp(&tk_loop);
+        self.label(&tk_string);
+        self.cp(b'"');
+        self.jp_nz(&tk_rem);
+        self.label(&tk_str_loop);   // Copy up to the closing quote
+        self.ld_de_ind_a();
+        self.inc_de();
+        self.inc_hl();
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z(&tk_copy);
+        self.cp(b'"');
+        self.jp_nz(&tk_str_loop);
+        self.jp(&tk_store);
+        self.label(&tk_rem);
+        self.call("bas_keyword");
+        self.jp_c(&tk_upper);
+        self.ld_de_ind_a();
+        self.inc_de();
+        self.cp(TOK_REM);
+        self.jp_nz(&tk_loop);
+        self.label(&tk_copy);       // Rest of the line as typed
+        self.ld_a_hl_ind();
+        self.ld_de_ind_a();
+        self.inc_de();
+        self.or_a_a();
+        self.ret_z();
+        self.inc_hl();
+        self.jp(&tk_copy);
+        self.label(&tk_upper);
+        self.ld_a_hl_ind();
+        self.cp(b'a');
+        self.jp_c(&tk_store);
+        self.cp(b'z' + 1);
+        self.jp_nc(&tk_store);
+        self.sub_a(0x20);
+        self.label(&tk_store);
+        self.ld_de_ind_a();
+        self.inc_de();
+        self.inc_hl();
+        self.jp(&tk_loop);
+
+        // bas_keyword: keyword at HL -> A = token, HL past it; carry set
+        // if none. Clobbers BC.
+        self.label("bas_keyword");
+        self.push_de();
+        self.ld_de_label("bas_keywords");
+        self.ld_c(0x80);
+        self.label(&kw_next);
+        self.ld_a_de_ind();
+        self.or_a_a();
+        self.jp_z(&kw_none);        // End of table: no match
+        self.push_hl();
+        self.label(&kw_cmp);
+        self.ld_a_hl_ind();
+        self.cp(b'a');
+        self.jp_c(&kw_upper);
+        self.sub_a(0x20);           // Upper-case (non-letters never match)
+        self.label(&kw_upper);
+        self.ld_b_a();
+        self.ld_a_de_ind();
+        self.and_a(0x7F);
+        self.cp_b();
+        self.jp_nz(&kw_miss);
+        self.ld_a_de_ind();
+        self.inc_de();
+        self.inc_hl();
+        self.or_a_a();
+        self.jp_p(&kw_cmp);
+        self.inc_sp();              // Matched: drop the saved HL
+        self.inc_sp();
+        self.ld_a_c();
+        self.pop_de();
+        self.or_a_a();
+        self.ret();
+        self.label(&kw_miss);       // Skip the rest of this keyword
+        self.ld_a_de_ind();
+        self.inc_de();
+        self.or_a_a();
+        self.jp_p(&kw_miss);
+        self.pop_hl();
+        self.inc_c();
+        self.jp(&kw_next);
+        self.label(&kw_none);
+        self.pop_de();
+        self.scf();
+        self.ret();
+
+        // bas_exec: run the statements at HL up to the end of the line
+        self.label("bas_exec");
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.ret_z();
+        self.call("bas_statement");
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.ret_z();
+        self.cp(b':');
+        self.jp_nz("bas_syntax_error");
+        self.inc_hl();
+        self.jp("bas_exec");
+
+        // bas_statement: one statement at HL; a letter is an implicit LET
+        self.label("bas_statement");
+        self.ld_a_hl_ind();
+        self.cp(0x80);
+        self.jp_c("bas_let");
+        self.sub_a(0x80);
+        self.cp(statements);
+        self.jp_nc("bas_syntax_error");
+        self.inc_hl();
+        self.add_a_a();
+        self.ld_e_a();
+        self.ld_d(0);
+        self.push_hl();
+        self.ld_hl_label("bas_stmt_table");
+        self.add_hl_de();
+        self.ld_e_hl_ind();
+        self.inc_hl();
+        self.ld_d_hl_ind();
+        self.pop_hl();
+        self.push_de();
+        self.ret();                 // Jump to the handler
+
+        // bas_run_from: run the program from the line at HL
+        self.label("bas_run_from");
+        self.label(&run_loop);
+        self.ld_de_addr(free);
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.add_hl_de();
+        self.jp_z("bas_ready");
+        self.ld_addr_hl(cur);
+        self.call("char_available");
+        self.jp_z(&run_next);
+        self.call("getchar");
+        self.cp(0x03);              // Ctrl-C
+        self.ld_hl_label(&msg_break);
+        self.jp_z("bas_error");
+        self.label(&run_next);
+        self.ld_hl_addr(cur);
+        self.ld_de(LINE_HEADER);
+        self.add_hl_de();
+        self.call("bas_exec");
+        self.ld_hl_addr(cur);
+        self.push_hl();
+        self.inc_hl();
+        self.inc_hl();
+        self.ld_e_hl_ind();
+        self.ld_d(0);
+        self.pop_hl();
+        self.add_hl_de();
+        self.jp(&run_loop);
+
+        // bas_find: line DE -> HL = that line (Z) or where it would go
+        // (NZ). Clobbers A, BC.
+        self.label("bas_find");
+        self.ld_hl(prog);
+        self.label(&find_loop);
+        self.push_de();
+        self.ld_de_addr(free);
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.add_hl_de();
+        self.pop_de();
+        self.jp_z(&find_end);
+        self.push_hl();
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.ld_h_hl_ind();
+        self.ld_l_a();
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.pop_hl();
+        self.ret_z();
+        self.ret_nc();              // Past it
+        self.push_hl();
+        self.inc_hl();
+        self.inc_hl();
+        self.ld_c_hl_ind();
+        self.ld_b(0);
+        self.pop_hl();
+        self.add_hl_bc();
+        self.jp(&find_loop);
+        self.label(&find_end);
+        self.ld_a(1);
+        self.or_a_a();              // NZ
+        self.ret();
+
+        // bas_delete: remove the line at HL, HL unchanged
+        self.label("bas_delete");
+        self.ld_d_h();
+        self.ld_e_l();
+        self.inc_hl();
+        self.inc_hl();
+        self.ld_c_hl_ind();
+        self.ld_b(0);
+        self.ld_h_d();
+        self.ld_l_e();
+        self.add_hl_bc();
+        self.push_de();
+        self.push_hl();
+        self.ld_hl_addr(free);
+        self.or_a_a();
+        self.sbc_hl_bc();
+        self.ld_addr_hl(free);
+        self.or_a_a();
+        self.sbc_hl_de();           // Bytes after the line
+        self.ld_b_h();
+        self.ld_c_l();
+        self.pop_hl();
+        self.ld_a_b();
+        self.or_c();
+        self.jp_z(&del_skip);
+        self.ldir();
+        self.label(&del_skip);
+        self.pop_hl();
+        self.ret();
+
+        // bas_insert: open a gap at HL and copy the record from bas_tok
+        self.label("bas_insert");
+        self.ld_a_addr(tok + 2);
+        self.ld_c_a();
+        self.ld_b(0);
+        self.push_hl();
+        self.ld_hl_addr(free);
+        self.push_hl();
+        self.add_hl_bc();
+        self.ex_de_hl();            // DE = new end
+        self.ld_hl(prog_limit);
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.jp_c(&err_mem);
+        self.ld_addr_de(free);
+        self.dec_de();
+        self.pop_hl();
+        self.pop_bc();
+        self.push_bc();
+        self.or_a_a();
+        self.sbc_hl_bc();           // Bytes to move up
+        self.ld_b_h();
+        self.ld_c_l();
+        self.pop_hl();
+        self.push_hl();
+        self.add_hl_bc();
+        self.dec_hl();
+        self.ld_a_b();
+        self.or_c();
+        self.jp_z(&ins_skip);
+        self.lddr();
+        self.label(&ins_skip);
+        self.pop_de();
+        self.ld_hl(tok);
+        self.ld_a_addr(tok + 2);
+        self.ld_c_a();
+        self.ld_b(0);
+        self.ldir();
+        self.ret();
+
+        // bas_expr: sum [relation sum]
+        self.label("bas_expr");
+        self.call("bas_sum");
+        self.ld_c(0);
+        self.label(&rel_loop);
+        self.ld_a_hl_ind();
+        self.cp(b'<');
+        self.jp_z(&rel_lt);
+        self.cp(b'=');
+        self.jp_z(&rel_eq);
+        self.cp(b'>');
+        self.jp_z(&rel_gt);
+        self.ld_a_c();
+        self.or_a_a();
+        self.ret_z();               // No comparison
+        self.push_bc();
+        self.push_de();
+        self.call("bas_sum");
+        self.ex_sp_hl();            // HL = left, DE = right
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.ld_a(2);               // Equal
+        self.jp_z(&rel_got);
+        self.jp_pe(&rel_ov);
+        self.ld_a(1);               // Less
+        self.jp_m(&rel_got);
+        self.ld_a(4);               // Greater
+        self.jp(&rel_got);
+        self.label(&rel_ov);        // Overflow inverts the sign
+        self.ld_a(4);
+        self.jp_m(&rel_got);
+        self.ld_a(1);
+        self.label(&rel_got);
+        self.pop_hl();
+        self.pop_bc();
+        self.and_c();
+        self.ld_de(0);
+        self.ret_z();
+        self.inc_e();
+        self.ret();
+        self.label(&rel_lt);
+        self.set_c(0);
+        self.inc_hl();
+        self.jp(&rel_loop);
+        self.label(&rel_eq);
+        self.set_c(1);
+        self.inc_hl();
+        self.jp(&rel_loop);
+        self.label(&rel_gt);
+        self.set_c(2);
+        self.inc_hl();
+        self.jp(&rel_loop);
+
+        // bas_sum: term {+|- term}
+        self.label("bas_sum");
+        self.call("bas_term");
+        self.label(&sum_loop);
+        self.ld_a_hl_ind();
+        self.cp(b'-');
+        self.jp_z(&sum_sub);
+        self.cp(b'+');
+        self.ret_nz();
+        self.inc_hl();
+        self.push_de();
+        self.call("bas_term");
+        self.ex_sp_hl();
+        self.add_hl_de();
+        self.ex_de_hl();
+        self.pop_hl();
+        self.jp(&sum_loop);
+        self.label(&sum_sub);
+        self.inc_hl();
+        self.push_de();
+        self.call("bas_term");
+        self.ex_sp_hl();
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.ex_de_hl();
+        self.pop_hl();
+        self.jp(&sum_loop);
+
+        // bas_term: factor {*|/ factor}
+        self.label("bas_term");
+        self.call("bas_factor");
+        self.label(&term_loop);
+        self.ld_a_hl_ind();
+        self.cp(b'/');
+        self.jp_z(&term_div);
+        self.cp(b'*');
+        self.ret_nz();
+        self.inc_hl();
+        self.push_de();
+        self.call("bas_factor");
+        self.ex_sp_hl();
+        self.call("mul16");
+        self.ex_de_hl();
+        self.pop_hl();
+        self.jp(&term_loop);
+        self.label(&term_div);
+        self.inc_hl();
+        self.push_de();
+        self.call("bas_factor");
+        self.ld_a_d();
+        self.or_e();
+        self.jp_z(&err_div);
+        self.ex_sp_hl();
+        self.call("sdiv16");
+        self.ex_de_hl();
+        self.pop_hl();
+        self.jp(&term_loop);
+
+        // bas_factor: number, variable, (expr) or -factor
+        self.label("bas_factor");
+        self.ld_a_hl_ind();
+        self.cp(b'-');
+        self.jp_z(&fac_neg);
+        self.cp(b'(');
+        self.jp_z(&fac_paren);
+        self.cp(b'0');
+        self.jp_c("bas_syntax_error");
+        self.cp(b'9' + 1);
+        self.jp_c(&fac_num);
+        self.call("bas_var");
+        self.jp_c("bas_syntax_error");
+        self.ex_de_hl();
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.ld_h_hl_ind();
+        self.ld_l_a();
+        self.ex_de_hl();
+        self.ret();
+        self.label(&fac_num);
+        self.call("parse_dec");
+        self.jp_c("bas_syntax_error");
+        self.ret();
+        self.label(&fac_paren);
+        self.inc_hl();
+        self.call("bas_expr");
+        self.ld_a_hl_ind();
+        self.cp(b')');
+        self.jp_nz("bas_syntax_error");
+        self.inc_hl();
+        self.ret();
+        self.label(&fac_neg);
+        self.inc_hl();
+        self.call("bas_factor");
+        self.label(&negate_de);
+        self.ld_a_e();
+        self.cpl();
+        self.ld_e_a();
+        self.ld_a_d();
+        self.cpl();
+        self.ld_d_a();
+        self.inc_de();
+        self.ret();
+
+        // bas_var: letter at HL -> DE = variable address
+        self.label("bas_var");
+        self.ld_a_hl_ind();
+        self.sub_a(b'A');
+        self.ret_c();
+        self.cp(26);
+        self.ccf();
+        self.ret_c();
+        self.inc_hl();
+        self.add_a_a();
+        self.ld_e_a();
+        self.ld_d(0);
+        self.push_hl();
+        self.ld_hl(vars);
+        self.add_hl_de();
+        self.ex_de_hl();
+        self.pop_hl();
+        self.or_a_a();
+        self.ret();
+
+        // Statement handlers, entered with HL past the keyword
+        self.label("bas_let");
+        self.call("bas_var");
+        self.jp_c("bas_syntax_error");
+        self.ld_a_hl_ind();
+        self.cp(b'=');
+        self.jp_nz("bas_syntax_error");
+        self.inc_hl();
+        self.push_de();
+        self.call("bas_expr");
+        self.ex_sp_hl();
+        self.ld_hl_ind_e();
+        self.inc_hl();
+        self.ld_hl_ind_d();
+        self.pop_hl();
+        self.ret();
+
+        // PRINT: strings and expressions; ; joins, , adds a space, and
+        // either one at the end suppresses the newline (bas_ready adds it
+        // back before OK)
+        self.label("bas_print");
+        self.label(&print_loop);
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z(&print_nl);
+        self.cp(b':');
+        self.jp_z(&print_nl);
+        self.cp(b'"');
+        self.jp_nz(&print_item);
+        self.inc_hl();
+        self.label(&print_str);
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z(&print_nl);       // Unterminated string
+        self.inc_hl();
+        self.cp(b'"');
+        self.jp_z(&print_sep);
+        self.call("conout");
+        self.jp(&print_str);
+        self.label(&print_item);
+        self.call("bas_expr");
+        self.push_hl();
+        self.ex_de_hl();
+        self.call("print_word_sdec");
+        self.pop_hl();
+        self.label(&print_sep);
+        self.ld_a_hl_ind();
+        self.cp(b';');
+        self.jp_z(&print_end);
+        self.cp(b',');
+        self.jp_nz(&print_nl);
+        self.ld_a(b' ');
+        self.call("conout");
+        self.label(&print_end);
+        self.inc_hl();
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z(&print_open);
+        self.cp(b':');
+        self.jp_nz(&print_loop);
+        self.label(&print_open);
+        self.ld_a(1);
+        self.ld_addr_a(col);        // Cursor left mid-line
+        self.ret();
+        self.label(&print_nl);
+        self.xor_a();
+        self.ld_addr_a(col);
+        self.push_hl();
+        self.call("newline");
+        self.pop_hl();
+        self.ret();
+
+        self.label("bas_if");
+        self.call("bas_expr");
+        self.ld_a_d();
+        self.or_e();
+        self.jp_z("bas_skip_line");
+        self.ld_a_hl_ind();
+        self.cp(tok_then);
+        self.jp_nz("bas_statement");
+        self.inc_hl();
+        self.ld_a_hl_ind();
+        self.cp(b'0');
+        self.jp_c(&if_then);
+        self.cp(b'9' + 1);
+        self.jp_c("bas_goto");      // THEN line
+        self.label(&if_then);
+        self.jp("bas_statement");
+
+        self.label("bas_goto");
+        self.call("bas_expr");
+        self.call("bas_find");
+        self.jp_nz(&err_line);
+        self.ld_sp_addr(stack);
+        self.jp("bas_run_from");
+
+        // INPUT var[,var]: ask until a number is typed
+        self.label("bas_input");
+        self.call("bas_var");
+        self.jp_c("bas_syntax_error");
+        self.push_hl();
+        self.push_de();
+        self.label(&input_ask);
+        self.ld_a(b'?');
+        self.call("conout");
+        self.ld_a(b' ');
+        self.call("conout");
+        self.call("bas_readline");
+        self.ld_hl(buf);
+        self.ld_a_hl_ind();
+        self.cp(b'-');
+        self.push_af();
+        self.jp_nz(&input_pos);
+        self.inc_hl();
+        self.label(&input_pos);
+        self.call("parse_dec");
+        self.jp_c(&input_bad);     // Not a number
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_nz(&input_bad);    // Trailing junk
+        self.pop_af();
+        self.call_z(&negate_de);
+        self.pop_hl();
+        self.ld_hl_ind_e();
+        self.inc_hl();
+        self.ld_hl_ind_d();
+        self.pop_hl();
+        self.ld_a_hl_ind();
+        self.cp(b',');
+        self.ret_nz();
+        self.inc_hl();
+        self.jp("bas_input");
+        self.label(&input_bad);
+        self.pop_af();
+        self.jp(&input_ask);
+
+        self.label("bas_rem");
+        self.label("bas_skip_line");
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.ret_z();
+        self.inc_hl();
+        self.jp("bas_skip_line");
+
+        self.label("bas_end");
+        self.jp("bas_ready");
+
+        self.label("bas_run");
+        self.call(&clear_vars);
+        self.ld_hl(prog);
+        self.ld_sp_addr(stack);
+        self.jp("bas_run_from");
+
+        // LIST: line number, then the text with keywords spelled out
+        self.label("bas_list");
+        self.push_hl();
+        self.ld_hl(prog);
+        self.label(&list_loop);
+        self.ld_de_addr(free);
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.add_hl_de();
+        self.jp_z(&list_done);
+        self.push_hl();
+        self.ld_e_hl_ind();
+        self.inc_hl();
+        self.ld_d_hl_ind();
+        self.ex_de_hl();
+        self.call("print_word_dec");
+        self.ld_a(b' ');
+        self.call("conout");
+        self.pop_hl();
+        self.push_hl();
+        self.ld_de(LINE_HEADER);
+        self.add_hl_de();
+        self.ld_c(1);               // At a keyword boundary
+        self.label(&list_text);
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z(&list_eol);
+        self.inc_hl();
+        self.or_a_a();
+        self.jp_m(&list_tok);
+        self.call("conout");
+        self.ld_c(0);
+        self.jp(&list_text);
+        self.label(&list_tok);
+        self.push_hl();
+        self.ld_b_a();
+        self.ld_a_c();
+        self.or_a_a();
+        self.ld_a(b' ');
+        self.call_z("conout");      // Space before, unless after a keyword
+        self.ld_a_b();
+        self.sub_a(0x80);
+        self.ld_b_a();
+        self.ld_hl_label("bas_keywords");
+        self.jp_z(&kw_print);
+        self.label(&kw_seek);
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.or_a_a();
+        self.jp_p(&kw_seek);
+        self.djnz(&kw_seek);
+        self.label(&kw_print);
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.push_af();
+        self.and_a(0x7F);
+        self.call("conout");
+        self.pop_af();
+        self.or_a_a();
+        self.jp_p(&kw_print);
+        self.pop_hl();
+        self.ld_c(1);
+        self.ld_a_hl_ind();         // Space after, unless at the end or
+        self.or_a_a();              // one follows (REM text)
+        self.jp_z(&list_text);
+        self.cp(b' ');
+        self.jp_z(&list_text);
+        self.ld_a(b' ');
+        self.call("conout");
+        self.jp(&list_text);
+        self.label(&list_eol);
+        self.call("newline");
+        self.pop_hl();
+        self.push_hl();
+        self.inc_hl();
+        self.inc_hl();
+        self.ld_e_hl_ind();
+        self.ld_d(0);
+        self.pop_hl();
+        self.add_hl_de();
+        self.jp(&list_loop);
+        self.label(&list_done);
+        self.pop_hl();
+        self.ret();
+
+        self.label("bas_new");
+        self.ld_hl(prog);
+        self.ld_addr_hl(free);
+        self.call(&clear_vars);
+        self.jp("bas_ready");
+
+        // Errors: ?MESSAGE [IN line], then back to the prompt
+        self.label(&err_div);
+        self.ld_hl_label(&msg_div);
+        self.jp("bas_error");
+        self.label(&err_line);
+        self.ld_hl_label(&msg_line);
+        self.jp("bas_error");
+        self.label(&err_mem);
+        self.ld_hl_label(&msg_mem);
+        self.jp("bas_error");
+        self.label("bas_syntax_error");
+        self.ld_hl_label(&msg_syntax);
+        self.label("bas_error");
+        self.xor_a();
+        self.ld_addr_a(col);        // Message starts on a fresh line
+        self.push_hl();
+        self.call("newline");
+        self.ld_a(b'?');
+        self.call("conout");
+        self.pop_hl();
+        self.call("print_string");
+        self.ld_hl_addr(cur);
+        self.ld_a_h();
+        self.or_l();
+        self.jp_z(&err_done);       // Not running
+        self.ld_hl_label(&msg_in);
+        self.call("print_string");
+        self.ld_hl_addr(cur);
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.ld_h_hl_ind();
+        self.ld_l_a();
+        self.call("print_word_dec");
+        self.label(&err_done);
+        self.call("newline");
+        self.jp("bas_ready");
+
+        self.label("bas_keywords");
+        self.emit(&keyword_bytes(&keywords));
+
+        self.label("bas_stmt_table");
+        for name in ["bas_let", "bas_print", "bas_if", "bas_goto", "bas_input", "bas_rem", "bas_end", "bas_run", "bas_list", "bas_new"] {
+            self.fixup(name);
+        }
+        for st in &config.statements {
+            self.fixup(&st.handler);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_bytes() {
+        let keywords = BasicConfig::default().keywords();
+        let table = keyword_bytes(&keywords);
+        assert_eq!(&table[..6], &[b'L', b'E', b'T' | 0x80, b'P', b'R', b'I']);
+        assert_eq!(&table[table.len() - 5..], &[b'T', b'H', b'E', b'N' | 0x80, 0]);
+        assert_eq!(keywords[(TOK_REM - 0x80) as usize], "REM");
+    }
+
+    #[test]
+    fn test_basic_builds() {
+        let rom = build(&BasicConfig::default());
+        assert!(rom.has_label("bas_expr"));
+        assert_eq!(rom.ram_var_size("bas_prog"), Some(4096));
+    }
+
+    #[test]
+    #[should_panic(expected = "already defined")]
+    fn test_duplicate_keyword() {
+        let config = BasicConfig {
+            statements: vec![BasicStatement { keyword: "PRINT".into(), handler: "p".into() }],
+            ..Default::default()
+        };
+        CodeGen::new().emit_basic(&config);
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod emulator_tests {
+    use super::*;
+    use crate::emulator::harness::Harness;
+
+    const CYCLES: u64 = 5_000_000;
+
+    /// Start `rom` and wait for the first OK
+    fn start(rom: &CodeGen) -> Harness {
+        let mut term = Harness::new(rom);
+        term.run_until_output_contains("OK\r\n", CYCLES).unwrap();
+        term
+    }
+
+    /// Type `line` and return everything up to the next OK
+    fn run(term: &mut Harness, line: &str) -> String {
+        term.command(line, "OK\r\n", CYCLES).unwrap()
+    }
+
+    /// Store a program line; numbered lines answer with a bare prompt
+    fn enter(term: &mut Harness, line: &str) {
+        term.command(line, ">", CYCLES).unwrap();
+    }
+
+    #[test]
+    fn test_let_print() {
+        let mut term = start(&build(&BasicConfig::default()));
+        run(&mut term, "LET A=6");
+        assert!(run(&mut term, "print a*7").ends_with("\r\n42\r\nOK\r\n"));
+        assert!(run(&mut term, "PRINT \"X=\";A,-A").ends_with("\r\nX=6 -6\r\nOK\r\n"));
+    }
+
+    #[test]
+    fn test_print_semicolon_ends_line_before_ok() {
+        let mut term = start(&build(&BasicConfig::default()));
+        assert!(run(&mut term, "PRINT \"HI\";").ends_with("\r\nHI\r\nOK\r\n"));
+        assert!(run(&mut term, "PRINT \"HI\"").ends_with("\r\nHI\r\nOK\r\n"));
+    }
+
+    #[test]
+    fn test_program_if_goto_input() {
+        let mut term = start(&build(&BasicConfig::default()));
+        enter(&mut term, "10 INPUT N");
+        enter(&mut term, "20 IF N>5 THEN 50");
+        enter(&mut term, "30 PRINT \"SMALL\"");
+        enter(&mut term, "40 GOTO 60");
+        enter(&mut term, "50 PRINT \"BIG\"");
+        enter(&mut term, "60 END");
+        term.command("RUN", "? ", CYCLES).unwrap();
+        assert!(run(&mut term, "9").ends_with("\r\nBIG\r\nOK\r\n"));
+        term.command("RUN", "? ", CYCLES).unwrap();
+        term.command("X", "? ", CYCLES).unwrap();   // Not a number: asked again
+        assert!(run(&mut term, "-2").ends_with("\r\nSMALL\r\nOK\r\n"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut term = start(&build(&BasicConfig::default()));
+        assert!(run(&mut term, "PRINT 1+").contains("?SYNTAX\r\n"));
+        assert!(run(&mut term, "GOTO 99").contains("?LINE\r\n"));
+        assert!(run(&mut term, "PRINT 1/0").contains("?DIV0\r\n"));
+        enter(&mut term, "10 LET A=1");
+        enter(&mut term, "20 GOTO 30");
+        assert!(run(&mut term, "RUN").contains("?LINE IN 20\r\n"));
+    }
+
+    #[test]
+    fn test_user_statement() {
+        // DBL V: double variable V
+        let config = BasicConfig {
+            statements: vec![BasicStatement { keyword: "DBL".into(), handler: "dbl".into() }],
+            ..Default::default()
+        };
+        let mut rom = CodeGen::new();
+        let stack_top = rom.config().stack_top;
+        rom.emit_startup(stack_top);
+        rom.jp("basic");
+        rom.label("dbl");
+        rom.call("bas_var");
+        rom.jp_c("bas_syntax_error");
+        rom.push_hl();
+        rom.ex_de_hl();
+        rom.ld_e_hl_ind();
+        rom.inc_hl();
+        rom.ld_d_hl_ind();
+        rom.ex_de_hl();
+        rom.add_hl_hl();
+        rom.ex_de_hl();
+        rom.ld_hl_ind_d();
+        rom.dec_hl();
+        rom.ld_hl_ind_e();
+        rom.pop_hl();
+        rom.ret();
+        rom.emit_basic(&config);
+        rom.include_stdlib();
+        rom.emit_char_available();
+        rom.emit_print_word_dec();
+        rom.emit_print_word_sdec();
+        rom.emit_parse_dec();
+        rom.emit_mul16();
+        rom.emit_sdiv16();
+        rom.resolve_fixups();
+
+        let mut term = start(&rom);
+        assert!(run(&mut term, "B=21:DBL B:PRINT B").ends_with("\r\n42\r\nOK\r\n"));
+        assert!(run(&mut term, "DBL 5").contains("?SYNTAX\r\n"));
+    }
+}
@@ -0,0 +1,525 @@
+//! Full-screen text editor ROM
+//!
+//! A small screen editor over the serial VT100 terminal: the text lives in
+//! a fixed `rows` x `cols` RAM buffer mirrored on screen, with arrow-key
+//! navigation, insert-mode typing, Delete/Backspace, Enter splitting the
+//! line at the cursor and Ctrl-L redraw.
+//!
+//! Ctrl-R loads and Ctrl-O saves the buffer as raw 512-byte blocks on an
+//! SD card, from `sd_block` on; the terminal bell rings if the card fails.
+
+use crate::stdlib::spi::SpiConfig;
+use crate::CodeGen;
+
+/// Editor layout configuration
+pub struct EditConfig {
+    /// Number of text rows (1-based screen rows 1..=rows)
+    pub rows: u8,
+    /// Number of columns per row
+    pub cols: u8,
+    /// SD card pins
+    pub spi: SpiConfig,
+    /// First SD block of the saved text
+    pub sd_block: u32,
+}
+
+impl Default for EditConfig {
+    fn default() -> Self {
+        Self { rows: 24, cols: 80, spi: SpiConfig::default(), sd_block: 0 }
+    }
+}
+
+/// Build a complete editor ROM with the default RomConfig
+pub fn build(config: &EditConfig) -> CodeGen {
+    let mut rom = CodeGen::new();
+    let stack_top = rom.config().stack_top;
+    rom.emit_startup(stack_top);
+    rom.jp("editor");
+    rom.emit_editor(config);
+    rom.include_stdlib();
+    rom.emit_spi(&config.spi);
+    rom.emit_sd();
+    rom.resolve_fixups();
+    rom
+}
+
+impl CodeGen {
+    /// Emit the editor main program (never returns)
+    ///
+    /// Labels created: `editor`, `ed_loop`, `ed_line_addr`, `ed_cur_addr`,
+    /// `ed_place_cursor`, `ed_draw_tail`, `ed_insert`, `ed_delete`, `ed_redraw`,
+    /// `ed_load`, `ed_save`
+    /// RAM used: `ed_row`, `ed_col`, `ed_buf` (rounded up to whole blocks)
+    /// Requires: `getchar`, `conout`, `clear_screen`, `cursor_pos`, `spi_init`,
+    /// `sd_init`, `sd_read_block`, `sd_write_block`
+    pub fn emit_editor(&mut self, config: &EditConfig) {
+        let rows = config.rows;
+        let cols = config.cols;
+        assert!(rows > 0 && cols > 1, "editor needs at least 1 row and 2 columns");
+        let buf_size = rows as u16 * cols as u16;
+        let blocks = (buf_size + 511) / 512;
+
+        let ed_row = self.ram_var("ed_row", 1);
+        let ed_col = self.ram_var("ed_col", 1);
+        let ed_buf = self.ram_var("ed_buf", blocks * 512);
+        let buf_end = ed_buf + buf_size;
+
+        // Entry: blank the buffer and draw it
+        self.label("editor");
+        self.call("spi_init");
+        self.ld_hl(ed_buf);
+        self.emit(&[0x36, b' ']);    // LD (HL), ' '
+        self.ld_de(ed_buf + 1);
+        self.ld_bc(buf_size - 1);
+        self.emit(&[0xED, 0xB0]);    // LDIR
+        self.xor_a();
+        self.ld_addr_a(ed_row);
+        self.ld_addr_a(ed_col);
+        self.call("ed_redraw");
+
+        // Main key loop
+        self.label("ed_loop");
+        self.call("ed_place_cursor");
+        self.call("getchar");
+        self.cp(0x1B);
+        self.jp_z("ed_escape");
+        self.cp(0x0D);
+        self.jp_z("ed_enter");
+        self.cp(0x08);
+        self.jp_z("ed_backspace");
+        self.cp(0x7F);
+        self.jp_z("ed_backspace");
+        self.cp(0x0C);               // Ctrl-L
+        self.jp_z("ed_redraw_key");
+        self.cp(0x12);               // Ctrl-R
+        self.jp_z("ed_load");
+        self.cp(0x0F);               // Ctrl-O
+        self.jp_z("ed_save");
+        self.cp(0x20);
+        self.jp_c("ed_loop");        // Ignore other control characters
+        self.call("ed_insert");
+        self.jp("ed_loop");
+
+        self.label("ed_redraw_key");
+        self.call("ed_redraw");
+        self.jp("ed_loop");
+
+        // Enter: move the rest of the row to a new row below it, pushing
+        // the rows underneath down (the last row drops off)
+        self.label("ed_enter");
+        self.ld_a_addr(ed_row);
+        self.cp(rows - 1);
+        self.jp_nc("ed_home");
+        self.call("ed_line_addr");
+        self.ld_de(cols as u16);
+        self.add_hl_de();
+        self.ex_de_hl();             // DE = start of the next row
+        self.ld_hl(buf_end - cols as u16);
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.ld_b_h();
+        self.ld_c_l();               // BC = bytes from there to the last row
+        self.ld_a_b();
+        self.or_c();
+        self.jp_z("ed_split");
+        self.ld_hl(buf_end - cols as u16 - 1);
+        self.ld_de(buf_end - 1);
+        self.lddr();
+        self.label("ed_split");
+        self.call("ed_cur_addr");
+        self.ld_a_addr(ed_col);
+        self.ld_b_a();
+        self.ld_a(cols);
+        self.sub_b();
+        self.ld_c_a();
+        self.ld_b(0);                // BC = cols - col
+        self.push_hl();
+        self.push_bc();
+        self.add_hl_bc();
+        self.ex_de_hl();             // DE = start of the new row
+        self.pop_bc();
+        self.pop_hl();
+        self.push_hl();
+        self.push_bc();
+        self.ldir();                 // Tail to the new row
+        self.ld_a_addr(ed_col);
+        self.or_a_a();
+        self.jp_z("ed_split_cut");
+        self.ld_b_a();
+        self.ld_a(b' ');
+        self.label("ed_split_pad");  // Blank the rest of the new row
+        self.ld_de_ind_a();
+        self.inc_de();
+        self.djnz("ed_split_pad");
+        self.label("ed_split_cut");
+        self.pop_bc();
+        self.pop_hl();
+        self.ld_b_c();
+        self.ld_a(b' ');
+        self.label("ed_split_cut_loop"); // Blank the tail on this row
+        self.ld_hl_ind_a();
+        self.inc_hl();
+        self.djnz("ed_split_cut_loop");
+        self.xor_a();
+        self.ld_addr_a(ed_col);
+        self.ld_a_addr(ed_row);
+        self.inc_a();
+        self.ld_addr_a(ed_row);
+        self.jp("ed_redraw_key");
+
+        self.label("ed_home");
+        self.xor_a();
+        self.ld_addr_a(ed_col);
+        self.jp("ed_loop");
+
+        self.label("ed_down");
+        self.ld_a_addr(ed_row);
+        self.cp(rows - 1);
+        self.jp_nc("ed_loop");
+        self.inc_a();
+        self.ld_addr_a(ed_row);
+        self.jp("ed_loop");
+
+        self.label("ed_backspace");
+        self.ld_a_addr(ed_col);
+        self.or_a_a();
+        self.jp_z("ed_loop");
+        self.dec_a();
+        self.ld_addr_a(ed_col);
+        self.call("ed_delete");
+        self.jp("ed_loop");
+
+        // ESC [ x sequences: arrows and Delete (ESC [ 3 ~)
+        self.label("ed_escape");
+        self.call("getchar");
+        self.cp(b'[');
+        self.jp_nz("ed_loop");
+        self.call("getchar");
+        self.cp(b'A');
+        self.jp_z("ed_up");
+        self.cp(b'B');
+        self.jp_z("ed_down");
+        self.cp(b'C');
+        self.jp_z("ed_right");
+        self.cp(b'D');
+        self.jp_z("ed_left");
+        self.cp(b'3');
+        self.jp_nz("ed_loop");
+        self.call("getchar");        // Swallow '~'
+        self.call("ed_delete");
+        self.jp("ed_loop");
+
+        self.label("ed_up");
+        self.ld_a_addr(ed_row);
+        self.or_a_a();
+        self.jp_z("ed_loop");
+        self.dec_a();
+        self.ld_addr_a(ed_row);
+        self.jp("ed_loop");
+
+        self.label("ed_right");
+        self.ld_a_addr(ed_col);
+        self.cp(cols - 1);
+        self.jp_nc("ed_loop");
+        self.inc_a();
+        self.ld_addr_a(ed_col);
+        self.jp("ed_loop");
+
+        self.label("ed_left");
+        self.ld_a_addr(ed_col);
+        self.or_a_a();
+        self.jp_z("ed_loop");
+        self.dec_a();
+        self.ld_addr_a(ed_col);
+        self.jp("ed_loop");
+
+        // Ctrl-R / Ctrl-O: read or write the buffer a block at a time,
+        // with the block count and number on the stack
+        for (label, routine, done) in [
+            ("ed_load", "sd_read_block", "ed_redraw_key"),
+            ("ed_save", "sd_write_block", "ed_loop"),
+        ] {
+            let next = self.unique_label(&format!("{}_next", label));
+            let same = self.unique_label(&format!("{}_same", label));
+            self.label(label);
+            self.call("sd_init");
+            self.jp_c("ed_io_fail");
+            self.ld_bc(ed_buf);
+            self.ld_de((config.sd_block >> 16) as u16);
+            self.ld_hl(config.sd_block as u16);
+            self.ld_a(blocks as u8);
+            self.label(&next);
+            self.push_af();
+            self.push_de();
+            self.push_hl();
+            self.call(routine);
+            self.ld_b_h();
+            self.ld_c_l();           // BC = next 512 bytes
+            self.pop_hl();
+            self.pop_de();
+            self.jp_c("ed_io_fail_pop");
+            self.inc_hl();
+            self.ld_a_h();
+            self.or_l();
+            self.jp_nz(&same);
+            self.inc_de();
+            self.label(&same);
+            self.pop_af();
+            self.dec_a();
+            self.jr_nz(&next);
+            self.jp(done);
+        }
+
+        self.label("ed_io_fail_pop");
+        self.pop_af();
+        self.label("ed_io_fail");
+        self.ld_a(0x07);             // Bell
+        self.call("conout");
+        self.jp("ed_loop");
+
+        // ed_line_addr: HL = start of current row (clobbers A, B, DE)
+        self.label("ed_line_addr");
+        self.ld_hl(ed_buf);
+        self.ld_a_addr(ed_row);
+        self.or_a_a();
+        self.ret_z();
+        self.ld_b_a();
+        self.ld_de(cols as u16);
+        self.label("ed_line_addr_loop");
+        self.add_hl_de();
+        self.djnz("ed_line_addr_loop");
+        self.ret();
+
+        // ed_cur_addr: HL = buffer cell under the cursor (clobbers A, B, DE)
+        self.label("ed_cur_addr");
+        self.call("ed_line_addr");
+        self.ld_a_addr(ed_col);
+        self.add_a_l();
+        self.emit(&[0x6F]);          // LD L, A
+        self.emit(&[0x30, 0x01]);    // JR NC, +1
+        self.emit(&[0x24]);          // INC H
+        self.ret();
+
+        // ed_place_cursor: move terminal cursor to (row, col)
+        self.label("ed_place_cursor");
+        self.ld_a_addr(ed_row);
+        self.inc_a();
+        self.ld_b_a();
+        self.ld_a_addr(ed_col);
+        self.inc_a();
+        self.ld_c_a();
+        self.jp("cursor_pos");
+
+        // ed_draw_tail: redraw current row from the cursor to its end
+        self.label("ed_draw_tail");
+        self.call("ed_place_cursor");
+        self.call("ed_cur_addr");
+        self.ld_a_addr(ed_col);
+        self.ld_b_a();
+        self.ld_a(cols);
+        self.sub_b();
+        self.ld_b_a();               // B = cols - col
+        self.label("ed_draw_tail_loop");
+        self.ld_a_hl_ind();
+        self.push_hl();
+        self.push_bc();
+        self.call("conout");
+        self.pop_bc();
+        self.pop_hl();
+        self.inc_hl();
+        self.djnz("ed_draw_tail_loop");
+        self.ret();
+
+        // ed_insert: insert A at the cursor, shifting the row right
+        self.label("ed_insert");
+        self.push_af();
+        self.call("ed_line_addr");
+        self.ld_de(cols as u16 - 1);
+        self.add_hl_de();            // HL = last cell of row
+        self.emit(&[0x54]);          // LD D, H
+        self.emit(&[0x5D]);          // LD E, L
+        self.dec_hl();
+        self.ld_a_addr(ed_col);
+        self.ld_c_a();
+        self.ld_a(cols - 1);
+        self.sub_c();
+        self.jp_z("ed_insert_store");
+        self.ld_c_a();
+        self.ld_b(0);
+        self.emit(&[0xED, 0xB8]);    // LDDR (leaves DE at the cursor cell)
+        self.label("ed_insert_store");
+        self.pop_af();
+        self.emit(&[0x12]);          // LD (DE), A
+        self.call("ed_draw_tail");
+        self.ld_a_addr(ed_col);
+        self.cp(cols - 1);
+        self.ret_nc();
+        self.inc_a();
+        self.ld_addr_a(ed_col);
+        self.ret();
+
+        // ed_delete: remove the char at the cursor, shifting the row left
+        self.label("ed_delete");
+        self.call("ed_cur_addr");
+        self.emit(&[0x54]);          // LD D, H
+        self.emit(&[0x5D]);          // LD E, L
+        self.inc_hl();
+        self.ld_a_addr(ed_col);
+        self.ld_c_a();
+        self.ld_a(cols - 1);
+        self.sub_c();
+        self.jp_z("ed_delete_blank");
+        self.ld_c_a();
+        self.ld_b(0);
+        self.emit(&[0xED, 0xB0]);    // LDIR (leaves DE at the last cell)
+        self.label("ed_delete_blank");
+        self.ld_a(b' ');
+        self.emit(&[0x12]);          // LD (DE), A
+        self.jp("ed_draw_tail");
+
+        // ed_redraw: clear the screen and draw every row
+        self.label("ed_redraw");
+        self.call("clear_screen");
+        self.ld_a_addr(ed_row);
+        self.push_af();
+        self.ld_a_addr(ed_col);
+        self.push_af();
+        self.xor_a();
+        self.ld_addr_a(ed_col);
+        self.ld_addr_a(ed_row);
+        self.label("ed_redraw_loop");
+        self.call("ed_draw_tail");
+        self.ld_a_addr(ed_row);
+        self.inc_a();
+        self.ld_addr_a(ed_row);
+        self.cp(rows);
+        self.jr_c("ed_redraw_loop");
+        self.pop_af();
+        self.ld_addr_a(ed_col);
+        self.pop_af();
+        self.ld_addr_a(ed_row);
+        self.ret();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_builds() {
+        let rom = build(&EditConfig::default());
+        assert!(rom.has_label("editor"));
+        assert!(rom.has_label("ed_insert"));
+        assert_eq!(rom.get_label("ed_buf"), Some(0x2002));
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod emulator_tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::stdlib::spi::test_card::SdCard;
+
+    const CONFIG: EditConfig = EditConfig {
+        rows: 4,
+        cols: 8,
+        spi: SpiConfig { out_port: 0x40, in_port: 0x40, sck_bit: 0, mosi_bit: 1, cs_bit: 2, miso_bit: 7 },
+        sd_block: 3,
+    };
+
+    /// Editor with `keys` typed, run until it waits for more
+    fn type_keys(emu: &mut Emulator, keys: &[u8]) {
+        emu.input(keys);
+        emu.run(200_000);
+    }
+
+    fn rows(emu: &Emulator) -> Vec<String> {
+        let buf = emu.label("ed_buf").unwrap();
+        (0..CONFIG.rows as u16)
+            .map(|r| {
+                let row: Vec<u8> = (0..CONFIG.cols as u16).map(|c| emu.peek(buf + r * 8 + c)).collect();
+                String::from_utf8_lossy(&row).trim_end().to_string()
+            })
+            .collect()
+    }
+
+    fn cursor(emu: &Emulator) -> (u8, u8) {
+        (emu.peek(emu.label("ed_row").unwrap()), emu.peek(emu.label("ed_col").unwrap()))
+    }
+
+    fn editor() -> Emulator {
+        let mut emu = Emulator::new(&build(&CONFIG));
+        emu.attach(Box::new(SdCard::new(8)));
+        type_keys(&mut emu, b"");
+        emu
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let mut emu = editor();
+        type_keys(&mut emu, b"abc\x1b[D\x1b[DX");
+        assert_eq!(rows(&emu)[0], "aXbc");
+        assert_eq!(cursor(&emu), (0, 2));
+
+        type_keys(&mut emu, b"\x08");        // Backspace
+        assert_eq!((rows(&emu)[0].as_str(), cursor(&emu)), ("abc", (0, 1)));
+        type_keys(&mut emu, b"\x1b[3~");     // Delete
+        assert_eq!((rows(&emu)[0].as_str(), cursor(&emu)), ("ac", (0, 1)));
+
+        type_keys(&mut emu, b"12345678");    // Full row: the end drops off,
+        assert_eq!(rows(&emu)[0], "a1234568");  // then the last cell is replaced
+        assert_eq!(cursor(&emu), (0, 7));
+    }
+
+    #[test]
+    fn test_cursor_movement() {
+        let mut emu = editor();
+        type_keys(&mut emu, b"\x1b[A\x1b[D");         // Stops at the top left
+        assert_eq!(cursor(&emu), (0, 0));
+        type_keys(&mut emu, b"\x1b[B\x1b[B\x1b[C");
+        assert_eq!(cursor(&emu), (2, 1));
+        type_keys(&mut emu, &b"\x1b[B".repeat(5));   // Stops at the bottom
+        type_keys(&mut emu, &b"\x1b[C".repeat(10));  // ...and the right edge
+        assert_eq!(cursor(&emu), (3, 7));
+        assert_eq!(rows(&emu), ["", "", "", ""]);
+    }
+
+    #[test]
+    fn test_enter_splits_line() {
+        let mut emu = editor();
+        type_keys(&mut emu, b"abcd\rxyz\x1b[A\x1b[D\r");
+        assert_eq!(rows(&emu), ["ab", "cd", "xyz", ""]);
+        assert_eq!(cursor(&emu), (1, 0));
+
+        type_keys(&mut emu, b"\x1b[B\x1b[B\rlast\r");  // No row below the last
+        assert_eq!(rows(&emu), ["ab", "cd", "xyz", "last"]);
+        assert_eq!(cursor(&emu), (3, 0));
+    }
+
+    #[test]
+    fn test_load_save() {
+        let mut emu = editor();
+        type_keys(&mut emu, b"hello\rworld\x0f");   // Ctrl-O
+        type_keys(&mut emu, b"!\x1b[A\x08\x08");
+        assert_eq!(rows(&emu), ["hell", "world!", "", ""]);
+        type_keys(&mut emu, b"\x12");                // Ctrl-R
+        assert_eq!(rows(&emu), ["hello", "world", "", ""]);
+        assert!(!emu.output().contains('\x07'));
+
+        // The text is on the card from `sd_block` on
+        emu.cpu.set_bc(0x3800);
+        emu.cpu.set_de(0);
+        emu.cpu.set_hl(CONFIG.sd_block as u16);
+        assert!(emu.call_label("sd_read_block", 200_000));
+        let block: Vec<u8> = (0..16).map(|i| emu.peek(0x3800 + i)).collect();
+        assert_eq!(block, b"hello   world   ");
+    }
+
+    #[test]
+    fn test_no_card_rings_bell() {
+        let mut emu = Emulator::new(&build(&CONFIG));
+        type_keys(&mut emu, b"\x0f");
+        assert!(emu.output().contains('\x07'));
+    }
+}
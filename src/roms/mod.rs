@@ -0,0 +1,8 @@
+//! Complete ROM generators
+//!
+//! Each module builds a ready-to-flash program on top of the stdlib and
+//! also exposes its emitter so it can be embedded in a larger ROM.
+
+pub mod asm;
+pub mod basic;
+pub mod edit;
@@ -0,0 +1,167 @@
+//! Firmware API jump table
+//!
+//! A table of `JP` instructions at a fixed address gives programs loaded
+//! into RAM entry points that stay put while the ROM behind them moves
+//! between firmware versions. Slot `n` is always at `addr + 3 * n`, so
+//! the table must only ever grow at the end; retire an entry with
+//! `reserved` rather than removing it.
+//!
+//! The same `ApiTable` value describes the layout to client programs:
+//! `import` defines its entries as labels in another `CodeGen`, and
+//! `symbol_file` writes them out for other assemblers.
+
+use crate::CodeGen;
+use std::fs;
+
+/// Size of one slot (a `JP nn`)
+pub const API_SLOT: u16 = 3;
+
+/// Layout of a firmware jump table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiTable {
+    /// Address of slot 0
+    pub addr: u16,
+    /// Slot targets in order; `None` is a reserved slot
+    pub entries: Vec<Option<String>>,
+}
+
+impl ApiTable {
+    /// Empty table at `addr`
+    pub fn new(addr: u16) -> Self {
+        Self {
+            addr,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add a slot jumping to the ROM routine `name`
+    ///
+    /// Panics if `name` is already in the table.
+    pub fn entry(mut self, name: &str) -> Self {
+        assert!(self.slot(name).is_none(), "API entry {} is already in the table", name);
+        self.entries.push(Some(name.to_string()));
+        self
+    }
+
+    /// Add a slot that just returns, keeping later slots in place
+    pub fn reserved(mut self) -> Self {
+        self.entries.push(None);
+        self
+    }
+
+    /// Slot number of `name`
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|e| e.as_deref() == Some(name))
+    }
+
+    /// Entry point of `name`
+    pub fn entry_addr(&self, name: &str) -> Option<u16> {
+        self.slot(name).map(|n| self.addr + n as u16 * API_SLOT)
+    }
+
+    /// Table size in bytes
+    pub fn size(&self) -> u16 {
+        self.entries.len() as u16 * API_SLOT
+    }
+
+    /// Named entry points in slot order
+    pub fn symbols(&self) -> Vec<(String, u16)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(n, e)| e.as_ref().map(|name| (name.clone(), self.addr + n as u16 * API_SLOT)))
+            .collect()
+    }
+
+    /// Define every entry point as a label in a client program, so
+    /// `cg.call("putchar")` goes through the table
+    pub fn import(&self, cg: &mut CodeGen) {
+        for (name, addr) in self.symbols() {
+            cg.equ(&name, addr);
+        }
+    }
+
+    /// Symbol file: one `ADDR label` line per entry point
+    pub fn symbol_file(&self) -> String {
+        self.symbols()
+            .iter()
+            .map(|(name, addr)| format!("{:04X} {}\n", addr, name))
+            .collect()
+    }
+
+    /// Write the symbol file to `path`
+    pub fn write_sym(&self, path: &str) -> std::io::Result<()> {
+        fs::write(path, self.symbol_file())
+    }
+}
+
+impl CodeGen {
+    /// Emit the jump table, padding with zeros up to `table.addr`
+    ///
+    /// Reserved slots are `RET` followed by two `NOP`s.
+    ///
+    /// Labels created: `api_table`
+    /// Requires: every entry's routine
+    pub fn emit_api_table(&mut self, table: &ApiTable) {
+        self.pad_to(table.addr, 0x00);
+        self.label("api_table");
+        for entry in &table.entries {
+            match entry {
+                Some(name) => self.jp(name),
+                None => {
+                    self.ret();
+                    self.nop();
+                    self.nop();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> ApiTable {
+        ApiTable::new(0x0010)
+            .entry("putchar")
+            .reserved()
+            .entry("getchar")
+    }
+
+    #[test]
+    fn test_api_table() {
+        let mut cg = CodeGen::new();
+        cg.emit_api_table(&table());
+        cg.label("putchar");
+        cg.label("getchar");
+        cg.resolve_fixups();
+        assert_eq!(&cg.rom()[0x10..], &[
+            0xC3, 0x19, 0x00,  // JP putchar
+            0xC9,              // RET
+            0x00,              // NOP
+            0x00,              // NOP
+            0xC3, 0x19, 0x00,  // JP getchar
+        ]);
+    }
+
+    #[test]
+    fn test_api_symbols() {
+        let table = table();
+        assert_eq!(table.entry_addr("getchar"), Some(0x0016));
+        assert_eq!(table.size(), 9);
+        assert_eq!(table.symbol_file(), "0010 putchar\n0016 getchar\n");
+
+        let mut client = CodeGen::new();
+        table.import(&mut client);
+        client.call("getchar");
+        client.resolve_fixups();
+        assert_eq!(client.rom(), &[0xCD, 0x16, 0x00]);  // CALL getchar
+    }
+
+    #[test]
+    #[should_panic(expected = "already in the table")]
+    fn test_api_duplicate() {
+        ApiTable::new(0).entry("putchar").entry("putchar");
+    }
+}
@@ -0,0 +1,216 @@
+//! Packed BCD routines
+//!
+//! Arithmetic on multi-byte packed BCD numbers (score counters) plus byte
+//! conversions for RTC chips. Multi-byte numbers are stored least
+//! significant byte first, two digits per byte.
+
+use crate::CodeGen;
+
+impl CodeGen {
+    /// Emit bcd_add routine - (HL) += (DE), B bytes of packed BCD
+    ///
+    /// Returns carry set if the sum overflowed. Leaves HL and DE just past
+    /// the numbers, B = 0.
+    ///
+    /// Labels created: `bcd_add`, `bcd_add_loop`
+    /// Clobbers A, B, DE, HL
+    pub fn emit_bcd_add(&mut self) {
+        self.label("bcd_add");
+        self.or_a_a();               // No carry into the first byte
+        self.label("bcd_add_loop");
+        self.ld_a_de_ind();
+        self.adc_a_hl_ind();
+        self.daa();
+        self.ld_hl_ind_a();
+        self.inc_hl();
+        self.inc_de();
+        self.djnz("bcd_add_loop");
+        self.ret();
+    }
+
+    /// Emit bcd_sub routine - (HL) -= (DE), B bytes of packed BCD
+    ///
+    /// Returns carry set if the result went below zero (the number is then
+    /// the ten's complement). Leaves HL and DE just past the numbers, B = 0.
+    ///
+    /// Labels created: `bcd_sub`, `bcd_sub_loop`
+    /// Clobbers A, B, DE, HL
+    pub fn emit_bcd_sub(&mut self) {
+        self.label("bcd_sub");
+        self.or_a_a();
+        self.label("bcd_sub_loop");
+        self.ld_a_hl_ind();
+        self.ex_de_hl();
+        self.sbc_a_hl_ind();         // There is no SBC A, (DE)
+        self.ex_de_hl();
+        self.daa();
+        self.ld_hl_ind_a();
+        self.inc_hl();
+        self.inc_de();
+        self.djnz("bcd_sub_loop");
+        self.ret();
+    }
+
+    /// Emit bcd_to_bin routine - packed BCD byte in A -> binary (0-99) in A
+    ///
+    /// Labels created: `bcd_to_bin`
+    /// Clobbers A only
+    pub fn emit_bcd_to_bin(&mut self) {
+        self.label("bcd_to_bin");
+        self.push_bc();
+        self.ld_b_a();
+        self.and_a(0xF0);
+        self.rrca();                 // Tens * 8
+        self.ld_c_a();
+        self.rrca();
+        self.rrca();                 // Tens * 2
+        self.add_a_c();
+        self.ld_c_a();               // Tens * 10
+        self.ld_a_b();
+        self.and_a(0x0F);
+        self.add_a_c();
+        self.pop_bc();
+        self.ret();
+    }
+
+    /// Emit bin_to_bcd routine - binary in A -> packed BCD byte in A
+    ///
+    /// Shifts the value in a bit at a time, with DAA keeping the result
+    /// decimal. Values over 99 give their last two digits.
+    ///
+    /// Labels created: `bin_to_bcd`, `bin_to_bcd_loop`
+    /// Clobbers A only
+    pub fn emit_bin_to_bcd(&mut self) {
+        self.label("bin_to_bcd");
+        self.push_bc();
+        self.ld_c_a();
+        self.ld_b(8);
+        self.xor_a();
+        self.label("bin_to_bcd_loop");
+        self.sla_c();
+        self.adc_a_a();              // A = A * 2 + bit
+        self.daa();
+        self.djnz("bin_to_bcd_loop");
+        self.pop_bc();
+        self.ret();
+    }
+
+    /// Emit print_bcd routine - print B bytes of packed BCD at HL
+    ///
+    /// Prints every digit, most significant first (`000120`). A single BCD
+    /// byte in A can be printed with `print_hex_byte`.
+    ///
+    /// Labels created: `print_bcd`, `print_bcd_loop`
+    /// Requires: `print_hex_byte`
+    /// Clobbers A, B, DE, HL
+    pub fn emit_print_bcd(&mut self) {
+        self.label("print_bcd");
+        self.ld_e_b();
+        self.ld_d(0);
+        self.add_hl_de();            // Just past the most significant byte
+        self.label("print_bcd_loop");
+        self.dec_hl();
+        self.ld_a_hl_ind();
+        self.call("print_hex_byte");
+        self.djnz("print_bcd_loop");
+        self.ret();
+    }
+
+    /// Emit all BCD routines
+    pub fn emit_bcd_routines(&mut self) {
+        self.emit_bcd_add();
+        self.emit_bcd_sub();
+        self.emit_bcd_to_bin();
+        self.emit_bin_to_bcd();
+        self.emit_print_bcd();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bcd_add() {
+        let mut cg = CodeGen::new();
+        cg.emit_bcd_add();
+        assert_eq!(cg.rom(), &[
+            0xB7,              // OR A
+            0x1A,              // LD A, (DE)
+            0x8E,              // ADC A, (HL)
+            0x27,              // DAA
+            0x77,              // LD (HL), A
+            0x23,              // INC HL
+            0x13,              // INC DE
+            0x10, 0xF8,        // DJNZ bcd_add_loop
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    fn test_bcd_routines_emit() {
+        let mut cg = CodeGen::new();
+        cg.emit_bcd_routines();
+        for label in ["bcd_add", "bcd_sub", "bcd_to_bin", "bin_to_bcd", "print_bcd"] {
+            assert!(cg.has_label(label), "{}", label);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod emulator_tests {
+    use crate::emulator::Emulator;
+    use crate::CodeGen;
+
+    fn emulator() -> Emulator {
+        let mut rom = CodeGen::new();
+        rom.emit_putchar();
+        rom.emit_conout();
+        rom.emit_print_hex_byte();
+        rom.emit_bcd_routines();
+        rom.resolve_fixups();
+        Emulator::new(&rom)
+    }
+
+    /// 6-digit packed BCD, low byte first
+    fn bcd(n: u32) -> [u8; 3] {
+        let d = |k: u32| ((n / 10u32.pow(k)) % 10) as u8;
+        [d(1) << 4 | d(0), d(3) << 4 | d(2), d(5) << 4 | d(4)]
+    }
+
+    #[test]
+    fn test_run_bcd_add_sub() {
+        let mut emu = emulator();
+        for (label, a, b, sum, carry) in [
+            ("bcd_add", 123456, 654321, 777777, false),
+            ("bcd_add", 999999, 1, 0, true),
+            ("bcd_sub", 120, 99, 21, false),
+            ("bcd_sub", 99, 120, 999979, true),
+        ] {
+            emu.poke_bytes(0x3000, &bcd(a));
+            emu.poke_bytes(0x3010, &bcd(b));
+            emu.cpu.set_hl(0x3000);
+            emu.cpu.set_de(0x3010);
+            emu.cpu.b = 3;
+            assert!(emu.call_label(label, 10_000));
+            assert_eq!([emu.peek(0x3000), emu.peek(0x3001), emu.peek(0x3002)], bcd(sum), "{} {} {}", label, a, b);
+            assert_eq!(emu.cpu.f & 1 != 0, carry, "{} {} {}", label, a, b);
+        }
+    }
+
+    #[test]
+    fn test_run_bcd_convert_and_print() {
+        let mut emu = emulator();
+        emu.cpu.a = 42;
+        assert!(emu.call_label("bin_to_bcd", 10_000));
+        assert_eq!(emu.cpu.a, 0x42);
+        assert!(emu.call_label("bcd_to_bin", 10_000));
+        assert_eq!(emu.cpu.a, 42);
+
+        emu.poke_bytes(0x3000, &bcd(1205));
+        emu.cpu.set_hl(0x3000);
+        emu.cpu.b = 3;
+        assert!(emu.call_label("print_bcd", 10_000));
+        assert_eq!(emu.output(), "001205");
+    }
+}
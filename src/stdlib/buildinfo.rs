@@ -0,0 +1,159 @@
+//! Build metadata block
+//!
+//! `embed_build_info` places a small block in the ROM: a magic number,
+//! then three null-terminated strings - your version string, the build
+//! time (UTC, ISO 8601) and the workbench crate version. `BuildInfo::find`
+//! reads it back out of a .bin, and `print_version` prints it on the
+//! target.
+//!
+//! The build time honours `SOURCE_DATE_EPOCH`, so reproducible builds stay
+//! byte-identical.
+
+use crate::CodeGen;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Marks the start of the block
+pub const BUILD_MAGIC: &[u8; 4] = b"Z80B";
+
+/// Contents of the build metadata block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// User-supplied version string
+    pub version: String,
+    /// Build time, `YYYY-MM-DDTHH:MM:SSZ`
+    pub timestamp: String,
+    /// Version of this crate
+    pub crate_version: String,
+}
+
+impl BuildInfo {
+    /// Metadata for a build of `version` made now
+    pub fn new(version: &str) -> Self {
+        let secs = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+            });
+        Self {
+            version: version.to_string(),
+            timestamp: iso_timestamp(secs),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Find the block in a ROM image; returns its offset and contents
+    pub fn find(image: &[u8]) -> Option<(usize, BuildInfo)> {
+        let mut from = 0;
+        while let Some(pos) = image[from..].windows(4).position(|w| w == BUILD_MAGIC) {
+            let start = from + pos;
+            let mut fields = image[start + 4..].split(|&b| b == 0);
+            let mut next = || fields.next().map(|f| String::from_utf8_lossy(f).into_owned());
+            if let (Some(version), Some(timestamp), Some(crate_version)) = (next(), next(), next()) {
+                // A fourth field means the strings were terminated
+                if next().is_some() {
+                    return Some((start, BuildInfo { version, timestamp, crate_version }));
+                }
+            }
+            from = start + 1;
+        }
+        None
+    }
+}
+
+/// Format seconds since 1970 as `YYYY-MM-DDTHH:MM:SSZ`
+fn iso_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // Civil date from day number (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+impl CodeGen {
+    /// Emit the build metadata block
+    ///
+    /// Panics if the version string contains a NUL.
+    ///
+    /// Labels created: `build_info`, `build_version`
+    pub fn embed_build_info(&mut self, info: &BuildInfo) {
+        assert!(!info.version.contains('\0'), "version string contains a NUL");
+        self.label("build_info");
+        self.emit(BUILD_MAGIC);
+        self.label("build_version");
+        self.emit_string(&info.version);
+        self.emit_string(&info.timestamp);
+        self.emit_string(&info.crate_version);
+    }
+
+    /// Emit print_version routine - print the build metadata on one line
+    ///
+    /// Prints "<version> built <timestamp> workbench <crate version>".
+    ///
+    /// Labels created: `print_version`
+    /// Requires: `build_info`, `print_string`, `newline`
+    /// Clobbers A, HL
+    pub fn emit_print_version(&mut self) {
+        let built = self.pooled_string(" built ");
+        let workbench = self.pooled_string(" workbench ");
+
+        self.label("print_version");
+        self.ld_hl_label("build_version");
+        self.call("print_string");   // Leaves HL on the terminator
+        for sep in [&built, &workbench] {
+            self.inc_hl();
+            self.push_hl();
+            self.ld_hl_label(sep);
+            self.call("print_string");
+            self.pop_hl();
+            self.call("print_string");
+        }
+        self.jp("newline");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iso_timestamp() {
+        assert_eq!(iso_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(iso_timestamp(951_827_696), "2000-02-29T12:34:56Z");
+        assert_eq!(iso_timestamp(1_792_108_800), "2026-10-16T00:00:00Z");
+    }
+
+    #[test]
+    fn test_build_info_roundtrip() {
+        let info = BuildInfo {
+            version: "v1.2".to_string(),
+            timestamp: "2026-10-16T00:00:00Z".to_string(),
+            crate_version: "0.1.1".to_string(),
+        };
+        let mut cg = CodeGen::new();
+        cg.emit(&[0x5A, 0x38]);  // A stray "Z8" before the block
+        cg.embed_build_info(&info);
+        assert_eq!(&cg.rom()[2..11], b"Z80Bv1.2\0");
+        assert_eq!(BuildInfo::find(cg.rom()), Some((2, info)));
+        assert_eq!(BuildInfo::find(b"Z80Bv1\0"), None);
+    }
+}
@@ -0,0 +1,216 @@
+//! Compressed data blobs
+//!
+//! `lz_compress` packs data in Rust at build time; `decompress` expands it
+//! into RAM at run time. The format is a series of tokens, simple enough
+//! for a 40-byte decoder:
+//!
+//! - `0`: end of data
+//! - `0x01`-`0x7F`: that many literal bytes follow
+//! - `0x80`-`0xFF`: copy `(token & 0x7F) + 4` bytes from `offset` bytes
+//!   back in the output; a 16-bit little-endian offset follows
+//!
+//! A copy may overlap its own output (offset 1 repeats the last byte), so
+//! runs compress as well as with RLE.
+
+use crate::CodeGen;
+use std::collections::HashMap;
+
+/// Shortest copy worth a 3-byte token
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = 0x7F + MIN_MATCH;
+const MAX_LITERALS: usize = 0x7F;
+/// Earlier positions tried per match search
+const MAX_CANDIDATES: usize = 256;
+
+/// Compress `data` into the format `decompress` expands
+pub fn lz_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literals: Vec<u8> = Vec::new();
+    // Positions of every 4-byte sequence seen, most recent last
+    let mut seen: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if let Some(candidates) = data.get(pos..pos + MIN_MATCH).and_then(|key| seen.get(key)) {
+            for &from in candidates.iter().rev().take(MAX_CANDIDATES) {
+                if pos - from > 0xFFFF {
+                    break;
+                }
+                let len = (0..MAX_MATCH.min(data.len() - pos))
+                    .take_while(|&i| data[from + i] == data[pos + i])
+                    .count();
+                if len > best.0 {
+                    best = (len, pos - from);
+                }
+            }
+        }
+        let step = if best.0 >= MIN_MATCH {
+            flush_literals(&mut out, &mut literals);
+            out.push(0x80 | (best.0 - MIN_MATCH) as u8);
+            out.extend_from_slice(&(best.1 as u16).to_le_bytes());
+            best.0
+        } else {
+            literals.push(data[pos]);
+            if literals.len() == MAX_LITERALS {
+                flush_literals(&mut out, &mut literals);
+            }
+            1
+        };
+        for p in pos..pos + step {
+            if let Some(key) = data.get(p..p + MIN_MATCH) {
+                seen.entry(key).or_default().push(p);
+            }
+        }
+        pos += step;
+    }
+    flush_literals(&mut out, &mut literals);
+    out.push(0);
+    out
+}
+
+fn flush_literals(out: &mut Vec<u8>, literals: &mut Vec<u8>) {
+    if !literals.is_empty() {
+        out.push(literals.len() as u8);
+        out.append(literals);
+    }
+}
+
+/// Expand data packed by `lz_compress`, as `decompress` does
+///
+/// Returns `None` if the data is malformed.
+pub fn lz_decompress(packed: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    loop {
+        let token = *packed.get(i)? as usize;
+        i += 1;
+        match token {
+            0 => return Some(out),
+            1..=0x7F => {
+                out.extend_from_slice(packed.get(i..i + token)?);
+                i += token;
+            }
+            _ => {
+                let offset = u16::from_le_bytes([*packed.get(i)?, *packed.get(i + 1)?]) as usize;
+                i += 2;
+                let from = out.len().checked_sub(offset)?;
+                for j in 0..(token & 0x7F) + MIN_MATCH {
+                    out.push(out[from + j]);
+                }
+            }
+        }
+    }
+}
+
+impl CodeGen {
+    /// Emit `data` compressed with `lz_compress` at `label`; returns the
+    /// size it expands to, for sizing the RAM buffer
+    ///
+    /// Labels created: `label`
+    pub fn emit_compressed(&mut self, label: &str, data: &[u8]) -> usize {
+        self.label(label);
+        self.emit(&lz_compress(data));
+        data.len()
+    }
+
+    /// Emit decompress routine - expand the data at HL (from
+    /// `emit_compressed`) to DE
+    ///
+    /// Returns HL just past the compressed data and DE just past the
+    /// output.
+    ///
+    /// Labels created: `decompress`, `decompress_match`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_decompress(&mut self) {
+        self.label("decompress");
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.or_a_a();
+        self.ret_z();                // End marker
+        self.jp_m("decompress_match");
+        self.ld_c_a();               // Literals: copy A bytes from the input
+        self.ld_b(0);
+        self.ldir();
+        self.jr("decompress");
+
+        self.label("decompress_match");
+        self.and_a(0x7F);
+        self.add_a(MIN_MATCH as u8);
+        self.ld_c_a();
+        self.ld_b(0);                // BC = length
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.push_hl();
+        self.ld_h_hl_ind();
+        self.ld_l_a();               // HL = offset
+        self.ld_a_e();
+        self.sub_l();
+        self.ld_l_a();
+        self.ld_a_d();
+        self.sbc_a_h();
+        self.ld_h_a();               // HL = DE - offset
+        self.ldir();                 // Byte by byte, so overlapping copies repeat
+        self.pop_hl();
+        self.inc_hl();
+        self.jr("decompress");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lz_round_trip() {
+        assert_eq!(lz_compress(&[]), [0]);
+        assert_eq!(lz_compress(b"abc"), b"\x03abc\x00");
+        assert_eq!(lz_compress(&[7; 10]), [0x01, 7, 0x85, 0x01, 0x00, 0x00]);
+
+        let mut text = Vec::new();
+        for i in 0..2000u32 {
+            text.extend_from_slice(format!("line {} of the splash screen\r\n", i % 37).as_bytes());
+            text.push((i.wrapping_mul(2_654_435_761) >> 24) as u8);
+        }
+        let packed = lz_compress(&text);
+        assert!(packed.len() < text.len() / 4);
+        assert_eq!(lz_decompress(&packed).as_deref(), Some(&text[..]));
+        assert_eq!(lz_decompress(&[0x85, 0x01, 0x00, 0x00]), None);  // Copy before the start
+    }
+
+    #[test]
+    fn test_decompress_routine() {
+        let mut cg = CodeGen::new();
+        cg.emit_decompress();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0x7E,              // decompress: LD A, (HL)
+            0x23,              // INC HL
+            0xB7,              // OR A
+            0xC8,              // RET Z
+            0xFA, 0x0E, 0x00,  // JP M, decompress_match
+            0x4F,              // LD C, A
+            0x06, 0x00,        // LD B, 0
+            0xED, 0xB0,        // LDIR
+            0x18, 0xF2,        // JR decompress
+            0xE6, 0x7F,        // decompress_match: AND 0x7F
+            0xC6, 0x04,        // ADD A, 4
+            0x4F,              // LD C, A
+            0x06, 0x00,        // LD B, 0
+            0x7E,              // LD A, (HL)
+            0x23,              // INC HL
+            0xE5,              // PUSH HL
+            0x66,              // LD H, (HL)
+            0x6F,              // LD L, A
+            0x7B,              // LD A, E
+            0x95,              // SUB L
+            0x6F,              // LD L, A
+            0x7A,              // LD A, D
+            0x9C,              // SBC A, H
+            0x67,              // LD H, A
+            0xED, 0xB0,        // LDIR
+            0xE1,              // POP HL
+            0x23,              // INC HL
+            0x18, 0xDA,        // JR decompress
+        ]);
+    }
+}
@@ -0,0 +1,425 @@
+//! CP/M 2.2 BIOS skeleton and BDOS calls
+//!
+//! Emits the 17-entry BIOS jump vector the BDOS calls through, with the
+//! console entries wired to the MC6850 routines from `stdlib::io` and the
+//! disk entries pointed at user-supplied handlers. Whatever is not supplied
+//! gets a harmless stub: no disks, read/write errors, no sector skew, and
+//! the character devices other than the console discard output.
+//!
+//! The BIOS is padded to `bios_addr` in the current image, so build the
+//! system image with `RomConfig::org` at the CCP address (`bios_addr -
+//! 0x1600` for the standard CCP and BDOS sizes), place the CCP and BDOS
+//! binaries first and emit the BIOS after them.
+//!
+//! For programs running under CP/M (`.COM` files, `RomConfig::org` =
+//! `TPA`), the `bdos_*` helpers load C (and DE) and call the BDOS at
+//! 0x0005. The BDOS clobbers all registers.
+
+use crate::CodeGen;
+
+/// Standard CP/M 2.2 sizes: CCP 0x800 bytes, BDOS 0xE00 bytes
+const CCP_SIZE: u16 = 0x0800;
+const BDOS_SIZE: u16 = 0x0E00;
+
+/// Page-zero locations
+const IOBYTE: u16 = 0x0003;
+const CDISK: u16 = 0x0004;
+const BDOS_JP: u16 = 0x0005;
+const DEFAULT_DMA: u16 = 0x0080;
+
+/// BDOS entry called by programs
+pub const BDOS: u16 = 0x0005;
+/// Load address of `.COM` programs
+pub const TPA: u16 = 0x0100;
+/// Default FCB built by the CCP from the first command-line argument
+pub const DEFAULT_FCB: u16 = 0x005C;
+
+/// BDOS function numbers (in C)
+pub const BDOS_CONIN: u8 = 1;
+pub const BDOS_CONOUT: u8 = 2;
+pub const BDOS_DIRECT_IO: u8 = 6;
+pub const BDOS_PRINT_STRING: u8 = 9;
+pub const BDOS_READ_LINE: u8 = 10;
+pub const BDOS_CONST: u8 = 11;
+pub const BDOS_RESET_DISKS: u8 = 13;
+pub const BDOS_SELECT_DISK: u8 = 14;
+pub const BDOS_OPEN: u8 = 15;
+pub const BDOS_CLOSE: u8 = 16;
+pub const BDOS_SEARCH_FIRST: u8 = 17;
+pub const BDOS_SEARCH_NEXT: u8 = 18;
+pub const BDOS_DELETE: u8 = 19;
+pub const BDOS_READ_SEQ: u8 = 20;
+pub const BDOS_WRITE_SEQ: u8 = 21;
+pub const BDOS_MAKE: u8 = 22;
+pub const BDOS_RENAME: u8 = 23;
+pub const BDOS_SET_DMA: u8 = 26;
+pub const BDOS_READ_RANDOM: u8 = 33;
+pub const BDOS_WRITE_RANDOM: u8 = 34;
+
+/// User-supplied disk handlers; `None` selects the stub
+///
+/// The handlers follow the CP/M 2.2 BIOS conventions. The current track,
+/// sector and DMA address set by the BDOS are in the RAM variables
+/// `bios_track`, `bios_sector` and `bios_dma` (2 bytes each).
+#[derive(Clone, Debug, Default)]
+pub struct CpmDiskHooks {
+    /// SELDSK: C = drive, return HL = disk parameter header or 0
+    pub seldsk: Option<String>,
+    /// READ: read the current sector to the DMA address, A = 0 or 1 (error)
+    pub read: Option<String>,
+    /// WRITE: write the current sector (C = deferred/directory/unallocated
+    /// hint), A = 0 or 1 (error)
+    pub write: Option<String>,
+    /// SECTRAN: BC = logical sector, DE = translate table, return HL
+    pub sectran: Option<String>,
+    /// Called by WBOOT to reload the CCP and BDOS; without it the copy in
+    /// memory is reused
+    pub reload: Option<String>,
+    /// Called once by BOOT before CP/M starts (e.g. `acia_init`)
+    pub boot: Option<String>,
+}
+
+/// BIOS placement and hooks for `emit_cpm_bios`
+#[derive(Clone, Debug)]
+pub struct CpmConfig {
+    /// Address of the jump vector (0xFA00 in a 64K system)
+    pub bios_addr: u16,
+    pub disk: CpmDiskHooks,
+}
+
+impl Default for CpmConfig {
+    fn default() -> Self {
+        Self {
+            bios_addr: 0xFA00,
+            disk: CpmDiskHooks::default(),
+        }
+    }
+}
+
+impl CpmConfig {
+    /// Start of the CCP
+    pub fn ccp_addr(&self) -> u16 {
+        self.bios_addr - BDOS_SIZE - CCP_SIZE
+    }
+
+    /// BDOS entry point (the target of `CALL 5`)
+    pub fn bdos_entry(&self) -> u16 {
+        self.bios_addr - BDOS_SIZE + 6
+    }
+}
+
+impl CodeGen {
+    /// Emit a CP/M 2.2 BIOS at `config.bios_addr`
+    ///
+    /// Vector order: BOOT, WBOOT, CONST, CONIN, CONOUT, LIST, PUNCH,
+    /// READER, HOME, SELDSK, SETTRK, SETSEC, SETDMA, READ, WRITE, LISTST,
+    /// SECTRAN. BOOT clears IOBYTE and selects drive A; BOOT and WBOOT then
+    /// set up the page-zero jumps at 0x0000 and 0x0005, reset the DMA
+    /// address to 0x0080 and enter the CCP with the current drive in C.
+    ///
+    /// Labels created: `bios`, `bios_boot`, `bios_wboot`, `bios_const`,
+    /// `bios_conin`, `bios_conout`, `bios_list`, `bios_reader`,
+    /// `bios_home`, `bios_seldsk`, `bios_settrk`, `bios_setsec`,
+    /// `bios_setdma`, `bios_read`, `bios_write`, `bios_listst`,
+    /// `bios_sectran`, `bios_gocpm`
+    /// RAM used: `bios_track`, `bios_sector`, `bios_dma`
+    /// Requires: `char_available`, `getchar`, `conout`, and the hooks
+    pub fn emit_cpm_bios(&mut self, config: &CpmConfig) {
+        let hooks = &config.disk;
+        let track = self.ram_var("bios_track", 2);
+        let sector = self.ram_var("bios_sector", 2);
+        let dma = self.ram_var("bios_dma", 2);
+        let hook = |h: &Option<String>, stub: &str| h.clone().unwrap_or_else(|| stub.to_string());
+
+        self.pad_to(config.bios_addr, 0x00);
+        self.label("bios");
+        for target in [
+            "bios_boot".to_string(),
+            "bios_wboot".to_string(),
+            "bios_const".to_string(),
+            "bios_conin".to_string(),
+            "bios_conout".to_string(),
+            "bios_list".to_string(),
+            "bios_list".to_string(),     // PUNCH
+            "bios_reader".to_string(),
+            "bios_home".to_string(),
+            hook(&hooks.seldsk, "bios_seldsk"),
+            "bios_settrk".to_string(),
+            "bios_setsec".to_string(),
+            "bios_setdma".to_string(),
+            hook(&hooks.read, "bios_read"),
+            hook(&hooks.write, "bios_write"),
+            "bios_listst".to_string(),
+            hook(&hooks.sectran, "bios_sectran"),
+        ] {
+            self.jp(&target);
+        }
+
+        self.label("bios_boot");
+        self.ld_sp(DEFAULT_DMA);
+        if let Some(boot) = &hooks.boot {
+            self.call(boot);
+        }
+        self.xor_a();
+        self.ld_addr_a(IOBYTE);
+        self.ld_addr_a(CDISK);
+        self.jp("bios_gocpm");
+
+        self.label("bios_wboot");
+        self.ld_sp(DEFAULT_DMA);
+        if let Some(reload) = &hooks.reload {
+            self.call(reload);
+        }
+
+        self.label("bios_gocpm");
+        self.ld_a(0xC3);            // JP
+        self.ld_addr_a(0x0000);
+        self.ld_hl(config.bios_addr + 3);
+        self.ld_addr_hl(0x0001);    // JP WBOOT
+        self.ld_addr_a(BDOS_JP);
+        self.ld_hl(config.bdos_entry());
+        self.ld_addr_hl(BDOS_JP + 1);
+        self.ld_bc(DEFAULT_DMA);
+        self.call("bios_setdma");
+        self.ld_a_addr(CDISK);
+        self.ld_c_a();
+        self.jp_addr(config.ccp_addr());
+
+        // Console: A = 0xFF if a character is waiting, else 0
+        self.label("bios_const");
+        self.call("char_available");
+        self.ret_z();
+        self.ld_a(0xFF);
+        self.ret();
+
+        self.label("bios_conin");
+        self.call("getchar");
+        self.and_a(0x7F);
+        self.ret();
+
+        self.label("bios_conout");
+        self.ld_a_c();
+        self.jp("conout");
+
+        // LIST and PUNCH discard, READER is always at end of file
+        self.label("bios_list");
+        self.ret();
+        self.label("bios_reader");
+        self.ld_a(0x1A);
+        self.ret();
+        self.label("bios_listst");
+        self.xor_a();               // Never ready
+        self.ret();
+
+        self.label("bios_home");
+        self.ld_bc(0);
+        self.label("bios_settrk");
+        self.ld_addr_bc(track);
+        self.ret();
+        self.label("bios_setsec");
+        self.ld_addr_bc(sector);
+        self.ret();
+        self.label("bios_setdma");
+        self.ld_addr_bc(dma);
+        self.ret();
+
+        if hooks.seldsk.is_none() {
+            self.label("bios_seldsk");
+            self.ld_hl(0);          // No such drive
+            self.ret();
+        }
+        if hooks.read.is_none() || hooks.write.is_none() {
+            self.label("bios_read");
+            self.label("bios_write");
+            self.ld_a(1);           // Error
+            self.ret();
+        }
+        if hooks.sectran.is_none() {
+            self.label("bios_sectran");
+            self.ld_h_b();          // No skew
+            self.ld_l_c();
+            self.ret();
+        }
+    }
+}
+
+impl CodeGen {
+    /// Emit a BDOS call: `LD C, func` and `CALL 0x0005`
+    pub fn bdos(&mut self, func: u8) {
+        self.ld_c(func);
+        self.call_addr(BDOS);
+    }
+
+    /// Emit a BDOS call with DE = `label`
+    pub fn bdos_de(&mut self, func: u8, label: &str) {
+        self.ld_de_label(label);
+        self.bdos(func);
+    }
+
+    /// Return to CP/M (warm boot through 0x0000)
+    pub fn bdos_exit(&mut self) {
+        self.jp_addr(0x0000);
+    }
+
+    /// Wait for a console character and echo it; A = character
+    pub fn bdos_conin(&mut self) {
+        self.bdos(BDOS_CONIN);
+    }
+
+    /// Print the character in E
+    pub fn bdos_conout(&mut self) {
+        self.bdos(BDOS_CONOUT);
+    }
+
+    /// A = 0xFF if a console character is waiting, else 0
+    pub fn bdos_const(&mut self) {
+        self.bdos(BDOS_CONST);
+    }
+
+    /// Print the `$`-terminated string at `label` (see `emit_bdos_string`)
+    pub fn bdos_print_string(&mut self, label: &str) {
+        self.bdos_de(BDOS_PRINT_STRING, label);
+    }
+
+    /// Read an edited line into the buffer at `label`: byte 0 = capacity,
+    /// byte 1 = length read, then the characters
+    pub fn bdos_read_line(&mut self, label: &str) {
+        self.bdos_de(BDOS_READ_LINE, label);
+    }
+
+    /// Select drive E (0 = A:)
+    pub fn bdos_select_disk(&mut self) {
+        self.bdos(BDOS_SELECT_DISK);
+    }
+
+    /// Set the DMA address for file reads and writes to `label`
+    pub fn bdos_set_dma(&mut self, label: &str) {
+        self.bdos_de(BDOS_SET_DMA, label);
+    }
+
+    /// Open the file named by `fcb`; A = 0xFF if not found
+    pub fn bdos_open_file(&mut self, fcb: &str) {
+        self.bdos_de(BDOS_OPEN, fcb);
+    }
+
+    /// Close the file; A = 0xFF on error
+    pub fn bdos_close_file(&mut self, fcb: &str) {
+        self.bdos_de(BDOS_CLOSE, fcb);
+    }
+
+    /// Create the file; A = 0xFF if the directory is full
+    pub fn bdos_make_file(&mut self, fcb: &str) {
+        self.bdos_de(BDOS_MAKE, fcb);
+    }
+
+    /// Delete the files matching `fcb`; A = 0xFF if none
+    pub fn bdos_delete_file(&mut self, fcb: &str) {
+        self.bdos_de(BDOS_DELETE, fcb);
+    }
+
+    /// Read the next 128-byte record to the DMA address; A = 0, or
+    /// non-zero at end of file
+    pub fn bdos_read_seq(&mut self, fcb: &str) {
+        self.bdos_de(BDOS_READ_SEQ, fcb);
+    }
+
+    /// Write the next 128-byte record from the DMA address; A = 0, or
+    /// non-zero if the disk is full
+    pub fn bdos_write_seq(&mut self, fcb: &str) {
+        self.bdos_de(BDOS_WRITE_SEQ, fcb);
+    }
+
+    /// Emit `s` terminated by `$` for `bdos_print_string`
+    ///
+    /// Panics if `s` contains `$`.
+    pub fn emit_bdos_string(&mut self, s: &str) {
+        assert!(!s.contains('$'), "BDOS strings cannot contain `$`");
+        self.emit(s.as_bytes());
+        self.emit_byte(b'$');
+    }
+
+    /// Emit a 36-byte FCB for `name` (8.3, e.g. `"B:DATA.TXT"`)
+    ///
+    /// The name is upper-cased and blank-padded; an optional `X:` prefix
+    /// selects the drive, otherwise the default drive is used.
+    ///
+    /// Labels created: `label`
+    pub fn emit_fcb(&mut self, label: &str, name: &str) {
+        let name = name.to_ascii_uppercase();
+        let (drive, name) = match name.as_bytes() {
+            [d @ b'A'..=b'P', b':', ..] => (d - b'A' + 1, &name[2..]),
+            _ => (0, &name[..]),
+        };
+        let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+        assert!(
+            !base.is_empty() && base.len() <= 8 && ext.len() <= 3,
+            "`{}` is not an 8.3 file name",
+            name
+        );
+        self.label(label);
+        self.emit_byte(drive);
+        self.emit(format!("{:<8}{:<3}", base, ext).as_bytes());
+        self.emit(&[0; 24]);        // Extent, S1, S2, RC, map, CR, R0-R2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RomConfig;
+
+    #[test]
+    fn test_addresses() {
+        let config = CpmConfig::default();
+        assert_eq!(config.ccp_addr(), 0xE400);
+        assert_eq!(config.bdos_entry(), 0xEC06);
+    }
+
+    #[test]
+    fn test_jump_vector() {
+        let mut cg = CodeGen::with_config(RomConfig { org: 0xE400, ..Default::default() });
+        let config = CpmConfig {
+            disk: CpmDiskHooks { read: Some("sd_read".into()), ..Default::default() },
+            ..Default::default()
+        };
+        cg.emit_cpm_bios(&config);
+        for label in ["sd_read", "char_available", "getchar", "conout"] {
+            cg.label(label);
+        }
+        cg.ret();
+        cg.resolve_fixups();
+        let bios = 0x1600;
+        assert_eq!(cg.get_label("bios"), Some(0xFA00));
+        assert_eq!(cg.rom()[bios..bios + 51].iter().step_by(3).filter(|&&b| b == 0xC3).count(), 17);
+        let read = cg.get_label("sd_read").unwrap().to_le_bytes();
+        assert_eq!(&cg.rom()[bios + 39..bios + 42], &[0xC3, read[0], read[1]]);
+        let boot = cg.get_label("bios_boot").unwrap().to_le_bytes();
+        assert_eq!(&cg.rom()[bios..bios + 3], &[0xC3, boot[0], boot[1]]);
+    }
+
+    #[test]
+    fn test_bdos_print_string() {
+        let mut cg = CodeGen::with_config(RomConfig { org: TPA, ..Default::default() });
+        cg.bdos_print_string("msg");
+        cg.bdos_exit();
+        cg.label("msg");
+        cg.emit_bdos_string("Hi");
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0x11, 0x0B, 0x01,  // LD DE, msg
+            0x0E, 0x09,        // LD C, 9
+            0xCD, 0x05, 0x00,  // CALL 0x0005
+            0xC3, 0x00, 0x00,  // JP 0x0000
+            b'H', b'i', b'$',
+        ]);
+    }
+
+    #[test]
+    fn test_fcb() {
+        let mut cg = CodeGen::new();
+        cg.emit_fcb("fcb", "b:data.txt");
+        assert_eq!(cg.size(), 36);
+        assert_eq!(&cg.rom()[..12], b"\x02DATA    TXT");
+        assert!(cg.rom()[12..].iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,229 @@
+//! CRC routines
+//!
+//! Bitwise CRC-8 and CRC-16/CCITT over a buffer at HL with length BC, for
+//! XMODEM blocks, SD card data and ROM self-checks. No lookup tables, so
+//! they cost a few hundred T-states per byte but only a few dozen bytes.
+
+use crate::CodeGen;
+
+/// CRC-16/CCITT of `data`, continuing from `crc`; what `crc16` computes
+pub fn crc16_ccitt(data: &[u8], mut crc: u16) -> u16 {
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+impl CodeGen {
+    /// Emit crc16 routine - CRC-16/CCITT (polynomial 0x1021) of BC bytes at HL
+    ///
+    /// DE holds the running CRC: start it at 0 for XMODEM and SD cards, or
+    /// 0xFFFF for CRC-16/CCITT-FALSE. Calls can be chained across buffers.
+    /// Returns DE = CRC, HL just past the buffer, BC = 0.
+    ///
+    /// Labels created: `crc16`, `crc16_byte`, `crc16_bit`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_crc16(&mut self) {
+        self.label("crc16");
+        self.ld_a_b();
+        self.or_c();
+        self.ret_z();
+
+        self.label("crc16_byte");
+        self.ld_a_hl_ind();
+        self.xor_d();
+        self.ld_d_a();               // Byte into the CRC's high half
+        self.push_bc();
+        self.ld_b(8);
+        self.label("crc16_bit");
+        self.sla_e();
+        self.rl_d();
+        self.emit(&[0x30, 0x08]);    // JR NC, +8
+        self.ld_a_d();
+        self.xor_n(0x10);
+        self.ld_d_a();
+        self.ld_a_e();
+        self.xor_n(0x21);
+        self.ld_e_a();
+        self.djnz("crc16_bit");
+        self.pop_bc();
+        self.inc_hl();
+        self.dec_bc();
+        self.ld_a_b();
+        self.or_c();
+        self.jr_nz("crc16_byte");
+        self.ret();
+    }
+
+    /// Emit crc8 routine - CRC-8 (polynomial 0x07) of BC bytes at HL
+    ///
+    /// A holds the running CRC (usually 0) on entry and the CRC on return.
+    /// Leaves HL just past the buffer, BC = 0.
+    ///
+    /// Labels created: `crc8`, `crc8_byte`, `crc8_bit`, `crc8_done`
+    /// Clobbers A, BC, HL
+    pub fn emit_crc8(&mut self) {
+        self.label("crc8");
+        self.push_de();
+        self.ld_e_a();               // E = CRC
+
+        self.label("crc8_byte");
+        self.ld_a_b();
+        self.or_c();
+        self.jp_z("crc8_done");
+        self.ld_a_hl_ind();
+        self.xor_e();
+        self.ld_d(8);
+        self.label("crc8_bit");
+        self.add_a_a();
+        self.emit(&[0x30, 0x02]);    // JR NC, +2
+        self.xor_n(0x07);
+        self.dec_d();
+        self.jr_nz("crc8_bit");
+        self.ld_e_a();
+        self.inc_hl();
+        self.dec_bc();
+        self.jp("crc8_byte");
+
+        self.label("crc8_done");
+        self.ld_a_e();
+        self.pop_de();
+        self.ret();
+    }
+
+    /// Emit rom_selfcheck routine - check the image against the slot
+    /// reserved by `embed_checksum(label)`
+    ///
+    /// Prints "ROM PASS" or "ROM FAIL" and a newline. Returns carry set on
+    /// a mismatch.
+    ///
+    /// Labels created: `rom_selfcheck`
+    /// Requires: `crc16`, `print_string`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_rom_selfcheck(&mut self, label: &str) {
+        let org = self.config().org;
+        let pass = self.pooled_string("ROM PASS\r\n");
+        let fail = self.pooled_string("ROM FAIL\r\n");
+
+        self.label("rom_selfcheck");
+        self.emit(&[0x2A]);          // LD HL, (label + 2)
+        self.fixup_expr(label, 2);
+        self.emit(&[0x01]);          // LD BC, label + 4
+        self.fixup_expr(label, 4);
+        self.or_a_a();
+        self.sbc_hl_bc();
+        self.push_hl();              // Length after the slot
+        self.ld_hl(org);
+        self.emit(&[0x01]);          // LD BC, label - org
+        self.fixup_expr(label, -(org as i32));
+        self.ld_de(0);
+        self.call("crc16");
+        self.ld_bc(4);
+        self.add_hl_bc();            // Skip the slot
+        self.pop_bc();
+        self.call("crc16");
+        self.emit(&[0x2A]);          // LD HL, (label)
+        self.fixup(label);
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.ld_hl_label(&pass);
+        self.jp_z("print_string");   // Carry clear
+        self.ld_hl_label(&fail);
+        self.call("print_string");
+        self.scf();
+        self.ret();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16_ccitt() {
+        assert_eq!(crc16_ccitt(b"123456789", 0), 0x31C3);     // XMODEM
+        assert_eq!(crc16_ccitt(b"123456789", 0xFFFF), 0x29B1);
+    }
+
+    #[test]
+    fn test_embed_checksum() {
+        let mut cg = CodeGen::new();
+        cg.emit(&[0x01, 0x02]);
+        cg.embed_checksum("rom_crc");
+        cg.emit(&[0x03]);
+        cg.resolve_fixups();
+        let crc = crc16_ccitt(&[0x01, 0x02, 0x03], 0);
+        assert_eq!(cg.rom(), &[0x01, 0x02, crc as u8, (crc >> 8) as u8, 0x07, 0x00, 0x03]);
+    }
+
+    #[test]
+    fn test_crc8() {
+        let mut cg = CodeGen::new();
+        cg.emit_crc8();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0xD5,              // PUSH DE
+            0x5F,              // LD E, A
+            0x78,              // LD A, B
+            0xB1,              // OR C
+            0xCA, 0x19, 0x00,  // JP Z, crc8_done
+            0x7E,              // LD A, (HL)
+            0xAB,              // XOR E
+            0x16, 0x08,        // LD D, 8
+            0x87,              // ADD A, A
+            0x30, 0x02,        // JR NC, +2
+            0xEE, 0x07,        // XOR 0x07
+            0x15,              // DEC D
+            0x20, 0xF8,        // JR NZ, crc8_bit
+            0x5F,              // LD E, A
+            0x23,              // INC HL
+            0x0B,              // DEC BC
+            0xC3, 0x02, 0x00,  // JP crc8_byte
+            0x7B,              // LD A, E
+            0xD1,              // POP DE
+            0xC9,              // RET
+        ]);
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod emulator_tests {
+    use super::crc16_ccitt;
+    use crate::emulator::Emulator;
+    use crate::CodeGen;
+
+    /// Run `label` over `data` at 0x3000 with DE and A holding `crc`;
+    /// returns the emulator after checking HL and BC
+    fn run(label: &str, data: &[u8], crc: u16) -> Emulator {
+        let mut rom = CodeGen::new();
+        rom.emit_crc8();
+        rom.emit_crc16();
+        rom.resolve_fixups();
+        let mut emu = Emulator::new(&rom);
+        emu.poke_bytes(0x3000, data);
+        emu.cpu.set_hl(0x3000);
+        emu.cpu.set_bc(data.len() as u16);
+        emu.cpu.set_de(crc);
+        emu.cpu.a = crc as u8;
+        assert!(emu.call_label(label, 100_000));
+        assert_eq!((emu.cpu.hl(), emu.cpu.bc()), (0x3000 + data.len() as u16, 0));
+        emu
+    }
+
+    #[test]
+    fn test_run_crc16() {
+        for (data, init) in [(&b"123456789"[..], 0), (b"123456789", 0xFFFF), (b"", 0x1234), (b"\x00\xFF", 0)] {
+            assert_eq!(run("crc16", data, init).cpu.de(), crc16_ccitt(data, init));
+        }
+    }
+
+    #[test]
+    fn test_run_crc8() {
+        let emu = run("crc8", b"123456789", 0x5500);
+        assert_eq!((emu.cpu.a, emu.cpu.de()), (0xF4, 0x5500));  // Check value, DE preserved
+        assert_eq!(run("crc8", b"", 0x0042).cpu.a, 0x42);
+    }
+}
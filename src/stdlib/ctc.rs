@@ -0,0 +1,252 @@
+//! Z80 CTC (counter/timer circuit) setup and interrupt vectors
+//!
+//! The CTC's four channels sit at `base_port` .. `base_port + 3`. Each
+//! configured channel is programmed by `ctc_init` as a timer or a counter;
+//! a channel with a handler also has its interrupt enabled. In IM 2 the
+//! CTC supplies a real vector byte, so its interrupts go through the small
+//! aligned `ctc_vectors` table instead of the 257-byte `emit_im2_table`.
+//!
+//! For the tick counter in `stdlib::timer`, give a timer channel the
+//! handler `timer_isr` and call `ctc_init` followed by EI in place of
+//! `timer_start`.
+
+use crate::CodeGen;
+
+/// Channel control word bits
+const CTC_IRQ: u8 = 0x80;
+const CTC_COUNTER: u8 = 0x40;
+const CTC_PRESCALE_256: u8 = 0x20;
+const CTC_RISING: u8 = 0x10;
+const CTC_CONSTANT: u8 = 0x04;
+const CTC_RESET: u8 = 0x02;
+const CTC_CONTROL: u8 = 0x01;
+
+/// What a CTC channel counts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CtcMode {
+    /// Timer interrupting at about `hz`; prescaler and time constant are
+    /// chosen from `RomConfig::clock_hz`
+    Timer { hz: u32 },
+    /// Timer with an explicit prescaler (16 or 256) and time constant
+    /// (1-256)
+    TimerRaw { prescaler: u16, constant: u16 },
+    /// Counter: one zero count per `count` CLK/TRG edges (1-256)
+    Counter { count: u16, rising: bool },
+}
+
+/// One CTC channel to program
+#[derive(Clone, Debug)]
+pub struct CtcChannel {
+    /// Channel number, 0-3
+    pub channel: u8,
+    pub mode: CtcMode,
+    /// Interrupt handler label; `None` leaves the channel's interrupt off
+    pub handler: Option<String>,
+}
+
+/// CTC configuration for `emit_ctc_init` and `emit_ctc_vectors`
+#[derive(Clone, Debug)]
+pub struct CtcConfig {
+    /// Port of channel 0
+    pub base_port: u8,
+    /// Channels to program; the rest are reset and left stopped
+    pub channels: Vec<CtcChannel>,
+}
+
+/// Prescaler and time constant for a timer of `hz` at `clock_hz`
+///
+/// Prefers the finer prescaler of 16 when the constant fits. Panics if
+/// the rate is out of reach of both prescalers.
+pub fn ctc_timer_constant(clock_hz: u32, hz: u32) -> (u16, u16) {
+    assert!(hz > 0, "CTC timer rate must be non-zero");
+    for prescaler in [16u32, 256] {
+        let div = prescaler as u64 * hz as u64;
+        let constant = (clock_hz as u64 + div / 2) / div;
+        if (1..=256).contains(&constant) {
+            return (prescaler as u16, constant as u16);
+        }
+    }
+    panic!("CTC timer rate {} Hz is out of range at {} Hz", hz, clock_hz);
+}
+
+impl CtcConfig {
+    /// Control word and time constant byte for `ch`
+    fn channel_words(&self, ch: &CtcChannel, clock_hz: u32) -> (u8, u8) {
+        let irq = if ch.handler.is_some() { CTC_IRQ } else { 0 };
+        let base = irq | CTC_CONSTANT | CTC_RESET | CTC_CONTROL;
+        let (mode, constant) = match ch.mode {
+            CtcMode::Timer { hz } => {
+                let (prescaler, constant) = ctc_timer_constant(clock_hz, hz);
+                (if prescaler == 256 { CTC_PRESCALE_256 } else { 0 }, constant)
+            }
+            CtcMode::TimerRaw { prescaler, constant } => {
+                assert!(
+                    prescaler == 16 || prescaler == 256,
+                    "CTC prescaler must be 16 or 256, got {}",
+                    prescaler
+                );
+                (if prescaler == 256 { CTC_PRESCALE_256 } else { 0 }, constant)
+            }
+            CtcMode::Counter { count, rising } => {
+                (CTC_COUNTER | if rising { CTC_RISING } else { 0 }, count)
+            }
+        };
+        assert!(
+            (1..=256).contains(&constant),
+            "CTC channel {} time constant {} is not 1-256",
+            ch.channel,
+            constant
+        );
+        (base | mode, constant as u8) // 256 is written as 0
+    }
+
+    fn has_handlers(&self) -> bool {
+        self.channels.iter().any(|ch| ch.handler.is_some())
+    }
+}
+
+impl CodeGen {
+    /// Emit ctc_init routine - program the configured CTC channels
+    ///
+    /// Channels not in the config get a software reset. If any channel has
+    /// a handler, it also points I at `ctc_vectors`, writes the vector to
+    /// channel 0 and selects IM 2; interrupts stay disabled until EI.
+    ///
+    /// Labels created: `ctc_init`
+    /// Requires: `ctc_vectors` (when a channel has a handler)
+    /// Clobbers: A
+    pub fn emit_ctc_init(&mut self, config: &CtcConfig) {
+        let clock_hz = self.config().clock_hz;
+        for (i, ch) in config.channels.iter().enumerate() {
+            assert!(ch.channel < 4, "CTC channel {} does not exist", ch.channel);
+            assert!(
+                config.channels[..i].iter().all(|c| c.channel != ch.channel),
+                "CTC channel {} configured twice",
+                ch.channel
+            );
+        }
+
+        self.label("ctc_init");
+        if config.has_handlers() {
+            self.ld_a_label_hi("ctc_vectors");
+            self.ld_i_a();
+            self.im2();
+            self.ld_a_label_lo("ctc_vectors");
+            self.out_a(config.base_port); // Vector word (bit 0 clear)
+        }
+        for n in 0..4u8 {
+            let port = config.base_port.wrapping_add(n);
+            match config.channels.iter().find(|ch| ch.channel == n) {
+                Some(ch) => {
+                    let (control, constant) = config.channel_words(ch, clock_hz);
+                    self.ld_a(control);
+                    self.out_a(port);
+                    self.ld_a(constant);
+                    self.out_a(port);
+                }
+                None => {
+                    self.ld_a(CTC_RESET | CTC_CONTROL);
+                    self.out_a(port);
+                }
+            }
+        }
+        self.ret();
+    }
+
+    /// Emit the CTC IM 2 vector table: one word per channel
+    ///
+    /// Pads to an 8-byte boundary (0xFF) first, as the CTC supplies only
+    /// bits 7-3 of the vector. Channels without a handler point at
+    /// `ctc_unused` (EI, RETI), emitted after the table if needed.
+    ///
+    /// Labels created: `ctc_vectors`, `ctc_unused`
+    pub fn emit_ctc_vectors(&mut self, config: &CtcConfig) {
+        let aligned = (self.pos() + 7) & !7;
+        self.pad_to(aligned, 0xFF);
+        self.label("ctc_vectors");
+        let mut unused = false;
+        for n in 0..4u8 {
+            match config.channels.iter().find(|ch| ch.channel == n).and_then(|ch| ch.handler.as_deref()) {
+                Some(handler) => self.fixup(handler),
+                None => {
+                    unused = true;
+                    self.fixup("ctc_unused");
+                }
+            }
+        }
+        if unused {
+            self.label("ctc_unused");
+            self.ei();
+            self.reti();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_constant() {
+        assert_eq!(ctc_timer_constant(4_000_000, 100), (256, 156));
+        assert_eq!(ctc_timer_constant(4_000_000, 1000), (16, 250));
+        assert_eq!(ctc_timer_constant(4_000_000, 10_000), (16, 25));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_timer_too_slow() {
+        ctc_timer_constant(4_000_000, 50);
+    }
+
+    #[test]
+    fn test_ctc_init() {
+        let config = CtcConfig {
+            base_port: 0x10,
+            channels: vec![
+                CtcChannel {
+                    channel: 1,
+                    mode: CtcMode::Timer { hz: 100 },
+                    handler: Some("timer_isr".into()),
+                },
+                CtcChannel {
+                    channel: 3,
+                    mode: CtcMode::Counter { count: 256, rising: true },
+                    handler: None,
+                },
+            ],
+        };
+        let mut cg = CodeGen::new();
+        cg.emit_ctc_init(&config);
+        cg.label("timer_isr");
+        cg.emit_ctc_vectors(&config);
+        cg.resolve_fixups();
+        assert_eq!(&cg.rom()[..29], &[
+            0x3E, 0x00,        // LD A, high(ctc_vectors)
+            0xED, 0x47,        // LD I, A
+            0xED, 0x5E,        // IM 2
+            0x3E, 0x28,        // LD A, low(ctc_vectors)
+            0xD3, 0x10,        // OUT (0x10), A
+            0x3E, 0x03,        // LD A, 0x03 (reset channel 0)
+            0xD3, 0x10,        // OUT (0x10), A
+            0x3E, 0xA7,        // LD A, 0xA7 (timer /256, interrupt)
+            0xD3, 0x11,        // OUT (0x11), A
+            0x3E, 0x9C,        // LD A, 156
+            0xD3, 0x11,        // OUT (0x11), A
+            0x3E, 0x03,        // LD A, 0x03 (reset channel 2)
+            0xD3, 0x12,        // OUT (0x12), A
+            0x3E, 0x57,        // LD A, 0x57 (counter, rising edge)
+            0xD3,              // OUT (0x13), A
+        ]);
+        assert_eq!(cg.get_label("ctc_vectors"), Some(0x28));
+        assert_eq!(&cg.rom()[0x23..0x32], &[
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0x30, 0x00,        // ctc_unused
+            0x23, 0x00,        // timer_isr
+            0x30, 0x00,        // ctc_unused
+            0x30, 0x00,        // ctc_unused
+            0xFB,              // EI
+            0xED,              // RETI
+        ]);
+    }
+}
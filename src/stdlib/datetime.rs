@@ -0,0 +1,246 @@
+//! Calendar and time-of-day arithmetic
+//!
+//! Leap years, month lengths, day of week and conversion between seconds
+//! since midnight and hours/minutes/seconds, for clock and calendar ROMs
+//! working from RTC readings. Years are full Gregorian years (e.g. 2024).
+
+use crate::CodeGen;
+
+impl CodeGen {
+    /// Emit is_leap_year routine - HL = year, returns C set if leap
+    ///
+    /// Labels created: `is_leap_year`
+    /// Requires: `div16`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_is_leap_year(&mut self) {
+        self.label("is_leap_year");
+        self.emit(&[0x7D]);          // LD A, L
+        self.and_a(3);
+        self.ret_nz();               // Not divisible by 4 (AND clears carry)
+        self.ld_de(100);
+        self.call("div16");          // HL = year / 100, DE = year % 100
+        self.emit(&[0x7A]);          // LD A, D
+        self.emit(&[0xB3]);          // OR E
+        let century = self.unique_label("leap_century");
+        self.jp_z(&century);
+        self.scf();
+        self.ret();
+        self.label(&century);        // Centuries are leap only if divisible by 400
+        self.emit(&[0x7D]);          // LD A, L
+        self.and_a(3);
+        self.ret_nz();
+        self.scf();
+        self.ret();
+    }
+
+    /// Emit days_in_month routine - HL = year, D = month (1-12) -> A = days
+    ///
+    /// Labels created: `days_in_month`, `month_days`
+    /// Requires: `is_leap_year`
+    /// Clobbers BC, DE, HL
+    pub fn emit_days_in_month(&mut self) {
+        self.label("days_in_month");
+        self.emit(&[0x7A]);          // LD A, D
+        self.cp(2);
+        let table = self.unique_label("dim_table");
+        self.jp_nz(&table);
+        self.call("is_leap_year");
+        self.ld_a(28);
+        self.ret_nc();
+        self.inc_a();
+        self.ret();
+        self.label(&table);
+        self.ld_hl_label_offset("month_days", -1);
+        self.ld_c_a();
+        self.ld_b(0);
+        self.add_hl_bc();
+        self.ld_a_hl_ind();
+        self.ret();
+        self.label("month_days");
+        self.emit(&[31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]);
+    }
+
+    /// Emit day_of_week routine (Sakamoto's method)
+    /// Input: HL = year, D = month (1-12), E = day (1-31)
+    /// Output: A = weekday, 0 = Sunday .. 6 = Saturday
+    ///
+    /// Labels created: `day_of_week`, `dow_table`
+    /// Requires: `div16`
+    /// Clobbers BC, DE, HL
+    pub fn emit_day_of_week(&mut self) {
+        self.label("day_of_week");
+        self.push_de();              // [month/day]
+        self.emit(&[0x7A]);          // LD A, D
+        self.cp(3);
+        self.emit(&[0x30, 0x01]);    // JR NC, +1
+        self.dec_hl();               // January/February count as the previous year
+
+        // HL = y + y/4 - y/100 + y/400
+        self.push_hl();              // [y]
+        self.ld_de(100);
+        self.call("div16");          // HL = y/100
+        self.push_hl();              // [y, y/100]
+        self.ld_de(4);
+        self.call("div16");          // HL = y/400
+        self.pop_de();               // DE = y/100
+        self.or_a_a();
+        self.sbc_hl_de();            // HL = y/400 - y/100
+        self.ex_sp_hl();             // HL = y, [partial]
+        self.push_hl();              // [partial, y]
+        self.ld_de(4);
+        self.call("div16");          // HL = y/4
+        self.pop_de();
+        self.add_hl_de();            // HL = y + y/4
+        self.pop_de();
+        self.add_hl_de();            // HL += y/400 - y/100
+
+        // + dow_table[month - 1] + day, then mod 7
+        self.pop_de();               // D = month, E = day
+        self.push_hl();
+        self.ld_hl_label_offset("dow_table", -1);
+        self.emit(&[0x4A]);          // LD C, D
+        self.ld_b(0);
+        self.add_hl_bc();
+        self.ld_a_hl_ind();
+        self.add_a_e();
+        self.pop_hl();
+        self.ld_c_a();
+        self.add_hl_bc();
+        self.ld_de(7);
+        self.call("div16");          // DE = sum % 7
+        self.ld_a_e();
+        self.ret();
+        self.label("dow_table");
+        self.emit(&[0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4]);
+    }
+
+    /// Emit secs_to_hms routine
+    /// Input: E:HL = seconds since midnight (< 86400)
+    /// Output: B = hours, C = minutes, A = seconds
+    ///
+    /// Labels created: `secs_to_hms`
+    /// Requires: `div16`
+    /// Clobbers DE, HL
+    pub fn emit_secs_to_hms(&mut self) {
+        self.label("secs_to_hms");
+        self.ld_d(0);                // D = hours
+        self.ld_bc(3600);
+        let hours = self.unique_label("s2h_hours");
+        let done = self.unique_label("s2h_done");
+        self.label(&hours);
+        self.or_a_a();
+        self.sbc_hl_bc();
+        self.emit(&[0x30, 0x04]);    // JR NC, +4
+        self.emit(&[0x1D]);          // DEC E (borrow into the high byte)
+        self.jp_m(&done);
+        self.emit(&[0x14]);          // INC D
+        self.jr(&hours);
+        self.label(&done);
+        self.add_hl_bc();            // Undo the last subtraction: HL < 3600
+        self.push_de();
+        self.ld_de(60);
+        self.call("div16");          // HL = minutes, DE = seconds
+        self.ld_a_e();
+        self.emit(&[0x4D]);          // LD C, L
+        self.pop_de();
+        self.emit(&[0x42]);          // LD B, D
+        self.ret();
+    }
+
+    /// Emit hms_to_secs routine
+    /// Input: B = hours, C = minutes, A = seconds
+    /// Output: E:HL = seconds since midnight
+    ///
+    /// Labels created: `hms_to_secs`
+    /// Clobbers A, BC
+    pub fn emit_hms_to_secs(&mut self) {
+        self.label("hms_to_secs");
+        self.push_bc();
+        self.emit(&[0x6F]);          // LD L, A
+        self.ld_h(0);
+        self.ld_a_c();
+        self.ld_bc(60);
+        self.or_a_a();
+        self.emit(&[0x28, 0x04]);    // JR Z, +4
+        let minutes = self.unique_label("h2s_minutes");
+        self.label(&minutes);
+        self.add_hl_bc();
+        self.dec_a();
+        self.jr_nz(&minutes);
+        self.pop_bc();
+        self.ld_a_b();
+        self.ld_e(0);
+        self.ld_bc(3600);
+        self.or_a_a();
+        self.ret_z();
+        let hours = self.unique_label("h2s_hours");
+        self.label(&hours);
+        self.add_hl_bc();
+        self.emit(&[0x30, 0x01]);    // JR NC, +1
+        self.emit(&[0x1C]);          // INC E
+        self.dec_a();
+        self.jr_nz(&hours);
+        self.ret();
+    }
+
+    /// Emit all date/time routines
+    ///
+    /// Labels created: `is_leap_year`, `days_in_month`, `month_days`,
+    /// `day_of_week`, `dow_table`, `secs_to_hms`, `hms_to_secs`
+    /// Requires: `div16`
+    pub fn emit_datetime_routines(&mut self) {
+        self.emit_is_leap_year();
+        self.emit_days_in_month();
+        self.emit_day_of_week();
+        self.emit_secs_to_hms();
+        self.emit_hms_to_secs();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datetime_emits() {
+        let mut cg = CodeGen::new();
+        cg.emit_div16();
+        cg.emit_datetime_routines();
+        cg.resolve_fixups();
+        for label in ["is_leap_year", "days_in_month", "day_of_week", "secs_to_hms", "hms_to_secs"] {
+            assert!(cg.has_label(label), "missing {}", label);
+        }
+        let table = cg.get_label("dow_table").unwrap() as usize - cg.config().org as usize;
+        assert_eq!(&cg.rom()[table..table + 12], &[0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4]);
+    }
+
+    #[test]
+    fn test_hms_to_secs() {
+        let mut cg = CodeGen::new();
+        cg.emit_hms_to_secs();
+        assert_eq!(cg.rom(), &[
+            0xC5,                   // PUSH BC
+            0x6F,                   // LD L, A
+            0x26, 0x00,             // LD H, 0
+            0x79,                   // LD A, C
+            0x01, 0x3C, 0x00,       // LD BC, 60
+            0xB7,                   // OR A
+            0x28, 0x04,             // JR Z, +4
+            0x09,                   // h2s_minutes: ADD HL, BC
+            0x3D,                   // DEC A
+            0x20, 0xFC,             // JR NZ, h2s_minutes
+            0xC1,                   // POP BC
+            0x78,                   // LD A, B
+            0x1E, 0x00,             // LD E, 0
+            0x01, 0x10, 0x0E,       // LD BC, 3600
+            0xB7,                   // OR A
+            0xC8,                   // RET Z
+            0x09,                   // h2s_hours: ADD HL, BC
+            0x30, 0x01,             // JR NC, +1
+            0x1C,                   // INC E
+            0x3D,                   // DEC A
+            0x20, 0xF9,             // JR NZ, h2s_hours
+            0xC9,                   // RET
+        ]);
+    }
+}
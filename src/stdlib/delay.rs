@@ -0,0 +1,98 @@
+//! Calibrated busy-wait delays
+//!
+//! The millisecond loop is cycle counted and its constant computed at
+//! build time from `RomConfig::clock_hz`, so the same source delays
+//! correctly on any crystal. Interrupts stretch the delay by the time
+//! their handlers take.
+
+use crate::CodeGen;
+
+/// T-states per iteration of the inner loop
+const DELAY_LOOP_T: u32 = 24;
+/// T-states per millisecond outside the inner loop
+const DELAY_OVERHEAD_T: u32 = 34;
+
+impl CodeGen {
+    /// Emit delay_ms routine - busy-wait HL milliseconds
+    ///
+    /// `delay_ms_a` waits A milliseconds. A count of 0 returns at once.
+    ///
+    /// Labels created: `delay_ms`, `delay_ms_a`, `delay_ms_loop`, `delay_ms_inner`
+    /// Clobbers A, BC, HL
+    pub fn emit_delay_ms(&mut self) {
+        let count = self.delay_ms_count();
+
+        self.label("delay_ms_a");
+        self.ld_l_a();
+        self.ld_h(0);
+        self.label("delay_ms");
+        self.ld_a_h();
+        self.or_l();
+        self.ret_z();
+
+        self.label("delay_ms_loop");
+        self.ld_bc(count);           // 10
+        self.label("delay_ms_inner");
+        self.dec_bc();               // 6
+        self.ld_a_b();               // 4
+        self.or_c();                 // 4
+        self.jp_nz("delay_ms_inner"); // 10
+        self.dec_hl();               // 6
+        self.ld_a_h();               // 4
+        self.or_l();                 // 4
+        self.jp_nz("delay_ms_loop"); // 10
+        self.ret();
+    }
+
+    /// Inner loop count for one millisecond at the configured clock
+    ///
+    /// Panics if the clock is too slow for the loop's overhead.
+    pub fn delay_ms_count(&self) -> u16 {
+        let clock_hz = self.config().clock_hz;
+        let ms_t = clock_hz / 1000;
+        assert!(
+            ms_t >= DELAY_OVERHEAD_T + DELAY_LOOP_T,
+            "a {} Hz clock is too slow for delay_ms",
+            clock_hz
+        );
+        let count = (ms_t - DELAY_OVERHEAD_T + DELAY_LOOP_T / 2) / DELAY_LOOP_T;
+        count.min(0xFFFF) as u16
+    }
+
+    /// Emit a call waiting `ms` milliseconds
+    ///
+    /// Requires: `delay_ms`
+    pub fn delay(&mut self, ms: u16) {
+        self.ld_hl(ms);
+        self.call("delay_ms");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RomConfig;
+
+    #[test]
+    fn test_delay_ms_count() {
+        // 4 MHz: 4000 T = 34 + 24 * 165.25
+        assert_eq!(CodeGen::new().delay_ms_count(), 165);
+        let cg = CodeGen::with_config(RomConfig { clock_hz: 8_000_000, ..Default::default() });
+        assert_eq!(cg.delay_ms_count(), 332);
+    }
+
+    #[test]
+    fn test_delay_ms() {
+        let mut cg = CodeGen::new();
+        cg.emit_delay_ms();
+        cg.resolve_fixups();
+        assert_eq!(&cg.rom()[..9], &[
+            0x6F,              // LD L, A
+            0x26, 0x00,        // LD H, 0
+            0x7C,              // LD A, H
+            0xB5,              // OR L
+            0xC8,              // RET Z
+            0x01, 165, 0x00,   // LD BC, 165
+        ]);
+    }
+}
@@ -0,0 +1,266 @@
+//! Event log ring buffer
+//!
+//! A post-mortem trace for hardware where single-stepping isn't possible:
+//! code drops `log_event` probes that append fixed 4-byte records (code,
+//! tick, value) to a RAM ring, overwriting the oldest once full, and
+//! `log_dump` prints them oldest first with names from a build-time table.
+//!
+//! `log_write` is not reentrant: probes inside interrupt handlers need the
+//! main-line probes to run with interrupts disabled.
+
+use crate::CodeGen;
+
+/// Event log configuration
+pub struct EventLogConfig {
+    /// Ring capacity in records (power of two, 2..=128)
+    pub entries: u8,
+    /// RAM byte stored with each record (e.g. a timer tick), if any
+    pub tick_label: Option<String>,
+    /// Names printed by `log_dump` for each event code
+    pub events: Vec<(u8, String)>,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self {
+            entries: 16,
+            tick_label: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl CodeGen {
+    /// Emit the event log routines
+    ///
+    /// - `log_init` clears the log; call once at startup.
+    /// - `log_write` appends a record: A = code, HL = value. Preserves all
+    ///   registers and flags.
+    /// - `log_dump` prints each record as `[tick ]NAME value` (hex), with
+    ///   `?nn` for codes missing from the table.
+    ///
+    /// Labels created: `log_init`, `log_write`, `log_dump`, `log_name`,
+    /// `log_hex8`, `log_hex_digit`, `log_names`
+    /// RAM used: `log_head`, `log_count`, `log_buf`
+    /// Requires: `conout`, `newline`, `print_string`
+    pub fn emit_event_log(&mut self, config: &EventLogConfig) {
+        assert!(
+            config.entries.is_power_of_two() && (2..=128).contains(&config.entries),
+            "entries must be a power of two between 2 and 128"
+        );
+        assert!(config.events.len() < 256, "too many event names");
+        let mask = config.entries - 1;
+
+        let log_head = self.ram_var("log_head", 1);
+        let log_count = self.ram_var("log_count", 1);
+        let log_buf = self.ram_var("log_buf", config.entries as u16 * 4);
+
+        // log_init: empty the ring
+        self.label("log_init");
+        self.xor_a();
+        self.ld_addr_a(log_head);
+        self.ld_addr_a(log_count);
+        self.ret();
+
+        // log_write: A = code, HL = value
+        self.label("log_write");
+        self.push_af();
+        self.push_bc();
+        self.push_de();
+        self.push_hl();
+        self.ex_de_hl();             // DE = value
+        self.ld_c_a();               // C = code
+        self.ld_a_addr(log_head);
+        self.ld_b_a();               // B = slot
+        self.inc_a();
+        self.and_a(mask);
+        self.ld_addr_a(log_head);
+        self.ld_a_addr(log_count);
+        self.cp(config.entries);
+        let full = self.unique_label("log_full");
+        self.jp_nc(&full);           // Full: the oldest record is overwritten
+        self.inc_a();
+        self.ld_addr_a(log_count);
+        self.label(&full);
+        self.ld_l_b();
+        self.ld_h(0);
+        self.add_hl_hl();
+        self.add_hl_hl();
+        self.push_de();
+        self.ld_de(log_buf);
+        self.add_hl_de();
+        self.pop_de();
+        self.ld_hl_ind_c();
+        self.inc_hl();
+        match &config.tick_label {
+            Some(tick) => {
+                self.emit(&[0x3A]);  // LD A, (tick)
+                self.fixup(tick);
+            }
+            None => self.xor_a(),
+        }
+        self.ld_hl_ind_a();
+        self.inc_hl();
+        self.ld_hl_ind_e();
+        self.inc_hl();
+        self.ld_hl_ind_d();
+        self.pop_hl();
+        self.pop_de();
+        self.pop_bc();
+        self.pop_af();
+        self.ret();
+
+        // log_dump: print every record, oldest first
+        self.label("log_dump");
+        self.ld_a_addr(log_count);
+        self.or_a_a();
+        self.ret_z();
+        self.ld_b_a();               // B = records left
+        self.ld_a_addr(log_head);
+        self.sub_b();
+        self.and_a(mask);
+        self.ld_c_a();               // C = oldest slot
+        let next = self.unique_label("log_dump_next");
+        self.label(&next);
+        self.push_bc();
+        self.ld_l_c();
+        self.ld_h(0);
+        self.add_hl_hl();
+        self.add_hl_hl();
+        self.ld_de(log_buf);
+        self.add_hl_de();            // HL = record
+        if config.tick_label.is_some() {
+            self.inc_hl();
+            self.ld_a_hl_ind();
+            self.dec_hl();
+            self.call("log_hex8");
+            self.ld_a(b' ');
+            self.call("conout");
+        }
+        self.ld_a_hl_ind();
+        self.push_hl();
+        self.call("log_name");
+        self.pop_hl();
+        self.ld_a(b' ');
+        self.call("conout");
+        self.inc_hl();
+        self.inc_hl();
+        self.inc_hl();
+        self.ld_a_hl_ind();          // Value, high byte first
+        self.call("log_hex8");
+        self.dec_hl();
+        self.ld_a_hl_ind();
+        self.call("log_hex8");
+        self.call("newline");
+        self.pop_bc();
+        self.ld_a_c();
+        self.inc_a();
+        self.and_a(mask);
+        self.ld_c_a();
+        self.djnz(&next);
+        self.ret();
+
+        // log_name: print the name of event code A (clobbers BC, HL)
+        self.label("log_name");
+        self.ld_c_a();
+        self.ld_hl_label("log_names");
+        self.ld_b_hl_ind();          // B = number of names
+        self.inc_hl();
+        let unknown = self.unique_label("log_unknown");
+        self.inc_b();
+        self.dec_b();
+        self.jp_z(&unknown);
+        let find = self.unique_label("log_find");
+        let skip = self.unique_label("log_skip");
+        self.label(&find);
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.cp_c();
+        self.jp_z("print_string");
+        self.label(&skip);
+        self.ld_a_hl_ind();
+        self.inc_hl();
+        self.or_a_a();
+        self.jr_nz(&skip);
+        self.djnz(&find);
+        self.label(&unknown);
+        self.ld_a(b'?');
+        self.call("conout");
+        self.ld_a_c();
+        // Fall through to log_hex8
+
+        // log_hex8: print A as two hex digits (clobbers A)
+        self.label("log_hex8");
+        self.push_af();
+        self.rrca();
+        self.rrca();
+        self.rrca();
+        self.rrca();
+        self.call("log_hex_digit");
+        self.pop_af();
+        self.label("log_hex_digit");
+        self.and_a(0x0F);
+        self.add_a(0x90);
+        self.daa();
+        self.adc_a(0x40);
+        self.daa();
+        self.jp("conout");
+
+        // log_names: count, then (code, name, 0) per event
+        self.label("log_names");
+        self.emit_byte(config.events.len() as u8);
+        for (code, name) in &config.events {
+            self.emit_byte(*code);
+            self.emit_string(name);
+        }
+    }
+
+    /// Emit an event log probe recording `code` and `value`
+    ///
+    /// Preserves all registers and flags.
+    ///
+    /// Requires: `log_write`
+    pub fn log_event(&mut self, code: u8, value: u16) {
+        self.push_af();
+        self.push_hl();
+        self.ld_a(code);
+        self.ld_hl(value);
+        self.call("log_write");
+        self.pop_hl();
+        self.pop_af();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_log_emits() {
+        let mut cg = CodeGen::new();
+        cg.emit_event_log(&EventLogConfig {
+            entries: 8,
+            events: vec![(1, "BOOT".to_string()), (7, "ERR".to_string())],
+            ..Default::default()
+        });
+        assert_eq!(cg.get_label("log_buf"), Some(0x2002));
+        assert_eq!(cg.ram_pos(), 0x2002 + 32);
+        let names = (cg.get_label("log_names").unwrap() - cg.config().org) as usize;
+        assert_eq!(&cg.rom()[names..], b"\x02\x01BOOT\x00\x07ERR\x00");
+    }
+
+    #[test]
+    fn test_log_event_probe() {
+        let mut cg = CodeGen::new();
+        cg.log_event(3, 0x1234);
+        assert_eq!(cg.rom(), &[
+            0xF5,                    // PUSH AF
+            0xE5,                    // PUSH HL
+            0x3E, 0x03,              // LD A, 3
+            0x21, 0x34, 0x12,        // LD HL, 0x1234
+            0xCD, 0x00, 0x00,        // CALL log_write (unresolved)
+            0xE1,                    // POP HL
+            0xF1,                    // POP AF
+        ]);
+    }
+}
@@ -0,0 +1,167 @@
+//! Signed 8.8 fixed-point routines
+//!
+//! Values live in HL and DE with the whole part in the high byte and the
+//! fraction in the low byte (0x0180 = 1.5), covering -128 to 127.996 in
+//! steps of 1/256. Results are truncated toward zero and wrap on overflow.
+
+use crate::CodeGen;
+
+/// Size of `fx_buf`: sign, three digits, point, two decimals, terminator
+const FX_BUF_SIZE: u16 = 8;
+
+impl CodeGen {
+    /// Emit fx_add routine - HL = HL + DE
+    ///
+    /// Labels created: `fx_add`
+    pub fn emit_fx_add(&mut self) {
+        self.label("fx_add");
+        self.add_hl_de();
+        self.ret();
+    }
+
+    /// Emit fx_sub routine - HL = HL - DE
+    ///
+    /// Labels created: `fx_sub`
+    pub fn emit_fx_sub(&mut self) {
+        self.label("fx_sub");
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.ret();
+    }
+
+    /// Make HL and DE positive, pushing the result sign (bit 7 of A)
+    fn fx_signs(&mut self) {
+        self.ld_a_h();
+        self.xor_d();
+        self.push_af();
+        self.bit_h(7);
+        self.call_nz("negate_hl");
+        self.ex_de_hl();
+        self.bit_h(7);
+        self.call_nz("negate_hl");
+        self.ex_de_hl();
+    }
+
+    /// Pop the result sign and apply it to HL, returning
+    fn fx_apply_sign(&mut self) {
+        self.pop_af();
+        self.or_a_a();
+        self.ret_p();
+        self.jp("negate_hl");
+    }
+
+    /// Emit fx_mul routine - HL = HL * DE
+    ///
+    /// Takes the middle 16 bits of the 32-bit product of the magnitudes.
+    ///
+    /// Labels created: `fx_mul`
+    /// Requires: `mul16`, `negate_hl`
+    /// Clobbers A, BC, DE
+    pub fn emit_fx_mul(&mut self) {
+        self.label("fx_mul");
+        self.fx_signs();
+        self.call("mul16");          // DE:HL = product, 16.16
+        self.ld_l_h();
+        self.ld_h_e();
+        self.fx_apply_sign();
+    }
+
+    /// Emit fx_div routine - HL = HL / DE
+    ///
+    /// `div16` gives the whole part, then eight more restoring steps give
+    /// the fraction. Dividing by 0 gives 0xFFFF or its negation.
+    ///
+    /// Labels created: `fx_div`, `fx_div_loop`
+    /// Requires: `div16`, `negate_hl`
+    /// Clobbers A, BC, DE
+    pub fn emit_fx_div(&mut self) {
+        self.label("fx_div");
+        self.fx_signs();
+        self.push_de();
+        self.call("div16");          // HL = whole part, DE = remainder
+        self.ld_a_l();
+        self.ex_de_hl();
+        self.pop_de();               // DE = divisor, at most 0x8000
+
+        // Remainder < divisor, so doubling it can't carry out of HL
+        self.ld_b(8);
+        self.label("fx_div_loop");
+        self.sla_c();
+        self.add_hl_hl();
+        self.sbc_hl_de();
+        self.inc_c();                // INC leaves carry alone
+        self.emit(&[0x30, 0x02]);    // JR NC, +2
+        self.add_hl_de();            // Didn't fit: restore, clear the bit
+        self.dec_c();
+        self.djnz("fx_div_loop");
+
+        self.ld_h_a();
+        self.ld_l_c();
+        self.fx_apply_sign();
+    }
+
+    /// Emit fx_print routine - print HL with two decimal places
+    ///
+    /// Prints `-1.50`, `0.25`, `127.99`; decimals are truncated.
+    ///
+    /// Labels created: `fx_print`
+    /// RAM used: `fx_buf`
+    /// Requires: `q88_to_str`, `print_string`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_fx_print(&mut self) {
+        let buf = self.ram_var("fx_buf", FX_BUF_SIZE);
+        self.label("fx_print");
+        self.ld_de(buf);
+        self.ld_b(2);
+        self.call("q88_to_str");
+        self.jp("print_string");
+    }
+
+    /// Emit all fixed-point routines
+    pub fn emit_fx_routines(&mut self) {
+        self.emit_fx_add();
+        self.emit_fx_sub();
+        self.emit_fx_mul();
+        self.emit_fx_div();
+        self.emit_fx_print();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fx_mul() {
+        let mut cg = CodeGen::new();
+        cg.emit_fx_mul();
+        assert_eq!(cg.rom(), &[
+            0x7C,              // LD A, H
+            0xAA,              // XOR D
+            0xF5,              // PUSH AF
+            0xCB, 0x7C,        // BIT 7, H
+            0xC4, 0x00, 0x00,  // CALL NZ, negate_hl (unresolved)
+            0xEB,              // EX DE, HL
+            0xCB, 0x7C,        // BIT 7, H
+            0xC4, 0x00, 0x00,  // CALL NZ, negate_hl (unresolved)
+            0xEB,              // EX DE, HL
+            0xCD, 0x00, 0x00,  // CALL mul16 (unresolved)
+            0x6C,              // LD L, H
+            0x63,              // LD H, E
+            0xF1,              // POP AF
+            0xB7,              // OR A
+            0xF0,              // RET P
+            0xC3, 0x00, 0x00,  // JP negate_hl (unresolved)
+        ]);
+    }
+
+    #[test]
+    fn test_fx_routines_emit() {
+        let mut cg = CodeGen::new();
+        cg.emit_fx_routines();
+        assert_eq!(cg.ram_var_size("fx_buf"), Some(FX_BUF_SIZE));
+        for label in ["fx_add", "fx_sub", "fx_mul", "fx_div", "fx_print"] {
+            assert!(cg.has_label(label), "{}", label);
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Terminal input forms
+//!
+//! A form is a list of labelled input fields placed on the VT100 screen.
+//! Each field is described in Rust, and `emit_form` turns the list into a
+//! ROM descriptor table plus one RAM buffer per field. The shared
+//! `form_run` routine draws the labels and current values, then lets the
+//! user type into the fields: Tab moves to the next field (wrapping),
+//! Backspace/Delete erases, Enter accepts the form and Escape cancels it.
+//!
+//! Values are kept as null-terminated strings in RAM variables named
+//! after the fields, so a program can prefill them before running the
+//! form and read them afterwards (`parse_dec` turns a numeric field into
+//! a number).
+
+use crate::CodeGen;
+
+/// Bytes per field descriptor in a form table
+const DESC_SIZE: u16 = 9;
+
+/// Descriptor offsets
+const ROW: i8 = 0;
+const LABEL_COL: i8 = 1;
+const VALUE_COL: i8 = 2;
+const MAX_LEN: i8 = 3;
+const KIND: i8 = 4;
+const BUF: i8 = 5;
+const LABEL: i8 = 7;
+
+/// What a field accepts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldKind {
+    /// Any printable ASCII character
+    Text,
+    /// Digits `0`-`9` only
+    Numeric,
+}
+
+/// One input field of a form
+#[derive(Clone, Copy, Debug)]
+pub struct Field {
+    /// RAM variable holding the value (`max_len + 1` bytes, null-terminated)
+    pub name: &'static str,
+    /// Text printed in front of the input area
+    pub label: &'static str,
+    /// Screen row of the label (1-based)
+    pub row: u8,
+    /// Screen column of the label (1-based); the input area starts one
+    /// space after the label
+    pub col: u8,
+    /// Maximum number of characters
+    pub max_len: u8,
+    /// Accepted characters
+    pub kind: FieldKind,
+}
+
+impl Field {
+    /// A text field
+    pub const fn text(name: &'static str, label: &'static str, row: u8, col: u8, max_len: u8) -> Self {
+        Self { name, label, row, col, max_len, kind: FieldKind::Text }
+    }
+
+    /// A numeric field
+    pub const fn numeric(name: &'static str, label: &'static str, row: u8, col: u8, max_len: u8) -> Self {
+        Self { name, label, row, col, max_len, kind: FieldKind::Numeric }
+    }
+
+    /// Screen column of the first input character
+    fn value_col(&self) -> u8 {
+        let col = self.col as usize + self.label.len() + 1;
+        assert!(
+            col + self.max_len as usize <= 256,
+            "form field `{}` runs past column 255",
+            self.name
+        );
+        col as u8
+    }
+}
+
+impl CodeGen {
+    /// Emit a form: an entry routine, its field table and the field RAM
+    ///
+    /// Calling `name` draws the form and edits it until Enter (carry
+    /// clear) or Escape (carry set). The values stay in the field RAM
+    /// variables either way.
+    ///
+    /// Labels created: `name`, `<name>_fields`
+    /// RAM used: one `max_len + 1` byte variable per field
+    /// Requires: `form_run`
+    /// Clobbers A, BC, DE, HL, IX
+    pub fn emit_form(&mut self, name: &str, fields: &[Field]) {
+        assert!(!fields.is_empty() && fields.len() < 256, "form `{}` needs 1 to 255 fields", name);
+        for field in fields {
+            assert!(field.max_len > 0, "form field `{}` has no room", field.name);
+            self.ram_var(field.name, field.max_len as u16 + 1);
+        }
+        let table = format!("{}_fields", name);
+
+        self.label(name);
+        self.ld_ix_label(&table);
+        self.ld_b(fields.len() as u8);
+        self.jp("form_run");
+
+        self.label(&table);
+        for field in fields {
+            let label = self.pooled_string(field.label);
+            self.emit(&[field.row, field.col, field.value_col(), field.max_len]);
+            self.emit_byte(match field.kind {
+                FieldKind::Text => 0,
+                FieldKind::Numeric => 1,
+            });
+            self.fixup(field.name);
+            self.fixup(&label);
+        }
+    }
+
+    /// Emit form_run routine - draw and edit a form
+    /// Input: IX = field table, B = number of fields
+    ///
+    /// Tables come from `emit_form`. Returns carry clear on Enter, carry
+    /// set on Escape. `form_show` prints the value of the field at IX and
+    /// pads it with `_` to the field width, setting `form_len`.
+    ///
+    /// Labels created: `form_run`, `form_draw`, `form_field`, `form_key`,
+    /// `form_accept`, `form_bs`, `form_next`, `form_show`
+    /// RAM used: `form_table` (2 bytes), `form_count`, `form_index`,
+    /// `form_len`
+    /// Requires: `getchar`, `conout`, `print_string`, `cursor_pos`
+    /// Clobbers A, BC, DE, HL, IX
+    pub fn emit_form_run(&mut self) {
+        let table = self.ram_var("form_table", 2);
+        let count = self.ram_var("form_count", 1);
+        let index = self.ram_var("form_index", 1);
+        let len = self.ram_var("form_len", 1);
+        let show_loop = self.unique_label("form_show_loop");
+        let show_pad = self.unique_label("form_show_pad");
+        let show_under = self.unique_label("form_show_under");
+        let cancel = self.unique_label("form_cancel");
+
+        self.label("form_run");
+        self.ld_addr_ix(table);
+        self.ld_a_b();
+        self.ld_addr_a(count);
+
+        // Labels and current values
+        self.label("form_draw");
+        self.push_bc();
+        self.ld_b_ix_ind(ROW);
+        self.ld_c_ix_ind(LABEL_COL);
+        self.call("cursor_pos");
+        self.ld_l_ix_ind(LABEL);
+        self.ld_h_ix_ind(LABEL + 1);
+        self.call("print_string");
+        self.ld_a(b' ');
+        self.call("conout");
+        self.call("form_show");
+        self.ld_de(DESC_SIZE);
+        self.add_ix_de();
+        self.pop_bc();
+        self.djnz("form_draw");
+
+        self.ld_ix_addr(table);
+        self.xor_a();
+        self.ld_addr_a(index);
+
+        // Redraw the current field to find its length, then park the
+        // cursor after the last character
+        self.label("form_field");
+        self.ld_b_ix_ind(ROW);
+        self.ld_c_ix_ind(VALUE_COL);
+        self.call("cursor_pos");
+        self.call("form_show");
+        self.ld_a_addr(len);
+        self.add_a_ix_ind(VALUE_COL);
+        self.ld_c_a();
+        self.ld_b_ix_ind(ROW);
+        self.call("cursor_pos");
+
+        self.label("form_key");
+        self.call("getchar");
+        self.cp(0x09);              // Tab
+        self.jp_z("form_next");
+        self.cp(0x0D);              // Enter: Z and NC
+        self.ret_z();
+        self.cp(0x1B);              // Escape
+        self.jp_z(&cancel);
+        self.cp(0x08);              // Backspace
+        self.jp_z("form_bs");
+        self.cp(0x7F);              // Delete
+        self.jp_z("form_bs");
+        self.jp_nc("form_key");
+        self.cp(b' ');
+        self.jp_c("form_key");      // Other control characters
+        self.ld_c_a();
+        self.ld_a_ix_ind(KIND);
+        self.or_a_a();
+        self.jp_z("form_accept");
+        self.ld_a_c();
+        self.cp(b'0');
+        self.jp_c("form_key");
+        self.cp(b'9' + 1);
+        self.jp_nc("form_key");
+
+        self.label("form_accept");
+        self.ld_a_addr(len);
+        self.cp_ix_ind(MAX_LEN);
+        self.jp_nc("form_key");     // Full
+        self.ld_l_ix_ind(BUF);
+        self.ld_h_ix_ind(BUF + 1);
+        self.ld_e_a();
+        self.ld_d(0);
+        self.add_hl_de();
+        self.ld_hl_ind_c();
+        self.inc_hl();
+        self.ld_hl_ind_n(0);
+        self.inc_a();
+        self.ld_addr_a(len);
+        self.ld_a_c();
+        self.call("conout");
+        self.jp("form_key");
+
+        self.label("form_bs");
+        self.ld_a_addr(len);
+        self.or_a_a();
+        self.jp_z("form_key");
+        self.dec_a();
+        self.ld_addr_a(len);
+        self.ld_l_ix_ind(BUF);
+        self.ld_h_ix_ind(BUF + 1);
+        self.ld_e_a();
+        self.ld_d(0);
+        self.add_hl_de();
+        self.ld_hl_ind_n(0);
+        self.ld_a(0x08);
+        self.call("conout");
+        self.ld_a(b'_');
+        self.call("conout");
+        self.ld_a(0x08);
+        self.call("conout");
+        self.jp("form_key");
+
+        self.label("form_next");
+        self.ld_de(DESC_SIZE);
+        self.add_ix_de();
+        self.ld_hl(index);
+        self.inc_hl_ind();
+        self.ld_a_addr(count);
+        self.cp_hl_ind();
+        self.jp_nz("form_field");
+        self.ld_hl_ind_n(0);        // Wrap to the first field
+        self.ld_ix_addr(table);
+        self.jp("form_field");
+
+        self.label(&cancel);
+        self.scf();
+        self.ret();
+
+        self.label("form_show");
+        self.ld_l_ix_ind(BUF);
+        self.ld_h_ix_ind(BUF + 1);
+        self.ld_b(0);
+        self.label(&show_loop);
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z(&show_pad);
+        self.call("conout");
+        self.inc_hl();
+        self.inc_b();
+        self.jp(&show_loop);
+        self.label(&show_pad);
+        self.ld_a_b();
+        self.ld_addr_a(len);
+        self.ld_a_ix_ind(MAX_LEN);
+        self.sub_b();
+        self.ret_z();
+        self.ld_b_a();
+        self.label(&show_under);
+        self.ld_a(b'_');
+        self.call("conout");
+        self.djnz(&show_under);
+        self.ret();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_form_table() {
+        let mut cg = CodeGen::new();
+        cg.emit_form("setup", &[
+            Field::text("host", "Host:", 3, 5, 12),
+            Field::numeric("port", "Port:", 4, 5, 5),
+        ]);
+        assert_eq!(cg.ram_var_size("host"), Some(13));
+        assert_eq!(cg.ram_var_size("port"), Some(6));
+        assert_eq!(cg.get_label("setup_fields"), Some(9));
+        assert_eq!(&cg.rom()[9..14], &[3, 5, 11, 12, 0]);
+        assert_eq!(&cg.rom()[18..23], &[4, 5, 11, 5, 1]);
+    }
+
+    #[test]
+    fn test_form_entry() {
+        let mut cg = CodeGen::new();
+        cg.emit_form("f", &[Field::text("name", "Name", 1, 1, 8)]);
+        assert_eq!(&cg.rom()[..9], &[
+            0xDD, 0x21, 0x00, 0x00,  // LD IX, f_fields
+            0x06, 0x01,              // LD B, 1
+            0xC3, 0x00, 0x00,        // JP form_run
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "runs past column 255")]
+    fn test_field_too_wide() {
+        let mut cg = CodeGen::new();
+        cg.emit_form("f", &[Field::text("name", "Name", 1, 240, 20)]);
+    }
+}
@@ -0,0 +1,110 @@
+//! Fixed-rate game loop
+//!
+//! `game_run` repeats the usual frame: poll the keyboard without waiting,
+//! call the update routine with the key, call the render routine, then
+//! wait for the next frame on the tick counter from `stdlib::timer`.
+//! Frame deadlines advance by a fixed step, so a slow frame is made up by
+//! shorter waits afterwards instead of slowing the game down.
+
+use crate::stdlib::timer::TimerConfig;
+use crate::CodeGen;
+
+/// Game loop configuration
+#[derive(Clone, Debug)]
+pub struct GameConfig {
+    /// Called each frame with A = key (0 if none); return carry set to
+    /// leave the loop
+    pub update: String,
+    /// Called each frame after `update`
+    pub render: String,
+    /// Frame period in milliseconds
+    pub frame_ms: u32,
+    /// The tick counter the frames are timed on
+    pub timer: TimerConfig,
+}
+
+impl GameConfig {
+    /// Config for `frame_ms` frames calling `update` and `render`
+    pub fn new(update: &str, render: &str, frame_ms: u32, timer: TimerConfig) -> Self {
+        Self {
+            update: update.to_string(),
+            render: render.to_string(),
+            frame_ms,
+            timer,
+        }
+    }
+}
+
+impl CodeGen {
+    /// Emit game_run routine - run the game loop until `update` sets carry
+    ///
+    /// The key is also left in `game_key`, and `game_frame` counts frames
+    /// (16-bit, wrapping) for animation. Interrupts must be running
+    /// (`timer_start`).
+    ///
+    /// Labels created: `game_run`, `game_loop`, `game_poll`
+    /// RAM used: `game_key`, `game_frame`, `game_next`
+    /// Requires: `getkey`, `char_available`, `timer_now`,
+    /// `timer_wait_until`, `update`, `render`
+    /// Clobbers A, BC, DE, HL, and whatever `update` and `render` do
+    pub fn emit_game_loop(&mut self, config: &GameConfig) {
+        let frame_ticks = config.timer.ticks(config.frame_ms).max(1);
+        let key = self.ram_var("game_key", 1);
+        let frame = self.ram_var("game_frame", 2);
+        let next = self.ram_var("game_next", 2);
+        let read = self.unique_label("game_read");
+        let got = self.unique_label("game_got");
+
+        self.label("game_run");
+        self.call("timer_now");
+        self.ld_addr_hl(next);
+
+        self.label("game_loop");
+        self.call("game_poll");
+        self.call(&config.update);
+        self.ret_c();
+        self.call(&config.render);
+        self.ld_hl_addr(frame);
+        self.inc_hl();
+        self.ld_addr_hl(frame);
+        self.ld_hl_addr(next);
+        self.ld_de(frame_ticks);
+        self.add_hl_de();
+        self.ld_addr_hl(next);
+        self.call("timer_wait_until");
+        self.jp("game_loop");
+
+        // game_poll: A = key or 0, without waiting
+        self.label("game_poll");
+        self.emit(&[0x3A]);          // LD A, (getkey_pending)
+        self.fixup("getkey_pending");
+        self.or_a_a();
+        self.jp_nz(&read);
+        self.call("char_available");
+        self.jp_z(&got);             // A = 0
+        self.label(&read);
+        self.call("getkey");
+        self.label(&got);
+        self.ld_addr_a(key);
+        self.ret();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_loop() {
+        let mut cg = CodeGen::new();
+        cg.emit_game_loop(&GameConfig::new("update", "render", 50, TimerConfig::default()));
+        assert_eq!(&cg.rom()[..12], &[
+            0xCD, 0x00, 0x00,  // CALL timer_now
+            0x22, 0x03, 0x20,  // LD (game_next), HL
+            0xCD, 0x00, 0x00,  // game_loop: CALL game_poll
+            0xCD, 0x00, 0x00,  // CALL update
+        ]);
+        let step = cg.get_label("game_loop").unwrap() as usize + 20;
+        assert_eq!(&cg.rom()[step..step + 3], &[0x11, 5, 0]); // LD DE, 5 ticks
+    }
+}
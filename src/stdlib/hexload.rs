@@ -0,0 +1,169 @@
+//! Intel HEX loader
+//!
+//! Reads Intel HEX records from the console, stores data records in RAM
+//! and jumps to the program on the end-of-file record, for developing over
+//! the serial port without reflashing. Characters between records (CR, LF,
+//! anything else) are skipped.
+
+use crate::CodeGen;
+
+impl CodeGen {
+    /// Emit hex_load routine - load Intel HEX from `getchar` and run it
+    ///
+    /// Data records (type 00) are written to their addresses. On the EOF
+    /// record (01) it jumps to the entry address: the last start address
+    /// record (03 or 05, low 16 bits), or else the first data record's
+    /// address. Extended address records (02, 04) are checked and ignored.
+    /// Returns carry set on a checksum error or a bad hex digit, leaving
+    /// the records before it loaded. Prints a `.` per good record.
+    ///
+    /// `hex_load_byte` reads two hex digits into A, adding them to the
+    /// checksum in C; carry on a bad digit. Clobbers B.
+    ///
+    /// Labels created: `hex_load`, `hex_load_record`, `hex_load_byte`
+    /// RAM used: `hex_entry`, `hex_entry_src`
+    /// Requires: `getchar`, `conout`, `parse_hex_digit`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_hex_load(&mut self) {
+        let entry = self.ram_var("hex_entry", 2);
+        let entry_src = self.ram_var("hex_entry_src", 1); // 0 none, 1 data, 2 start record
+        let fail = self.unique_label("hex_fail");
+        let not_data = self.unique_label("hex_not_data");
+        let data = self.unique_label("hex_data");
+        let skip = self.unique_label("hex_skip");
+        let skip_done = self.unique_label("hex_skip_done");
+        let check = self.unique_label("hex_check");
+        let not_start = self.unique_label("hex_not_start");
+
+        self.label("hex_load");
+        self.xor_a();
+        self.ld_addr_a(entry_src);
+
+        self.label("hex_load_record");
+        self.call("getchar");
+        self.cp(b':');
+        self.jr_nz("hex_load_record");
+        self.ld_c(0);                // Checksum
+        self.call("hex_load_byte");
+        self.jp_c(&fail);
+        self.ld_e_a();               // E = byte count
+        self.call("hex_load_byte");
+        self.jp_c(&fail);
+        self.ld_h_a();
+        self.call("hex_load_byte");
+        self.jp_c(&fail);
+        self.ld_l_a();               // HL = address
+        self.call("hex_load_byte");
+        self.jp_c(&fail);
+        self.ld_d_a();               // D = record type
+        self.or_a_a();
+        self.jp_nz(&not_data);
+
+        // Data: the first one sets a default entry address
+        self.ld_a_addr(entry_src);
+        self.or_a_a();
+        self.jp_nz(&data);
+        self.ld_addr_hl(entry);
+        self.inc_a();
+        self.ld_addr_a(entry_src);
+        self.label(&data);
+        self.ld_a_e();
+        self.or_a_a();
+        self.jp_z(&check);
+        let store = self.unique_label("hex_store");
+        self.label(&store);
+        self.call("hex_load_byte");
+        self.jp_c(&fail);
+        self.ld_hl_ind_a();
+        self.inc_hl();
+        self.dec_e();
+        self.jr_nz(&store);
+        self.jp(&check);
+
+        // Other records: keep the last two bytes in HL
+        self.label(&not_data);
+        self.ld_a_e();
+        self.or_a_a();
+        self.jp_z(&skip_done);
+        self.label(&skip);
+        self.call("hex_load_byte");
+        self.jp_c(&fail);
+        self.ld_h_l();
+        self.ld_l_a();
+        self.dec_e();
+        self.jr_nz(&skip);
+        self.label(&skip_done);
+
+        self.label(&check);
+        self.call("hex_load_byte");  // Checksum byte: total must be 0
+        self.jp_c(&fail);
+        self.ld_a_c();
+        self.or_a_a();
+        self.jp_nz(&fail);
+        self.ld_a(b'.');
+        self.call("conout");
+        self.ld_a_d();
+        self.cp(1);
+        let eof = self.unique_label("hex_eof");
+        self.jp_z(&eof);
+        self.cp(3);
+        let start = self.unique_label("hex_start");
+        self.jp_z(&start);
+        self.cp(5);
+        self.jp_nz(&not_start);
+        self.label(&start);
+        self.ld_addr_hl(entry);
+        self.ld_a(2);
+        self.ld_addr_a(entry_src);
+        self.label(&not_start);
+        self.jp("hex_load_record");
+
+        self.label(&eof);
+        self.ld_hl_addr(entry);
+        self.jp_hl();
+
+        self.label(&fail);
+        self.scf();
+        self.ret();
+
+        self.label("hex_load_byte");
+        self.call("getchar");
+        self.call("parse_hex_digit");
+        self.ret_c();
+        self.rlca();
+        self.rlca();
+        self.rlca();
+        self.rlca();
+        self.ld_b_a();
+        self.call("getchar");
+        self.call("parse_hex_digit");
+        self.ret_c();
+        self.or_b();
+        self.ld_b_a();
+        self.add_a_c();
+        self.ld_c_a();
+        self.ld_a_b();
+        self.or_a_a();               // Clear the carry from the sum
+        self.ret();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_load_emits() {
+        let mut cg = CodeGen::new();
+        cg.emit_hex_load();
+        assert_eq!(cg.ram_var_size("hex_entry"), Some(2));
+        assert_eq!(cg.ram_var_size("hex_entry_src"), Some(1));
+        assert!(cg.has_label("hex_load_record"));
+        let byte = cg.get_label("hex_load_byte").unwrap() as usize;
+        assert_eq!(&cg.rom()[byte..byte + 7], &[
+            0xCD, 0x00, 0x00,  // CALL getchar (unresolved)
+            0xCD, 0x00, 0x00,  // CALL parse_hex_digit (unresolved)
+            0xD8,              // RET C
+        ]);
+    }
+}
@@ -0,0 +1,315 @@
+//! Bit-banged I2C master and DS1307 real-time clock
+//!
+//! SCL and SDA are driven through an output latch (a 1 releases the line
+//! through an open-collector buffer; the bus pull-ups make it high) and
+//! SDA is read back on an input port. Other latch bits keep the value in
+//! the `i2c_out` shadow byte. Clock stretching is not supported, and the
+//! bus runs at whatever rate the code does (well under 100 kHz at 4 MHz).
+//!
+//! The DS1307 layer reads and writes the seven BCD time registers
+//! (seconds, minutes, hours, day, date, month, year) as a block and prints
+//! them.
+
+use crate::CodeGen;
+
+/// DS1307 bus address, write (read is +1)
+const DS1307_WRITE: u8 = 0xD0;
+
+/// Bit-banged I2C pin assignment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct I2cConfig {
+    /// Output latch holding SCL and SDA
+    pub out_port: u8,
+    /// Input port reading SDA
+    pub in_port: u8,
+    /// Bit numbers (0-7)
+    pub scl_bit: u8,
+    pub sda_bit: u8,
+    pub sda_in_bit: u8,
+}
+
+impl Default for I2cConfig {
+    fn default() -> Self {
+        Self {
+            out_port: 0x42,
+            in_port: 0x42,
+            scl_bit: 0,
+            sda_bit: 1,
+            sda_in_bit: 1,
+        }
+    }
+}
+
+impl CodeGen {
+    /// Emit the I2C routines
+    ///
+    /// - `i2c_init`: release both lines. Clobbers A.
+    /// - `i2c_start` / `i2c_stop`: start (or repeated start) and stop
+    ///   conditions. Clobber A.
+    /// - `i2c_write`: send A; carry set if the device did not acknowledge.
+    ///   Clobbers A.
+    /// - `i2c_read_ack` / `i2c_read_nack`: read a byte into A and
+    ///   acknowledge it (more to come) or not (last byte).
+    ///
+    /// Labels created: `i2c_init`, `i2c_start`, `i2c_stop`, `i2c_write`,
+    /// `i2c_read_ack`, `i2c_read_nack`, `i2c_read`, `i2c_sda_hi`,
+    /// `i2c_sda_lo`, `i2c_scl_hi`, `i2c_scl_lo`, `i2c_set`
+    /// RAM used: `i2c_out`
+    pub fn emit_i2c(&mut self, config: &I2cConfig) {
+        for bit in [config.scl_bit, config.sda_bit, config.sda_in_bit] {
+            assert!(bit < 8, "I2C bit must be 0-7");
+        }
+        let scl = 1u8 << config.scl_bit;
+        let sda = 1u8 << config.sda_bit;
+        let sda_in = 1u8 << config.sda_in_bit;
+        let shadow = self.ram_var("i2c_out", 1);
+        let write_bit = self.unique_label("i2c_write_bit");
+        let write_zero = self.unique_label("i2c_write_zero");
+        let write_clock = self.unique_label("i2c_write_clock");
+        let read_bit = self.unique_label("i2c_read_bit");
+        let read_nack = self.unique_label("i2c_read_no");
+        let read_clock = self.unique_label("i2c_read_clock");
+
+        // Line helpers: update the shadow and the latch
+        self.label("i2c_sda_hi");
+        self.ld_a_addr(shadow);
+        self.or_a(sda);
+        self.jp("i2c_set");
+        self.label("i2c_sda_lo");
+        self.ld_a_addr(shadow);
+        self.and_a(!sda);
+        self.jp("i2c_set");
+        self.label("i2c_scl_hi");
+        self.ld_a_addr(shadow);
+        self.or_a(scl);
+        self.jp("i2c_set");
+        self.label("i2c_scl_lo");
+        self.ld_a_addr(shadow);
+        self.and_a(!scl);
+        self.label("i2c_set");
+        self.ld_addr_a(shadow);
+        self.out_a(config.out_port);
+        self.ret();
+
+        self.label("i2c_init");
+        self.ld_a_addr(shadow);
+        self.or_a(scl | sda);
+        self.jp("i2c_set");
+
+        // SDA falls while SCL is high; also a repeated start after a byte
+        self.label("i2c_start");
+        self.call("i2c_sda_hi");
+        self.call("i2c_scl_hi");
+        self.call("i2c_sda_lo");
+        self.jp("i2c_scl_lo");
+
+        // SDA rises while SCL is high
+        self.label("i2c_stop");
+        self.call("i2c_sda_lo");
+        self.call("i2c_scl_hi");
+        self.jp("i2c_sda_hi");
+
+        self.label("i2c_write");
+        self.push_bc();
+        self.ld_c_a();
+        self.ld_b(8);
+        self.label(&write_bit);
+        self.sla_c();
+        self.jp_nc(&write_zero);
+        self.call("i2c_sda_hi");
+        self.jp(&write_clock);
+        self.label(&write_zero);
+        self.call("i2c_sda_lo");
+        self.label(&write_clock);
+        self.call("i2c_scl_hi");
+        self.call("i2c_scl_lo");
+        self.djnz(&write_bit);
+        self.call("i2c_sda_hi");    // Release SDA for the acknowledge
+        self.call("i2c_scl_hi");
+        self.in_a(config.in_port);
+        self.and_a(sda_in);
+        self.ld_c_a();
+        self.call("i2c_scl_lo");
+        self.ld_a_c();
+        self.add_a(0xFF);           // Carry = SDA high = no acknowledge
+        self.pop_bc();
+        self.ret();
+
+        // Carry set: answer the byte with NACK
+        self.label("i2c_read_nack");
+        self.scf();
+        self.jp("i2c_read");
+        self.label("i2c_read_ack");
+        self.or_a_a();
+        self.label("i2c_read");
+        self.push_bc();
+        self.push_af();
+        self.call("i2c_sda_hi");
+        self.ld_b(8);
+        self.label(&read_bit);
+        self.call("i2c_scl_hi");
+        self.in_a(config.in_port);
+        self.and_a(sda_in);
+        self.add_a(0xFF);           // Carry = SDA
+        self.rl_c();
+        self.call("i2c_scl_lo");
+        self.djnz(&read_bit);
+        self.pop_af();
+        self.jp_c(&read_nack);
+        self.call("i2c_sda_lo");
+        self.jp(&read_clock);
+        self.label(&read_nack);
+        self.call("i2c_sda_hi");
+        self.label(&read_clock);
+        self.call("i2c_scl_hi");
+        self.call("i2c_scl_lo");
+        self.call("i2c_sda_hi");
+        self.ld_a_c();
+        self.pop_bc();
+        self.ret();
+    }
+
+    /// Emit the DS1307 routines
+    ///
+    /// - `rtc_read`: copy registers 0-6 to the 7 bytes at HL. Clobbers A,
+    ///   B, HL.
+    /// - `rtc_write`: set registers 0-6 from the 7 bytes at HL; a seconds
+    ///   byte below 0x80 also starts the oscillator. Clobbers A, B, HL.
+    /// - `rtc_print_time`: print the buffer at HL as `HH:MM:SS`
+    ///   (24-hour mode). Clobbers A.
+    /// - `rtc_print_date`: print it as `20YY-MM-DD`. Clobbers A.
+    ///
+    /// `rtc_read` and `rtc_write` return carry set if the clock did not
+    /// answer.
+    ///
+    /// Labels created: `rtc_read`, `rtc_write`, `rtc_fail`,
+    /// `rtc_print_time`, `rtc_print_date`
+    /// Requires: `i2c_start`, `i2c_stop`, `i2c_write`, `i2c_read_ack`,
+    /// `i2c_read_nack`, `print_hex_byte`, `conout`
+    pub fn emit_ds1307(&mut self) {
+        let read_loop = self.unique_label("rtc_read_loop");
+        let write_loop = self.unique_label("rtc_write_loop");
+
+        self.label("rtc_read");
+        self.call("i2c_start");
+        self.ld_a(DS1307_WRITE);
+        self.call("i2c_write");
+        self.jp_c("rtc_fail");
+        self.xor_a();               // Register pointer = 0
+        self.call("i2c_write");
+        self.jp_c("rtc_fail");
+        self.call("i2c_start");
+        self.ld_a(DS1307_WRITE + 1);
+        self.call("i2c_write");
+        self.jp_c("rtc_fail");
+        self.ld_b(6);
+        self.label(&read_loop);
+        self.call("i2c_read_ack");
+        self.ld_hl_ind_a();
+        self.inc_hl();
+        self.djnz(&read_loop);
+        self.call("i2c_read_nack");
+        self.ld_hl_ind_a();
+        self.call("i2c_stop");
+        self.or_a_a();
+        self.ret();
+
+        self.label("rtc_write");
+        self.call("i2c_start");
+        self.ld_a(DS1307_WRITE);
+        self.call("i2c_write");
+        self.jp_c("rtc_fail");
+        self.xor_a();
+        self.call("i2c_write");
+        self.jp_c("rtc_fail");
+        self.ld_b(7);
+        self.label(&write_loop);
+        self.ld_a_hl_ind();
+        self.call("i2c_write");
+        self.jp_c("rtc_fail");
+        self.inc_hl();
+        self.djnz(&write_loop);
+        self.call("i2c_stop");
+        self.or_a_a();
+        self.ret();
+
+        self.label("rtc_fail");
+        self.call("i2c_stop");
+        self.scf();
+        self.ret();
+
+        // BCD bytes print as decimal through print_hex_byte
+        self.label("rtc_print_time");
+        self.push_hl();
+        self.inc_hl();
+        self.inc_hl();
+        self.ld_a_hl_ind();
+        self.and_a(0x3F);           // Hours without the 12-hour bit
+        self.call("print_hex_byte");
+        self.ld_a(b':');
+        self.call("conout");
+        self.dec_hl();
+        self.ld_a_hl_ind();
+        self.call("print_hex_byte");
+        self.ld_a(b':');
+        self.call("conout");
+        self.dec_hl();
+        self.ld_a_hl_ind();
+        self.and_a(0x7F);           // Seconds without the clock-halt bit
+        self.call("print_hex_byte");
+        self.pop_hl();
+        self.ret();
+
+        self.label("rtc_print_date");
+        self.push_de();
+        self.ld_de(6);
+        self.add_hl_de();
+        self.ld_a(0x20);            // Century
+        self.call("print_hex_byte");
+        self.ld_a_hl_ind();         // Year
+        self.call("print_hex_byte");
+        self.ld_a(b'-');
+        self.call("conout");
+        self.dec_hl();
+        self.ld_a_hl_ind();         // Month
+        self.call("print_hex_byte");
+        self.ld_a(b'-');
+        self.call("conout");
+        self.dec_hl();
+        self.ld_a_hl_ind();         // Date
+        self.call("print_hex_byte");
+        self.ld_de(-4i16 as u16);
+        self.add_hl_de();
+        self.pop_de();
+        self.ret();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_i2c_lines() {
+        let mut cg = CodeGen::new();
+        cg.emit_i2c(&I2cConfig::default());
+        assert_eq!(&cg.rom()[..8], &[
+            0x3A, 0x00, 0x20,  // i2c_sda_hi: LD A, (i2c_out)
+            0xF6, 0x02,        // OR SDA
+            0xC3, 0x00, 0x00,  // JP i2c_set
+        ]);
+        assert!(cg.has_label("i2c_read_nack"));
+        assert_eq!(cg.ram_var_size("i2c_out"), Some(1));
+    }
+
+    #[test]
+    fn test_rtc_read_address() {
+        let mut cg = CodeGen::new();
+        cg.emit_ds1307();
+        assert_eq!(&cg.rom()[..8], &[
+            0xCD, 0x00, 0x00,  // CALL i2c_start
+            0x3E, 0xD0,        // LD A, DS1307 write address
+            0xCD, 0x00, 0x00,  // CALL i2c_write
+        ]);
+    }
+}
@@ -0,0 +1,294 @@
+//! 32-bit integer routines
+//!
+//! Unsigned arithmetic on 4-byte little-endian values in RAM, addressed by
+//! HL and DE, for uptime counters and totals that outgrow 16 bits. Apart
+//! from `print32`, the routines leave HL and DE pointing at the values.
+
+use crate::CodeGen;
+
+impl CodeGen {
+    /// Emit add32 routine - (HL) += (DE)
+    ///
+    /// Returns carry set if the sum overflowed 32 bits.
+    ///
+    /// Labels created: `add32`, `add32_loop`
+    /// Clobbers A, B
+    pub fn emit_add32(&mut self) {
+        self.label("add32");
+        self.push_hl();
+        self.push_de();
+        self.ld_b(4);
+        self.or_a_a();
+        self.label("add32_loop");
+        self.ld_a_de_ind();
+        self.adc_a_hl_ind();
+        self.ld_hl_ind_a();
+        self.inc_hl();               // 16-bit INC/DEC leave carry alone
+        self.inc_de();
+        self.djnz("add32_loop");
+        self.pop_de();
+        self.pop_hl();
+        self.ret();
+    }
+
+    /// Emit sub32 routine - (HL) -= (DE)
+    ///
+    /// Returns carry set if the result went below zero.
+    ///
+    /// Labels created: `sub32`, `sub32_loop`
+    /// Clobbers A, B
+    pub fn emit_sub32(&mut self) {
+        self.label("sub32");
+        self.push_hl();
+        self.push_de();
+        self.ld_b(4);
+        self.or_a_a();
+        self.label("sub32_loop");
+        self.ld_a_hl_ind();
+        self.ex_de_hl();
+        self.sbc_a_hl_ind();         // There is no SBC A, (DE)
+        self.ex_de_hl();
+        self.ld_hl_ind_a();
+        self.inc_hl();
+        self.inc_de();
+        self.djnz("sub32_loop");
+        self.pop_de();
+        self.pop_hl();
+        self.ret();
+    }
+
+    /// Emit cmp32 routine - compare (HL) with (DE)
+    ///
+    /// Returns Z if equal, otherwise NZ with C set if (HL) is lower.
+    ///
+    /// Labels created: `cmp32`, `cmp32_loop`
+    /// Clobbers A, BC
+    pub fn emit_cmp32(&mut self) {
+        self.label("cmp32");
+        self.push_hl();
+        self.push_de();
+        self.ld_bc(3);
+        self.add_hl_bc();
+        self.ex_de_hl();
+        self.add_hl_bc();
+        self.ex_de_hl();             // Most significant bytes first
+        self.ld_b(4);
+        self.label("cmp32_loop");
+        self.ld_a_de_ind();
+        self.ld_c_a();
+        self.ld_a_hl_ind();
+        self.cp_c();
+        self.emit(&[0x20, 0x04]);    // JR NZ, +4
+        self.dec_hl();
+        self.dec_de();
+        self.djnz("cmp32_loop");
+        self.pop_de();
+        self.pop_hl();
+        self.ret();
+    }
+
+    /// Emit shl32 routine - shift (HL) left one bit
+    ///
+    /// Returns the bit shifted out in carry.
+    ///
+    /// Labels created: `shl32`
+    pub fn emit_shl32(&mut self) {
+        self.label("shl32");
+        self.sla_hl_ind();
+        for _ in 0..3 {
+            self.inc_hl();
+            self.rl_hl_ind();
+        }
+        self.dec_hl();
+        self.dec_hl();
+        self.dec_hl();
+        self.ret();
+    }
+
+    /// Emit shr32 routine - shift (HL) right one bit, unsigned
+    ///
+    /// Returns the bit shifted out in carry.
+    ///
+    /// Labels created: `shr32`
+    pub fn emit_shr32(&mut self) {
+        self.label("shr32");
+        self.inc_hl();
+        self.inc_hl();
+        self.inc_hl();
+        self.srl_hl_ind();
+        for _ in 0..3 {
+            self.dec_hl();
+            self.rr_hl_ind();
+        }
+        self.ret();
+    }
+
+    /// Emit div32_10 routine - divide (HL) by 10 in place
+    ///
+    /// Returns the remainder in A.
+    ///
+    /// Labels created: `div32_10`, `div32_10_byte`, `div32_10_bit`
+    /// Clobbers BC
+    pub fn emit_div32_10(&mut self) {
+        self.label("div32_10");
+        self.push_de();
+        self.inc_hl();
+        self.inc_hl();
+        self.inc_hl();
+        self.xor_a();                // A = remainder
+        self.ld_c(4);
+        self.label("div32_10_byte");
+        self.ld_e_hl_ind();
+        self.ld_b(8);
+        self.label("div32_10_bit");
+        self.sla_e();
+        self.rla();                  // Remainder * 2 + next bit
+        self.cp(10);
+        self.emit(&[0x38, 0x03]);    // JR C, +3
+        self.sub_a(10);
+        self.inc_e();                // Quotient bit
+        self.djnz("div32_10_bit");
+        self.ld_hl_ind_e();
+        self.dec_hl();
+        self.dec_c();
+        self.jr_nz("div32_10_byte");
+        self.inc_hl();
+        self.pop_de();
+        self.ret();
+    }
+
+    /// Emit print32 routine - print (HL) as unsigned decimal
+    ///
+    /// Works on a copy, so the value is left intact.
+    ///
+    /// Labels created: `print32`, `print32_div`, `print32_out`
+    /// RAM used: `print32_tmp`
+    /// Requires: `div32_10`, `conout`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_print32(&mut self) {
+        let tmp = self.ram_var("print32_tmp", 4);
+        self.label("print32");
+        self.ld_de(tmp);
+        self.ld_bc(4);
+        self.ldir();
+        self.ld_a(0xFF);
+        self.push_af();              // End marker below the digits
+
+        // Push digits, least significant first
+        self.label("print32_div");
+        self.ld_hl(tmp);
+        self.call("div32_10");
+        self.push_af();
+        self.ld_a_hl_ind();
+        for _ in 0..3 {
+            self.inc_hl();
+            self.or_hl_ind();
+        }
+        self.jr_nz("print32_div");
+
+        self.label("print32_out");
+        self.pop_af();
+        self.cp(10);
+        self.ret_nc();               // End marker
+        self.add_a(b'0');
+        self.call("conout");
+        self.jr("print32_out");
+    }
+
+    /// Emit all 32-bit routines
+    pub fn emit_int32_routines(&mut self) {
+        self.emit_add32();
+        self.emit_sub32();
+        self.emit_cmp32();
+        self.emit_shl32();
+        self.emit_shr32();
+        self.emit_div32_10();
+        self.emit_print32();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shl32() {
+        let mut cg = CodeGen::new();
+        cg.emit_shl32();
+        assert_eq!(cg.rom(), &[
+            0xCB, 0x26,        // SLA (HL)
+            0x23,              // INC HL
+            0xCB, 0x16,        // RL (HL)
+            0x23,              // INC HL
+            0xCB, 0x16,        // RL (HL)
+            0x23,              // INC HL
+            0xCB, 0x16,        // RL (HL)
+            0x2B,              // DEC HL
+            0x2B,              // DEC HL
+            0x2B,              // DEC HL
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    fn test_int32_routines_emit() {
+        let mut cg = CodeGen::new();
+        cg.emit_int32_routines();
+        assert_eq!(cg.ram_var_size("print32_tmp"), Some(4));
+        for label in ["add32", "sub32", "cmp32", "shl32", "shr32", "div32_10", "print32"] {
+            assert!(cg.has_label(label), "{}", label);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod emulator_tests {
+    use crate::emulator::Emulator;
+    use crate::CodeGen;
+
+    fn emulator() -> Emulator {
+        let mut rom = CodeGen::new();
+        rom.emit_putchar();
+        rom.emit_conout();
+        rom.emit_int32_routines();
+        rom.resolve_fixups();
+        Emulator::new(&rom)
+    }
+
+    /// Call `label` with HL -> `a` at 0x3000 and DE -> `b` at 0x3010;
+    /// returns the value at HL afterwards
+    fn call(emu: &mut Emulator, label: &str, a: u32, b: u32) -> u32 {
+        emu.poke_bytes(0x3000, &a.to_le_bytes());
+        emu.poke_bytes(0x3010, &b.to_le_bytes());
+        emu.cpu.set_hl(0x3000);
+        emu.cpu.set_de(0x3010);
+        assert!(emu.call_label(label, 100_000));
+        assert_eq!((emu.cpu.hl(), emu.cpu.de()), (0x3000, 0x3010), "{}", label);
+        u32::from_le_bytes([emu.peek(0x3000), emu.peek(0x3001), emu.peek(0x3002), emu.peek(0x3003)])
+    }
+
+    #[test]
+    fn test_run_int32() {
+        let mut emu = emulator();
+        for (a, b) in [(0u32, 0u32), (0xFFFF, 1), (0xFFFFFFFF, 1), (123456789, 987654321), (1, 2)] {
+            assert_eq!((call(&mut emu, "add32", a, b), emu.cpu.f & 1 != 0), a.overflowing_add(b));
+            assert_eq!((call(&mut emu, "sub32", a, b), emu.cpu.f & 1 != 0), a.overflowing_sub(b));
+            assert_eq!(call(&mut emu, "cmp32", a, b), a);
+            assert_eq!((emu.cpu.f & 0x40 != 0, emu.cpu.f & 1 != 0), (a == b, a < b));
+        }
+        assert_eq!((call(&mut emu, "shl32", 0x80000001, 0), emu.cpu.f & 1 != 0), (2, true));
+        assert_eq!((call(&mut emu, "shr32", 0x80000001, 0), emu.cpu.f & 1 != 0), (0x40000000, true));
+        assert_eq!((call(&mut emu, "div32_10", 123456789, 0), emu.cpu.a), (12345678, 9));
+    }
+
+    #[test]
+    fn test_run_print32() {
+        let mut emu = emulator();
+        for n in [0u32, 7, 65536, 4294967295] {
+            emu.poke_bytes(0x3000, &n.to_le_bytes());
+            emu.cpu.set_hl(0x3000);
+            assert!(emu.call_label("print32", 100_000));
+            assert_eq!(emu.take_output(), n.to_string());
+        }
+    }
+}
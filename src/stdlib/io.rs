@@ -1,6 +1,6 @@
 //! MC6850 ACIA Serial I/O routines
 //!
-//! Standard RetroShield configuration:
+//! Standard RetroShield configuration, the default `RomConfig::serial`:
 //! - Status port: 0x80
 //! - Data port: 0x81
 //! - Bit 0 of status: RX ready
@@ -9,6 +9,10 @@
 use crate::CodeGen;
 
 /// MC6850 port configuration
+///
+/// The console's is `RomConfig::serial`; the `_config` emitters take
+/// another, e.g. for a second ACIA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MC6850Config {
     pub status_port: u8,
     pub data_port: u8,
@@ -27,12 +31,93 @@ impl Default for MC6850Config {
     }
 }
 
+/// MC6850 clock divide ratio (control register bits 0-1)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AciaDivide {
+    Div1 = 0,
+    Div16 = 1,
+    Div64 = 2,
+}
+
+/// MC6850 word format: data bits, parity, stop bits (control bits 2-4)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AciaWord {
+    SevenEven2 = 0,
+    SevenOdd2 = 1,
+    SevenEven1 = 2,
+    SevenOdd1 = 3,
+    EightNone2 = 4,
+    EightNone1 = 5,
+    EightEven1 = 6,
+    EightOdd1 = 7,
+}
+
+/// MC6850 transmitter control: RTS level and TX interrupt (control bits 5-6)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AciaTx {
+    /// RTS low, TX interrupt disabled
+    RtsLow = 0,
+    /// RTS low, TX interrupt enabled
+    RtsLowIrq = 1,
+    /// RTS high, TX interrupt disabled
+    RtsHigh = 2,
+    /// RTS low, transmit a break level
+    Break = 3,
+}
+
+/// MC6850 initialization settings for `emit_acia_init`
+pub struct AciaConfig {
+    /// Control register port (the status port, written)
+    pub control_port: u8,
+    pub divide: AciaDivide,
+    pub word: AciaWord,
+    pub tx: AciaTx,
+    /// Enable the receive interrupt (bit 7)
+    pub rx_irq: bool,
+}
+
+impl AciaConfig {
+    /// Control register value for these settings
+    pub fn control(&self) -> u8 {
+        (self.rx_irq as u8) << 7 | (self.tx as u8) << 5 | (self.word as u8) << 2 | self.divide as u8
+    }
+}
+
+impl Default for AciaConfig {
+    /// Divide by 16, 8N1, RTS low, no interrupts (control 0x15)
+    fn default() -> Self {
+        Self {
+            control_port: 0x80,
+            divide: AciaDivide::Div16,
+            word: AciaWord::EightNone1,
+            tx: AciaTx::RtsLow,
+            rx_irq: false,
+        }
+    }
+}
+
 impl CodeGen {
+    /// Emit acia_init routine - master reset, then program the control register
+    ///
+    /// For sketches where the Arduino side leaves the ACIA unconfigured.
+    ///
+    /// Labels created: `acia_init`
+    /// Clobbers: A
+    pub fn emit_acia_init(&mut self, config: &AciaConfig) {
+        self.label("acia_init");
+        self.ld_a(0x03);            // Master reset
+        self.out_a(config.control_port);
+        self.ld_a(config.control());
+        self.out_a(config.control_port);
+        self.ret();
+    }
+
     /// Emit getchar routine (blocking read, char returned in A)
     ///
     /// Labels created: `getchar`
     pub fn emit_getchar(&mut self) {
-        self.emit_getchar_config(&MC6850Config::default());
+        let config = self.config().serial;
+        self.emit_getchar_config(&config);
     }
 
     /// Emit getchar with custom port configuration
@@ -45,11 +130,50 @@ impl CodeGen {
         self.ret();
     }
 
+    /// Emit char_available routine (NZ if a character is waiting, no wait)
+    ///
+    /// Labels created: `char_available`
+    /// Clobbers: A
+    pub fn emit_char_available(&mut self) {
+        let config = self.config().serial;
+        self.emit_char_available_config(&config);
+    }
+
+    /// Emit char_available with custom port configuration
+    pub fn emit_char_available_config(&mut self, config: &MC6850Config) {
+        self.label("char_available");
+        self.in_a(config.status_port);
+        self.and_a(config.rx_ready_bit);
+        self.ret();
+    }
+
+    /// Emit getchar_nowait routine (non-blocking read)
+    ///
+    /// Returns NZ with the character in A, or Z with A = 0 if none is
+    /// waiting.
+    ///
+    /// Labels created: `getchar_nowait`
+    pub fn emit_getchar_nowait(&mut self) {
+        let config = self.config().serial;
+        self.emit_getchar_nowait_config(&config);
+    }
+
+    /// Emit getchar_nowait with custom port configuration
+    pub fn emit_getchar_nowait_config(&mut self, config: &MC6850Config) {
+        self.label("getchar_nowait");
+        self.in_a(config.status_port);
+        self.and_a(config.rx_ready_bit);
+        self.ret_z();
+        self.in_a(config.data_port);     // IN A,(n) keeps the NZ from AND
+        self.ret();
+    }
+
     /// Emit putchar routine (blocking write, char in A)
     ///
     /// Labels created: `putchar`, `putchar_wait`
     pub fn emit_putchar(&mut self) {
-        self.emit_putchar_config(&MC6850Config::default());
+        let config = self.config().serial;
+        self.emit_putchar_config(&config);
     }
 
     /// Emit putchar with custom port configuration
@@ -65,40 +189,87 @@ impl CodeGen {
         self.ret();
     }
 
+    /// Emit conout vector (console output, char in A) pointing at `putchar`
+    ///
+    /// All formatted output routines write through `conout` rather than
+    /// `putchar`, so redirecting them to another device only needs this
+    /// one jump to change.
+    ///
+    /// Labels created: `conout`
+    /// Requires: `putchar`
+    pub fn emit_conout(&mut self) {
+        self.emit_conout_to("putchar");
+    }
+
+    /// Emit conout vector pointing at a custom output routine (char in A)
+    ///
+    /// Labels created: `conout`
+    /// Requires: `target`
+    pub fn emit_conout_to(&mut self, target: &str) {
+        self.label("conout");
+        self.jp(target);
+    }
+
     /// Emit newline routine (prints CR LF)
     ///
     /// Labels created: `newline`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_newline(&mut self) {
         self.label("newline");
         self.ld_a(0x0D); // CR
-        self.call("putchar");
+        self.call("conout");
         self.ld_a(0x0A); // LF
-        self.call("putchar");
+        self.call("conout");
         self.ret();
     }
 
     /// Emit print_string routine (prints null-terminated string at HL)
     ///
     /// Labels created: `print_string`, `print_string_loop`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_print_string(&mut self) {
         self.label("print_string");
         self.label("print_string_loop");
         self.ld_a_hl_ind();      // LD A, (HL)
         self.or_a_a();           // OR A (test for null)
         self.ret_z();            // RET Z (if null, done)
-        self.call("putchar");
+        self.call("conout");
         self.inc_hl();
         self.jp("print_string_loop");
     }
 
+    /// Emit print_pstring routine (prints the length-prefixed string at HL,
+    /// as emitted by `emit_pstring`)
+    ///
+    /// Returns with HL just past the string.
+    ///
+    /// Labels created: `print_pstring`, `print_pstring_loop`
+    /// Requires: `conout`
+    /// Clobbers: A, B
+    pub fn emit_print_pstring(&mut self) {
+        self.label("print_pstring");
+        self.ld_b_hl_ind();      // LD B, (HL) - length
+        self.inc_hl();
+        self.ld_a_b();
+        self.or_a_a();
+        self.ret_z();            // Empty string
+        self.label("print_pstring_loop");
+        self.ld_a_hl_ind();
+        self.push_bc();
+        self.call("conout");
+        self.pop_bc();
+        self.inc_hl();
+        self.djnz("print_pstring_loop");
+        self.ret();
+    }
+
     /// Emit all standard I/O routines
     ///
-    /// Includes: getchar, putchar, newline, print_string
+    /// Includes: getchar, putchar, conout, newline, print_string
     pub fn emit_io_routines(&mut self) {
         self.emit_getchar();
         self.emit_putchar();
+        self.emit_conout();
         self.emit_newline();
         self.emit_print_string();
     }
@@ -107,6 +278,7 @@ impl CodeGen {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::RomConfig;
 
     #[test]
     fn test_getchar_emits() {
@@ -123,4 +295,83 @@ mod tests {
         assert!(cg.has_label("putchar"));
         assert!(cg.has_label("putchar_wait"));
     }
+
+    #[test]
+    fn test_getchar_nowait() {
+        let mut cg = CodeGen::new();
+        cg.emit_getchar_nowait();
+        assert_eq!(cg.rom(), &[
+            0xDB, 0x80,  // IN A, (0x80)
+            0xE6, 0x01,  // AND 1
+            0xC8,        // RET Z
+            0xDB, 0x81,  // IN A, (0x81)
+            0xC9,        // RET
+        ]);
+    }
+
+    #[test]
+    fn test_print_pstring() {
+        let mut cg = CodeGen::new();
+        cg.emit_print_pstring();
+        cg.equ("conout", 0x1234);
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0x46,              // LD B, (HL)
+            0x23,              // INC HL
+            0x78,              // LD A, B
+            0xB7,              // OR A
+            0xC8,              // RET Z
+            0x7E,              // print_pstring_loop: LD A, (HL)
+            0xC5,              // PUSH BC
+            0xCD, 0x34, 0x12,  // CALL conout
+            0xC1,              // POP BC
+            0x23,              // INC HL
+            0x10, 0xF7,        // DJNZ print_pstring_loop
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    fn test_serial_from_rom_config() {
+        let serial = MC6850Config { status_port: 0x10, data_port: 0x11, rx_ready_bit: 0x01, tx_ready_bit: 0x02 };
+        let mut cg = CodeGen::with_config(RomConfig { serial, ..Default::default() });
+        cg.emit_putchar();
+        assert_eq!(cg.rom(), &[
+            0xF5,        // PUSH AF
+            0xDB, 0x10,  // putchar_wait: IN A, (0x10)
+            0xE6, 0x02,  // AND 2
+            0x28, 0xFA,  // JR Z, putchar_wait
+            0xF1,        // POP AF
+            0xD3, 0x11,  // OUT (0x11), A
+            0xC9,        // RET
+        ]);
+    }
+
+    #[test]
+    fn test_acia_init() {
+        let mut cg = CodeGen::new();
+        cg.emit_acia_init(&AciaConfig {
+            divide: AciaDivide::Div64,
+            rx_irq: true,
+            ..Default::default()
+        });
+        assert_eq!(cg.rom(), &[
+            0x3E, 0x03,  // LD A, 3
+            0xD3, 0x80,  // OUT (0x80), A
+            0x3E, 0x96,  // LD A, 0x96
+            0xD3, 0x80,  // OUT (0x80), A
+            0xC9,        // RET
+        ]);
+        assert_eq!(AciaConfig::default().control(), 0x15);
+    }
+
+    #[test]
+    fn test_conout_redirect() {
+        let mut cg = CodeGen::new();
+        cg.emit_conout_to("lcd_putchar");
+        cg.label("lcd_putchar");
+        cg.ret();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[0xC3, 0x03, 0x00, 0xC9]); // JP lcd_putchar
+    }
 }
@@ -0,0 +1,283 @@
+//! Keyboard event layer: debounce, make/break tracking and autorepeat
+//!
+//! Sits above any raw key source that can report the currently held key
+//! (a matrix keypad, PS/2 decoder, ...). The source routine is polled, its
+//! result debounced against a tick counter, and changes are turned into
+//! make/break/repeat events in a small RAM queue.
+//!
+//! The tick counter is a RAM byte incremented periodically (typically by a
+//! timer interrupt); delays and rates below are measured in its ticks.
+
+use crate::CodeGen;
+
+/// Event type: key pressed
+pub const KEY_MAKE: u8 = 1;
+/// Event type: key released
+pub const KEY_BREAK: u8 = 2;
+/// Event type: key held past the repeat delay
+pub const KEY_REPEAT: u8 = 3;
+
+/// Keyboard layer configuration
+pub struct KeyboardConfig {
+    /// Routine returning the currently held key code in A (0 = none)
+    pub scan_routine: String,
+    /// Label of the RAM byte incremented on every tick
+    pub tick_label: String,
+    /// Ticks a new scan result must stay stable before it is accepted
+    pub debounce_ticks: u8,
+    /// Ticks a key must be held before autorepeat starts
+    pub repeat_delay: u8,
+    /// Ticks between autorepeat events
+    pub repeat_rate: u8,
+    /// Event queue capacity (power of two, 2..=128)
+    pub queue_len: u8,
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        Self {
+            scan_routine: "key_scan".to_string(),
+            tick_label: "ticks".to_string(),
+            debounce_ticks: 2,
+            repeat_delay: 30,
+            repeat_rate: 5,
+            queue_len: 8,
+        }
+    }
+}
+
+impl CodeGen {
+    /// Emit the keyboard event layer with default configuration
+    ///
+    /// Labels created: `key_init`, `key_poll`, `key_push`, `key_get`, `key_wait`
+    /// RAM used: `key_raw`, `key_raw_time`, `key_last`, `key_next`,
+    /// `key_head`, `key_tail`, `key_queue`
+    /// Requires: `key_scan`, `ticks`
+    pub fn emit_keyboard_routines(&mut self) {
+        self.emit_keyboard_routines_config(&KeyboardConfig::default());
+    }
+
+    /// Emit the keyboard event layer with custom configuration
+    ///
+    /// - `key_init` clears the layer state; call once at startup.
+    /// - `key_poll` scans the source and queues events; call from the main
+    ///   loop or the tick interrupt.
+    /// - `key_get` returns NZ with A = key code, C = event type when an
+    ///   event is queued, Z when the queue is empty.
+    /// - `key_wait` polls until an event arrives (A = code, C = type).
+    pub fn emit_keyboard_routines_config(&mut self, config: &KeyboardConfig) {
+        assert!(
+            config.queue_len.is_power_of_two() && (2..=128).contains(&config.queue_len),
+            "queue_len must be a power of two between 2 and 128"
+        );
+        let mask = (config.queue_len as u16 * 2 - 1) as u8; // 2 bytes per event
+
+        let key_raw = self.ram_var("key_raw", 1);
+        let key_raw_time = self.ram_var("key_raw_time", 1);
+        let key_last = self.ram_var("key_last", 1);
+        let key_next = self.ram_var("key_next", 1);
+        let key_head = self.ram_var("key_head", 1);
+        let key_tail = self.ram_var("key_tail", 1);
+        let key_queue = self.ram_var("key_queue", config.queue_len as u16 * 2);
+
+        // key_init: clear state
+        self.label("key_init");
+        self.xor_a();
+        self.ld_addr_a(key_raw);
+        self.ld_addr_a(key_last);
+        self.ld_addr_a(key_head);
+        self.ld_addr_a(key_tail);
+        self.emit(&[0x3A]);          // LD A, (tick)
+        self.fixup(&config.tick_label);
+        self.ld_addr_a(key_raw_time);
+        self.ret();
+
+        // key_poll: debounce the raw scan and generate events
+        self.label("key_poll");
+        self.call(&config.scan_routine);
+        self.ld_hl(key_raw);
+        self.cp_hl_ind();
+        let stable = self.unique_label("key_stable");
+        self.jp_z(&stable);
+        self.ld_hl_ind_a();          // Raw state changed: restart debounce
+        self.emit(&[0x3A]);          // LD A, (tick)
+        self.fixup(&config.tick_label);
+        self.ld_addr_a(key_raw_time);
+        self.ret();
+
+        self.label(&stable);
+        self.emit(&[0x3A]);          // LD A, (tick)
+        self.fixup(&config.tick_label);
+        self.ld_hl(key_raw_time);
+        self.sub_hl_ind();
+        self.cp(config.debounce_ticks);
+        self.ret_c();                // Not stable long enough yet
+        self.ld_a_addr(key_raw);
+        self.ld_hl(key_last);
+        self.cp_hl_ind();
+        let held = self.unique_label("key_held");
+        self.jp_z(&held);
+
+        // Accepted key changed: break old key, make new key
+        self.ld_b_a();               // B = new key
+        self.ld_a_hl_ind();          // A = old key
+        self.or_a_a();
+        self.ld_c(KEY_BREAK);
+        self.call_nz("key_push");
+        self.ld_a_b();
+        self.ld_addr_a(key_last);
+        self.or_a_a();
+        self.ret_z();                // Released, nothing held now
+        self.ld_c(KEY_MAKE);
+        self.call("key_push");
+        self.emit(&[0x3A]);          // LD A, (tick)
+        self.fixup(&config.tick_label);
+        self.add_a(config.repeat_delay);
+        self.ld_addr_a(key_next);
+        self.ret();
+
+        // Same key still held: autorepeat once the deadline passes
+        self.label(&held);
+        self.or_a_a();
+        self.ret_z();                // No key held
+        self.ld_b_a();
+        self.emit(&[0x3A]);          // LD A, (tick)
+        self.fixup(&config.tick_label);
+        self.ld_hl(key_next);
+        self.sub_hl_ind();
+        self.bit_a(7);
+        self.ret_nz();               // tick - next < 0: not due yet
+        self.emit(&[0x3A]);          // LD A, (tick)
+        self.fixup(&config.tick_label);
+        self.add_a(config.repeat_rate);
+        self.ld_addr_a(key_next);
+        self.ld_a_b();
+        self.ld_c(KEY_REPEAT);
+        // Fall through to key_push
+
+        // key_push: queue event (A = code, C = type), dropped if full
+        // Clobbers A, DE, HL
+        self.label("key_push");
+        self.ld_e_a();
+        self.ld_a_addr(key_head);
+        self.ld_d_a();               // D = old head
+        self.add_a(2);
+        self.and_a(mask);
+        self.ld_hl(key_tail);
+        self.cp_hl_ind();
+        self.ret_z();                // Queue full
+        self.push_af();              // New head
+        self.ld_hl(key_queue);
+        self.emit(&[0x7A]);          // LD A, D
+        self.add_a_l();
+        self.emit(&[0x6F]);          // LD L, A
+        self.emit(&[0x30, 0x01]);    // JR NC, +1
+        self.emit(&[0x24]);          // INC H
+        self.emit(&[0x71]);          // LD (HL), C
+        self.inc_hl();
+        self.emit(&[0x73]);          // LD (HL), E
+        self.pop_af();
+        self.ld_addr_a(key_head);    // Publish only after the event is written
+        self.ret();
+
+        // key_get: dequeue event -> NZ, A = code, C = type; Z if empty
+        // Clobbers B, E, HL
+        self.label("key_get");
+        self.ld_a_addr(key_tail);
+        self.ld_hl(key_head);
+        self.cp_hl_ind();
+        self.ret_z();
+        self.ld_e_a();
+        self.ld_hl(key_queue);
+        self.add_a_l();
+        self.emit(&[0x6F]);          // LD L, A
+        self.emit(&[0x30, 0x01]);    // JR NC, +1
+        self.emit(&[0x24]);          // INC H
+        self.emit(&[0x4E]);          // LD C, (HL)
+        self.inc_hl();
+        self.emit(&[0x46]);          // LD B, (HL)
+        self.ld_a_e();
+        self.add_a(2);
+        self.and_a(mask);
+        self.ld_addr_a(key_tail);
+        self.ld_a_b();
+        self.inc_c();
+        self.dec_c();                // NZ: event types are never zero
+        self.ret();
+
+        // key_wait: block until an event is available
+        self.label("key_wait");
+        self.call("key_poll");
+        self.call("key_get");
+        self.jr_z("key_wait");
+        self.ret();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyboard_emits() {
+        let mut cg = CodeGen::new();
+        cg.emit_keyboard_routines();
+        assert!(cg.has_label("key_poll"));
+        assert!(cg.has_label("key_get"));
+        assert_eq!(cg.get_label("key_raw"), Some(0x2000));
+        assert_eq!(cg.ram_pos(), 0x2000 + 6 + 16);
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod emulator_tests {
+    use super::*;
+    use crate::emulator::{Emulator, FLAG_Z};
+
+    /// Poll once per tick with the key held at each tick, collecting
+    /// (tick, code, type) events
+    fn run(held: impl Fn(u8) -> u8, ticks: u8) -> Vec<(u8, u8, u8)> {
+        let mut rom = CodeGen::new();
+        let pressed = rom.ram_var("pressed", 1);
+        rom.ram_var("ticks", 1);
+        rom.label("key_scan");
+        rom.ld_a_addr(pressed);
+        rom.ret();
+        rom.emit_keyboard_routines();
+        rom.resolve_fixups();
+
+        let mut emu = Emulator::new(&rom);
+        let tick = emu.label("ticks").unwrap();
+        assert!(emu.call_label("key_init", 1_000));
+        let mut events = Vec::new();
+        for t in 1..=ticks {
+            emu.poke(tick, t);
+            emu.poke(pressed, held(t));
+            assert!(emu.call_label("key_poll", 1_000));
+            loop {
+                assert!(emu.call_label("key_get", 1_000));
+                if emu.cpu.f & FLAG_Z != 0 {
+                    break;                  // Queue empty
+                }
+                events.push((t, emu.cpu.a, emu.cpu.c));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_run_key_events() {
+        // One-tick glitch at 1, then 'A' held from 10 to 54
+        let events = run(|t| if t == 1 || (10..55).contains(&t) { b'A' } else { 0 }, 70);
+        assert_eq!(
+            events,
+            vec![
+                (12, b'A', KEY_MAKE),       // After the 2-tick debounce
+                (42, b'A', KEY_REPEAT),     // repeat_delay 30
+                (47, b'A', KEY_REPEAT),     // then every 5
+                (52, b'A', KEY_REPEAT),
+                (57, b'A', KEY_BREAK),      // Release, debounced
+            ]
+        );
+    }
+}
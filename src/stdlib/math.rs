@@ -9,7 +9,7 @@ impl CodeGen {
     /// Always prints exactly what's needed, no leading zeros except for zero itself
     ///
     /// Labels created: `print_byte_dec`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_print_byte_dec(&mut self) {
         self.label("print_byte_dec");
         // Use stack to reverse digits
@@ -40,32 +40,202 @@ impl CodeGen {
         let print_loop = self.unique_label("pbd_print");
         self.label(&print_loop);
         self.pop_af();
-        self.call("putchar");
+        self.call("conout");
         self.dec_c();
         self.jp_nz(&print_loop);
         self.ret();
     }
 
+    /// Emit print_word_dec routine - prints HL as unsigned decimal
+    ///
+    /// `print_word_dec` prints without leading zeros; `print_word_dec_pad`
+    /// prints all five digits (`00042`). Both clobber A, BC, DE, HL.
+    ///
+    /// Labels created: `print_word_dec`, `print_word_dec_pad`
+    /// Requires: `div16`, `conout`
+    pub fn emit_print_word_dec(&mut self) {
+        self.label("print_word_dec");
+        self.xor_a();            // A = digits still required
+        let start = self.unique_label("pwd_start");
+        self.jp(&start);
+        self.label("print_word_dec_pad");
+        self.ld_a(5);
+        self.label(&start);
+        self.ld_bc(0xFFFF);
+        self.push_bc();          // End marker below the digits
+
+        // Push digits, least significant first
+        let extract = self.unique_label("pwd_ext");
+        self.label(&extract);
+        self.push_af();
+        self.ld_de(10);
+        self.call("div16");      // HL = HL / 10, DE = digit
+        self.pop_af();
+        self.push_de();
+        let counted = self.unique_label("pwd_cnt");
+        self.or_a_a();
+        self.jp_z(&counted);
+        self.dec_a();
+        self.label(&counted);
+        self.ld_b_a();
+        self.or_h();
+        self.or_l();
+        self.ld_a_b();
+        self.jp_nz(&extract);    // More digits, or padding
+
+        // Pop and print down to the end marker
+        let print = self.unique_label("pwd_print");
+        self.label(&print);
+        self.pop_de();
+        self.inc_d();
+        self.ret_z();            // D = 0xFF only for the marker
+        self.ld_a_e();
+        self.add_a(b'0');
+        self.call("conout");
+        self.jp(&print);
+    }
+
+    /// Emit print_word_sdec routine - prints HL as signed decimal
+    ///
+    /// Negative values get a leading `-`. Clobbers A, BC, DE, HL.
+    ///
+    /// Labels created: `print_word_sdec`
+    /// Requires: `print_word_dec`, `negate_hl`, `conout`
+    pub fn emit_print_word_sdec(&mut self) {
+        self.label("print_word_sdec");
+        self.bit_h(7);
+        self.jp_z("print_word_dec");
+        self.ld_a(b'-');
+        self.call("conout");
+        self.call("negate_hl");
+        self.jp("print_word_dec");
+    }
+
+    /// Emit print_hex_byte routine - prints A as two hex digits
+    ///
+    /// `print_hex_digit` prints the low nibble of A as one digit.
+    /// Both clobber A only.
+    ///
+    /// Labels created: `print_hex_byte`, `print_hex_digit`
+    /// Requires: `conout`
+    pub fn emit_print_hex_byte(&mut self) {
+        self.label("print_hex_byte");
+        self.push_af();
+        self.rrca();
+        self.rrca();
+        self.rrca();
+        self.rrca();
+        self.call("print_hex_digit");
+        self.pop_af();
+        self.label("print_hex_digit");
+        self.and_a(0x0F);
+        self.add_a(0x90);        // 0-9 -> 0x90-0x99, A-F -> 0x00-0x05 + carry
+        self.daa();
+        self.adc_a(0x40);
+        self.daa();              // '0'-'9' or 'A'-'F'
+        self.jp("conout");
+    }
+
+    /// Emit print_hex_word routine - prints HL as four hex digits
+    ///
+    /// Preserves HL; clobbers A.
+    ///
+    /// Labels created: `print_hex_word`
+    /// Requires: `print_hex_byte`
+    pub fn emit_print_hex_word(&mut self) {
+        self.label("print_hex_word");
+        self.ld_a_h();
+        self.call("print_hex_byte");
+        self.ld_a_l();
+        self.jp("print_hex_byte");
+    }
+
     /// Emit div16 routine - 16-bit division HL / DE -> HL quotient, DE remainder
     ///
-    /// Labels created: `div16`, `div16_loop`, `div16_done`
+    /// Restoring shift-and-subtract over the 16 dividend bits, so the time
+    /// is roughly fixed (about 1300 T-states). Dividing by 0 gives a quotient
+    /// of 0xFFFF with HL as the remainder. Clobbers A, BC.
+    ///
+    /// Labels created: `div16`, `div16_loop`, `div16_over`, `div16_fit`, `div16_done`
     pub fn emit_div16(&mut self) {
         self.label("div16");
-        self.ld_bc(0);           // BC = quotient
+        self.ld_a_h();
+        self.ld_c_l();           // A:C = dividend, shifted into the quotient
+        self.ld_hl(0);           // HL = remainder
+        self.ld_b(16);
 
         self.label("div16_loop");
-        self.or_a_a();           // Clear carry
-        self.sbc_hl_de();        // HL = HL - DE
-        self.jp_c("div16_done");
-        self.inc_bc();
-        self.jp("div16_loop");
+        self.sla_c();
+        self.rla();              // Next dividend bit into carry
+        self.adc_hl_hl();        // ...and into the remainder
+        self.emit(&[0x38]);      // JR C, div16_over (remainder is 17 bits)
+        self.fixup_rel("div16_over", 0);
+        self.sbc_hl_de();        // Carry clear from ADC
+        self.emit(&[0x30]);      // JR NC, div16_fit
+        self.fixup_rel("div16_fit", 0);
+        self.add_hl_de();        // Doesn't fit: restore, quotient bit 0
+        self.djnz("div16_loop");
+        self.emit(&[0x18]);      // JR div16_done
+        self.fixup_rel("div16_done", 0);
+
+        self.label("div16_over");
+        self.ccf();              // Subtract without the borrow
+        self.sbc_hl_de();
+        self.label("div16_fit");
+        self.inc_c();            // Quotient bit 1
+        self.djnz("div16_loop");
 
         self.label("div16_done");
-        self.add_hl_de();        // Restore remainder to HL
         self.ex_de_hl();         // DE = remainder
-        // Move BC to HL
-        self.emit(&[0x60]);      // LD H, B
-        self.emit(&[0x69]);      // LD L, C
+        self.ld_h_a();
+        self.ld_l_c();           // HL = quotient
+        self.ret();
+    }
+
+    /// Emit sdiv16 routine - signed division HL / DE -> HL quotient, DE remainder
+    ///
+    /// Rounds toward zero like C: the remainder takes the dividend's sign
+    /// (-7 / 2 = -3 remainder -1). -32768 / -1 wraps to -32768.
+    /// Clobbers A, BC.
+    ///
+    /// Labels created: `sdiv16`
+    /// Requires: `div16`, `negate_hl`
+    pub fn emit_sdiv16(&mut self) {
+        self.label("sdiv16");
+        self.ld_a_h();
+        self.push_af();          // Bit 7: remainder sign
+        self.xor_d();
+        self.push_af();          // Bit 7: quotient sign
+        self.bit_h(7);
+        self.call_nz("negate_hl");
+        self.ex_de_hl();
+        self.bit_h(7);
+        self.call_nz("negate_hl");
+        self.ex_de_hl();
+        self.call("div16");
+        self.pop_af();
+        self.or_a_a();
+        self.call_m("negate_hl");
+        self.pop_af();
+        self.or_a_a();
+        self.ret_p();
+        self.ex_de_hl();
+        self.call("negate_hl");
+        self.ex_de_hl();
+        self.ret();
+    }
+
+    /// Emit smod16 routine - signed remainder HL % DE -> HL
+    ///
+    /// The remainder takes the dividend's sign, as with `sdiv16`; DE is
+    /// left holding the quotient. Clobbers A, BC.
+    ///
+    /// Labels created: `smod16`
+    /// Requires: `sdiv16`
+    pub fn emit_smod16(&mut self) {
+        self.label("smod16");
+        self.call("sdiv16");
+        self.ex_de_hl();
         self.ret();
     }
 
@@ -86,7 +256,7 @@ impl CodeGen {
         // HL = 0, C = multiplicand, B = multiplier
         self.label("mul8_loop");
         self.emit(&[0x79]);      // LD A, C
-        self.emit(&[0x85]);      // ADD A, L
+        self.add_a_l();
         self.emit(&[0x6F]);      // LD L, A
         self.emit(&[0x30, 0x01]); // JR NC, +1
         self.emit(&[0x24]);      // INC H
@@ -94,6 +264,35 @@ impl CodeGen {
         self.ret();
     }
 
+    /// Emit mul16 routine - 16-bit multiply HL * DE -> DE:HL
+    ///
+    /// Shift-and-add over the 16 multiplier bits, so the time is fixed
+    /// (around 1100 T-states). DE holds the high word of the product.
+    /// Clobbers A, BC.
+    ///
+    /// Labels created: `mul16`, `mul16_loop`
+    pub fn emit_mul16(&mut self) {
+        self.label("mul16");
+        self.ld_b_h();
+        self.ld_c_l();           // BC = multiplicand
+        self.ld_hl(0);
+        self.ld_a(16);
+
+        // DE:HL shifts left: multiplier bits leave the top of DE while the
+        // product's high bits move into the bottom
+        self.label("mul16_loop");
+        self.add_hl_hl();
+        self.rl_e();
+        self.rl_d();
+        self.emit(&[0x30, 0x04]); // JR NC, +4
+        self.add_hl_bc();
+        self.emit(&[0x30, 0x01]); // JR NC, +1
+        self.inc_de();
+        self.dec_a();
+        self.jr_nz("mul16_loop");
+        self.ret();
+    }
+
     /// Emit negate_hl routine - negate HL (two's complement)
     ///
     /// Labels created: `negate_hl`
@@ -109,6 +308,126 @@ impl CodeGen {
         self.ret();
     }
 
+    /// Emit parse_dec routine - parse an unsigned decimal number
+    ///
+    /// Input: HL = ASCII string. Reads digits up to the first non-digit.
+    /// Output: DE = value, HL = first character after the digits, carry set
+    /// if there were no digits or the value exceeds 65535 (HL then points at
+    /// the digit that overflowed). Clobbers A, B.
+    ///
+    /// Labels created: `parse_dec`
+    pub fn emit_parse_dec(&mut self) {
+        self.label("parse_dec");
+        self.ld_de(0);
+        self.ld_b(0);            // B = 1 once a digit is seen
+        let next = self.unique_label("pdec_next");
+        let done = self.unique_label("pdec_done");
+        let overflow = self.unique_label("pdec_ovf");
+        self.label(&next);
+        self.ld_a_hl_ind();
+        self.sub_a(b'0');
+        self.jp_c(&done);
+        self.cp(10);
+        self.jp_nc(&done);
+        // DE = DE * 10 + digit
+        self.push_hl();
+        self.ld_h_d();
+        self.ld_l_e();
+        self.add_hl_hl();        // x2
+        self.jp_c(&overflow);
+        self.ld_d_h();
+        self.ld_e_l();
+        self.add_hl_hl();        // x4
+        self.jp_c(&overflow);
+        self.add_hl_hl();        // x8
+        self.jp_c(&overflow);
+        self.add_hl_de();        // x10
+        self.jp_c(&overflow);
+        self.ld_e_a();
+        self.ld_d(0);
+        self.add_hl_de();
+        self.jp_c(&overflow);
+        self.ex_de_hl();
+        self.pop_hl();
+        self.inc_hl();
+        self.ld_b(1);
+        self.jp(&next);
+
+        self.label(&overflow);
+        self.pop_hl();
+        self.scf();
+        self.ret();
+
+        self.label(&done);
+        self.ld_a_b();
+        self.cp(1);              // Carry if no digits
+        self.ret();
+    }
+
+    /// Emit parse_hex routine - parse an unsigned hex number
+    ///
+    /// Input: HL = ASCII string. Reads hex digits (either case) up to the
+    /// first other character.
+    /// Output: DE = value, HL = first character after the digits, carry set
+    /// if there were no digits or more than four significant ones.
+    /// Clobbers A, B, C.
+    ///
+    /// `parse_hex_digit` converts the hex digit in A to its value, with
+    /// carry set if A is not a hex digit.
+    ///
+    /// Labels created: `parse_hex`, `parse_hex_digit`
+    pub fn emit_parse_hex(&mut self) {
+        self.label("parse_hex");
+        self.ld_de(0);
+        self.ld_b(0);            // B = 1 once a digit is seen
+        let next = self.unique_label("phex_next");
+        let done = self.unique_label("phex_done");
+        self.label(&next);
+        self.ld_a_hl_ind();
+        self.call("parse_hex_digit");
+        self.jp_c(&done);
+        self.ld_c_a();
+        self.ld_a_d();
+        self.and_a(0xF0);
+        self.scf();
+        self.ret_nz();           // A fifth significant digit
+        self.ex_de_hl();
+        self.add_hl_hl();
+        self.add_hl_hl();
+        self.add_hl_hl();
+        self.add_hl_hl();
+        self.ld_a_l();
+        self.or_c();
+        self.ld_l_a();
+        self.ex_de_hl();
+        self.inc_hl();
+        self.ld_b(1);
+        self.jp(&next);
+
+        self.label(&done);
+        self.ld_a_b();
+        self.cp(1);              // Carry if no digits
+        self.ret();
+
+        self.label("parse_hex_digit");
+        self.cp(b'0');
+        self.ret_c();
+        self.cp(b'9' + 1);
+        let decimal = self.unique_label("phex_dec");
+        self.jp_c(&decimal);
+        self.or_a(0x20);         // Lower case
+        self.cp(b'a');
+        self.ret_c();
+        self.cp(b'f' + 1);
+        self.ccf();
+        self.ret_c();
+        self.sub_a(b'a' - 10);
+        self.ret();
+        self.label(&decimal);
+        self.sub_a(b'0');
+        self.ret();
+    }
+
     /// Emit all math routines
     pub fn emit_math_routines(&mut self) {
         self.emit_print_byte_dec();
@@ -121,6 +440,80 @@ impl CodeGen {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_print_word_sdec() {
+        let mut cg = CodeGen::new();
+        cg.emit_print_word_sdec();
+        assert_eq!(cg.rom(), &[
+            0xCB, 0x7C,        // BIT 7, H
+            0xCA, 0x00, 0x00,  // JP Z, print_word_dec (unresolved)
+            0x3E, 0x2D,        // LD A, '-'
+            0xCD, 0x00, 0x00,  // CALL conout (unresolved)
+            0xCD, 0x00, 0x00,  // CALL negate_hl (unresolved)
+            0xC3, 0x00, 0x00,  // JP print_word_dec (unresolved)
+        ]);
+    }
+
+    #[test]
+    fn test_print_hex_word() {
+        let mut cg = CodeGen::new();
+        cg.emit_print_hex_word();
+        assert_eq!(cg.rom(), &[
+            0x7C,              // LD A, H
+            0xCD, 0x00, 0x00,  // CALL print_hex_byte (unresolved)
+            0x7D,              // LD A, L
+            0xC3, 0x00, 0x00,  // JP print_hex_byte (unresolved)
+        ]);
+    }
+
+    #[test]
+    fn test_parse_routines_emit() {
+        let mut cg = CodeGen::new();
+        cg.emit_parse_dec();
+        cg.emit_parse_hex();
+        cg.resolve_fixups();
+        assert!(cg.has_label("parse_dec"));
+        assert!(cg.has_label("parse_hex_digit"));
+        assert_eq!(&cg.rom()[..6], &[
+            0x11, 0x00, 0x00,  // LD DE, 0
+            0x06, 0x00,        // LD B, 0
+            0x7E,              // LD A, (HL)
+        ]);
+    }
+
+    #[test]
+    fn test_mul16() {
+        let mut cg = CodeGen::new();
+        cg.emit_mul16();
+        assert_eq!(cg.rom(), &[
+            0x44,              // LD B, H
+            0x4D,              // LD C, L
+            0x21, 0x00, 0x00,  // LD HL, 0
+            0x3E, 0x10,        // LD A, 16
+            0x29,              // ADD HL, HL
+            0xCB, 0x13,        // RL E
+            0xCB, 0x12,        // RL D
+            0x30, 0x04,        // JR NC, +4
+            0x09,              // ADD HL, BC
+            0x30, 0x01,        // JR NC, +1
+            0x13,              // INC DE
+            0x3D,              // DEC A
+            0x20, 0xF2,        // JR NZ, mul16_loop
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    fn test_smod16() {
+        let mut cg = CodeGen::new();
+        cg.emit_smod16();
+        assert_eq!(cg.rom(), &[
+            0xCD, 0x00, 0x00,  // CALL sdiv16 (unresolved)
+            0xEB,              // EX DE, HL
+            0xC9,              // RET
+        ]);
+    }
+
     #[test]
     fn test_div16_emits() {
         let mut cg = CodeGen::new();
@@ -129,3 +522,71 @@ mod tests {
         assert!(cg.has_label("div16_loop"));
     }
 }
+
+#[cfg(all(test, feature = "emulator"))]
+mod emulator_tests {
+    use crate::emulator::Emulator;
+    use crate::CodeGen;
+
+    fn emulator() -> Emulator {
+        let mut rom = CodeGen::new();
+        rom.emit_div16();
+        rom.emit_negate_hl();
+        rom.emit_sdiv16();
+        rom.emit_mul16();
+        rom.emit_parse_dec();
+        rom.emit_parse_hex();
+        rom.resolve_fixups();
+        Emulator::new(&rom)
+    }
+
+    fn call(emu: &mut Emulator, label: &str, hl: u16, de: u16) -> (u16, u16) {
+        emu.cpu.set_hl(hl);
+        emu.cpu.set_de(de);
+        assert!(emu.call_label(label, 10_000));
+        (emu.cpu.hl(), emu.cpu.de())
+    }
+
+    #[test]
+    fn test_run_div16() {
+        let mut emu = emulator();
+        for (a, b) in [(0, 1), (1000, 7), (0xFFFF, 0xFFFF), (0x8001, 2), (12345, 10)] {
+            assert_eq!(call(&mut emu, "div16", a, b), (a / b, a % b), "{} / {}", a, b);
+        }
+        assert_eq!(call(&mut emu, "div16", 1234, 0), (0xFFFF, 1234));
+        for (a, b) in [(-7i16, 2i16), (100, -3), (-32768, 7)] {
+            let (q, r) = call(&mut emu, "sdiv16", a as u16, b as u16);
+            assert_eq!((q as i16, r as i16), (a / b, a % b), "{} / {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_run_mul16() {
+        let mut emu = emulator();
+        for (a, b) in [(0u16, 0u16), (1234, 5678), (0xFFFF, 0xFFFF), (0x8000, 2)] {
+            let (lo, hi) = call(&mut emu, "mul16", a, b);
+            assert_eq!((hi as u32) << 16 | lo as u32, a as u32 * b as u32, "{} * {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_run_parse() {
+        let mut emu = emulator();
+        for (label, text, value, len, error) in [
+            ("parse_dec", &b"12345 "[..], 12345, 5, false),
+            ("parse_dec", b"007,", 7, 3, false),
+            ("parse_hex", b"1aF:", 0x1AF, 3, false),
+            ("parse_hex", b"BEEF", 0xBEEF, 4, false),
+        ] {
+            emu.poke_bytes(0x3000, text);
+            emu.poke(0x3000 + text.len() as u16, 0);
+            let (hl, de) = call(&mut emu, label, 0x3000, 0);
+            assert_eq!((de, hl - 0x3000, emu.cpu.f & 1 != 0), (value, len, error), "{}", label);
+        }
+        for (label, text) in [("parse_dec", &b"65536\0"[..]), ("parse_dec", b"x\0"), ("parse_hex", b"10000\0")] {
+            emu.poke_bytes(0x3000, text);
+            call(&mut emu, label, 0x3000, 0);
+            assert!(emu.cpu.f & 1 != 0, "{} {:?}", label, text);
+        }
+    }
+}
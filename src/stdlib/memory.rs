@@ -0,0 +1,204 @@
+//! Memory block routines
+//!
+//! Block copy and fill, and block comparison, including the natural-order key compare used to sort
+//! 8.3 directory entries (FAT directory entries, CP/M FCB names).
+
+use crate::CodeGen;
+
+impl CodeGen {
+    /// Emit memcpy routine - copy BC bytes from HL to DE
+    ///
+    /// Uses LDIR, so the blocks may only overlap if DE is below HL. A count
+    /// of 0 copies nothing. Returns HL and DE just past the blocks, BC = 0.
+    ///
+    /// Labels created: `memcpy`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_memcpy(&mut self) {
+        self.label("memcpy");
+        self.ld_a_b();
+        self.or_c();
+        self.ret_z();                // LDIR would copy 64K
+        self.ldir();
+        self.ret();
+    }
+
+    /// Emit memset routine - fill BC bytes at HL with A
+    ///
+    /// Stores the first byte, then lets LDIR ripple it through the rest of
+    /// the block. A count of 0 writes nothing.
+    ///
+    /// Labels created: `memset`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_memset(&mut self) {
+        self.label("memset");
+        self.ld_e_a();
+        self.ld_a_b();
+        self.or_c();
+        self.ret_z();
+        self.ld_hl_ind_e();
+        self.dec_bc();
+        self.ld_a_b();
+        self.or_c();
+        self.ret_z();                // Single byte
+        self.ld_d_h();
+        self.ld_e_l();
+        self.inc_de();
+        self.ldir();                 // (HL) -> (HL+1), BC times
+        self.ret();
+    }
+
+    /// Emit a fixed-length block compare `cmp_mem_<n>`
+    ///
+    /// Compares the `n` bytes at HL with those at DE: Z if equal, otherwise
+    /// NZ with C set if the HL block is lower. Also emits the shared
+    /// `cmp_mem` routine (BC = length, must be nonzero) the first time.
+    ///
+    /// Labels created: `cmp_mem_<n>`, `cmp_mem`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_cmp_mem(&mut self, n: u16) {
+        assert!(n > 0, "cmp_mem length must be at least 1");
+        let entry = format!("cmp_mem_{}", n);
+        if self.has_label(&entry) {
+            return;
+        }
+        self.label(&entry);
+        self.ld_bc(n);
+        if self.has_label("cmp_mem") {
+            self.jp("cmp_mem");
+            return;
+        }
+
+        // cmp_mem: compare BC bytes at HL and DE. CPI compares A with (HL),
+        // so the pointers are swapped while scanning.
+        self.label("cmp_mem");
+        self.ex_de_hl();
+        self.label("cmp_mem_loop");
+        self.emit(&[0x1A]);          // LD A, (DE)
+        self.emit(&[0xED, 0xA1]);    // CPI
+        let diff = self.unique_label("cmp_mem_diff");
+        self.jp_nz(&diff);
+        self.inc_de();
+        self.emit(&[0xEA]);          // JP PE, cmp_mem_loop (BC != 0)
+        self.fixup("cmp_mem_loop");
+        self.ex_de_hl();             // Z still set from the last CPI
+        self.ret();
+        self.label(&diff);
+        self.dec_hl();
+        self.cp_hl_ind();            // CPI leaves carry alone: redo for C
+        self.ex_de_hl();
+        self.ret();
+    }
+
+    /// Emit natural-order compare of 8.3 directory names
+    ///
+    /// HL and DE point at 11-byte space-padded names (8 name + 3 extension
+    /// bytes). Names compare first, then extensions; runs of digits compare
+    /// by value, so `FILE2` sorts before `FILE10`. Bit 7 (CP/M attribute
+    /// flags) is ignored. Returns Z if equal, otherwise NZ with C set if the
+    /// HL entry sorts first.
+    ///
+    /// Labels created: `dir_key_cmp`, `nat_cmp`, `nat_run`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_dir_key_cmp(&mut self) {
+        self.label("dir_key_cmp");
+        self.ld_b(8);
+        self.call("nat_cmp");
+        self.ret_nz();
+        self.ld_b(3);
+        // Fall through: compare extensions
+
+        // nat_cmp: compare B bytes at HL and DE in natural order
+        self.label("nat_cmp");
+        self.emit(&[0x1A]);          // LD A, (DE)
+        self.and_a(0x7F);
+        self.sub_a(b'0');
+        self.cp(10);
+        let plain = self.unique_label("nat_plain");
+        self.jp_nc(&plain);
+        self.ld_a_hl_ind();
+        self.and_a(0x7F);
+        self.sub_a(b'0');
+        self.cp(10);
+        self.jp_nc(&plain);
+        // Both digits: the longer digit run is the larger number
+        self.ex_de_hl();
+        self.call("nat_run");
+        self.ex_de_hl();
+        self.ld_c_a();               // C = DE run length
+        self.call("nat_run");        // A = HL run length
+        self.cp_c();
+        self.ret_nz();
+        self.label(&plain);
+        self.emit(&[0x1A]);          // LD A, (DE)
+        self.and_a(0x7F);
+        self.ld_c_a();
+        self.ld_a_hl_ind();
+        self.and_a(0x7F);
+        self.cp_c();
+        self.ret_nz();
+        self.inc_hl();
+        self.inc_de();
+        self.djnz("nat_cmp");
+        self.ret();                  // Z from the last CP
+
+        // nat_run: A = number of digits at HL, at most B (preserves BC, HL)
+        self.label("nat_run");
+        self.push_hl();
+        self.push_bc();
+        self.ld_c(0);
+        self.label("nat_run_loop");
+        self.ld_a_hl_ind();
+        self.and_a(0x7F);
+        self.sub_a(b'0');
+        self.cp(10);
+        let done = self.unique_label("nat_run_done");
+        self.jp_nc(&done);
+        self.inc_c();
+        self.inc_hl();
+        self.djnz("nat_run_loop");
+        self.label(&done);
+        self.ld_a_c();
+        self.pop_bc();
+        self.pop_hl();
+        self.ret();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memset() {
+        let mut cg = CodeGen::new();
+        cg.emit_memset();
+        assert_eq!(cg.rom(), &[
+            0x5F,              // LD E, A
+            0x78,              // LD A, B
+            0xB1,              // OR C
+            0xC8,              // RET Z
+            0x73,              // LD (HL), E
+            0x0B,              // DEC BC
+            0x78,              // LD A, B
+            0xB1,              // OR C
+            0xC8,              // RET Z
+            0x54,              // LD D, H
+            0x5D,              // LD E, L
+            0x13,              // INC DE
+            0xED, 0xB0,        // LDIR
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    fn test_cmp_mem_shares_core() {
+        let mut cg = CodeGen::new();
+        cg.emit_cmp_mem(11);
+        let core = cg.size();
+        cg.emit_cmp_mem(4);
+        cg.emit_cmp_mem(4);
+        assert!(cg.has_label("cmp_mem_11"));
+        assert!(cg.has_label("cmp_mem_4"));
+        assert_eq!(cg.size(), core + 6); // LD BC, 4; JP cmp_mem
+    }
+}
@@ -0,0 +1,124 @@
+//! RAM test
+//!
+//! A sanity check for new boards: walking ones through every byte, then
+//! an address-in-address pass that catches shorted or open address lines
+//! (aliased locations read back another address's pattern).
+
+use crate::CodeGen;
+
+impl CodeGen {
+    /// Emit memtest routine - test RAM from `start` to `end` inclusive
+    ///
+    /// Prints each failing address as four hex digits and a space (an
+    /// address can be reported by both passes). Returns carry set if
+    /// anything failed. The test overwrites the range, so the stack must
+    /// lie outside it.
+    ///
+    /// Labels created: `memtest`, `memtest_fail`
+    /// Requires: `print_hex_word`, `conout`
+    /// Clobbers A, DE, HL
+    pub fn emit_memtest(&mut self, start: u16, end: u16) {
+        assert!(start <= end, "memtest range {:#06X}-{:#06X} is empty", start, end);
+
+        self.label("memtest");
+        self.ld_d(0);                // D = 1 after a failure
+
+        // Pass 1: walking ones, then zero, in each byte
+        let walk = self.unique_label("memtest_walk");
+        let bit = self.unique_label("memtest_bit");
+        let bad = self.unique_label("memtest_bad");
+        let walk_next = self.unique_label("memtest_walk_next");
+        self.ld_hl(start);
+        self.label(&walk);
+        self.ld_e(1);
+        self.label(&bit);
+        self.ld_hl_ind_e();
+        self.ld_a_hl_ind();
+        self.cp_e();
+        self.jp_nz(&bad);
+        self.sla_e();
+        self.jr_nz(&bit);
+        self.ld_hl_ind_e();          // E = 0
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z(&walk_next);
+        self.label(&bad);
+        self.call("memtest_fail");
+        self.label(&walk_next);
+        let fill = self.unique_label("memtest_fill");
+        self.memtest_step(end, &walk, &fill);
+
+        // Pass 2: each byte holds its address's low ^ high byte
+        let fill_loop = self.unique_label("memtest_fill_loop");
+        let verify = self.unique_label("memtest_verify");
+        self.label(&fill);
+        self.ld_hl(start);
+        self.label(&fill_loop);
+        self.ld_a_l();
+        self.xor_h();
+        self.ld_hl_ind_a();
+        self.memtest_step(end, &fill_loop, &verify);
+
+        let verify_loop = self.unique_label("memtest_verify_loop");
+        let done = self.unique_label("memtest_done");
+        self.label(&verify);
+        self.ld_hl(start);
+        self.label(&verify_loop);
+        self.ld_a_l();
+        self.xor_h();
+        self.cp_hl_ind();
+        self.call_nz("memtest_fail");
+        self.memtest_step(end, &verify_loop, &done);
+
+        self.label(&done);
+        self.ld_a_d();
+        self.rrca();                 // Carry = failure flag
+        self.ret();
+
+        // memtest_fail: report HL, preserving HL and E
+        self.label("memtest_fail");
+        self.ld_d(1);
+        self.call("print_hex_word");
+        self.ld_a(b' ');
+        self.jp("conout");
+    }
+
+    /// Go to `done` if HL is `end`, otherwise advance HL and go to `next`
+    fn memtest_step(&mut self, end: u16, next: &str, done: &str) {
+        let more = self.unique_label("memtest_more");
+        self.ld_a_l();
+        self.cp(end as u8);
+        self.jp_nz(&more);
+        self.ld_a_h();
+        self.cp((end >> 8) as u8);
+        self.jp_z(done);
+        self.label(&more);
+        self.inc_hl();
+        self.jp(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memtest_emits() {
+        let mut cg = CodeGen::new();
+        cg.emit_memtest(0x2000, 0x2FFF);
+        assert_eq!(&cg.rom()[..9], &[
+            0x16, 0x00,        // LD D, 0
+            0x21, 0x00, 0x20,  // LD HL, 0x2000
+            0x1E, 0x01,        // LD E, 1
+            0x73,              // LD (HL), E
+            0x7E,              // LD A, (HL)
+        ]);
+        assert!(cg.has_label("memtest_fail"));
+    }
+
+    #[test]
+    #[should_panic(expected = "is empty")]
+    fn test_memtest_empty_range() {
+        CodeGen::new().emit_memtest(0x3000, 0x2000);
+    }
+}
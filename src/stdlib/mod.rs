@@ -5,3 +5,130 @@
 pub mod io;
 pub mod terminal;
 pub mod math;
+pub mod keyboard;
+pub mod memory;
+pub mod string;
+pub mod bcd;
+pub mod crc;
+pub mod fixed;
+pub mod int32;
+pub mod delay;
+pub mod timer;
+pub mod game;
+pub mod ctc;
+pub mod spi;
+pub mod i2c;
+pub mod vdp;
+pub mod hexload;
+pub mod cpm;
+pub mod api;
+pub mod buildinfo;
+pub mod memtest;
+pub mod datetime;
+pub mod sensor;
+pub mod tone;
+pub mod psg;
+pub mod eventlog;
+pub mod forms;
+pub mod compress;
+#[cfg(feature = "z180")]
+pub mod z180;
+#[cfg(feature = "r800")]
+pub mod r800;
+#[cfg(feature = "z80n")]
+pub mod z80n;
+#[cfg(feature = "undocumented")]
+pub mod undocumented;
+
+/// Individually emittable stdlib routines as (label, emitting method)
+///
+/// Used by coverage reports to suggest a trimmed replacement for
+/// `include_stdlib`.
+pub const ROUTINES: &[(&str, &str)] = &[
+    ("getchar", "emit_getchar"),
+    ("char_available", "emit_char_available"),
+    ("getchar_nowait", "emit_getchar_nowait"),
+    ("putchar", "emit_putchar"),
+    ("conout", "emit_conout"),
+    ("newline", "emit_newline"),
+    ("print_string", "emit_print_string"),
+    ("print_pstring", "emit_print_pstring"),
+    ("clear_screen", "emit_clear_screen"),
+    ("cursor_home", "emit_cursor_home"),
+    ("cursor_pos", "emit_cursor_pos"),
+    ("clear_to_eol", "emit_clear_to_eol"),
+    ("clear_to_eos", "emit_clear_to_eos"),
+    ("set_scroll_region", "emit_set_scroll_region"),
+    ("scroll_up", "emit_scroll_up"),
+    ("scroll_down", "emit_scroll_down"),
+    ("cursor_hide", "emit_cursor_hide"),
+    ("cursor_show", "emit_cursor_show"),
+    ("cursor_up", "emit_cursor_up"),
+    ("cursor_down", "emit_cursor_down"),
+    ("cursor_right", "emit_cursor_right"),
+    ("cursor_left", "emit_cursor_left"),
+    ("cursor_up_n", "emit_cursor_up_n"),
+    ("cursor_down_n", "emit_cursor_down_n"),
+    ("cursor_right_n", "emit_cursor_right_n"),
+    ("cursor_left_n", "emit_cursor_left_n"),
+    ("reset_attrs", "emit_reset_attrs"),
+    ("reverse_video", "emit_reverse_video"),
+    ("text_bold", "emit_text_bold"),
+    ("text_dim", "emit_text_dim"),
+    ("text_underline", "emit_text_underline"),
+    ("text_blink", "emit_text_blink"),
+    ("set_fg_color", "emit_set_fg_color"),
+    ("set_bg_color", "emit_set_bg_color"),
+    ("getkey", "emit_getkey"),
+    ("print_byte_dec", "emit_print_byte_dec"),
+    ("print_word_dec", "emit_print_word_dec"),
+    ("print_word_sdec", "emit_print_word_sdec"),
+    ("print_hex_byte", "emit_print_hex_byte"),
+    ("print_hex_word", "emit_print_hex_word"),
+    ("div16", "emit_div16"),
+    ("sdiv16", "emit_sdiv16"),
+    ("smod16", "emit_smod16"),
+    ("mul8", "emit_mul8"),
+    ("mul16", "emit_mul16"),
+    ("negate_hl", "emit_negate_hl"),
+    ("parse_dec", "emit_parse_dec"),
+    ("parse_hex", "emit_parse_hex"),
+    ("memcpy", "emit_memcpy"),
+    ("memset", "emit_memset"),
+    ("dir_key_cmp", "emit_dir_key_cmp"),
+    ("strlen", "emit_strlen"),
+    ("strcmp", "emit_strcmp"),
+    ("strcpy", "emit_strcpy"),
+    ("strcat", "emit_strcat"),
+    ("bcd_add", "emit_bcd_add"),
+    ("bcd_sub", "emit_bcd_sub"),
+    ("bcd_to_bin", "emit_bcd_to_bin"),
+    ("bin_to_bcd", "emit_bin_to_bcd"),
+    ("print_bcd", "emit_print_bcd"),
+    ("crc8", "emit_crc8"),
+    ("crc16", "emit_crc16"),
+    ("fx_add", "emit_fx_add"),
+    ("fx_sub", "emit_fx_sub"),
+    ("fx_mul", "emit_fx_mul"),
+    ("fx_div", "emit_fx_div"),
+    ("fx_print", "emit_fx_print"),
+    ("add32", "emit_add32"),
+    ("sub32", "emit_sub32"),
+    ("cmp32", "emit_cmp32"),
+    ("shl32", "emit_shl32"),
+    ("shr32", "emit_shr32"),
+    ("div32_10", "emit_div32_10"),
+    ("print32", "emit_print32"),
+    ("delay_ms", "emit_delay_ms"),
+    ("hex_load", "emit_hex_load"),
+    ("is_leap_year", "emit_is_leap_year"),
+    ("days_in_month", "emit_days_in_month"),
+    ("day_of_week", "emit_day_of_week"),
+    ("secs_to_hms", "emit_secs_to_hms"),
+    ("hms_to_secs", "emit_hms_to_secs"),
+    ("q88_to_str", "emit_q88_to_str"),
+    ("form_run", "emit_form_run"),
+    ("sd_init", "emit_sd"),
+    ("rtc_read", "emit_ds1307"),
+    ("decompress", "emit_decompress"),
+];
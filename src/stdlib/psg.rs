@@ -0,0 +1,244 @@
+//! AY-3-8910 / YM2149 programmable sound generator
+//!
+//! The chip is driven through two ports: a register is selected by writing
+//! its number to the address port, then written through the data port (or
+//! read back through the read port). It has three square-wave tone
+//! channels, one noise generator mixed into any of them, and a shared
+//! volume envelope.
+//!
+//! Tone periods depend on the chip's own clock, which is often not the CPU
+//! clock, so `PsgConfig` carries it. `play_note` looks the note up in a
+//! table of octave-0 periods computed here at build time and halves it
+//! once per octave.
+
+use crate::CodeGen;
+
+/// Registers
+const PSG_NOISE: u8 = 6;
+const PSG_MIXER: u8 = 7;
+const PSG_VOLUME: u8 = 8;
+const PSG_ENV_PERIOD: u8 = 11;
+const PSG_ENV_SHAPE: u8 = 13;
+
+/// Largest 12-bit tone period
+const MAX_PERIOD: u16 = 0x0FFF;
+
+/// AY-3-8910 ports and clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PsgConfig {
+    /// Register select port (BC1 + BDIR)
+    pub addr_port: u8,
+    /// Register write port (BDIR)
+    pub data_port: u8,
+    /// Register read port (BC1)
+    pub read_port: u8,
+    /// Chip clock in Hz
+    pub clock_hz: u32,
+    /// Bits 6-7 of the mixer register (I/O port A/B direction, 1 = output)
+    pub io_dir: u8,
+}
+
+impl Default for PsgConfig {
+    fn default() -> Self {
+        Self {
+            addr_port: 0xA0,
+            data_port: 0xA1,
+            read_port: 0xA2,
+            clock_hz: 1_789_773,
+            io_dir: 0x00,
+        }
+    }
+}
+
+/// Tone period for `freq_hz` at a chip clock of `clock_hz`
+///
+/// Not clamped: periods above 0x0FFF are out of the chip's range.
+pub fn psg_period(clock_hz: u32, freq_hz: f64) -> u32 {
+    assert!(freq_hz > 0.0, "frequency must be positive");
+    (clock_hz as f64 / (16.0 * freq_hz)).round() as u32
+}
+
+impl PsgConfig {
+    /// Octave-0 periods of C .. B (C0 = 16.35 Hz, A4 = 440 Hz)
+    pub fn note_table(&self) -> [u16; 12] {
+        let mut table = [0u16; 12];
+        for (note, period) in table.iter_mut().enumerate() {
+            let freq = 440.0 * 2f64.powf((note as f64 - 9.0) / 12.0 - 4.0);
+            let p = psg_period(self.clock_hz, freq);
+            assert!(p <= 0xFFFF, "PSG clock {} Hz is too high for the note table", self.clock_hz);
+            *period = p as u16;
+        }
+        table
+    }
+
+    /// Period `play_note` writes for `note` (0 = C .. 11 = B) in `octave`
+    pub fn note_period(&self, note: u8, octave: u8) -> u16 {
+        assert!(note < 12, "note must be 0-11");
+        (self.note_table()[note as usize] >> octave.min(15)).min(MAX_PERIOD)
+    }
+}
+
+impl CodeGen {
+    /// Emit the AY-3-8910 routines
+    ///
+    /// - `psg_write`: write E to register A. Clobbers A.
+    /// - `psg_read`: read register A into A.
+    /// - `psg_init`: zero all registers, all channels off. Clobbers A, DE.
+    /// - `psg_mixer`: A = enabled sources (bits 0-2 tone A-C, bits 3-5
+    ///   noise A-C, 1 = on). Clobbers A, E.
+    /// - `psg_tone`: set channel C (0-2) to tone period HL. Clobbers A, E.
+    /// - `psg_volume`: set channel C to volume A (0-15, 16 = envelope).
+    ///   Clobbers A, E.
+    /// - `psg_noise`: set the noise period to A (0-31). Clobbers A, E.
+    /// - `psg_envelope`: set the envelope period to HL and the shape to A
+    ///   (0-15, restarts the envelope). Clobbers A, E.
+    /// - `psg_silence`: set all three volumes to 0. Clobbers A, E.
+    /// - `play_note`: set channel C to note A (0 = C .. 11 = B) in octave
+    ///   B. Low notes out of range play at the longest period. The mixer
+    ///   and volume are left alone. Clobbers A, B, DE, HL.
+    ///
+    /// Labels created: `psg_write`, `psg_read`, `psg_init`, `psg_mixer`,
+    /// `psg_tone`, `psg_volume`, `psg_noise`, `psg_envelope`,
+    /// `psg_silence`, `play_note`, `psg_notes`
+    pub fn emit_psg(&mut self, config: &PsgConfig) {
+        assert!(config.io_dir & 0x3F == 0, "io_dir uses bits 6-7 only");
+        let init_loop = self.unique_label("psg_init_loop");
+        let shift = self.unique_label("psg_note_shift");
+        let shifted = self.unique_label("psg_note_shifted");
+        let in_range = self.unique_label("psg_note_ok");
+
+        self.label("psg_write");
+        self.out_a(config.addr_port);
+        self.ld_a_e();
+        self.out_a(config.data_port);
+        self.ret();
+
+        self.label("psg_read");
+        self.out_a(config.addr_port);
+        self.in_a(config.read_port);
+        self.ret();
+
+        self.label("psg_init");
+        self.ld_e(0);
+        self.ld_d(PSG_ENV_SHAPE);
+        self.label(&init_loop);
+        self.ld_a_d();
+        self.call("psg_write");
+        self.dec_d();
+        self.jp_p(&init_loop);
+        self.xor_a();               // Everything off
+
+        self.label("psg_mixer");
+        self.and_a(0x3F);
+        self.xor_n(0x3F);           // The chip's enables are active low
+        self.or_a(config.io_dir);
+        self.ld_e_a();
+        self.ld_a(PSG_MIXER);
+        self.jp("psg_write");
+
+        self.label("psg_tone");
+        self.ld_a_c();
+        self.add_a_a();
+        self.ld_e_l();
+        self.call("psg_write");     // Fine tune
+        self.ld_a_c();
+        self.add_a_a();
+        self.inc_a();
+        self.ld_e_h();
+        self.jp("psg_write");       // Coarse tune
+
+        self.label("psg_volume");
+        self.ld_e_a();
+        self.ld_a_c();
+        self.add_a(PSG_VOLUME);
+        self.jp("psg_write");
+
+        self.label("psg_noise");
+        self.ld_e_a();
+        self.ld_a(PSG_NOISE);
+        self.jp("psg_write");
+
+        self.label("psg_envelope");
+        self.push_af();
+        self.ld_e_l();
+        self.ld_a(PSG_ENV_PERIOD);
+        self.call("psg_write");
+        self.ld_e_h();
+        self.ld_a(PSG_ENV_PERIOD + 1);
+        self.call("psg_write");
+        self.pop_af();
+        self.ld_e_a();
+        self.ld_a(PSG_ENV_SHAPE);
+        self.jp("psg_write");
+
+        self.label("psg_silence");
+        self.ld_e(0);
+        self.ld_a(PSG_VOLUME);
+        self.call("psg_write");
+        self.ld_a(PSG_VOLUME + 1);
+        self.call("psg_write");
+        self.ld_a(PSG_VOLUME + 2);
+        self.jp("psg_write");
+
+        self.label("play_note");
+        self.add_a_a();
+        self.ld_e_a();
+        self.ld_d(0);
+        self.ld_hl_label("psg_notes");
+        self.add_hl_de();
+        self.ld_e_hl_ind();
+        self.inc_hl();
+        self.ld_d_hl_ind();
+        self.ex_de_hl();
+        self.ld_a_b();
+        self.or_a_a();
+        self.jp_z(&shifted);
+        self.label(&shift);
+        self.srl_h();
+        self.rr_l();
+        self.djnz(&shift);
+        self.label(&shifted);
+        self.ld_a_h();
+        self.cp((MAX_PERIOD >> 8) as u8 + 1);
+        self.jp_c(&in_range);
+        self.ld_hl(MAX_PERIOD);
+        self.label(&in_range);
+        self.jp("psg_tone");
+
+        self.label("psg_notes");
+        for period in config.note_table() {
+            self.emit_word(period);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_table() {
+        let config = PsgConfig::default();
+        let table = config.note_table();
+        assert_eq!(table[0], 6841);                 // C0
+        assert_eq!(config.note_period(9, 4), 254);  // A4 = 440 Hz
+        assert_eq!(config.note_period(0, 0), 0x0FFF);
+        assert_eq!(psg_period(2_000_000, 1000.0), 125);
+    }
+
+    #[test]
+    fn test_psg_write() {
+        let mut cg = CodeGen::new();
+        cg.emit_psg(&PsgConfig::default());
+        assert_eq!(&cg.rom()[..10], &[
+            0xD3, 0xA0,        // psg_write: OUT (0xA0), A
+            0x7B,              // LD A, E
+            0xD3, 0xA1,        // OUT (0xA1), A
+            0xC9,              // RET
+            0xD3, 0xA0,        // psg_read: OUT (0xA0), A
+            0xDB, 0xA2,        // IN A, (0xA2)
+        ]);
+        let notes = cg.get_label("psg_notes").unwrap() as usize;
+        assert_eq!(&cg.rom()[notes..notes + 2], &[0xB9, 0x1A]);
+    }
+}
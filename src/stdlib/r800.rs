@@ -0,0 +1,54 @@
+//! R800 multiply instructions (feature `r800`)
+//!
+//! The ASCII R800 (MSX turbo R) adds hardware multiplies to the Z80 set.
+//! The helpers panic unless `RomConfig::cpu` is `CpuProfile::R800`.
+
+use crate::instructions::instructions;
+use crate::CpuProfile;
+
+instructions! {
+    static R800_OPCODES for CpuProfile::R800;
+
+    // ========== R800 Extensions ==========
+
+    /// MULUB A, B (A * B -> HL)
+    mulub_a_b() => "MULUB A,B", [0xED, 0xC1], 14;
+
+    /// MULUB A, C (A * C -> HL)
+    mulub_a_c() => "MULUB A,C", [0xED, 0xC9], 14;
+
+    /// MULUB A, D (A * D -> HL)
+    mulub_a_d() => "MULUB A,D", [0xED, 0xD1], 14;
+
+    /// MULUB A, E (A * E -> HL)
+    mulub_a_e() => "MULUB A,E", [0xED, 0xD9], 14;
+
+    /// MULUW HL, BC (HL * BC -> DE:HL)
+    muluw_hl_bc() => "MULUW HL,BC", [0xED, 0xC3], 36;
+
+    /// MULUW HL, SP (HL * SP -> DE:HL)
+    muluw_hl_sp() => "MULUW HL,SP", [0xED, 0xF3], 36;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CodeGen, CpuProfile, RomConfig};
+
+    #[test]
+    fn test_r800_multiply() {
+        let mut cg = CodeGen::with_config(RomConfig {
+            cpu: CpuProfile::R800,
+            ..Default::default()
+        });
+        cg.mulub_a_e();
+        cg.muluw_hl_bc();
+        assert_eq!(cg.rom(), &[0xED, 0xD9, 0xED, 0xC3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "requires the R800 CPU profile")]
+    fn test_rejected_on_z80() {
+        let mut cg = CodeGen::new();
+        cg.mulub_a_b();
+    }
+}
@@ -0,0 +1,245 @@
+//! Sensor reading formatting
+//!
+//! Converts raw temperature register values into printable fixed-point
+//! strings such as `-12.125`, with register maps for common I2C
+//! temperature sensors.
+//!
+//! Read the two register bytes over I2C (`stdlib::i2c`, or your own bus
+//! routine) MSB first into H and L, then call the sensor's `<name>_to_str`
+//! routine and print the result with `print_string`.
+
+use crate::CodeGen;
+
+/// Layout of a raw temperature register value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TempFormat {
+    /// Two's complement, left-aligned: MSB = whole degrees, LSB = fraction
+    LeftAligned,
+    /// Two's complement in 1/16 degree steps, right-aligned (upper flag
+    /// bits are ignored)
+    Sixteenths,
+}
+
+/// Register map of an I2C temperature sensor
+#[derive(Clone, Copy, Debug)]
+pub struct TempSensor {
+    /// Name used for the emitted `<name>_to_str` routine
+    pub name: &'static str,
+    /// Default 7-bit I2C address
+    pub i2c_addr: u8,
+    /// Temperature register (two bytes, MSB first)
+    pub temp_reg: u8,
+    /// Configuration register
+    pub config_reg: u8,
+    /// Valid fraction bits; also the number of decimals printed
+    pub frac_bits: u8,
+    /// Register layout
+    pub format: TempFormat,
+}
+
+/// LM75: 9-bit, 0.5 degree steps
+pub const LM75: TempSensor = TempSensor {
+    name: "lm75",
+    i2c_addr: 0x48,
+    temp_reg: 0x00,
+    config_reg: 0x01,
+    frac_bits: 1,
+    format: TempFormat::LeftAligned,
+};
+
+/// LM75B: 11-bit, 0.125 degree steps
+pub const LM75B: TempSensor = TempSensor {
+    name: "lm75b",
+    i2c_addr: 0x48,
+    temp_reg: 0x00,
+    config_reg: 0x01,
+    frac_bits: 3,
+    format: TempFormat::LeftAligned,
+};
+
+/// TMP102: 12-bit, 0.0625 degree steps (normal mode)
+pub const TMP102: TempSensor = TempSensor {
+    name: "tmp102",
+    i2c_addr: 0x48,
+    temp_reg: 0x00,
+    config_reg: 0x01,
+    frac_bits: 4,
+    format: TempFormat::LeftAligned,
+};
+
+/// MCP9808: 13-bit, 0.0625 degree steps, alert flags in the top bits
+pub const MCP9808: TempSensor = TempSensor {
+    name: "mcp9808",
+    i2c_addr: 0x18,
+    temp_reg: 0x05,
+    config_reg: 0x01,
+    frac_bits: 4,
+    format: TempFormat::Sixteenths,
+};
+
+/// Size of `temp_buf`: sign, three digits, point, four decimals, terminator
+const TEMP_BUF_SIZE: u16 = 10;
+
+impl CodeGen {
+    /// Emit q88_to_str routine - format a signed 8.8 fixed-point value
+    /// Input: HL = value (H = whole part), DE = buffer, B = decimals (0-4)
+    /// Output: zero-terminated text at the buffer, HL = buffer
+    ///
+    /// Decimals are truncated, which is exact when B covers the
+    /// sensor's fraction bits.
+    ///
+    /// Labels created: `q88_to_str`
+    /// Clobbers A, BC, DE
+    pub fn emit_q88_to_str(&mut self) {
+        self.label("q88_to_str");
+        self.push_de();
+        self.emit(&[0xCB, 0x7C]);    // BIT 7, H
+        let whole = self.unique_label("q88_whole");
+        self.jp_z(&whole);
+        self.ld_a(b'-');
+        self.emit(&[0x12]);          // LD (DE), A
+        self.inc_de();
+        self.emit(&[0x7D]);          // LD A, L
+        self.cpl();
+        self.emit(&[0x6F]);          // LD L, A
+        self.emit(&[0x7C]);          // LD A, H
+        self.cpl();
+        self.emit(&[0x67]);          // LD H, A
+        self.inc_hl();
+
+        // Whole part 0-128 without leading zeros
+        self.label(&whole);
+        self.emit(&[0x7C]);          // LD A, H
+        self.cp(100);
+        let tens = self.unique_label("q88_tens");
+        let tens_loop = self.unique_label("q88_tens_loop");
+        let tens_done = self.unique_label("q88_tens_done");
+        let ones = self.unique_label("q88_ones");
+        self.jp_c(&tens);
+        self.sub_a(100);
+        self.ld_c_a();
+        self.ld_a(b'1');
+        self.emit(&[0x12]);          // LD (DE), A
+        self.inc_de();
+        self.ld_a_c();
+        self.ld_c(b'0');             // Tens digit is printed even if zero
+        self.jp(&tens_loop);
+        self.label(&tens);
+        self.cp(10);
+        self.jp_c(&ones);
+        self.ld_c(b'0');
+        self.label(&tens_loop);
+        self.cp(10);
+        self.jp_c(&tens_done);
+        self.sub_a(10);
+        self.inc_c();
+        self.jp(&tens_loop);
+        self.label(&tens_done);
+        self.emit(&[0xEB]);          // EX DE, HL
+        self.emit(&[0x71]);          // LD (HL), C
+        self.emit(&[0xEB]);          // EX DE, HL
+        self.inc_de();
+        self.label(&ones);
+        self.add_a(b'0');
+        self.emit(&[0x12]);          // LD (DE), A
+        self.inc_de();
+
+        // Decimals: multiply the fraction by 10, the high byte is the digit
+        let end = self.unique_label("q88_end");
+        self.ld_a_b();
+        self.or_a_a();
+        self.jp_z(&end);
+        self.ld_a(b'.');
+        self.emit(&[0x12]);          // LD (DE), A
+        self.inc_de();
+        self.emit(&[0x7D]);          // LD A, L
+        let frac = self.unique_label("q88_frac");
+        self.label(&frac);
+        self.push_bc();
+        self.emit(&[0x6F]);          // LD L, A
+        self.ld_h(0);
+        self.add_hl_hl();            // x2
+        self.emit(&[0x44]);          // LD B, H
+        self.emit(&[0x4D]);          // LD C, L
+        self.add_hl_hl();
+        self.add_hl_hl();            // x8
+        self.add_hl_bc();            // x10
+        self.pop_bc();
+        self.emit(&[0x7C]);          // LD A, H
+        self.add_a(b'0');
+        self.emit(&[0x12]);          // LD (DE), A
+        self.inc_de();
+        self.emit(&[0x7D]);          // LD A, L
+        self.djnz(&frac);
+
+        self.label(&end);
+        self.xor_a();
+        self.emit(&[0x12]);          // LD (DE), A
+        self.pop_hl();
+        self.ret();
+    }
+
+    /// Emit a `<name>_to_str` routine for a temperature sensor
+    /// Input: HL = raw temperature register (MSB in H)
+    /// Output: HL = `temp_buf` holding the temperature text
+    ///
+    /// Labels created: `<name>_to_str`
+    /// RAM used: `temp_buf` (shared by all sensors)
+    /// Requires: `q88_to_str`
+    /// Clobbers A, BC, DE
+    pub fn emit_temp_to_str(&mut self, sensor: &TempSensor) {
+        assert!(
+            (1..=4).contains(&sensor.frac_bits),
+            "frac_bits must be between 1 and 4"
+        );
+        if self.ram_var_size("temp_buf").is_none() {
+            self.ram_var("temp_buf", TEMP_BUF_SIZE);
+        }
+        let buf = self.get_label("temp_buf").unwrap();
+
+        self.label(&format!("{}_to_str", sensor.name));
+        if sensor.format == TempFormat::Sixteenths {
+            for _ in 0..4 {
+                self.add_hl_hl();    // 1/16 steps to 8.8, flag bits drop out
+            }
+        }
+        self.emit(&[0x7D]);          // LD A, L
+        self.and_a(!(0xFF >> sensor.frac_bits));
+        self.emit(&[0x6F]);          // LD L, A
+        self.ld_de(buf);
+        self.ld_b(sensor.frac_bits);
+        self.jp("q88_to_str");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_to_str() {
+        let mut cg = CodeGen::new();
+        cg.emit_temp_to_str(&MCP9808);
+        assert_eq!(cg.rom(), &[
+            0x29, 0x29, 0x29, 0x29,  // ADD HL, HL (x4)
+            0x7D,                    // LD A, L
+            0xE6, 0xF0,              // AND 0xF0
+            0x6F,                    // LD L, A
+            0x11, 0x00, 0x20,        // LD DE, temp_buf
+            0x06, 0x04,              // LD B, 4
+            0xC3, 0x00, 0x00,        // JP q88_to_str (unresolved)
+        ]);
+    }
+
+    #[test]
+    fn test_temp_buf_shared() {
+        let mut cg = CodeGen::new();
+        cg.emit_q88_to_str();
+        cg.emit_temp_to_str(&LM75);
+        cg.emit_temp_to_str(&LM75B);
+        cg.resolve_fixups();
+        assert!(cg.has_label("lm75_to_str"));
+        assert!(cg.has_label("lm75b_to_str"));
+        assert_eq!(cg.ram_pos(), 0x2000 + TEMP_BUF_SIZE);
+    }
+}
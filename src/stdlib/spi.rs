@@ -0,0 +1,575 @@
+//! Bit-banged SPI and SD card block access
+//!
+//! SPI runs in mode 0, MSB first, on four port bits: SCK, MOSI and CS on
+//! an output latch, MISO on an input port. Other bits of the output latch
+//! keep the value last written through the `spi_out` shadow byte, so the
+//! latch can be shared with other outputs that go through it too.
+//!
+//! The SD layer puts a card into SPI mode (CMD0, CMD8, ACMD41, CMD58) and
+//! reads and writes 512-byte blocks (CMD17, CMD24). SDSC cards are
+//! byte-addressed and SDHC/SDXC cards block-addressed; `sd_read_block` and
+//! `sd_write_block` take a block number either way.
+
+use crate::CodeGen;
+
+/// Bit-banged SPI pin assignment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpiConfig {
+    /// Output latch holding SCK, MOSI and CS
+    pub out_port: u8,
+    /// Input port holding MISO
+    pub in_port: u8,
+    /// Bit numbers (0-7)
+    pub sck_bit: u8,
+    pub mosi_bit: u8,
+    pub cs_bit: u8,
+    pub miso_bit: u8,
+}
+
+impl Default for SpiConfig {
+    fn default() -> Self {
+        Self {
+            out_port: 0x40,
+            in_port: 0x40,
+            sck_bit: 0,
+            mosi_bit: 1,
+            cs_bit: 2,
+            miso_bit: 7,
+        }
+    }
+}
+
+/// SD commands (with the start bits, 0x40 | index)
+const SD_CMD0: u8 = 0x40;
+const SD_CMD8: u8 = 0x48;
+const SD_CMD17: u8 = 0x51;
+const SD_CMD24: u8 = 0x58;
+const SD_CMD41: u8 = 0x69;
+const SD_CMD55: u8 = 0x77;
+const SD_CMD58: u8 = 0x7A;
+/// ACMD41 attempts before giving up (a few ms each)
+const SD_INIT_TRIES: u16 = 1000;
+
+impl CodeGen {
+    /// Emit the SPI routines
+    ///
+    /// - `spi_init`: CS high, SCK low, MOSI high. Clobbers A.
+    /// - `spi_select` / `spi_deselect`: drive CS low / high. Clobbers A.
+    /// - `spi_xfer`: send A, return the byte received in A. Preserves BC, DE.
+    ///
+    /// Labels created: `spi_init`, `spi_select`, `spi_deselect`, `spi_set`,
+    /// `spi_xfer`, `spi_xfer_bit`
+    /// RAM used: `spi_out`
+    pub fn emit_spi(&mut self, config: &SpiConfig) {
+        for bit in [config.sck_bit, config.mosi_bit, config.cs_bit, config.miso_bit] {
+            assert!(bit < 8, "SPI bit must be 0-7");
+        }
+        let sck = 1u8 << config.sck_bit;
+        let mosi = 1u8 << config.mosi_bit;
+        let cs = 1u8 << config.cs_bit;
+        let miso = 1u8 << config.miso_bit;
+        let shadow = self.ram_var("spi_out", 1);
+
+        self.label("spi_init");
+        self.ld_a(cs | mosi);
+        self.jp("spi_set");
+
+        self.label("spi_select");
+        self.ld_a_addr(shadow);
+        self.and_a(!cs);
+        self.jp("spi_set");
+
+        self.label("spi_deselect");
+        self.ld_a_addr(shadow);
+        self.or_a(cs);
+        self.label("spi_set");
+        self.ld_addr_a(shadow);
+        self.out_a(config.out_port);
+        self.ret();
+
+        self.label("spi_xfer");
+        self.push_bc();
+        self.push_de();
+        self.ld_c_a();              // C = out, shifted left; in bits enter at bit 0
+        self.ld_b(8);
+        self.label("spi_xfer_bit");
+        self.ld_a_addr(shadow);
+        self.and_a(!(sck | mosi));
+        self.bit_c(7);
+        self.emit(&[0x28, 0x02]);   // JR Z, +2
+        self.or_a(mosi);
+        self.out_a(config.out_port);
+        self.or_a(sck);             // Rising edge: both sides sample
+        self.out_a(config.out_port);
+        self.ld_d_a();
+        self.in_a(config.in_port);
+        self.and_a(miso);
+        self.add_a(0xFF);           // Carry = MISO
+        self.rl_c();
+        self.ld_a_d();
+        self.and_a(!sck);
+        self.out_a(config.out_port);
+        self.djnz("spi_xfer_bit");
+        self.ld_a_c();
+        self.pop_de();
+        self.pop_bc();
+        self.ret();
+    }
+
+    /// Emit the SD card routines
+    ///
+    /// - `sd_init`: reset the card into SPI mode; carry set on failure.
+    ///   Clobbers A, BC, DE, HL.
+    /// - `sd_read_block`: read block DE:HL (DE high) into 512 bytes at BC;
+    ///   carry set on failure, else HL = BC + 512. Clobbers A, BC, DE.
+    /// - `sd_write_block`: write 512 bytes at BC to block DE:HL and wait
+    ///   for the card to finish; carry set on failure, else HL = BC + 512.
+    ///   Clobbers A, BC, DE.
+    /// - `sd_cmd`: send command A with argument DE:HL and CRC B, return
+    ///   R1 in A (0xFF on timeout). Expects CS low. Clobbers B.
+    ///
+    /// Labels created: `sd_init`, `sd_cmd`, `sd_read_block`,
+    /// `sd_write_block`, `sd_fail`
+    /// RAM used: `sd_type` (non-zero for block-addressed cards)
+    /// Requires: `spi_xfer`, `spi_select`, `spi_deselect`
+    pub fn emit_sd(&mut self) {
+        let sd_type = self.ram_var("sd_type", 1);
+        let clocks = self.unique_label("sd_clocks");
+        let r7 = self.unique_label("sd_r7");
+        let acmd41_start = self.unique_label("sd_acmd41_start");
+        let acmd41 = self.unique_label("sd_acmd41");
+        let ready = self.unique_label("sd_ready");
+        let init_done = self.unique_label("sd_init_done");
+        let cmd_poll = self.unique_label("sd_cmd_poll");
+        let cmd_done = self.unique_label("sd_cmd_done");
+        let address = self.unique_label("sd_address");
+        let read_wait = self.unique_label("sd_read_wait");
+        let read_data = self.unique_label("sd_read_data");
+        let read_byte = self.unique_label("sd_read_byte");
+        let write_byte = self.unique_label("sd_write_byte");
+        let write_busy = self.unique_label("sd_write_busy");
+        let write_done = self.unique_label("sd_write_done");
+
+        self.label("sd_init");
+        self.call("spi_deselect");
+        self.ld_b(10);              // 80 clocks with CS high
+        self.label(&clocks);
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.djnz(&clocks);
+        self.call("spi_select");
+        self.ld_de(0);
+        self.ld_hl(0);
+        self.ld_a(SD_CMD0);
+        self.ld_b(0x95);
+        self.call("sd_cmd");
+        self.cp(0x01);              // Idle
+        self.jp_nz("sd_fail");
+        self.ld_hl(0x01AA);         // CMD8: 2.7-3.6 V, check pattern
+        self.ld_a(SD_CMD8);
+        self.ld_b(0x87);
+        self.call("sd_cmd");
+        self.ld_hl(0);
+        self.ld_d(0);               // ACMD41 HCS bit in D
+        self.and_a(0x04);           // Illegal command: SD v1
+        self.jp_nz(&acmd41_start);
+        self.ld_b(4);
+        self.label(&r7);
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.djnz(&r7);
+        self.cp(0xAA);
+        self.jp_nz("sd_fail");
+        self.ld_d(0x40);
+
+        // ACMD41 until the card leaves idle; counter on the stack
+        self.label(&acmd41_start);
+        self.ld_hl(SD_INIT_TRIES);
+        self.push_hl();
+        self.ld_hl(0);
+        self.label(&acmd41);
+        self.push_de();
+        self.ld_d(0);
+        self.ld_a(SD_CMD55);
+        self.ld_b(0x01);
+        self.call("sd_cmd");
+        self.pop_de();
+        self.ld_a(SD_CMD41);
+        self.ld_b(0x01);
+        self.call("sd_cmd");
+        self.or_a_a();
+        self.jp_z(&ready);
+        self.ex_sp_hl();
+        self.dec_hl();
+        self.ld_a_h();
+        self.or_l();
+        self.ex_sp_hl();
+        self.jp_nz(&acmd41);
+        self.pop_hl();
+        self.jp("sd_fail");
+
+        // SD v2: CMD58 reads the OCR; CCS (bit 30) means block addressing
+        self.label(&ready);
+        self.pop_bc();              // Drop the counter; HL stays 0
+        self.ld_a_d();
+        self.or_a_a();
+        self.jp_z(&init_done);      // v1: A = 0, byte addressing
+        self.ld_de(0);
+        self.ld_a(SD_CMD58);
+        self.ld_b(0x01);
+        self.call("sd_cmd");
+        self.or_a_a();
+        self.jp_nz("sd_fail");
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.and_a(0x40);
+        self.ld_c_a();
+        for _ in 0..3 {
+            self.ld_a(0xFF);
+            self.call("spi_xfer");
+        }
+        self.ld_a_c();
+        self.label(&init_done);
+        self.ld_addr_a(sd_type);
+        self.call("spi_deselect");
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.or_a_a();
+        self.ret();
+
+        self.label("sd_fail");
+        self.call("spi_deselect");
+        self.scf();
+        self.ret();
+
+        self.label("sd_cmd");
+        self.push_af();
+        self.ld_a(0xFF);            // Let the card finish
+        self.call("spi_xfer");
+        self.pop_af();
+        self.call("spi_xfer");
+        self.ld_a_d();              // Argument, MSB first
+        self.call("spi_xfer");
+        self.ld_a_e();
+        self.call("spi_xfer");
+        self.ld_a_h();
+        self.call("spi_xfer");
+        self.ld_a_l();
+        self.call("spi_xfer");
+        self.ld_a_b();
+        self.call("spi_xfer");
+        self.ld_b(10);
+        self.label(&cmd_poll);
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.bit_a(7);
+        self.jp_z(&cmd_done);
+        self.djnz(&cmd_poll);
+        self.label(&cmd_done);
+        self.ret();
+
+        // Block number in DE:HL to the card's address units
+        self.label(&address);
+        self.ld_a_addr(sd_type);
+        self.or_a_a();
+        self.ret_nz();
+        self.ld_d_e();              // Byte address = block * 512
+        self.ld_e_h();
+        self.ld_h_l();
+        self.ld_l(0);
+        self.add_hl_hl();
+        self.rl_e();
+        self.rl_d();
+        self.ret();
+
+        self.label("sd_read_block");
+        self.push_bc();
+        self.call(&address);
+        self.call("spi_select");
+        self.ld_a(SD_CMD17);
+        self.ld_b(0x01);
+        self.call("sd_cmd");
+        self.pop_hl();              // Destination
+        self.or_a_a();
+        self.jp_nz("sd_fail");
+        self.ld_bc(0);              // Up to 65536 polls for the data token
+        self.label(&read_wait);
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.cp(0xFE);
+        self.jp_z(&read_data);
+        self.dec_bc();
+        self.ld_a_b();
+        self.or_c();
+        self.jp_nz(&read_wait);
+        self.jp("sd_fail");
+        self.label(&read_data);
+        self.ld_bc(0x0002);         // B = 0 (256), C = 2 passes
+        self.label(&read_byte);
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.ld_hl_ind_a();
+        self.inc_hl();
+        self.djnz(&read_byte);
+        self.dec_c();
+        self.jp_nz(&read_byte);
+        self.ld_a(0xFF);            // Skip the CRC
+        self.call("spi_xfer");
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.call("spi_deselect");
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.or_a_a();
+        self.ret();
+
+        self.label("sd_write_block");
+        self.push_bc();
+        self.call(&address);
+        self.call("spi_select");
+        self.ld_a(SD_CMD24);
+        self.ld_b(0x01);
+        self.call("sd_cmd");
+        self.pop_hl();              // Source
+        self.or_a_a();
+        self.jp_nz("sd_fail");
+        self.ld_a(0xFF);            // Gap, then the data token
+        self.call("spi_xfer");
+        self.ld_a(0xFE);
+        self.call("spi_xfer");
+        self.ld_bc(0x0002);         // B = 0 (256), C = 2 passes
+        self.label(&write_byte);
+        self.ld_a_hl_ind();
+        self.call("spi_xfer");
+        self.inc_hl();
+        self.djnz(&write_byte);
+        self.dec_c();
+        self.jp_nz(&write_byte);
+        self.ld_a(0xFF);            // Dummy CRC
+        self.call("spi_xfer");
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.ld_a(0xFF);            // Data response: xxx00101 = accepted
+        self.call("spi_xfer");
+        self.and_a(0x1F);
+        self.cp(0x05);
+        self.jp_nz("sd_fail");
+        self.ld_bc(0);              // Up to 65536 polls while MISO is held low
+        self.label(&write_busy);
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.or_a_a();
+        self.jp_nz(&write_done);
+        self.dec_bc();
+        self.ld_a_b();
+        self.or_c();
+        self.jp_nz(&write_busy);
+        self.jp("sd_fail");
+        self.label(&write_done);
+        self.call("spi_deselect");
+        self.ld_a(0xFF);
+        self.call("spi_xfer");
+        self.or_a_a();
+        self.ret();
+    }
+}
+
+/// SDHC card on the default `SpiConfig` pins, for emulator tests
+#[cfg(all(test, feature = "emulator"))]
+pub(crate) mod test_card {
+    use crate::emulator::Device;
+    use std::collections::VecDeque;
+
+    /// What the card expects next on MOSI
+    enum Rx {
+        Command,
+        /// CMD24 accepted: waiting for the data token for this address
+        Token(usize),
+        Data(usize, Vec<u8>),
+    }
+
+    pub(crate) struct SdCard {
+        pub(crate) disk: Vec<u8>,
+        rx: Rx,
+        cmd: Vec<u8>,
+        resp: VecDeque<u8>,
+        /// Last latch value, for clock edges
+        out: u8,
+        shift_in: u8,
+        bits: u8,
+        miso: u8,
+    }
+
+    impl SdCard {
+        pub(crate) fn new(blocks: usize) -> Self {
+            Self {
+                disk: vec![0; blocks * 512],
+                rx: Rx::Command,
+                cmd: Vec::new(),
+                resp: VecDeque::new(),
+                out: 0x04,
+                shift_in: 0,
+                bits: 0,
+                miso: 0xFF,
+            }
+        }
+
+        /// Take a byte from the host; returns the byte to send back on the
+        /// next transfer
+        fn byte(&mut self, b: u8) -> u8 {
+            let reply = self.resp.pop_front().unwrap_or(0xFF);
+            match &mut self.rx {
+                Rx::Token(addr) => {
+                    if b == 0xFE {
+                        self.rx = Rx::Data(*addr, Vec::new());
+                    }
+                }
+                Rx::Data(addr, data) => {
+                    data.push(b);
+                    if data.len() == 512 + 2 {
+                        let addr = *addr;
+                        self.disk[addr..addr + 512].copy_from_slice(&data[..512]);
+                        self.rx = Rx::Command;
+                        self.resp.extend([0x00, 0x00]);  // Busy
+                        return 0x05;                      // Data accepted
+                    }
+                }
+                Rx::Command => {
+                    if self.cmd.is_empty() && b & 0xC0 != 0x40 {
+                        return reply;
+                    }
+                    self.cmd.push(b);
+                    if self.cmd.len() == 6 {
+                        let arg = u32::from_be_bytes([self.cmd[1], self.cmd[2], self.cmd[3], self.cmd[4]]);
+                        let addr = arg as usize * 512;
+                        self.resp.push_back(0xFF);
+                        match self.cmd[0] & 0x3F {
+                            0 => self.resp.push_back(0x01),
+                            8 => self.resp.extend([0x01, 0x00, 0x00, 0x01, 0xAA]),
+                            55 | 41 => self.resp.push_back(0x00),
+                            58 => self.resp.extend([0x00, 0xC0, 0xFF, 0x80, 0x00]),
+                            17 => {
+                                self.resp.extend([0x00, 0xFF, 0xFE]);
+                                self.resp.extend(self.disk[addr..addr + 512].iter());
+                                self.resp.extend([0xFF, 0xFF]);
+                            }
+                            24 => {
+                                self.resp.push_back(0x00);
+                                self.rx = Rx::Token(addr);
+                            }
+                            _ => self.resp.push_back(0x04),
+                        }
+                        self.cmd.clear();
+                    }
+                }
+            }
+            reply
+        }
+    }
+
+    impl Device for SdCard {
+        fn input(&mut self, port: u8) -> Option<u8> {
+            (port == 0x40).then_some(self.miso & 0x80)
+        }
+
+        fn output(&mut self, port: u8, value: u8) -> bool {
+            if port != 0x40 {
+                return false;
+            }
+            let selected = value & 0x04 == 0;
+            let (rising, falling) = (value & !self.out & 1 != 0, self.out & !value & 1 != 0);
+            if !selected {
+                self.bits = 0;
+            } else if rising {
+                self.shift_in = self.shift_in << 1 | (value >> 1 & 1);
+                self.bits += 1;
+            } else if falling {
+                self.miso <<= 1;
+                if self.bits == 8 {
+                    self.bits = 0;
+                    let b = self.shift_in;
+                    self.miso = self.byte(b);
+                }
+            }
+            self.out = value;
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spi_xfer() {
+        let mut cg = CodeGen::new();
+        cg.emit_spi(&SpiConfig::default());
+        let start = cg.get_label("spi_xfer").unwrap() as usize;
+        assert_eq!(&cg.rom()[start..start + 38], &[
+            0xC5,              // PUSH BC
+            0xD5,              // PUSH DE
+            0x4F,              // LD C, A
+            0x06, 0x08,        // LD B, 8
+            0x3A, 0x00, 0x20,  // spi_xfer_bit: LD A, (spi_out)
+            0xE6, 0xFC,        // AND ~(SCK|MOSI)
+            0xCB, 0x79,        // BIT 7, C
+            0x28, 0x02,        // JR Z, +2
+            0xF6, 0x02,        // OR MOSI
+            0xD3, 0x40,        // OUT (0x40), A
+            0xF6, 0x01,        // OR SCK
+            0xD3, 0x40,        // OUT (0x40), A
+            0x57,              // LD D, A
+            0xDB, 0x40,        // IN A, (0x40)
+            0xE6, 0x80,        // AND MISO
+            0xC6, 0xFF,        // ADD A, 0xFF
+            0xCB, 0x11,        // RL C
+            0x7A,              // LD A, D
+            0xE6, 0xFE,        // AND ~SCK
+            0xD3, 0x40,        // OUT (0x40), A
+            0x10, 0xDF,        // DJNZ spi_xfer_bit
+        ]);
+    }
+
+    #[test]
+    fn test_sd_labels() {
+        let mut cg = CodeGen::new();
+        cg.emit_sd();
+        for label in ["sd_init", "sd_cmd", "sd_read_block", "sd_write_block", "sd_fail"] {
+            assert!(cg.has_label(label), "{}", label);
+        }
+        assert_eq!(cg.ram_var_size("sd_type"), Some(1));
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn test_sd_write_read() {
+        use super::test_card::SdCard;
+        use crate::emulator::Emulator;
+
+        let mut cg = CodeGen::new();
+        cg.emit_spi(&SpiConfig::default());
+        cg.emit_sd();
+        cg.resolve_fixups();
+        let mut emu = Emulator::new(&cg);
+        emu.attach(Box::new(SdCard::new(4)));
+        assert!(emu.call_label("spi_init", 1_000));
+        assert!(emu.call_label("sd_init", 100_000));
+        assert_eq!(emu.cpu.f & 1, 0);
+
+        let data: Vec<u8> = (0..512u32).map(|i| (i * 7) as u8).collect();
+        emu.poke_bytes(0x3000, &data);
+        emu.cpu.set_bc(0x3000);
+        emu.cpu.set_de(0);
+        emu.cpu.set_hl(2);
+        assert!(emu.call_label("sd_write_block", 200_000));
+        assert_eq!((emu.cpu.f & 1, emu.cpu.hl()), (0, 0x3200));
+
+        emu.cpu.set_bc(0x3400);
+        emu.cpu.set_de(0);
+        emu.cpu.set_hl(2);
+        assert!(emu.call_label("sd_read_block", 200_000));
+        assert_eq!((emu.cpu.f & 1, emu.cpu.hl()), (0, 0x3600));
+        assert!((0..512).all(|i| emu.peek(0x3400 + i) == data[i as usize]));
+    }
+}
@@ -0,0 +1,184 @@
+//! Null-terminated string routines
+//!
+//! The classic C string functions on HL/DE pointers, for command
+//! interpreters and the like. Strings end with a 0 byte.
+
+use crate::CodeGen;
+
+impl CodeGen {
+    /// Emit strlen routine - length of the string at HL
+    ///
+    /// Returns BC = length, not counting the terminator. Preserves HL.
+    ///
+    /// Labels created: `strlen`
+    /// Clobbers A, BC
+    pub fn emit_strlen(&mut self) {
+        self.label("strlen");
+        self.push_hl();
+        self.xor_a();
+        self.ld_b_a();
+        self.ld_c_a();           // BC = 0: search up to 64K
+        self.cpir();             // BC = -(length + 1)
+        self.ld_a_b();
+        self.cpl();
+        self.ld_b_a();
+        self.ld_a_c();
+        self.cpl();
+        self.ld_c_a();           // BC = length
+        self.pop_hl();
+        self.ret();
+    }
+
+    /// Emit strcmp routine - compare the strings at HL and DE
+    ///
+    /// Returns Z if equal, otherwise NZ with C set if the HL string sorts
+    /// first (byte order; a prefix sorts before the longer string).
+    ///
+    /// Labels created: `strcmp`
+    /// Clobbers A, C, DE, HL
+    pub fn emit_strcmp(&mut self) {
+        self.label("strcmp");
+        self.ld_a_de_ind();
+        self.ld_c_a();
+        self.ld_a_hl_ind();
+        self.cp_c();
+        self.ret_nz();
+        self.or_a_a();
+        self.ret_z();                // Both ended
+        self.inc_hl();
+        self.inc_de();
+        self.jp("strcmp");
+    }
+
+    /// Emit strcpy routine - copy the string at HL to DE
+    ///
+    /// Copies up to and including the terminator; HL and DE are left
+    /// pointing at the source and destination terminators.
+    ///
+    /// Labels created: `strcpy`
+    /// Clobbers A, DE, HL
+    pub fn emit_strcpy(&mut self) {
+        self.label("strcpy");
+        self.ld_a_hl_ind();
+        self.ld_de_ind_a();
+        self.or_a_a();
+        self.ret_z();
+        self.inc_hl();
+        self.inc_de();
+        self.jp("strcpy");
+    }
+
+    /// Emit strcat routine - append the string at HL to the one at DE
+    ///
+    /// Leaves HL and DE pointing at the terminators, like `strcpy`.
+    ///
+    /// Labels created: `strcat`
+    /// Requires: `strcpy`
+    /// Clobbers A, DE, HL
+    pub fn emit_strcat(&mut self) {
+        self.label("strcat");
+        self.ld_a_de_ind();
+        self.or_a_a();
+        self.jp_z("strcpy");
+        self.inc_de();
+        self.jp("strcat");
+    }
+
+    /// Emit all string routines
+    pub fn emit_string_routines(&mut self) {
+        self.emit_strlen();
+        self.emit_strcmp();
+        self.emit_strcpy();
+        self.emit_strcat();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strcmp() {
+        let mut cg = CodeGen::new();
+        cg.emit_strcmp();
+        cg.resolve_fixups();
+        assert_eq!(cg.rom(), &[
+            0x1A,              // LD A, (DE)
+            0x4F,              // LD C, A
+            0x7E,              // LD A, (HL)
+            0xB9,              // CP C
+            0xC0,              // RET NZ
+            0xB7,              // OR A
+            0xC8,              // RET Z
+            0x23,              // INC HL
+            0x13,              // INC DE
+            0xC3, 0x00, 0x00,  // JP strcmp
+        ]);
+    }
+
+    #[test]
+    fn test_string_routines_emit() {
+        let mut cg = CodeGen::new();
+        cg.emit_string_routines();
+        cg.resolve_fixups();
+        for label in ["strlen", "strcmp", "strcpy", "strcat"] {
+            assert!(cg.has_label(label), "{}", label);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod emulator_tests {
+    use crate::emulator::Emulator;
+    use crate::CodeGen;
+
+    /// Emulator with `a` at 0x3000 and `b` at 0x3100, HL and DE pointing
+    /// at them
+    fn emulator(a: &[u8], b: &[u8]) -> Emulator {
+        let mut rom = CodeGen::new();
+        rom.emit_string_routines();
+        rom.resolve_fixups();
+        let mut emu = Emulator::new(&rom);
+        emu.poke_bytes(0x3000, a);
+        emu.poke(0x3000 + a.len() as u16, 0);
+        emu.poke_bytes(0x3100, b);
+        emu.poke(0x3100 + b.len() as u16, 0);
+        emu.cpu.set_hl(0x3000);
+        emu.cpu.set_de(0x3100);
+        emu
+    }
+
+    fn read(emu: &Emulator, addr: u16) -> Vec<u8> {
+        (addr..).map(|a| emu.peek(a)).take_while(|&b| b != 0).collect()
+    }
+
+    #[test]
+    fn test_run_strlen_strcmp() {
+        let mut emu = emulator(b"HELLO WORLD", b"");
+        assert!(emu.call_label("strlen", 10_000));
+        assert_eq!((emu.cpu.bc(), emu.cpu.hl()), (11, 0x3000));
+
+        for (a, b, zero, carry) in [
+            (&b"LIST"[..], &b"LIST"[..], true, false),
+            (b"LIS", b"LIST", false, true),
+            (b"LIST", b"LIS", false, false),
+            (b"ABC", b"ABD", false, true),
+        ] {
+            let mut emu = emulator(a, b);
+            assert!(emu.call_label("strcmp", 10_000));
+            assert_eq!((emu.cpu.f & 0x40 != 0, emu.cpu.f & 1 != 0), (zero, carry), "{:?} {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_run_strcpy_strcat() {
+        let mut emu = emulator(b"WORLD", b"XXXXXXXX");
+        assert!(emu.call_label("strcpy", 10_000));
+        assert_eq!(read(&emu, 0x3100), b"WORLD");
+        assert_eq!(emu.cpu.de(), 0x3105);
+
+        let mut emu = emulator(b"WORLD", b"HELLO ");
+        assert!(emu.call_label("strcat", 10_000));
+        assert_eq!(read(&emu, 0x3100), b"HELLO WORLD");
+    }
+}
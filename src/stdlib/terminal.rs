@@ -1,21 +1,207 @@
 //! VT100/VT220/ANSI Terminal escape sequences
 //!
 //! Provides routines for cursor control, screen clearing, etc.
+//!
+//! [`Ansi`] names each sequence once; it can be written inline
+//! (`emit_ansi`), stored as a pooled string (`pooled_ansi`), or wrapped in
+//! the fixed routines below.
 
 use crate::CodeGen;
+use std::fmt;
 
 /// ESC character
 const ESC: u8 = 0x1B;
 
+/// `getkey` code: cursor up (down, right and left follow)
+pub const KEY_UP: u8 = 0x80;
+/// `getkey` code: cursor down
+pub const KEY_DOWN: u8 = 0x81;
+/// `getkey` code: cursor right
+pub const KEY_RIGHT: u8 = 0x82;
+/// `getkey` code: cursor left
+pub const KEY_LEFT: u8 = 0x83;
+/// `getkey` code: Home
+pub const KEY_HOME: u8 = 0x84;
+/// `getkey` code: End
+pub const KEY_END: u8 = 0x85;
+/// `getkey` code: Insert
+pub const KEY_INSERT: u8 = 0x86;
+/// `getkey` code: Delete (the ESC [ 3 ~ key, not the 0x7F character)
+pub const KEY_DELETE: u8 = 0x87;
+/// `getkey` code: Page Up
+pub const KEY_PGUP: u8 = 0x88;
+/// `getkey` code: Page Down
+pub const KEY_PGDN: u8 = 0x89;
+/// `getkey` code: F1; F2 to F12 follow in order up to 0x9C
+pub const KEY_F1: u8 = 0x91;
+
+/// ANSI colour number
+///
+/// `Color::Red as u8` is the code `set_fg_color` and `set_bg_color` take
+/// in A.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+/// A terminal escape sequence with build-time parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ansi {
+    /// ESC[2J (cursor stays put)
+    ClearScreen,
+    /// ESC[K
+    ClearToEol,
+    /// ESC[J
+    ClearToEos,
+    /// ESC[H
+    CursorHome,
+    /// ESC[row;colH, 1-based
+    CursorTo(u8, u8),
+    /// ESC[nA
+    CursorUp(u8),
+    /// ESC[nB
+    CursorDown(u8),
+    /// ESC[nC
+    CursorRight(u8),
+    /// ESC[nD
+    CursorLeft(u8),
+    /// ESC[top;bottomr, 1-based; homes the cursor
+    ScrollRegion(u8, u8),
+    /// ESC[r: scroll the whole screen again
+    ResetScrollRegion,
+    /// ESC[nS: scroll the region up (text moves up)
+    ScrollUp(u8),
+    /// ESC[nT: scroll the region down
+    ScrollDown(u8),
+    /// ESC[?25l
+    CursorHide,
+    /// ESC[?25h
+    CursorShow,
+    /// ESC[0m
+    ResetAttrs,
+    /// ESC[1m
+    Bold,
+    /// ESC[2m
+    Dim,
+    /// ESC[4m
+    Underline,
+    /// ESC[5m
+    Blink,
+    /// ESC[7m
+    Reverse,
+    /// ESC[3xm
+    SetFg(Color),
+    /// ESC[4xm
+    SetBg(Color),
+}
+
+impl fmt::Display for Ansi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\x1B[")?;
+        // Counts of 1 are written without the number, as ESC[A etc.
+        let count = |f: &mut fmt::Formatter<'_>, n: u8, c: char| match n {
+            1 => write!(f, "{}", c),
+            _ => write!(f, "{}{}", n, c),
+        };
+        match *self {
+            Ansi::ClearScreen => write!(f, "2J"),
+            Ansi::ClearToEol => write!(f, "K"),
+            Ansi::ClearToEos => write!(f, "J"),
+            Ansi::CursorHome => write!(f, "H"),
+            Ansi::CursorTo(row, col) => write!(f, "{};{}H", row, col),
+            Ansi::CursorUp(n) => count(f, n, 'A'),
+            Ansi::CursorDown(n) => count(f, n, 'B'),
+            Ansi::CursorRight(n) => count(f, n, 'C'),
+            Ansi::CursorLeft(n) => count(f, n, 'D'),
+            Ansi::ScrollRegion(top, bottom) => write!(f, "{};{}r", top, bottom),
+            Ansi::ResetScrollRegion => write!(f, "r"),
+            Ansi::ScrollUp(n) => count(f, n, 'S'),
+            Ansi::ScrollDown(n) => count(f, n, 'T'),
+            Ansi::CursorHide => write!(f, "?25l"),
+            Ansi::CursorShow => write!(f, "?25h"),
+            Ansi::ResetAttrs => write!(f, "0m"),
+            Ansi::Bold => write!(f, "1m"),
+            Ansi::Dim => write!(f, "2m"),
+            Ansi::Underline => write!(f, "4m"),
+            Ansi::Blink => write!(f, "5m"),
+            Ansi::Reverse => write!(f, "7m"),
+            Ansi::SetFg(color) => write!(f, "3{}m", color as u8),
+            Ansi::SetBg(color) => write!(f, "4{}m", color as u8),
+        }
+    }
+}
+
+/// Characters `draw_box` draws frames with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoxStyle {
+    /// `+`, `-` and `|`: works on any terminal
+    #[default]
+    Ascii,
+    /// DEC special graphics line drawing (VT100 and later, xterm, PuTTY)
+    DecGraphics,
+}
+
+impl BoxStyle {
+    /// Top-left, top-right, bottom-left, bottom-right, horizontal, vertical
+    fn chars(self) -> [u8; 6] {
+        match self {
+            BoxStyle::Ascii => [b'+', b'+', b'+', b'+', b'-', b'|'],
+            BoxStyle::DecGraphics => [b'l', b'k', b'm', b'j', b'q', b'x'],
+        }
+    }
+}
+
+/// `getkey` codes for ESC [ n ~, indexed by n (0 = unknown)
+const GETKEY_TILDE: [u8; 25] = [
+    0, KEY_HOME, KEY_INSERT, KEY_DELETE, KEY_END, KEY_PGUP, KEY_PGDN, KEY_HOME, KEY_END, 0,
+    0, KEY_F1, KEY_F1 + 1, KEY_F1 + 2, KEY_F1 + 3, KEY_F1 + 4, 0,
+    KEY_F1 + 5, KEY_F1 + 6, KEY_F1 + 7, KEY_F1 + 8, KEY_F1 + 9, 0,
+    KEY_F1 + 10, KEY_F1 + 11,
+];
+
 impl CodeGen {
     // ========== Inline Escape Sequence Helpers ==========
 
     /// Emit ESC [ prefix (common to most sequences)
     fn emit_csi(&mut self) {
         self.ld_a(ESC);
-        self.call("putchar");
+        self.call("conout");
         self.ld_a(b'[');
-        self.call("putchar");
+        self.call("conout");
+    }
+
+    /// Emit inline code printing `seq` (LD A / CALL conout per byte)
+    ///
+    /// Clobbers A.
+    /// Requires: `conout`
+    pub fn emit_ansi(&mut self, seq: Ansi) {
+        for b in seq.to_string().bytes() {
+            self.ld_a(b);
+            self.call("conout");
+        }
+    }
+
+    /// Store `seq` in the string pool, returning its label
+    pub fn pooled_ansi(&mut self, seq: Ansi) -> String {
+        self.pooled_string(&seq.to_string())
+    }
+
+    /// Emit code printing `seq` from the string pool (smaller than inline
+    /// for long sequences)
+    ///
+    /// Clobbers A, HL.
+    /// Requires: `print_string`
+    pub fn emit_ansi_pooled(&mut self, seq: Ansi) {
+        let label = self.pooled_ansi(seq);
+        self.ld_hl_label(&label);
+        self.call("print_string");
     }
 
     // ========== Screen Control Routines ==========
@@ -23,33 +209,27 @@ impl CodeGen {
     /// Emit clear_screen routine (ESC[2J ESC[H)
     ///
     /// Labels created: `clear_screen`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_clear_screen(&mut self) {
         self.label("clear_screen");
-        self.emit_csi();
-        self.ld_a(b'2');
-        self.call("putchar");
-        self.ld_a(b'J');
-        self.call("putchar");
+        self.emit_ansi(Ansi::ClearScreen);
         // Fall through to cursor_home
     }
 
     /// Emit cursor_home routine (ESC[H)
     ///
     /// Labels created: `cursor_home`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_cursor_home(&mut self) {
         self.label("cursor_home");
-        self.emit_csi();
-        self.ld_a(b'H');
-        self.call("putchar");
+        self.emit_ansi(Ansi::CursorHome);
         self.ret();
     }
 
     /// Emit combined clear_screen + cursor_home
     ///
     /// Labels created: `clear_screen`, `cursor_home`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_clear_screen_and_home(&mut self) {
         self.emit_clear_screen();
         self.emit_cursor_home();
@@ -59,42 +239,85 @@ impl CodeGen {
     /// Input: B = row (1-based), C = col (1-based)
     ///
     /// Labels created: `cursor_pos`
-    /// Requires: `putchar`, `print_byte_dec`
+    /// Requires: `conout`, `print_byte_dec`
+    /// Clobbers: A, BC
     pub fn emit_cursor_pos(&mut self) {
         self.label("cursor_pos");
+        self.emit_csi_bc(b'H');
+    }
+
+    /// Body of the two-parameter routines: ESC [ B ; C `last`, then RET
+    fn emit_csi_bc(&mut self, last: u8) {
         self.emit_csi();
-        self.ld_a_b();              // Row
+        self.push_bc();             // print_byte_dec clobbers BC
+        self.ld_a_b();
         self.call("print_byte_dec");
         self.ld_a(b';');
-        self.call("putchar");
-        self.ld_a_c();              // Col
+        self.call("conout");
+        self.pop_bc();
+        self.ld_a_c();
         self.call("print_byte_dec");
-        self.ld_a(b'H');
-        self.call("putchar");
+        self.ld_a(last);
+        self.call("conout");
         self.ret();
     }
 
     /// Emit clear_to_eol routine (ESC[K)
     ///
     /// Labels created: `clear_to_eol`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_clear_to_eol(&mut self) {
         self.label("clear_to_eol");
-        self.emit_csi();
-        self.ld_a(b'K');
-        self.call("putchar");
+        self.emit_ansi(Ansi::ClearToEol);
         self.ret();
     }
 
     /// Emit clear_to_eos routine (ESC[J) - clear from cursor to end of screen
     ///
     /// Labels created: `clear_to_eos`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_clear_to_eos(&mut self) {
         self.label("clear_to_eos");
-        self.emit_csi();
-        self.ld_a(b'J');
-        self.call("putchar");
+        self.emit_ansi(Ansi::ClearToEos);
+        self.ret();
+    }
+
+    // ========== Scrolling ==========
+
+    /// Emit set_scroll_region routine (ESC[top;bottomr)
+    /// Input: B = top row, C = bottom row (1-based)
+    ///
+    /// Lines outside the region stay put, e.g. a status header. The
+    /// terminal homes the cursor; `reset_scroll_region` undoes it.
+    ///
+    /// Labels created: `set_scroll_region`, `reset_scroll_region`
+    /// Requires: `conout`, `print_byte_dec`
+    /// Clobbers: A, BC
+    pub fn emit_set_scroll_region(&mut self) {
+        self.label("set_scroll_region");
+        self.emit_csi_bc(b'r');
+        self.label("reset_scroll_region");
+        self.emit_ansi(Ansi::ResetScrollRegion);
+        self.ret();
+    }
+
+    /// Emit scroll_up routine (ESC[S) - scroll the region up one line
+    ///
+    /// Labels created: `scroll_up`
+    /// Requires: `conout`
+    pub fn emit_scroll_up(&mut self) {
+        self.label("scroll_up");
+        self.emit_ansi(Ansi::ScrollUp(1));
+        self.ret();
+    }
+
+    /// Emit scroll_down routine (ESC[T) - scroll the region down one line
+    ///
+    /// Labels created: `scroll_down`
+    /// Requires: `conout`
+    pub fn emit_scroll_down(&mut self) {
+        self.label("scroll_down");
+        self.emit_ansi(Ansi::ScrollDown(1));
         self.ret();
     }
 
@@ -103,36 +326,20 @@ impl CodeGen {
     /// Emit cursor_hide routine (ESC[?25l)
     ///
     /// Labels created: `cursor_hide`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_cursor_hide(&mut self) {
         self.label("cursor_hide");
-        self.emit_csi();
-        self.ld_a(b'?');
-        self.call("putchar");
-        self.ld_a(b'2');
-        self.call("putchar");
-        self.ld_a(b'5');
-        self.call("putchar");
-        self.ld_a(b'l');
-        self.call("putchar");
+        self.emit_ansi(Ansi::CursorHide);
         self.ret();
     }
 
     /// Emit cursor_show routine (ESC[?25h)
     ///
     /// Labels created: `cursor_show`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_cursor_show(&mut self) {
         self.label("cursor_show");
-        self.emit_csi();
-        self.ld_a(b'?');
-        self.call("putchar");
-        self.ld_a(b'2');
-        self.call("putchar");
-        self.ld_a(b'5');
-        self.call("putchar");
-        self.ld_a(b'h');
-        self.call("putchar");
+        self.emit_ansi(Ansi::CursorShow);
         self.ret();
     }
 
@@ -141,48 +348,96 @@ impl CodeGen {
     /// Emit cursor_up routine (ESC[A) - move cursor up 1 line
     ///
     /// Labels created: `cursor_up`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_cursor_up(&mut self) {
         self.label("cursor_up");
-        self.emit_csi();
-        self.ld_a(b'A');
-        self.call("putchar");
+        self.emit_ansi(Ansi::CursorUp(1));
         self.ret();
     }
 
     /// Emit cursor_down routine (ESC[B) - move cursor down 1 line
     ///
     /// Labels created: `cursor_down`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_cursor_down(&mut self) {
         self.label("cursor_down");
-        self.emit_csi();
-        self.ld_a(b'B');
-        self.call("putchar");
+        self.emit_ansi(Ansi::CursorDown(1));
         self.ret();
     }
 
     /// Emit cursor_right routine (ESC[C) - move cursor right 1 column
     ///
     /// Labels created: `cursor_right`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_cursor_right(&mut self) {
         self.label("cursor_right");
-        self.emit_csi();
-        self.ld_a(b'C');
-        self.call("putchar");
+        self.emit_ansi(Ansi::CursorRight(1));
         self.ret();
     }
 
     /// Emit cursor_left routine (ESC[D) - move cursor left 1 column
     ///
     /// Labels created: `cursor_left`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_cursor_left(&mut self) {
         self.label("cursor_left");
+        self.emit_ansi(Ansi::CursorLeft(1));
+        self.ret();
+    }
+
+    /// Emit cursor_up_n routine (ESC[nA) - move cursor up A lines
+    ///
+    /// Labels created: `cursor_up_n`
+    /// Requires: `conout`, `print_byte_dec`
+    /// Clobbers: A, BC
+    pub fn emit_cursor_up_n(&mut self) {
+        self.label("cursor_up_n");
+        self.emit_csi_count(b'A');
+    }
+
+    /// Emit cursor_down_n routine (ESC[nB) - move cursor down A lines
+    ///
+    /// Labels created: `cursor_down_n`
+    /// Requires: `conout`, `print_byte_dec`
+    /// Clobbers: A, BC
+    pub fn emit_cursor_down_n(&mut self) {
+        self.label("cursor_down_n");
+        self.emit_csi_count(b'B');
+    }
+
+    /// Emit cursor_right_n routine (ESC[nC) - move cursor right A columns
+    ///
+    /// Labels created: `cursor_right_n`
+    /// Requires: `conout`, `print_byte_dec`
+    /// Clobbers: A, BC
+    pub fn emit_cursor_right_n(&mut self) {
+        self.label("cursor_right_n");
+        self.emit_csi_count(b'C');
+    }
+
+    /// Emit cursor_left_n routine (ESC[nD) - move cursor left A columns
+    ///
+    /// Labels created: `cursor_left_n`
+    /// Requires: `conout`, `print_byte_dec`
+    /// Clobbers: A, BC
+    pub fn emit_cursor_left_n(&mut self) {
+        self.label("cursor_left_n");
+        self.emit_csi_count(b'D');
+    }
+
+    /// Body of the counted moves: ESC [ A `last`, then RET
+    ///
+    /// A count of 0 returns without output; terminals would read ESC[0A
+    /// as a move of one.
+    fn emit_csi_count(&mut self, last: u8) {
+        self.or_a_a();
+        self.ret_z();
+        self.push_af();
         self.emit_csi();
-        self.ld_a(b'D');
-        self.call("putchar");
+        self.pop_af();
+        self.call("print_byte_dec");
+        self.ld_a(last);
+        self.call("conout");
         self.ret();
     }
 
@@ -191,29 +446,321 @@ impl CodeGen {
     /// Emit reset_attrs routine (ESC[0m) - reset all text attributes
     ///
     /// Labels created: `reset_attrs`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_reset_attrs(&mut self) {
         self.label("reset_attrs");
-        self.emit_csi();
-        self.ld_a(b'0');
-        self.call("putchar");
-        self.ld_a(b'm');
-        self.call("putchar");
+        self.emit_ansi(Ansi::ResetAttrs);
         self.ret();
     }
 
     /// Emit reverse_video routine (ESC[7m)
     ///
     /// Labels created: `reverse_video`
-    /// Requires: `putchar`
+    /// Requires: `conout`
     pub fn emit_reverse_video(&mut self) {
         self.label("reverse_video");
+        self.emit_ansi(Ansi::Reverse);
+        self.ret();
+    }
+
+    /// Emit text_bold routine (ESC[1m)
+    ///
+    /// Labels created: `text_bold`
+    /// Requires: `conout`
+    pub fn emit_text_bold(&mut self) {
+        self.label("text_bold");
+        self.emit_ansi(Ansi::Bold);
+        self.ret();
+    }
+
+    /// Emit text_dim routine (ESC[2m)
+    ///
+    /// Labels created: `text_dim`
+    /// Requires: `conout`
+    pub fn emit_text_dim(&mut self) {
+        self.label("text_dim");
+        self.emit_ansi(Ansi::Dim);
+        self.ret();
+    }
+
+    /// Emit text_underline routine (ESC[4m)
+    ///
+    /// Labels created: `text_underline`
+    /// Requires: `conout`
+    pub fn emit_text_underline(&mut self) {
+        self.label("text_underline");
+        self.emit_ansi(Ansi::Underline);
+        self.ret();
+    }
+
+    /// Emit text_blink routine (ESC[5m)
+    ///
+    /// Labels created: `text_blink`
+    /// Requires: `conout`
+    pub fn emit_text_blink(&mut self) {
+        self.label("text_blink");
+        self.emit_ansi(Ansi::Blink);
+        self.ret();
+    }
+
+    /// Emit set_fg_color routine (ESC[3Xm) - foreground colour X = A (0-7)
+    ///
+    /// Labels created: `set_fg_color`
+    /// Requires: `conout`
+    pub fn emit_set_fg_color(&mut self) {
+        self.label("set_fg_color");
+        self.emit_color_attr(b'3');
+    }
+
+    /// Emit set_bg_color routine (ESC[4Xm) - background colour X = A (0-7)
+    ///
+    /// Labels created: `set_bg_color`
+    /// Requires: `conout`
+    pub fn emit_set_bg_color(&mut self) {
+        self.label("set_bg_color");
+        self.emit_color_attr(b'4');
+    }
+
+    /// Body of the colour routines: ESC [ `kind` (A & 7) m
+    fn emit_color_attr(&mut self, kind: u8) {
+        self.push_af();
         self.emit_csi();
-        self.ld_a(b'7');
-        self.call("putchar");
+        self.ld_a(kind);
+        self.call("conout");
+        self.pop_af();
+        self.and_a(7);
+        self.add_a(b'0');
+        self.call("conout");
         self.ld_a(b'm');
-        self.call("putchar");
+        self.call("conout");
+        self.ret();
+    }
+
+    // ========== Boxes ==========
+
+    /// Emit draw_box routine - draws a frame with cursor_pos
+    /// Input: B = top row, C = left col (1-based), D = width, E = height
+    ///
+    /// Width and height include the corners and must be at least 2. With
+    /// [`BoxStyle::DecGraphics`] the routine selects the line-drawing set
+    /// (ESC ( 0) and returns to ASCII (ESC ( B) when done. The cursor is
+    /// left after the bottom-right corner.
+    ///
+    /// Labels created: `draw_box`, `draw_box_side`, `draw_box_bottom`,
+    /// `draw_box_hline`, `draw_box_hloop`, `draw_box_hend`
+    /// Requires: `conout`, `print_byte_dec`, `cursor_pos`
+    /// RAM used: `box_pos` (2 bytes), `box_size` (2 bytes)
+    /// Clobbers: A, BC, DE, HL
+    pub fn emit_draw_box(&mut self, style: BoxStyle) {
+        let [tl, tr, bl, br, horiz, vert] = style.chars();
+        let pos = self.ram_var("box_pos", 2);
+        let size = self.ram_var("box_size", 2);
+
+        self.label("draw_box");
+        self.ld_addr_bc(pos);       // C = col, B = row
+        self.ld_addr_de(size);      // E = height, D = width
+        if style == BoxStyle::DecGraphics {
+            self.emit_charset(b'0');
+        }
+        self.call("cursor_pos");
+        self.ld_a(tl);
+        self.ld_c(tr);
+        self.call("draw_box_hline");
+
+        // Sides: one row per line between top and bottom
+        self.ld_a_addr(size);
+        self.sub_a(2);
+        self.jp_z("draw_box_bottom");
+        self.label("draw_box_side");
+        self.push_af();
+        self.ld_hl(pos + 1);
+        self.inc_hl_ind();          // Next row
+        self.ld_bc_addr(pos);
+        self.call("cursor_pos");
+        self.ld_a(vert);
+        self.call("conout");
+        self.ld_bc_addr(pos);
+        self.ld_a_addr(size + 1);
+        self.add_a_c();
+        self.dec_a();
+        self.ld_c_a();              // Right-hand column
+        self.call("cursor_pos");
+        self.ld_a(vert);
+        self.call("conout");
+        self.pop_af();
+        self.dec_a();
+        self.jp_nz("draw_box_side");
+
+        self.label("draw_box_bottom");
+        self.ld_hl(pos + 1);
+        self.inc_hl_ind();
+        self.ld_bc_addr(pos);
+        self.call("cursor_pos");
+        self.ld_a(bl);
+        self.ld_c(br);
+        self.call("draw_box_hline");
+        if style == BoxStyle::DecGraphics {
+            self.emit_charset(b'B');
+        }
+        self.ret();
+
+        // A = left corner, C = right corner, width - 2 lines between
+        self.label("draw_box_hline");
+        self.call("conout");
+        self.ld_a_addr(size + 1);
+        self.sub_a(2);
+        self.jp_z("draw_box_hend");
+        self.ld_b_a();
+        self.label("draw_box_hloop");
+        self.ld_a(horiz);
+        self.call("conout");
+        self.djnz("draw_box_hloop");
+        self.label("draw_box_hend");
+        self.ld_a_c();
+        self.call("conout");
+        self.ret();
+    }
+
+    /// Emit inline code selecting the G0 character set (ESC ( `set`)
+    fn emit_charset(&mut self, set: u8) {
+        self.ld_a(ESC);
+        self.call("conout");
+        self.ld_a(b'(');
+        self.call("conout");
+        self.ld_a(set);
+        self.call("conout");
+    }
+
+    // ========== Key Input ==========
+
+    /// Emit getkey routine - read a key, decoding escape sequences
+    ///
+    /// Returns the key in A: plain characters as received, cursor and
+    /// editing keys and F1-F12 as the `KEY_*` codes (0x80 and up). Both
+    /// ESC [ and ESC O forms are understood, and modifier parameters
+    /// (ESC [ 1 ; 5 A) are ignored. Unknown sequences are skipped.
+    ///
+    /// There is no timeout: a lone ESC is returned once the next
+    /// character arrives, and that character is returned by the next call.
+    ///
+    /// Labels created: `getkey`, `getkey_read`, `getkey_csi`, `getkey_num`,
+    /// `getkey_skip`, `getkey_end`, `getkey_final`, `getkey_tilde`,
+    /// `getkey_table`
+    /// RAM used: `getkey_pending`
+    /// Requires: `getchar`
+    /// Clobbers: A, BC, DE, HL
+    pub fn emit_getkey(&mut self) {
+        let pending = self.ram_var("getkey_pending", 1);
+        let not_digit = self.unique_label("getkey_not_digit");
+        let lone_esc = self.unique_label("getkey_esc");
+        let not_arrow = self.unique_label("getkey_not_arrow");
+        let not_home = self.unique_label("getkey_not_home");
+        let not_end = self.unique_label("getkey_not_end");
+
+        self.label("getkey");
+        self.ld_a_addr(pending);
+        self.or_a_a();
+        self.jp_z("getkey_read");
+        self.ld_b_a();
+        self.xor_a();
+        self.ld_addr_a(pending);
+        self.ld_a_b();
+        self.ret();
+
+        self.label("getkey_read");
+        self.call("getchar");
+        self.cp(ESC);
+        self.ret_nz();
+        self.call("getchar");
+        self.cp(b'[');
+        self.jp_z("getkey_csi");
+        self.cp(b'O');
+        self.jp_nz(&lone_esc);
+        self.call("getchar");       // ESC O x
+        self.jp("getkey_final");
+
+        // ESC [ n ... : B = first parameter
+        self.label("getkey_csi");
+        self.ld_b(0);
+        self.label("getkey_num");
+        self.call("getchar");
+        self.cp(b'0');
+        self.jp_c(&not_digit);
+        self.cp(b'9' + 1);
+        self.jp_nc(&not_digit);
+        self.sub_a(b'0');
+        self.ld_c_a();
+        self.ld_a_b();              // B = B * 10 + digit
+        self.add_a_a();
+        self.ld_b_a();
+        self.add_a_a();
+        self.add_a_a();
+        self.add_a_b();
+        self.add_a_c();
+        self.ld_b_a();
+        self.jp("getkey_num");
+        self.label(&not_digit);
+        self.cp(b';');
+        self.jp_nz("getkey_end");
+        // Skip further parameters
+        self.label("getkey_skip");
+        self.call("getchar");
+        self.cp(b'0');
+        self.jp_c("getkey_end");
+        self.cp(b';' + 1);
+        self.jp_c("getkey_skip");
+        self.label("getkey_end");
+        self.cp(b'~');
+        self.jp_z("getkey_tilde");
+
+        // Final letter: A-D arrows, H/F Home/End, P-S F1-F4
+        self.label("getkey_final");
+        self.cp(b'A');
+        self.jp_c("getkey_read");
+        self.cp(b'E');
+        self.jp_nc(&not_arrow);
+        self.add_a(KEY_UP.wrapping_sub(b'A'));
+        self.ret();
+        self.label(&not_arrow);
+        self.cp(b'H');
+        self.jp_nz(&not_home);
+        self.ld_a(KEY_HOME);
+        self.ret();
+        self.label(&not_home);
+        self.cp(b'F');
+        self.jp_nz(&not_end);
+        self.ld_a(KEY_END);
         self.ret();
+        self.label(&not_end);
+        self.cp(b'P');
+        self.jp_c("getkey_read");
+        self.cp(b'T');
+        self.jp_nc("getkey_read");
+        self.add_a(KEY_F1.wrapping_sub(b'P'));
+        self.ret();
+
+        // ESC followed by anything else: return ESC, keep the character
+        self.label(&lone_esc);
+        self.ld_addr_a(pending);
+        self.ld_a(ESC);
+        self.ret();
+
+        // ESC [ n ~
+        self.label("getkey_tilde");
+        self.ld_a_b();
+        self.cp(GETKEY_TILDE.len() as u8);
+        self.jp_nc("getkey_read");
+        self.ld_hl_label("getkey_table");
+        self.ld_e_a();
+        self.ld_d(0);
+        self.add_hl_de();
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z("getkey_read");
+        self.ret();
+
+        self.label("getkey_table");
+        self.emit(&GETKEY_TILDE);
     }
 
     // ========== Bundle Emitters ==========
@@ -222,7 +769,7 @@ impl CodeGen {
     ///
     /// Includes: clear_screen, cursor_home, cursor_pos, clear_to_eol,
     /// cursor_hide, cursor_show
-    /// Requires: `putchar`, `print_byte_dec`
+    /// Requires: `conout`, `print_byte_dec`
     pub fn emit_terminal_routines(&mut self) {
         self.emit_clear_screen_and_home();
         self.emit_cursor_pos();
@@ -236,6 +783,161 @@ impl CodeGen {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_ansi_text() {
+        assert_eq!(Ansi::CursorTo(5, 12).to_string(), "\x1B[5;12H");
+        assert_eq!(Ansi::CursorUp(1).to_string(), "\x1B[A");
+        assert_eq!(Ansi::CursorLeft(3).to_string(), "\x1B[3D");
+        assert_eq!(Ansi::SetFg(Color::Red).to_string(), "\x1B[31m");
+        assert_eq!(Ansi::SetBg(Color::White).to_string(), "\x1B[47m");
+        assert_eq!(Ansi::Dim.to_string(), "\x1B[2m");
+        assert_eq!(Ansi::ScrollRegion(2, 24).to_string(), "\x1B[2;24r");
+        assert_eq!(Ansi::ScrollDown(1).to_string(), "\x1B[T");
+    }
+
+    #[test]
+    fn test_emit_ansi() {
+        let mut cg = CodeGen::new();
+        cg.emit_ansi(Ansi::ClearToEol);
+        assert_eq!(cg.rom(), &[
+            0x3E, 0x1B,        // LD A, ESC
+            0xCD, 0x00, 0x00,  // CALL conout
+            0x3E, b'[',        // LD A, '['
+            0xCD, 0x00, 0x00,  // CALL conout
+            0x3E, b'K',        // LD A, 'K'
+            0xCD, 0x00, 0x00,  // CALL conout
+        ]);
+    }
+
+    #[test]
+    fn test_ansi_pooled() {
+        let mut cg = CodeGen::new();
+        cg.emit_ansi_pooled(Ansi::SetFg(Color::Green));
+        cg.emit_ansi_pooled(Ansi::SetFg(Color::Green));
+        cg.label("print_string");
+        cg.resolve_fixups();
+        let pool = cg.size() - 6;
+        assert_eq!(&cg.rom()[pool..], b"\x1B[32m\0");
+        assert_eq!(&cg.rom()[1..3], &[pool as u8, 0x00]);  // LD HL, pooled text
+        assert_eq!(&cg.rom()[7..9], &[pool as u8, 0x00]);  // Stored once
+    }
+
+    #[test]
+    fn test_set_fg_color() {
+        let mut cg = CodeGen::new();
+        cg.emit_set_fg_color();
+        assert_eq!(cg.rom(), &[
+            0xF5,              // PUSH AF
+            0x3E, 0x1B,        // LD A, ESC
+            0xCD, 0x00, 0x00,  // CALL conout
+            0x3E, b'[',        // LD A, '['
+            0xCD, 0x00, 0x00,  // CALL conout
+            0x3E, b'3',        // LD A, '3'
+            0xCD, 0x00, 0x00,  // CALL conout
+            0xF1,              // POP AF
+            0xE6, 0x07,        // AND 7
+            0xC6, b'0',        // ADD A, '0'
+            0xCD, 0x00, 0x00,  // CALL conout
+            0x3E, b'm',        // LD A, 'm'
+            0xCD, 0x00, 0x00,  // CALL conout
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    fn test_set_scroll_region() {
+        let mut cg = CodeGen::new();
+        cg.emit_set_scroll_region();
+        assert_eq!(cg.rom(), &[
+            0x3E, 0x1B,        // LD A, ESC
+            0xCD, 0x00, 0x00,  // CALL conout
+            0x3E, b'[',        // LD A, '['
+            0xCD, 0x00, 0x00,  // CALL conout
+            0xC5,              // PUSH BC
+            0x78,              // LD A, B
+            0xCD, 0x00, 0x00,  // CALL print_byte_dec
+            0x3E, b';',        // LD A, ';'
+            0xCD, 0x00, 0x00,  // CALL conout
+            0xC1,              // POP BC
+            0x79,              // LD A, C
+            0xCD, 0x00, 0x00,  // CALL print_byte_dec
+            0x3E, b'r',        // LD A, 'r'
+            0xCD, 0x00, 0x00,  // CALL conout
+            0xC9,              // RET
+            0x3E, 0x1B,        // reset_scroll_region: LD A, ESC
+            0xCD, 0x00, 0x00,  // CALL conout
+            0x3E, b'[',        // LD A, '['
+            0xCD, 0x00, 0x00,  // CALL conout
+            0x3E, b'r',        // LD A, 'r'
+            0xCD, 0x00, 0x00,  // CALL conout
+            0xC9,              // RET
+        ]);
+        assert_eq!(cg.get_label("reset_scroll_region"), Some(31));
+    }
+
+    #[test]
+    fn test_cursor_up_n() {
+        let mut cg = CodeGen::new();
+        cg.emit_cursor_up_n();
+        assert_eq!(cg.rom(), &[
+            0xB7,              // OR A
+            0xC8,              // RET Z
+            0xF5,              // PUSH AF
+            0x3E, 0x1B,        // LD A, ESC
+            0xCD, 0x00, 0x00,  // CALL conout
+            0x3E, b'[',        // LD A, '['
+            0xCD, 0x00, 0x00,  // CALL conout
+            0xF1,              // POP AF
+            0xCD, 0x00, 0x00,  // CALL print_byte_dec
+            0x3E, b'A',        // LD A, 'A'
+            0xCD, 0x00, 0x00,  // CALL conout
+            0xC9,              // RET
+        ]);
+    }
+
+    #[test]
+    fn test_getkey() {
+        let mut cg = CodeGen::new();
+        cg.emit_getkey();
+        assert_eq!(&cg.rom()[..11], &[
+            0x3A, 0x00, 0x20,  // LD A, (getkey_pending)
+            0xB7,              // OR A
+            0xCA, 0x00, 0x00,  // JP Z, getkey_read
+            0x47,              // LD B, A
+            0xAF,              // XOR A
+            0x32, 0x00,        // LD (getkey_pending), A
+        ]);
+        let table = cg.get_label("getkey_table").unwrap() as usize;
+        assert_eq!(&cg.rom()[table..table + 4], &[0, KEY_HOME, KEY_INSERT, KEY_DELETE]);
+        assert_eq!(cg.rom()[table + 24], KEY_F1 + 11);
+        assert_eq!(cg.rom().len(), table + 25);
+    }
+
+    #[test]
+    fn test_draw_box() {
+        let mut cg = CodeGen::new();
+        cg.emit_draw_box(BoxStyle::DecGraphics);
+        assert_eq!(&cg.rom()[..23], &[
+            0xED, 0x43, 0x00, 0x20,  // LD (box_pos), BC
+            0xED, 0x53, 0x02, 0x20,  // LD (box_size), DE
+            0x3E, 0x1B,              // LD A, ESC
+            0xCD, 0x00, 0x00,        // CALL conout
+            0x3E, b'(',              // LD A, '('
+            0xCD, 0x00, 0x00,        // CALL conout
+            0x3E, b'0',              // LD A, '0'
+            0xCD, 0x00, 0x00,        // CALL conout
+        ]);
+        assert!(cg.has_label("draw_box_hline"));
+        assert_eq!(cg.ram_var_size("box_size"), Some(2));
+
+        let mut cg = CodeGen::new();
+        cg.emit_draw_box(BoxStyle::Ascii);
+        assert_eq!(cg.get_label("draw_box"), Some(0));
+        assert_eq!(&cg.rom()[8..11], &[
+            0xCD, 0x00, 0x00,        // CALL cursor_pos
+        ]);
+    }
+
     #[test]
     fn test_clear_screen_emits() {
         let mut cg = CodeGen::new();
@@ -0,0 +1,194 @@
+//! Tick counter and timeouts driven by a periodic interrupt
+//!
+//! A timer interrupt (a CTC channel, a 555 on /INT, video vsync, ...)
+//! enters `timer_isr`, which increments the 32-bit RAM counter `ticks`.
+//! Its low byte doubles as the tick byte the keyboard layer and event log
+//! expect. Waits compare the low 16 bits with wraparound, so a single wait
+//! can span up to 32767 ticks.
+//!
+//! Route the interrupt to `timer_isr` with `rst_handler(0x38, ...)` for
+//! IM 1, or `emit_im2_table("timer_isr")` for IM 2, then `timer_start`.
+
+use crate::CodeGen;
+
+/// How the tick interrupt is taken
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerMode {
+    /// IM 1: the handler is reached through RST 38H
+    Im1,
+    /// IM 2: the handler is reached through `emit_im2_table`
+    Im2,
+}
+
+/// Tick counter configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerConfig {
+    /// Interrupt rate in Hz
+    pub tick_hz: u32,
+    /// Interrupt mode selected by `timer_start`
+    pub mode: TimerMode,
+}
+
+impl Default for TimerConfig {
+    fn default() -> Self {
+        Self {
+            tick_hz: 100,
+            mode: TimerMode::Im1,
+        }
+    }
+}
+
+impl TimerConfig {
+    /// Ticks in `ms` milliseconds, rounded up so waits are never short
+    ///
+    /// Panics if the result doesn't fit a 16-bit wait.
+    pub fn ticks(&self, ms: u32) -> u16 {
+        let ticks = (ms as u64 * self.tick_hz as u64 + 999) / 1000;
+        assert!(ticks <= 0x7FFF, "{} ms is too long for a tick wait", ms);
+        ticks as u16
+    }
+}
+
+impl CodeGen {
+    /// Emit the tick counter, its interrupt handler and the wait routines
+    ///
+    /// - `timer_isr`: interrupt handler; increments `ticks`, then EI, RETI.
+    /// - `timer_tick`: the increment alone, for calling from your own
+    ///   handler. Preserves all registers.
+    /// - `timer_now`: HL = low 16 bits of `ticks`.
+    /// - `timer_read`: copy all four bytes of `ticks` to DE, under DI.
+    ///   Clobbers BC, DE, HL.
+    /// - `timer_wait`: wait HL ticks. Clobbers A, DE, HL.
+    /// - `timer_wait_until`: wait until the low 16 bits of `ticks` reach
+    ///   HL. Clobbers A, DE, HL.
+    /// - `millis`: HL = milliseconds since start, modulo 65536; only
+    ///   emitted if the tick rate divides 1000 Hz. Clobbers DE.
+    ///
+    /// Labels created: `timer_isr`, `timer_tick`, `timer_now`, `timer_read`,
+    /// `timer_wait`, `timer_wait_until`, `millis`
+    /// RAM used: `ticks` (4 bytes; declare it yourself to place it)
+    pub fn emit_timer(&mut self, config: &TimerConfig) {
+        assert!(config.tick_hz > 0, "tick rate must be non-zero");
+        match self.ram_var_size("ticks") {
+            None => {
+                self.ram_var("ticks", 4);
+            }
+            Some(size) => assert!(size >= 4, "RAM variable ticks is {} bytes, need 4", size),
+        }
+        let ticks = self.get_label("ticks").unwrap();
+
+        self.label("timer_isr");
+        self.call("timer_tick");
+        self.ei();
+        self.reti();
+
+        let done = self.unique_label("timer_tick_done");
+        self.label("timer_tick");
+        self.push_af();
+        self.push_hl();
+        self.ld_hl(ticks);
+        for i in 0..4 {
+            if i > 0 {
+                self.inc_hl();
+            }
+            self.inc_hl_ind();
+            if i < 3 {
+                self.jp_nz(&done);
+            }
+        }
+        self.label(&done);
+        self.pop_hl();
+        self.pop_af();
+        self.ret();
+
+        self.label("timer_now");
+        self.ld_hl_addr(ticks);      // One instruction: no DI needed
+        self.ret();
+
+        self.label("timer_read");
+        self.ld_hl(ticks);
+        self.ld_bc(4);
+        self.di();
+        self.ldir();
+        self.ei();
+        self.ret();
+
+        self.label("timer_wait");
+        self.ex_de_hl();
+        self.ld_hl_addr(ticks);
+        self.add_hl_de();            // Target tick
+        self.label("timer_wait_until");
+        self.ex_de_hl();
+        let wait = self.unique_label("timer_wait_loop");
+        self.label(&wait);
+        self.ld_hl_addr(ticks);
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.bit_h(7);
+        self.jr_nz(&wait);           // Now - target still negative
+        self.ret();
+
+        if 1000 % config.tick_hz == 0 {
+            // HL = ticks * ms_per_tick, by shift-and-add at build time
+            let k = 1000 / config.tick_hz;
+            self.label("millis");
+            self.ld_hl_addr(ticks);
+            if k > 1 {
+                self.ld_d_h();
+                self.ld_e_l();
+                for bit in (0..31 - k.leading_zeros()).rev() {
+                    self.add_hl_hl();
+                    if k & (1 << bit) != 0 {
+                        self.add_hl_de();
+                    }
+                }
+            }
+            self.ret();
+        }
+    }
+
+    /// Emit timer startup: select the configured interrupt mode and EI
+    ///
+    /// Requires: `im2_table` in IM 2 mode
+    pub fn timer_start(&mut self, config: &TimerConfig) {
+        match config.mode {
+            TimerMode::Im1 => self.im1(),
+            TimerMode::Im2 => self.im2_setup(),
+        }
+        self.ei();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_ticks() {
+        let config = TimerConfig { tick_hz: 60, ..Default::default() };
+        assert_eq!(config.ticks(1000), 60);
+        assert_eq!(config.ticks(10), 1);
+        assert_eq!(TimerConfig::default().ticks(250), 25);
+    }
+
+    #[test]
+    fn test_timer_millis() {
+        let mut cg = CodeGen::new();
+        cg.emit_timer(&TimerConfig::default());
+        let millis = cg.get_label("millis").unwrap() as usize;
+        assert_eq!(&cg.rom()[millis..], &[
+            0x2A, 0x00, 0x20,  // LD HL, (ticks)
+            0x54,              // LD D, H
+            0x5D,              // LD E, L
+            0x29,              // ADD HL, HL
+            0x29,              // ADD HL, HL
+            0x19,              // ADD HL, DE
+            0x29,              // ADD HL, HL
+            0xC9,              // RET
+        ]);
+
+        let mut cg = CodeGen::new();
+        cg.emit_timer(&TimerConfig { tick_hz: 60, ..Default::default() });
+        assert!(!cg.has_label("millis"));
+    }
+}
@@ -0,0 +1,207 @@
+//! Square-wave tones on an output port bit
+//!
+//! For boards with a piezo or speaker on a latch bit and no sound chip. The
+//! tone loop is cycle counted, so pitches and durations are computed at
+//! build time from `RomConfig::clock_hz`.
+//!
+//! The routines write only the speaker bit: other bits of the latch are
+//! driven low while a tone plays.
+
+use crate::CodeGen;
+
+/// T-states per iteration of the half-period delay loop
+const TONE_LOOP_T: u32 = 24;
+/// T-states per half period outside the delay loop
+const TONE_OVERHEAD_T: u32 = 71;
+/// Set in a tune record's count word to play silence
+const TUNE_REST: u16 = 0x8000;
+/// Frequency whose timing is used for rests
+const REST_TIMING_HZ: u32 = 1000;
+/// Pitch and length of `beep`
+pub const BEEP_HZ: u32 = 1000;
+pub const BEEP_MS: u32 = 100;
+
+impl CodeGen {
+    /// Emit the tone generator and tune player for a speaker on `port` bit `bit`
+    ///
+    /// - `tone`: HL = delay count, DE = half periods; use `tone_params`
+    ///   or `play_tone` to compute them.
+    /// - `tune_play`: HL = tune emitted by `emit_tune`; returns at its end.
+    ///
+    /// Labels created: `tone`, `tone_core`, `tune_play`
+    /// Clobbers A, BC, DE, HL
+    pub fn emit_tone_port(&mut self, port: u8, bit: u8) {
+        assert!(bit < 8, "bit must be 0-7");
+
+        self.label("tone");
+        self.ld_b(1 << bit);
+
+        // tone_core: B = toggle mask (0 = silent)
+        self.label("tone_core");
+        self.ld_c(0);                // C = output value
+        let half = self.unique_label("tone_half");
+        let wait = self.unique_label("tone_wait");
+        self.label(&half);
+        self.ld_a_c();               // 4
+        self.emit(&[0xA8]);          // XOR B (4)
+        self.ld_c_a();               // 4
+        self.out_a(port);            // 11
+        self.push_hl();              // 11
+        self.label(&wait);
+        self.dec_hl();               // 6
+        self.ld_a_h();               // 4
+        self.or_l();                 // 4
+        self.jp_nz(&wait);           // 10
+        self.pop_hl();               // 10
+        self.dec_de();               // 6
+        self.ld_a_d();               // 4
+        self.emit(&[0xB3]);          // OR E (4)
+        self.jp_nz(&half);           // 10
+        self.ret();
+
+        // tune_play: 4-byte records (delay, count), delay 0 ends the tune
+        self.label("tune_play");
+        self.ld_e_hl_ind();
+        self.inc_hl();
+        self.ld_d_hl_ind();
+        self.inc_hl();
+        self.ld_a_d();
+        self.emit(&[0xB3]);          // OR E
+        self.ret_z();
+        self.ld_c_hl_ind();
+        self.inc_hl();
+        self.ld_b_hl_ind();
+        self.inc_hl();
+        self.push_hl();
+        self.ex_de_hl();             // HL = delay
+        self.ld_d_b();
+        self.ld_e_c();               // DE = count
+        self.ld_b(1 << bit);
+        let play = self.unique_label("tune_note");
+        self.emit(&[0xCB, 0x7A]);    // BIT 7, D
+        self.jp_z(&play);
+        self.emit(&[0xCB, 0xBA]);    // RES 7, D
+        self.ld_b(0);                // Rest: same timing, no toggling
+        self.label(&play);
+        self.call("tone_core");
+        self.pop_hl();
+        self.jp("tune_play");
+    }
+
+    /// Emit beep routine - a short alert tone on `port` bit `bit`
+    ///
+    /// Plays `BEEP_HZ` for `BEEP_MS` through `tone`, emitting the tone
+    /// generator as well unless it is already there; `play_tone` gives
+    /// other pitches and lengths. Preserves all registers, so it can be
+    /// called from anywhere (e.g. on a rejected key).
+    ///
+    /// Labels created: `beep`, and those of `emit_tone_port` if `tone` is
+    /// not yet defined
+    pub fn emit_beep(&mut self, port: u8, bit: u8) {
+        if !self.has_label("tone") {
+            self.emit_tone_port(port, bit);
+        }
+        let (delay, halves) = self.tone_params(BEEP_HZ, BEEP_MS);
+        self.label("beep");
+        self.push_af();
+        self.push_bc();
+        self.push_de();
+        self.push_hl();
+        self.ld_hl(delay);
+        self.ld_de(halves);
+        self.call("tone");
+        self.pop_hl();
+        self.pop_de();
+        self.pop_bc();
+        self.pop_af();
+        self.ret();
+    }
+
+    /// Compute `tone` parameters (delay count, half periods) for a note
+    ///
+    /// Panics if the frequency is too high for the clock or the note too long.
+    pub fn tone_params(&self, freq_hz: u32, ms: u32) -> (u16, u16) {
+        assert!(freq_hz > 0, "frequency must be non-zero");
+        let half_t = self.config().clock_hz / (2 * freq_hz);
+        assert!(
+            half_t >= TONE_OVERHEAD_T + TONE_LOOP_T,
+            "{} Hz is too high for a {} Hz clock",
+            freq_hz,
+            self.config().clock_hz
+        );
+        let delay = ((half_t - TONE_OVERHEAD_T) / TONE_LOOP_T).min(0xFFFF) as u16;
+        let halves = (freq_hz as u64 * ms as u64 * 2 / 1000).max(1);
+        assert!(halves < TUNE_REST as u64, "note too long");
+        (delay, halves as u16)
+    }
+
+    /// Emit a call playing `freq_hz` for `ms` milliseconds
+    ///
+    /// Requires: `tone`
+    pub fn play_tone(&mut self, freq_hz: u32, ms: u32) {
+        let (delay, halves) = self.tone_params(freq_hz, ms);
+        self.ld_hl(delay);
+        self.ld_de(halves);
+        self.call("tone");
+    }
+
+    /// Emit a tune for `tune_play` as (frequency Hz, milliseconds) notes
+    ///
+    /// A frequency of 0 is a rest.
+    ///
+    /// Labels created: `label`
+    pub fn emit_tune(&mut self, label: &str, notes: &[(u32, u32)]) {
+        self.label(label);
+        for &(freq_hz, ms) in notes {
+            let (delay, halves) = if freq_hz == 0 {
+                let (delay, halves) = self.tone_params(REST_TIMING_HZ, ms);
+                (delay, halves | TUNE_REST)
+            } else {
+                self.tone_params(freq_hz, ms)
+            };
+            self.emit_word(delay);
+            self.emit_word(halves);
+        }
+        self.emit_word(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tone_params() {
+        let cg = CodeGen::new();
+        // 4 MHz, 1 kHz: half period 2000 T = 71 + 24 * 80.4
+        assert_eq!(cg.tone_params(1000, 100), (80, 200));
+    }
+
+    #[test]
+    fn test_beep_reuses_tone() {
+        let mut cg = CodeGen::new();
+        cg.emit_tone_port(0x40, 0);
+        let start = cg.pos() as usize;
+        cg.emit_beep(0x40, 0);
+        assert_eq!(cg.get_label("beep"), Some(start as u16));
+        assert_eq!(&cg.rom()[start..start + 10], &[
+            0xF5,              // PUSH AF
+            0xC5,              // PUSH BC
+            0xD5,              // PUSH DE
+            0xE5,              // PUSH HL
+            0x21, 80, 0,       // LD HL, 80
+            0x11, 200, 0,      // LD DE, 200
+        ]);
+    }
+
+    #[test]
+    fn test_tune_data() {
+        let mut cg = CodeGen::new();
+        cg.emit_tune("tune", &[(1000, 100), (0, 50)]);
+        assert_eq!(cg.rom(), &[
+            80, 0, 200, 0,           // 1 kHz, 100 ms
+            80, 0, 100, 0x80,        // Rest, 50 ms
+            0, 0,                    // End
+        ]);
+    }
+}
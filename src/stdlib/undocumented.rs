@@ -0,0 +1,409 @@
+//! Undocumented Z80 instructions (feature `undocumented`)
+//!
+//! The NMOS and CMOS Z80 on the RetroShield execute these reliably, and
+//! ported code often depends on them: SLL, 8-bit access to the halves of IX
+//! and IY, and the DD CB / FD CB forms that also copy the result of a
+//! shift or RES/SET on `(IX+d)` into a register. The helpers panic unless
+//! `RomConfig::cpu` is `CpuProfile::Z80`: the Z180 traps these opcodes and
+//! the R800 and Z80N decode some of them differently.
+
+use crate::instructions::{instructions, Reg16, Reg8};
+use crate::{CodeGen, CpuProfile};
+
+instructions! {
+    static UNDOCUMENTED_OPCODES for CpuProfile::Z80;
+
+    // ========== SLL (shift left, bit 0 set) ==========
+    /// SLL B
+    sll_b() => "SLL B", [0xCB, 0x30], 8;
+
+    /// SLL C
+    sll_c() => "SLL C", [0xCB, 0x31], 8;
+
+    /// SLL D
+    sll_d() => "SLL D", [0xCB, 0x32], 8;
+
+    /// SLL E
+    sll_e() => "SLL E", [0xCB, 0x33], 8;
+
+    /// SLL H
+    sll_h() => "SLL H", [0xCB, 0x34], 8;
+
+    /// SLL L
+    sll_l() => "SLL L", [0xCB, 0x35], 8;
+
+    /// SLL (HL)
+    sll_hl_ind() => "SLL (HL)", [0xCB, 0x36], 15;
+
+    /// SLL A
+    sll_a() => "SLL A", [0xCB, 0x37], 8;
+
+    // ========== IXH / IXL ==========
+
+    /// LD B, IXH
+    ld_b_ixh() => "LD B,IXH", [0xDD, 0x44], 8;
+
+    /// LD C, IXH
+    ld_c_ixh() => "LD C,IXH", [0xDD, 0x4C], 8;
+
+    /// LD D, IXH
+    ld_d_ixh() => "LD D,IXH", [0xDD, 0x54], 8;
+
+    /// LD E, IXH
+    ld_e_ixh() => "LD E,IXH", [0xDD, 0x5C], 8;
+
+    /// LD A, IXH
+    ld_a_ixh() => "LD A,IXH", [0xDD, 0x7C], 8;
+
+    /// LD IXH, B
+    ld_ixh_b() => "LD IXH,B", [0xDD, 0x60], 8;
+
+    /// LD IXH, C
+    ld_ixh_c() => "LD IXH,C", [0xDD, 0x61], 8;
+
+    /// LD IXH, D
+    ld_ixh_d() => "LD IXH,D", [0xDD, 0x62], 8;
+
+    /// LD IXH, E
+    ld_ixh_e() => "LD IXH,E", [0xDD, 0x63], 8;
+
+    /// LD IXH, A
+    ld_ixh_a() => "LD IXH,A", [0xDD, 0x67], 8;
+
+    /// LD IXH, IXL
+    ld_ixh_ixl() => "LD IXH,IXL", [0xDD, 0x65], 8;
+
+    /// LD IXH, n
+    ld_ixh(n: imm8) => "LD IXH,n", [0xDD, 0x26], 11;
+
+    /// INC IXH
+    inc_ixh() => "INC IXH", [0xDD, 0x24], 8;
+
+    /// DEC IXH
+    dec_ixh() => "DEC IXH", [0xDD, 0x25], 8;
+
+    /// ADD A, IXH
+    add_a_ixh() => "ADD A,IXH", [0xDD, 0x84], 8;
+
+    /// ADC A, IXH
+    adc_a_ixh() => "ADC A,IXH", [0xDD, 0x8C], 8;
+
+    /// SUB IXH
+    sub_ixh() => "SUB IXH", [0xDD, 0x94], 8;
+
+    /// SBC A, IXH
+    sbc_a_ixh() => "SBC A,IXH", [0xDD, 0x9C], 8;
+
+    /// AND IXH
+    and_ixh() => "AND IXH", [0xDD, 0xA4], 8;
+
+    /// XOR IXH
+    xor_ixh() => "XOR IXH", [0xDD, 0xAC], 8;
+
+    /// OR IXH
+    or_ixh() => "OR IXH", [0xDD, 0xB4], 8;
+
+    /// CP IXH
+    cp_ixh() => "CP IXH", [0xDD, 0xBC], 8;
+
+    /// LD B, IXL
+    ld_b_ixl() => "LD B,IXL", [0xDD, 0x45], 8;
+
+    /// LD C, IXL
+    ld_c_ixl() => "LD C,IXL", [0xDD, 0x4D], 8;
+
+    /// LD D, IXL
+    ld_d_ixl() => "LD D,IXL", [0xDD, 0x55], 8;
+
+    /// LD E, IXL
+    ld_e_ixl() => "LD E,IXL", [0xDD, 0x5D], 8;
+
+    /// LD A, IXL
+    ld_a_ixl() => "LD A,IXL", [0xDD, 0x7D], 8;
+
+    /// LD IXL, B
+    ld_ixl_b() => "LD IXL,B", [0xDD, 0x68], 8;
+
+    /// LD IXL, C
+    ld_ixl_c() => "LD IXL,C", [0xDD, 0x69], 8;
+
+    /// LD IXL, D
+    ld_ixl_d() => "LD IXL,D", [0xDD, 0x6A], 8;
+
+    /// LD IXL, E
+    ld_ixl_e() => "LD IXL,E", [0xDD, 0x6B], 8;
+
+    /// LD IXL, A
+    ld_ixl_a() => "LD IXL,A", [0xDD, 0x6F], 8;
+
+    /// LD IXL, IXH
+    ld_ixl_ixh() => "LD IXL,IXH", [0xDD, 0x6C], 8;
+
+    /// LD IXL, n
+    ld_ixl(n: imm8) => "LD IXL,n", [0xDD, 0x2E], 11;
+
+    /// INC IXL
+    inc_ixl() => "INC IXL", [0xDD, 0x2C], 8;
+
+    /// DEC IXL
+    dec_ixl() => "DEC IXL", [0xDD, 0x2D], 8;
+
+    /// ADD A, IXL
+    add_a_ixl() => "ADD A,IXL", [0xDD, 0x85], 8;
+
+    /// ADC A, IXL
+    adc_a_ixl() => "ADC A,IXL", [0xDD, 0x8D], 8;
+
+    /// SUB IXL
+    sub_ixl() => "SUB IXL", [0xDD, 0x95], 8;
+
+    /// SBC A, IXL
+    sbc_a_ixl() => "SBC A,IXL", [0xDD, 0x9D], 8;
+
+    /// AND IXL
+    and_ixl() => "AND IXL", [0xDD, 0xA5], 8;
+
+    /// XOR IXL
+    xor_ixl() => "XOR IXL", [0xDD, 0xAD], 8;
+
+    /// OR IXL
+    or_ixl() => "OR IXL", [0xDD, 0xB5], 8;
+
+    /// CP IXL
+    cp_ixl() => "CP IXL", [0xDD, 0xBD], 8;
+
+    // ========== IYH / IYL ==========
+
+    /// LD B, IYH
+    ld_b_iyh() => "LD B,IYH", [0xFD, 0x44], 8;
+
+    /// LD C, IYH
+    ld_c_iyh() => "LD C,IYH", [0xFD, 0x4C], 8;
+
+    /// LD D, IYH
+    ld_d_iyh() => "LD D,IYH", [0xFD, 0x54], 8;
+
+    /// LD E, IYH
+    ld_e_iyh() => "LD E,IYH", [0xFD, 0x5C], 8;
+
+    /// LD A, IYH
+    ld_a_iyh() => "LD A,IYH", [0xFD, 0x7C], 8;
+
+    /// LD IYH, B
+    ld_iyh_b() => "LD IYH,B", [0xFD, 0x60], 8;
+
+    /// LD IYH, C
+    ld_iyh_c() => "LD IYH,C", [0xFD, 0x61], 8;
+
+    /// LD IYH, D
+    ld_iyh_d() => "LD IYH,D", [0xFD, 0x62], 8;
+
+    /// LD IYH, E
+    ld_iyh_e() => "LD IYH,E", [0xFD, 0x63], 8;
+
+    /// LD IYH, A
+    ld_iyh_a() => "LD IYH,A", [0xFD, 0x67], 8;
+
+    /// LD IYH, IYL
+    ld_iyh_iyl() => "LD IYH,IYL", [0xFD, 0x65], 8;
+
+    /// LD IYH, n
+    ld_iyh(n: imm8) => "LD IYH,n", [0xFD, 0x26], 11;
+
+    /// INC IYH
+    inc_iyh() => "INC IYH", [0xFD, 0x24], 8;
+
+    /// DEC IYH
+    dec_iyh() => "DEC IYH", [0xFD, 0x25], 8;
+
+    /// ADD A, IYH
+    add_a_iyh() => "ADD A,IYH", [0xFD, 0x84], 8;
+
+    /// ADC A, IYH
+    adc_a_iyh() => "ADC A,IYH", [0xFD, 0x8C], 8;
+
+    /// SUB IYH
+    sub_iyh() => "SUB IYH", [0xFD, 0x94], 8;
+
+    /// SBC A, IYH
+    sbc_a_iyh() => "SBC A,IYH", [0xFD, 0x9C], 8;
+
+    /// AND IYH
+    and_iyh() => "AND IYH", [0xFD, 0xA4], 8;
+
+    /// XOR IYH
+    xor_iyh() => "XOR IYH", [0xFD, 0xAC], 8;
+
+    /// OR IYH
+    or_iyh() => "OR IYH", [0xFD, 0xB4], 8;
+
+    /// CP IYH
+    cp_iyh() => "CP IYH", [0xFD, 0xBC], 8;
+
+    /// LD B, IYL
+    ld_b_iyl() => "LD B,IYL", [0xFD, 0x45], 8;
+
+    /// LD C, IYL
+    ld_c_iyl() => "LD C,IYL", [0xFD, 0x4D], 8;
+
+    /// LD D, IYL
+    ld_d_iyl() => "LD D,IYL", [0xFD, 0x55], 8;
+
+    /// LD E, IYL
+    ld_e_iyl() => "LD E,IYL", [0xFD, 0x5D], 8;
+
+    /// LD A, IYL
+    ld_a_iyl() => "LD A,IYL", [0xFD, 0x7D], 8;
+
+    /// LD IYL, B
+    ld_iyl_b() => "LD IYL,B", [0xFD, 0x68], 8;
+
+    /// LD IYL, C
+    ld_iyl_c() => "LD IYL,C", [0xFD, 0x69], 8;
+
+    /// LD IYL, D
+    ld_iyl_d() => "LD IYL,D", [0xFD, 0x6A], 8;
+
+    /// LD IYL, E
+    ld_iyl_e() => "LD IYL,E", [0xFD, 0x6B], 8;
+
+    /// LD IYL, A
+    ld_iyl_a() => "LD IYL,A", [0xFD, 0x6F], 8;
+
+    /// LD IYL, IYH
+    ld_iyl_iyh() => "LD IYL,IYH", [0xFD, 0x6C], 8;
+
+    /// LD IYL, n
+    ld_iyl(n: imm8) => "LD IYL,n", [0xFD, 0x2E], 11;
+
+    /// INC IYL
+    inc_iyl() => "INC IYL", [0xFD, 0x2C], 8;
+
+    /// DEC IYL
+    dec_iyl() => "DEC IYL", [0xFD, 0x2D], 8;
+
+    /// ADD A, IYL
+    add_a_iyl() => "ADD A,IYL", [0xFD, 0x85], 8;
+
+    /// ADC A, IYL
+    adc_a_iyl() => "ADC A,IYL", [0xFD, 0x8D], 8;
+
+    /// SUB IYL
+    sub_iyl() => "SUB IYL", [0xFD, 0x95], 8;
+
+    /// SBC A, IYL
+    sbc_a_iyl() => "SBC A,IYL", [0xFD, 0x9D], 8;
+
+    /// AND IYL
+    and_iyl() => "AND IYL", [0xFD, 0xA5], 8;
+
+    /// XOR IYL
+    xor_iyl() => "XOR IYL", [0xFD, 0xAD], 8;
+
+    /// OR IYL
+    or_iyl() => "OR IYL", [0xFD, 0xB5], 8;
+
+    /// CP IYL
+    cp_iyl() => "CP IYL", [0xFD, 0xBD], 8;
+}
+
+/// CB-prefix shift and rotate operation, in Z80 encoding order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shift {
+    Rlc,
+    Rrc,
+    Rl,
+    Rr,
+    Sla,
+    Sra,
+    /// Undocumented: shift left, bit 0 set
+    Sll,
+    Srl,
+}
+
+impl CodeGen {
+    /// Emit `prefix CB d op` for an `(IX+d)` / `(IY+d)` CB operation
+    /// whose result is also copied to `r`
+    fn emit_index_cb_copy(&mut self, idx: Reg16, d: i8, op: u8, r: Reg8, syntax: &str) {
+        self.require_cpu(CpuProfile::Z80, syntax);
+        let prefix = match idx {
+            Reg16::IX => 0xDD,
+            Reg16::IY => 0xFD,
+            _ => panic!("{}: index register must be IX or IY, got {:?}", syntax, idx),
+        };
+        assert!(r != Reg8::HlInd, "{}: the copy target must be a register", syntax);
+        self.emit(&[prefix, 0xCB, d as u8, op | r.code()]);
+    }
+
+    /// RLC/RRC/.../SRL (IX+d) or (IY+d), also copying the result to `r`
+    /// (e.g. `RLC (IX+2),B`)
+    pub fn shift_indexed_to(&mut self, op: Shift, idx: Reg16, d: i8, r: Reg8) {
+        self.emit_index_cb_copy(idx, d, (op as u8) << 3, r, "shift (IX+d),r");
+    }
+
+    /// RES b, (IX+d) or (IY+d), also copying the result to `r`
+    pub fn res_indexed_to(&mut self, bit: u8, idx: Reg16, d: i8, r: Reg8) {
+        self.emit_index_cb_copy(idx, d, 0x80 | (bit & 7) << 3, r, "RES b,(IX+d),r");
+    }
+
+    /// SET b, (IX+d) or (IY+d), also copying the result to `r`
+    pub fn set_indexed_to(&mut self, bit: u8, idx: Reg16, d: i8, r: Reg8) {
+        self.emit_index_cb_copy(idx, d, 0xC0 | (bit & 7) << 3, r, "SET b,(IX+d),r");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RomConfig;
+
+    #[test]
+    fn test_sll_and_index_halves() {
+        let mut cg = CodeGen::new();
+        cg.sll_a();
+        cg.sll_hl_ind();
+        cg.ld_a_ixh();
+        cg.ld_iyl_b();
+        cg.ld_ixl(0x12);
+        cg.ld_ixh_ixl();
+        cg.dec_iyh();
+        cg.add_a_ixl();
+        cg.cp_iyh();
+        assert_eq!(cg.rom(), &[
+            0xCB, 0x37,        // SLL A
+            0xCB, 0x36,        // SLL (HL)
+            0xDD, 0x7C,        // LD A, IXH
+            0xFD, 0x68,        // LD IYL, B
+            0xDD, 0x2E, 0x12,  // LD IXL, 0x12
+            0xDD, 0x65,        // LD IXH, IXL
+            0xFD, 0x25,        // DEC IYH
+            0xDD, 0x85,        // ADD A, IXL
+            0xFD, 0xBC,        // CP IYH
+        ]);
+    }
+
+    #[test]
+    fn test_indexed_cb_copy() {
+        let mut cg = CodeGen::new();
+        cg.shift_indexed_to(Shift::Rlc, Reg16::IX, 2, Reg8::B);
+        cg.shift_indexed_to(Shift::Sll, Reg16::IY, -1, Reg8::A);
+        cg.res_indexed_to(3, Reg16::IX, 0, Reg8::E);
+        cg.set_indexed_to(7, Reg16::IY, 4, Reg8::L);
+        assert_eq!(cg.rom(), &[
+            0xDD, 0xCB, 0x02, 0x00,  // RLC (IX+2), B
+            0xFD, 0xCB, 0xFF, 0x37,  // SLL (IY-1), A
+            0xDD, 0xCB, 0x00, 0x9B,  // RES 3, (IX+0), E
+            0xFD, 0xCB, 0x04, 0xFD,  // SET 7, (IY+4), L
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "requires the Z80 CPU profile")]
+    fn test_rejected_on_z180() {
+        let mut cg = CodeGen::with_config(RomConfig {
+            cpu: CpuProfile::Z180,
+            ..Default::default()
+        });
+        cg.sll_b();
+    }
+}
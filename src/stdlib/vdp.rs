@@ -0,0 +1,481 @@
+//! TMS9918 video display processor
+//!
+//! Register setup, VRAM access and a text console. The default registers
+//! select text mode: 40 x 24 characters from a 6 x 8 font in the pattern
+//! table at VRAM 0x0000, with the name table at 0x0800. The VDP has no
+//! built-in font; load one with `vdp_load` before printing.
+//!
+//! `vdp_putchar` prints to the screen like a small terminal: CR, LF, BS
+//! and FF (clear) are obeyed, the screen scrolls, and the sequences the
+//! terminal routines send for cursor positioning and clearing (ESC [ r ; c
+//! H, ESC [ J, ESC [ 2 J, ESC [ K) work. Other escape sequences are
+//! swallowed. Point `conout` at it with `emit_conout_to("vdp_putchar")`
+//! to send all formatted output to the screen.
+//!
+//! VRAM accesses are spaced by the instruction timing; at 4 MHz that is
+//! enough for text mode but not for the graphics modes during the active
+//! display.
+
+use crate::CodeGen;
+
+/// Text-mode registers: 16K, display on, M1, name table 0x0800, pattern
+/// table 0x0000, white on dark blue
+pub const VDP_TEXT_MODE: [u8; 8] = [0x00, 0xD0, 0x02, 0x00, 0x00, 0x00, 0x00, 0xF4];
+
+/// Text-mode screen size
+const COLS: u8 = 40;
+const ROWS: u8 = 24;
+
+/// TMS9918 ports and initial registers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VdpConfig {
+    /// VRAM data port (MODE = 0)
+    pub data_port: u8,
+    /// Register/address port (MODE = 1)
+    pub control_port: u8,
+    /// Values written to registers 0-7 by `vdp_init`
+    pub regs: [u8; 8],
+}
+
+impl Default for VdpConfig {
+    fn default() -> Self {
+        Self {
+            data_port: 0x98,
+            control_port: 0x99,
+            regs: VDP_TEXT_MODE,
+        }
+    }
+}
+
+impl VdpConfig {
+    /// VRAM address of the name table (register 2)
+    pub fn name_table(&self) -> u16 {
+        ((self.regs[2] & 0x0F) as u16) << 10
+    }
+
+    /// VRAM address of the pattern table (register 4)
+    pub fn pattern_table(&self) -> u16 {
+        ((self.regs[4] & 0x07) as u16) << 11
+    }
+}
+
+impl CodeGen {
+    /// Emit the TMS9918 routines
+    ///
+    /// - `vdp_init`: write `regs` to registers 0-7, clear the screen.
+    ///   Clobbers A, BC, DE, HL.
+    /// - `vdp_set_reg`: write A to register E. Clobbers A.
+    /// - `vdp_write_addr` / `vdp_read_addr`: point the VRAM address at HL
+    ///   for writing or reading through the data port. Clobber A.
+    /// - `vdp_write`: copy BC bytes from DE to VRAM at HL. Clobbers A, BC,
+    ///   DE.
+    /// - `vdp_fill`: fill BC bytes of VRAM at HL with A. Clobbers A, BC, E.
+    /// - `vdp_cls`: blank the name table and home the cursor. Clobbers A,
+    ///   BC, DE, HL.
+    /// - `vdp_putchar`: print A at the cursor. Preserves all registers.
+    ///
+    /// Labels created: `vdp_init`, `vdp_regs`, `vdp_set_reg`,
+    /// `vdp_write_addr`, `vdp_read_addr`, `vdp_write`, `vdp_fill`, `vdp_cls`,
+    /// `vdp_putchar`, `vdp_cursor_addr`, `vdp_scroll`
+    /// RAM used: `vdp_row`, `vdp_col`, `vdp_esc`, `vdp_parm` (3 bytes),
+    /// `vdp_line` (40 bytes)
+    pub fn emit_vdp(&mut self, config: &VdpConfig) {
+        let data = config.data_port;
+        let ctrl = config.control_port;
+        let name = config.name_table();
+        let screen = COLS as u16 * ROWS as u16;
+        let row = self.ram_var("vdp_row", 1);
+        let col = self.ram_var("vdp_col", 1);
+        let esc = self.ram_var("vdp_esc", 1);     // 0 text, 1 after ESC, 2 in ESC [
+        let parm = self.ram_var("vdp_parm", 3);   // First, second parameter, index
+        let line = self.ram_var("vdp_line", COLS as u16);
+
+        let init_loop = self.unique_label("vdp_init_loop");
+        let write_loop = self.unique_label("vdp_write_loop");
+        let fill_loop = self.unique_label("vdp_fill_loop");
+        let done = self.unique_label("vdp_done");
+        let not_esc = self.unique_label("vdp_not_esc");
+        let not_cr = self.unique_label("vdp_not_cr");
+        let not_lf = self.unique_label("vdp_not_lf");
+        let not_bs = self.unique_label("vdp_not_bs");
+        let not_ff = self.unique_label("vdp_not_ff");
+        let newline = self.unique_label("vdp_newline");
+        let scroll_loop = self.unique_label("vdp_scroll_loop");
+        let scroll_read = self.unique_label("vdp_scroll_read");
+        let scroll_write = self.unique_label("vdp_scroll_write");
+        let esc_char = self.unique_label("vdp_esc_char");
+        let csi = self.unique_label("vdp_csi");
+        let second = self.unique_label("vdp_second");
+        let not_digit = self.unique_label("vdp_not_digit");
+        let not_semi = self.unique_label("vdp_not_semi");
+        let not_h = self.unique_label("vdp_not_h");
+        let row_ok = self.unique_label("vdp_row_ok");
+        let col_ok = self.unique_label("vdp_col_ok");
+        let not_j = self.unique_label("vdp_not_j");
+        let esc_state = self.unique_label("vdp_esc_state");
+        let fill = self.unique_label("vdp_clear_fill");
+
+        self.label("vdp_init");
+        self.in_a(ctrl);            // Reset the control port byte latch
+        self.ld_hl_label("vdp_regs");
+        self.ld_e(0);
+        self.label(&init_loop);
+        self.ld_a_hl_ind();
+        self.call("vdp_set_reg");
+        self.inc_hl();
+        self.inc_e();
+        self.ld_a_e();
+        self.cp(8);
+        self.jr_nz(&init_loop);
+        self.jp("vdp_cls");
+
+        self.label("vdp_regs");
+        self.emit(&config.regs);
+
+        self.label("vdp_set_reg");
+        self.out_a(ctrl);
+        self.ld_a_e();
+        self.or_a(0x80);
+        self.out_a(ctrl);
+        self.ret();
+
+        self.label("vdp_write_addr");
+        self.ld_a_l();
+        self.out_a(ctrl);
+        self.ld_a_h();
+        self.and_a(0x3F);
+        self.or_a(0x40);
+        self.out_a(ctrl);
+        self.ret();
+
+        self.label("vdp_read_addr");
+        self.ld_a_l();
+        self.out_a(ctrl);
+        self.ld_a_h();
+        self.and_a(0x3F);
+        self.out_a(ctrl);
+        self.ret();
+
+        self.label("vdp_write");
+        self.call("vdp_write_addr");
+        self.label(&write_loop);
+        self.ld_a_de_ind();
+        self.out_a(data);
+        self.inc_de();
+        self.dec_bc();
+        self.ld_a_b();
+        self.or_c();
+        self.jr_nz(&write_loop);
+        self.ret();
+
+        self.label("vdp_fill");
+        self.ld_e_a();
+        self.call("vdp_write_addr");
+        self.label(&fill_loop);
+        self.ld_a_e();
+        self.out_a(data);
+        self.dec_bc();
+        self.ld_a_b();
+        self.or_c();
+        self.jr_nz(&fill_loop);
+        self.ret();
+
+        self.label("vdp_cls");
+        self.ld_hl(name);
+        self.ld_bc(screen);
+        self.ld_a(b' ');
+        self.call("vdp_fill");
+        self.xor_a();
+        self.ld_addr_a(row);
+        self.ld_addr_a(col);
+        self.ret();
+
+        // HL = name table address of the cursor; clobbers A, DE
+        self.label("vdp_cursor_addr");
+        self.ld_a_addr(row);
+        self.ld_l_a();
+        self.ld_h(0);
+        self.add_hl_hl();
+        self.add_hl_hl();
+        self.add_hl_hl();           // Row * 8
+        self.ld_d_h();
+        self.ld_e_l();
+        self.add_hl_hl();
+        self.add_hl_hl();           // Row * 32
+        self.add_hl_de();           // Row * 40
+        self.ld_a_addr(col);
+        self.ld_e_a();
+        self.ld_d(0);
+        self.add_hl_de();
+        self.ld_de(name);
+        self.add_hl_de();
+        self.ret();
+
+        self.label("vdp_putchar");
+        self.push_af();
+        self.push_bc();
+        self.push_de();
+        self.push_hl();
+        self.ld_c_a();
+        self.ld_a_addr(esc);
+        self.or_a_a();
+        self.jp_nz(&esc_char);
+        self.ld_a_c();
+        self.cp(0x1B);
+        self.jp_nz(&not_esc);
+        self.ld_a(1);
+        self.ld_addr_a(esc);
+        self.jp(&done);
+        self.label(&not_esc);
+        self.cp(0x0D);
+        self.jp_nz(&not_cr);
+        self.xor_a();
+        self.ld_addr_a(col);
+        self.jp(&done);
+        self.label(&not_cr);
+        self.cp(0x0A);
+        self.jp_nz(&not_lf);
+        self.call(&newline);
+        self.jp(&done);
+        self.label(&not_lf);
+        self.cp(0x08);
+        self.jp_nz(&not_bs);
+        self.ld_hl(col);
+        self.ld_a_hl_ind();
+        self.or_a_a();
+        self.jp_z(&done);
+        self.dec_hl_ind();
+        self.jp(&done);
+        self.label(&not_bs);
+        self.cp(0x0C);
+        self.jp_nz(&not_ff);
+        self.call("vdp_cls");
+        self.jp(&done);
+        self.label(&not_ff);
+        self.cp(b' ');
+        self.jp_c(&done);           // Other control characters
+        self.call("vdp_cursor_addr");
+        self.call("vdp_write_addr");
+        self.ld_a_c();
+        self.out_a(data);
+        self.ld_hl(col);
+        self.inc_hl_ind();
+        self.ld_a_hl_ind();
+        self.cp(COLS);
+        self.jp_c(&done);
+        self.ld_hl_ind_n(0);        // Wrap to the next line
+        self.call(&newline);
+        self.label(&done);
+        self.pop_hl();
+        self.pop_de();
+        self.pop_bc();
+        self.pop_af();
+        self.ret();
+
+        // Cursor down a row, scrolling at the bottom
+        self.label(&newline);
+        self.ld_a_addr(row);
+        self.inc_a();
+        self.cp(ROWS);
+        self.jp_nc("vdp_scroll");
+        self.ld_addr_a(row);
+        self.ret();
+
+        // Move rows 1-23 up through vdp_line, then blank the last row
+        self.label("vdp_scroll");
+        self.ld_hl(name + COLS as u16);
+        self.ld_b(ROWS - 1);
+        self.label(&scroll_loop);
+        self.push_bc();
+        self.push_hl();
+        self.call("vdp_read_addr");
+        self.ld_de(line);
+        self.ld_b(COLS);
+        self.label(&scroll_read);
+        self.in_a(data);
+        self.ld_de_ind_a();
+        self.inc_de();
+        self.djnz(&scroll_read);
+        self.pop_hl();
+        self.push_hl();
+        self.ld_de((COLS as u16).wrapping_neg());
+        self.add_hl_de();
+        self.call("vdp_write_addr");
+        self.ld_de(line);
+        self.ld_b(COLS);
+        self.label(&scroll_write);
+        self.ld_a_de_ind();
+        self.out_a(data);
+        self.inc_de();
+        self.djnz(&scroll_write);
+        self.pop_hl();
+        self.ld_de(COLS as u16);
+        self.add_hl_de();
+        self.pop_bc();
+        self.djnz(&scroll_loop);
+        self.ld_hl(name + screen - COLS as u16);
+        self.ld_bc(COLS as u16);
+        self.ld_a(b' ');
+        self.jp("vdp_fill");
+
+        // Escape sequences: A = state, C = character
+        self.label(&esc_char);
+        self.cp(2);
+        self.jp_z(&csi);
+        self.xor_a();               // After ESC: only ESC [ continues
+        self.ld_addr_a(parm);
+        self.ld_addr_a(parm + 1);
+        self.ld_addr_a(parm + 2);
+        self.ld_a_c();
+        self.cp(b'[');
+        self.ld_a(0);
+        self.jp_nz(&esc_state);
+        self.ld_a(2);
+        self.label(&esc_state);
+        self.ld_addr_a(esc);
+        self.jp(&done);
+
+        self.label(&csi);
+        self.ld_a_c();
+        self.cp(b'0');
+        self.jp_c(&not_digit);
+        self.cp(b'9' + 1);
+        self.jp_nc(&not_digit);
+        self.sub_a(b'0');
+        self.ld_b_a();
+        self.ld_hl(parm);
+        self.ld_a_addr(parm + 2);
+        self.or_a_a();
+        self.jp_z(&second);
+        self.inc_hl();
+        self.label(&second);
+        self.ld_a_hl_ind();         // Parameter * 10 + digit
+        self.add_a_a();
+        self.ld_e_a();
+        self.add_a_a();
+        self.add_a_a();
+        self.add_a_e();
+        self.add_a_b();
+        self.ld_hl_ind_a();
+        self.jp(&done);
+        self.label(&not_digit);
+        self.cp(b';');
+        self.jp_nz(&not_semi);
+        self.ld_a(1);
+        self.ld_addr_a(parm + 2);
+        self.jp(&done);
+        self.label(&not_semi);
+        self.cp(0x40);
+        self.jp_c(&done);           // Intermediate bytes such as '?'
+        self.xor_a();
+        self.ld_addr_a(esc);
+        self.ld_a_c();
+        self.cp(b'H');
+        self.jp_nz(&not_h);
+        self.ld_a_addr(parm);       // 1-based, 0 or missing = 1
+        self.sub_a(1);
+        self.adc_a(0);
+        self.cp(ROWS);
+        self.jp_c(&row_ok);
+        self.ld_a(ROWS - 1);
+        self.label(&row_ok);
+        self.ld_addr_a(row);
+        self.ld_a_addr(parm + 1);
+        self.sub_a(1);
+        self.adc_a(0);
+        self.cp(COLS);
+        self.jp_c(&col_ok);
+        self.ld_a(COLS - 1);
+        self.label(&col_ok);
+        self.ld_addr_a(col);
+        self.jp(&done);
+        self.label(&not_h);
+        self.cp(b'J');
+        self.jp_nz(&not_j);
+        self.ld_hl(name);           // ESC [ 2 J: whole screen
+        self.ld_bc(screen);
+        self.ld_a_addr(parm);
+        self.cp(2);
+        self.ld_a(b' ');
+        self.jp_z(&fill);
+        self.call("vdp_cursor_addr");   // ESC [ J: cursor to end
+        self.ex_de_hl();
+        self.ld_hl(name + screen);
+        self.or_a_a();
+        self.sbc_hl_de();
+        self.ld_b_h();
+        self.ld_c_l();
+        self.ex_de_hl();
+        self.ld_a(b' ');
+        self.label(&fill);
+        self.call("vdp_fill");
+        self.jp(&done);
+        self.label(&not_j);
+        self.cp(b'K');
+        self.jp_nz(&done);
+        self.call("vdp_cursor_addr");   // ESC [ K: cursor to end of line
+        self.ld_a_addr(col);
+        self.ld_b_a();
+        self.ld_a(COLS);
+        self.sub_b();
+        self.ld_c_a();
+        self.ld_b(0);
+        self.ld_a(b' ');
+        self.jp(&fill);
+    }
+
+    /// Emit code copying `len` bytes of ROM data at `label` to VRAM `vram`
+    ///
+    /// For fonts, patterns and name tables embedded with `label` + `emit`.
+    ///
+    /// Requires: `vdp_write`
+    pub fn vdp_load(&mut self, vram: u16, label: &str, len: u16) {
+        assert!(len > 0, "nothing to load");
+        self.ld_hl(vram);
+        self.ld_de_label(label);
+        self.ld_bc(len);
+        self.call("vdp_write");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tables() {
+        let config = VdpConfig::default();
+        assert_eq!(config.name_table(), 0x0800);
+        assert_eq!(config.pattern_table(), 0x0000);
+    }
+
+    #[test]
+    fn test_vdp_write_addr() {
+        let mut cg = CodeGen::new();
+        cg.emit_vdp(&VdpConfig::default());
+        let start = cg.get_label("vdp_write_addr").unwrap() as usize;
+        assert_eq!(&cg.rom()[start..start + 11], &[
+            0x7D,              // LD A, L
+            0xD3, 0x99,        // OUT (0x99), A
+            0x7C,              // LD A, H
+            0xE6, 0x3F,        // AND 0x3F
+            0xF6, 0x40,        // OR 0x40
+            0xD3, 0x99,        // OUT (0x99), A
+            0xC9,              // RET
+        ]);
+        let regs = cg.get_label("vdp_regs").unwrap() as usize;
+        assert_eq!(&cg.rom()[regs..regs + 8], &VDP_TEXT_MODE);
+    }
+
+    #[test]
+    fn test_vdp_load() {
+        let mut cg = CodeGen::new();
+        cg.vdp_load(0x0000, "font", 0x0800);
+        assert_eq!(cg.rom(), &[
+            0x21, 0x00, 0x00,  // LD HL, 0x0000
+            0x11, 0x00, 0x00,  // LD DE, font
+            0x01, 0x00, 0x08,  // LD BC, 0x0800
+            0xCD, 0x00, 0x00,  // CALL vdp_write
+        ]);
+    }
+}
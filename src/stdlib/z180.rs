@@ -0,0 +1,251 @@
+//! Z180 extended instructions and internal I/O (feature `z180`)
+//!
+//! Helpers for the instructions the Z180 adds to the Z80 set (MLT, IN0/OUT0,
+//! TST, TSTIO, SLP) and the on-chip I/O register map, plus serial routines
+//! on the internal ASCI that stand in for the MC6850 `getchar`/`putchar`.
+//!
+//! The helpers panic unless `RomConfig::cpu` is `CpuProfile::Z180`.
+//!
+//! Register constants are offsets from the internal I/O base, which is 0x00
+//! after reset and can be moved in steps of 0x40 through ICR.
+
+use crate::instructions::instructions;
+use crate::{CodeGen, CpuProfile};
+
+// ========== Internal I/O Registers ==========
+
+/// ASCI channel 0 control A
+pub const CNTLA0: u8 = 0x00;
+/// ASCI channel 1 control A
+pub const CNTLA1: u8 = 0x01;
+/// ASCI channel 0 control B (baud rate)
+pub const CNTLB0: u8 = 0x02;
+/// ASCI channel 1 control B (baud rate)
+pub const CNTLB1: u8 = 0x03;
+/// ASCI channel 0 status
+pub const STAT0: u8 = 0x04;
+/// ASCI channel 1 status
+pub const STAT1: u8 = 0x05;
+/// ASCI channel 0 transmit data
+pub const TDR0: u8 = 0x06;
+/// ASCI channel 1 transmit data
+pub const TDR1: u8 = 0x07;
+/// ASCI channel 0 receive data
+pub const RDR0: u8 = 0x08;
+/// ASCI channel 1 receive data
+pub const RDR1: u8 = 0x09;
+/// CSI/O control
+pub const CNTR: u8 = 0x0A;
+/// CSI/O transmit/receive data
+pub const TRDR: u8 = 0x0B;
+/// Timer 0 data, low byte
+pub const TMDR0L: u8 = 0x0C;
+/// Timer 0 data, high byte
+pub const TMDR0H: u8 = 0x0D;
+/// Timer 0 reload, low byte
+pub const RLDR0L: u8 = 0x0E;
+/// Timer 0 reload, high byte
+pub const RLDR0H: u8 = 0x0F;
+/// Timer control
+pub const TCR: u8 = 0x10;
+/// Timer 1 data, low byte
+pub const TMDR1L: u8 = 0x14;
+/// Timer 1 data, high byte
+pub const TMDR1H: u8 = 0x15;
+/// Timer 1 reload, low byte
+pub const RLDR1L: u8 = 0x16;
+/// Timer 1 reload, high byte
+pub const RLDR1H: u8 = 0x17;
+/// Free running counter
+pub const FRC: u8 = 0x18;
+/// Clock multiplier (Z8S180)
+pub const CMR: u8 = 0x1E;
+/// CPU control (Z8S180)
+pub const CCR: u8 = 0x1F;
+/// DMA status
+pub const DSTAT: u8 = 0x30;
+/// DMA mode
+pub const DMODE: u8 = 0x31;
+/// DMA/WAIT control
+pub const DCNTL: u8 = 0x32;
+/// Interrupt vector low
+pub const IL: u8 = 0x33;
+/// INT/TRAP control
+pub const ITC: u8 = 0x34;
+/// Refresh control
+pub const RCR: u8 = 0x36;
+/// MMU common base
+pub const CBR: u8 = 0x38;
+/// MMU bank base
+pub const BBR: u8 = 0x39;
+/// MMU common/bank area
+pub const CBAR: u8 = 0x3A;
+/// Operation mode control
+pub const OMCR: u8 = 0x3E;
+/// I/O control (internal I/O base); always at 0x3F after reset
+pub const ICR: u8 = 0x3F;
+
+/// ASCI status: receive data register full
+pub const STAT_RDRF: u8 = 0x80;
+/// ASCI status: transmit data register empty
+pub const STAT_TDRE: u8 = 0x02;
+
+instructions! {
+    static Z180_OPCODES for CpuProfile::Z180;
+
+    // ========== Z180 Extensions ==========
+
+    /// MLT BC (B * C -> BC)
+    mlt_bc() => "MLT BC", [0xED, 0x4C], 17;
+
+    /// MLT DE (D * E -> DE)
+    mlt_de() => "MLT DE", [0xED, 0x5C], 17;
+
+    /// MLT HL (H * L -> HL)
+    mlt_hl() => "MLT HL", [0xED, 0x6C], 17;
+
+    /// IN0 A, (n) - input from internal I/O page 0
+    in0_a(port: imm8) => "IN0 A,(n)", [0xED, 0x38], 12;
+
+    /// OUT0 (n), A - output to internal I/O page 0
+    out0_a(port: imm8) => "OUT0 (n),A", [0xED, 0x39], 13;
+
+    /// TST n (flags from A AND n, A unchanged)
+    tst(n: imm8) => "TST n", [0xED, 0x64], 9;
+
+    /// TST A
+    tst_a() => "TST A", [0xED, 0x3C], 7;
+
+    /// TST (HL)
+    tst_hl_ind() => "TST (HL)", [0xED, 0x34], 10;
+
+    /// TSTIO n (flags from (C) AND n on page 0)
+    tstio(n: imm8) => "TSTIO n", [0xED, 0x74], 12;
+
+    /// SLP - enter sleep mode until an interrupt
+    slp() => "SLP", [0xED, 0x76], 8;
+}
+
+/// Z180 internal ASCI configuration
+pub struct Z180AsciConfig {
+    /// Internal I/O base (0x00, 0x40, 0x80 or 0xC0)
+    pub io_base: u8,
+    /// ASCI channel (0 or 1)
+    pub channel: u8,
+    /// CNTLA value (default: RX and TX enabled, 8N1)
+    pub cntla: u8,
+    /// CNTLB value (baud rate divisor; depends on the CPU clock)
+    pub cntlb: u8,
+}
+
+impl Default for Z180AsciConfig {
+    fn default() -> Self {
+        Self {
+            io_base: 0x00,
+            channel: 0,
+            cntla: 0x64,
+            cntlb: 0x00,
+        }
+    }
+}
+
+impl CodeGen {
+    /// Emit Z180 ASCI serial routines with default configuration
+    ///
+    /// Labels created: `asci_init`, `getchar`, `putchar`
+    pub fn emit_z180_asci_routines(&mut self) {
+        self.emit_z180_asci_routines_config(&Z180AsciConfig::default());
+    }
+
+    /// Emit Z180 ASCI serial routines with custom configuration
+    ///
+    /// Use in place of the MC6850 `getchar`/`putchar`; `asci_init` must run
+    /// once at startup (it also moves the internal I/O base if configured).
+    /// Requires the `CpuProfile::Z180` profile.
+    pub fn emit_z180_asci_routines_config(&mut self, config: &Z180AsciConfig) {
+        assert!(config.io_base & 0x3F == 0, "io_base must be a multiple of 0x40");
+        assert!(config.channel < 2, "ASCI channel must be 0 or 1");
+        let base = config.io_base;
+        let ch = config.channel;
+
+        // asci_init: relocate internal I/O and program the channel
+        self.label("asci_init");
+        if base != 0 {
+            self.ld_a(base);
+            self.out0_a(ICR);
+        }
+        self.ld_a(config.cntla);
+        self.out0_a(base + CNTLA0 + ch);
+        self.ld_a(config.cntlb);
+        self.out0_a(base + CNTLB0 + ch);
+        self.ret();
+
+        // getchar: blocking read, char returned in A
+        self.label("getchar");
+        self.in0_a(base + STAT0 + ch);
+        self.and_a(STAT_RDRF);
+        self.jr_z("getchar");
+        self.in0_a(base + RDR0 + ch);
+        self.ret();
+
+        // putchar: blocking write, char in A
+        self.label("putchar");
+        self.push_af();
+        self.label("putchar_wait");
+        self.in0_a(base + STAT0 + ch);
+        self.and_a(STAT_TDRE);
+        self.jr_z("putchar_wait");
+        self.pop_af();
+        self.out0_a(base + TDR0 + ch);
+        self.ret();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RomConfig;
+
+    fn z180() -> CodeGen {
+        CodeGen::with_config(RomConfig {
+            cpu: CpuProfile::Z180,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_z180_instructions() {
+        let mut cg = z180();
+        cg.mlt_de();
+        cg.in0_a(STAT0);
+        cg.tst(0x80);
+        cg.slp();
+        assert_eq!(cg.rom(), &[
+            0xED, 0x5C,        // MLT DE
+            0xED, 0x38, 0x04,  // IN0 A, (STAT0)
+            0xED, 0x64, 0x80,  // TST 0x80
+            0xED, 0x76,        // SLP
+        ]);
+        assert_eq!(Z180_OPCODES.len(), 10);
+    }
+
+    #[test]
+    fn test_asci_routines() {
+        let mut cg = z180();
+        cg.emit_z180_asci_routines_config(&Z180AsciConfig {
+            io_base: 0xC0,
+            channel: 1,
+            ..Default::default()
+        });
+        assert!(cg.has_label("asci_init"));
+        assert!(cg.has_label("getchar"));
+        assert_eq!(&cg.rom()[..4], &[0x3E, 0xC0, 0xED, 0x39]); // LD A, 0xC0; OUT0
+    }
+
+    #[test]
+    #[should_panic(expected = "requires the Z180 CPU profile")]
+    fn test_rejected_on_z80() {
+        let mut cg = CodeGen::new();
+        cg.mlt_bc();
+    }
+}
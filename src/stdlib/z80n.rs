@@ -0,0 +1,145 @@
+//! ZX Spectrum Next Z80N extensions (feature `z80n`)
+//!
+//! The Z80N core adds nibble/bit manipulation, multiply, 16-bit immediate
+//! arithmetic, Next register access and accelerated block copies. The
+//! helpers panic unless `RomConfig::cpu` is `CpuProfile::Z80N`.
+
+use crate::instructions::instructions;
+use crate::{CodeGen, CpuProfile};
+
+instructions! {
+    static Z80N_OPCODES for CpuProfile::Z80N;
+
+    // ========== Z80N Extensions ==========
+
+    /// SWAPNIB (swap the nibbles of A)
+    swapnib() => "SWAPNIB", [0xED, 0x23], 8;
+
+    /// MIRROR A (reverse the bits of A)
+    mirror_a() => "MIRROR A", [0xED, 0x24], 8;
+
+    /// TEST n (flags from A AND n, A unchanged)
+    test(n: imm8) => "TEST n", [0xED, 0x27], 11;
+
+    /// BSLA DE, B (DE shifted left B places)
+    bsla_de_b() => "BSLA DE,B", [0xED, 0x28], 8;
+
+    /// BSRA DE, B (DE arithmetic shift right B places)
+    bsra_de_b() => "BSRA DE,B", [0xED, 0x29], 8;
+
+    /// BSRL DE, B (DE logical shift right B places)
+    bsrl_de_b() => "BSRL DE,B", [0xED, 0x2A], 8;
+
+    /// BSRF DE, B (DE shift right B places, filling with ones)
+    bsrf_de_b() => "BSRF DE,B", [0xED, 0x2B], 8;
+
+    /// BRLC DE, B (DE rotated left B places)
+    brlc_de_b() => "BRLC DE,B", [0xED, 0x2C], 8;
+
+    /// MUL D, E (D * E -> DE)
+    mul_d_e() => "MUL D,E", [0xED, 0x30], 8;
+
+    /// ADD HL, A
+    add_hl_a() => "ADD HL,A", [0xED, 0x31], 8;
+
+    /// ADD DE, A
+    add_de_a() => "ADD DE,A", [0xED, 0x32], 8;
+
+    /// ADD BC, A
+    add_bc_a() => "ADD BC,A", [0xED, 0x33], 8;
+
+    /// ADD HL, nn
+    add_hl_nn(nn: imm16) => "ADD HL,nn", [0xED, 0x34], 16;
+
+    /// ADD DE, nn
+    add_de_nn(nn: imm16) => "ADD DE,nn", [0xED, 0x35], 16;
+
+    /// ADD BC, nn
+    add_bc_nn(nn: imm16) => "ADD BC,nn", [0xED, 0x36], 16;
+
+    /// OUTINB (OUT (C), (HL); INC HL, B unchanged)
+    outinb() => "OUTINB", [0xED, 0x90], 16;
+
+    /// NEXTREG n, A
+    nextreg_a(reg: imm8) => "NEXTREG n,A", [0xED, 0x92], 17;
+
+    /// PIXELDN (HL = screen address one pixel row down)
+    pixeldn() => "PIXELDN", [0xED, 0x93], 8;
+
+    /// PIXELAD (HL = screen address of pixel D = y, E = x)
+    pixelad() => "PIXELAD", [0xED, 0x94], 8;
+
+    /// SETAE (A = pixel mask for x in E)
+    setae() => "SETAE", [0xED, 0x95], 8;
+
+    /// JP (C) (jump within the 16K block, to IN (C) * 64)
+    jp_c_ind() => "JP (C)", [0xED, 0x98], 13;
+
+    /// LDIX (LDI, skipping bytes equal to A)
+    ldix() => "LDIX", [0xED, 0xA4], 16;
+
+    /// LDWS (copy (HL) to (DE), INC L, INC D)
+    ldws() => "LDWS", [0xED, 0xA5], 14;
+
+    /// LDDX (LDD, skipping bytes equal to A)
+    lddx() => "LDDX", [0xED, 0xAC], 16;
+
+    /// LDIRX (LDIR, skipping bytes equal to A)
+    ldirx() => "LDIRX", [0xED, 0xB4], 21/16;
+
+    /// LDPIRX (pattern fill from the 8-byte aligned block at HL)
+    ldpirx() => "LDPIRX", [0xED, 0xB7], 21/16;
+
+    /// LDDRX (LDDR, skipping bytes equal to A)
+    lddrx() => "LDDRX", [0xED, 0xBC], 21/16;
+}
+
+impl CodeGen {
+    /// NEXTREG reg, value
+    ///
+    /// Not in [`Z80N_OPCODES`]: the table has no two-operand encoding.
+    pub fn nextreg(&mut self, reg: u8, value: u8) {
+        self.require_cpu(CpuProfile::Z80N, "NEXTREG n,n");
+        self.emit(&[0xED, 0x91, reg, value]);
+    }
+
+    /// PUSH nn
+    ///
+    /// Not in [`Z80N_OPCODES`]: the operand is stored big-endian.
+    pub fn push_nn(&mut self, nn: u16) {
+        self.require_cpu(CpuProfile::Z80N, "PUSH nn");
+        self.emit(&[0xED, 0x8A]);
+        self.emit(&nn.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RomConfig;
+
+    #[test]
+    fn test_z80n_instructions() {
+        let mut cg = CodeGen::with_config(RomConfig {
+            cpu: CpuProfile::Z80N,
+            ..Default::default()
+        });
+        cg.mul_d_e();
+        cg.add_hl_nn(0x1234);
+        cg.nextreg(0x07, 0x03);
+        cg.push_nn(0xABCD);
+        assert_eq!(cg.rom(), &[
+            0xED, 0x30,              // MUL D, E
+            0xED, 0x34, 0x34, 0x12,  // ADD HL, 0x1234
+            0xED, 0x91, 0x07, 0x03,  // NEXTREG 7, 3
+            0xED, 0x8A, 0xAB, 0xCD,  // PUSH 0xABCD (big-endian)
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "requires the Z80N CPU profile")]
+    fn test_rejected_on_z80() {
+        let mut cg = CodeGen::new();
+        cg.swapnib();
+    }
+}
@@ -0,0 +1,175 @@
+//! Golden-master snapshots of ROM builds
+//!
+//! `assert_rom_matches_snapshot` compares a resolved ROM against a
+//! checked-in text file holding its bytes as a hex dump and its symbol
+//! table, and panics with a row-by-row diff if anything changed. A missing
+//! snapshot is written on the first run; set `UPDATE_SNAPSHOTS=1` to
+//! accept a deliberate change.
+//!
+//! ```rust,no_run
+//! use retroshield_z80_workbench::prelude::*;
+//! use retroshield_z80_workbench::testing::assert_rom_matches_snapshot;
+//!
+//! let mut rom = CodeGen::new();
+//! rom.emit_io_routines();
+//! rom.resolve_fixups();
+//! assert_rom_matches_snapshot(&rom, "io_routines");  // tests/snapshots/io_routines.snap
+//! ```
+
+use crate::CodeGen;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Differing rows shown before the diff is cut short
+const MAX_DIFF_LINES: usize = 40;
+
+/// Snapshot text for `rom`: a header, 16 bytes per row, then the labels
+/// sorted by address
+pub fn snapshot_text(rom: &CodeGen) -> String {
+    let org = rom.config().org;
+    let mut out = format!("# org {:04X}, {} bytes\n", org, rom.size());
+    for (i, row) in rom.rom().chunks(16).enumerate() {
+        let bytes: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+        out.push_str(&format!("{:04X}: {}\n", org.wrapping_add(i as u16 * 16), bytes.join(" ")));
+    }
+    let mut labels: Vec<(u16, &str)> = rom.labels().map(|(name, addr)| (addr, name)).collect();
+    labels.sort();
+    out.push_str("# symbols\n");
+    for (addr, name) in labels {
+        out.push_str(&format!("{:04X} {}\n", addr, name));
+    }
+    out
+}
+
+/// Rows of `expected` and `actual` that differ, as `-`/`+` pairs
+///
+/// Hex rows are matched by address; symbol lines by content. Returns an
+/// empty string if the snapshots agree.
+pub fn snapshot_diff(expected: &str, actual: &str) -> String {
+    let split = |s: &str| -> (Vec<String>, Vec<String>) {
+        let mut lines = s.lines().filter(|l| !l.starts_with('#'));
+        let rows = lines.by_ref().take_while(|l| l.contains(':')).map(str::to_string).collect();
+        (rows, s.lines().skip_while(|l| *l != "# symbols").skip(1).map(str::to_string).collect())
+    };
+    let (old_rows, old_syms) = split(expected);
+    let (new_rows, new_syms) = split(actual);
+    let mut diff = Vec::new();
+    for i in 0..old_rows.len().max(new_rows.len()) {
+        let (old, new) = (old_rows.get(i), new_rows.get(i));
+        if old != new {
+            if let Some(old) = old {
+                diff.push(format!("- {}", old));
+            }
+            if let Some(new) = new {
+                diff.push(format!("+ {}", new));
+            }
+        }
+    }
+    for old in old_syms.iter().filter(|l| !new_syms.contains(l)) {
+        diff.push(format!("- {}", old));
+    }
+    for new in new_syms.iter().filter(|l| !old_syms.contains(l)) {
+        diff.push(format!("+ {}", new));
+    }
+    let header = |s: &str| s.lines().next().unwrap_or("").to_string();
+    if header(expected) != header(actual) {
+        diff.insert(0, format!("+ {}", header(actual)));
+        diff.insert(0, format!("- {}", header(expected)));
+    }
+    if diff.len() > MAX_DIFF_LINES {
+        let more = diff.len() - MAX_DIFF_LINES;
+        diff.truncate(MAX_DIFF_LINES);
+        diff.push(format!("... {} more lines", more));
+    }
+    diff.iter().map(|l| format!("{}\n", l)).collect()
+}
+
+/// Compare `rom` with `<dir>/<name>.snap`, writing the file if it is
+/// missing or `update` is set
+///
+/// Returns the diff as the error on a mismatch.
+pub fn check_snapshot(rom: &CodeGen, dir: &Path, name: &str, update: bool) -> Result<(), String> {
+    let path = dir.join(format!("{}.snap", name));
+    let actual = snapshot_text(rom);
+    match fs::read_to_string(&path) {
+        Ok(expected) if !update => {
+            let diff = snapshot_diff(&expected, &actual);
+            if diff.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("{} does not match the ROM:\n{}", path.display(), diff))
+            }
+        }
+        _ => {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+            fs::write(&path, actual).map_err(|e| format!("{}: {}", path.display(), e))
+        }
+    }
+}
+
+/// Directory snapshots live in: `tests/snapshots` of the crate under test
+fn snapshot_dir() -> PathBuf {
+    let root = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+    root.join("tests").join("snapshots")
+}
+
+/// Panic unless `rom` matches the snapshot `tests/snapshots/<name>.snap`
+///
+/// The snapshot is written if it doesn't exist yet, or rewritten when the
+/// `UPDATE_SNAPSHOTS` environment variable is set to anything but `0`.
+pub fn assert_rom_matches_snapshot(rom: &CodeGen, name: &str) {
+    let update = std::env::var("UPDATE_SNAPSHOTS").is_ok_and(|v| v != "0");
+    if let Err(e) = check_snapshot(rom, &snapshot_dir(), name, update) {
+        panic!("{}\n(rerun with UPDATE_SNAPSHOTS=1 to accept the change)", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(byte: u8) -> CodeGen {
+        let mut rom = CodeGen::new();
+        rom.label("start");
+        rom.emit(&[0x00; 17]);
+        rom.label("end");
+        rom.emit(&[byte]);
+        rom
+    }
+
+    #[test]
+    fn test_snapshot_text() {
+        assert_eq!(
+            snapshot_text(&rom(0x76)),
+            "# org 0000, 18 bytes\n\
+             0000: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00\n\
+             0010: 00 76\n\
+             # symbols\n\
+             0000 start\n\
+             0011 end\n"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let old = snapshot_text(&rom(0x76));
+        assert_eq!(snapshot_diff(&old, &old), "");
+        let mut new = rom(0xC9);
+        new.label("extra");
+        assert_eq!(
+            snapshot_diff(&old, &snapshot_text(&new)),
+            "- 0010: 00 76\n+ 0010: 00 C9\n+ 0012 extra\n"
+        );
+    }
+
+    #[test]
+    fn test_check_snapshot() {
+        let dir = std::env::temp_dir().join(format!("z80wb-snap-{}", std::process::id()));
+        assert_eq!(check_snapshot(&rom(0x76), &dir, "t", false), Ok(()));  // Written
+        assert_eq!(check_snapshot(&rom(0x76), &dir, "t", false), Ok(()));
+        assert!(check_snapshot(&rom(0xC9), &dir, "t", false).unwrap_err().contains("+ 0010: 00 C9"));
+        assert_eq!(check_snapshot(&rom(0xC9), &dir, "t", true), Ok(()));
+        assert_eq!(check_snapshot(&rom(0xC9), &dir, "t", false), Ok(()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+//! T-state counts for emitted code
+//!
+//! Sums the opcode table's T-state counts over a stretch of ROM, so delay
+//! loops and bit-banged timing can be checked against the clock without
+//! hand-counting. Counts are straight-line: each instruction is counted
+//! once, with conditional branches giving the range (not taken / taken).
+//!
+//! ```rust
+//! use retroshield_z80_workbench::prelude::*;
+//!
+//! let mut rom = CodeGen::new();
+//! rom.ld_b(100);
+//! rom.label("delay");
+//! rom.nop();
+//! rom.djnz("delay");
+//! rom.label("delay_end");
+//! rom.resolve_fixups();
+//!
+//! // One pass of the loop body: NOP + DJNZ
+//! let pass = rom.cycles_between("delay", "delay_end").unwrap();
+//! assert_eq!((pass.min, pass.max), (12, 17));
+//! // 100 passes, the last one falling through
+//! assert_eq!(7 + 99 * pass.max + pass.min, 1702);
+//! ```
+
+use std::fmt;
+
+use crate::opcodes::decode;
+use crate::CodeGen;
+
+/// T-state total for a range of instructions
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CycleCount {
+    /// T-states with every conditional branch not taken
+    pub min: u32,
+    /// T-states with every conditional branch taken
+    pub max: u32,
+    /// Instructions counted
+    pub instructions: usize,
+    /// Addresses of bytes not in the opcode table (data, or raw `emit`
+    /// opcodes without a helper), each skipped as one byte
+    pub unknown: Vec<u16>,
+}
+
+impl CycleCount {
+    /// Duration of `cycles` T-states in microseconds at `clock_hz`
+    pub fn micros(cycles: u32, clock_hz: u32) -> f64 {
+        cycles as f64 * 1_000_000.0 / clock_hz as f64
+    }
+}
+
+/// Timing of one routine in a [`TimingReport`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoutineTiming {
+    /// Label the routine starts at
+    pub name: String,
+    /// Start address
+    pub addr: u16,
+    /// Size in bytes, up to the next routine label
+    pub size: u16,
+    /// Straight-line T-states of the routine body
+    pub cycles: CycleCount,
+}
+
+/// Result of [`CodeGen::timing_report`]
+#[derive(Clone, Debug, Default)]
+pub struct TimingReport {
+    /// Clock used for the microsecond column
+    pub clock_hz: u32,
+    /// Every routine label in the ROM, by address
+    pub routines: Vec<RoutineTiming>,
+}
+
+impl fmt::Display for TimingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Addr  Routine                 Bytes    T-states      us @ {} Hz", self.clock_hz)?;
+        for r in &self.routines {
+            let c = &r.cycles;
+            let t = if c.min == c.max {
+                c.min.to_string()
+            } else {
+                format!("{}-{}", c.min, c.max)
+            };
+            write!(
+                f,
+                "{:04X}  {:<22} {:>6} {:>11} {:>11.1}",
+                r.addr,
+                r.name,
+                r.size,
+                t,
+                CycleCount::micros(c.max, self.clock_hz)
+            )?;
+            if !c.unknown.is_empty() {
+                write!(f, "  ({} unknown bytes)", c.unknown.len())?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl CodeGen {
+    /// Count the T-states of the code from `addr` up to (not including) `end`
+    fn cycles_in(&self, addr: u16, end: u16) -> CycleCount {
+        let org = self.config().org;
+        let start = addr.wrapping_sub(org) as usize;
+        let end = (end.wrapping_sub(org) as usize).min(self.rom().len());
+        let mut count = CycleCount::default();
+        let mut pos = start;
+        while pos < end {
+            match decode(&self.rom()[pos..]) {
+                Some(d) => {
+                    let taken = d.opcode.cycles as u32;
+                    let not_taken = d.opcode.cycles_not_taken.map_or(taken, |t| t as u32);
+                    count.min += not_taken.min(taken);
+                    count.max += not_taken.max(taken);
+                    count.instructions += 1;
+                    pos += d.size();
+                }
+                None => {
+                    count.unknown.push(org.wrapping_add(pos as u16));
+                    pos += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// T-states of the straight-line code from label `from` up to label `to`
+    ///
+    /// Returns `None` if either label is undefined or `to` comes before
+    /// `from`.
+    pub fn cycles_between(&self, from: &str, to: &str) -> Option<CycleCount> {
+        let from = self.get_label(from)?;
+        let to = self.get_label(to)?;
+        if to < from {
+            return None;
+        }
+        Some(self.cycles_in(from, to))
+    }
+
+    /// Per-routine timing of every label in the ROM
+    ///
+    /// Each label (except generated `_name_N` labels) starts a routine that
+    /// runs to the next one. Data labels show up with unknown bytes.
+    pub fn timing_report(&self) -> TimingReport {
+        let org = self.config().org;
+        let end = org.wrapping_add(self.rom().len() as u16);
+        let mut labels: Vec<(u16, &str)> = self
+            .labels()
+            .filter(|(name, addr)| !name.starts_with('_') && *addr >= org && *addr < end)
+            .map(|(name, addr)| (addr, name))
+            .collect();
+        labels.sort_unstable();
+
+        let mut report = TimingReport {
+            clock_hz: self.config().clock_hz,
+            routines: Vec::new(),
+        };
+        for (i, &(addr, name)) in labels.iter().enumerate() {
+            let next = labels[i + 1..]
+                .iter()
+                .map(|&(a, _)| a)
+                .find(|&a| a > addr)
+                .unwrap_or(end);
+            report.routines.push(RoutineTiming {
+                name: name.to_string(),
+                addr,
+                size: next - addr,
+                cycles: self.cycles_in(addr, next),
+            });
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycles_between() {
+        let mut rom = CodeGen::new();
+        rom.label("start");
+        rom.ld_a(1);                 // 7
+        rom.or_a_a();                // 4
+        rom.ret_z();                 // 11/5
+        rom.call("start");           // 17
+        rom.label("end");
+        rom.resolve_fixups();
+        let c = rom.cycles_between("start", "end").unwrap();
+        assert_eq!((c.min, c.max, c.instructions), (33, 39, 4));
+        assert!(c.unknown.is_empty());
+        assert_eq!(rom.cycles_between("end", "start"), None);
+        assert_eq!(rom.cycles_between("start", "missing"), None);
+    }
+
+    #[test]
+    fn test_timing_report() {
+        let mut rom = CodeGen::new();
+        rom.label("one");
+        rom.nop();
+        rom.ret();
+        rom.label("msg");
+        rom.emit_byte(0xED);         // Not an instruction start
+        rom.label("two");
+        rom.label("two_alias");
+        let back = rom.unique_label("back");
+        rom.label(&back);
+        rom.jr(&back);
+        rom.ret();
+        let report = rom.timing_report();
+        let rows: Vec<_> = report
+            .routines
+            .iter()
+            .map(|r| (r.name.as_str(), r.addr, r.size, r.cycles.max, r.cycles.unknown.len()))
+            .collect();
+        assert_eq!(rows, [
+            ("one", 0x0000, 2, 14, 0),
+            ("msg", 0x0002, 1, 0, 1),
+            ("two", 0x0003, 3, 22, 0),
+            ("two_alias", 0x0003, 3, 22, 0),
+        ]);
+        assert!(report.to_string().contains("two_alias"));
+    }
+}
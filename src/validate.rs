@@ -0,0 +1,177 @@
+//! Build problems collected into one report
+//!
+//! `resolve_fixups` panics at the first undefined label or unreachable
+//! relative jump. `validate` finds all of them at once, along with labels
+//! that were defined twice - typically your own routine and the stdlib's
+//! under the same name, where the later definition silently wins.
+//!
+//! ```rust
+//! use retroshield_z80_workbench::prelude::*;
+//!
+//! let mut rom = CodeGen::new();
+//! rom.call("print_strng");
+//! rom.jr("far_away");
+//! rom.emit(&[0; 200]);
+//! rom.label("far_away");
+//! let report = rom.validate();
+//! assert!(!report.is_ok());
+//! print!("{}", report);
+//! ```
+
+use std::fmt;
+
+use crate::codegen::FixupKind;
+use crate::stdlib::ROUTINES;
+use crate::CodeGen;
+
+/// A reference to a label nothing defines
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UndefinedRef {
+    pub label: String,
+    /// Address of the reference's operand
+    pub addr: u16,
+}
+
+/// A relative jump whose target is more than 128 bytes away
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeError {
+    pub label: String,
+    /// Address of the displacement byte
+    pub addr: u16,
+    /// Distance from the end of the instruction to the target
+    pub distance: i32,
+}
+
+/// A label defined a second time at another address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redefinition {
+    pub label: String,
+    pub first: u16,
+    /// The address the label now resolves to
+    pub second: u16,
+    /// The name is one of the stdlib routines (`stdlib::ROUTINES`)
+    pub stdlib: bool,
+}
+
+/// Everything `validate` found wrong with a build
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub undefined: Vec<UndefinedRef>,
+    pub out_of_range: Vec<RangeError>,
+    pub redefined: Vec<Redefinition>,
+}
+
+impl ValidationReport {
+    /// No problems found
+    pub fn is_ok(&self) -> bool {
+        self.undefined.is_empty() && self.out_of_range.is_empty() && self.redefined.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return writeln!(f, "No problems found");
+        }
+        for u in &self.undefined {
+            writeln!(f, "{:04X}: undefined label {}", u.addr, u.label)?;
+        }
+        for r in &self.out_of_range {
+            writeln!(f, "{:04X}: relative jump to {} out of range ({} bytes)", r.addr, r.label, r.distance)?;
+        }
+        for r in &self.redefined {
+            let what = if r.stdlib { "stdlib label" } else { "label" };
+            writeln!(f, "{:04X}: {} {} redefined (first at {:04X})", r.second, what, r.label, r.first)?;
+        }
+        Ok(())
+    }
+}
+
+impl CodeGen {
+    /// Check the build without panicking
+    ///
+    /// Places the generated tail of the ROM first, as `resolve_fixups`
+    /// does, so it can be called at any point after all code is emitted.
+    pub fn validate(&mut self) -> ValidationReport {
+        self.finalize();
+        let org = self.config().org;
+        let mut report = ValidationReport::default();
+
+        for (offset, name, delta, kind) in self.fixups() {
+            let addr = org.wrapping_add(*offset as u16);
+            match self.get_label(name) {
+                None => report.undefined.push(UndefinedRef { label: name.clone(), addr }),
+                Some(target) if *kind == FixupKind::Rel => {
+                    let distance = target as i32 + delta - (addr as i32 + 1);
+                    if !(-128..=127).contains(&distance) {
+                        report.out_of_range.push(RangeError { label: name.clone(), addr, distance });
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        for (offset, name, distance) in self.rel_errors() {
+            report.out_of_range.push(RangeError {
+                label: name.clone(),
+                addr: org.wrapping_add(*offset as u16),
+                distance: *distance,
+            });
+        }
+        report.out_of_range.sort_by_key(|r| r.addr);
+        for (name, first, second) in self.redefined_labels() {
+            report.redefined.push(Redefinition {
+                label: name.clone(),
+                first: *first,
+                second: *second,
+                stdlib: ROUTINES.iter().any(|(label, _)| label == name),
+            });
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_clean() {
+        let mut rom = CodeGen::new();
+        rom.call("newline");
+        rom.jr("done");
+        rom.label("done");
+        rom.halt();
+        rom.include_stdlib();
+        let report = rom.validate();
+        assert_eq!(report, ValidationReport::default());
+        assert_eq!(report.to_string(), "No problems found\n");
+    }
+
+    #[test]
+    fn test_validate_problems() {
+        let mut rom = CodeGen::new();
+        rom.label("putchar");
+        rom.call("missing");           // 0x0001
+        rom.jr("far");                 // 0x0004
+        rom.emit(&[0; 200]);
+        rom.label("far");
+        rom.djnz("putchar");           // 0x00CE
+        rom.label("putchar");
+        let report = rom.validate();
+        assert_eq!(report.undefined, vec![UndefinedRef { label: "missing".to_string(), addr: 0x0001 }]);
+        assert_eq!(report.out_of_range, vec![
+            RangeError { label: "far".to_string(), addr: 0x0004, distance: 200 },
+            RangeError { label: "putchar".to_string(), addr: 0x00CE, distance: -207 },
+        ]);
+        assert_eq!(report.redefined, vec![
+            Redefinition { label: "putchar".to_string(), first: 0, second: 0x00CF, stdlib: true },
+        ]);
+        assert_eq!(
+            report.to_string(),
+            "0001: undefined label missing\n\
+             0004: relative jump to far out of range (200 bytes)\n\
+             00CE: relative jump to putchar out of range (-207 bytes)\n\
+             00CF: stdlib label putchar redefined (first at 0000)\n"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Z80 instruction syntax shared by retroshield-z80-workbench and its
+//! `z80!` macro
+//!
+//! The workbench's text assembler and the macro's compile-time check both
+//! match statements against these syntax strings with the same rules, so
+//! anything the macro accepts `emit_asm` assembles.
+
+mod tables;
+
+pub use tables::{R800, UNDOCUMENTED, Z180, Z80, Z80N};
+
+/// Register and condition names, which can't be used as symbols
+pub const RESERVED: &[&str] = &[
+    "A", "B", "C", "D", "E", "H", "L", "I", "R", "AF", "AF'", "BC", "DE", "HL", "SP", "IX", "IY",
+    "IXH", "IXL", "IYH", "IYL", "NZ", "Z", "NC", "PO", "PE", "P", "M",
+];
+
+/// Assembler directives, which are not in the opcode tables
+pub const DIRECTIVES: &[&str] = &["ORG", "DB", "DEFB", "DEFM", "DW", "DEFW", "DS", "DEFS", "END", "EQU"];
+
+/// Mnemonic of a syntax string, e.g. `"LD"` for `"LD A,n"`
+pub fn mnemonic(syntax: &str) -> &str {
+    syntax.split(' ').next().unwrap_or("")
+}
+
+/// Check that `text` reads as an expression rather than a register
+pub fn is_expr(text: &str) -> bool {
+    let text = text.trim();
+    !text.is_empty()
+        && !text.starts_with('(')
+        && text
+            .split(['+', '-'])
+            .all(|term| !RESERVED.contains(&term.trim().to_ascii_uppercase().as_str()))
+}
+
+/// Match one operand against its syntax pattern
+///
+/// Returns `None` if it doesn't match, `Some(None)` for a fixed operand,
+/// and `Some(Some(expr))` for a placeholder.
+pub fn match_operand(pattern: &str, input: &str) -> Option<Option<String>> {
+    let compact: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+    match pattern {
+        "n" | "nn" | "e" | "b" => is_expr(input).then(|| Some(input.trim().to_string())),
+        "(n)" | "(nn)" => {
+            let inner = compact.strip_prefix('(')?.strip_suffix(')')?;
+            is_expr(inner).then(|| Some(inner.to_string()))
+        }
+        "(IX+d)" | "(IY+d)" => {
+            let inner = compact.strip_prefix('(')?.strip_suffix(')')?;
+            let (reg, disp) = inner.split_at(inner.len().min(2));
+            if !reg.eq_ignore_ascii_case(&pattern[1..3]) {
+                return None;
+            }
+            match disp.chars().next() {
+                None => Some(Some("0".to_string())),
+                Some('+') => Some(Some(disp[1..].to_string())),
+                Some('-') => Some(Some(disp.to_string())),
+                Some(_) => None,
+            }
+        }
+        _ => compact.eq_ignore_ascii_case(pattern).then_some(None),
+    }
+}
+
+/// Match the operands of a statement against a syntax string with the
+/// same mnemonic
+///
+/// Returns the placeholder expressions in order, or `None` if the operands
+/// don't fit.
+pub fn match_operands(syntax: &str, operands: &[&str]) -> Option<Vec<String>> {
+    let pattern: Vec<&str> = match syntax.split_once(' ') {
+        Some((_, pattern)) => pattern.split(',').collect(),
+        None => Vec::new(),
+    };
+    if pattern.len() != operands.len() {
+        return None;
+    }
+    pattern
+        .iter()
+        .zip(operands)
+        .map(|(p, input)| match_operand(p, input))
+        .collect::<Option<Vec<_>>>()
+        .map(|caps| caps.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_operands() {
+        assert_eq!(match_operands("LD A,n", &["a", "count + 1"]), Some(vec!["count + 1".to_string()]));
+        assert_eq!(match_operands("LD (IX+d),n", &["( ix - 2 )", "0"]), Some(vec!["-2".to_string(), "0".to_string()]));
+        assert_eq!(match_operands("LD A,(IX+d)", &["a", "(ix)"]), Some(vec!["0".to_string()]));
+        assert_eq!(match_operands("EX AF,AF'", &["af", "af'"]), Some(vec![]));
+        assert_eq!(match_operands("LD A,n", &["a", "hl"]), None);
+        assert_eq!(match_operands("LD A,n", &["a", "(5)"]), None);
+        assert_eq!(match_operands("NOP", &["a"]), None);
+    }
+}
@@ -0,0 +1,632 @@
+//! Syntax strings of the workbench's opcode tables, in table order
+//!
+//! Kept in step with the `instructions!` tables by the workbench test
+//! `opcodes::tests::test_syntax_tables`, which prints the new list when they
+//! differ.
+
+/// Base instruction set (`OPCODES`)
+pub const Z80: &[&str] = &[
+    "LD A,n",
+    "LD B,n",
+    "LD C,n",
+    "LD D,n",
+    "LD E,n",
+    "LD H,n",
+    "LD L,n",
+    "LD B,B",
+    "LD B,C",
+    "LD B,D",
+    "LD B,E",
+    "LD B,H",
+    "LD B,L",
+    "LD B,(HL)",
+    "LD B,A",
+    "LD C,B",
+    "LD C,C",
+    "LD C,D",
+    "LD C,E",
+    "LD C,H",
+    "LD C,L",
+    "LD C,(HL)",
+    "LD C,A",
+    "LD D,B",
+    "LD D,C",
+    "LD D,D",
+    "LD D,E",
+    "LD D,H",
+    "LD D,L",
+    "LD D,(HL)",
+    "LD D,A",
+    "LD E,B",
+    "LD E,C",
+    "LD E,D",
+    "LD E,E",
+    "LD E,H",
+    "LD E,L",
+    "LD E,(HL)",
+    "LD E,A",
+    "LD H,B",
+    "LD H,C",
+    "LD H,D",
+    "LD H,E",
+    "LD H,H",
+    "LD H,L",
+    "LD H,(HL)",
+    "LD H,A",
+    "LD L,B",
+    "LD L,C",
+    "LD L,D",
+    "LD L,E",
+    "LD L,H",
+    "LD L,L",
+    "LD L,(HL)",
+    "LD L,A",
+    "LD (HL),B",
+    "LD (HL),C",
+    "LD (HL),D",
+    "LD (HL),E",
+    "LD (HL),H",
+    "LD (HL),L",
+    "LD (HL),A",
+    "LD A,B",
+    "LD A,C",
+    "LD A,D",
+    "LD A,E",
+    "LD A,H",
+    "LD A,L",
+    "LD A,(HL)",
+    "LD A,A",
+    "LD A,(nn)",
+    "LD (nn),A",
+    "LD A,(BC)",
+    "LD A,(DE)",
+    "LD (BC),A",
+    "LD (DE),A",
+    "LD (HL),n",
+    "LD BC,nn",
+    "LD DE,nn",
+    "LD HL,nn",
+    "LD SP,nn",
+    "LD HL,(nn)",
+    "LD (nn),HL",
+    "LD DE,(nn)",
+    "LD (nn),DE",
+    "LD BC,(nn)",
+    "LD (nn),BC",
+    "LD SP,(nn)",
+    "LD (nn),SP",
+    "LD SP,HL",
+    "PUSH AF",
+    "PUSH BC",
+    "PUSH DE",
+    "PUSH HL",
+    "POP AF",
+    "POP BC",
+    "POP DE",
+    "POP HL",
+    "EX DE,HL",
+    "EX AF,AF'",
+    "EXX",
+    "EX (SP),HL",
+    "ADD A,n",
+    "ADD A,B",
+    "ADD A,C",
+    "ADD A,D",
+    "ADD A,E",
+    "ADD A,H",
+    "ADD A,L",
+    "ADD A,(HL)",
+    "ADD A,A",
+    "ADC A,n",
+    "ADC A,B",
+    "ADC A,C",
+    "ADC A,D",
+    "ADC A,E",
+    "ADC A,H",
+    "ADC A,L",
+    "ADC A,(HL)",
+    "ADC A,A",
+    "SUB n",
+    "SUB B",
+    "SUB C",
+    "SUB D",
+    "SUB E",
+    "SUB H",
+    "SUB L",
+    "SUB (HL)",
+    "SUB A",
+    "SBC A,n",
+    "SBC A,B",
+    "SBC A,C",
+    "SBC A,D",
+    "SBC A,E",
+    "SBC A,H",
+    "SBC A,L",
+    "SBC A,(HL)",
+    "SBC A,A",
+    "DAA",
+    "NEG",
+    "INC A",
+    "INC B",
+    "INC C",
+    "DEC A",
+    "DEC B",
+    "DEC C",
+    "INC D",
+    "INC E",
+    "INC H",
+    "INC L",
+    "INC (HL)",
+    "DEC D",
+    "DEC E",
+    "DEC H",
+    "DEC L",
+    "DEC (HL)",
+    "INC HL",
+    "INC DE",
+    "INC BC",
+    "DEC HL",
+    "DEC DE",
+    "DEC BC",
+    "INC SP",
+    "DEC SP",
+    "ADD HL,BC",
+    "ADD HL,DE",
+    "ADD HL,HL",
+    "ADD HL,SP",
+    "ADC HL,BC",
+    "ADC HL,DE",
+    "ADC HL,HL",
+    "ADC HL,SP",
+    "SBC HL,DE",
+    "SBC HL,BC",
+    "SBC HL,HL",
+    "SBC HL,SP",
+    "AND n",
+    "OR n",
+    "OR A",
+    "OR B",
+    "OR C",
+    "OR L",
+    "AND B",
+    "AND C",
+    "AND D",
+    "AND E",
+    "AND H",
+    "AND L",
+    "AND (HL)",
+    "AND A",
+    "XOR B",
+    "XOR C",
+    "XOR D",
+    "XOR E",
+    "XOR H",
+    "XOR L",
+    "XOR (HL)",
+    "OR D",
+    "OR E",
+    "OR H",
+    "OR (HL)",
+    "XOR A",
+    "XOR n",
+    "CP n",
+    "CP B",
+    "CP C",
+    "CP D",
+    "CP E",
+    "CP H",
+    "CP L",
+    "CP (HL)",
+    "CP A",
+    "CPL",
+    "JP nn",
+    "JP nn",
+    "JP Z,nn",
+    "JP NZ,nn",
+    "JP C,nn",
+    "JP NC,nn",
+    "JP P,nn",
+    "JP M,nn",
+    "JP PO,nn",
+    "JP PE,nn",
+    "JP (HL)",
+    "JR e",
+    "JR Z,e",
+    "JR NZ,e",
+    "JR C,e",
+    "JR NC,e",
+    "DJNZ e",
+    "CALL nn",
+    "CALL nn",
+    "CALL Z,nn",
+    "CALL NZ,nn",
+    "CALL C,nn",
+    "CALL NC,nn",
+    "CALL PO,nn",
+    "CALL PE,nn",
+    "CALL P,nn",
+    "CALL M,nn",
+    "RET",
+    "RET Z",
+    "RET NZ",
+    "RET C",
+    "RET NC",
+    "RET PO",
+    "RET PE",
+    "RET P",
+    "RET M",
+    "RST 00H",
+    "RST 08H",
+    "RST 10H",
+    "RST 18H",
+    "RST 20H",
+    "RST 28H",
+    "RST 30H",
+    "RST 38H",
+    "IN A,(n)",
+    "OUT (n),A",
+    "IN B,(C)",
+    "IN C,(C)",
+    "IN D,(C)",
+    "IN E,(C)",
+    "IN H,(C)",
+    "IN L,(C)",
+    "IN A,(C)",
+    "OUT (C),B",
+    "OUT (C),C",
+    "OUT (C),D",
+    "OUT (C),E",
+    "OUT (C),H",
+    "OUT (C),L",
+    "OUT (C),A",
+    "INI",
+    "INIR",
+    "IND",
+    "INDR",
+    "OUTI",
+    "OTIR",
+    "OUTD",
+    "OTDR",
+    "NOP",
+    "HALT",
+    "DI",
+    "EI",
+    "SCF",
+    "CCF",
+    "RLD",
+    "RRD",
+    "IM 0",
+    "IM 1",
+    "IM 2",
+    "LD I,A",
+    "LD A,I",
+    "LD R,A",
+    "LD A,R",
+    "RETI",
+    "RETN",
+    "LDI",
+    "LDIR",
+    "LDD",
+    "LDDR",
+    "CPI",
+    "CPIR",
+    "CPD",
+    "CPDR",
+    "BIT b,A",
+    "SET b,A",
+    "RES b,A",
+    "BIT b,B",
+    "BIT b,C",
+    "BIT b,D",
+    "BIT b,E",
+    "BIT b,H",
+    "BIT b,L",
+    "BIT b,(HL)",
+    "SET b,B",
+    "SET b,C",
+    "SET b,D",
+    "SET b,E",
+    "SET b,H",
+    "SET b,L",
+    "SET b,(HL)",
+    "RES b,B",
+    "RES b,C",
+    "RES b,D",
+    "RES b,E",
+    "RES b,H",
+    "RES b,L",
+    "RES b,(HL)",
+    "RLA",
+    "RRA",
+    "RLCA",
+    "RRCA",
+    "SLA A",
+    "SRA A",
+    "SRL A",
+    "RLC B",
+    "RLC C",
+    "RLC D",
+    "RLC E",
+    "RLC H",
+    "RLC L",
+    "RLC (HL)",
+    "RLC A",
+    "RRC B",
+    "RRC C",
+    "RRC D",
+    "RRC E",
+    "RRC H",
+    "RRC L",
+    "RRC (HL)",
+    "RRC A",
+    "RL B",
+    "RL C",
+    "RL D",
+    "RL E",
+    "RL H",
+    "RL L",
+    "RL (HL)",
+    "RL A",
+    "RR B",
+    "RR C",
+    "RR D",
+    "RR E",
+    "RR H",
+    "RR L",
+    "RR (HL)",
+    "RR A",
+    "SLA B",
+    "SLA C",
+    "SLA D",
+    "SLA E",
+    "SLA H",
+    "SLA L",
+    "SLA (HL)",
+    "SRA B",
+    "SRA C",
+    "SRA D",
+    "SRA E",
+    "SRA H",
+    "SRA L",
+    "SRA (HL)",
+    "SRL B",
+    "SRL C",
+    "SRL D",
+    "SRL E",
+    "SRL H",
+    "SRL L",
+    "SRL (HL)",
+    "LD A,(IX+d)",
+    "LD B,(IX+d)",
+    "LD C,(IX+d)",
+    "LD D,(IX+d)",
+    "LD E,(IX+d)",
+    "LD H,(IX+d)",
+    "LD L,(IX+d)",
+    "LD (IX+d),A",
+    "LD (IX+d),B",
+    "LD (IX+d),C",
+    "LD (IX+d),D",
+    "LD (IX+d),E",
+    "LD (IX+d),H",
+    "LD (IX+d),L",
+    "LD (IX+d),n",
+    "ADD A,(IX+d)",
+    "ADC A,(IX+d)",
+    "SUB (IX+d)",
+    "SBC A,(IX+d)",
+    "AND (IX+d)",
+    "XOR (IX+d)",
+    "OR (IX+d)",
+    "CP (IX+d)",
+    "INC (IX+d)",
+    "DEC (IX+d)",
+    "LD IX,nn",
+    "LD IX,(nn)",
+    "LD (nn),IX",
+    "LD SP,IX",
+    "PUSH IX",
+    "POP IX",
+    "EX (SP),IX",
+    "INC IX",
+    "DEC IX",
+    "ADD IX,BC",
+    "ADD IX,DE",
+    "ADD IX,IX",
+    "ADD IX,SP",
+    "JP (IX)",
+    "LD A,(IY+d)",
+    "LD B,(IY+d)",
+    "LD C,(IY+d)",
+    "LD D,(IY+d)",
+    "LD E,(IY+d)",
+    "LD H,(IY+d)",
+    "LD L,(IY+d)",
+    "LD (IY+d),A",
+    "LD (IY+d),B",
+    "LD (IY+d),C",
+    "LD (IY+d),D",
+    "LD (IY+d),E",
+    "LD (IY+d),H",
+    "LD (IY+d),L",
+    "LD (IY+d),n",
+    "ADD A,(IY+d)",
+    "ADC A,(IY+d)",
+    "SUB (IY+d)",
+    "SBC A,(IY+d)",
+    "AND (IY+d)",
+    "XOR (IY+d)",
+    "OR (IY+d)",
+    "CP (IY+d)",
+    "INC (IY+d)",
+    "DEC (IY+d)",
+    "LD IY,nn",
+    "LD IY,(nn)",
+    "LD (nn),IY",
+    "LD SP,IY",
+    "PUSH IY",
+    "POP IY",
+    "EX (SP),IY",
+    "INC IY",
+    "DEC IY",
+    "ADD IY,BC",
+    "ADD IY,DE",
+    "ADD IY,IY",
+    "ADD IY,SP",
+    "JP (IY)",
+];
+
+/// Undocumented Z80 instructions (`UNDOCUMENTED_OPCODES`)
+pub const UNDOCUMENTED: &[&str] = &[
+    "SLL B",
+    "SLL C",
+    "SLL D",
+    "SLL E",
+    "SLL H",
+    "SLL L",
+    "SLL (HL)",
+    "SLL A",
+    "LD B,IXH",
+    "LD C,IXH",
+    "LD D,IXH",
+    "LD E,IXH",
+    "LD A,IXH",
+    "LD IXH,B",
+    "LD IXH,C",
+    "LD IXH,D",
+    "LD IXH,E",
+    "LD IXH,A",
+    "LD IXH,IXL",
+    "LD IXH,n",
+    "INC IXH",
+    "DEC IXH",
+    "ADD A,IXH",
+    "ADC A,IXH",
+    "SUB IXH",
+    "SBC A,IXH",
+    "AND IXH",
+    "XOR IXH",
+    "OR IXH",
+    "CP IXH",
+    "LD B,IXL",
+    "LD C,IXL",
+    "LD D,IXL",
+    "LD E,IXL",
+    "LD A,IXL",
+    "LD IXL,B",
+    "LD IXL,C",
+    "LD IXL,D",
+    "LD IXL,E",
+    "LD IXL,A",
+    "LD IXL,IXH",
+    "LD IXL,n",
+    "INC IXL",
+    "DEC IXL",
+    "ADD A,IXL",
+    "ADC A,IXL",
+    "SUB IXL",
+    "SBC A,IXL",
+    "AND IXL",
+    "XOR IXL",
+    "OR IXL",
+    "CP IXL",
+    "LD B,IYH",
+    "LD C,IYH",
+    "LD D,IYH",
+    "LD E,IYH",
+    "LD A,IYH",
+    "LD IYH,B",
+    "LD IYH,C",
+    "LD IYH,D",
+    "LD IYH,E",
+    "LD IYH,A",
+    "LD IYH,IYL",
+    "LD IYH,n",
+    "INC IYH",
+    "DEC IYH",
+    "ADD A,IYH",
+    "ADC A,IYH",
+    "SUB IYH",
+    "SBC A,IYH",
+    "AND IYH",
+    "XOR IYH",
+    "OR IYH",
+    "CP IYH",
+    "LD B,IYL",
+    "LD C,IYL",
+    "LD D,IYL",
+    "LD E,IYL",
+    "LD A,IYL",
+    "LD IYL,B",
+    "LD IYL,C",
+    "LD IYL,D",
+    "LD IYL,E",
+    "LD IYL,A",
+    "LD IYL,IYH",
+    "LD IYL,n",
+    "INC IYL",
+    "DEC IYL",
+    "ADD A,IYL",
+    "ADC A,IYL",
+    "SUB IYL",
+    "SBC A,IYL",
+    "AND IYL",
+    "XOR IYL",
+    "OR IYL",
+    "CP IYL",
+];
+
+/// Z180 extensions (`Z180_OPCODES`)
+pub const Z180: &[&str] = &[
+    "MLT BC",
+    "MLT DE",
+    "MLT HL",
+    "IN0 A,(n)",
+    "OUT0 (n),A",
+    "TST n",
+    "TST A",
+    "TST (HL)",
+    "TSTIO n",
+    "SLP",
+];
+
+/// R800 extensions (`R800_OPCODES`)
+pub const R800: &[&str] = &[
+    "MULUB A,B",
+    "MULUB A,C",
+    "MULUB A,D",
+    "MULUB A,E",
+    "MULUW HL,BC",
+    "MULUW HL,SP",
+];
+
+/// Z80N extensions (`Z80N_OPCODES`)
+pub const Z80N: &[&str] = &[
+    "SWAPNIB",
+    "MIRROR A",
+    "TEST n",
+    "BSLA DE,B",
+    "BSRA DE,B",
+    "BSRL DE,B",
+    "BSRF DE,B",
+    "BRLC DE,B",
+    "MUL D,E",
+    "ADD HL,A",
+    "ADD DE,A",
+    "ADD BC,A",
+    "ADD HL,nn",
+    "ADD DE,nn",
+    "ADD BC,nn",
+    "OUTINB",
+    "NEXTREG n,A",
+    "PIXELDN",
+    "PIXELAD",
+    "SETAE",
+    "JP (C)",
+    "LDIX",
+    "LDWS",
+    "LDDX",
+    "LDIRX",
+    "LDPIRX",
+    "LDDRX",
+];